@@ -0,0 +1,57 @@
+use serde_json::json;
+use std::fmt;
+
+/// Stable codes of the error envelope sent to websocket clients. The codes are identical across all engines,
+/// so that client SDKs can branch on them instead of parsing messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    BadInput,
+    RequestTooLarge,
+    Timeout,
+    InferenceFailed,
+    EngineUnavailable,
+    /// The host is under resource pressure and sheds load
+    ResourceExhausted,
+    /// The request was not signed by the task owner, or its signature was replayed
+    Unauthorized,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadInput => "BAD_INPUT",
+            ErrorCode::RequestTooLarge => "REQUEST_TOO_LARGE",
+            ErrorCode::Timeout => "TIMEOUT",
+            ErrorCode::InferenceFailed => "INFERENCE_FAILED",
+            ErrorCode::EngineUnavailable => "ENGINE_UNAVAILABLE",
+            ErrorCode::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            ErrorCode::Unauthorized => "UNAUTHORIZED",
+        }
+    }
+}
+
+/// An inference error that already knows which code it maps to
+#[derive(Debug)]
+pub struct EngineError {
+    pub code: ErrorCode,
+    pub detail: String,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.detail)
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// Builds the error envelope, `{"error":{"code":"BAD_INPUT","detail":"..."}}`
+pub fn error_response(code: ErrorCode, detail: impl fmt::Display) -> String {
+    json!({
+        "error": {
+            "code": code.as_str(),
+            "detail": detail.to_string(),
+        }
+    })
+    .to_string()
+}
@@ -0,0 +1,5 @@
+pub mod error_response;
+pub mod request_options;
+
+pub use error_response::{error_response, EngineError, ErrorCode};
+pub use request_options::{split_request_options, RequestOptions};
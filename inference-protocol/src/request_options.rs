@@ -0,0 +1,48 @@
+use serde_json::Value;
+use std::time::Duration;
+
+/// Options a client can add to any JSON object request, they are removed before the request reaches the model
+#[derive(Debug, Default, PartialEq)]
+pub struct RequestOptions {
+    /// Overrides the default deadline of the inference
+    pub timeout: Option<Duration>,
+    /// Echoed in the response, so that clients can match responses delivered out of order
+    pub request_id: Option<Value>,
+}
+
+/// Removes the optional `timeout_ms` and `request_id` fields from a JSON object request, returning the remaining
+/// request and the options
+pub fn split_request_options(request: String) -> (String, RequestOptions) {
+    let Ok(Value::Object(mut fields)) = serde_json::from_str(&request) else {
+        return (request, RequestOptions::default());
+    };
+
+    let timeout = fields.remove("timeout_ms");
+    let request_id = fields.remove("request_id");
+    if timeout.is_none() && request_id.is_none() {
+        return (request, RequestOptions::default());
+    }
+
+    let options = RequestOptions {
+        timeout: timeout
+            .and_then(|value| value.as_u64())
+            .map(Duration::from_millis),
+        request_id,
+    };
+    (Value::Object(fields).to_string(), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_options_are_split_from_inputs() {
+        let (request, options) =
+            split_request_options(r#"{"x":{"F32":[1.0]},"timeout_ms":50,"request_id":"a"}"#.into());
+
+        assert_eq!(request, r#"{"x":{"F32":[1.0]}}"#);
+        assert_eq!(options.timeout, Some(Duration::from_millis(50)));
+        assert_eq!(options.request_id, Some(Value::from("a")));
+    }
+}
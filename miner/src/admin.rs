@@ -0,0 +1,665 @@
+use crate::{
+    chain_properties::{self, ChainProperties},
+    config,
+    error::{Error, Result},
+    log,
+    parachain_interactor::identity,
+    parent_runtime::{
+        inference_history::{self, ModelStats},
+        proof,
+        server_control::{BOUND_ADDRESSES, ENGINE_STATUS, REQUESTS_RECEIVED},
+        setup_progress::{self, ExtractionTimings, SetupStage, TaskSetupTimings},
+        storage_upload::{self, StoredObject},
+    },
+    utils::{
+        blocking::run_blocking, container_monitor, load_shedding, tx_queue::TRANSACTION_QUEUE,
+    },
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, Once},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::TcpListener;
+
+/// Port of the admin API unless `ADMIN_PORT` is set
+pub const DEFAULT_ADMIN_PORT: u16 = 7300;
+/// Log lines included in a status
+const RECENT_LOG_LINES: usize = 20;
+/// Bytes at the end of the log file the recent lines are taken from
+const LOG_TAIL_BYTES: u64 = 64 * 1024;
+/// How long `prove-now` waits for the proof
+const PROVE_NOW_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+/// Bytes at the end of the log file that are uploaded on request, unless `LOG_UPLOAD_MAX_BYTES` is set
+const DEFAULT_LOG_UPLOAD_BYTES: u64 = 16 * 1024 * 1024;
+/// File next to the identity holding the bearer token of the routes that change the state of the miner
+const ADMIN_TOKEN_FILE_NAME: &str = "admin-token";
+
+static ADMIN_API: Once = Once::new();
+
+/// What an operator sees of a running miner process, served as JSON by the admin API
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AdminStatus {
+    pub tasks: Vec<TaskStatus>,
+    /// Transactions waiting to be submitted
+    pub tx_queue_pending: usize,
+    /// Why new load is rejected, `None` if the host has capacity
+    pub load_shedding: Option<String>,
+    pub gpus: Vec<GpuUsage>,
+    pub recent_logs: Vec<String>,
+    /// Rolling statistics of the models served, across restarts
+    #[serde(default)]
+    pub models: Vec<ModelStats>,
+    /// Of the miner process, to tell which instance holds a data directory
+    #[serde(default)]
+    pub pid: u32,
+    /// The last known state of the containers the miner owns, by name
+    #[serde(default)]
+    pub containers: BTreeMap<String, String>,
+    /// How the connected chain presents accounts and balances
+    #[serde(default)]
+    pub chain: ChainProperties,
+    /// Where the setups of the latest tasks spent their time
+    #[serde(default)]
+    pub setup: Vec<TaskSetupTimings>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskStatus {
+    pub task_id: u64,
+    pub engine_status: String,
+    /// Requests received since the inference server started, rates are derived from consecutive statuses
+    pub requests_received: u64,
+    pub addresses: Vec<SocketAddr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GpuUsage {
+    pub name: String,
+    pub utilization_percent: f32,
+    pub memory_used_mib: u64,
+    pub memory_total_mib: u64,
+}
+
+/// Starts the admin API once per process on `127.0.0.1:ADMIN_PORT`, unless `ADMIN_API=false`. It is only reachable
+/// from the host, `cyborg-miner top` renders it for operators. Miners of a fleet share it.
+///
+/// Any local user and, through a simple cross-site POST, any website the operator visits can reach the port. Requests
+/// sent by a browser (with an `Origin` header) are therefore rejected, and the routes that change the state of the
+/// miner require the bearer token in `admin_token_path`, which only the user of the miner can read.
+pub fn start() {
+    ADMIN_API.call_once(|| {
+        match config::security_env("ADMIN_API", true) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                println!("{}, not starting the admin API", e);
+                return;
+            }
+        }
+        let token = match ensure_admin_token() {
+            Ok(token) => Arc::<str>::from(token),
+            Err(e) => {
+                println!(
+                    "Failed to create the token of the admin API, not starting it: {}",
+                    e
+                );
+                return;
+            }
+        };
+
+        let port = config::optional_env("ADMIN_PORT", DEFAULT_ADMIN_PORT);
+        tokio::spawn(async move {
+            let protected = Router::new()
+                .route("/logs/upload", post(upload_logs_handler))
+                .route("/proofs/prove-now", post(prove_now_handler))
+                .route_layer(middleware::from_fn_with_state(token, require_token));
+            let app = Router::new()
+                .route("/status", get(status_handler))
+                .route("/metrics", get(metrics_handler))
+                .route("/proofs/log", get(prover_log_handler))
+                .merge(protected)
+                .layer(middleware::from_fn(reject_browsers));
+            let listener =
+                match TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        println!("Failed to start the admin API on port {}: {}", port, e);
+                        return;
+                    }
+                };
+
+            println!("Admin API listening on http://127.0.0.1:{}", port);
+            if let Err(e) = axum::serve(listener, app).await {
+                println!("Admin API failed: {}", e);
+            }
+        });
+    });
+}
+
+/// Where the bearer token of the admin API is kept, `ADMIN_TOKEN_PATH` or `admin-token` next to the identity. It is
+/// derived from the environment, the commands talking to a running miner have no paths of their own.
+pub fn admin_token_path() -> PathBuf {
+    if let Ok(path) = std::env::var("ADMIN_TOKEN_PATH") {
+        return PathBuf::from(path);
+    }
+    std::env::var("IDENTITY_FILE_PATH")
+        .ok()
+        .and_then(|identity_path| {
+            Path::new(&identity_path)
+                .parent()
+                .map(|dir| dir.join(ADMIN_TOKEN_FILE_NAME))
+        })
+        .unwrap_or_else(|| PathBuf::from(ADMIN_TOKEN_FILE_NAME))
+}
+
+/// Reads the token of the admin API, a new one is generated on the first start. The file is (re)written readable by
+/// the user of the miner only.
+fn ensure_admin_token() -> Result<String> {
+    let path = admin_token_path();
+    let token = match fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => token.trim().to_string(),
+        _ => {
+            let mut token = [0u8; 32];
+            OsRng.fill_bytes(&mut token);
+            hex::encode(token)
+        }
+    };
+    identity::write_private(&path, token.as_bytes())?;
+    Ok(token)
+}
+
+/// Reads the token of the admin API, for the commands talking to a running miner
+fn read_admin_token() -> Result<String> {
+    let path = admin_token_path();
+    fs::read_to_string(&path)
+        .map(|token| token.trim().to_string())
+        .map_err(|e| {
+            Error::Custom(format!(
+                "Failed to read the admin API token from {}, set ADMIN_TOKEN_PATH if the miner keeps it elsewhere: {}",
+                path.display(),
+                e
+            ))
+        })
+}
+
+async fn require_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| presented.trim() == &*token);
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            format!(
+                "Send the token in {} as 'Authorization: Bearer <token>'",
+                admin_token_path().display()
+            ),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Browsers send an `Origin` header with cross-site requests, the clients of the admin API never do
+async fn reject_browsers(request: Request, next: Next) -> Response {
+    if request.headers().contains_key(header::ORIGIN) {
+        return (
+            StatusCode::FORBIDDEN,
+            "The admin API does not serve browsers",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+async fn status_handler() -> Json<AdminStatus> {
+    Json(status().await)
+}
+
+/// Uploads the tail of the log file to the `logs` directory of the storage backend, eg. to hand it to support, and
+/// answers with where it was stored
+async fn upload_logs_handler() -> Response {
+    match upload_logs().await {
+        Ok(stored) => {
+            Json(serde_json::json!({ "id": stored.id, "url": stored.url })).into_response()
+        }
+        Err(e) => (
+            StatusCode::BAD_GATEWAY,
+            format!("Failed to upload the logs: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+async fn upload_logs() -> Result<StoredObject> {
+    let max_bytes = config::optional_env("LOG_UPLOAD_MAX_BYTES", DEFAULT_LOG_UPLOAD_BYTES);
+    let logs = run_blocking(move || Ok(log_tail(max_bytes)?)).await?;
+    let uploaded_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    storage_upload::backend_from_env()?
+        .upload(
+            &format!("logs/{}-{}.log", uploaded_at, std::process::id()),
+            "text/plain",
+            logs,
+        )
+        .await
+}
+
+#[derive(Deserialize)]
+struct TaskQuery {
+    task_id: Option<u64>,
+}
+
+/// Generates a proof for the NeuroZK task being served, see `proof::prove_now`, and answers with it once it is done
+async fn prove_now_handler(Query(query): Query<TaskQuery>) -> Response {
+    match proof::prove_now(query.task_id).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Failed to generate the proof: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Serves the EZKL output of the last proof of the NeuroZK task being served, see `proof::last_prover_log`, the path
+/// of the log is sent in `x-log-path`
+async fn prover_log_handler(Query(query): Query<TaskQuery>) -> Response {
+    match proof::last_prover_log(query.task_id) {
+        Ok((path, log)) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/plain; charset=utf-8".to_string(),
+                ),
+                (
+                    header::HeaderName::from_static("x-log-path"),
+                    path.display().to_string(),
+                ),
+            ],
+            log,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::NOT_FOUND,
+            format!("No prover log available: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Serves the model statistics and request counters in the Prometheus text format
+async fn metrics_handler() -> ([(header::HeaderName, &'static str); 1], String) {
+    let status = status().await;
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        prometheus_metrics(&status),
+    )
+}
+
+fn prometheus_metrics(status: &AdminStatus) -> String {
+    let mut metrics = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        metrics.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for (labels, value) in samples {
+            metrics.push_str(&format!("{}{} {}\n", name, labels, value));
+        }
+    };
+    let model_label = |model: &ModelStats| {
+        format!(
+            "model=\"{}\"",
+            model
+                .model
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        )
+    };
+
+    metric(
+        "cyborg_requests_received_total",
+        "counter",
+        "Requests received by the inference server of a task since it started",
+        status
+            .tasks
+            .iter()
+            .map(|task| {
+                (
+                    format!("{{task_id=\"{}\"}}", task.task_id),
+                    task.requests_received.to_string(),
+                )
+            })
+            .collect(),
+    );
+    metric(
+        "cyborg_tx_queue_pending",
+        "gauge",
+        "Transactions waiting to be submitted",
+        vec![(String::new(), status.tx_queue_pending.to_string())],
+    );
+    metric(
+        "cyborg_model_latency_ms",
+        "gauge",
+        "Inference latency of a model over its latest inferences",
+        status
+            .models
+            .iter()
+            .flat_map(|model| {
+                [
+                    ("0.5", model.p50_latency_ms),
+                    ("0.95", model.p95_latency_ms),
+                ]
+                .map(|(quantile, latency)| {
+                    (
+                        format!("{{{},quantile=\"{}\"}}", model_label(model), quantile),
+                        latency.to_string(),
+                    )
+                })
+            })
+            .collect(),
+    );
+    metric(
+        "cyborg_model_tokens_per_second",
+        "gauge",
+        "Tokens a model generates per second of inference",
+        status
+            .models
+            .iter()
+            .filter_map(|model| {
+                model.tokens_per_sec.map(|tokens_per_sec| {
+                    (
+                        format!("{{{}}}", model_label(model)),
+                        tokens_per_sec.to_string(),
+                    )
+                })
+            })
+            .collect(),
+    );
+    metric(
+        "cyborg_model_error_rate",
+        "gauge",
+        "Share of the latest inferences of a model that failed",
+        status
+            .models
+            .iter()
+            .map(|model| {
+                (
+                    format!("{{{}}}", model_label(model)),
+                    model.error_rate.to_string(),
+                )
+            })
+            .collect(),
+    );
+    metric(
+        "cyborg_setup_stage_seconds",
+        "gauge",
+        "Time the setup of a task spent in a stage, the current stage counts until now",
+        status
+            .setup
+            .iter()
+            .flat_map(|setup| {
+                setup.stages.iter().enumerate().map(|(index, timing)| {
+                    (
+                        format!(
+                            "{{task_id=\"{}\",stage=\"{}\",index=\"{}\"}}",
+                            setup.task_id,
+                            stage_label(timing.stage),
+                            index
+                        ),
+                        timing.seconds.to_string(),
+                    )
+                })
+            })
+            .collect(),
+    );
+    let extractions: Vec<(u64, &ExtractionTimings)> = status
+        .setup
+        .iter()
+        .filter_map(|setup| Some((setup.task_id, setup.extraction.as_ref()?)))
+        .collect();
+    metric(
+        "cyborg_extraction_seconds",
+        "gauge",
+        "Time the extraction of a NeuroZK archive spent decompressing and writing, which overlap if it was pipelined",
+        extractions
+            .iter()
+            .flat_map(|(task_id, extraction)| {
+                [
+                    ("decompress", Some(extraction.decompress_seconds)),
+                    ("write", Some(extraction.write_seconds)),
+                    ("total", Some(extraction.total_seconds)),
+                    ("benchmark", extraction.benchmark_seconds),
+                ]
+                .into_iter()
+                .filter_map(move |(part, seconds)| {
+                    Some((
+                        format!("{{task_id=\"{}\",part=\"{}\"}}", task_id, part),
+                        seconds?.to_string(),
+                    ))
+                })
+            })
+            .collect(),
+    );
+    metric(
+        "cyborg_extraction_throughput_mbps",
+        "gauge",
+        "Throughput of the host measured before extracting a NeuroZK archive, in MB/s",
+        extractions
+            .iter()
+            .flat_map(|(task_id, extraction)| {
+                [
+                    ("read", extraction.read_mbps),
+                    ("decompress", extraction.decompress_mbps),
+                    ("write", extraction.write_mbps),
+                ]
+                .into_iter()
+                .filter_map(move |(resource, mbps)| {
+                    Some((
+                        format!("{{task_id=\"{}\",resource=\"{}\"}}", task_id, resource),
+                        mbps?.to_string(),
+                    ))
+                })
+            })
+            .collect(),
+    );
+    metric(
+        "cyborg_extraction_pipelined",
+        "gauge",
+        "Whether the files of a NeuroZK archive were written while it was decompressed further",
+        extractions
+            .iter()
+            .map(|(task_id, extraction)| {
+                (
+                    format!("{{task_id=\"{}\"}}", task_id),
+                    u8::from(extraction.pipelined).to_string(),
+                )
+            })
+            .collect(),
+    );
+
+    metrics
+}
+
+/// The stage as it is serialized, eg. `downloading`
+fn stage_label(stage: SetupStage) -> String {
+    serde_json::to_value(stage)
+        .ok()
+        .and_then(|stage| stage.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Gathers the status of the miner process, probes that fail are left out
+pub async fn status() -> AdminStatus {
+    let mut tasks: Vec<TaskStatus> = {
+        let engine_status = ENGINE_STATUS.lock().unwrap();
+        let requests_received = REQUESTS_RECEIVED.lock().unwrap();
+        let bound_addresses = BOUND_ADDRESSES.lock().unwrap();
+
+        engine_status
+            .iter()
+            .map(|(task_id, status)| TaskStatus {
+                task_id: *task_id,
+                engine_status: status.clone(),
+                requests_received: requests_received.get(task_id).copied().unwrap_or(0),
+                addresses: bound_addresses.get(task_id).cloned().unwrap_or_default(),
+            })
+            .collect()
+    };
+    tasks.sort_by_key(|task| task.task_id);
+
+    let tx_queue_pending = match TRANSACTION_QUEUE.get() {
+        Some(tx_queue) => tx_queue.pending().await,
+        None => 0,
+    };
+
+    // nvidia-smi and the log file must not block the reactor serving the inference requests
+    let (gpus, recent_logs) = run_blocking(|| Ok((gpu_usage(), recent_logs())))
+        .await
+        .unwrap_or_default();
+
+    AdminStatus {
+        tasks,
+        tx_queue_pending,
+        load_shedding: load_shedding::pressure(),
+        gpus,
+        recent_logs,
+        models: inference_history::stats(),
+        pid: std::process::id(),
+        containers: container_monitor::statuses(),
+        chain: chain_properties::get(),
+        setup: setup_progress::timings(),
+    }
+}
+
+/// Usage of every NVIDIA GPU, empty without any
+fn gpu_usage() -> Vec<GpuUsage> {
+    let output = match Command::new("nvidia-smi")
+        .arg("--query-gpu=name,utilization.gpu,memory.used,memory.total")
+        .arg("--format=csv,noheader,nounits")
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            match fields.as_slice() {
+                [name, utilization, used, total] => Some(GpuUsage {
+                    name: name.to_string(),
+                    utilization_percent: utilization.parse().ok()?,
+                    memory_used_mib: used.parse().ok()?,
+                    memory_total_mib: total.parse().ok()?,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The last lines of the log file, only its tail is read as it is never rotated
+fn recent_logs() -> Vec<String> {
+    let Ok(tail) = log_tail(LOG_TAIL_BYTES) else {
+        return Vec::new();
+    };
+
+    let content = String::from_utf8_lossy(&tail);
+    let lines: Vec<&str> = content.lines().collect();
+    lines[lines.len().saturating_sub(RECENT_LOG_LINES)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// The last `max_bytes` of the log file
+fn log_tail(max_bytes: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(log::log_file_path())?;
+    let length = file.metadata()?.len();
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(length.saturating_sub(max_bytes)))?;
+    file.read_to_end(&mut tail)?;
+    Ok(tail)
+}
+
+/// Asks a running miner to prove its NeuroZK task now and writes the proof to `out`, for operators to check their
+/// prover setup and its timings before the chain requests a proof
+///
+/// # Arguments
+/// * `admin_url` - The admin API of the miner, eg. `http://127.0.0.1:7300`
+/// * `task_id` - The task to prove, the only NeuroZK task the miner serves if `None`
+/// * `out` - Where the proof and its timings are written as JSON
+pub async fn prove_now(admin_url: &str, task_id: Option<u64>, out: &Path) -> Result<()> {
+    // Proofs of large circuits take a long time and send nothing until they are done
+    let client = config::http_client_builder()?
+        .read_timeout(PROVE_NOW_TIMEOUT)
+        .build()?;
+    let mut request = client
+        .post(format!(
+            "{}/proofs/prove-now",
+            admin_url.trim_end_matches('/')
+        ))
+        .bearer_auth(read_admin_token()?);
+    if let Some(task_id) = task_id {
+        request = request.query(&[("task_id", task_id)]);
+    }
+
+    println!("Generating a proof, this can take several minutes");
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(Error::Custom(response.text().await?));
+    }
+    let report: serde_json::Value = response.json().await?;
+
+    fs::write(out, serde_json::to_string_pretty(&report)?)?;
+    for stage in report["stages"].as_array().into_iter().flatten() {
+        println!(
+            "{:<10} {} ms",
+            stage["stage"].as_str().unwrap_or_default(),
+            stage["elapsed_ms"]
+        );
+    }
+    println!(
+        "Proof of task {} ({} bytes) written to {}",
+        report["task_id"],
+        report["proof_bytes"],
+        out.display()
+    );
+    Ok(())
+}
+
+/// Fetches the status of the miner process listening at `admin_url`
+pub async fn fetch_status(client: &reqwest::Client, admin_url: &str) -> Result<AdminStatus> {
+    Ok(client
+        .get(format!("{}/status", admin_url.trim_end_matches('/')))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
@@ -0,0 +1,292 @@
+use crate::{
+    config,
+    error::{Error, Result},
+    utils::blocking::run_blocking,
+};
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, Once},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use sysinfo::{Disks, System};
+
+/// How often the disk is checked for free space
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When each kind of alert was last sent, repeats within `ALERT_COOLDOWN_SECS` are only logged
+static LAST_SENT: Lazy<Mutex<HashMap<Alert, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static DISK_MONITOR: Once = Once::new();
+
+/// A critical condition that needs an operator, miners run headless and would otherwise fail unnoticed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Alert {
+    /// The inference engine of a task failed and won't recover without intervention
+    EngineFailed,
+    /// A transaction ran out of retries and was dropped from the queue
+    TransactionDeadLettered,
+    DiskNearlyFull,
+    /// The chain removed the miner, it no longer receives tasks
+    RegistrationLost,
+    /// Task artifacts differ from what the task owner or the gatekeeper committed to, the task is not served
+    IntegrityMismatch,
+}
+
+impl Alert {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Alert::EngineFailed => "engine_failed",
+            Alert::TransactionDeadLettered => "transaction_dead_lettered",
+            Alert::DiskNearlyFull => "disk_nearly_full",
+            Alert::RegistrationLost => "registration_lost",
+            Alert::IntegrityMismatch => "integrity_mismatch",
+        }
+    }
+}
+
+/// Where alerts are sent, every configured sink receives every alert:
+/// - `ALERT_WEBHOOK_URL`: The alert is `POST`ed as JSON, eg. `{"alert":"engine_failed","detail":...,"host":...}`
+/// - `ALERT_SMTP_HOST`: The alert is mailed from `ALERT_EMAIL_FROM` to `ALERT_EMAIL_TO` through the server on
+///   `ALERT_SMTP_PORT` (default 587, STARTTLS), authenticated with `ALERT_SMTP_USERNAME` and `ALERT_SMTP_PASSWORD`
+///   if they are set
+struct Sinks {
+    webhook_url: Option<String>,
+    smtp: Option<SmtpSink>,
+}
+
+struct SmtpSink {
+    host: String,
+    port: u16,
+    credentials: Option<Credentials>,
+    from: String,
+    to: String,
+}
+
+impl Sinks {
+    fn from_env() -> Self {
+        let setting = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        let smtp = setting("ALERT_SMTP_HOST").map(|host| SmtpSink {
+            host,
+            port: config::optional_env("ALERT_SMTP_PORT", 587),
+            credentials: setting("ALERT_SMTP_USERNAME").map(|username| {
+                Credentials::new(username, setting("ALERT_SMTP_PASSWORD").unwrap_or_default())
+            }),
+            from: setting("ALERT_EMAIL_FROM").unwrap_or_default(),
+            to: setting("ALERT_EMAIL_TO").unwrap_or_default(),
+        });
+
+        Self {
+            webhook_url: setting("ALERT_WEBHOOK_URL"),
+            smtp,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.webhook_url.is_none() && self.smtp.is_none()
+    }
+}
+
+/// Sends an alert to the configured sinks in the background. The same kind of alert is sent at most once per
+/// `ALERT_COOLDOWN_SECS` (default 3600), so a flapping condition doesn't flood the operator.
+///
+/// # Arguments
+/// * `alert` - What happened
+/// * `detail` - A description for the operator, eg. the error
+pub fn raise(alert: Alert, detail: impl Into<String>) {
+    let detail = detail.into();
+    println!("ALERT {}: {}", alert.as_str(), detail);
+
+    let sinks = Sinks::from_env();
+    if sinks.is_empty() {
+        return;
+    }
+    let cooldown = Duration::from_secs(config::optional_env("ALERT_COOLDOWN_SECS", 3600));
+    if !is_due(
+        &mut LAST_SENT.lock().unwrap(),
+        alert,
+        cooldown,
+        Instant::now(),
+    ) {
+        return;
+    }
+
+    // Alerts are raised from within the runtime, but never when there is none to send them with
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    runtime.spawn(async move {
+        if let Err(e) = send(&sinks, alert, &detail).await {
+            println!("Failed to send the {} alert: {}", alert.as_str(), e);
+        }
+    });
+}
+
+/// Whether an alert may be sent at `now`, records it as sent if so
+fn is_due(
+    last_sent: &mut HashMap<Alert, Instant>,
+    alert: Alert,
+    cooldown: Duration,
+    now: Instant,
+) -> bool {
+    if let Some(sent) = last_sent.get(&alert) {
+        if now.duration_since(*sent) < cooldown {
+            return false;
+        }
+    }
+    last_sent.insert(alert, now);
+    true
+}
+
+async fn send(sinks: &Sinks, alert: Alert, detail: &str) -> Result<()> {
+    let host = System::host_name().unwrap_or_else(|| "unknown host".to_string());
+
+    if let Some(webhook_url) = &sinks.webhook_url {
+        let body = serde_json::json!({
+            "alert": alert.as_str(),
+            "detail": detail,
+            "host": host,
+            "raised_at": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+        });
+        let response = config::http_client()?
+            .post(webhook_url)
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::Custom(format!(
+                "Webhook answered {}",
+                response.status()
+            )));
+        }
+    }
+
+    if let Some(smtp) = &sinks.smtp {
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| Error::Custom(format!("Invalid email address '{}': {}", address, e)))
+        };
+        let message = Message::builder()
+            .from(mailbox(&smtp.from)?)
+            .to(mailbox(&smtp.to)?)
+            .subject(format!("[cyborg-miner] {} on {}", alert.as_str(), host))
+            .body(detail.to_string())
+            .map_err(|e| Error::Custom(format!("Failed to build the alert email: {}", e)))?;
+
+        let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)
+            .map_err(|e| Error::Custom(format!("Invalid SMTP host {}: {}", smtp.host, e)))?
+            .port(smtp.port);
+        if let Some(credentials) = &smtp.credentials {
+            transport = transport.credentials(credentials.clone());
+        }
+        transport
+            .build()
+            .send(message)
+            .await
+            .map_err(|e| Error::Custom(format!("Failed to send the alert email: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+/// Starts checking the disk of the task directory once per process, an alert is raised once it is used beyond
+/// `ALERT_DISK_PERCENT` (default 90, 0 disables the check). A fleet shares the disk, so the members share the check.
+pub fn start_disk_monitor() {
+    DISK_MONITOR.call_once(|| {
+        let threshold = config::optional_env("ALERT_DISK_PERCENT", 90.0f32);
+        if threshold <= 0.0 {
+            return;
+        }
+        let path = std::env::var("TASK_DIR_PATH")
+            .map(PathBuf::from)
+            .or_else(|_| std::env::current_dir())
+            .unwrap_or_else(|_| PathBuf::from("/"));
+
+        tokio::spawn(async move {
+            loop {
+                let checked_path = path.clone();
+                let usage = run_blocking(move || Ok(disk_usage(&checked_path)))
+                    .await
+                    .ok()
+                    .flatten();
+                if let Some((mount_point, usage)) = usage {
+                    if usage >= threshold {
+                        raise(
+                            Alert::DiskNearlyFull,
+                            format!(
+                                "The disk at {} is {:.0}% full",
+                                mount_point.display(),
+                                usage
+                            ),
+                        );
+                    }
+                }
+                tokio::time::sleep(DISK_CHECK_INTERVAL).await;
+            }
+        });
+    });
+}
+
+/// The mount point of the disk `path` is on and how much of it is used in percent
+fn disk_usage(path: &Path) -> Option<(PathBuf, f32)> {
+    // The directory may not exist yet, its closest existing ancestor is on the same disk
+    let path = path
+        .ancestors()
+        .find_map(|ancestor| ancestor.canonicalize().ok())?;
+    let disks = Disks::new_with_refreshed_list();
+    let disk = disks
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())?;
+    if disk.total_space() == 0 {
+        return None;
+    }
+
+    let used = disk.total_space().saturating_sub(disk.available_space());
+    Some((
+        disk.mount_point().to_path_buf(),
+        used as f32 * 100.0 / disk.total_space() as f32,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_alerts_wait_for_the_cooldown() {
+        let mut last_sent = HashMap::new();
+        let cooldown = Duration::from_secs(60);
+        let now = Instant::now();
+
+        assert!(is_due(&mut last_sent, Alert::EngineFailed, cooldown, now));
+        assert!(!is_due(
+            &mut last_sent,
+            Alert::EngineFailed,
+            cooldown,
+            now + Duration::from_secs(30)
+        ));
+        assert!(is_due(&mut last_sent, Alert::DiskNearlyFull, cooldown, now));
+        assert!(is_due(
+            &mut last_sent,
+            Alert::EngineFailed,
+            cooldown,
+            now + cooldown
+        ));
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{
     config,
     error::Result,
-    types::{AccountKeypair, Miner, MinerData, ParentRuntime},
+    schema,
+    traits::{ChainApi, SubxtChain},
+    types::{AccountKeypair, Miner, ParentRuntime},
 };
-use std::{fs, /* str::FromStr, */ sync::Arc};
+use std::{/* str::FromStr, */ sync::Arc};
 use subxt::utils::AccountId32;
 use subxt_signer::{sr25519::Keypair as SR25519Keypair, /*SecretUri*/};
 use tokio::sync::RwLock;
@@ -18,6 +20,8 @@ pub struct MinerBuilder<Keypair> {
     keypair: Keypair,
     identity: Option<(AccountId32, u64)>,
     creator: Option<AccountId32>,
+    inference_port: Option<u16>,
+    chain: Option<Arc<dyn ChainApi>>,
 }
 
 pub struct NoKeypair;
@@ -33,6 +37,8 @@ impl Default for MinerBuilder<NoKeypair> {
             keypair: NoKeypair,
             identity: None,
             creator: None,
+            inference_port: None,
+            chain: None,
         }
     }
 }
@@ -64,13 +70,37 @@ impl<Keypair> MinerBuilder<Keypair> {
             keypair: AccountKeypair(keypair),
             identity: self.identity,
             creator: self.creator,
+            inference_port: self.inference_port,
+            chain: self.chain,
         }
     }
 
-    /// Sets the identity and the creator of the miner they are kept separate because the way that IDs are generated for the workers is subject to change.
+    /// Sets the port the inference server of the miner listens on, miners sharing a host need distinct ports.
+    ///
+    /// # Arguments
+    /// * `port` - The port of the inference server, 3000 if it is not set
+    ///
+    /// # Returns
+    /// A `MinerBuilder` instance with the inference port set.
+    pub fn inference_port(mut self, port: u16) -> Self {
+        self.inference_port = Some(port);
+        self
+    }
+
+    /// Sets the chain the miner submits to and queries, the connected parachain if it is not set.
     ///
     /// # Arguments
-    /// * `config` - A `MinerData` struct containing the identity and the creator of the worker.
+    /// * `chain` - The `ChainApi` to use, eg. a simulated chain for local development
+    ///
+    /// # Returns
+    /// A `MinerBuilder` instance with the chain set.
+    pub fn chain(mut self, chain: Arc<dyn ChainApi>) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Sets the identity and the creator of the miner they are kept separate because the way that IDs are generated for the workers is subject to change.
+    /// Reads them from the identity file, a file that exists but can't be read is an error rather than a missing identity.
     ///
     /// # Returns
     /// A `MinerBuilder` instance with the identity and the creator set.
@@ -78,15 +108,13 @@ impl<Keypair> MinerBuilder<Keypair> {
         let mut identity: Option<(AccountId32, u64)> = None;
         let mut creator: Option<AccountId32> = None;
 
-        if let Some(paths) = config::PATHS.get() {
-            match fs::read_to_string(&paths.identity_path)
-                .and_then(|s| serde_json::from_str::<MinerData>(&s).map_err(|e| e.into()))
-            {
-                Ok(config) => {
-                    identity = Some(config.miner_identity.clone());
-                    creator = Some(config.miner_identity.0);
+        if let Ok(paths) = config::get_paths() {
+            match schema::read_identity(&paths.identity_path)? {
+                Some(miner_identity) => {
+                    identity = Some(miner_identity.as_tuple());
+                    creator = Some(miner_identity.owner);
                 }
-                Err(_e) => {
+                None => {
                     warn!("No miner identity present, identity will be set when registering...")
                 }
             }
@@ -106,13 +134,20 @@ impl MinerBuilder<AccountKeypair> {
     /// # Returns
     /// A `Result` that, if successful, contains the constructed `Miner`.
     pub async fn build(self) -> Result<Miner> {
+        let chain = self.chain.unwrap_or_else(|| Arc::new(SubxtChain));
         Ok(Miner {
-            parent_runtime: Arc::new(RwLock::new(ParentRuntime { port: None })),
+            parent_runtime: Arc::new(RwLock::new(ParentRuntime {
+                port: self.inference_port,
+                chain: chain.clone(),
+            })),
             keypair: self.keypair.0,
             miner_identity: self.identity,
             creator: self.creator,
             current_task: None,
+            standby_task: None,
             log_failure_count: 0,
+            published_endpoint: None,
+            chain,
         })
     }
 }
@@ -0,0 +1,187 @@
+use crate::{config, error::Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sp_core::crypto::{Ss58AddressFormat, Ss58Codec};
+use subxt::{
+    backend::{legacy::LegacyRpcMethods, rpc::RpcClient},
+    utils::AccountId32,
+    OnlineClient, PolkadotConfig,
+};
+
+/// The properties of the chain the miner is connected to, discovered once per process
+static CHAIN_PROPERTIES: OnceCell<ChainProperties> = OnceCell::new();
+
+/// How a Cyborg network presents accounts and balances, as announced in the properties of its chain spec
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainProperties {
+    /// The SS58 prefix addresses are encoded with
+    pub ss58_format: u16,
+    /// The decimals of the native token, a balance of `10^token_decimals` is one token
+    pub token_decimals: u8,
+    pub token_symbol: String,
+}
+
+impl Default for ChainProperties {
+    /// The generic Substrate properties, which chains without properties in their spec use
+    fn default() -> Self {
+        Self {
+            ss58_format: 42,
+            token_decimals: 12,
+            token_symbol: "UNIT".to_string(),
+        }
+    }
+}
+
+impl ChainProperties {
+    /// Reads the properties a node reports through `system_properties`. Chains with several tokens list them in
+    /// arrays, the first one is the native token. `SS58_PREFIX`, `TOKEN_DECIMALS` and `TOKEN_SYMBOL` override what the
+    /// chain reports, eg. for development chains without properties.
+    ///
+    /// # Arguments
+    /// * `properties` - The properties as reported by the node
+    ///
+    /// # Returns
+    /// The `ChainProperties`, missing properties fall back to the generic Substrate ones
+    pub fn from_system_properties(properties: &Map<String, Value>) -> Self {
+        let first = |key: &str| match properties.get(key) {
+            Some(Value::Array(values)) => values.first().cloned(),
+            value => value.cloned(),
+        };
+        let defaults = Self::default();
+
+        let ss58_format = first("ss58Format")
+            .and_then(|value| value.as_u64())
+            .and_then(|value| u16::try_from(value).ok())
+            .unwrap_or(defaults.ss58_format);
+        let token_decimals = first("tokenDecimals")
+            .and_then(|value| value.as_u64())
+            .and_then(|value| u8::try_from(value).ok())
+            // More don't fit a balance
+            .filter(|decimals| *decimals <= 38)
+            .unwrap_or(defaults.token_decimals);
+        let token_symbol = first("tokenSymbol")
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or(defaults.token_symbol);
+
+        Self {
+            ss58_format: config::optional_env("SS58_PREFIX", ss58_format),
+            token_decimals: config::optional_env("TOKEN_DECIMALS", token_decimals),
+            token_symbol: config::optional_env("TOKEN_SYMBOL", token_symbol),
+        }
+    }
+
+    /// Encodes the account as an address of the chain
+    pub fn format_account(&self, account: &AccountId32) -> String {
+        sp_core::crypto::AccountId32::from(account.0)
+            .to_ss58check_with_version(Ss58AddressFormat::custom(self.ss58_format))
+    }
+
+    /// Presents a balance in the smallest unit as tokens, eg. `1.25 UNIT` for 1250000000000
+    pub fn format_balance(&self, balance: u128) -> String {
+        let unit = 10u128.pow(self.token_decimals as u32);
+        let fraction = format!(
+            "{:0width$}",
+            balance % unit,
+            width = self.token_decimals as usize
+        );
+        let fraction = fraction.trim_end_matches('0');
+
+        if fraction.is_empty() {
+            format!("{} {}", balance / unit, self.token_symbol)
+        } else {
+            format!("{}.{} {}", balance / unit, fraction, self.token_symbol)
+        }
+    }
+}
+
+/// Connects to a parachain node and discovers the properties of its chain, so that the miner presents accounts and
+/// balances the way the Cyborg network it is connected to does, without being built for it. A node that doesn't
+/// report its properties leaves the generic Substrate ones.
+///
+/// # Arguments
+/// * `parachain_url` - The URL of the parachain node to connect to
+///
+/// # Returns
+/// The connected client, or an `Error` if the node can't be reached
+pub async fn connect(parachain_url: &str) -> Result<OnlineClient<PolkadotConfig>> {
+    let rpc = RpcClient::from_url(parachain_url).await?;
+
+    let properties = match LegacyRpcMethods::<PolkadotConfig>::new(rpc.clone())
+        .system_properties()
+        .await
+    {
+        Ok(properties) => ChainProperties::from_system_properties(&properties),
+        Err(e) => {
+            println!(
+                "Failed to discover the chain properties, using the Substrate defaults: {}",
+                e
+            );
+            ChainProperties::from_system_properties(&Map::new())
+        }
+    };
+    // All miners of a process are connected to the same chain
+    if CHAIN_PROPERTIES.set(properties.clone()).is_ok() {
+        println!(
+            "Chain properties: SS58 prefix {}, token {} with {} decimals",
+            properties.ss58_format, properties.token_symbol, properties.token_decimals
+        );
+    }
+
+    Ok(OnlineClient::<PolkadotConfig>::from_rpc_client(rpc).await?)
+}
+
+/// The properties of the connected chain, the generic Substrate ones before the miner connected
+pub fn get() -> ChainProperties {
+    CHAIN_PROPERTIES.get().cloned().unwrap_or_default()
+}
+
+/// Encodes the account as an address of the connected chain
+pub fn format_account(account: &AccountId32) -> String {
+    get().format_account(account)
+}
+
+/// Presents a balance in the smallest unit as tokens of the connected chain
+pub fn format_balance(balance: u128) -> String {
+    get().format_balance(balance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounts_and_balances_follow_the_chain_properties() {
+        let properties = ChainProperties::from_system_properties(
+            serde_json::json!({
+                "ss58Format": 0,
+                "tokenDecimals": [10, 12],
+                "tokenSymbol": ["DOT", "OTHER"],
+            })
+            .as_object()
+            .unwrap(),
+        );
+        assert_eq!(
+            properties,
+            ChainProperties {
+                ss58_format: 0,
+                token_decimals: 10,
+                token_symbol: "DOT".to_string(),
+            }
+        );
+
+        assert_eq!(properties.format_balance(12_500_000_000), "1.25 DOT");
+        assert_eq!(properties.format_balance(20_000_000_000), "2 DOT");
+        assert_eq!(properties.format_balance(1), "0.0000000001 DOT");
+
+        let account = AccountId32([0u8; 32]);
+        assert_ne!(
+            properties.format_account(&account),
+            ChainProperties::default().format_account(&account)
+        );
+        assert_eq!(
+            ChainProperties::default().format_account(&account),
+            account.to_string()
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::error::{Error, Result};
+use crate::substrate_interface::api::{
+    self, neuro_zk, runtime_types::bounded_collections::bounded_vec::BoundedVec,
+    runtime_types::cyborg_primitives::worker::WorkerType, task_management,
+};
+use subxt::{events::StaticEvent, utils::AccountId32, Metadata, OnlineClient, PolkadotConfig};
+
+/// The newest metadata format the interfaces are decoded with, chains that only serve older formats fall back to V14
+const NEWEST_METADATA_VERSION: u32 = 15;
+
+/// A part of the interface the miner uses and whether the metadata of the chain still matches it
+type Probe = (&'static str, std::result::Result<(), subxt::Error>);
+
+/// A parachain runtime this binary carries a generated interface for. Supporting another runtime, eg. the previous
+/// one during an upgrade, means generating its interface into a module of its own and adding it to
+/// `SUPPORTED_RUNTIMES`.
+struct SupportedRuntime {
+    name: &'static str,
+    /// Whether the metadata of the chain matches the generated interface exactly
+    matches: fn(&Metadata) -> bool,
+    /// The storage entries and calls the miner uses, checked one by one if the metadata doesn't match exactly
+    probes: fn(&OnlineClient<PolkadotConfig>) -> Vec<Probe>,
+}
+
+const SUPPORTED_RUNTIMES: &[SupportedRuntime] = &[
+    SupportedRuntime {
+        name: "cyborg-runtime",
+        matches: api::is_codegen_valid_for,
+        probes: current_probes,
+    },
+    // The runtime before the Payment pallet was exposed to miners. Tasks are served as on the current runtime, only
+    // the rewards command fails. There is no generated interface of its own, so it never matches exactly.
+    SupportedRuntime {
+        name: "cyborg-runtime-previous",
+        matches: |_| false,
+        probes: serving_probes,
+    },
+];
+
+/// The runtime of the parachain the miner talks to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectedRuntime {
+    /// The generated interface used for the runtime
+    pub interface: &'static str,
+    pub spec_version: u32,
+    /// The metadata format the chain is decoded with
+    pub metadata_version: u32,
+    /// Whether the metadata matches the interface exactly, or only the parts the miner uses are unchanged
+    pub exact: bool,
+}
+
+/// Detects the runtime of the chain the client is connected to and picks the interface that decodes it. A runtime
+/// upgrade that leaves the pallets the miner uses untouched keeps working, one that changes them fails here instead
+/// of when the first event or transaction can't be decoded.
+///
+/// # Arguments
+/// * `client` - The freshly connected parachain client
+///
+/// # Returns
+/// The `DetectedRuntime`, or an `Error` naming what the chain changed if no interface of this binary decodes it
+pub async fn detect(client: &OnlineClient<PolkadotConfig>) -> Result<DetectedRuntime> {
+    let metadata = client.metadata();
+    let spec_version = client.runtime_version().spec_version;
+    let metadata_version = metadata_version(client).await;
+
+    if let Some(runtime) = SUPPORTED_RUNTIMES
+        .iter()
+        .find(|runtime| (runtime.matches)(&metadata))
+    {
+        return Ok(DetectedRuntime {
+            interface: runtime.name,
+            spec_version,
+            metadata_version,
+            exact: true,
+        });
+    }
+
+    let mut incompatible = Vec::new();
+    for runtime in SUPPORTED_RUNTIMES {
+        let changed: Vec<&str> = (runtime.probes)(client)
+            .into_iter()
+            .filter_map(|(item, validation)| validation.err().map(|_| item))
+            .collect();
+        if changed.is_empty() {
+            println!(
+                "The parachain runtime (spec version {}) differs from the {} interface, but not in anything the miner uses",
+                spec_version, runtime.name
+            );
+            return Ok(DetectedRuntime {
+                interface: runtime.name,
+                spec_version,
+                metadata_version,
+                exact: false,
+            });
+        }
+        incompatible.push(format!("{}: {}", runtime.name, changed.join(", ")));
+    }
+
+    Err(Error::Custom(format!(
+        "The parachain runs runtime spec version {}, which this miner can't decode (changed since {}). The chain is \
+         ahead of the miner, update it to a release built for this runtime.",
+        spec_version,
+        incompatible.join("; ")
+    )))
+}
+
+/// The metadata format the client decodes the chain with: the newest one both support, V14 for chains that don't
+/// list their formats
+async fn metadata_version(client: &OnlineClient<PolkadotConfig>) -> u32 {
+    let versions = match client.runtime_api().at_latest().await {
+        Ok(runtime_api) => runtime_api
+            .call_raw::<Vec<u32>>("Metadata_metadata_versions", None)
+            .await
+            .unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+
+    newest_common_version(&versions)
+}
+
+fn newest_common_version(versions: &[u32]) -> u32 {
+    versions
+        .iter()
+        .copied()
+        .filter(|version| *version <= NEWEST_METADATA_VERSION)
+        .max()
+        .unwrap_or(14)
+}
+
+/// Everything the miner uses on the current runtime, serving tasks and claiming rewards
+fn current_probes(client: &OnlineClient<PolkadotConfig>) -> Vec<Probe> {
+    let storage = client.storage();
+    let tx = client.tx();
+    let account = AccountId32([0u8; 32]);
+
+    let mut probes = serving_probes(client);
+    probes.extend([
+        (
+            "Payment::SubscriptionFee",
+            storage.validate(&api::storage().payment().subscription_fee()),
+        ),
+        (
+            "Payment::MinerPendingRewards",
+            storage.validate(&api::storage().payment().miner_pending_rewards(&account)),
+        ),
+        (
+            "Payment::ActiveRewardRates",
+            storage.validate(&api::storage().payment().active_reward_rates(&account)),
+        ),
+        (
+            "Payment::distribute_rewards",
+            tx.validate(&api::tx().payment().distribute_rewards()),
+        ),
+    ]);
+    probes
+}
+
+/// What the miner needs to register, be scheduled tasks and prove them
+fn serving_probes(client: &OnlineClient<PolkadotConfig>) -> Vec<Probe> {
+    let storage = client.storage();
+    let tx = client.tx();
+    let metadata = client.metadata();
+
+    vec![
+        (
+            "TaskManagement::Tasks",
+            storage.validate(&api::storage().task_management().tasks(0)),
+        ),
+        (
+            "TaskManagement::TaskAllocations",
+            storage.validate(&api::storage().task_management().task_allocations_iter()),
+        ),
+        (
+            "TaskManagement::GatekeeperAccount",
+            storage.validate(&api::storage().task_management().gatekeeper_account()),
+        ),
+        (
+            "EdgeConnect::ExecutableWorkers",
+            storage.validate(&api::storage().edge_connect().executable_workers_iter()),
+        ),
+        (
+            "EdgeConnect::register_worker",
+            tx.validate(&api::tx().edge_connect().register_worker(
+                WorkerType::Executable,
+                BoundedVec(Vec::new()),
+                0,
+                0,
+                0,
+                0,
+                0,
+            )),
+        ),
+        (
+            "EdgeConnect::remove_worker",
+            tx.validate(
+                &api::tx()
+                    .edge_connect()
+                    .remove_worker(WorkerType::Executable, 0),
+            ),
+        ),
+        (
+            "TaskManagement::confirm_task_reception",
+            tx.validate(&api::tx().task_management().confirm_task_reception(0)),
+        ),
+        (
+            "TaskManagement::confirm_miner_vacation",
+            tx.validate(&api::tx().task_management().confirm_miner_vacation(0)),
+        ),
+        (
+            "NeuroZk::submit_proof",
+            tx.validate(&api::tx().neuro_zk().submit_proof(0, BoundedVec(Vec::new()))),
+        ),
+        (
+            "TaskManagement::TaskScheduled",
+            validate_event::<task_management::events::TaskScheduled>(
+                &metadata,
+                &[
+                    "assigned_worker",
+                    "task_kind",
+                    "task_owner",
+                    "task_id",
+                    "task",
+                ],
+            ),
+        ),
+        (
+            "NeuroZk::NzkProofRequested",
+            validate_event::<neuro_zk::events::NzkProofRequested>(
+                &metadata,
+                &["requesting_account", "task_id"],
+            ),
+        ),
+    ]
+}
+
+/// Checks that the chain emits an event with the fields of the generated interface. Unlike storage entries and calls,
+/// subxt can't validate events against the metadata, so their field names are compared.
+///
+/// # Arguments
+/// * `metadata` - The metadata of the chain
+/// * `fields` - The fields of the event in the generated interface, in order
+fn validate_event<E: StaticEvent>(
+    metadata: &Metadata,
+    fields: &[&str],
+) -> std::result::Result<(), subxt::Error> {
+    let variant = metadata
+        .pallet_by_name(E::PALLET)
+        .and_then(|pallet| pallet.event_variants())
+        .and_then(|variants| variants.iter().find(|variant| variant.name == E::EVENT))
+        .ok_or_else(|| {
+            subxt::Error::Other(format!("{}::{} is not emitted", E::PALLET, E::EVENT))
+        })?;
+
+    let names: Vec<Option<&str>> = variant
+        .fields
+        .iter()
+        .map(|field| field.name.as_deref())
+        .collect();
+    if !event_fields_match(&names, fields) {
+        return Err(subxt::Error::Other(format!(
+            "{}::{} has changed its fields",
+            E::PALLET,
+            E::EVENT
+        )));
+    }
+    Ok(())
+}
+
+fn event_fields_match(names: &[Option<&str>], fields: &[&str]) -> bool {
+    names.len() == fields.len()
+        && names
+            .iter()
+            .zip(fields)
+            .all(|(name, field)| *name == Some(*field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_newest_common_metadata_version_is_used() {
+        assert_eq!(newest_common_version(&[14, 15]), 15);
+        assert_eq!(newest_common_version(&[14, 15, 16]), 15);
+        assert_eq!(newest_common_version(&[]), 14);
+    }
+
+    #[test]
+    fn events_match_by_their_field_names() {
+        let fields = ["requesting_account", "task_id"];
+
+        assert!(event_fields_match(
+            &[Some("requesting_account"), Some("task_id")],
+            &fields
+        ));
+        assert!(!event_fields_match(&[Some("task_id")], &fields));
+        assert!(!event_fields_match(
+            &[Some("task_id"), Some("requesting_account")],
+            &fields
+        ));
+        assert!(!event_fields_match(&[None, None], &fields));
+    }
+}
@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser, PartialEq)]
 #[command(
@@ -26,16 +27,175 @@ pub enum Commands {
     /// Start the worker with specified API URL and IPFS URL.
     StartMiner {
         /// API URL for starting the worker
-        #[clap(long, value_name = "API_URL")]
-        parachain_url: String,
+        #[clap(long, value_name = "API_URL", required_unless_present = "simulate")]
+        parachain_url: Option<String>,
 
         /// Account ID for the worker registration.
         #[clap(long, value_name = "ACCOUNT_SEED")]
         account_seed: String,
+
+        /// Play the chain events of a JSON scenario file instead of connecting to a parachain, for local development
+        #[clap(long, value_name = "SCENARIO", conflicts_with = "parachain_url")]
+        simulate: Option<PathBuf>,
         //// IPFS URL for the worker.
         //#[clap(long, value_name = "IPFS_URL")]
         //ipfs_url: String,
     },
+
+    /// Start several miners from one process, as listed in a fleet configuration.
+    StartFleet {
+        /// API URL for starting the miners, the connection is shared by all of them
+        #[clap(long, value_name = "API_URL")]
+        parachain_url: String,
+
+        /// JSON file listing the account seed, base directory and inference port of every miner
+        #[clap(long, value_name = "FLEET_CONFIG")]
+        fleet_config: PathBuf,
+    },
+
+    /// Inspect tasks on the parachain.
+    Task {
+        #[command(subcommand)]
+        command: TaskCommands,
+    },
+
+    /// Show and claim the rewards of the miner.
+    Rewards {
+        #[command(subcommand)]
+        command: RewardsCommands,
+    },
+
+    /// Move the state of the miner to new hardware.
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+
+    /// Monitor a running miner live in the terminal.
+    Top {
+        /// Admin API of the miner, it listens on 127.0.0.1 at `ADMIN_PORT`
+        #[clap(
+            long,
+            value_name = "ADMIN_URL",
+            default_value = "http://127.0.0.1:7300"
+        )]
+        admin_url: String,
+    },
+
+    /// Check that the host can run the miner and print a pass/fail report, nothing is registered.
+    Preflight {
+        /// API URL of the parachain node to check connectivity and the clock against, `PARACHAIN_URL` if not given
+        #[clap(long, value_name = "API_URL")]
+        parachain_url: Option<String>,
+    },
+
+    /// Generate a proof for the NeuroZK task of a running miner now and write it locally, nothing is submitted.
+    ProveNow {
+        /// Admin API of the miner, it listens on 127.0.0.1 at `ADMIN_PORT`
+        #[clap(
+            long,
+            value_name = "ADMIN_URL",
+            default_value = "http://127.0.0.1:7300"
+        )]
+        admin_url: String,
+
+        /// The task to prove, needed only if the miner serves several NeuroZK tasks
+        #[clap(long, value_name = "TASK_ID")]
+        task_id: Option<u64>,
+
+        /// Path the proof and its timings are written to
+        #[clap(long, value_name = "OUT", default_value = "proof.json")]
+        out: PathBuf,
+    },
+
+    /// Move the identity, task state and logs of a worker node installation to the paths of the miner.
+    MigrateFromWorker {
+        /// Directory of the worker node
+        #[clap(
+            long,
+            value_name = "WORKER_DIR",
+            default_value = "/var/lib/cyborg/worker-node"
+        )]
+        worker_dir: PathBuf,
+
+        /// API URL of the parachain node the registration of the worker is checked with
+        #[clap(long, value_name = "API_URL")]
+        parachain_url: String,
+
+        /// Replace the identity of this miner if it already has one
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Serve an EZKL job in a child process of the miner, started by the miner itself.
+    #[command(hide = true)]
+    ProverJob,
+}
+
+/// `TaskCommands` enum defines the subcommands for inspecting tasks, they only query the parachain.
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum TaskCommands {
+    /// Show the definition, assignment, status and proof state of a task.
+    Info {
+        /// The id of the task
+        task_id: u64,
+
+        /// API URL of the parachain node to query
+        #[clap(long, value_name = "API_URL")]
+        parachain_url: String,
+    },
+}
+
+/// `RewardsCommands` enum defines the subcommands for the rewards of a miner account.
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum RewardsCommands {
+    /// Show the pending rewards, reward rates and the proof state of the allocated tasks.
+    Show {
+        /// API URL of the parachain node to query
+        #[clap(long, value_name = "API_URL")]
+        parachain_url: String,
+
+        /// Account seed of the miner
+        #[clap(long, value_name = "ACCOUNT_SEED")]
+        account_seed: String,
+    },
+
+    /// Submit the distribution of the pending rewards, if the parachain allows the miner to trigger it.
+    Claim {
+        /// API URL of the parachain node to submit to
+        #[clap(long, value_name = "API_URL")]
+        parachain_url: String,
+
+        /// Account seed of the miner
+        #[clap(long, value_name = "ACCOUNT_SEED")]
+        account_seed: String,
+    },
+}
+
+/// `SnapshotCommands` enum defines the subcommands for bundling and restoring the state of a miner.
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum SnapshotCommands {
+    /// Bundle the identity, task owner, response anchors and task state into a tarball.
+    Create {
+        /// Path of the snapshot to write
+        #[clap(long, value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Also bundle the model files of the current task
+        #[clap(long)]
+        include_models: bool,
+    },
+
+    /// Restore a snapshot to the paths configured in the environment.
+    Restore {
+        /// Path of the snapshot to restore
+        #[clap(long, value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Replace the identity of this miner if it already has one
+        #[clap(long)]
+        force: bool,
+    },
 }
 
 /*
@@ -1,14 +1,21 @@
 use once_cell::sync::Lazy;
+use open_inference_runtime::{ComponentCache, HttpOptions, PluginLimits};
 use once_cell::sync::OnceCell;
-use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use subxt_signer::sr25519::Keypair;
+use std::collections::HashMap;
+use std::future::Future;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{env, path::PathBuf};
-use subxt::utils::AccountId32;
 use subxt::OnlineClient;
 use subxt::PolkadotConfig;
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 
+use crate::chain_properties;
+use crate::chain_spec;
 use crate::error::{Error, Result};
 use crate::utils::tx_queue::TransactionQueue;
 use crate::utils::tx_queue::TRANSACTION_QUEUE;
@@ -28,17 +35,27 @@ pub struct Paths {
     pub identity_path: String,
 }
 
-#[derive(Deserialize, Debug)]
-#[allow(dead_code)]
-struct MinerIdentity {
-    owner: AccountId32,
-    id: u32,
-}
-
 // We're setting a few global variables here for easy access throughout
 pub static PATHS: OnceCell<Paths> = OnceCell::new();
 pub static STORAGE_LOCATION: OnceCell<String> = OnceCell::new();
 pub static PARACHAIN_CLIENT: OnceCell<OnlineClient<PolkadotConfig>> = OnceCell::new();
+pub static CONFIG_ENCRYPTION_KEY: OnceCell<[u8; 32]> = OnceCell::new();
+static SHARED_CONFIG: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+/// The environment of the process before `.env` was loaded into it
+static PROCESS_ENV: OnceCell<HashMap<String, String>> = OnceCell::new();
+
+/// The configuration that differs between the miners of a fleet, everything else is shared by the process
+#[derive(Debug)]
+pub struct MemberContext {
+    pub paths: Paths,
+    pub config_encryption_key: Option<[u8; 32]>,
+}
+
+tokio::task_local! {
+    /// The fleet member the current task runs for, takes precedence over `PATHS` and `CONFIG_ENCRYPTION_KEY`
+    static MEMBER_CONTEXT: &'static MemberContext;
+}
+
 #[allow(dead_code)]
 pub static CESS_GATEWAY: Lazy<Arc<RwLock<String>>> =
     Lazy::new(|| Arc::new(RwLock::new(String::from("https://deoss-sgp.cess.network"))));
@@ -48,18 +65,96 @@ pub static CESS_GATEWAY: Lazy<Arc<RwLock<String>>> =
 /// # Arguments
 /// * `parachain_url` - A string representing the URL of the parachain node to connect to.
 /// * `account_seed` - A string representing the seed phrase for generating the keypair.
-pub async fn run_config(parachain_url: &str, _account: Keypair) {
+pub async fn run_config(parachain_url: &str, account: Keypair) {
+    try_run_config(parachain_url, account)
+        .await
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+/// Runs the configuration for the miner like `run_config`, for callers that handle a failed setup themselves, eg. an
+/// application embedding the miner. Nothing is set up if the setup fails, so it can be run again.
+///
+/// # Returns
+/// `Ok(())` once the miner is configured, or an `Error` if a setting is missing or the parachain can't be reached
+pub async fn try_run_config(parachain_url: &str, _account: Keypair) -> Result<()> {
+    load_dotenv();
+
+    let paths = try_paths_from_env()?;
+    try_init_shared_config(parachain_url).await?;
+
+    PATHS
+        .set(paths)
+        .map_err(|_| Error::Custom("Paths are already initialized!".to_string()))
+}
+
+/// Loads `.env` into the environment of the process. Variables the process was started with take precedence, they
+/// are remembered so that settings read from `.env` again later can still tell them apart.
+pub fn load_dotenv() {
+    PROCESS_ENV.get_or_init(|| env::vars().collect());
     dotenv::dotenv().ok();
+}
 
-    let storage_location = String::from(env::var("STORAGE_LOCATION").expect("STORAGE_LOCATION must be set"));
-    let log_path = PathBuf::from(env::var("LOG_FILE_PATH").expect("LOG_PATH must be set"));
-    let task_file_name =
-        String::from(env::var("TASK_FILE_NAME").expect("TASK_FILE_NAME must be set"));
-    let task_dir_path = String::from(env::var("TASK_DIR_PATH").expect("TASK_DIR_PATH must be set"));
-    let identity_path =
-        String::from(env::var("IDENTITY_FILE_PATH").expect("IDENTITY_PATH must be set"));
-    let task_owner_path =
-        String::from(env::var("TASK_OWNER_FILE_PATH").expect("TASK_OWNER_PATH must be set"));
+/// A variable the process was started with, as opposed to one loaded from `.env`
+pub fn process_env(key: &str) -> Option<String> {
+    match PROCESS_ENV.get() {
+        Some(process_env) => process_env.get(key).cloned(),
+        None => env::var(key).ok(),
+    }
+}
+
+/// Reads the file locations of the miner from the environment, fails fast if one of them is not set
+pub fn paths_from_env() -> Paths {
+    try_paths_from_env().unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Reads the file locations of the miner from the environment
+///
+/// # Returns
+/// The `Paths`, or an `Error` naming the first location that is not set
+pub fn try_paths_from_env() -> Result<Paths> {
+    let required = |key: &str| {
+        env::var(key).map_err(|_| Error::Custom(format!("{} must be set", key)))
+    };
+
+    Ok(Paths {
+        log_path: PathBuf::from(required("LOG_FILE_PATH")?),
+        task_file_name: required("TASK_FILE_NAME")?,
+        task_dir_path: required("TASK_DIR_PATH")?,
+        task_owner_path: required("TASK_OWNER_FILE_PATH")?,
+        identity_path: required("IDENTITY_FILE_PATH")?,
+    })
+}
+
+/// Sets up the configuration shared by all miners of the process: the storage location, the parachain client and the
+/// transaction queue. Fails fast like `run_config`. Only the first call sets it up, miners embedded into the same
+/// process share it.
+///
+/// # Arguments
+/// * `parachain_url` - A string representing the URL of the parachain node to connect to, unless `PARACHAIN_URL` is set.
+pub async fn init_shared_config(parachain_url: &str) {
+    try_init_shared_config(parachain_url)
+        .await
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+/// Sets up the configuration shared by all miners of the process like `init_shared_config`, for callers that handle a
+/// failed setup themselves. A setup that failed is attempted again by the next call.
+///
+/// # Returns
+/// `Ok(())` once the shared configuration is set up, or an `Error` if a setting is missing or the parachain can't be
+/// reached
+pub async fn try_init_shared_config(parachain_url: &str) -> Result<()> {
+    SHARED_CONFIG
+        .get_or_try_init(|| connect_shared_config(parachain_url))
+        .await?;
+    Ok(())
+}
+
+async fn connect_shared_config(parachain_url: &str) -> Result<()> {
+    load_dotenv();
+
+    let storage_location = env::var("STORAGE_LOCATION")
+        .map_err(|_| Error::Custom("STORAGE_LOCATION must be set".to_string()))?;
     let parachain_url = if let Ok(parachain_url_env) = env::var("PARACHAIN_URL") {
         parachain_url_env
     } else {
@@ -68,31 +163,283 @@ pub async fn run_config(parachain_url: &str, _account: Keypair) {
 
     println!("Using parachain URL: {}", parachain_url);
 
-    PATHS
-        .set(Paths {
-            log_path,
-            task_file_name,
-            task_dir_path,
-            task_owner_path,
-            identity_path,
-        })
-        .expect("Paths are already initialized!");
+    try_init_parachain_client(&parachain_url).await?;
+
+    STORAGE_LOCATION
+        .set(storage_location)
+        .map_err(|_| Error::Custom("Storage location is already initialized!".to_string()))
+}
+
+/// Connects the parachain client and sets up the transaction queue, enough for commands that only submit transactions
+///
+/// # Arguments
+/// * `parachain_url` - A string representing the URL of the parachain node to connect to.
+pub async fn init_parachain_client(parachain_url: &str) {
+    try_init_parachain_client(parachain_url)
+        .await
+        .unwrap_or_else(|e| panic!("{}", e));
+}
 
-    let client = OnlineClient::<PolkadotConfig>::from_url(parachain_url)
+/// Connects the parachain client and sets up the transaction queue like `init_parachain_client`, for callers that
+/// handle a failed connection themselves
+///
+/// # Returns
+/// `Ok(())` once the client is connected, or an `Error` if the parachain can't be reached or runs an unknown runtime
+pub async fn try_init_parachain_client(parachain_url: &str) -> Result<()> {
+    let client = chain_properties::connect(parachain_url)
         .await
-        .expect("Failed to connect to parachain node");
+        .map_err(|e| Error::Custom(format!("Failed to connect to parachain node: {}", e)))?;
 
-    if let Err(_) = TRANSACTION_QUEUE.set(TransactionQueue::new()) {
-        panic!("Failed to set transaction queue.");
+    let runtime = chain_spec::detect(&client).await?;
+    println!(
+        "Parachain runtime spec version {} (metadata V{}), decoded with the {} interface{}",
+        runtime.spec_version,
+        runtime.metadata_version,
+        runtime.interface,
+        if runtime.exact { "" } else { " (partial match)" }
+    );
+
+    if TRANSACTION_QUEUE.get().is_some() || PARACHAIN_CLIENT.get().is_some() {
+        return Err(Error::Custom("Client is already initialized!".to_string()));
     }
+    let _ = TRANSACTION_QUEUE.set(TransactionQueue::new());
+    let _ = PARACHAIN_CLIENT.set(client);
 
+    Ok(())
+}
+
+/// Sets up the configuration of a simulated run: the paths, the storage location and the transaction queue, without
+/// connecting to a parachain. Fails fast like `run_config`.
+pub fn init_simulation_config() {
+    load_dotenv();
+
+    PATHS
+        .set(paths_from_env())
+        .expect("Paths are already initialized!");
+
+    let storage_location = String::from(env::var("STORAGE_LOCATION").expect("STORAGE_LOCATION must be set"));
     STORAGE_LOCATION
         .set(storage_location)
         .expect("Storage location is already initialized!");
 
-    PARACHAIN_CLIENT
-        .set(client)
-        .expect("Client is already initialized!");
+    if TRANSACTION_QUEUE.set(TransactionQueue::new()).is_err() {
+        panic!("Failed to set transaction queue.");
+    }
+}
+
+/// Derives the key for sensitive config files from the account seed, if `ENCRYPT_CONFIG_FILES` is enabled.
+/// Has to run before any identity or task owner file is read.
+///
+/// # Arguments
+/// * `account_seed` - The secret URI the miner keypair is derived from
+///
+/// # Returns
+/// `Ok(())` once the key is set up, or an `Error` if `ENCRYPT_CONFIG_FILES` is invalid
+pub fn init_config_encryption(account_seed: &str) -> Result<()> {
+    if let Some(key) = derive_config_encryption_key(account_seed)? {
+        CONFIG_ENCRYPTION_KEY
+            .set(key)
+            .map_err(|_| {
+                Error::Custom("Config encryption key is already initialized!".to_string())
+            })?;
+    }
+    Ok(())
+}
+
+/// Derives the config file key of a keypair
+///
+/// # Returns
+/// The key, `None` if `ENCRYPT_CONFIG_FILES` is not enabled, or an `Error` if it is invalid
+pub fn derive_config_encryption_key(account_seed: &str) -> Result<Option<[u8; 32]>> {
+    if !security_env("ENCRYPT_CONFIG_FILES", false)? {
+        return Ok(None);
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"cyborg-miner/config-encryption");
+    hasher.update(account_seed.as_bytes());
+
+    Ok(Some(hasher.finalize().into()))
+}
+
+pub fn get_config_encryption_key() -> Option<&'static [u8; 32]> {
+    match MEMBER_CONTEXT.try_with(|context| *context) {
+        Ok(context) => context.config_encryption_key.as_ref(),
+        Err(_) => CONFIG_ENCRYPTION_KEY.get(),
+    }
+}
+
+/// Runs `future` for a fleet member, `get_paths` and `get_config_encryption_key` resolve to the member inside of it
+pub async fn in_member_context<F: Future>(context: &'static MemberContext, future: F) -> F::Output {
+    MEMBER_CONTEXT.scope(context, future).await
+}
+
+/// Spawns a task that keeps the fleet member context of the caller, tasks spawned with `tokio::spawn` lose it
+pub fn spawn_in_context<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match MEMBER_CONTEXT.try_with(|context| *context) {
+        Ok(context) => tokio::spawn(MEMBER_CONTEXT.scope(context, future)),
+        Err(_) => tokio::spawn(future),
+    }
+}
+
+/// Reads an optional setting from the environment, falling back to `default` if it is not set. A value that can't be
+/// parsed falls back too, with a warning.
+pub fn optional_env<T: FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(value) => value.parse::<T>().unwrap_or_else(|_| {
+            println!("Ignoring {}='{}', it can't be parsed, using the default", key, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Reads an optional setting that weakens or hardens the miner, eg. whether requests are authenticated, falling back
+/// to `default` if it is not set
+///
+/// # Returns
+/// The setting, or an `Error` if it can't be parsed, so that a typo never silently falls back to a weaker default
+pub fn security_env<T: FromStr>(key: &str, default: T) -> Result<T> {
+    match env::var(key) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<T>()
+            .map_err(|_| Error::Custom(format!("Invalid {}: '{}' can't be parsed", key, value))),
+        _ => Ok(default),
+    }
+}
+
+/// Hosts that are reached without the proxy unless `NO_PROXY_HOSTS` is set: the local Triton server and the
+/// addresses and names of the tailnet
+const DEFAULT_NO_PROXY_HOSTS: &str = "localhost,127.0.0.1,::1,100.64.0.0/10,.ts.net";
+
+/// Sent with every request of the miner, so that storage and lookup services can tell miners and their versions apart
+const USER_AGENT: &str = concat!("cyborg-miner/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_HTTP_RETRIES: u32 = 2;
+const HTTP_RETRY_BASE_DELAY_MS: u64 = 500;
+
+/// The client shared by every request that doesn't need settings of its own, so connections are pooled across modules
+static HTTP_CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+
+/// Builder of every HTTP client of the miner, so that storage downloads, IP and location lookups and all other
+/// outbound requests go through the proxy in `PROXY_URL` (`http://`, `https://` or `socks5://`) if one is configured.
+/// Hosts in the comma separated `NO_PROXY_HOSTS` are reached directly. No request can hang forever:
+/// - `HTTP_CONNECT_TIMEOUT_SECS`: Longest wait for a connection (default 10)
+/// - `HTTP_READ_TIMEOUT_SECS`: Longest wait for the next bytes of a response (default 60), a download that keeps
+///   receiving runs as long as it needs
+/// - `HTTP_POOL_MAX_IDLE_PER_HOST`: Idle connections kept open per host (default 8)
+pub fn http_client_builder() -> Result<reqwest::ClientBuilder> {
+    let builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(optional_env("HTTP_CONNECT_TIMEOUT_SECS", 10)))
+        .read_timeout(Duration::from_secs(optional_env("HTTP_READ_TIMEOUT_SECS", 60)))
+        .pool_max_idle_per_host(optional_env("HTTP_POOL_MAX_IDLE_PER_HOST", 8));
+
+    let proxy_url = optional_env("PROXY_URL", String::new());
+    if proxy_url.is_empty() {
+        return Ok(builder);
+    }
+
+    let no_proxy_hosts = optional_env("NO_PROXY_HOSTS", DEFAULT_NO_PROXY_HOSTS.to_string());
+    let proxy = reqwest::Proxy::all(&proxy_url)
+        .map_err(|e| Error::Custom(format!("Invalid PROXY_URL {}: {}", proxy_url, e)))?
+        .no_proxy(reqwest::NoProxy::from_string(&no_proxy_hosts));
+
+    Ok(builder.proxy(proxy))
+}
+
+/// The shared HTTP client with the proxy and timeout settings of the miner, see `http_client_builder`
+pub fn http_client() -> Result<reqwest::Client> {
+    // Cloning shares the connection pool
+    HTTP_CLIENT
+        .get_or_try_init(|| Ok(http_client_builder()?.build()?))
+        .cloned()
+}
+
+/// Sends a request, retrying connection failures, timeouts and `5xx`/`429` responses up to `HTTP_RETRIES` times (default
+/// 2) with exponential backoff. Only for idempotent requests, eg. lookups and uploads to a fixed name.
+///
+/// # Returns
+/// The last response, or the `Error` of the last attempt
+pub async fn send_with_retries(request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    let retries = optional_env("HTTP_RETRIES", DEFAULT_HTTP_RETRIES);
+    let mut attempt = 0;
+    loop {
+        // Streamed bodies can't be sent twice
+        let Some(attempt_request) = request.try_clone() else {
+            return Ok(request.send().await?);
+        };
+        let result = attempt_request.send().await;
+        let transient = match &result {
+            Ok(response) => {
+                response.status().is_server_error()
+                    || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+        if !transient || attempt >= retries {
+            return Ok(result?);
+        }
+
+        attempt += 1;
+        tokio::time::sleep(Duration::from_millis(
+            HTTP_RETRY_BASE_DELAY_MS << (attempt - 1),
+        ))
+        .await;
+    }
+}
+
+/// Where the Triton server every task loads its models into is reached, `TRITON_URL` (default
+/// `http://localhost:8000/v2`)
+pub fn triton_url() -> String {
+    optional_env("TRITON_URL", "http://localhost:8000/v2".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Settings of the connections to Triton, the same as those of the other HTTP clients of the miner
+pub fn triton_http_options() -> HttpOptions {
+    HttpOptions {
+        connect_timeout: Duration::from_secs(optional_env("HTTP_CONNECT_TIMEOUT_SECS", 10)),
+        pool_max_idle_per_host: optional_env("HTTP_POOL_MAX_IDLE_PER_HOST", 8),
+        user_agent: USER_AGENT.to_string(),
+    }
+}
+
+/// The most the WebAssembly plugins of a task may use, whatever its manifest asks for. Configurable with
+/// `PLUGIN_MAX_FUEL` and `PLUGIN_MAX_MEMORY_BYTES`, default to the defaults of a plugin.
+pub fn plugin_limits() -> PluginLimits {
+    let defaults = PluginLimits::default();
+    PluginLimits {
+        max_fuel: optional_env("PLUGIN_MAX_FUEL", defaults.max_fuel),
+        max_memory_bytes: optional_env("PLUGIN_MAX_MEMORY_BYTES", defaults.max_memory_bytes),
+    }
+}
+
+/// The cache of model components shared by the tasks of this miner, `None` with `COMPONENT_CACHE=false`. Kept in
+/// `COMPONENT_CACHE_DIR`, by default next to the task directory so that hard links stay on one filesystem and the
+/// cache outlives the task directory. Fleet members sharing a host can point to the same directory.
+pub fn component_cache() -> Result<Option<ComponentCache>> {
+    if !optional_env("COMPONENT_CACHE", true) {
+        return Ok(None);
+    }
+
+    let default_dir = PathBuf::from(&get_paths()?.task_dir_path)
+        .parent()
+        .map(|dir| dir.join("component-cache"))
+        .unwrap_or_else(|| PathBuf::from("component-cache"));
+    let dir = optional_env("COMPONENT_CACHE_DIR", default_dir.to_string_lossy().to_string());
+
+    Ok(Some(
+        ComponentCache::new(PathBuf::from(dir)).with_min_size(optional_env(
+            "COMPONENT_CACHE_MIN_BYTES",
+            open_inference_runtime::component_cache::DEFAULT_MIN_COMPONENT_BYTES,
+        )),
+    ))
 }
 
 pub fn get_parachain_client() -> Result<&'static OnlineClient<PolkadotConfig>> {
@@ -114,6 +461,10 @@ pub fn get_tx_queue() -> Result<&'static TransactionQueue> {
 }
 
 pub fn get_paths() -> Result<&'static Paths> {
+    if let Ok(context) = MEMBER_CONTEXT.try_with(|context| *context) {
+        return Ok(&context.paths);
+    }
+
     PATHS.get().ok_or(Error::config_paths_not_initialized())
 }
 
@@ -0,0 +1,257 @@
+use crate::{
+    admin, alerting, builder,
+    config::{self, MemberContext},
+    error::{Error, Result},
+    events::{self, MinerEvent},
+    fleet,
+    parachain_interactor::{fingerprint, identity},
+    parent_runtime::server_control::stop_inference_server,
+    reconcile, schema,
+    traits::ParachainInteractor,
+    utils::{container_monitor, instance_lock, load_shedding},
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+use subxt_signer::{sr25519::Keypair, SecretUri};
+use tokio::sync::{broadcast::error::RecvError, watch, OnceCell};
+
+type EventHook = Arc<dyn Fn(&MinerEvent) + Send + Sync>;
+
+/// A miner embedded into another binary, eg. a custom orchestrator or a GUI, instead of running the CLI.
+///
+/// Settings without a builder method are read from the environment like for the CLI. Miners with a base directory
+/// keep all of their files in it, like the members of a fleet, and can run side by side in one process. A miner
+/// without one uses the file locations of the environment, only one such miner can run per process. A stopped miner
+/// can be run again.
+pub struct Miner {
+    parachain_url: String,
+    account_seed: String,
+    inference_port: Option<u16>,
+    base_dir: Option<PathBuf>,
+    task_file_name: Option<String>,
+    hooks: Vec<EventHook>,
+    stop: watch::Sender<bool>,
+    // Set up by the first run and reused by the next ones, the configuration can only be set up once per process
+    env_config: OnceCell<()>,
+    member_context: OnceLock<&'static MemberContext>,
+}
+
+/// Configures a `Miner`, the parachain URL and the account seed are required
+#[derive(Default)]
+pub struct MinerBuilder {
+    parachain_url: Option<String>,
+    account_seed: Option<String>,
+    inference_port: Option<u16>,
+    base_dir: Option<PathBuf>,
+    task_file_name: Option<String>,
+    hooks: Vec<EventHook>,
+}
+
+impl MinerBuilder {
+    pub fn parachain_url(mut self, url: impl Into<String>) -> Self {
+        self.parachain_url = Some(url.into());
+        self
+    }
+
+    /// Sets the secret URI the keypair of the miner is derived from, eg. a mnemonic or `//Alice`
+    pub fn account_seed(mut self, account_seed: impl Into<String>) -> Self {
+        self.account_seed = Some(account_seed.into());
+        self
+    }
+
+    /// Sets the port of the inference server, 3000 if it is not set
+    pub fn inference_port(mut self, port: u16) -> Self {
+        self.inference_port = Some(port);
+        self
+    }
+
+    /// Keeps the identity, task and log files of the miner below `base_dir` instead of the locations of the environment
+    pub fn base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = Some(base_dir.into());
+        self
+    }
+
+    /// Sets the name task archives are stored under, `TASK_FILE_NAME` if it is not set
+    pub fn task_file_name(mut self, task_file_name: impl Into<String>) -> Self {
+        self.task_file_name = Some(task_file_name.into());
+        self
+    }
+
+    /// Adds a hook that is called with every event of the miner while it runs. Hooks run on a task of their own, so
+    /// they never hold up the miner, but they should return quickly or they miss events.
+    pub fn on_event(mut self, hook: impl Fn(&MinerEvent) + Send + Sync + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// # Returns
+    /// The configured `Miner`, or an `Error` if a required setting is missing
+    pub fn build(self) -> Result<Miner> {
+        let parachain_url = self
+            .parachain_url
+            .ok_or(Error::Custom("A parachain URL is required".to_string()))?;
+        let account_seed = self
+            .account_seed
+            .ok_or(Error::Custom("An account seed is required".to_string()))?;
+
+        Ok(Miner {
+            parachain_url,
+            account_seed,
+            inference_port: self.inference_port,
+            base_dir: self.base_dir,
+            task_file_name: self.task_file_name,
+            hooks: self.hooks,
+            stop: watch::channel(false).0,
+            env_config: OnceCell::new(),
+            member_context: OnceLock::new(),
+        })
+    }
+}
+
+impl Miner {
+    pub fn builder() -> MinerBuilder {
+        MinerBuilder::default()
+    }
+
+    /// Runs the miner until it is stopped, removed from the parachain or fails
+    ///
+    /// # Returns
+    /// `Ok(())` if the miner stopped cleanly, or an `Error` if its setup or the mining session failed
+    pub async fn run(&self) -> Result<()> {
+        let uri = SecretUri::from_str(&self.account_seed)
+            .map_err(|e| Error::Custom(format!("Invalid account seed: {}", e)))?;
+        let keypair = Keypair::from_uri(&uri)
+            .map_err(|e| Error::Custom(format!("Invalid keypair: {}", e)))?;
+
+        if self.base_dir.is_none()
+            && config::PATHS.get().is_some()
+            && !self.env_config.initialized()
+        {
+            return Err(Error::Custom(
+                "A miner configured from the environment is already running in this process"
+                    .to_string(),
+            ));
+        }
+
+        self.stop.send_replace(false);
+        let hook_forwarder = self.forward_events(&keypair);
+        let result = match &self.base_dir {
+            Some(base_dir) => {
+                match config::try_init_shared_config(&self.parachain_url)
+                    .await
+                    .and_then(|_| self.member_context(base_dir))
+                {
+                    Ok(context) => {
+                        config::in_member_context(context, self.run_session(keypair)).await
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            None => {
+                let configured = self
+                    .env_config
+                    .get_or_try_init(|| async {
+                        config::try_run_config(&self.parachain_url, keypair.clone()).await?;
+                        config::init_config_encryption(&self.account_seed)
+                    })
+                    .await;
+                match configured {
+                    Ok(_) => self.run_session(keypair).await,
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        hook_forwarder.abort();
+        result
+    }
+
+    /// Stops a running miner, `run` returns once it stopped. The inference server of its current task is shut down,
+    /// the task stays assigned on chain and is picked up again by the next run.
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    async fn run_session(&self, keypair: Keypair) -> Result<()> {
+        let _instance_lock = instance_lock::acquire(&config::get_paths()?.identity_path).await?;
+        identity::secure_config_files()?;
+        schema::migrate_config_files()?;
+        fingerprint::check_identity_binding()?;
+        reconcile::remove_orphaned_resources()?;
+        load_shedding::start_monitor();
+        admin::start();
+        alerting::start_disk_monitor();
+        container_monitor::start();
+
+        let mut miner_builder = builder::MinerBuilder::default()
+            .parachain_url(self.parachain_url.clone())
+            .keypair(keypair);
+        if let Some(port) = self.inference_port {
+            miner_builder = miner_builder.inference_port(port);
+        }
+        let mut miner = miner_builder.config()?.build().await?;
+
+        let mut stop = self.stop.subscribe();
+        let result = tokio::select! {
+            result = miner.start_miner() => result,
+            _ = stop.wait_for(|stopped| *stopped) => Ok(()),
+        };
+
+        if let Some(current_task) = &miner.current_task {
+            stop_inference_server(current_task.id);
+        }
+
+        result
+    }
+
+    /// The configuration of a miner with a base directory. It lives as long as the process, like the configuration of a
+    /// single miner, so it is created by the first run only.
+    ///
+    /// # Returns
+    /// The context to run the miner in, or an `Error` if the name of task archives is not set or `ENCRYPT_CONFIG_FILES`
+    /// is invalid
+    fn member_context(&self, base_dir: &Path) -> Result<&'static MemberContext> {
+        if let Some(context) = self.member_context.get().copied() {
+            return Ok(context);
+        }
+        let task_file_name = self
+            .task_file_name
+            .clone()
+            .or_else(|| env::var("TASK_FILE_NAME").ok())
+            .ok_or(Error::Custom("TASK_FILE_NAME must be set".to_string()))?;
+        let config_encryption_key = config::derive_config_encryption_key(&self.account_seed)?;
+
+        Ok(*self.member_context.get_or_init(|| {
+            let context: &'static MemberContext = Box::leak(Box::new(MemberContext {
+                paths: fleet::member_paths(base_dir, task_file_name),
+                config_encryption_key,
+            }));
+            context
+        }))
+    }
+
+    /// Calls the hooks with the events of this miner until the returned task is aborted
+    fn forward_events(&self, keypair: &Keypair) -> tokio::task::JoinHandle<()> {
+        let account = keypair.public_key().to_account_id();
+        let hooks = self.hooks.clone();
+        let mut events = events::subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok((miner, event)) if miner == account => {
+                        for hook in &hooks {
+                            hook(&event);
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
@@ -11,6 +11,9 @@ pub enum Error {
     #[from]
     Custom(String),
 
+    /// Work of a task was abandoned, it can't complete before the deadline of the chain
+    DeadlineMissed(String),
+
     // -- Externals
     #[from]
     Io(std::io::Error),
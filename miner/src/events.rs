@@ -0,0 +1,71 @@
+use crate::parachain_interactor::task_deadlines::TaskDeadline;
+use crate::parent_runtime::setup_progress::SetupStage;
+use once_cell::sync::Lazy;
+use subxt::utils::AccountId32;
+use tokio::sync::broadcast;
+
+/// What a miner did, delivered to the event hooks of embedding binaries
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum MinerEvent {
+    /// The miner registered on the parachain and was assigned `miner_id`
+    Registered {
+        miner_id: u64,
+    },
+    /// The chain removed the miner, it stops serving its task
+    Removed,
+    TaskAssigned {
+        task_id: u64,
+    },
+    /// The miner was designated as the backup miner of a task, it prepares the task without serving it
+    StandbyAssigned {
+        task_id: u64,
+    },
+    /// The setup of the assigned task reached a new stage
+    TaskSetup {
+        task_id: u64,
+        stage: SetupStage,
+        detail: Option<String>,
+    },
+    TaskStopped {
+        task_id: u64,
+    },
+    /// A proof was submitted, bound to the hex encoded hash of the transcript of the requests served before it
+    ProofSubmitted {
+        task_id: u64,
+        transcript_hash: String,
+    },
+    ProofRejected {
+        task_id: u64,
+    },
+    /// The miner abandoned work of a task that can't complete before the deadline of the chain
+    DeadlineMissed {
+        task_id: u64,
+        deadline: TaskDeadline,
+        detail: String,
+    },
+    /// A finite task was completed, its results were uploaded and their hex encoded hash submitted
+    TaskCompleted {
+        task_id: u64,
+        result_hash: String,
+    },
+}
+
+/// Events of every miner of the process, tagged with the account of the miner they happened to. Slow hooks lag behind
+/// and miss events instead of holding up the miners.
+static EVENTS: Lazy<broadcast::Sender<(AccountId32, MinerEvent)>> =
+    Lazy::new(|| broadcast::channel(256).0);
+
+/// Publishes an event of a miner, a no-op if nothing listens
+///
+/// # Arguments
+/// * `miner` - The account of the miner the event happened to
+/// * `event` - What happened
+pub fn emit(miner: &AccountId32, event: MinerEvent) {
+    // Sending only fails without subscribers
+    let _ = EVENTS.send((miner.clone(), event));
+}
+
+pub fn subscribe() -> broadcast::Receiver<(AccountId32, MinerEvent)> {
+    EVENTS.subscribe()
+}
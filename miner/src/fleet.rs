@@ -0,0 +1,196 @@
+use crate::{
+    admin, alerting,
+    builder::MinerBuilder,
+    chain_properties,
+    config::{self, MemberContext, Paths},
+    error::{Error, Result},
+    parachain_interactor::{fingerprint, identity},
+    reconcile, schema,
+    traits::ParachainInteractor,
+    utils::{container_monitor, instance_lock, load_shedding},
+};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use subxt_signer::{sr25519::Keypair, SecretUri};
+
+/// Configuration of a fleet, a JSON file listing the logical miners one process runs
+#[derive(Deserialize)]
+struct FleetConfig {
+    members: Vec<FleetMember>,
+}
+
+/// A logical miner of the fleet. Every member has its own keypair, identity, task directory and inference port,
+/// the parachain connection and the transaction queue are shared.
+#[derive(Deserialize)]
+struct FleetMember {
+    account_seed: String,
+    /// Holds the identity, task and log files of the member
+    base_dir: PathBuf,
+    /// The port of the inference server of the member
+    port: u16,
+}
+
+/// Starts every miner of the fleet configuration and runs them until all of them stopped.
+///
+/// # Arguments
+/// * `parachain_url` - The URL of the parachain node all members connect through
+/// * `fleet_config_path` - The path of the fleet configuration
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if all members stopped cleanly, or an `Error` if the configuration is invalid or a member failed.
+pub async fn start_fleet(parachain_url: &str, fleet_config_path: &Path) -> Result<()> {
+    let fleet_config: FleetConfig = serde_json::from_str(&fs::read_to_string(fleet_config_path)?)?;
+    validate(&fleet_config)?;
+
+    config::init_shared_config(parachain_url).await;
+    load_shedding::start_monitor();
+    admin::start();
+    alerting::start_disk_monitor();
+    container_monitor::start();
+    let task_file_name = env::var("TASK_FILE_NAME")
+        .map_err(|_| Error::Custom("TASK_FILE_NAME must be set".to_string()))?;
+
+    println!("Starting a fleet of {} miners", fleet_config.members.len());
+
+    // The members run concurrently on this task, everything that blocks runs on the blocking pool
+    let members = fleet_config
+        .members
+        .into_iter()
+        .map(|member| run_member(parachain_url, member, task_file_name.clone()));
+    let results = futures::future::join_all(members).await;
+
+    let failures = results.iter().filter(|result| result.is_err()).count();
+    if failures > 0 {
+        return Err(Error::Custom(format!("{} fleet members failed", failures)));
+    }
+
+    Ok(())
+}
+
+async fn run_member(parachain_url: &str, member: FleetMember, task_file_name: String) -> Result<()> {
+    let uri = SecretUri::from_str(&member.account_seed)
+        .map_err(|e| Error::Custom(format!("Invalid account seed of a fleet member: {}", e)))?;
+    let keypair = Keypair::from_uri(&uri)
+        .map_err(|e| Error::Custom(format!("Invalid keypair of a fleet member: {}", e)))?;
+    let account = keypair.public_key().to_account_id();
+
+    // Lives as long as the process, like the configuration of a single miner
+    let context: &'static MemberContext = Box::leak(Box::new(MemberContext {
+        paths: member_paths(&member.base_dir, task_file_name),
+        config_encryption_key: config::derive_config_encryption_key(&member.account_seed)?,
+    }));
+
+    let result = config::in_member_context(context, async {
+        let _instance_lock = instance_lock::acquire(&config::get_paths()?.identity_path).await?;
+        identity::secure_config_files()?;
+        schema::migrate_config_files()?;
+        fingerprint::check_identity_binding()?;
+        reconcile::remove_orphaned_resources()?;
+
+        let mut miner = MinerBuilder::default()
+            .parachain_url(parachain_url.to_string())
+            .keypair(keypair)
+            .inference_port(member.port)
+            .config()?
+            .build()
+            .await?;
+
+        println!(
+            "Starting fleet member {} on port {}",
+            chain_properties::format_account(&account),
+            member.port
+        );
+        miner.start_miner().await
+    })
+    .await;
+
+    if let Err(e) = &result {
+        println!(
+            "Fleet member {} stopped: {}",
+            chain_properties::format_account(&account),
+            e
+        );
+    }
+
+    result
+}
+
+/// Lays out the files of a member like the files of a single miner below `/var/lib/cyborg/worker-node`
+pub(crate) fn member_paths(base_dir: &Path, task_file_name: String) -> Paths {
+    let path_string = |relative: &str| base_dir.join(relative).to_string_lossy().to_string();
+
+    Paths {
+        log_path: base_dir.join("logs").join("worker_log.txt"),
+        task_file_name,
+        task_dir_path: path_string("task/current_task"),
+        task_owner_path: path_string("task/task_owner.json"),
+        identity_path: path_string("identity.json"),
+    }
+}
+
+/// Members must not share a keypair, a directory or a port, they would overwrite each other's state
+fn validate(fleet_config: &FleetConfig) -> Result<()> {
+    if fleet_config.members.is_empty() {
+        return Err(Error::Custom("The fleet configuration has no members".to_string()));
+    }
+
+    let mut seeds = HashSet::new();
+    let mut base_dirs = HashSet::new();
+    let mut ports = HashSet::new();
+
+    for member in &fleet_config.members {
+        if !seeds.insert(&member.account_seed) {
+            return Err(Error::Custom("Fleet members must use distinct account seeds".to_string()));
+        }
+        if !base_dirs.insert(&member.base_dir) {
+            return Err(Error::Custom(format!(
+                "Base directory {} is used by more than one fleet member",
+                member.base_dir.display()
+            )));
+        }
+        if !ports.insert(member.port) {
+            return Err(Error::Custom(format!(
+                "Port {} is used by more than one fleet member",
+                member.port
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(account_seed: &str, base_dir: &str, port: u16) -> FleetMember {
+        FleetMember {
+            account_seed: account_seed.to_string(),
+            base_dir: PathBuf::from(base_dir),
+            port,
+        }
+    }
+
+    #[test]
+    fn rejects_shared_ports() {
+        let fleet_config = FleetConfig {
+            members: vec![member("//Alice", "/fleet/alice", 3000), member("//Bob", "/fleet/bob", 3000)],
+        };
+
+        assert!(validate(&fleet_config).is_err());
+    }
+
+    #[test]
+    fn accepts_distinct_members() {
+        let fleet_config = FleetConfig {
+            members: vec![member("//Alice", "/fleet/alice", 3000), member("//Bob", "/fleet/bob", 3001)],
+        };
+
+        assert!(validate(&fleet_config).is_ok());
+    }
+}
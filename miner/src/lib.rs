@@ -0,0 +1,47 @@
+mod admin;
+mod alerting;
+mod builder;
+mod chain_properties;
+mod chain_spec;
+mod config;
+mod embedded;
+mod error;
+mod events;
+mod fleet;
+mod log;
+mod parachain_interactor;
+mod parent_runtime;
+mod preflight;
+mod reconcile;
+mod rewards;
+mod schema;
+mod simulation;
+mod snapshot;
+mod specs;
+mod substrate_interface;
+mod task_info;
+mod top;
+mod traits;
+mod types;
+mod utils;
+mod worker_migration;
+
+pub use embedded::{Miner, MinerBuilder};
+pub use error::{Error, Result};
+pub use events::MinerEvent;
+pub use parent_runtime::setup_progress::SetupStage;
+
+/// The CLI subcommands besides mining, so that the binary is just another embedder of the library
+pub mod commands {
+    pub use crate::admin::prove_now;
+    pub use crate::fleet::start_fleet;
+    pub use crate::log::init_logger;
+    pub use crate::parent_runtime::proof::{enable_prover_process, serve_prover_job};
+    pub use crate::preflight::run_preflight;
+    pub use crate::rewards::{claim_rewards, print_rewards};
+    pub use crate::simulation::run_simulation;
+    pub use crate::snapshot::{create_snapshot, restore_snapshot};
+    pub use crate::task_info::print_task_info;
+    pub use crate::top::run_top;
+    pub use crate::worker_migration::migrate_from_worker;
+}
@@ -1,5 +1,6 @@
 use crate::error::Result;
 use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use tracing_appender::non_blocking;
 use tracing_appender::non_blocking::WorkerGuard;
@@ -7,8 +8,16 @@ use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
 static LOG_GUARD: Lazy<Mutex<Option<WorkerGuard>>> = Lazy::new(|| Mutex::new(None));
 
+const LOG_DIR: &str = "miner/logs";
+const LOG_FILE_NAME: &str = "miner.log";
+
+/// The file the miner logs to, relative to the working directory
+pub fn log_file_path() -> PathBuf {
+    Path::new(LOG_DIR).join(LOG_FILE_NAME)
+}
+
 pub fn init_logger() {
-    let file_appender = tracing_appender::rolling::never("miner/logs", "miner.log");
+    let file_appender = tracing_appender::rolling::never(LOG_DIR, LOG_FILE_NAME);
 
     let (non_blocking_writer, guard) = non_blocking(file_appender);
 
@@ -25,7 +34,7 @@ pub fn init_logger() {
 fn reset_log_file() -> Result<()> {
     *LOG_GUARD.lock().unwrap() = None;
 
-    std::fs::remove_file("miner/logs/miner.log")?;
+    std::fs::remove_file(log_file_path())?;
 
     Ok(())
 }
@@ -3,7 +3,15 @@
 ///
 /// # Commands:
 ///
-/// - `startminer`: Starts a mining session with the provided parachain URL URL, and account seed
+/// - `startminer`: Starts a mining session with the provided parachain URL URL, and account seed, or plays a scenario
+///   of chain events with `--simulate`
+/// - `start-fleet`: Starts several miners from one process, as listed in the fleet configuration
+/// - `task info <task_id>`: Prints the on-chain definition, assignment, status and proof state of a task
+/// - `rewards show|claim`: Prints the pending rewards of the miner, or submits their distribution
+/// - `snapshot create|restore`: Bundles the identity and task state of the miner into a tarball, or restores one
+/// - `top`: Renders the live status of a running miner in the terminal
+/// - `preflight`: Checks Docker, the NVIDIA runtime, Triton, disk space, Tailscale, the parachain, the clock and the
+///   configuration of the host, and prints a pass/fail report
 ///
 /// # Errors:
 ///
@@ -12,32 +20,17 @@
 /// # Usage:
 ///
 /// Run the executable with appropriate arguments to start mining.
-mod builder;
 mod cli;
-mod config;
-mod error;
-mod log;
-mod parachain_interactor;
-mod parent_runtime;
-mod specs;
-mod substrate_interface;
-mod traits;
-mod types;
-mod utils;
-
-use builder::MinerBuilder;
+
 use clap::Parser;
-use cli::{Cli, Commands};
-use config::run_config;
-use error::Result;
-use subxt_signer::SecretUri;
-use traits::ParachainInteractor;
-use subxt_signer::sr25519::Keypair;
-use std::str::FromStr;
+use cli::{Cli, Commands, RewardsCommands, SnapshotCommands, TaskCommands};
+use cyborg_miner::{commands, Miner, Result};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    // This binary serves the EZKL jobs the miner runs in child processes
+    commands::enable_prover_process();
 
     // Match on the provided subcommand and execute the corresponding action.
     match &cli.command {
@@ -45,26 +38,93 @@ async fn main() -> Result<()> {
         Some(Commands::StartMiner {
             parachain_url,
             account_seed,
+            simulate,
+        }) => {
+            let _log_guard = commands::init_logger();
+
+            match (simulate, parachain_url) {
+                // Play the scenario instead of connecting to a parachain.
+                (Some(scenario), _) => commands::run_simulation(account_seed, scenario).await?,
+                (None, Some(parachain_url)) => {
+                    // Build the Miner using the provided parachain URL and account seed, the rest is configured by the environment.
+                    let miner = Miner::builder()
+                        .parachain_url(parachain_url)
+                        .account_seed(account_seed)
+                        .build()?;
+
+                    // Start the mining session using the built miner.
+                    miner.run().await?;
+                }
+                (None, None) => println!("A parachain URL is required. Exiting."),
+            }
+        }
+
+        // Handle the "start_fleet" subcommand.
+        Some(Commands::StartFleet {
+            parachain_url,
+            fleet_config,
         }) => {
-            let _log_guard = log::init_logger();
+            let _log_guard = commands::init_logger();
 
-            let uri = SecretUri::from_str(account_seed).expect("Keypair was not set correctly");
-            let keypair = Keypair::from_uri(&uri).expect("Keypair from URI failed");
+            commands::start_fleet(parachain_url, fleet_config).await?;
+        }
+
+        // Handle the "task" subcommands, which only query the parachain.
+        Some(Commands::Task { command }) => match command {
+            TaskCommands::Info {
+                task_id,
+                parachain_url,
+            } => commands::print_task_info(parachain_url, *task_id).await?,
+        },
 
-            run_config(parachain_url, keypair.clone()).await;
+        // Handle the "rewards" subcommands.
+        Some(Commands::Rewards { command }) => match command {
+            RewardsCommands::Show {
+                parachain_url,
+                account_seed,
+            } => commands::print_rewards(parachain_url, account_seed).await?,
+            RewardsCommands::Claim {
+                parachain_url,
+                account_seed,
+            } => commands::claim_rewards(parachain_url, account_seed).await?,
+        },
 
-            // Build the Miner using the provided parachain URL, account seed, and CESS gateway.
-            let mut miner = MinerBuilder::default()
-                .parachain_url(parachain_url.to_string())
-                .keypair(keypair)
-                .config()?
-                .build()
-                .await?;
+        // Handle the "snapshot" subcommands, they only touch local files.
+        Some(Commands::Snapshot { command }) => match command {
+            SnapshotCommands::Create {
+                output,
+                include_models,
+            } => commands::create_snapshot(output, *include_models)?,
+            SnapshotCommands::Restore { input, force } => {
+                commands::restore_snapshot(input, *force)?
+            }
+        },
 
-            // Start the mining session using the built miner.
-            miner.start_miner().await?;
+        // Handle the "top" subcommand, it only talks to the admin API of a running miner.
+        Some(Commands::Top { admin_url }) => commands::run_top(admin_url).await?,
+
+        // Handle the "preflight" subcommand, it only reads the host and the parachain.
+        Some(Commands::Preflight { parachain_url }) => {
+            commands::run_preflight(parachain_url.as_deref()).await?
         }
 
+        // Handle the "prove-now" subcommand, the running miner proves and the proof is written here.
+        Some(Commands::ProveNow {
+            admin_url,
+            task_id,
+            out,
+        }) => commands::prove_now(admin_url, *task_id, out).await?,
+
+        // Handle the "migrate-from-worker" subcommand, it checks the registration before moving any files.
+        Some(Commands::MigrateFromWorker {
+            worker_dir,
+            parachain_url,
+            force,
+        }) => commands::migrate_from_worker(worker_dir, parachain_url, *force).await?,
+
+        // Handle a job of the parent miner, the result is reported on stdout.
+        Some(Commands::ProverJob) => std::process::exit(commands::serve_prover_job().await),
+
         _ => {
             println!("No command provided. Exiting.");
         }
@@ -0,0 +1,55 @@
+// Events of the standby role, where a backup miner is designated for a task next to the miner serving it. They are
+// not part of the generated interface of the current runtime, the dispatch table ignores them until the runtime
+// emits them.
+
+use crate::substrate_interface::api::runtime_types::{
+    bounded_collections::bounded_vec::BoundedVec, cyborg_primitives::task::TaskKind,
+};
+use subxt::{events::StaticEvent, utils::AccountId32};
+
+#[derive(
+    :: subxt :: ext :: subxt_core :: ext :: codec :: Decode,
+    :: subxt :: ext :: subxt_core :: ext :: codec :: Encode,
+    :: subxt :: ext :: subxt_core :: ext :: scale_decode :: DecodeAsType,
+    :: subxt :: ext :: subxt_core :: ext :: scale_encode :: EncodeAsType,
+    Debug,
+)]
+#[codec(crate = ::subxt::ext::subxt_core::ext::codec)]
+#[codec(dumb_trait_bound)]
+#[decode_as_type(crate_path = ":: subxt :: ext :: subxt_core :: ext :: scale_decode")]
+#[encode_as_type(crate_path = ":: subxt :: ext :: subxt_core :: ext :: scale_encode")]
+/// A worker was designated as the backup of a scheduled task, it prepares the task without serving it
+pub struct BackupWorkerAssigned {
+    pub backup_worker: (AccountId32, u64),
+    pub task_kind: TaskKind,
+    pub task_owner: AccountId32,
+    pub task_id: u64,
+    pub task: BoundedVec<u8>,
+}
+
+impl StaticEvent for BackupWorkerAssigned {
+    const PALLET: &'static str = "TaskManagement";
+    const EVENT: &'static str = "BackupWorkerAssigned";
+}
+
+#[derive(
+    :: subxt :: ext :: subxt_core :: ext :: codec :: Decode,
+    :: subxt :: ext :: subxt_core :: ext :: codec :: Encode,
+    :: subxt :: ext :: subxt_core :: ext :: scale_decode :: DecodeAsType,
+    :: subxt :: ext :: subxt_core :: ext :: scale_encode :: EncodeAsType,
+    Debug,
+)]
+#[codec(crate = ::subxt::ext::subxt_core::ext::codec)]
+#[codec(dumb_trait_bound)]
+#[decode_as_type(crate_path = ":: subxt :: ext :: subxt_core :: ext :: scale_decode")]
+#[encode_as_type(crate_path = ":: subxt :: ext :: subxt_core :: ext :: scale_encode")]
+/// The primary worker of a task failed, its backup worker takes the task over
+pub struct BackupWorkerPromoted {
+    pub task_id: u64,
+    pub backup_worker: (AccountId32, u64),
+}
+
+impl StaticEvent for BackupWorkerPromoted {
+    const PALLET: &'static str = "TaskManagement";
+    const EVENT: &'static str = "BackupWorkerPromoted";
+}
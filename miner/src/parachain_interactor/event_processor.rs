@@ -1,31 +1,221 @@
-use crate::config::{self, get_paths, get_tx_queue};
-use crate::parachain_interactor::identity::update_identity_file;
+use crate::alerting::{self, Alert};
+use crate::config::{self, get_parachain_client, get_paths, get_tx_queue, Paths};
+use crate::events::{self, MinerEvent};
+use crate::parachain_interactor::backup_events::{BackupWorkerAssigned, BackupWorkerPromoted};
+use crate::parachain_interactor::task_deadlines::{self, TaskDeadline};
+use crate::parachain_interactor::task_identifier::TaskIdentifier;
+use crate::parent_runtime::model_retention;
+use crate::parent_runtime::proof;
+use crate::parent_runtime::server_control::stop_inference_server;
+use crate::parent_runtime::setup_progress::{self, SetupStage};
+use crate::parent_runtime::storage_interactor;
+use crate::parent_runtime::task_manifest::ProofSubmission;
+use crate::parent_runtime::transcript::{self, TranscriptCheckpoint};
+use crate::schema;
+use crate::specs;
 use crate::substrate_interface;
-use crate::traits::{InferenceServer};
-use crate::types::{CurrentTask, TaskType};
-use crate::utils::tx_builder::{confirm_miner_vacation, submit_proof};
+use crate::traits::InferenceServer;
+use crate::types::{CurrentTask, StandbyTask, TaskType};
+use crate::utils::{block_pacing, idle_power, load_shedding};
+use crate::utils::tx_builder::anchor_proof_transcript;
 use crate::utils::tx_queue::TxOutput;
 use crate::{
     error::{Error, Result},
-    types::{Miner, MinerData},
+    types::Miner,
 };
-use std::path::PathBuf;
-use std::sync::Arc;
-use serde::Serialize;
+use once_cell::sync::{Lazy, OnceCell};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs;
-use subxt::utils::AccountId32;
-use subxt::{events::EventDetails, PolkadotConfig};
-use crate::utils::tx_builder::confirm_task_reception;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use substrate_interface::api::{edge_connect, neuro_zk, task_management};
+use subxt::{
+    events::{EventDetails, StaticEvent},
+    PolkadotConfig,
+};
+use tokio::task::JoinHandle;
+
+/// The events the miner reacts to, every other event of a block is skipped without being decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelevantEvent {
+    WorkerRegistered,
+    WorkerRemoved,
+    WorkerStatusUpdated,
+    TaskScheduled,
+    TaskStopRequested,
+    NzkProofRequested,
+    NzkProofRejected,
+    NzkProofVerified,
+    BackupWorkerAssigned,
+    BackupWorkerPromoted,
+}
+
+impl RelevantEvent {
+    const ALL: [RelevantEvent; 10] = [
+        RelevantEvent::WorkerRegistered,
+        RelevantEvent::WorkerRemoved,
+        RelevantEvent::WorkerStatusUpdated,
+        RelevantEvent::TaskScheduled,
+        RelevantEvent::TaskStopRequested,
+        RelevantEvent::NzkProofRequested,
+        RelevantEvent::NzkProofRejected,
+        RelevantEvent::NzkProofVerified,
+        RelevantEvent::BackupWorkerAssigned,
+        RelevantEvent::BackupWorkerPromoted,
+    ];
+
+    /// The (pallet, event) names as they appear in the runtime metadata
+    fn names(&self) -> (&'static str, &'static str) {
+        fn names_of<E: StaticEvent>() -> (&'static str, &'static str) {
+            (E::PALLET, E::EVENT)
+        }
+
+        match self {
+            RelevantEvent::WorkerRegistered => names_of::<edge_connect::events::WorkerRegistered>(),
+            RelevantEvent::WorkerRemoved => names_of::<edge_connect::events::WorkerRemoved>(),
+            RelevantEvent::WorkerStatusUpdated => {
+                names_of::<edge_connect::events::WorkerStatusUpdated>()
+            }
+            RelevantEvent::TaskScheduled => names_of::<task_management::events::TaskScheduled>(),
+            RelevantEvent::TaskStopRequested => {
+                names_of::<task_management::events::TaskStopRequested>()
+            }
+            RelevantEvent::NzkProofRequested => names_of::<neuro_zk::events::NzkProofRequested>(),
+            RelevantEvent::NzkProofRejected => names_of::<neuro_zk::events::NzkProofRejected>(),
+            RelevantEvent::NzkProofVerified => names_of::<neuro_zk::events::NzkProofVerified>(),
+            RelevantEvent::BackupWorkerAssigned => names_of::<BackupWorkerAssigned>(),
+            RelevantEvent::BackupWorkerPromoted => names_of::<BackupWorkerPromoted>(),
+        }
+    }
+}
+
+//TODO take the cipher from the scheduled task after subxt is regen
+const STORAGE_ENCRYPTION_CIPHER: &str = "password";
+
+/// Maps the (pallet index, variant index) of the relevant events to their kind, built once from the metadata of the parachain client
+static DISPATCH_TABLE: OnceCell<HashMap<(u8, u8), RelevantEvent>> = OnceCell::new();
+
+/// Resubmissions of a rejected proof per task, until the proof is verified or given up on
+static PROOF_RESUBMISSIONS: Lazy<Mutex<HashMap<u64, u32>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The moment the proof requested per task has to be submitted by, resubmissions after a rejection share it
+static PROOF_DEADLINES: Lazy<Mutex<HashMap<u64, Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Number of events that matched the dispatch table but could not be decoded, per event name
+static DECODE_ERRORS: Lazy<Mutex<HashMap<&'static str, u64>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn dispatch_table() -> Result<&'static HashMap<(u8, u8), RelevantEvent>> {
+    DISPATCH_TABLE.get_or_try_init(|| {
+        let metadata = get_parachain_client()?.metadata();
+
+        Ok(build_dispatch_table(|pallet_name, event_name| {
+            let pallet = metadata
+                .pallet_by_name(pallet_name)
+                .ok_or(format!("Pallet {} not found in metadata", pallet_name))?;
+            pallet
+                .event_variants()
+                .and_then(|variants| variants.iter().find(|variant| variant.name == event_name))
+                .map(|variant| (pallet.index(), variant.index))
+                .ok_or(format!(
+                    "Event {}::{} not found in metadata",
+                    pallet_name, event_name
+                ))
+        }))
+    })
+}
+
+/// Builds the dispatch table, relevant events the runtime doesn't know are left out and never dispatched
+///
+/// # Arguments
+/// * `event_index` - Looks up the (pallet index, variant index) of an event by its pallet and event name, or why the
+///   runtime doesn't have it
+fn build_dispatch_table(
+    event_index: impl Fn(&str, &str) -> std::result::Result<(u8, u8), String>,
+) -> HashMap<(u8, u8), RelevantEvent> {
+    let mut table = HashMap::new();
+
+    for kind in RelevantEvent::ALL {
+        let (pallet_name, event_name) = kind.names();
+        match event_index(pallet_name, event_name) {
+            Ok(index) => {
+                table.insert(index, kind);
+            }
+            Err(e) => println!("{}, {:?} events will be ignored", e, kind),
+        }
+    }
+
+    table
+}
+
+/// Returns how often each relevant event failed to decode since the miner started
+pub fn decode_error_counts() -> HashMap<&'static str, u64> {
+    DECODE_ERRORS.lock().unwrap().clone()
+}
+
+/// Decodes an event already known to be of type `E`, counting failures
+fn decode<E: StaticEvent>(event: &EventDetails<PolkadotConfig>) -> Result<E> {
+    match event.as_event::<E>() {
+        Ok(Some(decoded)) => Ok(decoded),
+        Ok(None) => Err(Error::Custom(format!(
+            "Event {}::{} does not match the dispatch table",
+            E::PALLET,
+            E::EVENT
+        ))),
+        Err(e) => {
+            *DECODE_ERRORS.lock().unwrap().entry(E::EVENT).or_default() += 1;
+            println!("Error decoding {} event: {:?}", E::EVENT, e);
+            Err(Error::Subxt(e.into()))
+        }
+    }
+}
 
-#[derive(Serialize)]
-struct TaskOwner {
-    address: AccountId32,
+/// A decoded event the miner reacts to, from a finalized block or from a simulation scenario
+pub enum ChainEvent {
+    WorkerRegistered(edge_connect::events::WorkerRegistered),
+    WorkerRemoved(edge_connect::events::WorkerRemoved),
+    WorkerStatusUpdated(edge_connect::events::WorkerStatusUpdated),
+    TaskScheduled(task_management::events::TaskScheduled),
+    TaskStopRequested(task_management::events::TaskStopRequested),
+    NzkProofRequested(neuro_zk::events::NzkProofRequested),
+    NzkProofRejected(neuro_zk::events::NzkProofRejected),
+    NzkProofVerified(neuro_zk::events::NzkProofVerified),
+    BackupWorkerAssigned(BackupWorkerAssigned),
+    BackupWorkerPromoted(BackupWorkerPromoted),
 }
 
 pub async fn process_event(miner: &mut Miner, event: &EventDetails<PolkadotConfig>) -> Result<()> {
-    // Check for WorkerRegistered event
-    match event.as_event::<substrate_interface::api::edge_connect::events::WorkerRegistered>() {
-        Ok(Some(worker_registered)) => {
+    let Some(kind) = dispatch_table()?
+        .get(&(event.pallet_index(), event.variant_index()))
+        .copied()
+    else {
+        return Ok(());
+    };
+
+    let event = match kind {
+        RelevantEvent::WorkerRegistered => ChainEvent::WorkerRegistered(decode(event)?),
+        RelevantEvent::WorkerRemoved => ChainEvent::WorkerRemoved(decode(event)?),
+        RelevantEvent::WorkerStatusUpdated => ChainEvent::WorkerStatusUpdated(decode(event)?),
+        RelevantEvent::TaskScheduled => ChainEvent::TaskScheduled(decode(event)?),
+        RelevantEvent::TaskStopRequested => ChainEvent::TaskStopRequested(decode(event)?),
+        RelevantEvent::NzkProofRequested => ChainEvent::NzkProofRequested(decode(event)?),
+        RelevantEvent::NzkProofRejected => ChainEvent::NzkProofRejected(decode(event)?),
+        RelevantEvent::NzkProofVerified => ChainEvent::NzkProofVerified(decode(event)?),
+        RelevantEvent::BackupWorkerAssigned => ChainEvent::BackupWorkerAssigned(decode(event)?),
+        RelevantEvent::BackupWorkerPromoted => ChainEvent::BackupWorkerPromoted(decode(event)?),
+    };
+
+    handle_event(miner, event).await
+}
+
+/// Reacts to a decoded event
+pub async fn handle_event(miner: &mut Miner, event: ChainEvent) -> Result<()> {
+    match event {
+        ChainEvent::WorkerRegistered(worker_registered) => {
             let creator = &worker_registered.creator;
             let worker = &worker_registered.worker;
             let domain = &worker_registered.domain;
@@ -35,16 +225,7 @@ pub async fn process_event(miner: &mut Miner, event: &EventDetails<PolkadotConfi
                 creator, worker, domain
             );
         }
-        Err(e) => {
-            println!("Error decoding WorkerRegistered event: {:?}", e);
-            return Err(Error::Subxt(e.into()));
-        }
-        _ => {} // Skip non-matching events
-    }
-
-    // Check for WorkerRemoved event
-    match event.as_event::<substrate_interface::api::edge_connect::events::WorkerRemoved>() {
-        Ok(Some(worker_removed)) => {
+        ChainEvent::WorkerRemoved(worker_removed) => {
             let creator = &worker_removed.creator;
             let worker_id = &worker_removed.worker_id;
 
@@ -52,17 +233,12 @@ pub async fn process_event(miner: &mut Miner, event: &EventDetails<PolkadotConfi
                 "Worker Removed: Creator: {:?}, Worker ID: {:?}",
                 creator, worker_id
             );
-        }
-        Err(e) => {
-            println!("Error decoding WorkerRemoved event: {:?}", e);
-            return Err(Error::Subxt(e.into()));
-        }
-        _ => {} // Skip non-matching events
-    }
 
-    // Check for WorkerStatusUpdated event
-    match event.as_event::<substrate_interface::api::edge_connect::events::WorkerStatusUpdated>() {
-        Ok(Some(status_updated)) => {
+            if miner.miner_identity.as_ref() == Some(&(creator.clone(), *worker_id)) {
+                handle_own_removal(miner)?;
+            }
+        }
+        ChainEvent::WorkerStatusUpdated(status_updated) => {
             let creator = &status_updated.creator;
             let worker_id = &status_updated.worker_id;
             let worker_status = &status_updated.worker_status;
@@ -72,179 +248,904 @@ pub async fn process_event(miner: &mut Miner, event: &EventDetails<PolkadotConfi
                 creator, worker_id, worker_status
             );
         }
-        Err(e) => {
-            println!("Error decoding WorkerStatusUpdated event: {:?}", e);
-            return Err(Error::Subxt(e.into()));
-        }
-        _ => {} // Skip non-matching events
-    }
-
-    // Check for TaskScheduled event
-    match event.as_event::<substrate_interface::api::task_management::events::TaskScheduled>() {
-        Ok(Some(task_scheduled)) => {
-            let assigned_miner = &task_scheduled.assigned_worker;
-            let identity_path = &get_paths()?.identity_path;
-
-            let file_content = fs::read_to_string(identity_path)?;
-            let miner_data: MinerData = serde_json::from_str(&file_content)?;
-
-             // Immediately confirm task reception
-             let tx_queue = config::get_tx_queue()?;
-             let keypair = miner.keypair.clone();
-             let task_id = task_scheduled.task_id;
-             
-             let rx = tx_queue.enqueue(move || {
-                 let keypair = keypair.clone();
-                 async move {
-                     let _ = confirm_task_reception(keypair, task_id).await?;
-                     Ok(TxOutput::Success)
-                 }
-             }).await?;
-             
-             // Handle response 
-             match rx.await {
-                 Ok(Ok(TxOutput::Success)) => println!("Task reception confirmed immediately"),
-                 Ok(Err(e)) => println!("Error confirming task reception: {}", e),
-                 _ => println!("Unexpected response for task confirmation"),
-             }
-
-            if assigned_miner == &miner_data.miner_identity {
-                //TODO uncomment this and remove the hardcoded cipher after subxt is regen
-                //let storage_encryption_cipher = &task_scheduled.cipher;
-                let storage_encryption_cipher = "password";
-                let task_fid_string = String::from_utf8(task_scheduled.task.0)?;
-
-                miner.current_task = Some(CurrentTask {
-                    id: task_scheduled.task_id,
-                    //TODO uncomment after subxt regen
-                    //task_type: task_scheduled.task_type,
-                    task_type: TaskType::NeuroZk,
-                });
-
-                let task_owner_string = serde_json::to_string(&TaskOwner {
-                    address: task_scheduled.task_owner,
-                })?;
-
-                let task_owner_path = &get_paths()?.task_owner_path;
-
-                update_identity_file(task_owner_path, &task_owner_string)?;
-
-                println!("New task scheduled for worker: {}", task_fid_string);
-
-                let parent_runtime_clone = Arc::clone(&miner.parent_runtime);
-                let current_task_clone = miner.current_task.clone();
-                let keypair_clone = miner.keypair.clone();
-
-                if let Some(current_task) = current_task_clone {
-                    tokio::spawn(async move {
-                        if let Err(e) = parent_runtime_clone
-                            .read()
-                            .await
-                            .download_model_archive(&task_fid_string, storage_encryption_cipher)
-                            .await
-                        {
-                            println!("Error downloading model archive: {}", e);
-                        };
-
-                        if let Err(e) = parent_runtime_clone
-                            .read()
-                            .await
-                            .spawn_inference_server(&current_task, &keypair_clone)
-                            .await
-                        {
-                            println!("Error performing inference: {}", e)
-                        };
-                    });
-                } else {
-                    return Err(Error::Custom("No current task".to_string()));
-                }
+        ChainEvent::TaskScheduled(task_scheduled) => {
+            handle_task_scheduled(miner, task_scheduled).await?;
+        }
+        ChainEvent::TaskStopRequested(task_stop_requested) => {
+            handle_task_stop_requested(miner, task_stop_requested.task_id).await?;
+        }
+        ChainEvent::NzkProofRequested(requested_proof) => {
+            handle_proof_requested(miner, requested_proof.task_id).await?;
+        }
+        ChainEvent::NzkProofRejected(rejected_proof) => {
+            handle_proof_rejected(miner, rejected_proof.task_id).await?;
+        }
+        ChainEvent::NzkProofVerified(verified_proof) => {
+            handle_proof_verified(miner, verified_proof.task_id)?;
+        }
+        ChainEvent::BackupWorkerAssigned(backup_assigned) => {
+            handle_backup_assigned(miner, backup_assigned).await?;
+        }
+        ChainEvent::BackupWorkerPromoted(backup_promoted) => {
+            handle_backup_promoted(miner, backup_promoted).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_task_scheduled(
+    miner: &mut Miner,
+    task_scheduled: task_management::events::TaskScheduled,
+) -> Result<()> {
+    let assigned_miner = &task_scheduled.assigned_worker;
+    let identity_path = &get_paths()?.identity_path;
+
+    let miner_identity = schema::read_identity(identity_path)?
+        .ok_or(Error::identity_not_initialized())?
+        .as_tuple();
+
+    let task_type = TaskType::from(&task_scheduled.task_kind);
+
+    // Declined before confirming reception, so the task is never accepted by a miner that can't serve it
+    let task_identifier = if assigned_miner == &miner_identity {
+        if let Some(reason) = specs::unsupported_task_reason(&task_type).await {
+            println!("Declining task {}: {}", task_scheduled.task_id, reason);
+            return decline_task(miner, task_scheduled.task_id, reason).await;
+        }
+        // The task owner learns what is wrong with the identifier, instead of the task being left unconfirmed
+        match TaskIdentifier::decode(&task_scheduled.task.0) {
+            Ok(task_identifier) => Some(task_identifier),
+            Err(reason) => {
+                println!("Declining task {}: {}", task_scheduled.task_id, reason);
+                return decline_task(miner, task_scheduled.task_id, reason).await;
             }
         }
-        Err(e) => {
-            println!("Error decoding WorkerStatusUpdated event: {:?}", e);
-            return Err(Error::Subxt(e.into()));
+    } else {
+        None
+    };
+
+    // Immediately confirm task reception
+    confirm_reception(miner, task_scheduled.task_id).await?;
+
+    if let Some(task_identifier) = task_identifier {
+        let task_fid_string = task_identifier.to_string();
+        // The task directory is the one the standby task was prepared in
+        drop_standby_task(miner)?;
+
+        miner.current_task = Some(CurrentTask {
+            id: task_scheduled.task_id,
+            task_type,
+        });
+        events::emit(
+            &miner.keypair.public_key().to_account_id(),
+            MinerEvent::TaskAssigned {
+                task_id: task_scheduled.task_id,
+            },
+        );
+
+        let task_owner_path = &get_paths()?.task_owner_path;
+
+        schema::write_task_owner(task_owner_path, task_scheduled.task_owner)?;
+        // The download may wait for load to drop, it keeps the storage location of the assignment meanwhile
+        storage_interactor::capture_task_source(&get_paths()?.task_dir_path, &task_fid_string)?;
+
+        println!("New task scheduled for worker: {}", task_fid_string);
+
+        if let Some(current_task) = miner.current_task.clone() {
+            spawn_task_setup(miner, current_task, task_fid_string, None);
+        } else {
+            return Err(Error::Custom("No current task".to_string()));
         }
-        _ => {} // Skip non-matching events
     }
 
-    if let Some(current_task) = &miner.current_task {
-        match event.as_event::<substrate_interface::api::task_management::events::TaskStopRequested>() {
-            Ok(Some(task_stop_requested)) => {
-                if current_task.id == task_stop_requested.task_id {
-                    let paths = get_paths()?;
-                    let keypair = miner.keypair.clone();
-                    let tx_que = get_tx_queue()?;
-
-                    fs::remove_dir_all(PathBuf::from(&paths.task_dir_path))?;
-                    if let Some(dir) = paths.log_path.parent() {
-                        fs::remove_dir_all(dir)?;
-                    };
-                    if let Some(dir) = PathBuf::from(&paths.task_owner_path).parent() {
-                        fs::remove_dir_all(dir)?; 
-                    };
-
-                    let current_task_id = current_task.id.clone();
-                    miner.current_task = None;
-
-                    let rx = tx_que.enqueue( move || {
-                        let keypair = keypair.clone();
-                        async move {
-                            let _ = confirm_miner_vacation(keypair, current_task_id).await?;
-                            Ok(TxOutput::Success)
-                        }
-                    })
-                    .await?;
+    Ok(())
+}
 
-                    match rx.await {
-                        Ok(Ok(TxOutput::Success)) => println!("Miner vacated."),
-                        Ok(Err(e)) => println!("Error vacating miner: {}", e),
-                        Err(_) => println!("Response channel dropped on miner vacation."),
-                        _ => println!("Unexpected response from miner vacation event.")
-                    }
-                }
+/// Confirms the reception of a task assigned to the miner
+async fn confirm_reception(miner: &Miner, task_id: u64) -> Result<()> {
+    let tx_queue = config::get_tx_queue()?;
+    let keypair = miner.keypair.clone();
+    let chain = Arc::clone(&miner.chain);
+
+    let rx = tx_queue
+        .enqueue(move || {
+            let keypair = keypair.clone();
+            let chain = Arc::clone(&chain);
+            async move {
+                chain.confirm_reception(keypair, task_id).await?;
+                Ok(TxOutput::Success)
             }
+        })
+        .await?;
+
+    // Handle response
+    match rx.await {
+        Ok(Ok(TxOutput::Success)) => println!("Task reception confirmed immediately"),
+        Ok(Err(e)) => println!("Error confirming task reception: {}", e),
+        _ => println!("Unexpected response for task confirmation"),
+    }
+
+    Ok(())
+}
+
+/// Downloads the model of the current task and starts serving it, in the background
+///
+/// # Arguments
+/// * `current_task` - The task the miner serves now
+/// * `storage_identifier` - The storage identifier of the model archive
+/// * `standby_download` - The download started while the miner stood by for the task, the model is only downloaded
+///   again if it failed
+///
+/// A setup that can't complete before the setup deadline is abandoned and reported as soon as that is known.
+fn spawn_task_setup(
+    miner: &Miner,
+    current_task: CurrentTask,
+    storage_identifier: String,
+    standby_download: Option<JoinHandle<bool>>,
+) {
+    let parent_runtime_clone = Arc::clone(&miner.parent_runtime);
+    let keypair_clone = miner.keypair.clone();
+    let deadline = TaskDeadline::Setup.start();
+
+    // Keeps the paths of the fleet member this task was scheduled for
+    config::spawn_in_context(TaskDeadline::Setup.scope(deadline, async move {
+        let downloaded = match standby_download {
+            Some(download) => download.await.unwrap_or(false),
+            None => false,
+        };
+
+        if !downloaded {
+            load_shedding::wait_for_relief("model download").await;
+            setup_progress::report(
+                &keypair_clone,
+                current_task.id,
+                SetupStage::Downloading,
+                None,
+            );
+            if let Err(e) = parent_runtime_clone
+                .read()
+                .await
+                .download_model_archive(
+                    current_task.id,
+                    &storage_identifier,
+                    STORAGE_ENCRYPTION_CIPHER,
+                )
+                .await
+            {
+                if let Error::DeadlineMissed(reason) = e {
+                    task_deadlines::report_missed(
+                        &keypair_clone,
+                        current_task.id,
+                        TaskDeadline::Setup,
+                        reason,
+                    );
+                    return;
+                }
+                println!("Error downloading model archive: {}", e);
+                setup_progress::report(
+                    &keypair_clone,
+                    current_task.id,
+                    SetupStage::Failed,
+                    Some(format!("Download failed: {}", e)),
+                );
+                return;
+            };
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            task_deadlines::report_missed(
+                &keypair_clone,
+                current_task.id,
+                TaskDeadline::Setup,
+                "The setup deadline passed before the model was set up".to_string(),
+            );
+            return;
+        }
+
+        if let Err(e) = parent_runtime_clone
+            .read()
+            .await
+            .spawn_inference_server(&current_task, &keypair_clone)
+            .await
+        {
+            println!("Error performing inference: {}", e);
+            setup_progress::report(
+                &keypair_clone,
+                current_task.id,
+                SetupStage::Failed,
+                Some(e.to_string()),
+            );
+        };
+    }));
+}
+
+/// The miner was designated as the backup miner of a task: downloads its model ahead, so that the miner serves the
+/// task within moments once it is promoted. Only a miner without a task stands by, it doesn't confirm the reception
+/// of the task until it is promoted.
+async fn handle_backup_assigned(
+    miner: &mut Miner,
+    backup_assigned: BackupWorkerAssigned,
+) -> Result<()> {
+    let miner_identity = schema::read_identity(&get_paths()?.identity_path)?
+        .ok_or(Error::identity_not_initialized())?
+        .as_tuple();
+    if backup_assigned.backup_worker != miner_identity {
+        return Ok(());
+    }
+
+    let task_id = backup_assigned.task_id;
+    if let Some(current_task) = &miner.current_task {
+        println!(
+            "Not standing by for task {}, task {} is served",
+            task_id, current_task.id
+        );
+        return Ok(());
+    }
+    let task_type = TaskType::from(&backup_assigned.task_kind);
+    if let Some(reason) = specs::unsupported_task_reason(&task_type).await {
+        println!("Not standing by for task {}: {}", task_id, reason);
+        return Ok(());
+    }
+
+    // Standing by for one task at a time, they share the task directory
+    drop_standby_task(miner)?;
+    let storage_identifier = match TaskIdentifier::decode(&backup_assigned.task.0) {
+        Ok(storage_identifier) => storage_identifier.to_string(),
+        Err(reason) => {
+            println!("Not standing by for task {}: {}", task_id, reason);
+            return Ok(());
+        }
+    };
+    storage_interactor::capture_task_source(&get_paths()?.task_dir_path, &storage_identifier)?;
+    println!(
+        "Standing by for task {}, downloading its model: {}",
+        task_id, storage_identifier
+    );
+
+    let parent_runtime = Arc::clone(&miner.parent_runtime);
+    let keypair = miner.keypair.clone();
+    let download_identifier = storage_identifier.clone();
+    let download = config::spawn_in_context(async move {
+        load_shedding::wait_for_relief("model download").await;
+        setup_progress::report(&keypair, task_id, SetupStage::Downloading, None);
+        match parent_runtime
+            .read()
+            .await
+            .download_model_archive(task_id, &download_identifier, STORAGE_ENCRYPTION_CIPHER)
+            .await
+        {
+            Ok(()) => true,
+            // Tried again when the miner is promoted
             Err(e) => {
-                println!("Error decoding WorkerStatusUpdated event: {:?}", e);
-                return Err(Error::Subxt(e.into()));
+                println!(
+                    "Error downloading the model of standby task {}: {}",
+                    task_id, e
+                );
+                false
             }
-            _ => {} // Skip non-matching events
         }
+    });
+
+    miner.standby_task = Some(StandbyTask {
+        id: task_id,
+        task_type,
+        task_owner: backup_assigned.task_owner,
+        storage_identifier,
+        download,
+    });
+    events::emit(
+        &miner.keypair.public_key().to_account_id(),
+        MinerEvent::StandbyAssigned { task_id },
+    );
+
+    Ok(())
+}
+
+/// The primary miner of the task this miner stands by for failed: takes the task over and serves it with the model
+/// downloaded while standing by
+async fn handle_backup_promoted(
+    miner: &mut Miner,
+    backup_promoted: BackupWorkerPromoted,
+) -> Result<()> {
+    if miner.miner_identity.as_ref() != Some(&backup_promoted.backup_worker) {
+        return Ok(());
     }
+    let standby_task = match miner.standby_task.take() {
+        Some(standby_task) if standby_task.id == backup_promoted.task_id => standby_task,
+        other => {
+            miner.standby_task = other;
+            println!(
+                "Promoted for task {}, but this miner doesn't stand by for it",
+                backup_promoted.task_id
+            );
+            return Ok(());
+        }
+    };
 
-    if let Some(current_task) = &miner.current_task {
-        match event.as_event::<substrate_interface::api::neuro_zk::events::NzkProofRequested>() {
-            Ok(Some(requested_proof)) => {
-                let task_id = requested_proof.task_id;
-                let tx_queue = config::get_tx_queue()?;
-
-                if task_id == current_task.id {
-                    let proof = miner.parent_runtime.read().await.generate_proof().await?;
-                    let keypair = miner.keypair.clone();
-                    let rx = tx_queue.enqueue( move || {
-                        let keypair = keypair.clone();
-                        let proof = proof.clone();
-                        async move {
-                            let _ = submit_proof(proof, keypair, task_id).await?;
-                            Ok(TxOutput::Success)
-                        }
-                    })
-                    .await?;
+    println!(
+        "Promoted from standby, taking over task {}",
+        standby_task.id
+    );
+    confirm_reception(miner, standby_task.id).await?;
+
+    let current_task = CurrentTask {
+        id: standby_task.id,
+        task_type: standby_task.task_type,
+    };
+    miner.current_task = Some(current_task.clone());
+    events::emit(
+        &miner.keypair.public_key().to_account_id(),
+        MinerEvent::TaskAssigned {
+            task_id: current_task.id,
+        },
+    );
+    schema::write_task_owner(&get_paths()?.task_owner_path, standby_task.task_owner)?;
 
-                    match rx.await {
-                        Ok(Ok(TxOutput::Success)) => println!("Proof submitted."),
-                        Ok(Err(e)) => println!("Error submitting proof: {}", e),
-                        Err(_) => println!("Response channel dropped on proof submission."),
-                        _ => println!("Unexpected response from proof submission.")
+    spawn_task_setup(
+        miner,
+        current_task,
+        standby_task.storage_identifier,
+        Some(standby_task.download),
+    );
+
+    Ok(())
+}
+
+/// Stops standing by, eg. because the task was stopped or the miner was assigned a task of its own, and removes
+/// what was prepared for the standby task
+fn drop_standby_task(miner: &mut Miner) -> Result<()> {
+    let Some(standby_task) = miner.standby_task.take() else {
+        return Ok(());
+    };
+
+    standby_task.download.abort();
+    remove_task_files(get_paths()?)?;
+    println!("No longer standing by for task {}", standby_task.id);
+    Ok(())
+}
+
+async fn handle_task_stop_requested(miner: &mut Miner, task_id: u64) -> Result<()> {
+    if matches!(&miner.standby_task, Some(standby_task) if standby_task.id == task_id) {
+        return drop_standby_task(miner);
+    }
+
+    let Some(current_task) = &miner.current_task else {
+        return Ok(());
+    };
+
+    if current_task.id == task_id {
+        let paths = get_paths()?;
+        let keypair = miner.keypair.clone();
+        let chain = Arc::clone(&miner.chain);
+        let tx_que = get_tx_queue()?;
+
+        // The same model is often scheduled again, its user data is removed right away regardless
+        if let TaskType::OpenInference = current_task.task_type {
+            if let Err(e) = model_retention::retain(paths) {
+                println!("Error retaining the model of task {}: {}", task_id, e);
+            }
+        }
+        remove_task_files(paths)?;
+
+        let current_task_id = current_task.id.clone();
+        miner.current_task = None;
+        transcript::clear(current_task_id);
+        tokio::spawn(idle_power::task_ended(current_task_id));
+        events::emit(
+            &miner.keypair.public_key().to_account_id(),
+            MinerEvent::TaskStopped {
+                task_id: current_task_id,
+            },
+        );
+
+        let rx = tx_que
+            .enqueue(move || {
+                let keypair = keypair.clone();
+                let chain = Arc::clone(&chain);
+                async move {
+                    chain.confirm_vacation(keypair, current_task_id).await?;
+                    Ok(TxOutput::Success)
+                }
+            })
+            .await?;
+
+        match rx.await {
+            Ok(Ok(TxOutput::Success)) => println!("Miner vacated."),
+            Ok(Err(e)) => println!("Error vacating miner: {}", e),
+            Err(_) => println!("Response channel dropped on miner vacation."),
+            _ => println!("Unexpected response from miner vacation event."),
+        }
+    }
+
+    Ok(())
+}
+
+/// The chain removed this miner: stops serving, cleans up the current task and invalidates the identity file, so that
+/// neither this run nor the next start acts on the removed identity. Whether the miner then exits or registers
+/// again is up to `start_miner`.
+fn handle_own_removal(miner: &mut Miner) -> Result<()> {
+    println!(
+        "This miner was removed from the parachain: {:?}",
+        miner.miner_identity
+    );
+    alerting::raise(
+        Alert::RegistrationLost,
+        format!(
+            "Miner {:?} was removed from the parachain and no longer receives tasks",
+            miner.miner_identity
+        ),
+    );
+    let paths = get_paths()?;
+
+    drop_standby_task(miner)?;
+    if let Some(current_task) = miner.current_task.take() {
+        if stop_inference_server(current_task.id) {
+            println!("Stopped the inference server of task {}", current_task.id);
+        }
+        remove_task_files(paths)?;
+        tokio::spawn(idle_power::task_ended(current_task.id));
+    }
+
+    // Kept for the operator instead of deleting it, the identity can't be used anymore either way
+    let identity_path = PathBuf::from(&paths.identity_path);
+    if identity_path.exists() {
+        let invalidated_path = identity_path.with_extension("removed");
+        fs::rename(&identity_path, &invalidated_path)?;
+        println!("Identity file moved to {}", invalidated_path.display());
+    }
+
+    miner.miner_identity = None;
+    events::emit(
+        &miner.keypair.public_key().to_account_id(),
+        MinerEvent::Removed,
+    );
+
+    Ok(())
+}
+
+/// Removes the files of the current task: the model, its logs and the task owner
+fn remove_task_files(paths: &Paths) -> Result<()> {
+    let task_dir = PathBuf::from(&paths.task_dir_path);
+    if task_dir.exists() {
+        fs::remove_dir_all(task_dir)?;
+    }
+    if let Some(dir) = paths.log_path.parent() {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+    };
+    if let Some(dir) = PathBuf::from(&paths.task_owner_path).parent() {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+    };
+    // Best effort, components left behind are pruned at the next start
+    if let Some(component_cache) = config::component_cache()? {
+        if let Err(e) = component_cache.prune() {
+            println!("Error pruning the component cache: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Flags a task the miner can't serve on chain instead of confirming its reception
+async fn decline_task(miner: &Miner, task_id: u64, reason: String) -> Result<()> {
+    let tx_queue = config::get_tx_queue()?;
+    let keypair = miner.keypair.clone();
+    let chain = Arc::clone(&miner.chain);
+    let rx = tx_queue
+        .enqueue(move || {
+            let keypair = keypair.clone();
+            let chain = Arc::clone(&chain);
+            let reason = reason.clone();
+            async move {
+                chain.decline_task(keypair, task_id, &reason).await?;
+                Ok(TxOutput::Success)
+            }
+        })
+        .await?;
+
+    match rx.await {
+        Ok(Ok(_)) => println!("Task {} declined.", task_id),
+        Ok(Err(e)) => println!("Error declining task {}: {}", task_id, e),
+        Err(_) => println!("Response channel dropped on declining task {}.", task_id),
+    }
+
+    Ok(())
+}
+
+async fn handle_proof_requested(miner: &mut Miner, task_id: u64) -> Result<()> {
+    let Some(current_task) = &miner.current_task else {
+        return Ok(());
+    };
+
+    if task_id == current_task.id {
+        PROOF_RESUBMISSIONS.lock().unwrap().remove(&task_id);
+        match TaskDeadline::Proof.start() {
+            Some(deadline) => PROOF_DEADLINES.lock().unwrap().insert(task_id, deadline),
+            None => PROOF_DEADLINES.lock().unwrap().remove(&task_id),
+        };
+        submit_proof(miner, task_id).await?;
+    }
+
+    Ok(())
+}
+
+/// The chain rejected the proof of the current task: diagnoses it by verifying it locally, then proves again with a
+/// fresh witness and resubmits, up to `MAX_PROOF_RESUBMISSIONS` (default 2) times per task
+async fn handle_proof_rejected(miner: &mut Miner, task_id: u64) -> Result<()> {
+    let Some(current_task) = &miner.current_task else {
+        return Ok(());
+    };
+    if task_id != current_task.id {
+        return Ok(());
+    }
+
+    println!("The proof for task {} was rejected by the chain", task_id);
+    events::emit(
+        &miner.keypair.public_key().to_account_id(),
+        MinerEvent::ProofRejected { task_id },
+    );
+    let diagnosis = match miner
+        .parent_runtime
+        .read()
+        .await
+        .verify_submitted_proof(task_id)
+        .await
+    {
+        Ok(true) => {
+            "the proof verifies locally, the chain checked it against a different input or settings"
+                .to_string()
+        }
+        Ok(false) => "the proof does not verify against the on-chain verifying key".to_string(),
+        Err(e) => format!("the proof could not be verified locally: {}", e),
+    };
+    println!(
+        "Diagnosis of the rejected proof for task {}: {}",
+        task_id, diagnosis
+    );
+
+    let max_resubmissions = config::optional_env("MAX_PROOF_RESUBMISSIONS", 2u32);
+    let Some(resubmission) = next_resubmission(task_id, max_resubmissions) else {
+        println!(
+            "Giving up on the proof for task {} after {} resubmissions",
+            task_id, max_resubmissions
+        );
+        return proof::record_proof_outcome(
+            task_id,
+            "abandoned",
+            max_resubmissions,
+            Some(&diagnosis),
+        );
+    };
+
+    let outcome = if submit_proof(miner, task_id).await? {
+        "resubmitted"
+    } else {
+        "resubmission failed"
+    };
+
+    proof::record_proof_outcome(task_id, outcome, resubmission, Some(&diagnosis))
+}
+
+fn handle_proof_verified(miner: &Miner, task_id: u64) -> Result<()> {
+    if miner.current_task.as_ref().map(|task| task.id) != Some(task_id) {
+        return Ok(());
+    }
+
+    let resubmissions = PROOF_RESUBMISSIONS
+        .lock()
+        .unwrap()
+        .remove(&task_id)
+        .unwrap_or(0);
+
+    proof::record_proof_outcome(task_id, "verified", resubmissions, None)
+}
+
+/// Counts a resubmission of the proof of a task
+///
+/// # Returns
+/// The number of the resubmission, or `None` if the task ran out of resubmissions
+fn next_resubmission(task_id: u64, max_resubmissions: u32) -> Option<u32> {
+    let mut resubmissions = PROOF_RESUBMISSIONS.lock().unwrap();
+    let count = resubmissions.entry(task_id).or_default();
+    if *count >= max_resubmissions {
+        return None;
+    }
+
+    *count += 1;
+    Some(*count)
+}
+
+/// Generates a proof for the task and submits it. A proof the last one suggests can't be generated before the proof
+/// deadline is given up right away, one still running when the deadline passes is aborted, both are reported as missed.
+///
+/// # Returns
+/// Whether the proof was submitted
+async fn submit_proof(miner: &Miner, task_id: u64) -> Result<bool> {
+    let tx_queue = config::get_tx_queue()?;
+
+    load_shedding::wait_for_relief("proof generation").await;
+    let deadline = PROOF_DEADLINES.lock().unwrap().get(&task_id).copied();
+    if let (Some(deadline), Some(estimate)) = (deadline, proof::estimated_duration()) {
+        let time_left = deadline.saturating_duration_since(Instant::now());
+        if estimate > time_left {
+            task_deadlines::report_missed(
+                &miner.keypair,
+                task_id,
+                TaskDeadline::Proof,
+                format!(
+                    "The last proof took {} s, only {} s are left before the proof deadline",
+                    estimate.as_secs(),
+                    time_left.as_secs()
+                ),
+            );
+            return Ok(false);
+        }
+    }
+
+    let proof = TaskDeadline::Proof
+        .scope(deadline, async {
+            miner
+                .parent_runtime
+                .read()
+                .await
+                .generate_proof(task_id)
+                .await
+        })
+        .await;
+    let proof = match proof {
+        Ok(proof) => proof,
+        Err(e) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+            task_deadlines::report_missed(
+                &miner.keypair,
+                task_id,
+                TaskDeadline::Proof,
+                e.to_string(),
+            );
+            return Ok(false);
+        }
+        Err(e) => return Err(e),
+    };
+    // Binds the proof to everything the task served since the last one, so the traffic can't differ from what the
+    // proofs attest to between checkpoints
+    let account = miner.keypair.public_key().to_account_id();
+    let checkpoint = transcript::checkpoint(&account, task_id);
+    let transcript_hash = hex::encode(checkpoint.hash);
+    let proof_sha256 = hex::encode(Sha256::digest(&proof));
+    println!(
+        "Submitting proof for task {} with transcript {} ({} requests since the last proof)",
+        task_id,
+        transcript_hash,
+        checkpoint.leaves.len()
+    );
+    // Proofs too large for an extrinsic are uploaded once, only their location is resubmitted if the remark fails
+    let location = match proof::proof_submission()? {
+        ProofSubmission::Inline => None,
+        ProofSubmission::Storage => {
+            let location = proof::upload_proof(task_id, &proof_sha256, proof.clone()).await?;
+            println!("Uploaded the proof of task {} to {}", task_id, location);
+            Some(location)
+        }
+    };
+    let keypair = miner.keypair.clone();
+    let chain = Arc::clone(&miner.chain);
+    let proof_hash = proof_sha256.clone();
+    let rx = tx_queue
+        .enqueue_paced(block_pacing::wait_for_proof_slot, move || {
+            let keypair = keypair.clone();
+            let chain = Arc::clone(&chain);
+            let proof = proof.clone();
+            let proof_hash = proof_hash.clone();
+            let location = location.clone();
+            async move {
+                match location {
+                    Some(location) => {
+                        chain
+                            .submit_proof_reference(keypair, task_id, &proof_hash, &location)
+                            .await?
                     }
+                    None => chain.submit_proof(keypair, task_id, proof).await?,
                 }
+                Ok(TxOutput::Success)
             }
-            Err(e) => {
-                println!("Error decoding SubmittedCompletedTask event: {:?}", e);
-                return Err(Error::Subxt(e.into()));
+        })
+        .await?;
+
+    match rx.await {
+        Ok(Ok(TxOutput::Success)) => {
+            println!("Proof submitted.");
+            // The segment only ends with a submitted proof, the leaves of a failed submission go with the next one
+            transcript::commit(&account, &checkpoint);
+            if let Err(e) = transcript::persist(&checkpoint, &proof_sha256) {
+                println!("Error recording the transcript of the proof: {}", e);
             }
-            _ => {} // Skip non-matching events
+            publish_transcript(miner, &checkpoint, &proof_sha256).await;
+            events::emit(
+                &account,
+                MinerEvent::ProofSubmitted {
+                    task_id,
+                    transcript_hash,
+                },
+            );
+            return Ok(true);
         }
+        Ok(Err(e)) => println!("Error submitting proof: {}", e),
+        Err(_) => println!("Response channel dropped on proof submission."),
+        _ => println!("Unexpected response from proof submission."),
     }
 
-    Ok(())
+    Ok(false)
+}
+
+/// Publishes the transcript hash of a submitted proof on chain, in a queued job of its own so that a failing remark
+/// never resubmits the proof
+async fn publish_transcript(miner: &Miner, checkpoint: &TranscriptCheckpoint, proof_sha256: &str) {
+    let result = async {
+        let keypair = miner.keypair.clone();
+        let task_id = checkpoint.task_id;
+        let transcript_hash = hex::encode(checkpoint.hash);
+        let previous_transcript_hash = hex::encode(checkpoint.previous);
+        let leaf_count = checkpoint.leaves.len();
+        let proof_sha256 = proof_sha256.to_string();
+
+        let rx = get_tx_queue()?
+            .enqueue(move || {
+                let keypair = keypair.clone();
+                let transcript_hash = transcript_hash.clone();
+                let previous_transcript_hash = previous_transcript_hash.clone();
+                let proof_sha256 = proof_sha256.clone();
+                async move {
+                    anchor_proof_transcript(
+                        keypair,
+                        task_id,
+                        &transcript_hash,
+                        &previous_transcript_hash,
+                        leaf_count,
+                        &proof_sha256,
+                    )
+                    .await?;
+                    Ok(TxOutput::Success)
+                }
+            })
+            .await?;
+        rx.await
+            .map_err(|_| Error::Custom("Response channel dropped.".to_string()))?
+    }
+    .await;
+
+    if let Err(e) = result {
+        println!(
+            "Error publishing the transcript of the proof for task {}: {}",
+            checkpoint.task_id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::mock_chain::{self, ChainCall, MockChain};
+    use crate::utils::tx_queue::{TransactionQueue, TRANSACTION_QUEUE};
+
+    #[tokio::test]
+    async fn declined_tasks_are_flagged_with_the_reason() {
+        TRANSACTION_QUEUE.get_or_init(TransactionQueue::new);
+        let chain = Arc::new(MockChain::default());
+        let miner = mock_chain::miner(Arc::clone(&chain));
+
+        decline_task(&miner, 7, "Triton is not available".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            chain.calls(),
+            vec![ChainCall::DeclineTask(
+                7,
+                "Triton is not available".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_requests_for_other_tasks_are_ignored() {
+        let chain = Arc::new(MockChain::default());
+        let mut miner = mock_chain::miner(Arc::clone(&chain));
+        miner.current_task = Some(CurrentTask {
+            id: 1,
+            task_type: TaskType::OpenInference,
+        });
+
+        handle_task_stop_requested(&mut miner, 2).await.unwrap();
+
+        assert!(miner.current_task.is_some());
+        assert!(chain.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn promotions_for_other_tasks_leave_the_standby_task() {
+        let chain = Arc::new(MockChain::default());
+        let mut miner = mock_chain::miner(Arc::clone(&chain));
+        miner.standby_task = Some(StandbyTask {
+            id: 3,
+            task_type: TaskType::OpenInference,
+            task_owner: miner.keypair.public_key().to_account_id(),
+            storage_identifier: "model".to_string(),
+            download: tokio::spawn(async { true }),
+        });
+
+        let backup_worker = miner.miner_identity.clone().unwrap();
+        handle_backup_promoted(
+            &mut miner,
+            BackupWorkerPromoted {
+                task_id: 4,
+                backup_worker,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(miner.standby_task.as_ref().map(|task| task.id), Some(3));
+        assert!(miner.current_task.is_none());
+        assert!(chain.calls().is_empty());
+    }
+
+    /// Indices as a runtime without the backup events assigns them
+    fn event_index(pallet_name: &str, event_name: &str) -> std::result::Result<(u8, u8), String> {
+        let pallet = substrate_interface::api::PALLETS
+            .iter()
+            .position(|pallet| *pallet == pallet_name)
+            .ok_or(format!("Pallet {} not found in metadata", pallet_name))?;
+        let variant = RelevantEvent::ALL
+            .iter()
+            .filter(|kind| kind.names().0 == pallet_name)
+            .position(|kind| kind.names().1 == event_name)
+            .filter(|_| !event_name.starts_with("BackupWorker"))
+            .ok_or(format!(
+                "Event {}::{} not found in metadata",
+                pallet_name, event_name
+            ))?;
+        Ok((pallet as u8, variant as u8))
+    }
+
+    #[test]
+    fn relevant_events_are_dispatched_by_their_index() {
+        let table = build_dispatch_table(event_index);
+
+        assert_eq!(table.len(), RelevantEvent::ALL.len() - 2);
+        for kind in RelevantEvent::ALL {
+            let (pallet_name, event_name) = kind.names();
+            match event_index(pallet_name, event_name) {
+                Ok(index) => assert_eq!(table.get(&index), Some(&kind)),
+                Err(_) => assert!(!table.values().any(|dispatched| *dispatched == kind)),
+            }
+        }
+    }
+
+    #[test]
+    fn relevant_events_have_distinct_names() {
+        for (position, kind) in RelevantEvent::ALL.iter().enumerate() {
+            assert!(
+                RelevantEvent::ALL[position + 1..]
+                    .iter()
+                    .all(|other| other.names() != kind.names()),
+                "{:?} shares its names with another event",
+                kind
+            );
+        }
+        assert_eq!(
+            RelevantEvent::TaskScheduled.names(),
+            ("TaskManagement", "TaskScheduled")
+        );
+    }
+
+    #[test]
+    fn proof_resubmissions_are_limited_per_task() {
+        assert_eq!(next_resubmission(11, 2), Some(1));
+        assert_eq!(next_resubmission(11, 2), Some(2));
+        assert_eq!(next_resubmission(11, 2), None);
+        assert_eq!(next_resubmission(12, 2), Some(1));
+    }
 }
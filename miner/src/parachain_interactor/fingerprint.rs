@@ -0,0 +1,237 @@
+use crate::config;
+use crate::error::{Error, Result};
+use crate::parachain_interactor::identity::write_private;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use subxt::utils::AccountId32;
+
+const FINGERPRINT_VERSION: u32 = 1;
+/// Stored next to the identity file, so that both are copied together
+const FINGERPRINT_FILE: &str = "hardware-fingerprint.json";
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Hashes of the hardware an identity is bound to, the raw identifiers never leave the host
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HardwareFingerprint {
+    pub version: u32,
+    pub machine_id: Option<String>,
+    /// Of the physical network interfaces, virtual ones change with every container
+    pub macs: Vec<String>,
+    pub gpus: Vec<String>,
+}
+
+/// What happens when the identity was bound to other hardware, set by `IDENTITY_BINDING`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindingPolicy {
+    /// Refuse to start (default)
+    Enforce,
+    Warn,
+    Off,
+}
+
+impl BindingPolicy {
+    fn from_env() -> Self {
+        match config::optional_env("IDENTITY_BINDING", "enforce".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "warn" => BindingPolicy::Warn,
+            "off" | "false" => BindingPolicy::Off,
+            _ => BindingPolicy::Enforce,
+        }
+    }
+}
+
+impl HardwareFingerprint {
+    /// Fingerprints the hardware of this host, every identifier is best effort
+    pub fn current() -> Self {
+        let machine_id = MACHINE_ID_PATHS
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .map(|id| id.trim().to_string())
+            .find(|id| !id.is_empty())
+            .map(|id| hash(&id));
+
+        Self {
+            version: FINGERPRINT_VERSION,
+            machine_id,
+            macs: physical_macs().iter().map(|mac| hash(mac)).collect(),
+            gpus: gpu_uuids().iter().map(|uuid| hash(uuid)).collect(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.machine_id.is_none() && self.macs.is_empty() && self.gpus.is_empty()
+    }
+
+    /// Whether both fingerprints belong to the same host. Replacing a NIC or a GPU keeps the host, so any identifier
+    /// in common is enough, only a host that shares none of them is another one.
+    pub fn same_host(&self, other: &HardwareFingerprint) -> bool {
+        if self.machine_id.is_some() && self.machine_id == other.machine_id {
+            return true;
+        }
+        self.macs.iter().any(|mac| other.macs.contains(mac))
+            || self.gpus.iter().any(|gpu| other.gpus.contains(gpu))
+    }
+
+    /// A hash of the fingerprint salted with the miner identity, safe to publish on chain. Miners running the same
+    /// identity on different hosts publish different commitments, while the hardware of a host can't be linked
+    /// across identities.
+    pub fn commitment(&self, miner_identity: &(AccountId32, u64)) -> String {
+        let (account, miner_id) = miner_identity;
+        let mut hasher = Sha256::new();
+        hasher.update(account);
+        hasher.update(miner_id.to_le_bytes());
+        hasher.update(self.machine_id.as_deref().unwrap_or_default());
+        for id in self.macs.iter().chain(&self.gpus) {
+            hasher.update(id);
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Checks that the identity of the miner was not copied from another host, two miners running one identity would
+/// serve and submit for each other. An identity without a fingerprint, eg. one restored from a snapshot, is bound to
+/// this host.
+///
+/// # Returns
+/// An `Error` if the identity is bound to other hardware and `IDENTITY_BINDING` is not `warn` or `off`
+pub fn check_identity_binding() -> Result<()> {
+    let policy = BindingPolicy::from_env();
+    let identity_path = config::get_paths()?.identity_path;
+    if policy == BindingPolicy::Off || !Path::new(&identity_path).exists() {
+        return Ok(());
+    }
+
+    let current = HardwareFingerprint::current();
+    if current.is_empty() {
+        println!("No hardware identifiers found, the identity can't be bound to this host");
+        return Ok(());
+    }
+
+    let path = fingerprint_path(&identity_path);
+    let stored = match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str::<HardwareFingerprint>(&content)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return bind(&path, &current),
+        Err(e) => return Err(e.into()),
+    };
+
+    if stored.same_host(&current) {
+        // Keeps up with replaced hardware
+        if stored != current {
+            bind(&path, &current)?;
+        }
+        return Ok(());
+    }
+
+    let message = format!(
+        "The identity in {} was bound to another host, it was probably copied. Running one identity on two hosts \
+         double-submits transactions. Restore it with `snapshot restore` to move it, or delete {} if this host \
+         really is the same",
+        identity_path,
+        path.display()
+    );
+    match policy {
+        BindingPolicy::Warn => {
+            println!("Warning: {}", message);
+            Ok(())
+        }
+        _ => Err(Error::Custom(message)),
+    }
+}
+
+/// Binds the identity of the miner to this host, called once it registered
+pub fn bind_identity() -> Result<()> {
+    let current = HardwareFingerprint::current();
+    if current.is_empty() {
+        return Ok(());
+    }
+    bind(
+        &fingerprint_path(&config::get_paths()?.identity_path),
+        &current,
+    )
+}
+
+/// Forgets the host the identity was bound to, so that a moved identity is bound to its new host
+pub fn unbind_identity(identity_path: &str) -> Result<()> {
+    match fs::remove_file(fingerprint_path(identity_path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn bind(path: &Path, fingerprint: &HardwareFingerprint) -> Result<()> {
+    println!("Binding the identity to this host");
+    write_private(path, serde_json::to_string(fingerprint)?.as_bytes())
+}
+
+fn fingerprint_path(identity_path: &str) -> PathBuf {
+    Path::new(identity_path)
+        .parent()
+        .map(|dir| dir.join(FINGERPRINT_FILE))
+        .unwrap_or_else(|| PathBuf::from(FINGERPRINT_FILE))
+}
+
+fn hash(id: &str) -> String {
+    hex::encode(Sha256::digest(id.to_lowercase().as_bytes()))
+}
+
+/// The MAC addresses of the interfaces backed by a device, sorted
+fn physical_macs() -> Vec<String> {
+    let mut macs: Vec<String> = fs::read_dir("/sys/class/net")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|interface| interface.path().join("device").exists())
+        .filter_map(|interface| fs::read_to_string(interface.path().join("address")).ok())
+        .map(|mac| mac.trim().to_string())
+        .filter(|mac| !mac.is_empty() && mac != "00:00:00:00:00:00")
+        .collect();
+    macs.sort();
+    macs
+}
+
+fn gpu_uuids() -> Vec<String> {
+    Command::new("nvidia-smi")
+        .args(["--query-gpu=uuid", "--format=csv,noheader"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|uuid| uuid.trim().to_string())
+                .filter(|uuid| !uuid.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(machine_id: Option<&str>, macs: &[&str], gpus: &[&str]) -> HardwareFingerprint {
+        HardwareFingerprint {
+            version: FINGERPRINT_VERSION,
+            machine_id: machine_id.map(hash),
+            macs: macs.iter().map(|mac| hash(mac)).collect(),
+            gpus: gpus.iter().map(|gpu| hash(gpu)).collect(),
+        }
+    }
+
+    #[test]
+    fn hosts_sharing_any_identifier_are_the_same() {
+        let host = fingerprint(Some("a1"), &["aa:bb"], &["GPU-1", "GPU-2"]);
+
+        // The NIC and a GPU were replaced
+        assert!(host.same_host(&fingerprint(Some("a1"), &["cc:dd"], &["GPU-1", "GPU-3"])));
+        // The OS was reinstalled
+        assert!(host.same_host(&fingerprint(Some("b2"), &["aa:bb"], &["GPU-1", "GPU-2"])));
+        assert!(!host.same_host(&fingerprint(Some("b2"), &["cc:dd"], &["GPU-3"])));
+        assert!(!fingerprint(None, &[], &["GPU-1"]).same_host(&fingerprint(None, &[], &["GPU-3"])));
+    }
+}
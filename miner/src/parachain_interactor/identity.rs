@@ -1,14 +1,138 @@
-use crate::error::Result;
-use std::fs;
-use std::path::PathBuf;
+use crate::config;
+use crate::error::{Error, Result};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 
+/// Marks a file that is encrypted with the key derived from the account seed, followed by hex(nonce || ciphertext)
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LENGTH: usize = 12;
+
+/// Writes a sensitive config file (identity, task owner) readable only by the miner, encrypted if `ENCRYPT_CONFIG_FILES` is enabled.
 pub fn update_identity_file(path: &str, content: &str) -> Result<()> {
     let path = PathBuf::from(path);
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    fs::write(&path, content)?;
+    let content = match config::get_config_encryption_key() {
+        Some(key) => encrypt(key, content)?,
+        None => content.to_string(),
+    };
+
+    write_private(&path, content.as_bytes())?;
 
     Ok(())
 }
+
+/// Reads a file written by `update_identity_file`, transparently decrypting it. Plaintext files are returned as they are.
+pub fn read_identity_file(path: &str) -> Result<String> {
+    let content = fs::read_to_string(path)?;
+
+    match content.strip_prefix(ENCRYPTED_PREFIX) {
+        Some(encrypted) => {
+            let key = config::get_config_encryption_key().ok_or(Error::Custom(format!(
+                "{} is encrypted, but ENCRYPT_CONFIG_FILES is not enabled",
+                path
+            )))?;
+            decrypt(key, encrypted.trim())
+        }
+        None => Ok(content),
+    }
+}
+
+/// Migrates the sensitive config files of existing installs: restricts their permissions to the owner and
+/// (re-)writes them in the configured format, so enabling encryption also encrypts files written before.
+pub fn secure_config_files() -> Result<()> {
+    let paths = config::get_paths()?;
+
+    for path in [&paths.identity_path, &paths.task_owner_path] {
+        if !Path::new(path).exists() {
+            continue;
+        }
+
+        let content = read_identity_file(path)?;
+        update_identity_file(path, &content)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a file that only the miner can read
+pub fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+
+    // The mode above only applies to newly created files
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(content)?;
+
+    Ok(())
+}
+
+fn encrypt(key: &[u8; 32], content: &str) -> Result<String> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(&nonce, content.as_bytes())
+        .map_err(|_| Error::Custom("Failed to encrypt config file".to_string()))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, hex::encode(sealed)))
+}
+
+fn decrypt(key: &[u8; 32], encrypted: &str) -> Result<String> {
+    let sealed = hex::decode(encrypted)
+        .map_err(|e| Error::Custom(format!("Malformed encrypted config file: {}", e)))?;
+
+    if sealed.len() < NONCE_LENGTH {
+        return Err(Error::Custom("Malformed encrypted config file".to_string()));
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| {
+            Error::Custom("Failed to decrypt config file, was the account seed changed?".to_string())
+        })?;
+
+    Ok(String::from_utf8(plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_content_roundtrips() {
+        let key = [7u8; 32];
+        let encrypted = encrypt(&key, "{\"task_owner\":\"alice\"}").unwrap();
+
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert_eq!(
+            decrypt(&key, &encrypted[ENCRYPTED_PREFIX.len()..]).unwrap(),
+            "{\"task_owner\":\"alice\"}"
+        );
+    }
+
+    #[test]
+    fn decrypting_with_another_key_fails() {
+        let encrypted = encrypt(&[7u8; 32], "secret").unwrap();
+
+        assert!(decrypt(&[8u8; 32], &encrypted[ENCRYPTED_PREFIX.len()..]).is_err());
+    }
+}
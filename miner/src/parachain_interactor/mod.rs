@@ -1,4 +1,8 @@
+pub mod backup_events;
 pub mod behavior_control;
 pub mod event_processor;
+pub mod fingerprint;
 pub mod identity;
-pub mod registration;
\ No newline at end of file
+pub mod registration;
+pub mod task_deadlines;
+pub mod task_identifier;
\ No newline at end of file
@@ -1,19 +1,69 @@
+use crate::chain_properties;
 use crate::config;
 use crate::error::{Error, Result};
-use crate::substrate_interface;
-use crate::utils::tx_builder::register;
+use crate::events::{self, MinerEvent};
+use crate::parachain_interactor::{event_processor, fingerprint};
+use crate::schema;
+use crate::specs;
+use crate::utils::blocking::run_blocking;
+use crate::utils::scheduler::{MaintenanceJob, Scheduler};
+use crate::parent_runtime::audit_sampling;
+use crate::parent_runtime::response_anchor::{self, AnchorBatch};
+use crate::utils::tx_builder::anchor_response_root;
 use crate::utils::tx_queue::TxOutput;
-use crate::traits::ParachainInteractor;
-use crate::types::{Miner, MinerData};
-use serde::Deserialize;
-use std::fs;
+use crate::traits::{ChainApi, ParachainInteractor};
+use crate::types::Miner;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use subxt::utils::AccountId32;
+use subxt_signer::sr25519::Keypair;
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
-struct Identity {
-    miner_owner: String,
-    miner_identity: (AccountId32, u64),
+/// Wait before a failed re-registration after a hardware change is retried, doubled with every failure
+const REREGISTRATION_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_REREGISTRATION_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// What the miner does once the chain removed it, configured with `ON_WORKER_REMOVED`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RemovalPolicy {
+    /// Stop the miner, the operator decides how to proceed
+    Exit,
+    /// Register again with a new identity and keep waiting for tasks
+    Reregister,
+}
+
+impl FromStr for RemovalPolicy {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "exit" => Ok(RemovalPolicy::Exit),
+            "reregister" => Ok(RemovalPolicy::Reregister),
+            _ => Err(Error::Custom(format!(
+                "Unknown removal policy '{}', expected 'exit' or 'reregister'",
+                value
+            ))),
+        }
+    }
+}
+
+/// The `RemovalPolicy` set with `ON_WORKER_REMOVED`, see `parse_removal_policy`
+fn removal_policy() -> Result<RemovalPolicy> {
+    parse_removal_policy(std::env::var("ON_WORKER_REMOVED").ok().as_deref())
+}
+
+/// Parses the value of `ON_WORKER_REMOVED`, `exit` if it is unset
+///
+/// # Returns
+/// The `RemovalPolicy`, or an error if the setting names no policy, so a typo doesn't surface only once the chain
+/// removed the miner
+fn parse_removal_policy(policy: Option<&str>) -> Result<RemovalPolicy> {
+    match policy.map(str::trim) {
+        Some(policy) if !policy.is_empty() => policy
+            .parse()
+            .map_err(|e| Error::Custom(format!("Invalid ON_WORKER_REMOVED: {}", e))),
+        _ => Ok(RemovalPolicy::Exit),
+    }
 }
 
 pub enum RegistrationStatus{
@@ -21,34 +71,18 @@ pub enum RegistrationStatus{
     Unknown,
 }
 
-pub async fn confirm_registration(_: &Miner) -> Result<RegistrationStatus> {
-    let client = config::get_parachain_client()?;
-
+pub async fn confirm_registration(miner: &Miner) -> Result<RegistrationStatus> {
     let identity_path = &config::get_paths()?.identity_path;
-    let identity_file_content = fs::read_to_string(identity_path)?;
-    let identity: Identity = serde_json::from_str(&identity_file_content)?;
-    let identity = identity.miner_identity;
+    let identity = schema::read_identity(identity_path)?
+        .ok_or(Error::identity_not_initialized())?
+        .as_tuple();
 
     println!("Confirming miner registration...");
 
     println!("identity: {:?}", identity);
 
-    // Since there seems to be a bug in subxt that should have been resolved (and we possibly won't have a separate storage map for querying workers by id)
-    let miner_registration_confirmation_query = substrate_interface::api::storage()
-        .edge_connect()
-        .executable_workers_iter();
-
-    let mut result = client
-        .storage()
-        .at_latest()
-        .await?
-        .iter(miner_registration_confirmation_query)
-        .await?;
-
-    while let Some(Ok(miner)) = result.next().await {
-        if miner.value.owner == identity.0 && miner.value.id == identity.1 {
-            return Ok(RegistrationStatus::Registered(identity.0, identity.1));
-        }
+    if miner.chain.iter_workers().await?.contains(&identity) {
+        return Ok(RegistrationStatus::Registered(identity.0, identity.1));
     }
 
     println!("Miner is not registered");
@@ -58,89 +92,432 @@ pub async fn confirm_registration(_: &Miner) -> Result<RegistrationStatus> {
 pub async fn start_miner(miner: &mut Miner) -> Result<()> {
     println!("Starting miner...");
 
+    let removal_policy = removal_policy()?;
+
     println!("Waiting for tasks...");
 
     let client = config::get_parachain_client()?;
-    let tx_queue = config::get_tx_queue()?;
 
     match miner.confirm_registration().await {
         Ok(RegistrationStatus::Registered(owner, id)) => {
             miner.miner_identity = Some((owner, id));
-        }, 
-        Ok(RegistrationStatus::Unknown) => {
-            let keypair = miner.keypair.clone();
-            let rx = tx_queue.enqueue( move || {
-                let keypair = keypair.clone();
-                async move {
-                    let result = register(keypair).await?;
-                    Ok(TxOutput::RegistrationInfo(result))
-                }
-            })
-            .await?;
-
-            match rx.await {
-                Ok(Ok(TxOutput::RegistrationInfo(data))) => {
-                    miner.miner_identity = Some(data.clone());
-                    let miner_identity_json = serde_json::to_string(&MinerData {
-                        miner_owner: data.0.to_string(),
-                        miner_identity: (data.0, data.1),
-                    })?;
-                    miner.update_identity_file(&config::get_paths()?.identity_path, &miner_identity_json)?;
-                },
-                Ok(Err(e)) => println!("Error registering miner: {}", e),
-                Err(_) => println!("Response channel dropped."),
-                _ => println!("Missing identity string from registration event"),
-            }
         },
+        Ok(RegistrationStatus::Unknown) => register_miner(miner).await?,
         Err(e) => {
             println!("Error confirming miner registration: {}, registering...", e);
-            let keypair = miner.keypair.clone();
-            let rx = tx_queue.enqueue( move || {
-                let keypair = keypair.clone();
-                async move {
-                    let result = register(keypair).await?;
-                    Ok(TxOutput::RegistrationInfo(result))
-                }
-            })
-            .await?;
-
-            match rx.await {
-                Ok(Ok(TxOutput::RegistrationInfo(data))) => {
-                    miner.miner_identity = Some(data.clone());
-                    let miner_identity_json = serde_json::to_string(&MinerData {
-                        miner_owner: data.0.to_string(),
-                        miner_identity: (data.0, data.1),
-                    })?;
-                    miner.update_identity_file(&config::get_paths()?.identity_path, &miner_identity_json)?;
-                },
-                Ok(Err(e)) => println!("Error registering miner: {}", e),
-                Err(_) => println!("Response channel dropped."),
-                _ => println!("Missing identity data from registration event"),
-            }
+            register_miner(miner).await?;
         }
     }
+    if miner.miner_identity.is_none() {
+        return Err(Error::Custom("Registration of the miner failed".to_string()));
+    }
+
+    if let Err(e) = miner.refresh_registered_spec().await {
+        println!("Error comparing hardware with registered specs: {}", e);
+    }
+
+    if let Err(e) = report_capabilities(miner).await {
+        println!("Error publishing capability report: {}", e);
+    }
 
     let mut blocks = client.blocks().subscribe_finalized().await?;
+    let mut scheduler = Scheduler::start(&[
+        MaintenanceJob::SpecCheck,
+        MaintenanceJob::CapabilityReport,
+        MaintenanceJob::Heartbeat,
+        MaintenanceJob::ResponseAnchor,
+        MaintenanceJob::EndpointCheck,
+        MaintenanceJob::AuditDigest,
+    ]);
 
-    while let Some(Ok(block)) = blocks.next().await {
-        println!("New block imported: {:?}", block.hash());
-        let miner_identity = miner.miner_identity.clone()
-            .ok_or(Error::Custom("Miner identity not present!!!".to_string()))?;
-        println!("Active miner identity: {:?}", miner_identity);
+    loop {
+        // The identity is only dropped if the chain removed this miner, a re-registration after a hardware change
+        // returns with the new identity
+        if miner.miner_identity.is_none() {
+            match removal_policy {
+                RemovalPolicy::Exit => {
+                    println!("Miner was removed from the parachain, exiting.");
+                    return Ok(());
+                }
+                RemovalPolicy::Reregister => {
+                    println!("Miner was removed from the parachain, registering again...");
+                    register_miner(miner).await?;
+                    if miner.miner_identity.is_none() {
+                        return Err(Error::Custom(
+                            "Registration after removal from the parachain failed".to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        tokio::select! {
+            block = blocks.next() => {
+                let Some(Ok(block)) = block else {
+                    break;
+                };
+
+                println!("New block imported: {:?}", block.hash());
 
-        let events = block.events().await?;
+                let miner_identity = miner.miner_identity.clone()
+                    .ok_or(Error::Custom("Miner identity not present!!!".to_string()))?;
+                println!(
+                    "Active miner identity: {} / {}",
+                    chain_properties::format_account(&miner_identity.0),
+                    miner_identity.1
+                );
 
-        for event in events.iter() {
-            match event {
-                Ok(ev) => {
-                    if let Err(e) = miner.process_event(&ev).await {
-                        println!("Error processing event: {:?}", e);
+                let events = block.events().await?;
+
+                for event in events.iter() {
+                    match event {
+                        Ok(ev) => {
+                            if let Err(e) = miner.process_event(&ev).await {
+                                println!("Error processing event: {:?}", e);
+                            }
+                        }
+                        Err(e) => eprintln!("Error decoding event: {:?}", e),
                     }
                 }
-                Err(e) => eprintln!("Error decoding event: {:?}", e),
             }
+            Some(job) = scheduler.next() => {
+                run_maintenance_job(miner, job).await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Registers the miner and persists the identity assigned by the parachain
+async fn register_miner(miner: &mut Miner) -> Result<()> {
+    let tx_queue = config::get_tx_queue()?;
+    let keypair = miner.keypair.clone();
+    let chain = Arc::clone(&miner.chain);
+    let rx = tx_queue.enqueue( move || {
+        let keypair = keypair.clone();
+        let chain = Arc::clone(&chain);
+        async move {
+            let result = chain.register(keypair).await?;
+            Ok(TxOutput::RegistrationInfo(result))
         }
+    })
+    .await?;
+
+    match rx.await {
+        Ok(Ok(TxOutput::RegistrationInfo(data))) => {
+            miner.miner_identity = Some(data.clone());
+            schema::write_identity(&config::get_paths()?.identity_path, data.0, data.1)?;
+            fingerprint::bind_identity()?;
+            events::emit(
+                &miner.keypair.public_key().to_account_id(),
+                MinerEvent::Registered { miner_id: data.1 },
+            );
+        },
+        Ok(Err(e)) => println!("Error registering miner: {}", e),
+        Err(_) => println!("Response channel dropped."),
+        _ => println!("Missing identity data from registration event"),
     }
 
     Ok(())
 }
+
+async fn run_maintenance_job(miner: &mut Miner, job: MaintenanceJob) {
+    let result = match job {
+        MaintenanceJob::SpecCheck => miner.refresh_registered_spec().await,
+        MaintenanceJob::CapabilityReport => report_capabilities(miner).await,
+        MaintenanceJob::Heartbeat => {
+            tracing::info!(
+                "Heartbeat: miner {:?} is alive, current task: {:?}, event decode errors: {:?}",
+                miner.miner_identity,
+                miner.current_task,
+                event_processor::decode_error_counts()
+            );
+            Ok(())
+        }
+        MaintenanceJob::ResponseAnchor => anchor_served_responses(miner).await,
+        MaintenanceJob::EndpointCheck => republish_endpoint(miner).await,
+        MaintenanceJob::AuditDigest => match audit_sampling::seal_digests(&miner.keypair) {
+            Ok(sealed) => {
+                if !sealed.is_empty() {
+                    println!("Sealed {} audit digests", sealed.len());
+                }
+                audit_sampling::upload_digests(&sealed).await
+            }
+            Err(e) => Err(e),
+        },
+    };
+
+    if let Err(e) = result {
+        println!("Error running maintenance job {:?}: {}", job, e);
+    }
+}
+
+pub async fn refresh_registered_spec(miner: &mut Miner) -> Result<()> {
+    let (owner, miner_id) = miner
+        .miner_identity
+        .clone()
+        .ok_or(Error::identity_not_initialized())?;
+
+    let registered_spec = miner.chain.get_registered_spec(&owner, miner_id).await?;
+    let current_spec = run_blocking(|| Ok(specs::gather_hardware_spec())).await?;
+
+    if !specs::spec_changed_materially(&registered_spec, &current_spec) {
+        return Ok(());
+    }
+
+    // Re-registering assigns a new identity, which would orphan the running task
+    if miner.current_task.is_some() {
+        println!(
+            "Hardware changed (registered: {:?}, current: {:?}), re-registration deferred until the current task is finished",
+            registered_spec, current_spec
+        );
+        return Ok(());
+    }
+
+    println!(
+        "Hardware changed (registered: {:?}, current: {:?}), re-registering miner...",
+        registered_spec, current_spec
+    );
+
+    // Removing and registering are queued as separate jobs, so that a registration that keeps failing is retried
+    // without removing the already removed worker again, which could never succeed
+    let tx_queue = config::get_tx_queue()?;
+    let keypair = miner.keypair.clone();
+    let chain = Arc::clone(&miner.chain);
+    let rx = tx_queue.enqueue( move || {
+        let keypair = keypair.clone();
+        let chain = Arc::clone(&chain);
+        let owner = owner.clone();
+        async move {
+            remove_worker_if_registered(chain.as_ref(), keypair, &owner, miner_id).await?;
+            Ok(TxOutput::Success)
+        }
+    })
+    .await?;
+
+    match rx.await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => return Err(Error::Custom(format!("Error removing the outdated worker: {}", e))),
+        Err(_) => return Err(Error::Custom("Response channel dropped.".to_string())),
+    }
+
+    // The identity went with the worker, registering is retried until there is a new one, the miner can't serve
+    // anything without it
+    miner.miner_identity = None;
+    let mut retry_delay = REREGISTRATION_RETRY_DELAY;
+    loop {
+        if let Err(e) = register_miner(miner).await {
+            println!("Error re-registering miner: {}", e);
+        }
+        if let Some((owner, miner_id)) = &miner.miner_identity {
+            println!(
+                "Miner re-registered with updated specs: {} / {}",
+                chain_properties::format_account(owner),
+                miner_id
+            );
+            return Ok(());
+        }
+
+        println!(
+            "Re-registration with updated specs failed, retrying in {} s",
+            retry_delay.as_secs()
+        );
+        tokio::time::sleep(retry_delay).await;
+        retry_delay = (retry_delay * 2).min(MAX_REREGISTRATION_RETRY_DELAY);
+    }
+}
+
+/// Removes the worker unless it is gone already, eg. because an earlier attempt removed it
+async fn remove_worker_if_registered(
+    chain: &dyn ChainApi,
+    keypair: Keypair,
+    owner: &AccountId32,
+    miner_id: u64,
+) -> Result<()> {
+    let registered = chain
+        .iter_workers()
+        .await?
+        .iter()
+        .any(|(worker_owner, worker_id)| worker_owner == owner && *worker_id == miner_id);
+    if registered {
+        chain.remove_worker(keypair, miner_id).await?;
+    }
+    Ok(())
+}
+
+/// Gathers the runtime capabilities of the miner and publishes them for the registered worker
+async fn report_capabilities(miner: &Miner) -> Result<()> {
+    let miner_identity = miner
+        .miner_identity
+        .clone()
+        .ok_or(Error::identity_not_initialized())?;
+
+    let mut capabilities = specs::gather_capabilities(&config::get_paths()?.task_dir_path).await;
+    if config::optional_env("PUBLISH_HARDWARE_FINGERPRINT", false) {
+        capabilities.hardware_fingerprint = Some(
+            run_blocking(|| Ok(fingerprint::HardwareFingerprint::current()))
+                .await?
+                .commitment(&miner_identity),
+        );
+    }
+    println!("Miner capabilities: {:?}", capabilities);
+
+    let tx_queue = config::get_tx_queue()?;
+    let keypair = miner.keypair.clone();
+    let chain = Arc::clone(&miner.chain);
+    let rx = tx_queue.enqueue( move || {
+        let keypair = keypair.clone();
+        let chain = Arc::clone(&chain);
+        let miner_identity = miner_identity.clone();
+        let capabilities = capabilities.clone();
+        async move {
+            chain.publish_capabilities(keypair, miner_identity, &capabilities).await?;
+            Ok(TxOutput::Success)
+        }
+    })
+    .await?;
+
+    match rx.await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(Error::Custom("Response channel dropped.".to_string())),
+    }
+}
+
+/// Publishes the endpoint of the miner again if it changed since it was registered or last published, so that the
+/// miner keeps serving under its new address instead of having to register again
+async fn republish_endpoint(miner: &mut Miner) -> Result<()> {
+    let miner_identity = miner
+        .miner_identity
+        .clone()
+        .ok_or(Error::identity_not_initialized())?;
+
+    let endpoint = specs::resolve_endpoint().await?;
+    let published = match &miner.published_endpoint {
+        Some(published) => published.clone(),
+        None => {
+            miner
+                .chain
+                .get_registered_domain(&miner_identity.0, miner_identity.1)
+                .await?
+        }
+    };
+
+    if endpoint == published {
+        miner.published_endpoint = Some(endpoint);
+        return Ok(());
+    }
+
+    println!("Endpoint changed from {} to {}, publishing it", published, endpoint);
+
+    let tx_queue = config::get_tx_queue()?;
+    let keypair = miner.keypair.clone();
+    let chain = Arc::clone(&miner.chain);
+    let new_endpoint = endpoint.clone();
+    let rx = tx_queue.enqueue( move || {
+        let keypair = keypair.clone();
+        let chain = Arc::clone(&chain);
+        let miner_identity = miner_identity.clone();
+        let endpoint = new_endpoint.clone();
+        async move {
+            chain.publish_endpoint(keypair, miner_identity, &endpoint).await?;
+            Ok(TxOutput::Success)
+        }
+    })
+    .await?;
+
+    match rx.await {
+        Ok(Ok(_)) => {
+            miner.published_endpoint = Some(endpoint);
+            Ok(())
+        }
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(Error::Custom("Response channel dropped.".to_string())),
+    }
+}
+
+/// Anchors the Merkle roots of the responses served since the last anchor, one remark per task
+async fn anchor_served_responses(miner: &Miner) -> Result<()> {
+    let tx_queue = config::get_tx_queue()?;
+
+    let miner_account = miner.keypair.public_key().to_account_id();
+    for batch in response_anchor::take_batches(&miner_account)? {
+        let AnchorBatch { task_id, root, leaf_count } = batch;
+        println!("Anchoring {} served responses of task {}", leaf_count, task_id);
+
+        let keypair = miner.keypair.clone();
+        let rx = tx_queue.enqueue( move || {
+            let keypair = keypair.clone();
+            async move {
+                anchor_response_root(keypair, task_id, root, leaf_count).await?;
+                Ok(TxOutput::Success)
+            }
+        })
+        .await?;
+
+        match rx.await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => println!("Error anchoring responses of task {}: {}", task_id, e),
+            Err(_) => println!("Response channel dropped."),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{CurrentTask, HardwareSpec, TaskType};
+    use crate::utils::mock_chain::{self, ChainCall, MockChain};
+
+    #[tokio::test]
+    async fn re_registration_is_deferred_while_serving_a_task() {
+        let chain = Arc::new(MockChain::default());
+        *chain.registered_spec.lock().unwrap() = Some(HardwareSpec {
+            ram: 1,
+            storage: 1,
+            cpu: 1,
+        });
+        let mut miner = mock_chain::miner(Arc::clone(&chain));
+        miner.current_task = Some(CurrentTask {
+            id: 1,
+            task_type: TaskType::NeuroZk,
+        });
+
+        refresh_registered_spec(&mut miner).await.unwrap();
+
+        assert!(chain.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_removed_worker_is_not_removed_again() {
+        let chain = Arc::new(MockChain::default());
+        let miner = mock_chain::miner(Arc::clone(&chain));
+        let (owner, miner_id) = miner.miner_identity.clone().unwrap();
+
+        remove_worker_if_registered(chain.as_ref(), miner.keypair.clone(), &owner, miner_id)
+            .await
+            .unwrap();
+        assert!(chain.calls().is_empty());
+
+        chain.workers.lock().unwrap().push((owner.clone(), miner_id));
+        remove_worker_if_registered(chain.as_ref(), miner.keypair.clone(), &owner, miner_id)
+            .await
+            .unwrap();
+        assert_eq!(chain.calls(), vec![ChainCall::RemoveWorker(miner_id)]);
+    }
+
+    #[test]
+    fn a_misspelled_removal_policy_is_refused() {
+        assert_eq!(parse_removal_policy(None).unwrap(), RemovalPolicy::Exit);
+        assert_eq!(parse_removal_policy(Some(" ")).unwrap(), RemovalPolicy::Exit);
+        assert_eq!(
+            parse_removal_policy(Some("reregister")).unwrap(),
+            RemovalPolicy::Reregister
+        );
+
+        assert!(parse_removal_policy(Some("re-register")).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    config,
+    events::{self, MinerEvent},
+    parent_runtime::{
+        proof,
+        setup_progress::{self, SetupStage},
+    },
+    substrate_interface,
+};
+use std::{
+    future::Future,
+    path::Path,
+    time::{Duration, Instant},
+};
+use subxt_signer::sr25519::Keypair;
+
+/// How often a running download is checked against the setup deadline
+const PROJECTION_INTERVAL: Duration = Duration::from_secs(5);
+/// A download is only projected once it ran this long, its first seconds are dominated by connecting
+const PROJECTION_GRACE: Duration = Duration::from_secs(15);
+
+tokio::task_local! {
+    /// The deadline the work of the current task has to meet, the kind keeps setup and proof deadlines apart
+    static DEADLINE: (TaskDeadline, Instant);
+}
+
+/// The work of a task the chain expects to be done in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskDeadline {
+    /// Downloading and setting up the model, counted from the assignment of the task
+    Setup,
+    /// Generating and submitting a proof, counted from the request of the proof
+    Proof,
+}
+
+impl TaskDeadline {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskDeadline::Setup => "setup",
+            TaskDeadline::Proof => "proof",
+        }
+    }
+
+    /// The setting overriding the deadline and the pallet constant the runtime declares it with, in blocks
+    fn source(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            TaskDeadline::Setup => (
+                "TASK_SETUP_DEADLINE_SECS",
+                "TaskManagement",
+                "TaskSetupDeadline",
+            ),
+            TaskDeadline::Proof => ("PROOF_DEADLINE_SECS", "NeuroZk", "ProofSubmissionDeadline"),
+        }
+    }
+
+    /// How long the work may take. `TASK_SETUP_DEADLINE_SECS` and `PROOF_DEADLINE_SECS` take precedence (0 disables
+    /// the deadline), otherwise the runtime is asked for `TaskManagement::TaskSetupDeadline` and
+    /// `NeuroZk::ProofSubmissionDeadline`, converted from blocks with the slot duration of Aura.
+    ///
+    /// # Returns
+    /// The budget, or `None` if neither the settings nor the runtime specify a deadline
+    pub fn budget(&self) -> Option<Duration> {
+        let (setting, pallet, constant) = self.source();
+        match std::env::var(setting)
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+        {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => chain_budget(pallet, constant),
+        }
+    }
+
+    /// The moment the work started now has to be done by, `None` without a deadline
+    pub fn start(&self) -> Option<Instant> {
+        self.budget().map(|budget| Instant::now() + budget)
+    }
+
+    /// Runs `future` with the deadline, so that the work within it can tell whether it still completes in time
+    pub async fn scope<F: Future>(self, deadline: Option<Instant>, future: F) -> F::Output {
+        match deadline {
+            Some(deadline) => DEADLINE.scope((self, deadline), future).await,
+            None => future.await,
+        }
+    }
+
+    /// The deadline of the current scope
+    pub fn current(&self) -> Option<Instant> {
+        DEADLINE
+            .try_with(|(kind, deadline)| (kind == self).then_some(*deadline))
+            .ok()
+            .flatten()
+    }
+
+    /// Resolves once the deadline of the current scope passed, never without one
+    pub async fn passed(&self) {
+        match self.current() {
+            Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// Reads a deadline the runtime declares as a constant in blocks. The current runtime declares none, the constants
+/// are looked up dynamically so that miners honour them as soon as a runtime upgrade adds them.
+fn chain_budget(pallet: &str, constant: &str) -> Option<Duration> {
+    let client = config::get_parachain_client().ok()?;
+    let blocks = client
+        .constants()
+        .at(&subxt::dynamic::constant(pallet, constant))
+        .ok()?
+        .to_value()
+        .ok()?
+        .as_u128()?;
+    let block_time_ms = client
+        .constants()
+        .at(&substrate_interface::api::constants().aura().slot_duration())
+        .ok()?;
+
+    Some(Duration::from_millis(
+        u64::try_from(blocks)
+            .unwrap_or(u64::MAX)
+            .saturating_mul(block_time_ms),
+    ))
+}
+
+/// How long work takes to complete at the pace it progressed so far
+///
+/// # Arguments
+/// * `done` - The work done, eg. bytes downloaded
+/// * `left` - The work left
+/// * `elapsed` - The time `done` took
+///
+/// # Returns
+/// The projected duration of the work left, `None` if nothing was done to project from
+pub fn projected_duration(done: u64, left: u64, elapsed: Duration) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+    let nanos = elapsed.as_nanos() * left as u128 / done as u128;
+    Some(Duration::from_nanos(
+        u64::try_from(nanos).unwrap_or(u64::MAX),
+    ))
+}
+
+/// Watches a running download against the setup deadline of the current scope. The download is abandoned as soon as
+/// it can't complete in time at the pace it progressed so far, not only once the deadline passed.
+///
+/// # Arguments
+/// * `file_path` - The file the archive is downloaded into
+/// * `size` - The size of the archive, downloads of unknown size are only abandoned once the deadline passed
+///
+/// # Returns
+/// Why the download was abandoned, never resolves without a deadline
+pub async fn missed_download(file_path: &Path, size: Option<u64>) -> String {
+    let Some(deadline) = TaskDeadline::Setup.current() else {
+        return std::future::pending().await;
+    };
+    let written = || {
+        std::fs::metadata(file_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    };
+    let started = Instant::now();
+    let initially_written = written();
+
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return "The setup deadline passed during the download".to_string();
+        }
+        tokio::time::sleep(PROJECTION_INTERVAL.min(deadline - now)).await;
+
+        let Some(size) = size else {
+            continue;
+        };
+        let elapsed = started.elapsed();
+        if elapsed < PROJECTION_GRACE {
+            continue;
+        }
+        let written = written();
+        let time_left = deadline.saturating_duration_since(Instant::now());
+        if let Some(projected) = projected_duration(
+            written.saturating_sub(initially_written),
+            size.saturating_sub(written),
+            elapsed,
+        ) {
+            if projected > time_left {
+                return format!(
+                    "The download can't complete before the setup deadline: {} of {} bytes after {} s, about {} s needed but {} s left",
+                    written,
+                    size,
+                    elapsed.as_secs(),
+                    projected.as_secs(),
+                    time_left.as_secs()
+                );
+            }
+        }
+    }
+}
+
+/// Reports a deadline the miner misses as soon as it knows, instead of letting the chain time the task out. A missed
+/// setup is published as a failed setup stage, a missed proof is recorded with the outcomes of proofs.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The task whose deadline is missed
+/// * `deadline` - The deadline that is missed
+/// * `reason` - Why it is missed
+pub fn report_missed(keypair: &Keypair, task_id: u64, deadline: TaskDeadline, reason: String) {
+    println!(
+        "Task {} misses its {} deadline: {}",
+        task_id,
+        deadline.as_str(),
+        reason
+    );
+    events::emit(
+        &keypair.public_key().to_account_id(),
+        MinerEvent::DeadlineMissed {
+            task_id,
+            deadline,
+            detail: reason.clone(),
+        },
+    );
+
+    match deadline {
+        TaskDeadline::Setup => {
+            setup_progress::report(keypair, task_id, SetupStage::Failed, Some(reason))
+        }
+        TaskDeadline::Proof => {
+            if let Err(e) =
+                proof::record_proof_outcome(task_id, "deadline missed", 0, Some(&reason))
+            {
+                println!("Error recording the missed proof deadline: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn work_is_projected_from_its_pace_within_the_scope_of_its_deadline() {
+        assert_eq!(
+            projected_duration(25, 75, Duration::from_secs(10)),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(projected_duration(0, 75, Duration::from_secs(10)), None);
+        assert_eq!(
+            projected_duration(100, 0, Duration::from_secs(10)),
+            Some(Duration::ZERO)
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert_eq!(TaskDeadline::Setup.current(), None);
+        TaskDeadline::Setup
+            .scope(Some(deadline), async {
+                assert_eq!(TaskDeadline::Setup.current(), Some(deadline));
+                assert_eq!(TaskDeadline::Proof.current(), None);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+
+/// Longest identifier accepted, the storage backends and remarks can't carry more
+const MAX_IDENTIFIER_LEN: usize = 512;
+
+/// Where the archive of a task is stored, as the task owner wrote it on chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskIdentifier {
+    /// An IPFS content identifier, CIDv0 (`Qm...`) or CIDv1 in base32 (`b...`), resolved against the storage location
+    Cid(String),
+    /// An absolute URL, downloaded from as it is
+    Url(String),
+    /// Any other key of the storage backend, eg. a CESS file id or a file name, resolved against the storage location
+    Fid(String),
+}
+
+impl TaskIdentifier {
+    /// Decodes the identifier of a task as stored on chain. Padding of fixed size buffers (trailing NULs and
+    /// whitespace) and a byte order mark are ignored, and CIDs submitted in their binary form are encoded as text.
+    ///
+    /// # Arguments
+    /// * `bytes` - The identifier as stored on chain
+    ///
+    /// # Returns
+    /// The `TaskIdentifier`, or why the bytes aren't a usable identifier, with the offending bytes printed lossily
+    pub fn decode(bytes: &[u8]) -> std::result::Result<Self, String> {
+        // Binary CIDs start with bytes no text starts with, and may end with what looks like padding
+        if let Some(cid) = binary_cid(bytes) {
+            return Ok(TaskIdentifier::Cid(cid));
+        }
+
+        let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+        let end = bytes
+            .iter()
+            .rposition(|byte| *byte != 0 && !byte.is_ascii_whitespace())
+            .map_or(0, |last| last + 1);
+        let start = bytes[..end]
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(end);
+        let bytes = &bytes[start..end];
+
+        if bytes.is_empty() {
+            return Err("The task identifier is empty".to_string());
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(identifier) => Self::parse(identifier),
+            Err(_) => Err(format!(
+                "The task identifier is neither UTF-8 nor a binary CID: {:?} (0x{})",
+                String::from_utf8_lossy(bytes),
+                hex::encode(&bytes[..bytes.len().min(64)])
+            )),
+        }
+    }
+
+    /// Classifies a textual identifier
+    ///
+    /// # Returns
+    /// The `TaskIdentifier`, or why the text isn't a usable identifier
+    pub fn parse(identifier: &str) -> std::result::Result<Self, String> {
+        if identifier.len() > MAX_IDENTIFIER_LEN {
+            return Err(format!(
+                "The task identifier is {} bytes long, at most {} are supported",
+                identifier.len(),
+                MAX_IDENTIFIER_LEN
+            ));
+        }
+        if let Some(c) = identifier
+            .chars()
+            .find(|c| c.is_control() || c.is_whitespace() || *c == char::REPLACEMENT_CHARACTER)
+        {
+            return Err(format!(
+                "The task identifier {:?} contains the invalid character {:?}",
+                identifier, c
+            ));
+        }
+
+        if identifier.contains("://") {
+            return match url::Url::parse(identifier) {
+                Ok(url) if url.host_str().is_some() => {
+                    Ok(TaskIdentifier::Url(identifier.to_string()))
+                }
+                Ok(_) => Err(format!("The task URL {:?} has no host", identifier)),
+                Err(e) => Err(format!("The task URL {:?} is invalid: {}", identifier, e)),
+            };
+        }
+        if identifier.split('/').any(|segment| segment == "..") {
+            return Err(format!(
+                "The task identifier {:?} leaves the storage location",
+                identifier
+            ));
+        }
+
+        if is_cid(identifier) {
+            Ok(TaskIdentifier::Cid(identifier.to_string()))
+        } else {
+            Ok(TaskIdentifier::Fid(identifier.to_string()))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TaskIdentifier::Cid(identifier)
+            | TaskIdentifier::Url(identifier)
+            | TaskIdentifier::Fid(identifier) => identifier,
+        }
+    }
+}
+
+impl fmt::Display for TaskIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+fn is_cid(identifier: &str) -> bool {
+    let cid_v0 = identifier.len() == 46
+        && identifier.starts_with("Qm")
+        && identifier
+            .bytes()
+            .all(|byte| BASE58_ALPHABET.contains(&byte));
+    let cid_v1 = identifier.len() >= 50
+        && identifier.starts_with("ba")
+        && identifier[1..]
+            .bytes()
+            .all(|byte| BASE32_ALPHABET.contains(&byte));
+    cid_v0 || cid_v1
+}
+
+/// Encodes a CID in its binary form the way it is written as text: a sha2-256 multihash as CIDv0, a CIDv1 in
+/// multibase base32
+fn binary_cid(bytes: &[u8]) -> Option<String> {
+    match bytes {
+        [0x12, 0x20, digest @ ..] if digest.len() == 32 => Some(base58(bytes)),
+        [0x01, _, ..] if bytes.len() >= 34 => Some(format!("b{}", base32(bytes))),
+        _ => None,
+    }
+}
+
+fn base58(bytes: &[u8]) -> String {
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in bytes {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    std::iter::repeat_n(BASE58_ALPHABET[0], zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|digit| BASE58_ALPHABET[*digit as usize]),
+        )
+        .map(char::from)
+        .collect()
+}
+
+fn base32(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_classified_and_malformed_ones_explained() {
+        assert_eq!(
+            TaskIdentifier::decode(b"QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG\0\0\0"),
+            Ok(TaskIdentifier::Cid(
+                "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG".to_string()
+            ))
+        );
+        assert_eq!(
+            TaskIdentifier::decode(b" https://mirror.example.com/model-7.tar.gz\n"),
+            Ok(TaskIdentifier::Url(
+                "https://mirror.example.com/model-7.tar.gz".to_string()
+            ))
+        );
+        assert_eq!(
+            TaskIdentifier::decode(b"model-7.tar.gz"),
+            Ok(TaskIdentifier::Fid("model-7.tar.gz".to_string()))
+        );
+
+        // The binary form of the CIDv0 above
+        let binary =
+            hex::decode("12209d6c2be50f706953479ab9df2ce3edca90b68053c00b3004b7f0accbe1e8eedf")
+                .unwrap();
+        assert_eq!(
+            TaskIdentifier::decode(&binary),
+            Ok(TaskIdentifier::Cid(
+                "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG".to_string()
+            ))
+        );
+        let mut cid_v1 = vec![0x01, 0x70];
+        cid_v1.extend_from_slice(&binary);
+        assert_eq!(
+            TaskIdentifier::decode(&cid_v1),
+            Ok(TaskIdentifier::Cid(
+                "bafybeie5nqv6kd3qnfjupgvz34woh3oksc3iau6abmyajn7qvtf6d2ho34".to_string()
+            ))
+        );
+        assert_eq!(
+            TaskIdentifier::parse("bafybeie5nqv6kd3qnfjupgvz34woh3oksc3iau6abmyajn7qvtf6d2ho34"),
+            TaskIdentifier::decode(&cid_v1)
+        );
+
+        assert!(TaskIdentifier::decode(b"\0\0").is_err());
+        assert!(TaskIdentifier::decode(b"\xff\xfemodel").is_err());
+        assert!(TaskIdentifier::decode(b"model 7").is_err());
+        assert!(TaskIdentifier::decode(b"../../etc/passwd").is_err());
+        assert!(TaskIdentifier::decode(b"https://").is_err());
+    }
+}
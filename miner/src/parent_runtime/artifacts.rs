@@ -0,0 +1,256 @@
+use crate::{
+    config,
+    error::{Error, Result},
+    parent_runtime::storage_upload::{self, PutUpload, StorageBackend},
+    utils::blocking::run_blocking,
+};
+use futures::future::BoxFuture;
+use open_inference_runtime::{Artifact, ArtifactStore};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Subdirectory of the task directory artifacts are kept in, it is removed with the task
+const ARTIFACT_DIR: &str = "artifacts";
+/// Artifacts are pruned at most this often, not on every stored artifact
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The directory the artifacts of a task are written to with `ARTIFACT_STORAGE=task_dir`
+pub fn artifact_dir(task_dir: &str) -> PathBuf {
+    Path::new(task_dir).join(ARTIFACT_DIR)
+}
+
+/// The artifact store of an OpenInference task as configured by `ARTIFACT_STORAGE`:
+/// - `task_dir` (default): Written to the task directory and served by the inference server, they are removed after
+///   `ARTIFACT_RETENTION_SECS` (a day by default, 0 keeps them) or once they exceed `ARTIFACT_MAX_BYTES` in total
+///   (1 GiB by default, 0 for no limit), oldest first
+/// - `upload`: Uploaded with a `PUT` to `ARTIFACT_UPLOAD_URL` if it is set, or else to the `artifacts` directory of the
+///   storage backend configured by `STORAGE_UPLOAD`, retention is up to the storage backend
+/// - `inline`: Embedded into the responses as base64
+///
+/// # Arguments
+/// * `artifact_dir` - The directory of the task artifacts, see `artifact_dir`
+/// * `artifacts_path` - The path the inference server serves the task artifacts under
+///
+/// # Returns
+/// The store, `None` for inline artifacts, or an `Error` if the storage setting is invalid
+pub fn store_from_env(
+    artifact_dir: PathBuf,
+    artifacts_path: String,
+) -> Result<Option<Arc<dyn ArtifactStore>>> {
+    match config::optional_env("ARTIFACT_STORAGE", "task_dir".to_string()).as_str() {
+        "task_dir" => Ok(Some(Arc::new(TaskDirArtifacts {
+            dir: artifact_dir,
+            artifacts_path,
+            retention: match config::optional_env("ARTIFACT_RETENTION_SECS", 86_400u64) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            max_bytes: match config::optional_env("ARTIFACT_MAX_BYTES", 1u64 << 30) {
+                0 => None,
+                bytes => Some(bytes),
+            },
+            last_pruned: Mutex::new(None),
+        }))),
+        "upload" => {
+            let uploaded = match std::env::var("ARTIFACT_UPLOAD_URL") {
+                Ok(url) => UploadedArtifacts {
+                    backend: Arc::new(PutUpload::new(config::http_client()?, &url)),
+                    prefix: String::new(),
+                },
+                Err(_) => UploadedArtifacts {
+                    backend: storage_upload::backend_from_env()?,
+                    prefix: format!("{}/", ARTIFACT_DIR),
+                },
+            };
+            Ok(Some(Arc::new(uploaded)))
+        }
+        "inline" => Ok(None),
+        other => Err(Error::Custom(format!(
+            "Invalid ARTIFACT_STORAGE '{}', expected task_dir, upload or inline",
+            other
+        ))),
+    }
+}
+
+/// Artifacts written to the task directory. They are served without authentication, but only under the SHA-256 of
+/// their content, so only clients that received a reference can fetch them.
+struct TaskDirArtifacts {
+    dir: PathBuf,
+    artifacts_path: String,
+    retention: Option<Duration>,
+    max_bytes: Option<u64>,
+    last_pruned: Mutex<Option<Instant>>,
+}
+
+impl ArtifactStore for TaskDirArtifacts {
+    fn store<'a>(&'a self, artifact: &'a Artifact) -> BoxFuture<'a, Result<Value, String>> {
+        Box::pin(async move {
+            let file_name = artifact.file_name();
+            let path = self.dir.join(&file_name);
+            let bytes = artifact.bytes.clone();
+            let prune = {
+                let mut last_pruned = self.last_pruned.lock().unwrap();
+                let due = last_pruned.is_none_or(|pruned| pruned.elapsed() >= PRUNE_INTERVAL);
+                if due {
+                    *last_pruned = Some(Instant::now());
+                }
+                due
+            };
+            let (dir, retention, max_bytes) = (self.dir.clone(), self.retention, self.max_bytes);
+
+            run_blocking(move || {
+                fs::create_dir_all(&dir)?;
+                fs::write(&path, bytes)?;
+                if prune {
+                    prune_artifacts(&dir, retention, max_bytes, &path)?;
+                }
+                Ok(())
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+
+            Ok(artifact.reference(json!({
+                "storage": "task_dir",
+                // Relative to the host the client connected to
+                "url": format!("{}/{}", self.artifacts_path, file_name),
+            })))
+        })
+    }
+}
+
+/// Removes artifacts older than `retention`, then the oldest ones until they fit into `max_bytes`
+///
+/// # Arguments
+/// * `keep` - The artifact that was just stored, it is never removed
+fn prune_artifacts(
+    dir: &Path,
+    retention: Option<Duration>,
+    max_bytes: Option<u64>,
+    keep: &Path,
+) -> Result<()> {
+    let mut artifacts: Vec<(PathBuf, SystemTime, u64)> = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+        })
+        .filter(|(path, _, _)| path != keep)
+        .collect();
+    artifacts.sort_by_key(|(_, modified, _)| *modified);
+
+    let now = SystemTime::now();
+    let mut total: u64 = artifacts.iter().map(|(_, _, len)| len).sum::<u64>()
+        + fs::metadata(keep)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+    for (path, modified, len) in artifacts {
+        let expired = retention
+            .is_some_and(|retention| now.duration_since(modified).unwrap_or_default() > retention);
+        let over_quota = max_bytes.is_some_and(|max_bytes| total > max_bytes);
+        if expired || over_quota {
+            fs::remove_file(&path)?;
+            total -= len;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads an artifact the inference server serves
+///
+/// # Arguments
+/// * `artifact_dir` - The directory of the task artifacts
+/// * `file_name` - The file name of the artifact, as in its reference
+///
+/// # Returns
+/// The content type and the content, or `None` if the name is not one of an artifact or the artifact was removed
+pub fn read_artifact(artifact_dir: &Path, file_name: &str) -> Option<(&'static str, Vec<u8>)> {
+    let (hash, extension) = file_name.split_once('.')?;
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let content_type = match extension {
+        "png" => "image/png",
+        "wav" => "audio/wav",
+        _ => return None,
+    };
+
+    Some((content_type, fs::read(artifact_dir.join(file_name)).ok()?))
+}
+
+/// Artifacts uploaded to a storage backend
+struct UploadedArtifacts {
+    backend: Arc<dyn StorageBackend>,
+    prefix: String,
+}
+
+impl ArtifactStore for UploadedArtifacts {
+    fn store<'a>(&'a self, artifact: &'a Artifact) -> BoxFuture<'a, Result<Value, String>> {
+        Box::pin(async move {
+            let name = format!("{}{}", self.prefix, artifact.file_name());
+            let stored = self
+                .backend
+                .upload(&name, artifact.content_type, artifact.bytes.clone())
+                .await
+                .map_err(|e| format!("Upload failed: {}", e))?;
+
+            let url = stored.url.clone().unwrap_or_else(|| stored.id.clone());
+            let mut location = json!({ "storage": "upload", "url": url });
+            if stored.id != url {
+                location["cid"] = json!(stored.id);
+            }
+            Ok(artifact.reference(location))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pruning_keeps_the_newest_artifacts_within_the_quota() {
+        let dir = std::env::temp_dir().join(format!("miner-artifacts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old.png");
+        let newer = dir.join("newer.png");
+        let stored = dir.join("stored.png");
+        fs::write(&old, [0u8; 10]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(&newer, [0u8; 10]).unwrap();
+        fs::write(&stored, [0u8; 10]).unwrap();
+
+        prune_artifacts(&dir, None, Some(25), &stored).unwrap();
+
+        assert!(!old.exists());
+        assert!(newer.exists());
+        assert!(stored.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_artifact_names_are_served() {
+        let dir = std::env::temp_dir().join(format!("miner-artifact-names-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let name = format!("{}.png", "a".repeat(64));
+        fs::write(dir.join(&name), b"png").unwrap();
+
+        assert_eq!(
+            read_artifact(&dir, &name),
+            Some(("image/png", b"png".to_vec()))
+        );
+        assert_eq!(read_artifact(&dir, "../identity.json"), None);
+        assert_eq!(
+            read_artifact(&dir, &format!("{}.json", "a".repeat(64))),
+            None
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{config, config::get_paths, error::Result, parent_runtime::storage_upload};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use subxt::utils::AccountId32;
+use subxt_signer::sr25519::Keypair;
+
+/// Signed digests kept per task for the audit endpoint, older ones are only kept on disk
+const MAX_SERVED_DIGESTS: usize = 24;
+
+/// Sampling is configured with `AUDIT_SAMPLE_RATE` (0.0 to 1.0, default 0) and `AUDIT_SALT`, a secret shared with the
+/// task owner out of band. Without a salt nothing is sampled, as unsalted hashes of low-entropy requests could be
+/// reversed by anyone reading the digests.
+struct AuditConfig {
+    salt: Vec<u8>,
+    rate: f64,
+}
+
+static AUDIT_CONFIG: Lazy<Option<AuditConfig>> = Lazy::new(|| {
+    let rate: f64 = config::optional_env("AUDIT_SAMPLE_RATE", 0.0);
+    let salt = config::optional_env("AUDIT_SALT", String::new());
+    if rate <= 0.0 {
+        return None;
+    }
+    if salt.is_empty() {
+        println!("AUDIT_SAMPLE_RATE is set without AUDIT_SALT, request audit sampling is disabled");
+        return None;
+    }
+
+    Some(AuditConfig {
+        salt: salt.into_bytes(),
+        rate: rate.min(1.0),
+    })
+});
+
+/// A sampled request/response pair, only the salted hashes of the messages are kept
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditSample {
+    pub request_hash: String,
+    pub response_hash: String,
+    /// Unix time in milliseconds the request was received at
+    pub received_at: u64,
+    pub latency_ms: u64,
+}
+
+struct Window {
+    /// Unix time in milliseconds the window was opened at
+    since: u64,
+    samples: Vec<AuditSample>,
+}
+
+/// Samples recorded since the last digest, per serving miner and task
+static WINDOWS: Lazy<Mutex<HashMap<(AccountId32, u64), Window>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static DIGESTS: Lazy<Mutex<HashMap<(AccountId32, u64), VecDeque<serde_json::Value>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Whether requests are sampled, a salt and a sample rate are configured
+pub fn is_enabled() -> bool {
+    AUDIT_CONFIG.is_some()
+}
+
+/// Records a served request/response pair if it is sampled. Whether a request is sampled only depends on its salted
+/// hash, so the task owner can tell from their own client logs which requests must appear in the digests: a missing
+/// one was dropped, an unknown one was fabricated.
+///
+/// # Arguments
+/// * `miner` - The miner serving the task
+/// * `task_id` - The task the request was served for
+/// * `request` - The request as received
+/// * `response` - The response as sent
+/// * `received_at` - When the request was received
+/// * `latency` - How long answering the request took
+pub fn record(
+    miner: &AccountId32,
+    task_id: u64,
+    request: &str,
+    response: &str,
+    received_at: SystemTime,
+    latency: Duration,
+) {
+    let Some(audit_config) = AUDIT_CONFIG.as_ref() else {
+        return;
+    };
+
+    let request_hash = salted_hash(&audit_config.salt, request);
+    if !is_sampled(&request_hash, audit_config.rate) {
+        return;
+    }
+
+    let sample = AuditSample {
+        request_hash: hex::encode(request_hash),
+        response_hash: hex::encode(salted_hash(&audit_config.salt, response)),
+        received_at: unix_millis(received_at),
+        latency_ms: latency.as_millis() as u64,
+    };
+
+    let mut windows = WINDOWS.lock().unwrap();
+    let window = windows
+        .entry((miner.clone(), task_id))
+        .or_insert_with(|| Window {
+            since: unix_millis(SystemTime::now()),
+            samples: Vec::new(),
+        });
+    window.samples.push(sample);
+}
+
+/// Closes the sampling window of every task the miner served and signs a digest of it with the key of the miner.
+/// Digests are kept on disk and the latest ones are served to task owners.
+///
+/// # Returns
+/// The digests that were sealed
+pub fn seal_digests(keypair: &Keypair) -> Result<Vec<serde_json::Value>> {
+    let miner = keypair.public_key().to_account_id();
+    let windows: Vec<(u64, Window)> = {
+        let mut windows = WINDOWS.lock().unwrap();
+        let keys: Vec<_> = windows
+            .keys()
+            .filter(|(server, _)| *server == miner)
+            .cloned()
+            .collect();
+
+        keys.into_iter()
+            .filter_map(|key| windows.remove(&key).map(|window| (key.1, window)))
+            .collect()
+    };
+
+    let sample_rate = AUDIT_CONFIG
+        .as_ref()
+        .map_or(0.0, |audit_config| audit_config.rate);
+    let mut sealed = Vec::new();
+    for (task_id, window) in windows {
+        if window.samples.is_empty() {
+            continue;
+        }
+
+        let digest = serde_json::json!({
+            "task_id": task_id,
+            "miner": miner.to_string(),
+            "since": window.since,
+            "until": unix_millis(SystemTime::now()),
+            "sample_rate": sample_rate,
+            "samples": window.samples,
+        });
+        let signature = keypair.sign(digest.to_string().as_bytes());
+        let signed = serde_json::json!({
+            "digest": digest,
+            "signature": hex::encode(signature.0),
+        });
+
+        persist_digest(task_id, window.since, &signed)?;
+
+        let mut digests = DIGESTS.lock().unwrap();
+        let served = digests.entry((miner.clone(), task_id)).or_default();
+        served.push_back(signed.clone());
+        if served.len() > MAX_SERVED_DIGESTS {
+            served.pop_front();
+        }
+        sealed.push(signed);
+    }
+
+    Ok(sealed)
+}
+
+/// Uploads sealed digests to the `audit-digests` directory of the storage backend if `AUDIT_DIGEST_UPLOAD` is set,
+/// so that they outlive the miner and can be reconciled without reaching it
+///
+/// # Arguments
+/// * `sealed` - The digests returned by `seal_digests`
+pub async fn upload_digests(sealed: &[serde_json::Value]) -> Result<()> {
+    if sealed.is_empty() || !config::optional_env("AUDIT_DIGEST_UPLOAD", false) {
+        return Ok(());
+    }
+
+    let backend = storage_upload::backend_from_env()?;
+    for signed in sealed {
+        let name = format!(
+            "audit-digests/{}-{}.json",
+            signed["digest"]["task_id"], signed["digest"]["since"]
+        );
+        let stored = backend
+            .upload(&name, "application/json", signed.to_string().into_bytes())
+            .await?;
+        println!("Uploaded audit digest {} as {}", name, stored.id);
+    }
+
+    Ok(())
+}
+
+/// The latest signed digests of a task, oldest first. The signature covers the compact JSON of `digest`.
+pub fn digests(miner: &AccountId32, task_id: u64) -> Vec<serde_json::Value> {
+    DIGESTS
+        .lock()
+        .unwrap()
+        .get(&(miner.clone(), task_id))
+        .map(|digests| digests.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn salted_hash(salt: &[u8], message: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(message.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Reads the first 8 bytes of the hash as a uniformly distributed fraction
+fn is_sampled(hash: &[u8; 32], rate: f64) -> bool {
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&hash[..8]);
+    (u64::from_be_bytes(prefix) as f64 / u64::MAX as f64) < rate
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn persist_digest(task_id: u64, since: u64, signed: &serde_json::Value) -> Result<()> {
+    // Kept next to the identity, the task and log directories are removed when a task stops
+    let identity_path = PathBuf::from(&get_paths()?.identity_path);
+    let digest_dir = identity_path
+        .parent()
+        .map(|dir| dir.join("audit-digests"))
+        .unwrap_or_else(|| PathBuf::from("audit-digests"));
+    fs::create_dir_all(&digest_dir)?;
+
+    fs::write(
+        digest_dir.join(format!("{}-{}.json", task_id, since)),
+        signed.to_string(),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_depends_only_on_the_salted_request() {
+        let hash = salted_hash(b"salt", "{\"inputs\":[]}");
+        assert_eq!(hash, salted_hash(b"salt", "{\"inputs\":[]}"));
+        assert_ne!(hash, salted_hash(b"other salt", "{\"inputs\":[]}"));
+
+        assert!(is_sampled(&hash, 1.0));
+        assert!(!is_sampled(&hash, 0.0));
+    }
+}
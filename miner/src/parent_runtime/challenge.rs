@@ -0,0 +1,260 @@
+use crate::{
+    config,
+    error::{Error, Result},
+    utils::{tx_builder::anchor_challenge_commitment, tx_queue::TxOutput},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use subxt_signer::sr25519::Keypair;
+
+/// Window `CHALLENGE_ANCHORS_PER_HOUR` counts the anchored challenges in
+const ANCHOR_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// When the challenges anchored within the window were answered, across all tasks, every anchor costs the miner a fee
+static ANCHORS: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+
+/// A spot-check of the task owner: an input the miner answers with a commitment to its output within a deadline,
+/// eg. `{"command":"challenge","request_id":1,"nonce":"...","input":{...},"deadline_ms":5000,"anchor":true}`. Unlike
+/// proofs it works for every engine, the owner compares the output with what a reference deployment computes.
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub request_id: Option<Value>,
+    /// Chosen by the task owner, so that a commitment can't be prepared before the challenge
+    pub nonce: String,
+    /// The request the engine answers, as a client would send it
+    pub input: Value,
+    pub deadline: Duration,
+    /// Whether the commitment is also anchored on chain
+    pub anchor: bool,
+    pub received_at: Instant,
+}
+
+impl Challenge {
+    /// Reads a challenge from a `challenge` control message. The deadline defaults to `CHALLENGE_DEFAULT_DEADLINE_MS`
+    /// (default 30000) and is capped at `CHALLENGE_MAX_DEADLINE_MS` (default 300000).
+    ///
+    /// # Returns
+    /// The `Challenge`, or why the message is not a valid challenge
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let message: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+
+        let nonce = message
+            .get("nonce")
+            .and_then(Value::as_str)
+            .filter(|nonce| !nonce.is_empty())
+            .ok_or("A challenge needs a non-empty `nonce`")?;
+        let input = message
+            .get("input")
+            .filter(|input| input.is_object())
+            .ok_or("A challenge needs an `input` object, the request to answer")?;
+        if input.get("command").is_some() {
+            return Err("The input of a challenge can't be a command".to_string());
+        }
+
+        let max_deadline_ms = config::optional_env("CHALLENGE_MAX_DEADLINE_MS", 300_000u64);
+        let deadline_ms = message
+            .get("deadline_ms")
+            .and_then(Value::as_u64)
+            .unwrap_or_else(|| config::optional_env("CHALLENGE_DEFAULT_DEADLINE_MS", 30_000u64))
+            .min(max_deadline_ms);
+
+        Ok(Self {
+            request_id: message.get("request_id").cloned(),
+            nonce: nonce.to_string(),
+            input: input.clone(),
+            deadline: Duration::from_millis(deadline_ms),
+            anchor: message
+                .get("anchor")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            received_at: Instant::now(),
+        })
+    }
+
+    /// The request handed to the engine, it carries the `request_id` of the challenge so that its response is paired
+    /// with it
+    pub fn engine_request(&self) -> String {
+        let mut request = self.input.clone();
+        if let (Some(request), Some(request_id)) = (request.as_object_mut(), &self.request_id) {
+            request.insert("request_id".to_string(), request_id.clone());
+        }
+        request.to_string()
+    }
+
+    /// The hex encoded `sha256(nonce || sha256(input) || sha256(output))`, the request ids are left out of both
+    pub fn commitment(&self, output: &Value) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.nonce.as_bytes());
+        hasher.update(Sha256::digest(without_request_id(&self.input).to_string()));
+        hasher.update(Sha256::digest(without_request_id(output).to_string()));
+        hex::encode(hasher.finalize())
+    }
+
+    /// Answers the challenge with the response of the engine. The commitment is anchored in the background if asked
+    /// to, waiting for its inclusion would hold back every later response of the connection.
+    ///
+    /// # Arguments
+    /// * `keypair` - The keypair of the miner, the anchor is submitted with it
+    /// * `task_id` - The task the challenge was sent to
+    /// * `response` - The response of the engine to the input of the challenge
+    ///
+    /// # Returns
+    /// The `challenge` message sent to the task owner, `anchored` is `"pending"` while the anchor is submitted,
+    /// `"rate_limited"` if more than `CHALLENGE_ANCHORS_PER_HOUR` (default 12) challenges asked to be anchored
+    pub fn answer(&self, keypair: &Keypair, task_id: u64, response: &str) -> String {
+        let elapsed = self.received_at.elapsed();
+        let output = serde_json::from_str::<Value>(response)
+            .unwrap_or_else(|_| Value::String(response.to_string()));
+        let failed = output.get("error").is_some();
+        let commitment = self.commitment(&output);
+
+        // Late or failed answers are reported as they are, they aren't worth a transaction
+        let within_deadline = elapsed <= self.deadline;
+        let anchored = if self.anchor && within_deadline && !failed {
+            let admitted = admit_anchor(
+                &mut ANCHORS.lock().unwrap(),
+                Instant::now(),
+                config::optional_env("CHALLENGE_ANCHORS_PER_HOUR", 12usize),
+            );
+            if admitted {
+                let keypair = keypair.clone();
+                let nonce = self.nonce.clone();
+                let commitment = commitment.clone();
+                tokio::spawn(async move {
+                    match anchor(&keypair, task_id, &nonce, &commitment).await {
+                        Ok(()) => println!("Anchored the challenge {} of task {}", nonce, task_id),
+                        Err(e) => println!(
+                            "Failed to anchor the challenge commitment of task {}: {}",
+                            task_id, e
+                        ),
+                    }
+                });
+                Some("pending")
+            } else {
+                println!(
+                    "Not anchoring the challenge {} of task {}, CHALLENGE_ANCHORS_PER_HOUR is reached",
+                    self.nonce, task_id
+                );
+                Some("rate_limited")
+            }
+        } else {
+            None
+        };
+
+        json!({
+            "command": "challenge",
+            "request_id": self.request_id,
+            "nonce": self.nonce,
+            "commitment": commitment,
+            "output": output,
+            "elapsed_ms": elapsed.as_millis() as u64,
+            "within_deadline": within_deadline,
+            "anchored": anchored,
+        })
+        .to_string()
+    }
+}
+
+/// Admits an anchor unless `limit` anchors were admitted within the window before `now`
+fn admit_anchor(anchors: &mut VecDeque<Instant>, now: Instant, limit: usize) -> bool {
+    while anchors
+        .front()
+        .is_some_and(|anchored| now.duration_since(*anchored) >= ANCHOR_WINDOW)
+    {
+        anchors.pop_front();
+    }
+    if anchors.len() >= limit {
+        return false;
+    }
+    anchors.push_back(now);
+    true
+}
+
+fn without_request_id(value: &Value) -> Value {
+    let mut value = value.clone();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("request_id");
+    }
+    value
+}
+
+async fn anchor(keypair: &Keypair, task_id: u64, nonce: &str, commitment: &str) -> Result<()> {
+    let keypair = keypair.clone();
+    let nonce = nonce.to_string();
+    let commitment = commitment.to_string();
+
+    let rx = config::get_tx_queue()?
+        .enqueue(move || {
+            let keypair = keypair.clone();
+            let nonce = nonce.clone();
+            let commitment = commitment.clone();
+            async move {
+                anchor_challenge_commitment(keypair, task_id, &nonce, &commitment).await?;
+                Ok(TxOutput::Success)
+            }
+        })
+        .await?;
+    rx.await
+        .map_err(|_| Error::Custom("Response channel dropped.".to_string()))??;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commitments_bind_the_nonce_and_ignore_request_ids() {
+        let challenge = Challenge::parse(
+            r#"{"command":"challenge","request_id":7,"nonce":"n1","input":{"inputs":[1]},"deadline_ms":999999999}"#,
+        )
+        .unwrap();
+        assert_eq!(challenge.deadline, Duration::from_millis(300_000));
+        assert!(!challenge.anchor);
+        assert_eq!(
+            challenge.engine_request(),
+            r#"{"inputs":[1],"request_id":7}"#
+        );
+
+        let output = json!({"outputs": [2]});
+        let mut with_request_id = output.clone();
+        with_request_id["request_id"] = json!(7);
+        assert_eq!(
+            challenge.commitment(&output),
+            challenge.commitment(&with_request_id)
+        );
+
+        let mut other_nonce = challenge.clone();
+        other_nonce.nonce = "n2".to_string();
+        assert_ne!(
+            challenge.commitment(&output),
+            other_nonce.commitment(&output)
+        );
+
+        assert!(Challenge::parse(r#"{"command":"challenge","input":{}}"#).is_err());
+        assert!(Challenge::parse(
+            r#"{"command":"challenge","nonce":"n","input":{"command":"bench"}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn anchors_are_rate_limited_within_the_window() {
+        let mut anchors = VecDeque::new();
+        let start = Instant::now();
+        assert!(admit_anchor(&mut anchors, start, 2));
+        assert!(admit_anchor(&mut anchors, start, 2));
+        assert!(!admit_anchor(
+            &mut anchors,
+            start + Duration::from_secs(60),
+            2
+        ));
+        assert!(admit_anchor(&mut anchors, start + ANCHOR_WINDOW, 2));
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Time a connection may wait for a free slot of the task before it is rejected
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Limits the number of concurrent websocket connections per client IP and per task. Connections exceeding the task limit
+/// wait for a free slot in arrival order (the semaphore is fair), and queued connections count towards the IP limit, so a
+/// single client opening hundreds of sockets can neither exhaust file descriptors nor starve other clients of the same task.
+pub struct ConnectionLimiter {
+    max_per_ip: usize,
+    task_slots: Arc<Semaphore>,
+    connections_per_ip: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/// Held for the lifetime of a websocket connection, frees the IP and task slots when dropped
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+    _task_slot: OwnedSemaphorePermit,
+}
+
+#[derive(Debug)]
+pub enum ConnectionRejection {
+    IpLimitReached,
+    TaskBusy,
+}
+
+impl fmt::Display for ConnectionRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionRejection::IpLimitReached => {
+                write!(f, "Too many concurrent connections from this address")
+            }
+            ConnectionRejection::TaskBusy => {
+                write!(f, "Task endpoint is at capacity, try again later")
+            }
+        }
+    }
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_per_ip: usize, max_per_task: usize) -> Self {
+        Self {
+            max_per_ip,
+            task_slots: Arc::new(Semaphore::new(max_per_task)),
+            connections_per_ip: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reserves a connection slot for the given client, waiting in line for a free task slot if necessary.
+    ///
+    /// # Arguments
+    /// * `ip` - The IP address of the connecting client
+    ///
+    /// # Returns
+    /// A `ConnectionPermit` that must be kept alive for the duration of the connection, or the reason for the rejection.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        ip: IpAddr,
+    ) -> Result<ConnectionPermit, ConnectionRejection> {
+        {
+            let mut connections = self.connections_per_ip.lock().unwrap();
+            let count = connections.entry(ip).or_insert(0);
+            if *count >= self.max_per_ip {
+                return Err(ConnectionRejection::IpLimitReached);
+            }
+            *count += 1;
+        }
+
+        match tokio::time::timeout(QUEUE_TIMEOUT, Arc::clone(&self.task_slots).acquire_owned())
+            .await
+        {
+            Ok(Ok(task_slot)) => Ok(ConnectionPermit {
+                limiter: Arc::clone(self),
+                ip,
+                _task_slot: task_slot,
+            }),
+            _ => {
+                self.release(ip);
+                Err(ConnectionRejection::TaskBusy)
+            }
+        }
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut connections = self.connections_per_ip.lock().unwrap();
+        if let Some(count) = connections.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                connections.remove(&ip);
+            }
+        }
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.limiter.release(self.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+    const OTHER_CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2));
+
+    #[tokio::test]
+    async fn connections_are_limited_per_ip() {
+        let limiter = Arc::new(ConnectionLimiter::new(2, 10));
+
+        let first = limiter.acquire(CLIENT).await.unwrap();
+        let _second = limiter.acquire(CLIENT).await.unwrap();
+        assert!(matches!(
+            limiter.acquire(CLIENT).await,
+            Err(ConnectionRejection::IpLimitReached)
+        ));
+        let _other = limiter.acquire(OTHER_CLIENT).await.unwrap();
+
+        // Closing a connection frees its slot
+        drop(first);
+        let _third = limiter.acquire(CLIENT).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn queued_connections_wait_for_a_task_slot_and_count_towards_the_ip_limit() {
+        let limiter = Arc::new(ConnectionLimiter::new(2, 1));
+
+        let serving = limiter.acquire(OTHER_CLIENT).await.unwrap();
+        let queued = tokio::spawn({
+            let limiter = Arc::clone(&limiter);
+            async move { limiter.acquire(CLIENT).await.map(|_| ()) }
+        });
+        tokio::task::yield_now().await;
+
+        assert_eq!(limiter.connections_per_ip.lock().unwrap()[&CLIENT], 1);
+        assert!(!queued.is_finished());
+
+        drop(serving);
+        queued.await.unwrap().unwrap();
+        assert!(limiter.connections_per_ip.lock().unwrap().is_empty());
+    }
+}
@@ -1,42 +1,111 @@
+use crate::alerting::{self, Alert};
 use crate::config;
-use crate::parent_runtime::server_control::SHUTDOWN_SENDER;
+use crate::parent_runtime::artifacts;
+use crate::parent_runtime::audit_sampling;
+use crate::parent_runtime::challenge::Challenge;
+use crate::parent_runtime::connection_limiter::ConnectionLimiter;
+use crate::parent_runtime::inference_history;
+use crate::parent_runtime::integrity;
+use crate::parent_runtime::keepalive::{self, Keepalive, Liveness};
+use crate::parent_runtime::message_auth::{AuthPolicy, Session};
+use crate::parent_runtime::pricing::PricingCache;
+use crate::parent_runtime::proof;
+use crate::parent_runtime::response_anchor;
+use crate::parent_runtime::route_aliases;
+use crate::parent_runtime::routes::InferenceRoutes;
+use crate::parent_runtime::server_control::{
+    self, BOUND_ADDRESSES, ENGINE_STATUS, NZK_TASKS, PROOF_PROGRESS, REQUESTS_RECEIVED,
+    SHUTDOWN_SENDERS,
+};
+use crate::parent_runtime::session_affinity::InferenceSession;
+use crate::parent_runtime::setup_progress::{self, SetupStage};
+use crate::parent_runtime::task_completion::{self, FiniteTask};
+use crate::parent_runtime::task_manifest;
+use crate::parent_runtime::transcript;
+use crate::parent_runtime::usage_policy::{self, OperatorPolicy, UsageRestrictions};
 use crate::utils::tx_builder::confirm_task_reception;
+use crate::utils::fault_injection::{self, Fault};
+use crate::utils::idle_power;
+use crate::utils::load_shedding;
 use crate::utils::tx_queue::TxOutput;
 use crate::{
     config::get_paths,
     error::{Error, Result},
+    specs,
+    traits::ChainApi,
     types::{CurrentTask, TaskType},
 };
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        ConnectInfo, State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Path, Query, State,
     },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
 use futures::{SinkExt, StreamExt};
-use neuro_zk_runtime::NeuroZKEngine;
+use neuro_zk_runtime::{ExtractionReport, ExtractionTuning, NeuroZKEngine};
+use subxt::utils::AccountId32;
 use subxt_signer::sr25519::Keypair;
-use open_inference_runtime::TritonClient;
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+// The error codes are identical across engines, the miner uses them for its own engine status messages
+use open_inference_runtime::{
+    bench::bench_request, binary_request, error_response, Delivery, ErrorCode, ExtractionOptions,
+    ExtractionProgress, RepositoryChange, RepositoryIndex, TritonClient,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{HashSet, VecDeque},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 use tokio::{
     net::TcpListener,
-    sync::{watch, Mutex},
+    sync::{broadcast::error::RecvError, watch, Mutex},
 };
 
 #[derive(Clone)]
 pub enum InferenceEngine {
     OpenInference(Arc<Mutex<TritonClient>>),
-    NeuroZk(Arc<Mutex<NeuroZKEngine>>),
+    // All NeuroZK operations only need shared access, so connections (and the prover) don't serialize on a lock
+    NeuroZk(Arc<NeuroZKEngine>),
 }
 
 #[derive(Clone)]
 #[allow(dead_code)]
 struct AppState {
     task: CurrentTask,
+    // The miner serving the task, a fleet runs several miners in one process
+    miner: AccountId32,
     engine: InferenceEngine,
     status: Arc<watch::Receiver<EngineStatus>>,
+    connection_limiter: Arc<ConnectionLimiter>,
+    // Set once the engine is ready, fetching it per request would contend with the inference sessions for the engine
+    model_metadata: Arc<OnceLock<serde_json::Value>>,
+    // Set while an OpenInference task is served on the CPU fallback because Triton is unreachable
+    degraded: Option<Arc<AtomicBool>>,
+    routes: InferenceRoutes,
+    // Binary outputs of OpenInference tasks kept in the task directory
+    artifact_dir: PathBuf,
+    pricing: Arc<PricingCache>,
+    // Set if requests must be signed by the task owner
+    auth_policy: Option<AuthPolicy>,
+    // Announced to every client, `None` for models without restrictions
+    usage_restrictions: Option<UsageRestrictions>,
+    // The model the inference history is kept for, and the output its generated tokens are counted in
+    model_name: String,
+    token_output: Option<String>,
+    // Set for batch jobs, which are completed with the keypair of the miner once their input is processed
+    finite: Option<FiniteTask>,
+    keypair: Keypair,
+    task_dir: PathBuf,
 }
 
 #[derive(Debug, Clone)]
@@ -44,10 +113,26 @@ enum EngineStatus {
     Idle,
     Initializing,
     Ready,
+    // Serving at reduced speed, eg. on the CPU fallback while Triton is unreachable
+    Degraded(String),
     Failed(String),
 }
 
+impl EngineStatus {
+    /// The status as shown to operators
+    fn describe(&self) -> String {
+        match self {
+            EngineStatus::Idle => "idle".to_string(),
+            EngineStatus::Initializing => "initializing".to_string(),
+            EngineStatus::Ready => "ready".to_string(),
+            EngineStatus::Degraded(reason) => format!("degraded: {}", reason),
+            EngineStatus::Failed(e) => format!("failed: {}", e),
+        }
+    }
+}
+
 pub async fn spawn_inference_server(
+    chain: Arc<dyn ChainApi>,
     task: &CurrentTask,
     port: Option<u16>,
     keypair: &Keypair
@@ -61,17 +146,86 @@ pub async fn spawn_inference_server(
     //     )))
     //     .map_err(|e| Error::Custom(format!("Failed to create engine: {}", e.to_string())))?,
     // ));
+    // Default deadline for a single inference request, 0 disables it. Clients can override it per request with `timeout_ms`.
+    let request_timeout = match config::optional_env("INFERENCE_TIMEOUT_SECS", 120u64) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    idle_power::task_started(task.id).await;
+    // Hashed before the engines are created, the OpenInference engine removes the archive once it is extracted
+    let model_hash = route_aliases::model_hash(paths).await?;
+    // Creating the engines extracts the model archive
+    setup_progress::report(keypair, task.id, SetupStage::Extracting, None);
+    let mut degraded = None;
+    let manifest = task_manifest::read_manifest(&paths.task_dir_path)?;
+    usage_policy::enforce(
+        task.id,
+        &manifest.usage_restrictions,
+        &OperatorPolicy::from_env(),
+        &paths.identity_path,
+    )?;
+    let usage_restrictions =
+        (!manifest.usage_restrictions.is_empty()).then(|| manifest.usage_restrictions.clone());
+    let model_name = manifest
+        .model_name
+        .clone()
+        .unwrap_or_else(|| format!("task-{}", task.id));
+    let token_output = manifest.token_output.clone();
+    let finite = manifest.finite.clone();
+    inference_history::load(&paths.identity_path);
+    let routes = InferenceRoutes::from_env()?;
+    let artifact_dir = artifacts::artifact_dir(&paths.task_dir_path);
+    let max_extracted_bytes =
+        specs::extraction_quota(&paths.task_dir_path, manifest.max_extracted_bytes).await;
     let engine = match task.task_type {
         TaskType::OpenInference => {
-            let triton_client = TritonClient::new(
-                "http://localhost:8000/v2",
+            let progress = Arc::new(ExtractionProgress::default());
+            let extraction = ExtractionOptions {
+                component_cache: config::component_cache()?,
+                config_generation: specs::triton_config_generation().await,
+                max_extracted_bytes,
+                expected_hashes: manifest.file_hashes.clone(),
+                workers: config::optional_env("EXTRACTION_WORKERS", 0),
+                progress: Arc::clone(&progress),
+            };
+            let progress_reporter = spawn_extraction_reporter(keypair, task.id, progress);
+            let triton_client = TritonClient::new_with_extraction(
+                &config::triton_url(),
                 &paths.task_file_name,
                 PathBuf::from(&paths.task_dir_path),
+                extraction,
             )
-            .await
-            .map_err(|e| {
-                Error::Custom(format!("Failed to create Triton client: {}", e.to_string()))
-            })?;
+            .await;
+            progress_reporter.abort();
+            let triton_client = triton_client
+                .map_err(|e| {
+                    Error::Custom(format!("Failed to create Triton client: {}", e.to_string()))
+                })?
+                .with_http_options(config::triton_http_options())
+                .map_err(|e| Error::Custom(format!("Failed to create the Triton HTTP client: {}", e)))?
+                .with_request_timeout(request_timeout)
+                .with_max_in_flight(config::optional_env("MAX_IN_FLIGHT_REQUESTS", 1))
+                .with_preprocessing(manifest.preprocessing)
+                .with_postprocessing(manifest.postprocessing)
+                .map_err(|e| {
+                    Error::Custom(format!(
+                        "Failed to load post-processing of the model: {}",
+                        e
+                    ))
+                })?
+                .with_pipeline(manifest.pipeline)
+                .map_err(|e| Error::Custom(format!("Invalid pipeline in task manifest: {}", e)))?
+                .with_plugins(manifest.plugins, config::plugin_limits())
+                .map_err(|e| Error::Custom(format!("Failed to load the plugins of the model: {}", e)))?
+                .with_artifact_store(artifacts::store_from_env(
+                    artifact_dir.clone(),
+                    routes.artifacts_path(task.id),
+                )?)
+                .with_onnx_fallback(config::optional_env("ONNX_FALLBACK", true))
+                .with_fault_injection(fault_injection::hook(Fault::InferenceEngine));
+            degraded = Some(triton_client.degraded_flag());
+            idle_power::models_loaded(task.id, triton_client.served_models());
             InferenceEngine::OpenInference(Arc::new(Mutex::new(triton_client)))
         }
 
@@ -80,16 +234,30 @@ pub async fn spawn_inference_server(
                 "{}/{}",
                 paths.task_dir_path, paths.task_file_name
             )))
-            .map_err(|e| Error::Custom(format!("Failed to create engine: {}", e.to_string())))?;
-            InferenceEngine::NeuroZk(Arc::new(Mutex::new(neurozk_engine)))
+            .map_err(|e| Error::Custom(format!("Failed to create engine: {}", e.to_string())))?
+            .with_request_timeout(request_timeout)
+            .with_max_request_bytes(config::optional_env(
+                "MAX_INFERENCE_REQUEST_BYTES",
+                neuro_zk_runtime::DEFAULT_MAX_REQUEST_BYTES,
+            ))
+            .with_max_extracted_bytes(max_extracted_bytes)
+            // Tuned to the disk and CPU of the host unless disabled
+            .with_extraction_tuning(
+                (!config::optional_env("NZK_ADAPTIVE_EXTRACTION", true))
+                    .then(ExtractionTuning::default),
+            )
+            .with_prover_process(proof::prover_process());
+            NZK_TASKS.lock().unwrap().insert(task.id);
+            InferenceEngine::NeuroZk(Arc::new(neurozk_engine))
         }
     };
 
-    let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
-    {
-        let mut global_sender = SHUTDOWN_SENDER.lock().unwrap();
-        *global_sender = Some(shutdown_tx.clone());
-    }
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let model_metadata = Arc::new(OnceLock::new());
+    SHUTDOWN_SENDERS
+        .lock()
+        .unwrap()
+        .insert(task.id, shutdown_tx.clone());
 
     {
         let engine = engine.clone();
@@ -97,6 +265,12 @@ pub async fn spawn_inference_server(
         let tx_queue = config::get_tx_queue()?;
         let task_id = task.id.clone();
         let keypair = keypair.clone();
+        let reporting_keypair = keypair.clone();
+        let task = task.clone();
+        let task_dir = paths.task_dir_path.clone();
+        let chain = Arc::clone(&chain);
+        let model_metadata = Arc::clone(&model_metadata);
+        let repository_shutdown = shutdown_tx.subscribe();
 
         let rx = tx_queue.enqueue( move || {
             let keypair = keypair.clone();
@@ -115,29 +289,151 @@ pub async fn spawn_inference_server(
         };
 
         tokio::spawn(async move {
-            let _ = status_tx.send(EngineStatus::Initializing);
+            // Every status change past initialization is also reported on chain
+            let set_status = |status: EngineStatus| {
+                match &status {
+                    EngineStatus::Ready => setup_progress::report(
+                        &reporting_keypair,
+                        task_id,
+                        SetupStage::Ready,
+                        None,
+                    ),
+                    EngineStatus::Degraded(reason) => setup_progress::report(
+                        &reporting_keypair,
+                        task_id,
+                        SetupStage::Ready,
+                        Some(format!("degraded: {}", reason)),
+                    ),
+                    EngineStatus::Failed(e) => {
+                        setup_progress::report(
+                            &reporting_keypair,
+                            task_id,
+                            SetupStage::Failed,
+                            Some(e.clone()),
+                        );
+                        alerting::raise(
+                            Alert::EngineFailed,
+                            format!("The engine of task {} failed: {}", task_id, e),
+                        );
+                    }
+                    EngineStatus::Idle | EngineStatus::Initializing => {}
+                }
+                ENGINE_STATUS
+                    .lock()
+                    .unwrap()
+                    .insert(task_id, status.describe());
+                let _ = status_tx.send(status);
+            };
+
+            set_status(EngineStatus::Initializing);
+            setup_progress::report(&reporting_keypair, task_id, SetupStage::Compiling, None);
+
+            if let Err(e) = fault_injection::inject(Fault::EngineCrash) {
+                set_status(EngineStatus::Failed(e.to_string()));
+                return;
+            }
 
             match &engine {
-                InferenceEngine::OpenInference(_client) => {
-                    let _ = status_tx.send(EngineStatus::Ready);
+                InferenceEngine::OpenInference(client) => {
+                    if let Err(e) = integrity::verify_task_commitment(chain.as_ref(), &reporting_keypair, &task, &task_dir).await {
+                        tracing::error!("Refusing to serve task {}: {}", task.id, e);
+                        set_status(EngineStatus::Failed(format!("Model integrity check failed: {}", e)));
+                        return;
+                    }
+
+                    let client = client.lock().await;
+                    if client.has_fallback() && !client.is_reachable().await {
+                        client.degraded_flag().store(true, Ordering::Relaxed);
+                        // Triton would describe the model, clients still learn from the metadata that serving is degraded
+                        let _ = model_metadata.set(Value::Null);
+                        set_status(EngineStatus::Degraded(
+                            "Triton is unreachable, serving on the CPU fallback".to_string(),
+                        ));
+                        return;
+                    }
+
+                    match client.describe_model().await {
+                        Ok(metadata) => {
+                            let _ = model_metadata.set(metadata);
+                        }
+                        Err(e) => println!("Failed to fetch the metadata of task {}: {}", task.id, e),
+                    }
+                    set_status(EngineStatus::Ready);
 
+                    let index = client.repository_index();
+                    let models = client
+                        .served_models()
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect();
+                    // Connections lock the client while they are served
+                    drop(client);
+                    watch_repository(task_id, index, models, repository_shutdown, set_status).await;
                 }
-                InferenceEngine::NeuroZk(engine) => match engine.lock().await.setup().await {
-                    Ok(()) => {
-                        let _ = status_tx.send(EngineStatus::Ready);
+                InferenceEngine::NeuroZk(engine) => {
+                    let setup_result = engine.setup().await.map_err(|e| e.to_string());
+                    if let Some(report) = ExtractionReport::load(std::path::Path::new(&task_dir)) {
+                        setup_progress::record_extraction(task_id, &report);
                     }
-                    Err(e) => {
-                        let _ = status_tx.send(EngineStatus::Failed(e.to_string()));
+                    // A broken archive fails here once, instead of failing every request
+                    let setup_result = match setup_result {
+                        Ok(()) if config::optional_env("NZK_VERIFY_SETUP", true) => {
+                            engine.verify_setup().await.map_err(|e| e.to_string())
+                        }
+                        result => result,
+                    };
+
+                    match setup_result {
+                        Ok(()) => match integrity::verify_task_commitment(chain.as_ref(), &reporting_keypair, &task, &task_dir).await {
+                            Ok(()) => {
+                                match engine.describe_model().map_err(|e| e.to_string()) {
+                                    Ok(metadata) => {
+                                        let _ = model_metadata.set(metadata);
+                                    }
+                                    Err(e) => println!("Failed to describe the model of task {}: {}", task.id, e),
+                                }
+                                set_status(EngineStatus::Ready);
+                            }
+                            Err(e) => {
+                                tracing::error!("Refusing to serve task {}: {}", task.id, e);
+                                set_status(EngineStatus::Failed(format!(
+                                    "Model integrity check failed: {}",
+                                    e
+                                )));
+                            }
+                        },
+                        Err(e) => {
+                            set_status(EngineStatus::Failed(e));
+                        }
                     }
-                },
+                }
             }
         });
     }
 
+    let connection_limiter = ConnectionLimiter::new(
+        config::optional_env("MAX_CONNECTIONS_PER_IP", 8),
+        config::optional_env("MAX_CONNECTIONS_PER_TASK", 64),
+    );
+
     let state = AppState {
         task: task.clone(),
+        miner: keypair.public_key().to_account_id(),
         engine: engine,
         status: Arc::new(status_rx),
+        connection_limiter: Arc::new(connection_limiter),
+        model_metadata,
+        degraded,
+        routes,
+        artifact_dir,
+        pricing: Arc::new(PricingCache::from_env()),
+        auth_policy: AuthPolicy::from_env(&paths.task_owner_path)?,
+        usage_restrictions,
+        model_name,
+        token_output,
+        finite,
+        keypair: keypair.clone(),
+        task_dir: PathBuf::from(&paths.task_dir_path),
     };
 
     let mut default_port: u16 = 3000;
@@ -146,85 +442,834 @@ pub async fn spawn_inference_server(
         default_port = port
     }
 
-    let app = Router::new()
-        .route(&format!("/inference/{}", &task.id), get(ws_handler))
-        .with_state(state);
+    let task_path = state.routes.task_path(task.id);
+    let mut app = Router::new()
+        .route(&task_path, get(ws_handler))
+        .route(&state.routes.metadata_path(task.id), get(metadata_handler))
+        .route(&state.routes.audit_path(task.id), get(audit_handler))
+        .route(&state.routes.pricing_path(task.id), get(pricing_handler))
+        .route(
+            &format!("{}/{{file}}", state.routes.artifacts_path(task.id)),
+            get(artifact_handler),
+        );
+    // The aliases serve the same handlers, so clients keep their URL when the model is scheduled under a new task id
+    let alias_paths: Vec<String> = route_aliases::bind(paths, model_hash.as_deref())?
+        .iter()
+        .map(|alias| state.routes.alias_path(alias))
+        .collect();
+    for alias_path in &alias_paths {
+        app = app
+            .route(alias_path, get(ws_handler))
+            .route(&format!("{}/metadata", alias_path), get(metadata_handler))
+            .route(&format!("{}/audit", alias_path), get(audit_handler))
+            .route(&format!("{}/pricing", alias_path), get(pricing_handler))
+            .route(
+                &format!("{}/artifacts/{{file}}", alias_path),
+                get(artifact_handler),
+            );
+    }
+    let app = app.with_state(state);
+
+    // One listener per configured address, "::" alone binds dual-stack on hosts without `bindv6only`
+    let bind_addresses = parse_bind_addresses(&config::optional_env(
+        "INFERENCE_BIND_ADDRESSES",
+        "127.0.0.1".to_string(),
+    ))?;
 
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", default_port)).await?;
+    let mut listeners = Vec::new();
+    for ip in bind_addresses {
+        let listener = TcpListener::bind(SocketAddr::new(ip, default_port)).await?;
+        println!("listening on ws://{}{}", listener.local_addr()?, task_path);
+        for alias_path in &alias_paths {
+            println!("listening on ws://{}{}", listener.local_addr()?, alias_path);
+        }
+        listeners.push(listener);
+    }
 
-    println!("listening on {}", listener.local_addr().unwrap());
+    let task_id = task.id;
+    BOUND_ADDRESSES.lock().unwrap().insert(
+        task_id,
+        listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect(),
+    );
 
     let handle = tokio::spawn(async move {
         println!("Starting inference server...");
-        axum::serve(
-            listener,
-            app.into_make_service_with_connect_info::<SocketAddr>(),
-        )
-        .with_graceful_shutdown(async move {
-            shutdown_rx.changed().await.ok();
-            println!("Shutdown signal received, stopping inference server!");
-        })
-        .await
-        .expect("Server failed to start...");
+        let servers = listeners.into_iter().map(|listener| {
+            let app = app.clone();
+            let mut shutdown_rx = shutdown_rx.clone();
+
+            async move {
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(async move {
+                    shutdown_rx.changed().await.ok();
+                    println!("Shutdown signal received, stopping inference server!");
+                })
+                .await
+            }
+        });
+
+        for result in futures::future::join_all(servers).await {
+            if let Err(e) = result {
+                eprintln!("Inference server failed: {}", e);
+            }
+        }
+
+        BOUND_ADDRESSES.lock().unwrap().remove(&task_id);
+        SHUTDOWN_SENDERS.lock().unwrap().remove(&task_id);
+        NZK_TASKS.lock().unwrap().remove(&task_id);
+        ENGINE_STATUS.lock().unwrap().remove(&task_id);
+        REQUESTS_RECEIVED.lock().unwrap().remove(&task_id);
     });
 
     Ok(handle)
 }
 
+/// Proof progress is broadcast for every task of the process, each server only forwards the events of its own task
+fn is_progress_of_task(event: &str, task_id: u64) -> bool {
+    serde_json::from_str::<serde_json::Value>(event)
+        .ok()
+        .and_then(|event| event.get("task_id").and_then(|id| id.as_u64()))
+        == Some(task_id)
+}
+
+/// Parses a comma separated list of IP addresses, eg. "127.0.0.1,100.64.0.7" or "::"
+fn parse_bind_addresses(addresses: &str) -> Result<Vec<IpAddr>> {
+    let addresses = addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            address
+                .parse::<IpAddr>()
+                .map_err(|e| Error::Custom(format!("Invalid bind address '{}': {}", address, e)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if addresses.is_empty() {
+        return Err(Error::Custom("No inference server bind address configured".to_string()));
+    }
+
+    Ok(addresses)
+}
+
+/// Polls the repository index of Triton every `MODEL_INDEX_POLL_SECS` (default 30, 0 disables it) until the task is
+/// shut down. A model of the task that is unloaded or removed by someone else fails the engine, instead of every
+/// request failing on its own, and the engine is ready again once all models of the task are loaded again.
+///
+/// # Arguments
+/// * `task_id` - The task the models are served for
+/// * `index` - The repository index of the Triton client of the task
+/// * `models` - The models served for the task
+/// * `shutdown_rx` - Ends the polling when the task is shut down
+/// * `set_status` - Sets the engine status of the task
+async fn watch_repository(
+    task_id: u64,
+    index: Arc<RepositoryIndex>,
+    models: Vec<String>,
+    mut shutdown_rx: watch::Receiver<bool>,
+    set_status: impl Fn(EngineStatus),
+) {
+    let interval = match config::optional_env("MODEL_INDEX_POLL_SECS", 30u64) {
+        0 => return,
+        secs => Duration::from_secs(secs),
+    };
+    let mut changes = index.subscribe();
+    let mut unavailable = HashSet::new();
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {
+                if let Err(e) = index.refresh().await {
+                    println!("Failed to poll the model repository of task {}: {}", task_id, e);
+                }
+            }
+            change = changes.recv() => {
+                let change = match change {
+                    Ok(change) => change,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                };
+                let (model, available) = match change {
+                    RepositoryChange::Removed(model) => (model, false),
+                    RepositoryChange::Added(model) | RepositoryChange::StateChanged(model) => {
+                        let ready = model.is_ready();
+                        (model, ready)
+                    }
+                };
+                if !models.contains(&model.name) {
+                    continue;
+                }
+
+                let was_available = unavailable.is_empty();
+                if available {
+                    unavailable.remove(&model.name);
+                } else {
+                    unavailable.insert(model.name.clone());
+                }
+                match (was_available, unavailable.is_empty()) {
+                    (true, false) => set_status(EngineStatus::Failed(format!(
+                        "Model '{}' is no longer available in Triton: {}",
+                        model.name,
+                        model
+                            .reason
+                            .filter(|reason| !reason.is_empty())
+                            .or(model.state)
+                            .unwrap_or_else(|| "removed from the repository".to_string())
+                    ))),
+                    (false, true) => {
+                        println!("The models of task {} are available in Triton again", task_id);
+                        set_status(EngineStatus::Ready);
+                    }
+                    _ => {}
+                }
+            }
+            _ = shutdown_rx.changed() => return,
+        }
+    }
+}
+
+/// Options a client selects when it connects, e.g. `/inference/{id}?delivery=unordered` with the default base path
+#[derive(Deserialize)]
+struct ConnectionOptions {
+    /// Only honored by OpenInference tasks, NeuroZK requests are always answered in order
+    #[serde(default)]
+    delivery: Delivery,
+    /// `new` to start an inference session, or the id of the session to resume after reconnecting
+    session: Option<String>,
+}
+
+/// Reports how far the extraction of the model archive got every `EXTRACTION_PROGRESS_INTERVAL_SECS` (default 10)
+/// until the returned task is aborted, extracting multi-GB archives takes long enough for operators to wonder
+fn spawn_extraction_reporter(
+    keypair: &Keypair,
+    task_id: u64,
+    progress: Arc<ExtractionProgress>,
+) -> tokio::task::JoinHandle<()> {
+    let keypair = keypair.clone();
+    let interval = Duration::from_secs(
+        config::optional_env("EXTRACTION_PROGRESS_INTERVAL_SECS", 10u64).max(1),
+    );
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            setup_progress::report_progress(
+                &keypair,
+                task_id,
+                SetupStage::Extracting,
+                format!(
+                    "{:.0}% of the archive read, {} files extracted",
+                    progress.fraction() * 100.0,
+                    progress.files()
+                ),
+            );
+        }
+    })
+}
+
 #[axum_macros::debug_handler]
 async fn ws_handler(
     State(state): State<AppState>,
+    Query(options): Query<ConnectionOptions>,
     ws: WebSocketUpgrade,
-    ConnectInfo(_addr): ConnectInfo<SocketAddr>,
-) -> impl axum::response::IntoResponse {
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Response {
+    let client_ip = state.routes.client_ip(&headers, addr);
+    let permit = match state.connection_limiter.acquire(client_ip).await {
+        Ok(permit) => permit,
+        Err(rejection) => {
+            println!("Rejected connection from {}: {}", client_ip, rejection);
+            return (StatusCode::TOO_MANY_REQUESTS, rejection.to_string()).into_response();
+        }
+    };
+
     ws.on_upgrade(move |socket| {
         let state = state.clone();
 
         async move {
-            if let Err(e) = handle_socket(socket, state).await {
+            let _permit = permit;
+            if let Err(e) = handle_socket(socket, state, options.delivery, options.session).await {
                 eprintln!("WebSocket handling error: {:?}", e);
             }
         }
     })
 }
 
-async fn handle_socket(socket: WebSocket, state: AppState) -> Result<()> {
+/// The `request_id` a client attached to a request, if any
+fn request_id(text: &str) -> Option<Value> {
+    serde_json::from_str::<Value>(text)
+        .ok()?
+        .get_mut("request_id")
+        .map(Value::take)
+}
+
+/// The command of a control message, eg. `{"command":"pricing"}`, control messages are answered by the miner itself
+/// instead of the engine
+fn command(text: &str) -> Option<String> {
+    serde_json::from_str::<Value>(text)
+        .ok()?
+        .get("command")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// What a connection needs to answer the commands of its client itself
+struct Commands {
+    task_id: u64,
+    miner: AccountId32,
+    pricing: Arc<PricingCache>,
+    // Batch jobs can be finished by the task owner
+    is_finite: bool,
+    // Only OpenInference tasks can be benchmarked
+    benchmarkable: bool,
+    // Benchmarks, challenges and finishing a task are reserved to the task owner
+    owner_authenticated: bool,
+    // Benchmarks run many inferences per message
+    max_bench_requests: usize,
+    // Set once the task owner finished the task, the connection then takes no more requests
+    finish_requested: Arc<AtomicBool>,
+}
+
+/// Handles the commands the miner answers itself instead of the engine. An accepted `finish` sets
+/// `finish_requested`, accepted benchmarks and challenges go on to the engine like requests.
+///
+/// # Arguments
+/// * `text` - A message of the client
+/// * `commands` - What the connection needs to answer commands
+///
+/// # Returns
+/// The reply to the client if the message was handled here, or `None` if it goes on to the engine
+async fn handle_command(text: &str, commands: &Commands) -> Option<String> {
+    let command = command(text);
+    if command.as_deref() == Some("finish") {
+        return if !commands.is_finite {
+            Some(error_response(
+                ErrorCode::BadInput,
+                "Only finite tasks can be finished",
+            ))
+        } else if !commands.owner_authenticated {
+            Some(error_response(
+                ErrorCode::Unauthorized,
+                "Finishing a task must be signed by the task owner, the miner doesn't authenticate requests",
+            ))
+        } else {
+            commands.finish_requested.store(true, Ordering::Relaxed);
+            None
+        };
+    }
+    if commands.is_finite && task_completion::is_completed(&commands.miner, commands.task_id) {
+        return Some(error_response(
+            ErrorCode::BadInput,
+            "The task is completed and accepts no more requests",
+        ));
+    }
+
+    match command.as_deref() {
+        Some("pricing") => {
+            let pricing = commands
+                .pricing
+                .get(commands.task_id, &commands.miner)
+                .await;
+            Some(match pricing {
+                Ok(pricing) => serde_json::json!({
+                    "command": "pricing",
+                    "request_id": request_id(text),
+                    "pricing": pricing,
+                })
+                .to_string(),
+                Err(e) => error_response(
+                    ErrorCode::EngineUnavailable,
+                    format!("Pricing is not available: {}", e),
+                ),
+            })
+        }
+        Some("bench") if !commands.benchmarkable => Some(error_response(
+            ErrorCode::BadInput,
+            "Only OpenInference tasks can be benchmarked",
+        )),
+        Some("bench") if !commands.owner_authenticated => Some(error_response(
+            ErrorCode::Unauthorized,
+            "Benchmarks must be signed by the task owner, the miner doesn't authenticate requests",
+        )),
+        Some("bench") => bench_request(text)
+            .filter(|n| *n > commands.max_bench_requests)
+            .map(|n| {
+                error_response(
+                    ErrorCode::BadInput,
+                    format!(
+                        "Benchmarks run at most {} inferences, {} were requested",
+                        commands.max_bench_requests, n
+                    ),
+                )
+            }),
+        // Challenges spot-check what the miner serves, a client answering them could vouch for any miner
+        Some("challenge") if !commands.owner_authenticated => Some(error_response(
+            ErrorCode::Unauthorized,
+            "Challenges must be signed by the task owner, the miner doesn't authenticate requests",
+        )),
+        Some("challenge") => Challenge::parse(text)
+            .err()
+            .map(|e| error_response(ErrorCode::BadInput, format!("Invalid challenge: {}", e))),
+        _ => None,
+    }
+}
+
+/// Completes a finite task and describes the completion to the client, eg.
+/// `{"command":"finish","completed":{"task_id":1,"result_hash":"...","result":"...","result_count":100}}`
+async fn completion_message(keypair: &Keypair, task_id: u64, task_dir: &std::path::Path) -> String {
+    match task_completion::complete_task(keypair, task_id, task_dir).await {
+        Ok(completed) => serde_json::json!({
+            "command": "finish",
+            "completed": completed,
+        })
+        .to_string(),
+        Err(e) => error_response(
+            ErrorCode::InferenceFailed,
+            format!("Failed to complete the task: {}", e),
+        ),
+    }
+}
+
+/// A request waiting for its response, kept to anchor and sample the pair once it is answered
+struct PendingRequest {
+    id: Option<Value>,
+    text: String,
+    received_at: SystemTime,
+    started: Instant,
+    /// Set when the request is the input of a challenge of the task owner, its response is answered with a commitment
+    challenge: Option<Challenge>,
+}
+
+/// Removes the request a response answers from the pending requests. With unordered delivery the responses can't be
+/// paired by their order, a response is then only paired with the request carrying its `request_id`.
+fn take_pending_request(
+    pending: &mut VecDeque<PendingRequest>,
+    response: &str,
+    delivery: Delivery,
+) -> Option<PendingRequest> {
+    let position = request_id(response).and_then(|id| {
+        pending
+            .iter()
+            .position(|request| request.id.as_ref() == Some(&id))
+    });
+    match (position, delivery) {
+        (Some(position), _) => pending.remove(position),
+        (None, Delivery::Ordered) => pending.pop_front(),
+        (None, Delivery::Unordered) => None,
+    }
+}
+
+/// Checks that a request of an unordered connection can be paired with its response
+///
+/// # Returns
+/// A rejection if the request has no `request_id` or shares it with a pending request
+fn unordered_pairing_rejection(
+    pending: &VecDeque<PendingRequest>,
+    id: Option<&Value>,
+) -> Option<String> {
+    match id {
+        None => Some("Requests of unordered connections need a request_id".to_string()),
+        Some(id) if pending.iter().any(|request| request.id.as_ref() == Some(id)) => {
+            Some(format!(
+                "The request_id {} is already pending, requests of unordered connections need unique ids",
+                id
+            ))
+        }
+        Some(_) => None,
+    }
+}
+
+/// Describes the model of the task, so clients can build requests without knowing the model beforehand
+async fn metadata_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(model) = state.model_metadata.get() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            error_response(
+                ErrorCode::EngineUnavailable,
+                "Model metadata is not available, the engine is not ready",
+            ),
+        )
+            .into_response();
+    };
+
+    let engine = match state.engine {
+        InferenceEngine::OpenInference(_) => "open_inference",
+        InferenceEngine::NeuroZk(_) => "neuro_zk",
+    };
+
+    Json(serde_json::json!({
+        "task_id": state.task.id,
+        "url": state.routes.task_url(&headers, state.task.id),
+        "engine": engine,
+        "model": model,
+        "degraded": state
+            .degraded
+            .as_ref()
+            .is_some_and(|degraded| degraded.load(Ordering::Relaxed)),
+        "usage_restrictions": state.usage_restrictions,
+    }))
+    .into_response()
+}
+
+/// The signed audit digests of the task, task owners reconcile them against their own client logs
+async fn audit_handler(State(state): State<AppState>) -> Response {
+    Json(serde_json::json!({
+        "task_id": state.task.id,
+        "miner": state.miner.to_string(),
+        "digests": audit_sampling::digests(&state.miner, state.task.id),
+    }))
+    .into_response()
+}
+
+/// The on-chain pricing of the task, the same the `pricing` command answers with
+async fn pricing_handler(State(state): State<AppState>) -> Response {
+    match state.pricing.get(state.task.id, &state.miner).await {
+        Ok(pricing) => Json(pricing).into_response(),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            error_response(
+                ErrorCode::EngineUnavailable,
+                format!("Pricing is not available: {}", e),
+            ),
+        )
+            .into_response(),
+    }
+}
+
+/// A binary output of the task, clients receive its URL in place of the output
+async fn artifact_handler(State(state): State<AppState>, Path(file): Path<String>) -> Response {
+    let artifact_dir = state.artifact_dir.clone();
+    match tokio::task::spawn_blocking(move || artifacts::read_artifact(&artifact_dir, &file)).await {
+        Ok(Some((content_type, content))) => {
+            ([(header::CONTENT_TYPE, content_type)], content).into_response()
+        }
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    delivery: Delivery,
+    session: Option<String>,
+) -> Result<()> {
     let (sender, mut receiver) = socket.split();
     let current_status = state.status.borrow().clone();
+    let sender = Arc::new(Mutex::new(sender));
+
+    // Tasks without zk proofs are held accountable by anchoring hashes of what they served, and every inference is
+    // timed for the inference history. Every request is answered exactly once, in order unless the client asked for
+    // unordered delivery, so responses are paired with the pending request carrying the same `request_id`, or else
+    // with the oldest pending request. Unordered connections only accept requests with a unique `request_id`, as the
+    // order says nothing about which request a response answers.
+    let task_id = state.task.id;
+    let miner = state.miner.clone();
+    let anchor_responses = matches!(state.engine, InferenceEngine::OpenInference(_));
+    // NeuroZK tasks can instead be asked to prove the last request they served, the transcript of what they served
+    // is bound to every proof
+    let record_proof_input = matches!(state.engine, InferenceEngine::NeuroZk(_));
+    let audit_requests = audit_sampling::is_enabled();
+    let model_name = state.model_name.clone();
+    let token_output = state.token_output.clone();
+    let pending_requests = Arc::new(std::sync::Mutex::new(VecDeque::<PendingRequest>::new()));
+    let accepts_binary = matches!(state.engine, InferenceEngine::OpenInference(_));
+    // Batch jobs are completed once their expected results were served, or once the task owner finishes them
+    let finite = state.finite.clone();
+    let finish_requested = Arc::new(AtomicBool::new(false));
+    let commands = Commands {
+        task_id,
+        miner: state.miner.clone(),
+        pricing: Arc::clone(&state.pricing),
+        is_finite: finite.is_some(),
+        benchmarkable: accepts_binary,
+        owner_authenticated: state.auth_policy.is_some(),
+        max_bench_requests: config::optional_env("BENCH_MAX_REQUESTS", 1000usize),
+        finish_requested: Arc::clone(&finish_requested),
+    };
+    let completion_keypair = state.keypair.clone();
+    let task_dir = state.task_dir.clone();
+
+    let fault_sender = Arc::clone(&sender);
+    // Clients learn the terms of the model before their first request
+    if let Some(usage_restrictions) = &state.usage_restrictions {
+        sender
+            .lock()
+            .await
+            .send(Message::Text(usage_restrictions.announcement().into()))
+            .await
+            .ok();
+    }
+    let mut auth_session = state.auth_policy.clone().map(Session::new);
+    if let Some(session) = &auth_session {
+        sender
+            .lock()
+            .await
+            .send(Message::Text(session.announcement().into()))
+            .await
+            .ok();
+    }
+    // Clients resuming a session after a restart of the miner learn that its context is lost
+    let inference_session = match session {
+        Some(requested) => match InferenceSession::open(&state.task_dir, &requested).await {
+            Ok(inference_session) => {
+                sender
+                    .lock()
+                    .await
+                    .send(Message::Text(inference_session.announcement.clone().into()))
+                    .await
+                    .ok();
+                Some(inference_session)
+            }
+            Err(e) => {
+                println!("Failed to open an inference session of task {}: {}", task_id, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let served_requests = Arc::new(AtomicU64::new(0));
+    let session_served_requests = Arc::clone(&served_requests);
+    let stream_pending_requests = Arc::clone(&pending_requests);
+    let liveness = Arc::new(std::sync::Mutex::new(Liveness::default()));
+    let (reap_tx, reap_rx) = watch::channel(None);
+    let mut reaped = reap_rx.clone();
+    let busy_requests = Arc::clone(&pending_requests);
+    let keepalive = keepalive::spawn(
+        Keepalive::from_env(),
+        Arc::clone(&sender),
+        Arc::clone(&liveness),
+        move || !busy_requests.lock().unwrap().is_empty(),
+        reap_tx,
+    );
     let request_stream = Box::pin(async_stream::stream! {
-        while let Some(Ok(msg)) = receiver.next().await {
-            if let Message::Text(text) = msg {
-                yield text.to_string();
+        loop {
+            // A reaped connection ends the stream, the engine finishes the pending requests and releases it
+            let msg = tokio::select! {
+                msg = receiver.next() => msg,
+                _ = reaped.changed() => break,
+            };
+            let Some(Ok(msg)) = msg else {
+                break;
+            };
+            liveness.lock().unwrap().frame();
+            // Binary messages carry a raw image or audio payload for the pre-processed input of the model
+            let text = match msg {
+                Message::Text(text) => Some(text.to_string()),
+                Message::Binary(payload) if accepts_binary => Some(binary_request(&payload)),
+                Message::Binary(_) => {
+                    let _ = fault_sender
+                        .lock()
+                        .await
+                        .send(Message::Text(
+                            error_response(
+                                ErrorCode::BadInput,
+                                "Binary requests are only accepted by OpenInference tasks",
+                            )
+                            .into(),
+                        ))
+                        .await;
+                    continue;
+                }
+                _ => None,
+            };
+            let text = match (text, auth_session.as_mut()) {
+                (Some(text), Some(session)) => match session.open(&text) {
+                    Ok(payload) => Some(payload),
+                    Err(rejection) => {
+                        let _ = fault_sender
+                            .lock()
+                            .await
+                            .send(Message::Text(
+                                error_response(ErrorCode::Unauthorized, rejection).into(),
+                            ))
+                            .await;
+                        continue;
+                    }
+                },
+                (text, _) => text,
+            };
+            if let Some(text) = text {
+                if let Some(reply) = handle_command(&text, &commands).await {
+                    let _ = fault_sender
+                        .lock()
+                        .await
+                        .send(Message::Text(reply.into()))
+                        .await;
+                    continue;
+                }
+                if commands.finish_requested.load(Ordering::Relaxed) {
+                    // Ends the input, the engine answers the pending requests before the task is completed
+                    break;
+                }
+                // Only valid challenges of the task owner are let through by `handle_command`
+                let challenge = (command(&text).as_deref() == Some("challenge"))
+                    .then(|| Challenge::parse(&text).ok())
+                    .flatten();
+                let text = challenge
+                    .as_ref()
+                    .map(Challenge::engine_request)
+                    .unwrap_or(text);
+                if let Some(reason) = load_shedding::pressure() {
+                    let _ = fault_sender
+                        .lock()
+                        .await
+                        .send(Message::Text(
+                            error_response(
+                                ErrorCode::ResourceExhausted,
+                                format!("Miner is overloaded, try again later: {}", reason),
+                            )
+                            .into(),
+                        ))
+                        .await;
+                    continue;
+                }
+                let id = request_id(&text);
+                let rejection = (delivery == Delivery::Unordered)
+                    .then(|| {
+                        unordered_pairing_rejection(
+                            &stream_pending_requests.lock().unwrap(),
+                            id.as_ref(),
+                        )
+                    })
+                    .flatten();
+                if let Some(rejection) = rejection {
+                    let _ = fault_sender
+                        .lock()
+                        .await
+                        .send(Message::Text(
+                            error_response(ErrorCode::BadInput, rejection).into(),
+                        ))
+                        .await;
+                    continue;
+                }
+                liveness.lock().unwrap().request();
+                stream_pending_requests.lock().unwrap().push_back(PendingRequest {
+                    id,
+                    text: text.clone(),
+                    received_at: SystemTime::now(),
+                    started: Instant::now(),
+                    challenge,
+                });
+                if record_proof_input {
+                    proof::record_served_request(task_id, text.as_str());
+                }
+                server_control::record_request(task_id);
+                yield text;
+            }
+        }
+    });
+
+    let mut proof_progress = PROOF_PROGRESS.subscribe();
+    let progress_sender = Arc::clone(&sender);
+    let progress_forwarder = tokio::spawn(async move {
+        loop {
+            match proof_progress.recv().await {
+                Ok(event) => {
+                    if !is_progress_of_task(&event, task_id) {
+                        continue;
+                    }
+                    if progress_sender
+                        .lock()
+                        .await
+                        .send(Message::Text(event.into()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
             }
         }
     });
 
-    let sender = Arc::new(Mutex::new(sender));
     let response_stream = {
         let sender = Arc::clone(&sender);
         move |response: String| {
             let sender = Arc::clone(&sender);
             println!("Sending response: {}", response);
+            let request =
+                take_pending_request(&mut pending_requests.lock().unwrap(), &response, delivery);
+            // A benchmark is many synthetic inferences, it would skew the history of the model
+            let request =
+                request.filter(|request| command(&request.text).as_deref() != Some("bench"));
+            let mut completes = false;
+            let mut challenge = None;
+            if let Some(request) = request {
+                session_served_requests.fetch_add(1, Ordering::Relaxed);
+                let latency = request.started.elapsed();
+                let failed = serde_json::from_str::<Value>(&response)
+                    .is_ok_and(|response| response.get("error").is_some());
+                let tokens = token_output
+                    .as_deref()
+                    .and_then(|output| inference_history::count_tokens(&response, output));
+                inference_history::record(&model_name, latency, tokens, !failed);
+
+                if anchor_responses {
+                    response_anchor::record_response(&miner, task_id, &request.text, &response);
+                }
+                if record_proof_input {
+                    transcript::record(&miner, task_id, &request.text, &response);
+                }
+                if audit_requests {
+                    audit_sampling::record(
+                        &miner,
+                        task_id,
+                        &request.text,
+                        &response,
+                        request.received_at,
+                        latency,
+                    );
+                }
+                // The input of a challenge is not part of a batch job
+                challenge = request.challenge;
+                if let (Some(finite), None) = (&finite, &challenge) {
+                    completes = task_completion::record_result(&miner, task_id, &task_dir, finite, &response)
+                        .unwrap_or_else(|e| {
+                            println!("Failed to record a result of task {}: {}", task_id, e);
+                            false
+                        });
+                }
+            }
+            let completion = completes.then(|| (completion_keypair.clone(), task_dir.clone()));
+            let challenge_keypair = completion_keypair.clone();
             async move {
+                let response = match challenge {
+                    Some(challenge) => challenge.answer(&challenge_keypair, task_id, &response),
+                    None => response,
+                };
                 let _ = sender
                     .lock()
                     .await
                     .send(Message::Text(response.into()))
                     .await;
+                if let Some((keypair, task_dir)) = completion {
+                    let message = completion_message(&keypair, task_id, &task_dir).await;
+                    let _ = sender.lock().await.send(Message::Text(message.into())).await;
+                }
             }
         }
     };
 
     match current_status {
-        EngineStatus::Ready => match &state.engine {
+        EngineStatus::Ready | EngineStatus::Degraded(_) => match &state.engine {
             InferenceEngine::OpenInference(client) => {
                 let client = client.lock().await;
-                if let Err(e) = client.run(request_stream, response_stream).await {
+                if let Err(e) = client
+                    .run_with_delivery(request_stream, response_stream, delivery)
+                    .await
+                {
                     tracing::error!("Error running Nvidia Inference: {}", e);
                 }
             }
             InferenceEngine::NeuroZk(engine) => {
-                let engine = engine.lock().await;
                 if let Err(e) = engine.run(request_stream, response_stream).await {
                     tracing::error!("Error running NeuroZK inference: {}", e);
                 }
@@ -234,7 +1279,9 @@ async fn handle_socket(socket: WebSocket, state: AppState) -> Result<()> {
             sender
                 .lock()
                 .await
-                .send(Message::Text("Engine is initializing...".into()))
+                .send(Message::Text(
+                    error_response(ErrorCode::EngineUnavailable, "Engine is initializing...").into(),
+                ))
                 .await
                 .ok();
         }
@@ -243,7 +1290,11 @@ async fn handle_socket(socket: WebSocket, state: AppState) -> Result<()> {
                 .lock()
                 .await
                 .send(Message::Text(
-                    format!("Engine failed to initialize: {}", err).into(),
+                    error_response(
+                        ErrorCode::EngineUnavailable,
+                        format!("Engine failed to initialize: {}", err),
+                    )
+                    .into(),
                 ))
                 .await
                 .ok();
@@ -252,11 +1303,45 @@ async fn handle_socket(socket: WebSocket, state: AppState) -> Result<()> {
             sender
                 .lock()
                 .await
-                .send(Message::Text("Engine has not started.".into()))
+                .send(Message::Text(
+                    error_response(ErrorCode::EngineUnavailable, "Engine has not started.").into(),
+                ))
                 .await
                 .ok();
         }
     }
 
+    if finish_requested.load(Ordering::Relaxed) {
+        let message = completion_message(&state.keypair, task_id, &state.task_dir).await;
+        sender
+            .lock()
+            .await
+            .send(Message::Text(message.into()))
+            .await
+            .ok();
+    }
+
+    progress_forwarder.abort();
+    keepalive.abort();
+    if let Some(inference_session) = &inference_session {
+        let served = served_requests.load(Ordering::Relaxed);
+        if let Err(e) = inference_session.close(&state.task_dir, served).await {
+            println!("Failed to record the inference session of task {}: {}", task_id, e);
+        }
+    }
+    let reap_reason = *reap_rx.borrow();
+    if let Some(reason) = reap_reason {
+        println!("Reaped a connection of task {}: {}", task_id, reason);
+        sender
+            .lock()
+            .await
+            .send(Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: reason.into(),
+            })))
+            .await
+            .ok();
+    }
+
     Ok(())
 }
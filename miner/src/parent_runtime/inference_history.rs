@@ -0,0 +1,221 @@
+use crate::{config, utils::blocking::run_blocking};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// File next to the identity file the history is kept in, so it outlives restarts and tasks
+const HISTORY_FILE_NAME: &str = "inference-history.json";
+/// The history is written at most this often, a crash loses the inferences since the last write
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The latest inferences per model, bounded by `INFERENCE_HISTORY_SIZE`
+static HISTORY: Lazy<Mutex<HashMap<String, VecDeque<Sample>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static HISTORY_PATH: OnceLock<PathBuf> = OnceLock::new();
+static LAST_FLUSH: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// A served inference
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Sample {
+    latency_ms: u64,
+    /// Tokens generated, only known for models that declare a token output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tokens: Option<u64>,
+    ok: bool,
+}
+
+/// Rolling statistics of a model over its latest inferences, operators compare them across hosts to decide which
+/// hardware fits which model families
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelStats {
+    pub model: String,
+    /// Inferences the statistics are based on
+    pub samples: usize,
+    pub p50_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    /// Tokens generated per second of inference, `None` if the model declares no token output
+    pub tokens_per_sec: Option<f64>,
+    /// Share of the inferences that failed, between 0 and 1
+    pub error_rate: f64,
+}
+
+/// Loads the history kept next to the identity file once per process, the miners of a fleet share the history of
+/// the first one that serves a task
+///
+/// # Arguments
+/// * `identity_path` - The identity file of the miner
+pub fn load(identity_path: &str) {
+    let path = Path::new(identity_path).with_file_name(HISTORY_FILE_NAME);
+    if HISTORY_PATH.set(path.clone()).is_err() {
+        return;
+    }
+
+    let Ok(content) = fs::read_to_string(&path) else {
+        return;
+    };
+    match serde_json::from_str::<HashMap<String, VecDeque<Sample>>>(&content) {
+        Ok(loaded) => {
+            let mut history = HISTORY.lock().unwrap();
+            for (model, samples) in loaded {
+                history.entry(model).or_insert(samples);
+            }
+        }
+        Err(e) => println!(
+            "Ignoring the inference history in {}: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Records a served inference of a model
+///
+/// # Arguments
+/// * `model` - The model that served the inference
+/// * `latency` - From receiving the request to sending the response
+/// * `tokens` - Tokens the inference generated, if the model declares a token output
+/// * `ok` - Whether the inference succeeded
+pub fn record(model: &str, latency: Duration, tokens: Option<u64>, ok: bool) {
+    let limit = config::optional_env("INFERENCE_HISTORY_SIZE", 1000usize).max(1);
+    let sample = Sample {
+        latency_ms: latency.as_millis() as u64,
+        tokens,
+        ok,
+    };
+    {
+        let mut history = HISTORY.lock().unwrap();
+        let samples = history.entry(model.to_string()).or_default();
+        samples.push_back(sample);
+        while samples.len() > limit {
+            samples.pop_front();
+        }
+    }
+
+    flush_if_due();
+}
+
+/// The statistics of every model with a history, ordered by model
+pub fn stats() -> Vec<ModelStats> {
+    let history = HISTORY.lock().unwrap();
+    let mut stats: Vec<ModelStats> = history
+        .iter()
+        .filter_map(|(model, samples)| model_stats(model, samples))
+        .collect();
+    stats.sort_by(|a, b| a.model.cmp(&b.model));
+    stats
+}
+
+/// Tokens a response generated: the number of elements of the output the task manifest declares as token output
+///
+/// # Arguments
+/// * `response` - The response of the engine
+/// * `token_output` - The name of the output holding the generated token ids
+pub fn count_tokens(response: &str, token_output: &str) -> Option<u64> {
+    let response: serde_json::Value = serde_json::from_str(response).ok()?;
+    let output =
+        response.get("outputs")?.as_array()?.iter().find(|output| {
+            output.get("name").and_then(|name| name.as_str()) == Some(token_output)
+        })?;
+
+    output
+        .get("shape")?
+        .as_array()?
+        .iter()
+        .map(|dim| dim.as_u64())
+        .product()
+}
+
+fn model_stats(model: &str, samples: &VecDeque<Sample>) -> Option<ModelStats> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    let mut latencies: Vec<u64> = samples.iter().map(|sample| sample.latency_ms).collect();
+    latencies.sort_unstable();
+    // Nearest rank
+    let percentile = |p: f64| latencies[((p * latencies.len() as f64).ceil() as usize).max(1) - 1];
+
+    let (tokens, token_ms) = samples
+        .iter()
+        .filter(|sample| sample.ok)
+        .filter_map(|sample| sample.tokens.map(|tokens| (tokens, sample.latency_ms)))
+        .fold((0u64, 0u64), |(tokens, ms), (sample_tokens, sample_ms)| {
+            (tokens + sample_tokens, ms + sample_ms)
+        });
+    let has_tokens = samples.iter().any(|sample| sample.tokens.is_some());
+
+    Some(ModelStats {
+        model: model.to_string(),
+        samples: samples.len(),
+        p50_latency_ms: percentile(0.5),
+        p95_latency_ms: percentile(0.95),
+        tokens_per_sec: (has_tokens && token_ms > 0)
+            .then(|| tokens as f64 * 1000.0 / token_ms as f64),
+        error_rate: samples.iter().filter(|sample| !sample.ok).count() as f64
+            / samples.len() as f64,
+    })
+}
+
+/// Writes the history in the background, at most once per `FLUSH_INTERVAL`
+fn flush_if_due() {
+    let Some(path) = HISTORY_PATH.get().cloned() else {
+        return;
+    };
+    {
+        let mut last_flush = LAST_FLUSH.lock().unwrap();
+        if last_flush.is_some_and(|flushed| flushed.elapsed() < FLUSH_INTERVAL) {
+            return;
+        }
+        *last_flush = Some(Instant::now());
+    }
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+
+    let content = serde_json::to_string(&*HISTORY.lock().unwrap());
+    runtime.spawn(async move {
+        let write = run_blocking(move || Ok(fs::write(&path, content?)?));
+        if let Err(e) = write.await {
+            println!("Failed to write the inference history: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_summarize_the_samples() {
+        let samples: VecDeque<Sample> = (1..=20)
+            .map(|i| Sample {
+                latency_ms: i * 10,
+                tokens: Some(5),
+                ok: i != 20,
+            })
+            .collect();
+
+        let stats = model_stats("llama", &samples).unwrap();
+
+        assert_eq!(stats.p50_latency_ms, 100);
+        assert_eq!(stats.p95_latency_ms, 190);
+        assert_eq!(stats.error_rate, 0.05);
+        // 95 tokens in 1.9 s, the failed inference doesn't count
+        assert_eq!(stats.tokens_per_sec, Some(50.0));
+    }
+
+    #[test]
+    fn tokens_are_counted_from_the_declared_output() {
+        let response =
+            r#"{"outputs":[{"name":"logits","shape":[1,8]},{"name":"ids","shape":[2,16]}]}"#;
+
+        assert_eq!(count_tokens(response, "ids"), Some(32));
+        assert_eq!(count_tokens(response, "missing"), None);
+    }
+}
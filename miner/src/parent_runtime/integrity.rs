@@ -0,0 +1,370 @@
+use crate::{
+    alerting::{self, Alert},
+    config,
+    error::{Error, Result},
+    traits::ChainApi,
+    types::{CurrentTask, TaskType},
+    utils::blocking::run_blocking,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use subxt_signer::sr25519::{self, Keypair, PublicKey, Signature};
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+const INPUT_FILE_NAME: &str = "input.json";
+const PROVING_KEY_FILE_NAME: &str = "pk.key";
+/// File next to the archive recording the hex encoded SHA-256 of the archive whose gatekeeper signature was verified
+const SIGNED_ARCHIVE_FILE_NAME: &str = "signed-archive.sha256";
+
+/// Compares the extracted task artifacts with their commitment, so that the miner never serves a model that differs
+/// from what was published (eg. because the storage location was tampered with). NeuroZK tasks are compared with the
+/// commitment the task owner published on-chain: the settings, the input and the verifying key, which the compiled
+/// circuit is bound to through the proving key. OpenInference tasks carry none, their commitment is the gatekeeper
+/// signature over the archive, which was verified when the archive was downloaded. A mismatch raises an alert and is
+/// reported on-chain.
+///
+/// # Arguments
+/// * `chain` - The chain the commitment is published on
+/// * `keypair` - The keypair of the miner, to report a mismatch with
+/// * `task` - The task whose artifacts should be verified
+/// * `task_dir` - The directory the task archive was extracted to
+///
+/// # Returns
+/// `Ok(())` if the artifacts match the commitment, or an `Error` describing the mismatch.
+pub async fn verify_task_commitment(
+    chain: &dyn ChainApi,
+    keypair: &Keypair,
+    task: &CurrentTask,
+    task_dir: &str,
+) -> Result<()> {
+    let task_dir = PathBuf::from(task_dir);
+    let mismatches: Vec<String> = match task.task_type {
+        TaskType::NeuroZk => {
+            let commitment = chain
+                .get_nzk_commitment(task.id)
+                .await?
+                .ok_or(Error::Custom(format!("Task {} has no NeuroZK commitment on-chain", task.id)))?;
+
+            run_blocking(move || {
+                let mut mismatches = Vec::new();
+                mismatches.extend(artifact_mismatch(&task_dir.join(SETTINGS_FILE_NAME), &commitment.zk_settings)?);
+
+                if !commitment.zk_input.is_empty() {
+                    mismatches.extend(artifact_mismatch(&task_dir.join(INPUT_FILE_NAME), &commitment.zk_input)?);
+                }
+
+                if !commitment.zk_verifying_key.is_empty() {
+                    mismatches.extend(verifying_key_mismatch(
+                        &task_dir.join(PROVING_KEY_FILE_NAME),
+                        &commitment.zk_verifying_key,
+                    )?);
+                }
+
+                Ok(mismatches)
+            })
+            .await?
+        }
+        TaskType::OpenInference => {
+            if !config::security_env("REQUIRE_ARCHIVE_SIGNATURE", true)? {
+                tracing::warn!("REQUIRE_ARCHIVE_SIGNATURE=false, serving OpenInference task {} without a commitment", task.id);
+                return Ok(());
+            }
+
+            let archive = task_dir.join(&config::get_paths()?.task_file_name);
+            run_blocking(move || signed_archive_mismatch(&task_dir, &archive))
+                .await?
+                .into_iter()
+                .collect()
+        }
+    };
+
+    if !mismatches.is_empty() {
+        let mismatch = mismatches.join(", ");
+        alerting::raise(
+            Alert::IntegrityMismatch,
+            format!("Refusing to serve task {}: {}", task.id, mismatch),
+        );
+        if let Err(e) = chain.report_integrity_mismatch(keypair.clone(), task.id, &mismatch).await {
+            println!("Failed to report the integrity mismatch of task {}: {}", task.id, e);
+        }
+        return Err(Error::Custom(mismatch));
+    }
+
+    tracing::info!("✅ Task artifacts match the commitment");
+    Ok(())
+}
+
+/// Verifies that the downloaded task archive was signed by the gatekeeper registered on-chain for this task. The
+/// gatekeeper signs the task id followed by the SHA-256 digest of the archive with its sr25519 key, so a compromised
+/// storage location can neither swap in a different model nor replay the archive and signature of another task.
+///
+/// # Arguments
+/// * `chain` - The chain the gatekeeper is registered on
+/// * `task_id` - The task the archive was downloaded for
+/// * `archive_path` - The path of the downloaded archive
+/// * `signature_hex` - The hex encoded signature published next to the archive
+///
+/// # Returns
+/// `Ok(())` if the signature is valid, or an `Error` if it is malformed or doesn't match the task and archive.
+pub async fn verify_archive_signature(
+    chain: &dyn ChainApi,
+    task_id: u64,
+    archive_path: &Path,
+    signature_hex: &str,
+) -> Result<()> {
+    let gatekeeper = chain.get_gatekeeper().await?;
+
+    let signature_bytes: [u8; 64] = hex::decode(signature_hex.trim_start_matches("0x"))
+        .map_err(|e| Error::Custom(format!("Malformed archive signature: {}", e)))?
+        .try_into()
+        .map_err(|_| Error::Custom("Archive signature must be 64 bytes".to_string()))?;
+
+    let archive_file = archive_path.to_path_buf();
+    let archive_digest = run_blocking(move || {
+        let mut archive = fs::File::open(archive_file)?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut archive, &mut hasher)?;
+        Ok(hasher.finalize())
+    })
+    .await?;
+
+    let message = signed_archive_message(task_id, &archive_digest);
+    if sr25519::verify(&Signature(signature_bytes), message, &PublicKey(gatekeeper.0)) {
+        tracing::info!("✅ Archive signature verified against gatekeeper {}", gatekeeper);
+        // The archive of OpenInference tasks may be gone by the time the engine is set up, the record outlives it
+        let record = signed_archive_path(archive_path);
+        let digest = hex::encode(archive_digest);
+        run_blocking(move || Ok(fs::write(record, digest)?)).await
+    } else {
+        Err(Error::Custom(format!(
+            "Archive {} was not signed by gatekeeper {} for task {}",
+            archive_path.display(),
+            gatekeeper,
+            task_id
+        )))
+    }
+}
+
+/// The message the gatekeeper signs for an archive: the SCALE encoded (little endian) task id followed by the digest
+fn signed_archive_message(task_id: u64, archive_digest: &[u8]) -> Vec<u8> {
+    let mut message = task_id.to_le_bytes().to_vec();
+    message.extend_from_slice(archive_digest);
+    message
+}
+
+/// The record of the verified archive digest, next to the archive
+fn signed_archive_path(archive_path: &Path) -> PathBuf {
+    archive_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(SIGNED_ARCHIVE_FILE_NAME)
+}
+
+/// Compares the archive with the digest recorded when its gatekeeper signature was verified
+///
+/// # Returns
+/// The mismatch, if the archive was never verified or changed since. An archive that was removed after extraction is
+/// covered by its record.
+fn signed_archive_mismatch(task_dir: &Path, archive: &Path) -> Result<Option<String>> {
+    let Ok(signed) = fs::read_to_string(task_dir.join(SIGNED_ARCHIVE_FILE_NAME)) else {
+        return Ok(Some(format!(
+            "The archive in {} was not verified against a gatekeeper signature",
+            task_dir.display()
+        )));
+    };
+    if !archive.is_file() {
+        return Ok(None);
+    }
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(archive)?, &mut hasher)?;
+    let local = hex::encode(hasher.finalize());
+    Ok((local != signed.trim()).then(|| {
+        format!(
+            "Hash mismatch for {}: local {}, signed {}",
+            archive.display(),
+            local,
+            signed.trim()
+        )
+    }))
+}
+
+/// Compares a task artifact with its on-chain commitment
+///
+/// # Returns
+/// The mismatch, if the artifact differs
+fn artifact_mismatch(path: &Path, committed: &[u8]) -> Result<Option<String>> {
+    let local = fs::read(path)?;
+
+    let local_hash = hex::encode(commitment_hash(&local));
+    let committed_hash = hex::encode(commitment_hash(committed));
+
+    Ok((local_hash != committed_hash).then(|| {
+        format!(
+            "Hash mismatch for {}: local {}, on-chain {}",
+            path.display(),
+            local_hash,
+            committed_hash
+        )
+    }))
+}
+
+/// Compares the verifying key the proving key starts with against the committed one. The verifying key is derived from
+/// the compiled circuit, so a model that was swapped after the commitment was published shows up here.
+///
+/// # Returns
+/// The mismatch, if the proving key was generated for a different verifying key
+fn verifying_key_mismatch(proving_key_path: &Path, committed_vk: &[u8]) -> Result<Option<String>> {
+    let mut local_vk = Vec::with_capacity(committed_vk.len());
+    fs::File::open(proving_key_path)?
+        .take(committed_vk.len() as u64)
+        .read_to_end(&mut local_vk)?;
+
+    Ok((local_vk != committed_vk).then(|| {
+        format!(
+            "Verifying key mismatch for {}: local {}, on-chain {}",
+            proving_key_path.display(),
+            hex::encode(Sha256::digest(&local_vk)),
+            hex::encode(Sha256::digest(committed_vk))
+        )
+    }))
+}
+
+/// JSON artifacts are normalized before hashing, so that formatting differences introduced when packing the archive don't cause false mismatches
+fn commitment_hash(data: &[u8]) -> Vec<u8> {
+    let normalized = serde_json::from_slice::<serde_json::Value>(data)
+        .ok()
+        .and_then(|value| serde_json::to_vec(&value).ok());
+
+    match normalized {
+        Some(normalized) => Sha256::digest(&normalized).to_vec(),
+        None => Sha256::digest(data).to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        mock_chain::{ChainCall, MockChain},
+        substrate_queries::NzkCommitment,
+    };
+    use std::str::FromStr;
+    use subxt_signer::SecretUri;
+
+    #[test]
+    fn artifacts_are_compared_with_their_commitment() {
+        let dir = std::env::temp_dir().join("cyborg-integrity-test");
+        fs::create_dir_all(&dir).unwrap();
+        let settings = dir.join(SETTINGS_FILE_NAME);
+
+        // Formatting introduced when packing the archive is not a mismatch
+        fs::write(&settings, "{\n  \"scale\": 7,\n  \"logrows\": 17\n}").unwrap();
+        assert_eq!(artifact_mismatch(&settings, br#"{"scale":7,"logrows":17}"#).unwrap(), None);
+        assert!(artifact_mismatch(&settings, br#"{"scale":7,"logrows":18}"#)
+            .unwrap()
+            .is_some());
+
+        let archive = dir.join("model.tar.gz");
+        fs::write(&archive, b"model").unwrap();
+        assert!(signed_archive_mismatch(&dir, &archive).unwrap().is_some());
+
+        fs::write(signed_archive_path(&archive), hex::encode(Sha256::digest(b"model"))).unwrap();
+        assert_eq!(signed_archive_mismatch(&dir, &archive).unwrap(), None);
+
+        fs::write(&archive, b"swapped model").unwrap();
+        assert!(signed_archive_mismatch(&dir, &archive).unwrap().is_some());
+
+        // Once extracted the archive is gone, the record still covers the task
+        fs::remove_file(&archive).unwrap();
+        assert_eq!(signed_archive_mismatch(&dir, &archive).unwrap(), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn archive_signatures_are_bound_to_their_task() {
+        let dir = std::env::temp_dir().join("cyborg-integrity-signature-test");
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("model.tar.gz");
+        fs::write(&archive, b"model").unwrap();
+
+        let gatekeeper = Keypair::from_uri(&SecretUri::from_str("//Alice").unwrap()).unwrap();
+        let chain = MockChain::default();
+        *chain.gatekeeper.lock().unwrap() = Some(gatekeeper.public_key().to_account_id());
+        let sign = |task_id: u64| {
+            let message = signed_archive_message(task_id, &Sha256::digest(b"model"));
+            hex::encode(gatekeeper.sign(&message).0)
+        };
+
+        assert!(verify_archive_signature(&chain, 3, &archive, &sign(3)).await.is_ok());
+        assert_eq!(signed_archive_mismatch(&dir, &archive).unwrap(), None);
+
+        // The archive and signature of another task don't verify for this one
+        assert!(verify_archive_signature(&chain, 4, &archive, &sign(3)).await.is_err());
+        // Neither does a signature over the digest alone
+        let digest_only = hex::encode(gatekeeper.sign(&Sha256::digest(b"model")).0);
+        assert!(verify_archive_signature(&chain, 3, &archive, &digest_only).await.is_err());
+
+        fs::write(&archive, b"swapped model").unwrap();
+        assert!(verify_archive_signature(&chain, 3, &archive, &sign(3)).await.is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn neuro_zk_tasks_are_verified_against_the_chain() {
+        let dir = std::env::temp_dir().join("cyborg-integrity-chain-test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(SETTINGS_FILE_NAME), r#"{"scale":7}"#).unwrap();
+        fs::write(dir.join(PROVING_KEY_FILE_NAME), b"vk-of-the-circuit|rest of the proving key").unwrap();
+        let task = CurrentTask {
+            id: 3,
+            task_type: TaskType::NeuroZk,
+        };
+        let task_dir = dir.to_str().unwrap();
+        let keypair = Keypair::from_uri(&SecretUri::from_str("//Alice").unwrap()).unwrap();
+
+        let chain = MockChain::default();
+        assert!(verify_task_commitment(&chain, &keypair, &task, task_dir).await.is_err());
+
+        let commitment = |settings: &str, vk: &[u8]| NzkCommitment {
+            zk_input: Vec::new(),
+            zk_settings: settings.as_bytes().to_vec(),
+            zk_verifying_key: vk.to_vec(),
+        };
+        chain
+            .nzk_commitments
+            .lock()
+            .unwrap()
+            .insert(3, commitment(r#"{"scale":7}"#, b"vk-of-the-circuit"));
+        assert!(verify_task_commitment(&chain, &keypair, &task, task_dir).await.is_ok());
+        assert!(chain.calls().is_empty());
+
+        chain
+            .nzk_commitments
+            .lock()
+            .unwrap()
+            .insert(3, commitment(r#"{"scale":8}"#, b"vk-of-the-circuit"));
+        assert!(verify_task_commitment(&chain, &keypair, &task, task_dir).await.is_err());
+
+        // A proving key generated for a different circuit is reported like any other mismatch
+        chain
+            .nzk_commitments
+            .lock()
+            .unwrap()
+            .insert(3, commitment(r#"{"scale":7}"#, b"vk-of-another-circuit"));
+        assert!(verify_task_commitment(&chain, &keypair, &task, task_dir).await.is_err());
+        assert!(matches!(
+            chain.calls().as_slice(),
+            [ChainCall::ReportIntegrityMismatch(3, _), ChainCall::ReportIntegrityMismatch(3, mismatch)]
+                if mismatch.starts_with("Verifying key mismatch")
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::config;
+use axum::extract::ws::{Message, WebSocket};
+use futures::{stream::SplitSink, SinkExt};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Mutex};
+
+/// How connections are kept alive, half-open connections would otherwise keep the engine waiting on dead peers:
+/// - `WS_PING_INTERVAL_SECS`: A ping is sent after this long (default 30)
+/// - `WS_PONG_TIMEOUT_SECS`: The connection is reaped if the peer sends nothing within this long of a ping (default 10)
+/// - `WS_IDLE_TIMEOUT_SECS`: The connection is reaped after this long without requests while none is pending
+///   (default 600, 0 keeps idle connections)
+#[derive(Debug, Clone, Copy)]
+pub struct Keepalive {
+    ping_interval: Duration,
+    pong_timeout: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+impl Keepalive {
+    pub fn from_env() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(
+                config::optional_env("WS_PING_INTERVAL_SECS", 30u64).max(1),
+            ),
+            pong_timeout: Duration::from_secs(
+                config::optional_env("WS_PONG_TIMEOUT_SECS", 10u64).max(1),
+            ),
+            idle_timeout: match config::optional_env("WS_IDLE_TIMEOUT_SECS", 600u64) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+        }
+    }
+}
+
+/// What was last received on a connection
+pub struct Liveness {
+    last_frame: Instant,
+    last_request: Instant,
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self {
+            last_frame: Instant::now(),
+            last_request: Instant::now(),
+        }
+    }
+}
+
+impl Liveness {
+    /// Records a frame of the peer, pongs and control messages included
+    pub fn frame(&mut self) {
+        self.last_frame = Instant::now();
+    }
+
+    /// Records a request of the peer
+    pub fn request(&mut self) {
+        self.last_request = Instant::now();
+    }
+}
+
+/// Pings the peer of a connection until it is reaped or the returned task is aborted. The reason is sent on `reaped`,
+/// the request stream ends on it so that the engine finishes the pending requests and releases the connection.
+///
+/// # Arguments
+/// * `keepalive` - The settings of the connection
+/// * `sender` - The sending half of the connection
+/// * `liveness` - Updated by the request stream with every frame received
+/// * `is_busy` - Whether requests of the connection are pending, busy connections are never idle
+/// * `reaped` - Receives the reason once the connection is reaped
+pub fn spawn(
+    keepalive: Keepalive,
+    sender: Arc<Mutex<SplitSink<WebSocket, Message>>>,
+    liveness: Arc<std::sync::Mutex<Liveness>>,
+    is_busy: impl Fn() -> bool + Send + 'static,
+    reaped: watch::Sender<Option<&'static str>>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(keepalive.ping_interval).await;
+            if let Some(reason) = idle_reason(&liveness.lock().unwrap(), &keepalive, is_busy()) {
+                reaped.send_replace(Some(reason));
+                return;
+            }
+
+            let ping_sent = Instant::now();
+            if sender
+                .lock()
+                .await
+                .send(Message::Ping(Default::default()))
+                .await
+                .is_err()
+            {
+                reaped.send_replace(Some("the connection is closed"));
+                return;
+            }
+            tokio::time::sleep(keepalive.pong_timeout).await;
+            if liveness.lock().unwrap().last_frame < ping_sent {
+                reaped.send_replace(Some("no pong within the timeout"));
+                return;
+            }
+        }
+    })
+}
+
+fn idle_reason(liveness: &Liveness, keepalive: &Keepalive, busy: bool) -> Option<&'static str> {
+    let idle_timeout = keepalive.idle_timeout?;
+    (!busy && liveness.last_request.elapsed() >= idle_timeout)
+        .then_some("no requests within the idle timeout")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_connections_without_pending_requests_idle() {
+        let keepalive = Keepalive {
+            ping_interval: Duration::from_secs(30),
+            pong_timeout: Duration::from_secs(10),
+            idle_timeout: Some(Duration::from_secs(60)),
+        };
+        let stale = Liveness {
+            last_frame: Instant::now(),
+            last_request: Instant::now() - Duration::from_secs(120),
+        };
+
+        assert!(idle_reason(&stale, &keepalive, false).is_some());
+        assert!(idle_reason(&stale, &keepalive, true).is_none());
+        assert!(idle_reason(&Liveness::default(), &keepalive, false).is_none());
+        let keep_idle = Keepalive {
+            idle_timeout: None,
+            ..keepalive
+        };
+        assert!(idle_reason(&stale, &keep_idle, false).is_none());
+    }
+}
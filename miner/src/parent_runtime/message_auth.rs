@@ -0,0 +1,166 @@
+use crate::{config, error::Result, schema};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subxt_signer::sr25519::{self, PublicKey, Signature};
+
+/// Authentication of websocket requests by the task owner, enabled with `WS_AUTH=true`. Every request is wrapped in an
+/// envelope signed with the sr25519 key of the task owner:
+/// `{"payload":"<request>","sequence":1,"timestamp":1700000000000,"signature":"0x..."}`
+///
+/// The signature covers `<session>:<sequence>:<timestamp>:<payload>`, where `session` is a random nonce the server
+/// sends as `{"event":"session","session":"..."}` when a connection opens. A captured frame can't be replayed on
+/// another connection, as the session differs, nor on the same one, as sequences must strictly increase. Frames
+/// whose timestamp is off by more than `WS_AUTH_MAX_SKEW_SECS` (default 30) are rejected as well, so held back frames
+/// can't skew usage accounting. Binary requests can't carry an envelope, they are rejected while requests are
+/// authenticated.
+#[derive(Clone)]
+pub struct AuthPolicy {
+    owner: PublicKey,
+    max_skew: Duration,
+}
+
+impl AuthPolicy {
+    /// # Returns
+    /// The policy for the task owner, `None` if requests are not authenticated, or an `Error` if authentication is
+    /// enabled without a known task owner
+    pub fn from_env(task_owner_path: &str) -> Result<Option<Self>> {
+        if !config::security_env("WS_AUTH", false)? {
+            return Ok(None);
+        }
+
+        let task_owner = schema::read_task_owner(task_owner_path)?
+            .ok_or("WS_AUTH is enabled, but the task owner is unknown")?;
+
+        Ok(Some(Self {
+            owner: PublicKey(task_owner.address.0),
+            max_skew: Duration::from_secs(config::optional_env("WS_AUTH_MAX_SKEW_SECS", 30)),
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    payload: String,
+    sequence: u64,
+    /// Unix time in milliseconds the client signed the request at
+    timestamp: u64,
+    signature: String,
+}
+
+/// The authentication state of a single connection
+pub struct Session {
+    policy: AuthPolicy,
+    id: String,
+    last_sequence: Option<u64>,
+}
+
+impl Session {
+    pub fn new(policy: AuthPolicy) -> Self {
+        let mut id = [0u8; 16];
+        OsRng.fill_bytes(&mut id);
+
+        Self {
+            policy,
+            id: hex::encode(id),
+            last_sequence: None,
+        }
+    }
+
+    /// The message announcing the session clients have to sign for
+    pub fn announcement(&self) -> String {
+        serde_json::json!({ "event": "session", "session": self.id }).to_string()
+    }
+
+    /// Checks the envelope of a request
+    ///
+    /// # Arguments
+    /// * `message` - The message as received
+    ///
+    /// # Returns
+    /// The request wrapped in the envelope, or why the message was rejected
+    pub fn open(&mut self, message: &str) -> std::result::Result<String, String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0);
+        self.open_at(message, now)
+    }
+
+    fn open_at(&mut self, message: &str, now: u64) -> std::result::Result<String, String> {
+        let envelope: Envelope = serde_json::from_str(message)
+            .map_err(|e| format!("Requests must be signed by the task owner: {}", e))?;
+
+        let signature: [u8; 64] = hex::decode(envelope.signature.trim_start_matches("0x"))
+            .map_err(|e| format!("Malformed signature: {}", e))?
+            .try_into()
+            .map_err(|_| "Signature must be 64 bytes".to_string())?;
+        let signed = format!(
+            "{}:{}:{}:{}",
+            self.id, envelope.sequence, envelope.timestamp, envelope.payload
+        );
+        if !sr25519::verify(&Signature(signature), signed, &self.policy.owner) {
+            return Err("Invalid signature".to_string());
+        }
+
+        // Only checked once the signature is valid, so forged frames can't advance the sequence
+        if self
+            .last_sequence
+            .is_some_and(|last_sequence| envelope.sequence <= last_sequence)
+        {
+            return Err(format!("Sequence {} was already used", envelope.sequence));
+        }
+        if now.abs_diff(envelope.timestamp) > self.policy.max_skew.as_millis() as u64 {
+            return Err("Timestamp is outside of the accepted window".to_string());
+        }
+
+        self.last_sequence = Some(envelope.sequence);
+        Ok(envelope.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use subxt_signer::{sr25519::Keypair, SecretUri};
+
+    fn envelope(keypair: &Keypair, session: &str, sequence: u64, timestamp: u64) -> String {
+        let payload = "{\"inputs\":[]}";
+        let signature =
+            keypair.sign(format!("{}:{}:{}:{}", session, sequence, timestamp, payload).as_bytes());
+
+        serde_json::json!({
+            "payload": payload,
+            "sequence": sequence,
+            "timestamp": timestamp,
+            "signature": hex::encode(signature.0),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn replayed_and_stale_frames_are_rejected() {
+        let owner = Keypair::from_uri(&SecretUri::from_str("//Alice").unwrap()).unwrap();
+        let policy = AuthPolicy {
+            owner: owner.public_key(),
+            max_skew: Duration::from_secs(30),
+        };
+        let mut session = Session::new(policy.clone());
+        let now = 1_700_000_000_000;
+
+        let first = envelope(&owner, &session.id, 1, now);
+        assert!(session.open_at(&first, now).is_ok());
+        assert!(session.open_at(&first, now).is_err());
+        assert!(session
+            .open_at(&envelope(&owner, &session.id, 2, now - 60_000), now)
+            .is_err());
+        assert!(session
+            .open_at(&envelope(&owner, &session.id, 2, now), now)
+            .is_ok());
+
+        // Frames captured on one connection are worthless on another
+        let mut other_session = Session::new(policy);
+        assert!(other_session.open_at(&first, now).is_err());
+    }
+}
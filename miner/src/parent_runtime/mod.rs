@@ -1,4 +1,24 @@
+pub mod artifacts;
+pub mod audit_sampling;
+pub mod challenge;
+pub mod connection_limiter;
 pub mod storage_interactor;
+pub mod storage_upload;
 pub mod inference;
+pub mod inference_history;
+pub mod integrity;
+pub mod keepalive;
+pub mod message_auth;
+pub mod model_retention;
+pub mod pricing;
 pub mod proof;
+pub mod response_anchor;
+pub mod route_aliases;
+pub mod routes;
 pub mod server_control;
+pub mod session_affinity;
+pub mod setup_progress;
+pub mod task_completion;
+pub mod task_manifest;
+pub mod transcript;
+pub mod usage_policy;
@@ -0,0 +1,274 @@
+use crate::{
+    config::{self, Paths},
+    error::Result,
+    parent_runtime::storage_interactor,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Describes a retained model, next to its files in the directory named after the hash of its archive
+const ENTRY_FILE_NAME: &str = "retained.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RetainedModel {
+    /// The storage identifier the model was assigned with, a re-assignment names the same
+    storage_identifier: String,
+    /// Hex encoded SHA-256 of the model archive
+    sha256: String,
+    /// Seconds since the Unix epoch
+    retained_at: u64,
+}
+
+/// How long the model of a stopped task is kept for a re-assignment, set with `MODEL_RETENTION_SECS`. The default of
+/// 0 evicts the model together with the rest of the task directory.
+fn retention_period() -> Option<Duration> {
+    match config::optional_env("MODEL_RETENTION_SECS", 0u64) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Where retained models are kept, `MODEL_RETENTION_DIR` or by default next to the task directory, so that they are
+/// moved instead of copied and outlive the task directory
+fn retention_dir(paths: &Paths) -> PathBuf {
+    match std::env::var("MODEL_RETENTION_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => Path::new(&paths.task_dir_path)
+            .parent()
+            .map(|dir| dir.join("retained-models"))
+            .unwrap_or_else(|| PathBuf::from("retained-models")),
+    }
+}
+
+/// Moves the model of a stopped OpenInference task out of the task directory, keyed by the hash of its archive, so
+/// that a re-assignment of the same model skips the download and the extraction. Everything else in the task
+/// directory, like results and transcripts of its users, is removed with the task directory as before. Does nothing
+/// unless `MODEL_RETENTION_SECS` is set.
+///
+/// # Arguments
+/// * `paths` - The paths of the miner whose task stopped
+///
+/// # Returns
+/// `Ok(())` if the model was retained or there is nothing to retain, or an `Error` if it can't be moved
+pub fn retain(paths: &Paths) -> Result<()> {
+    match retention_period() {
+        Some(period) => retain_for(paths, period),
+        None => Ok(()),
+    }
+}
+
+/// Moves the model of a stopped task out of the task directory for `period`, see [`retain`]
+fn retain_for(paths: &Paths, period: Duration) -> Result<()> {
+    let dir = retention_dir(paths);
+    prune_expired(&dir, period)?;
+
+    let task_dir = Path::new(&paths.task_dir_path);
+    let archive = task_dir.join(&paths.task_file_name);
+    let Some(source) = storage_interactor::read_task_source(&paths.task_dir_path)? else {
+        return Ok(());
+    };
+    if !archive.is_file() {
+        return Ok(());
+    }
+
+    let sha256 = hash_file(&archive)?;
+    let entry_dir = dir.join(&sha256);
+    if entry_dir.exists() {
+        fs::remove_dir_all(&entry_dir)?;
+    }
+    fs::create_dir_all(&entry_dir)?;
+    for path in model_paths(task_dir, &paths.task_file_name) {
+        fs::rename(&path, entry_dir.join(path.file_name().unwrap_or_default()))?;
+    }
+    fs::write(
+        entry_dir.join(ENTRY_FILE_NAME),
+        serde_json::to_string(&RetainedModel {
+            storage_identifier: source.storage_identifier,
+            sha256: sha256.clone(),
+            retained_at: now(),
+        })?,
+    )?;
+
+    println!("Retained model {} for {} seconds", sha256, period.as_secs());
+    Ok(())
+}
+
+/// Moves a retained model of the storage identifier back into the task directory. Its archive is hashed again, a model
+/// that changed while it was retained is evicted instead.
+///
+/// # Arguments
+/// * `paths` - The paths of the miner the model is set up for
+/// * `storage_identifier` - The storage identifier the task was assigned with
+///
+/// # Returns
+/// Whether a retained model was restored, the download can be skipped then
+pub fn restore(paths: &Paths, storage_identifier: &str) -> Result<bool> {
+    match retention_period() {
+        Some(period) => restore_within(paths, storage_identifier, period),
+        None => Ok(false),
+    }
+}
+
+/// Moves a retained model of the storage identifier back unless it was retained longer than `period`, see [`restore`]
+fn restore_within(paths: &Paths, storage_identifier: &str, period: Duration) -> Result<bool> {
+    let dir = retention_dir(paths);
+    prune_expired(&dir, period)?;
+
+    for (entry_dir, retained) in entries(&dir)? {
+        if retained.storage_identifier != storage_identifier {
+            continue;
+        }
+
+        let archive = entry_dir.join(&paths.task_file_name);
+        if !archive.is_file() || hash_file(&archive)? != retained.sha256 {
+            println!(
+                "Evicting retained model {}, it changed while it was retained",
+                retained.sha256
+            );
+            fs::remove_dir_all(&entry_dir)?;
+            continue;
+        }
+
+        let task_dir = Path::new(&paths.task_dir_path);
+        fs::create_dir_all(task_dir)?;
+        for path in model_paths(&entry_dir, &paths.task_file_name) {
+            let destination = task_dir.join(path.file_name().unwrap_or_default());
+            if destination.is_dir() {
+                fs::remove_dir_all(&destination)?;
+            } else if destination.exists() {
+                fs::remove_file(&destination)?;
+            }
+            fs::rename(&path, destination)?;
+        }
+        fs::remove_dir_all(&entry_dir)?;
+
+        println!("Restored retained model {}", retained.sha256);
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Removes the retained models whose retention period is over, eg. at startup
+///
+/// # Returns
+/// The number of models removed
+pub fn prune(paths: &Paths) -> Result<usize> {
+    match retention_period() {
+        Some(period) => prune_expired(&retention_dir(paths), period),
+        // Retention was turned off, nothing retained before is restored anymore
+        None => prune_expired(&retention_dir(paths), Duration::ZERO),
+    }
+}
+
+fn prune_expired(dir: &Path, period: Duration) -> Result<usize> {
+    let mut removed = 0;
+    for (entry_dir, retained) in entries(dir)? {
+        if now().saturating_sub(retained.retained_at) >= period.as_secs() {
+            fs::remove_dir_all(&entry_dir)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// The retained models in `dir`, directories without a readable description are left to the operator
+fn entries(dir: &Path) -> Result<Vec<(PathBuf, RetainedModel)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let retained = fs::read_to_string(path.join(ENTRY_FILE_NAME))
+            .ok()
+            .and_then(|entry| serde_json::from_str::<RetainedModel>(&entry).ok());
+        if let Some(retained) = retained {
+            entries.push((path, retained));
+        }
+    }
+    Ok(entries)
+}
+
+/// The files of the model in `dir`: the archive and the directory it is extracted to
+fn model_paths(dir: &Path, task_file_name: &str) -> Vec<PathBuf> {
+    let stem = task_file_name
+        .strip_suffix(".tar.gz")
+        .or_else(|| task_file_name.strip_suffix(".zip"))
+        .unwrap_or(task_file_name);
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for name in [
+        task_file_name.to_string(),
+        stem.to_string(),
+        format!("{}.tar.gz", stem),
+        format!("{}.zip", stem),
+    ] {
+        let path = dir.join(name);
+        if path.exists() && !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_retained_model_is_restored_for_its_storage_identifier() {
+        let root = std::env::temp_dir().join(format!("model-retention-{}", std::process::id()));
+        let paths = Paths {
+            log_path: root.join("logs/log.txt"),
+            task_file_name: "model.tar.gz".to_string(),
+            task_dir_path: root.join("task").to_string_lossy().to_string(),
+            task_owner_path: root.join("task_owner.json").to_string_lossy().to_string(),
+            identity_path: root.join("identity.json").to_string_lossy().to_string(),
+        };
+        let task_dir = PathBuf::from(&paths.task_dir_path);
+        storage_interactor::write_task_source(
+            &paths.task_dir_path,
+            &storage_interactor::TaskSource {
+                storage_location: "https://storage.example".to_string(),
+                storage_identifier: "model-fid".to_string(),
+            },
+        )
+        .unwrap();
+        fs::write(task_dir.join("model.tar.gz"), b"archive").unwrap();
+        fs::create_dir_all(task_dir.join("model/1")).unwrap();
+        fs::write(task_dir.join("results.jsonl"), b"{}").unwrap();
+        let period = Duration::from_secs(3600);
+
+        retain_for(&paths, period).unwrap();
+        fs::remove_dir_all(&task_dir).unwrap();
+        assert!(!restore_within(&paths, "other-fid", period).unwrap());
+        assert!(restore_within(&paths, "model-fid", period).unwrap());
+
+        assert_eq!(fs::read(task_dir.join("model.tar.gz")).unwrap(), b"archive");
+        assert!(task_dir.join("model/1").is_dir());
+        assert!(!task_dir.join("results.jsonl").exists());
+        assert!(entries(&retention_dir(&paths)).unwrap().is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
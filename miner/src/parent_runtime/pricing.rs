@@ -0,0 +1,65 @@
+use crate::{
+    chain_properties,
+    config::{self, get_parachain_client},
+    error::Result,
+    utils::substrate_queries::get_task_pricing,
+};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use subxt::utils::AccountId32;
+use tokio::sync::Mutex;
+
+/// The on-chain pricing of a served task, so client SDKs can estimate costs without querying the parachain
+/// themselves. Pricing rarely changes, it is cached for `PRICING_CACHE_SECS` (default 60) instead of querying the
+/// chain for every client asking.
+pub struct PricingCache {
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, Value)>>,
+}
+
+impl PricingCache {
+    pub fn from_env() -> Self {
+        Self {
+            ttl: Duration::from_secs(config::optional_env("PRICING_CACHE_SECS", 60)),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// The pricing of the task as served to clients
+    ///
+    /// # Arguments
+    /// * `task_id` - The served task
+    /// * `miner` - The miner serving the task, its reward rates are part of the pricing
+    ///
+    /// # Returns
+    /// The pricing as JSON, or an `Error` if the chain can't be queried
+    pub async fn get(&self, task_id: u64, miner: &AccountId32) -> Result<Value> {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched_at, pricing)) = cached.as_ref() {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(pricing.clone());
+            }
+        }
+
+        let pricing = get_task_pricing(get_parachain_client()?, task_id, miner).await?;
+        let token = chain_properties::get();
+        // Balances are strings, they exceed the integers JSON clients can represent
+        let pricing = serde_json::json!({
+            "task_id": task_id,
+            "billing_unit": "compute_hour",
+            "token_symbol": token.token_symbol,
+            "token_decimals": token.token_decimals,
+            "subscription_fee_per_hour": pricing.subscription_fee_per_hour.to_string(),
+            "compute_hours_deposit": pricing.compute_hours_deposit,
+            "consumed_compute_hours": pricing.consumed_compute_hours,
+            "miner_reward_rates": pricing.miner_reward_rates.map(|rates| serde_json::json!({
+                "cpu": rates.cpu.to_string(),
+                "ram": rates.ram.to_string(),
+                "storage": rates.storage.to_string(),
+            })),
+        });
+
+        *cached = Some((Instant::now(), pricing.clone()));
+        Ok(pricing)
+    }
+}
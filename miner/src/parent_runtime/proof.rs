@@ -1,31 +1,564 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::{
-    config::get_paths,
+    config::{self, get_parachain_client, get_paths},
     error::{Error, Result},
+    parachain_interactor::task_deadlines::TaskDeadline,
+    parent_runtime::{
+        server_control::{NZK_TASKS, PROOF_PROGRESS, SHUTDOWN_SENDERS},
+        storage_upload,
+        task_manifest::{read_manifest, ProofInput, ProofSubmission},
+    },
+    substrate_interface::api::task_management::events::TaskStopRequested,
+    utils::substrate_queries::get_nzk_commitment,
 };
-use neuro_zk_runtime::{self, NeuroZKEngine};
+use futures::StreamExt;
+use neuro_zk_runtime::{self, NeuroZKEngine, ProofProgress, ProofStage, ProverProcess};
+use once_cell::sync::Lazy;
+use tokio::sync::oneshot;
 
-pub async fn generate_proof() -> Result<Vec<u8>> {
+/// Witness file of the prover, kept apart from anything the inference path touches
+const PROOF_WITNESS_PATH: &str = "proof-witness.json";
+
+/// The input shipped with the model archive
+const CANNED_INPUT_PATH: &str = "input.json";
+/// Input of the prover if it is not the canned one, written right before proving
+const PROOF_INPUT_PATH: &str = "proof-input.json";
+
+/// The last proof generated for the task, kept to diagnose a rejection
+const SUBMITTED_PROOF_PATH: &str = "submitted-proof.json";
+/// The verifying key and settings the chain checks proofs of the task with
+const CHAIN_VK_PATH: &str = "chain-vk.key";
+const CHAIN_SETTINGS_PATH: &str = "chain-settings.json";
+/// Directory in the task directory the output of EZKL is logged to, one file per job
+const PROVER_LOG_DIR: &str = "prover-logs";
+
+/// The input data of the last request served per task, only requests in the EZKL input format are kept
+static LAST_SERVED_REQUEST: Lazy<Mutex<HashMap<u64, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Proofs of the task, whether requested by the chain or on demand, wait for each other
+static PROVING: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Duration of the last completed proof in milliseconds, used to estimate the duration of the next one
+static LAST_PROOF_DURATION_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Arguments the binary of the miner serves EZKL jobs with
+const PROVER_JOB_ARGS: [&str; 1] = ["prover-job"];
+/// Whether the binary running the miner serves `prover-job`, see `enable_prover_process`
+static PROVER_PROCESS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Lets EZKL run in child processes of the current executable, which must call `serve_prover_job` when started with
+/// `prover-job`. The `cyborg-miner` binary does, embedders that don't keep EZKL in their own process.
+pub fn enable_prover_process() {
+    PROVER_PROCESS_ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Serves an EZKL job in a child process of the miner
+///
+/// # Returns
+/// The exit code of the process
+pub async fn serve_prover_job() -> i32 {
+    neuro_zk_runtime::prover::serve_job().await
+}
+
+/// The child processes EZKL runs in, so that a crash of EZKL doesn't take down the miner, a proof can be killed when
+/// its task stops, and large circuits, which take tens of GB, don't get the miner OOM-killed:
+/// - `PROVER_SUBPROCESS`: Runs EZKL in child processes if the binary serves them (default true)
+/// - `NZK_MEMORY_LIMIT_BYTES`: Memory budget of a single EZKL job, eg. a witness or a proof (default 0, unlimited)
+/// - `PROVER_CGROUP`: Delegated cgroup v2 directory the budget is enforced in with `memory.max`, the address space
+///   of the child is limited without it
+/// - `PROVER_LOGS`: Writes the output of every job to its own file in `prover-logs` of the task directory instead of
+///   the log of the miner (default true)
+///
+/// # Returns
+/// The child processes to use, `None` to run EZKL in this process
+pub fn prover_process() -> Option<ProverProcess> {
+    if !PROVER_PROCESS_ENABLED.load(Ordering::Relaxed)
+        || !config::optional_env("PROVER_SUBPROCESS", true)
+    {
+        return None;
+    }
+    let program = match std::env::current_exe() {
+        Ok(program) => program,
+        Err(e) => {
+            println!(
+                "Running EZKL in the miner process, its executable is unknown: {}",
+                e
+            );
+            return None;
+        }
+    };
+
+    let memory_limit = match config::optional_env("NZK_MEMORY_LIMIT_BYTES", 0u64) {
+        0 => None,
+        limit => Some(limit),
+    };
+    let cgroup_parent = std::env::var("PROVER_CGROUP")
+        .ok()
+        .filter(|cgroup| !cgroup.is_empty())
+        .map(PathBuf::from);
+
+    Some(
+        ProverProcess::new(
+            program,
+            PROVER_JOB_ARGS.iter().map(|arg| arg.to_string()).collect(),
+        )
+        .with_memory_limit(memory_limit)
+        .with_cgroup_parent(cgroup_parent)
+        .with_log_dir(prover_log_dir()),
+    )
+}
+
+/// Where the jobs of the prover are logged, `None` if they log to the log of the miner
+fn prover_log_dir() -> Option<PathBuf> {
+    if !config::optional_env("PROVER_LOGS", true) {
+        return None;
+    }
+    get_paths()
+        .ok()
+        .map(|paths| Path::new(&paths.task_dir_path).join(PROVER_LOG_DIR))
+}
+
+pub async fn generate_proof(task_id: u64) -> Result<Vec<u8>> {
+    let paths = get_paths()?;
+    let (proof, _) = prove(task_id).await?;
+
+    fs::write(
+        Path::new(&paths.task_dir_path).join(SUBMITTED_PROOF_PATH),
+        &proof,
+    )?;
+
+    Ok(proof.into())
+}
+
+/// How the task owner asked for the proofs of the task to be submitted
+pub fn proof_submission() -> Result<ProofSubmission> {
+    Ok(read_manifest(&get_paths()?.task_dir_path)?.proof_submission)
+}
+
+/// Uploads a proof to the `proofs` directory of the storage backend, for tasks whose proofs are submitted by reference
+///
+/// # Arguments
+/// * `task_id` - The task the proof was generated for
+/// * `proof_hash` - The hex encoded SHA-256 of the proof
+/// * `proof` - The proof as EZKL writes it
+///
+/// # Returns
+/// The identifier of the upload that is submitted on chain
+pub async fn upload_proof(task_id: u64, proof_hash: &str, proof: Vec<u8>) -> Result<String> {
+    let name = format!("proofs/{}-{}.json", task_id, proof_hash);
+    let stored = storage_upload::backend_from_env()?
+        .upload(&name, "application/json", proof)
+        .await
+        .map_err(|e| {
+            Error::Custom(format!(
+                "Failed to upload the proof of task {}: {}",
+                task_id, e
+            ))
+        })?;
+    Ok(stored.id)
+}
+
+/// How long the next proof is expected to take, `None` before the first proof of the miner completed
+pub fn estimated_duration() -> Option<Duration> {
+    match LAST_PROOF_DURATION_MS.load(Ordering::Relaxed) {
+        0 => None,
+        elapsed_ms => Some(Duration::from_millis(elapsed_ms)),
+    }
+}
+
+/// Generates a proof for a NeuroZK task outside of the proofs the chain requests, so that operators can check their
+/// prover setup and its timings before the first request arrives. Nothing is submitted, and the proof that was last
+/// submitted for the task is kept for diagnosing rejections.
+///
+/// # Arguments
+/// * `task_id` - The task to prove, the only NeuroZK task being served if `None`
+///
+/// # Returns
+/// `{"task_id":..,"proof":{..},"proof_bytes":..,"elapsed_ms":..,"stages":[{"stage":"witness","elapsed_ms":..},..]}`
+/// with the proof as EZKL writes it, or an `Error` if there is no such task or the proof fails
+pub async fn prove_now(task_id: Option<u64>) -> Result<serde_json::Value> {
+    let task_id = served_nzk_task(task_id)?;
+
+    println!("Proving task {} on demand", task_id);
+    let (proof, stages) = prove(task_id).await?;
+    let proof_bytes = proof.len();
+
+    Ok(serde_json::json!({
+        "task_id": task_id,
+        "proof": serde_json::from_str::<serde_json::Value>(&proof)
+            .unwrap_or(serde_json::Value::String(proof)),
+        "proof_bytes": proof_bytes,
+        "elapsed_ms": stages.last().map(|(_, elapsed_ms)| *elapsed_ms),
+        "stages": stages
+            .iter()
+            .map(|(stage, elapsed_ms)| serde_json::json!({
+                "stage": stage.as_str(),
+                "elapsed_ms": elapsed_ms,
+            }))
+            .collect::<Vec<_>>(),
+    }))
+}
+
+/// The log of the last proof of a NeuroZK task, to see why a proof failed without searching the log of the miner.
+/// Only proofs run in child processes are logged on their own, see `prover_process`.
+///
+/// # Arguments
+/// * `task_id` - The task, the only NeuroZK task being served if `None`
+///
+/// # Returns
+/// The path and content of the log, or an `Error` if there is no such task or none of its proofs was logged
+pub fn last_prover_log(task_id: Option<u64>) -> Result<(PathBuf, String)> {
+    let task_id = served_nzk_task(task_id)?;
+    let log_dir = Path::new(&get_paths()?.task_dir_path).join(PROVER_LOG_DIR);
+
+    let path = neuro_zk_runtime::last_job_log(&log_dir, Some("prove")).ok_or(Error::Custom(
+        format!("No proof of task {} was logged in {}", task_id, log_dir.display()),
+    ))?;
+    let log = String::from_utf8_lossy(&fs::read(&path)?).to_string();
+    Ok((path, log))
+}
+
+/// The NeuroZK task an operator asked about, the only one being served if none was named
+fn served_nzk_task(task_id: Option<u64>) -> Result<u64> {
+    let served: Vec<u64> = NZK_TASKS.lock().unwrap().iter().copied().collect();
+    match (task_id, served.as_slice()) {
+        (Some(task_id), _) if served.contains(&task_id) => Ok(task_id),
+        (Some(task_id), _) => Err(Error::Custom(format!(
+            "Task {} is not a NeuroZK task served by this miner",
+            task_id
+        ))),
+        (None, [task_id]) => Ok(*task_id),
+        (None, []) => Err(Error::Custom(
+            "No NeuroZK task is served by this miner".to_string(),
+        )),
+        (None, _) => Err(Error::Custom(format!(
+            "Several NeuroZK tasks are served, choose one of {:?}",
+            served
+        ))),
+    }
+}
+
+/// Runs the prover for a task, one proof at a time as the proofs of a task share their witness file. The proof is
+/// aborted if the task stops meanwhile.
+///
+/// # Returns
+/// The proof as EZKL writes it and the stages the prover reached, with the milliseconds elapsed when each was reached
+async fn prove(task_id: u64) -> Result<(String, Vec<(ProofStage, u64)>)> {
+    let _proving = PROVING.lock().await;
     let paths = get_paths()?;
 
     let engine = NeuroZKEngine::new(PathBuf::from(format!(
         "{}/{}",
         paths.task_dir_path, paths.task_file_name
     )))
-    .map_err(|e| Error::Custom(format!("Failed to create engine: {}", e.to_string())))?;
-
-    let proof = engine
-        .prove_inference(
-            &paths.task_dir_path,
-            "circuit.ezkl",
-            "pk.key",
-            "kzg.srs",
-            "proof-witness.json",
-            "input.json",
-        )
-        .await
-        .map_err(|e| Error::Custom(format!("Failed to generate proof: {}", e.to_string())))?;
+    .map_err(|e| Error::Custom(format!("Failed to create engine: {}", e.to_string())))?
+    .with_prover_process(prover_process());
 
-    Ok(proof.into())
+    let manifest = read_manifest(&paths.task_dir_path)?;
+    let proof_input_path =
+        prepare_proof_input(task_id, manifest.proof_input, &paths.task_dir_path).await?;
+
+    let estimated_total_ms = LAST_PROOF_DURATION_MS.load(Ordering::Relaxed);
+    let task_dir_path = paths.task_dir_path.clone();
+    let stages = Arc::new(Mutex::new(Vec::new()));
+    let reached_stages = Arc::clone(&stages);
+
+    // The witness is always generated anew, a stale one must never end up in a proof
+    let _ = fs::remove_file(Path::new(&task_dir_path).join(PROOF_WITNESS_PATH));
+
+    // Proving is CPU bound and takes minutes, so it gets its own thread and runtime instead of starving the
+    // workers that serve inference. It only writes to its own witness file, the model files are read-only.
+    let (abort_tx, abort_rx) = oneshot::channel::<&'static str>();
+    let proving = tokio::task::spawn_blocking(move || {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        runtime.block_on(async {
+            let proof = engine.prove_inference_with_progress(
+                &task_dir_path,
+                "circuit.ezkl",
+                "pk.key",
+                "kzg.srs",
+                PROOF_WITNESS_PATH,
+                proof_input_path,
+                |progress| {
+                    reached_stages
+                        .lock()
+                        .unwrap()
+                        .push((progress.stage, progress.elapsed.as_millis() as u64));
+                    report_progress(task_id, progress, estimated_total_ms)
+                },
+            );
+            // Dropping the proof kills its prover processes, EZKL running in this process only stops once it returns
+            tokio::select! {
+                proof = proof => proof.map_err(|e| e.to_string()),
+                Ok(reason) = abort_rx => Err(format!("aborted, {}", reason)),
+            }
+        })
+    });
+    tokio::pin!(proving);
+
+    let proof = tokio::select! {
+        proof = &mut proving => proof,
+        reason = task_stopped(task_id) => {
+            println!("Aborting the proof of task {}, {}", task_id, reason);
+            let _ = abort_tx.send(reason);
+            proving.await
+        }
+    }
+    .map_err(|e| Error::Custom(format!("Prover thread failed: {}", e)))?
+    .map_err(|e| Error::Custom(format!("Failed to generate proof: {}", e)))?;
+
+    let stages = std::mem::take(&mut *stages.lock().unwrap());
+    Ok((proof, stages))
+}
+
+/// Resolves once the task is stopped on chain, the miner stops serving it, or the proof deadline passed. Chain events
+/// are only processed after the proof, so the stop is watched for separately.
+///
+/// # Returns
+/// Why the task stopped
+async fn task_stopped(task_id: u64) -> &'static str {
+    let shutdown = SHUTDOWN_SENDERS
+        .lock()
+        .unwrap()
+        .get(&task_id)
+        .map(|sender| sender.subscribe());
+    let miner_stopping = async {
+        if let Some(mut shutdown) = shutdown {
+            if shutdown.wait_for(|stopped| *stopped).await.is_ok() {
+                return;
+            }
+        }
+        // The inference server ended on its own, that doesn't stop the task
+        std::future::pending::<()>().await
+    };
+
+    let stop_requested = async {
+        if let Ok(client) = get_parachain_client() {
+            if let Ok(mut blocks) = client.blocks().subscribe_finalized().await {
+                while let Some(Ok(block)) = blocks.next().await {
+                    let Ok(events) = block.events().await else {
+                        continue;
+                    };
+                    if events
+                        .find::<TaskStopRequested>()
+                        .flatten()
+                        .any(|stop| stop.task_id == task_id)
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+        std::future::pending::<()>().await
+    };
+
+    tokio::select! {
+        _ = miner_stopping => "the miner is stopping",
+        _ = stop_requested => "the task was stopped",
+        _ = TaskDeadline::Proof.passed() => "the proof deadline passed",
+    }
+}
+
+/// Verifies the last proof generated for a task against the verifying key and settings the task owner committed to
+/// on chain, to tell a broken proof apart from a proof checked against something else than what the miner proved
+///
+/// # Arguments
+/// * `task_id` - The id of the task whose proof was rejected
+///
+/// # Returns
+/// Whether the proof verifies locally, or an error if there is no proof or on-chain commitment to verify with
+pub async fn verify_submitted_proof(task_id: u64) -> Result<bool> {
+    let paths = get_paths()?;
+    let task_dir = Path::new(&paths.task_dir_path);
+    if !task_dir.join(SUBMITTED_PROOF_PATH).exists() {
+        return Err(Error::Custom(format!(
+            "No proof was generated for task {}",
+            task_id
+        )));
+    }
+
+    let commitment = get_nzk_commitment(get_parachain_client()?, task_id)
+        .await?
+        .ok_or(Error::Custom(format!(
+            "Task {} has no NeuroZK data on chain",
+            task_id
+        )))?;
+    fs::write(task_dir.join(CHAIN_VK_PATH), commitment.zk_verifying_key)?;
+    fs::write(task_dir.join(CHAIN_SETTINGS_PATH), commitment.zk_settings)?;
+
+    let engine = NeuroZKEngine::new(PathBuf::from(format!(
+        "{}/{}",
+        paths.task_dir_path, paths.task_file_name
+    )))
+    .map_err(|e| Error::Custom(format!("Failed to create engine: {}", e.to_string())))?
+    .with_prover_process(prover_process());
+    let task_dir_path = paths.task_dir_path.clone();
+
+    // Verification is CPU bound like proving, just much shorter
+    tokio::task::spawn_blocking(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        // A proof that fails to verify is reported as an error by EZKL
+        Ok(runtime
+            .block_on(engine.verify_proof(
+                &task_dir_path,
+                SUBMITTED_PROOF_PATH,
+                CHAIN_SETTINGS_PATH,
+                CHAIN_VK_PATH,
+                "kzg.srs",
+            ))
+            .unwrap_or_else(|e| {
+                println!(
+                    "Local verification of the proof for task {} failed: {}",
+                    task_id, e
+                );
+                false
+            }))
+    })
+    .await
+    .map_err(|e| Error::Custom(format!("Verifier thread failed: {}", e)))?
+    .map_err(|e: String| Error::Custom(format!("Failed to verify proof: {}", e)))
+}
+
+/// Appends the outcome of a proof submission to `proof-outcomes.jsonl`, kept next to the identity since the task
+/// directory is removed when the task stops
+///
+/// # Arguments
+/// * `task_id` - The task the proof was generated for
+/// * `outcome` - What happened to the proof, eg. `verified`, `resubmitted` or `abandoned`
+/// * `resubmissions` - How often the proof was resubmitted after a rejection
+/// * `detail` - The diagnosis of a rejection, if any
+pub fn record_proof_outcome(
+    task_id: u64,
+    outcome: &str,
+    resubmissions: u32,
+    detail: Option<&str>,
+) -> Result<()> {
+    tracing::info!(
+        "Proof for task {}: {} after {} resubmissions",
+        task_id,
+        outcome,
+        resubmissions
+    );
+
+    let identity_path = PathBuf::from(&get_paths()?.identity_path);
+    let outcomes_path = identity_path
+        .parent()
+        .map(|dir| dir.join("proof-outcomes.jsonl"))
+        .unwrap_or_else(|| PathBuf::from("proof-outcomes.jsonl"));
+
+    let record = serde_json::json!({
+        "task_id": task_id,
+        "outcome": outcome,
+        "resubmissions": resubmissions,
+        "detail": detail,
+        "recorded_at": std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0),
+    });
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(outcomes_path)?;
+    writeln!(file, "{}", record)?;
+
+    Ok(())
+}
+
+/// Keeps the input data of a served request, so the next proof can be generated for it
+///
+/// # Arguments
+/// * `task_id` - The id of the task the request was served for
+/// * `request` - The raw request, requests that are not in the EZKL input format are ignored
+pub fn record_served_request(task_id: u64, request: &str) {
+    let Ok(request) = serde_json::from_str::<serde_json::Value>(request) else {
+        return;
+    };
+    let Some(input_data) = request.get("input_data") else {
+        return;
+    };
+
+    // Fields like `timeout_ms` only concern serving, the prover gets nothing but the input data
+    let input = serde_json::json!({ "input_data": input_data });
+    LAST_SERVED_REQUEST
+        .lock()
+        .unwrap()
+        .insert(task_id, input.to_string());
+}
+
+/// Provides the input selected by the task manifest to the prover, returns its path relative to the task directory
+async fn prepare_proof_input(
+    task_id: u64,
+    source: ProofInput,
+    task_dir: &str,
+) -> Result<&'static str> {
+    let input = match source {
+        ProofInput::Canned => return Ok(CANNED_INPUT_PATH),
+        ProofInput::LastServed => LAST_SERVED_REQUEST
+            .lock()
+            .unwrap()
+            .get(&task_id)
+            .cloned()
+            .ok_or(Error::Custom(format!(
+                "No request has been served for task {} yet, nothing to prove",
+                task_id
+            )))?,
+        ProofInput::Chain => {
+            let commitment = get_nzk_commitment(get_parachain_client()?, task_id)
+                .await?
+                .ok_or(Error::Custom(format!(
+                    "Task {} has no NeuroZK data on chain",
+                    task_id
+                )))?;
+            String::from_utf8(commitment.zk_input)?
+        }
+    };
+
+    println!("Proving task {} for its {:?} input", task_id, source);
+    fs::write(Path::new(task_dir).join(PROOF_INPUT_PATH), input)?;
+
+    Ok(PROOF_INPUT_PATH)
+}
+
+fn report_progress(task_id: u64, progress: ProofProgress, estimated_total_ms: u64) {
+    let elapsed_ms = progress.elapsed.as_millis() as u64;
+
+    tracing::info!(
+        "Proof for task {}: stage '{}' reached after {} ms",
+        task_id,
+        progress.stage.as_str(),
+        elapsed_ms
+    );
+
+    if progress.stage == ProofStage::Done {
+        LAST_PROOF_DURATION_MS.store(elapsed_ms, Ordering::Relaxed);
+    }
+
+    let event = serde_json::json!({
+        "event": "proof_progress",
+        "task_id": task_id,
+        "stage": progress.stage.as_str(),
+        "elapsed_ms": elapsed_ms,
+        "estimated_total_ms": (estimated_total_ms > 0).then_some(estimated_total_ms),
+    });
+
+    // Sending only fails if no websocket is connected, which is fine
+    let _ = PROOF_PROGRESS.send(event.to_string());
 }
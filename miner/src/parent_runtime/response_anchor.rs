@@ -0,0 +1,130 @@
+use crate::{config::get_paths, error::Result};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs, path::PathBuf, sync::Mutex};
+use subxt::utils::AccountId32;
+
+/// Hashes of the request/response pairs served since the last anchor, per serving miner and task. Only tasks without zk proofs
+/// record them, anchoring their Merkle root on chain lets task owners spot-check outputs after the fact.
+static SERVED_RESPONSES: Lazy<Mutex<HashMap<(AccountId32, u64), Vec<[u8; 32]>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A batch of served responses, ready to be anchored
+pub struct AnchorBatch {
+    pub task_id: u64,
+    pub root: [u8; 32],
+    pub leaf_count: usize,
+}
+
+/// Records a served request/response pair as the leaf `sha256(sha256(request) || sha256(response))`
+pub fn record_response(miner: &AccountId32, task_id: u64, request: &str, response: &str) {
+    let leaf = leaf_hash(request, response);
+    SERVED_RESPONSES
+        .lock()
+        .unwrap()
+        .entry((miner.clone(), task_id))
+        .or_default()
+        .push(leaf);
+}
+
+/// Takes all leaves the miner recorded since the last call and returns one batch per task.
+/// The leaves are kept on disk, so inclusion proofs for the anchored roots can be produced later.
+pub fn take_batches(miner: &AccountId32) -> Result<Vec<AnchorBatch>> {
+    let served: Vec<(u64, Vec<[u8; 32]>)> = {
+        let mut served_responses = SERVED_RESPONSES.lock().unwrap();
+        let keys: Vec<_> = served_responses
+            .keys()
+            .filter(|(server, _)| server == miner)
+            .cloned()
+            .collect();
+
+        keys.into_iter()
+            .filter_map(|key| served_responses.remove(&key).map(|leaves| (key.1, leaves)))
+            .collect()
+    };
+
+    served
+        .into_iter()
+        .filter(|(_, leaves)| !leaves.is_empty())
+        .map(|(task_id, leaves)| {
+            let root = merkle_root(&leaves);
+            persist_batch(task_id, &root, &leaves)?;
+
+            Ok(AnchorBatch {
+                task_id,
+                root,
+                leaf_count: leaves.len(),
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn leaf_hash(request: &str, response: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(request.as_bytes()));
+    hasher.update(Sha256::digest(response.as_bytes()));
+    hasher.finalize().into()
+}
+
+/// Binary SHA-256 Merkle tree, the last node of an odd level is paired with itself
+fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair.get(1).unwrap_or(&pair[0]));
+                hasher.finalize().into()
+            })
+            .collect();
+    }
+
+    level[0]
+}
+
+fn persist_batch(task_id: u64, root: &[u8; 32], leaves: &[[u8; 32]]) -> Result<()> {
+    // Kept next to the identity, the task and log directories are removed when a task stops
+    let identity_path = PathBuf::from(&get_paths()?.identity_path);
+    let anchor_dir = identity_path
+        .parent()
+        .map(|dir| dir.join("response-anchors"))
+        .unwrap_or_else(|| PathBuf::from("response-anchors"));
+    fs::create_dir_all(&anchor_dir)?;
+
+    let batch = serde_json::json!({
+        "task_id": task_id,
+        "root": hex::encode(root),
+        "leaves": leaves.iter().map(hex::encode).collect::<Vec<_>>(),
+    });
+    fs::write(
+        anchor_dir.join(format!("{}.json", hex::encode(root))),
+        batch.to_string(),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_leaf_is_its_own_root() {
+        let leaf = leaf_hash("request", "response");
+        assert_eq!(merkle_root(&[leaf]), leaf);
+    }
+
+    #[test]
+    fn odd_leaf_is_paired_with_itself() {
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let expected = merkle_root(&[
+            merkle_root(&[[1u8; 32], [2u8; 32]]),
+            merkle_root(&[[3u8; 32], [3u8; 32]]),
+        ]);
+
+        assert_eq!(merkle_root(&leaves), expected);
+    }
+}
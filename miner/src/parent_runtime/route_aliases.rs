@@ -0,0 +1,225 @@
+use crate::{
+    config::{self, Paths},
+    error::{Error, Result},
+    schema,
+    utils::blocking::run_blocking,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+use subxt::utils::AccountId32;
+
+/// File next to the identity recording the task owner every alias is bound to, it outlives the task directory
+const ALIASES_FILE_NAME: &str = "route-aliases.json";
+/// File in the task directory keeping the hash of the model archive, the archive of OpenInference tasks is removed
+/// once it is extracted
+const MODEL_HASH_FILE_NAME: &str = "model.sha256";
+
+/// Stable routes a task is served under besides `/{task_id}`, so that clients keep their URL when the same model is
+/// scheduled again under a new task id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteAlias {
+    /// `/latest`, the task the miner serves now
+    Latest,
+    /// `/by-model/{hash}`, the task serving the model archive with the hex encoded SHA-256 `hash`
+    ByModel,
+}
+
+impl RouteAlias {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "latest" => Some(RouteAlias::Latest),
+            "by-model" => Some(RouteAlias::ByModel),
+            _ => None,
+        }
+    }
+}
+
+/// The aliases enabled with `INFERENCE_ROUTE_ALIASES`, a comma separated list of `latest` and `by-model` (default
+/// none)
+fn configured_aliases() -> Vec<RouteAlias> {
+    config::optional_env("INFERENCE_ROUTE_ALIASES", String::new())
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| {
+            let alias = RouteAlias::parse(name);
+            if alias.is_none() {
+                println!("Ignoring the unknown route alias '{}'", name);
+            }
+            alias
+        })
+        .collect()
+}
+
+/// The hash of the model archive of the task, recorded in the task directory before the archive is extracted
+///
+/// # Arguments
+/// * `paths` - The paths of the miner serving the task
+///
+/// # Returns
+/// The hex encoded SHA-256 of the archive, `None` if `by-model` routes are disabled or the archive and its recorded
+/// hash are both gone
+pub async fn model_hash(paths: &Paths) -> Result<Option<String>> {
+    if !configured_aliases().contains(&RouteAlias::ByModel) {
+        return Ok(None);
+    }
+    let task_dir = PathBuf::from(&paths.task_dir_path);
+    let archive = task_dir.join(&paths.task_file_name);
+
+    // Hashing a multi-GB archive takes a while, the reactor keeps serving the other tasks meanwhile
+    run_blocking(move || {
+        let hash_path = task_dir.join(MODEL_HASH_FILE_NAME);
+        if !archive.is_file() {
+            return Ok(fs::read_to_string(hash_path)
+                .ok()
+                .map(|hash| hash.trim().to_string()));
+        }
+
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(&archive)?, &mut hasher)?;
+        let hash = hex::encode(hasher.finalize());
+        fs::write(hash_path, &hash)?;
+        Ok(Some(hash))
+    })
+    .await
+}
+
+/// Binds the enabled aliases to the task being served. An alias is bound to the owner of the first task served under
+/// it, or to `INFERENCE_ALIAS_OWNER` if it is set, and is only served for tasks of that owner, so that clients of one
+/// owner are never routed to the model of another. Deleting `route-aliases.json` next to the identity releases them.
+///
+/// # Arguments
+/// * `paths` - The paths of the miner serving the task
+/// * `model_hash` - The hash of the model archive, see `model_hash`
+///
+/// # Returns
+/// The aliases to serve the task under, relative to the base path, eg. `latest` and `by-model/<hash>`
+pub fn bind(paths: &Paths, model_hash: Option<&str>) -> Result<Vec<String>> {
+    let aliases = configured_aliases();
+    if aliases.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(task_owner) = schema::read_task_owner(&paths.task_owner_path)? else {
+        println!("The task owner is unknown, serving the task without route aliases");
+        return Ok(Vec::new());
+    };
+    let pinned_owner = match std::env::var("INFERENCE_ALIAS_OWNER") {
+        Ok(owner) if !owner.trim().is_empty() => Some(
+            owner
+                .trim()
+                .parse::<AccountId32>()
+                .map_err(|e| Error::Custom(format!("Invalid INFERENCE_ALIAS_OWNER: {}", e)))?,
+        ),
+        _ => None,
+    };
+
+    let bindings_path = bindings_path(paths);
+    let mut bindings = read_bindings(&bindings_path);
+    let owner = task_owner.address.to_string();
+    let pinned_owner = pinned_owner.map(|owner| owner.to_string());
+
+    let mut bound = Vec::new();
+    for alias in aliases {
+        let name = match (alias, model_hash) {
+            (RouteAlias::Latest, _) => "latest".to_string(),
+            (RouteAlias::ByModel, Some(hash)) => format!("by-model/{}", hash),
+            (RouteAlias::ByModel, None) => {
+                println!(
+                    "The hash of the model is unknown, serving the task without a by-model route"
+                );
+                continue;
+            }
+        };
+        match claim(&mut bindings, &name, &owner, pinned_owner.as_deref()) {
+            Ok(()) => bound.push(name),
+            Err(bound_owner) => println!(
+                "Not serving the task under '{}', the alias is bound to the task owner {}",
+                name, bound_owner
+            ),
+        }
+    }
+
+    write_bindings(&bindings_path, &bindings)?;
+    Ok(bound)
+}
+
+/// Claims an alias for the owner of a task
+///
+/// # Arguments
+/// * `bindings` - The owner every alias is bound to, an unbound alias is bound to `owner`
+/// * `name` - The alias
+/// * `owner` - The owner of the task to serve under the alias
+/// * `pinned_owner` - The only owner aliases may be bound to, if any
+///
+/// # Returns
+/// `Ok(())` if the task may be served under the alias, or the owner the alias is bound to otherwise
+fn claim(
+    bindings: &mut BTreeMap<String, String>,
+    name: &str,
+    owner: &str,
+    pinned_owner: Option<&str>,
+) -> std::result::Result<(), String> {
+    let bound_owner = pinned_owner
+        .or(bindings.get(name).map(String::as_str))
+        .unwrap_or(owner)
+        .to_string();
+    if bound_owner != owner {
+        return Err(bound_owner);
+    }
+
+    bindings.insert(name.to_string(), bound_owner);
+    Ok(())
+}
+
+fn bindings_path(paths: &Paths) -> PathBuf {
+    Path::new(&paths.identity_path)
+        .parent()
+        .map(|dir| dir.join(ALIASES_FILE_NAME))
+        .unwrap_or_else(|| PathBuf::from(ALIASES_FILE_NAME))
+}
+
+fn read_bindings(path: &Path) -> BTreeMap<String, String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Written to a temporary file first, so a crash never leaves a corrupt file
+fn write_bindings(path: &Path, bindings: &BTreeMap<String, String>) -> Result<()> {
+    let temporary_path = path.with_extension("json.tmp");
+    fs::write(&temporary_path, serde_json::to_string(bindings)?)?;
+    fs::rename(temporary_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_stay_with_the_owner_that_claimed_them() {
+        let mut bindings = BTreeMap::new();
+
+        assert_eq!(claim(&mut bindings, "latest", "alice", None), Ok(()));
+        assert_eq!(claim(&mut bindings, "latest", "alice", None), Ok(()));
+        assert_eq!(
+            claim(&mut bindings, "latest", "bob", None),
+            Err("alice".to_string())
+        );
+        assert_eq!(claim(&mut bindings, "by-model/ab12", "bob", None), Ok(()));
+
+        // A pinned owner takes precedence over earlier claims
+        assert_eq!(
+            claim(&mut bindings, "latest", "alice", Some("bob")),
+            Err("bob".to_string())
+        );
+        assert_eq!(claim(&mut bindings, "latest", "bob", Some("bob")), Ok(()));
+        assert_eq!(bindings.get("latest").map(String::as_str), Some("bob"));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::config;
+use crate::error::Result;
+use axum::http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Where the inference server serves its task. The base path is configurable with `INFERENCE_BASE_PATH`, so that a
+/// reverse proxy can forward a path prefix unchanged. `X-Forwarded-*` headers are only honored with
+/// `TRUST_FORWARDED_HEADERS=true`, as any client could set them when the server is reachable directly.
+#[derive(Debug, Clone)]
+pub struct InferenceRoutes {
+    base_path: String,
+    trust_forwarded: bool,
+}
+
+impl InferenceRoutes {
+    /// # Returns
+    /// The routes, or an `Error` if `TRUST_FORWARDED_HEADERS` is invalid
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(
+            &config::optional_env("INFERENCE_BASE_PATH", "/inference".to_string()),
+            config::security_env("TRUST_FORWARDED_HEADERS", false)?,
+        ))
+    }
+
+    /// # Arguments
+    /// * `base_path` - The path tasks are served under, eg. "/inference" or "" to serve them at the root
+    /// * `trust_forwarded` - Whether the server is only reachable through a proxy that sets `X-Forwarded-*` headers
+    pub fn new(base_path: &str, trust_forwarded: bool) -> Self {
+        let base_path = match base_path.trim().trim_matches('/') {
+            "" => String::new(),
+            path => format!("/{}", path),
+        };
+
+        Self {
+            base_path,
+            trust_forwarded,
+        }
+    }
+
+    pub fn task_path(&self, task_id: u64) -> String {
+        format!("{}/{}", self.base_path, task_id)
+    }
+
+    /// Where a stable alias of the task is served, eg. "latest" or "by-model/<hash>"
+    pub fn alias_path(&self, alias: &str) -> String {
+        format!("{}/{}", self.base_path, alias.trim_matches('/'))
+    }
+
+    pub fn metadata_path(&self, task_id: u64) -> String {
+        format!("{}/metadata", self.task_path(task_id))
+    }
+
+    pub fn audit_path(&self, task_id: u64) -> String {
+        format!("{}/audit", self.task_path(task_id))
+    }
+
+    pub fn pricing_path(&self, task_id: u64) -> String {
+        format!("{}/pricing", self.task_path(task_id))
+    }
+
+    /// Where the binary outputs of the task are served, each under its file name
+    pub fn artifacts_path(&self, task_id: u64) -> String {
+        format!("{}/artifacts", self.task_path(task_id))
+    }
+
+    /// The address connections are limited by. Proxies append the address they received a connection from to
+    /// `X-Forwarded-For`, so the last entry is the only one a client can't forge.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        self.forwarded(headers, "x-forwarded-for")
+            .and_then(|addresses| addresses.rsplit(',').next()?.trim().parse().ok())
+            .unwrap_or_else(|| peer.ip())
+    }
+
+    /// The URL clients connect to for the task, as the client of the request sees the server
+    ///
+    /// # Returns
+    /// The URL, or `None` if the request names no host
+    pub fn task_url(&self, headers: &HeaderMap, task_id: u64) -> Option<String> {
+        let host = self
+            .forwarded(headers, "x-forwarded-host")
+            .or_else(|| header(headers, "host"))?;
+        let scheme = match self.forwarded(headers, "x-forwarded-proto").as_deref() {
+            Some("https") | Some("wss") => "wss",
+            _ => "ws",
+        };
+        let prefix = self
+            .forwarded(headers, "x-forwarded-prefix")
+            .unwrap_or_default();
+
+        Some(format!(
+            "{}://{}{}{}",
+            scheme,
+            host,
+            prefix.trim_end_matches('/'),
+            self.task_path(task_id)
+        ))
+    }
+
+    fn forwarded(&self, headers: &HeaderMap, name: &str) -> Option<String> {
+        if self.trust_forwarded {
+            header(headers, name)
+        } else {
+            None
+        }
+    }
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn base_path_is_normalized() {
+        assert_eq!(
+            InferenceRoutes::new("/inference", false).task_path(7),
+            "/inference/7"
+        );
+        assert_eq!(
+            InferenceRoutes::new("miner/inference/", false).task_path(7),
+            "/miner/inference/7"
+        );
+        assert_eq!(
+            InferenceRoutes::new("/", false).metadata_path(7),
+            "/7/metadata"
+        );
+        assert_eq!(
+            InferenceRoutes::new("/inference/", false).alias_path("by-model/ab12"),
+            "/inference/by-model/ab12"
+        );
+    }
+
+    #[test]
+    fn forwarded_headers_are_only_honored_when_trusted() {
+        let headers = headers(&[
+            ("host", "127.0.0.1:3000"),
+            ("x-forwarded-host", "miner.example.com"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-prefix", "/gpu-1/"),
+            ("x-forwarded-for", "10.0.0.1, 203.0.113.9"),
+        ]);
+        let peer: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+
+        let trusted = InferenceRoutes::new("/inference", true);
+        assert_eq!(
+            trusted.task_url(&headers, 7).as_deref(),
+            Some("wss://miner.example.com/gpu-1/inference/7")
+        );
+        assert_eq!(
+            trusted.client_ip(&headers, peer),
+            "203.0.113.9".parse::<IpAddr>().unwrap()
+        );
+
+        let untrusted = InferenceRoutes::new("/inference", false);
+        assert_eq!(
+            untrusted.task_url(&headers, 7).as_deref(),
+            Some("ws://127.0.0.1:3000/inference/7")
+        );
+        assert_eq!(untrusted.client_ip(&headers, peer), peer.ip());
+    }
+}
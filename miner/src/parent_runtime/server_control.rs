@@ -1,6 +1,41 @@
 use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::Mutex;
-use tokio::sync::watch;
+use tokio::sync::{broadcast, watch};
 
-pub static SHUTDOWN_SENDER: Lazy<Mutex<Option<watch::Sender<bool>>>> =
-    Lazy::new(|| Mutex::new(None));
+/// Shutdown signals of the running inference servers, per task
+pub static SHUTDOWN_SENDERS: Lazy<Mutex<HashMap<u64, watch::Sender<bool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Addresses the inference servers are currently listening on, per task
+pub static BOUND_ADDRESSES: Lazy<Mutex<HashMap<u64, Vec<SocketAddr>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Proof progress events, forwarded to every websocket connected to the inference server of the task
+pub static PROOF_PROGRESS: Lazy<broadcast::Sender<String>> =
+    Lazy::new(|| broadcast::channel(16).0);
+
+/// Engine status of the running inference servers, per task, as shown by the admin API
+pub static ENGINE_STATUS: Lazy<Mutex<HashMap<u64, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The NeuroZK tasks with a running inference server, which can be proven on demand
+pub static NZK_TASKS: Lazy<Mutex<HashSet<u64>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Requests the inference servers received since they started, per task
+pub static REQUESTS_RECEIVED: Lazy<Mutex<HashMap<u64, u64>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Counts a request to the inference server of a task
+pub fn record_request(task_id: u64) {
+    *REQUESTS_RECEIVED.lock().unwrap().entry(task_id).or_insert(0) += 1;
+}
+
+/// Signals the inference server of a task to stop, returns whether one was running
+pub fn stop_inference_server(task_id: u64) -> bool {
+    match SHUTDOWN_SENDERS.lock().unwrap().get(&task_id) {
+        Some(sender) => sender.send(true).is_ok(),
+        None => false,
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{config, error::Result, utils::blocking::run_blocking};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// File in the task directory keeping the inference sessions of clients across restarts of the miner
+const SESSIONS_FILE_NAME: &str = "inference_sessions.json";
+
+/// Identifies this run of the miner, sessions of earlier runs lost whatever context the engine held for them
+static RUN_ID: Lazy<String> = Lazy::new(random_id);
+
+/// Connections of all tasks update their session files from several blocking threads
+static SESSIONS_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredSession {
+    /// The run of the miner the session was last used in
+    run_id: String,
+    /// Seconds since the Unix epoch
+    last_seen: u64,
+    /// Requests served in the session over all of its connections
+    served: u64,
+}
+
+/// The inference session of a connection, clients that connect with `?session=new` are given one and resume it
+/// after reconnecting with `?session=<id>`
+#[derive(Debug, Clone)]
+pub struct InferenceSession {
+    pub id: String,
+    /// Requests served in the session before this connection
+    pub served: u64,
+    /// The message announcing the session to the client
+    pub announcement: String,
+}
+
+impl InferenceSession {
+    /// Opens the session a client asked for. A session of this run of the miner is resumed. A session of an earlier
+    /// run, or one that expired after `SESSION_TTL_SECS` (default 3600), is replaced by a new one, and the client is
+    /// told that the context of the previous one is lost, so it resends the context of its conversation instead of
+    /// relying on it.
+    ///
+    /// # Arguments
+    /// * `task_dir` - The task directory the sessions are kept in
+    /// * `requested` - `new`, or the id of the session to resume
+    ///
+    /// # Returns
+    /// The opened `InferenceSession`, or an `Error` if the sessions can't be persisted
+    pub async fn open(task_dir: &Path, requested: &str) -> Result<Self> {
+        let task_dir = task_dir.to_path_buf();
+        let requested = requested.to_string();
+        run_blocking(move || Self::open_blocking(&task_dir, &requested)).await
+    }
+
+    fn open_blocking(task_dir: &Path, requested: &str) -> Result<Self> {
+        let _lock = SESSIONS_LOCK.lock().unwrap();
+        let mut sessions = read_sessions(task_dir);
+        let now = unix_now();
+
+        let previous = (requested != "new").then(|| sessions.get(requested).cloned());
+        let session = match previous {
+            Some(Some(stored)) if stored.run_id == *RUN_ID => Self {
+                id: requested.to_string(),
+                served: stored.served,
+                announcement: json!({
+                    "event": "inference_session",
+                    "session_id": requested,
+                    "resumed": true,
+                    "served": stored.served,
+                })
+                .to_string(),
+            },
+            previous => {
+                let id = random_id();
+                let context_lost = previous.map(|stored| match stored {
+                    Some(stored) => json!({
+                        "previous_session_id": requested,
+                        "reason": "miner_restarted",
+                        "last_seen": stored.last_seen,
+                        "served": stored.served,
+                    }),
+                    None => json!({
+                        "previous_session_id": requested,
+                        "reason": "unknown_session",
+                    }),
+                });
+                if context_lost.is_some() {
+                    // The stale session is replaced, resuming it again would announce the loss again
+                    sessions.remove(requested);
+                }
+
+                Self {
+                    announcement: json!({
+                        "event": "inference_session",
+                        "session_id": id,
+                        "resumed": false,
+                        "context_lost": context_lost,
+                    })
+                    .to_string(),
+                    id,
+                    served: 0,
+                }
+            }
+        };
+
+        sessions.insert(
+            session.id.clone(),
+            StoredSession {
+                run_id: RUN_ID.clone(),
+                last_seen: now,
+                served: session.served,
+            },
+        );
+        write_sessions(task_dir, &sessions)?;
+        Ok(session)
+    }
+
+    /// Records the requests served on a closed connection of the session
+    ///
+    /// # Arguments
+    /// * `task_dir` - The task directory the sessions are kept in
+    /// * `served` - The requests served on the connection
+    pub async fn close(&self, task_dir: &Path, served: u64) -> Result<()> {
+        let task_dir = task_dir.to_path_buf();
+        let id = self.id.clone();
+        let served = self.served + served;
+
+        run_blocking(move || {
+            let _lock = SESSIONS_LOCK.lock().unwrap();
+            let mut sessions = read_sessions(&task_dir);
+            sessions.insert(
+                id,
+                StoredSession {
+                    run_id: RUN_ID.clone(),
+                    last_seen: unix_now(),
+                    served,
+                },
+            );
+            write_sessions(&task_dir, &sessions)
+        })
+        .await
+    }
+}
+
+/// The sessions kept in the task directory, without the expired ones
+fn read_sessions(task_dir: &Path) -> HashMap<String, StoredSession> {
+    let ttl = config::optional_env("SESSION_TTL_SECS", 3600u64);
+    let now = unix_now();
+
+    fs::read_to_string(task_dir.join(SESSIONS_FILE_NAME))
+        .ok()
+        .and_then(|content| serde_json::from_str::<HashMap<String, StoredSession>>(&content).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, session)| now.saturating_sub(session.last_seen) <= ttl)
+        .collect()
+}
+
+/// Written to a temporary file first, so a crash never leaves a corrupt file
+fn write_sessions(task_dir: &Path, sessions: &HashMap<String, StoredSession>) -> Result<()> {
+    fs::create_dir_all(task_dir)?;
+    let path = task_dir.join(SESSIONS_FILE_NAME);
+    let temporary_path = path.with_extension("json.tmp");
+    fs::write(&temporary_path, serde_json::to_string(sessions)?)?;
+    fs::rename(temporary_path, path)?;
+    Ok(())
+}
+
+fn random_id() -> String {
+    let mut id = [0u8; 16];
+    OsRng.fill_bytes(&mut id);
+    hex::encode(id)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn sessions_of_earlier_runs_announce_their_lost_context() {
+        let task_dir =
+            std::env::temp_dir().join(format!("inference-sessions-{}", std::process::id()));
+
+        let session = InferenceSession::open(&task_dir, "new").await.unwrap();
+        session.close(&task_dir, 3).await.unwrap();
+        let resumed = InferenceSession::open(&task_dir, &session.id)
+            .await
+            .unwrap();
+        assert_eq!(resumed.id, session.id);
+        assert_eq!(resumed.served, 3);
+
+        // As if the miner restarted since
+        let mut sessions = read_sessions(&task_dir);
+        sessions.get_mut(&session.id).unwrap().run_id = "earlier-run".to_string();
+        write_sessions(&task_dir, &sessions).unwrap();
+
+        let replaced = InferenceSession::open(&task_dir, &session.id)
+            .await
+            .unwrap();
+        assert_ne!(replaced.id, session.id);
+        let announcement: Value = serde_json::from_str(&replaced.announcement).unwrap();
+        assert_eq!(announcement["resumed"], false);
+        assert_eq!(
+            announcement["context_lost"]["previous_session_id"],
+            session.id
+        );
+        assert_eq!(announcement["context_lost"]["reason"], "miner_restarted");
+
+        let unknown = InferenceSession::open(&task_dir, &session.id)
+            .await
+            .unwrap();
+        let announcement: Value = serde_json::from_str(&unknown.announcement).unwrap();
+        assert_eq!(announcement["context_lost"]["reason"], "unknown_session");
+
+        fs::remove_dir_all(task_dir).unwrap();
+    }
+}
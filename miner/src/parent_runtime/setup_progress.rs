@@ -0,0 +1,258 @@
+use crate::config;
+use crate::error::Error;
+use crate::events::{self, MinerEvent};
+use crate::utils::tx_builder::publish_setup_stage;
+use crate::utils::tx_queue::TxOutput;
+use neuro_zk_runtime::ExtractionReport;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use subxt_signer::sr25519::Keypair;
+
+/// Tasks whose setup timings are kept, the oldest ones are dropped
+const MAX_TIMED_SETUPS: usize = 16;
+
+/// The stages every task reached and when, to attribute the time of its setup
+static SETUP_CLOCKS: Lazy<Mutex<HashMap<u64, SetupClock>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Default)]
+struct SetupClock {
+    reached: Vec<(SetupStage, Instant)>,
+    extraction: Option<ExtractionTimings>,
+}
+
+/// The stages a task goes through between being scheduled and being served. Downloading archives and compiling
+/// circuits can take many minutes, reporting the stages keeps task owners watching the chain informed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SetupStage {
+    Downloading,
+    Extracting,
+    /// Setting up the circuit and SRS of NeuroZK tasks, or loading the model into Triton
+    Compiling,
+    Ready,
+    Failed,
+}
+
+/// Reports progress within a stage of the task setup, eg. how much of the archive is extracted. It is only printed
+/// and passed to the event hooks, a transaction per update would flood the chain.
+pub fn report_progress(keypair: &Keypair, task_id: u64, stage: SetupStage, detail: String) {
+    println!("Task {} setup stage {:?}: {}", task_id, stage, detail);
+    events::emit(
+        &keypair.public_key().to_account_id(),
+        MinerEvent::TaskSetup {
+            task_id,
+            stage,
+            detail: Some(detail),
+        },
+    );
+}
+
+/// Publishes the stage of the task setup on chain without waiting for the transaction, so that reporting never delays
+/// the setup itself. Disabled with `REPORT_SETUP_PROGRESS=false`, as every report is a transaction.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner setting up the task
+/// * `task_id` - The task being set up
+/// * `stage` - The stage the setup reached
+/// * `detail` - Context for the stage, eg. why the setup failed
+pub fn report(keypair: &Keypair, task_id: u64, stage: SetupStage, detail: Option<String>) {
+    println!("Task {} setup stage: {:?}", task_id, stage);
+    record_stage(task_id, stage);
+    events::emit(
+        &keypair.public_key().to_account_id(),
+        MinerEvent::TaskSetup {
+            task_id,
+            stage,
+            detail: detail.clone(),
+        },
+    );
+
+    if !config::optional_env("REPORT_SETUP_PROGRESS", true) {
+        return;
+    }
+
+    let keypair = keypair.clone();
+    tokio::spawn(async move {
+        let result = async {
+            let rx = config::get_tx_queue()?
+                .enqueue(move || {
+                    let keypair = keypair.clone();
+                    let detail = detail.clone();
+                    async move {
+                        publish_setup_stage(keypair, task_id, stage, detail.as_deref()).await?;
+                        Ok(TxOutput::Success)
+                    }
+                })
+                .await?;
+
+            rx.await
+                .map_err(|_| Error::Custom("Response channel dropped.".to_string()))?
+        }
+        .await;
+
+        if let Err(e) = result {
+            println!(
+                "Error reporting setup stage {:?} of task {}: {}",
+                stage, task_id, e
+            );
+        }
+    });
+}
+
+/// The time the setup of a task spent in one stage
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StageTiming {
+    pub stage: SetupStage,
+    /// Until the next stage was reached, or until now for the current stage. `ready` and `failed` end the setup and
+    /// take no time.
+    pub seconds: f64,
+}
+
+/// Where the extraction of a NeuroZK archive spent its time, see `neuro_zk_runtime::ExtractionReport`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExtractionTimings {
+    /// Throughputs measured before extracting in MB/s, `None` if the extraction wasn't tuned to the host
+    pub read_mbps: Option<f64>,
+    pub decompress_mbps: Option<f64>,
+    pub write_mbps: Option<f64>,
+    pub benchmark_seconds: Option<f64>,
+    pub read_buffer_bytes: usize,
+    pub write_buffer_bytes: usize,
+    /// Whether the files were written while the archive was decompressed further
+    pub pipelined: bool,
+    pub archive_bytes: u64,
+    pub extracted_bytes: u64,
+    pub decompress_seconds: f64,
+    pub write_seconds: f64,
+    pub total_seconds: f64,
+}
+
+impl From<&ExtractionReport> for ExtractionTimings {
+    fn from(report: &ExtractionReport) -> Self {
+        Self {
+            read_mbps: report.benchmark.map(|benchmark| benchmark.read_mbps),
+            decompress_mbps: report.benchmark.map(|benchmark| benchmark.decode_mbps),
+            write_mbps: report.benchmark.map(|benchmark| benchmark.write_mbps),
+            benchmark_seconds: report
+                .benchmark
+                .map(|benchmark| benchmark.elapsed.as_secs_f64()),
+            read_buffer_bytes: report.tuning.read_buffer,
+            write_buffer_bytes: report.tuning.write_buffer,
+            pipelined: report.tuning.pipelined,
+            archive_bytes: report.archive_bytes,
+            extracted_bytes: report.extracted_bytes,
+            decompress_seconds: report.decode.as_secs_f64(),
+            write_seconds: report.write.as_secs_f64(),
+            total_seconds: report.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+/// Where the setup of a task spent its time, served by the admin API to see where startups of several minutes go
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaskSetupTimings {
+    pub task_id: u64,
+    /// In the order the stages were reached, a stage reached again (eg. a download after a promotion) is listed again
+    pub stages: Vec<StageTiming>,
+    pub extraction: Option<ExtractionTimings>,
+}
+
+fn record_stage(task_id: u64, stage: SetupStage) {
+    let mut clocks = SETUP_CLOCKS.lock().unwrap();
+    if !clocks.contains_key(&task_id) && clocks.len() >= MAX_TIMED_SETUPS {
+        let oldest = clocks
+            .iter()
+            .min_by_key(|(_, clock)| clock.reached.first().map(|(_, at)| *at))
+            .map(|(task_id, _)| *task_id);
+        if let Some(oldest) = oldest {
+            clocks.remove(&oldest);
+        }
+    }
+
+    let reached = &mut clocks.entry(task_id).or_default().reached;
+    // Repeated reports of a stage, eg. a degraded engine that is ready, don't start it again
+    if reached.last().map(|(last, _)| *last) != Some(stage) {
+        reached.push((stage, Instant::now()));
+    }
+}
+
+/// Records where the extraction of the archive of a task spent its time
+///
+/// # Arguments
+/// * `task_id` - The task whose archive was extracted
+/// * `report` - The report of the extraction
+pub fn record_extraction(task_id: u64, report: &ExtractionReport) {
+    SETUP_CLOCKS
+        .lock()
+        .unwrap()
+        .entry(task_id)
+        .or_default()
+        .extraction = Some(report.into());
+}
+
+/// Where the setups of the latest tasks spent their time, ordered by task
+pub fn timings() -> Vec<TaskSetupTimings> {
+    let clocks = SETUP_CLOCKS.lock().unwrap();
+    let now = Instant::now();
+    let mut timings: Vec<TaskSetupTimings> = clocks
+        .iter()
+        .map(|(task_id, clock)| TaskSetupTimings {
+            task_id: *task_id,
+            stages: stage_timings(&clock.reached, now),
+            extraction: clock.extraction.clone(),
+        })
+        .collect();
+    timings.sort_by_key(|timing| timing.task_id);
+    timings
+}
+
+fn stage_timings(reached: &[(SetupStage, Instant)], now: Instant) -> Vec<StageTiming> {
+    reached
+        .iter()
+        .enumerate()
+        .map(|(index, (stage, at))| {
+            let until = match reached.get(index + 1) {
+                Some((_, next)) => *next,
+                None if matches!(stage, SetupStage::Ready | SetupStage::Failed) => *at,
+                None => now,
+            };
+            StageTiming {
+                stage: *stage,
+                seconds: until.duration_since(*at).as_secs_f64(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn stages_last_until_the_next_one_is_reached() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let reached = [
+            (SetupStage::Downloading, at(0)),
+            (SetupStage::Extracting, at(90)),
+            (SetupStage::Compiling, at(150)),
+            (SetupStage::Ready, at(400)),
+        ];
+
+        let seconds: Vec<f64> = stage_timings(&reached, at(1000))
+            .iter()
+            .map(|timing| timing.seconds)
+            .collect();
+        assert_eq!(seconds, vec![90.0, 60.0, 250.0, 0.0]);
+
+        // The current stage counts until now
+        let timings = stage_timings(&reached[..2], at(100));
+        assert_eq!(timings[1].stage, SetupStage::Extracting);
+        assert_eq!(timings[1].seconds, 10.0);
+    }
+}
@@ -1,5 +1,11 @@
-use crate::config::{self/* , CESS_GATEWAY*/, PATHS};
+use crate::config::{self/* , CESS_GATEWAY, PATHS*/};
 use crate::error::{Error, Result};
+use crate::parachain_interactor::task_deadlines::{self, TaskDeadline};
+use crate::parachain_interactor::task_identifier::TaskIdentifier;
+use crate::parent_runtime::integrity;
+use crate::parent_runtime::model_retention;
+use crate::traits::ChainApi;
+use crate::utils::fault_injection::{self, Fault};
 //use cess_rust_sdk::gateway::file::{download, download_encrypt};
 //use cess_rust_sdk::polkadot::runtime_apis::asset_conversion_api::types::get_reserves::output;
 //use cess_rust_sdk::subxt::ext::sp_core::{sr25519::Pair as PairS, Pair};
@@ -7,9 +13,11 @@ use crate::error::{Error, Result};
 //use cess_rust_sdk::utils::str::get_random_code;
 //use tracing::info;
 use futures_util::StreamExt;
-use reqwest::Client;
+use reqwest::{header, Client, StatusCode};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use tokio::fs::File;
+use std::time::{Duration, Instant};
+use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWriteExt;
 use std::fs;
 
@@ -57,51 +65,462 @@ pub async fn download_model_archive(cess_fid: &str, cipher: &str) -> Result<()>
 }
 */ 
 
-pub async fn download_model_archive(storage_identifier: &str, _cipher: &str) -> Result<()> {
+/// File in the task directory that records where the archive of the task is downloaded from
+const TASK_SOURCE_FILE_NAME: &str = "task_source.json";
+/// Attempts of a download, each one resumes where the previous one stopped
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 3;
+/// Attempts of the `HEAD` request probing the size of an archive, if the store fails transiently
+const MAX_PROBE_ATTEMPTS: u32 = 3;
+/// Delay before the first retry of a failed probe or download, doubled for every further one
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Where the archive of a task is downloaded from, captured when the task is assigned. A download keeps the storage
+/// location it started with, unless it fails and the operator configured another location meanwhile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskSource {
+    pub storage_location: String,
+    pub storage_identifier: String,
+}
+
+impl TaskSource {
+    /// The URL the archive is downloaded from, identifiers that are URLs themselves don't depend on the location
+    pub fn archive_url(&self) -> String {
+        if let Ok(TaskIdentifier::Url(url)) = TaskIdentifier::parse(&self.storage_identifier) {
+            return url;
+        }
+        format!(
+            "{}/{}",
+            self.storage_location.trim_end_matches('/'),
+            self.storage_identifier
+        )
+    }
+}
+
+/// The storage location as configured right now. Like at startup, a `STORAGE_LOCATION` the process was started with
+/// takes precedence. Otherwise `.env` is read again, so edits of the operator take effect for the next assignment and
+/// for interrupted downloads without a restart.
+fn configured_storage_location() -> Result<String> {
+    if let Some(location) = config::process_env("STORAGE_LOCATION") {
+        return Ok(location);
+    }
+
+    let edited = dotenv::dotenv_iter().ok().and_then(|entries| {
+        entries
+            .filter_map(|entry| entry.ok())
+            .find(|(key, _)| key == "STORAGE_LOCATION")
+            .map(|(_, location)| location)
+    });
+
+    match edited {
+        Some(location) => Ok(location),
+        None => Ok(config::get_storage_location()?.clone()),
+    }
+}
+
+/// Captures the storage location a newly assigned task is downloaded from
+///
+/// # Arguments
+/// * `task_dir` - The task directory the source is recorded in
+/// * `storage_identifier` - The identifier of the task archive
+///
+/// # Returns
+/// The captured `TaskSource`, or an `Error` if no storage location is configured or it can't be recorded
+pub fn capture_task_source(task_dir: &str, storage_identifier: &str) -> Result<TaskSource> {
+    let source = TaskSource {
+        storage_location: configured_storage_location()?,
+        storage_identifier: storage_identifier.to_string(),
+    };
+    write_task_source(task_dir, &source)?;
+    Ok(source)
+}
+
+/// Records the source of the task archive in the task directory
+pub fn write_task_source(task_dir: &str, source: &TaskSource) -> Result<()> {
+    fs::create_dir_all(task_dir)?;
+    fs::write(
+        Path::new(task_dir).join(TASK_SOURCE_FILE_NAME),
+        serde_json::to_string(source)?,
+    )?;
+    Ok(())
+}
+
+/// The source the task archive was captured with, `None` before the task was assigned
+pub fn read_task_source(task_dir: &str) -> Result<Option<TaskSource>> {
+    let path = Path::new(task_dir).join(TASK_SOURCE_FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+}
+
+/// Re-resolves the source of an interrupted download against the storage location configured now. Only plain
+/// identifiers are re-resolved, identifiers carrying a scheme or an absolute path are specific to the location they
+/// were issued for.
+///
+/// # Returns
+/// The source at the new location, or `None` if the location didn't change or the identifier can't move with it
+pub fn migrate_task_source(source: &TaskSource, new_location: &str) -> Option<TaskSource> {
+    let identifier = &source.storage_identifier;
+    let compatible = !identifier.contains("://") && !identifier.starts_with('/');
+    if !compatible
+        || new_location.trim_end_matches('/') == source.storage_location.trim_end_matches('/')
+    {
+        return None;
+    }
+
+    Some(TaskSource {
+        storage_location: new_location.to_string(),
+        storage_identifier: identifier.clone(),
+    })
+}
+
+pub async fn download_model_archive(
+    chain: &dyn ChainApi,
+    task_id: u64,
+    storage_identifier: &str,
+    _cipher: &str,
+) -> Result<()> {
+    fault_injection::inject(Fault::StorageDownload)?;
+
     let (task_file_name, task_dir_path) = {
-        let paths = &PATHS.get()
-        .ok_or(Error::config_paths_not_initialized())?;
+        let paths = config::get_paths()?;
 
         (&paths.task_file_name, &paths.task_dir_path)
     };
     std::fs::create_dir_all(task_dir_path)?;
 
-    let base_storage_location = config::get_storage_location()?;
-    let blob_url = format!("{}/{}", base_storage_location, storage_identifier);
-    println!("Downloading model archive from: {}", blob_url);
-    
+    // Tasks are captured when they are assigned, only a download started some other way captures them here
+    let mut source = match read_task_source(task_dir_path)? {
+        Some(source) if source.storage_identifier == storage_identifier => source,
+        _ => capture_task_source(task_dir_path, storage_identifier)?,
+    };
+
     let output_path = format!("{}/{}", task_dir_path, task_file_name);
+    let file_path = Path::new(&output_path);
+    let client = config::http_client()?;
+
+    // A retained archive was verified for the task it was downloaded for, it must be signed for this task too
+    if model_retention::restore(config::get_paths()?, storage_identifier)? {
+        if let Err(e) = verify_signature(chain, &client, task_id, &source.archive_url(), file_path).await {
+            fs::remove_file(file_path)?;
+            return Err(e);
+        }
+        return Ok(());
+    }
+
     println!("Saving model archive to: {}", output_path);
+    // Only attempts of this download resume, never a leftover of another one
+    if file_path.exists() {
+        fs::remove_file(file_path)?;
+    }
 
-    let client = Client::new();
+    let mut attempt = 1;
+    loop {
+        println!("Downloading model archive from: {}", source.archive_url());
+        let size = probe_size(&client, &source.archive_url()).await;
+        let download = tokio::select! {
+            download = download_to(&client, &source.archive_url(), file_path, size) => download,
+            reason = task_deadlines::missed_download(file_path, size) => {
+                // An archive that can't be set up in time isn't worth its disk space
+                fs::remove_file(file_path).ok();
+                return Err(Error::DeadlineMissed(reason));
+            }
+        };
+        match download {
+            Ok(()) => break,
+            Err(e) => {
+                let migrated = configured_storage_location()
+                    .ok()
+                    .and_then(|location| migrate_task_source(&source, &location));
+                if let Some(migrated) = migrated {
+                    println!(
+                        "Download from {} failed ({}), STORAGE_LOCATION changed to {} since the task was assigned, resuming there",
+                        source.storage_location, e, migrated.storage_location
+                    );
+                    write_task_source(task_dir_path, &migrated)?;
+                    source = migrated;
+                } else if attempt >= MAX_DOWNLOAD_ATTEMPTS {
+                    return Err(Error::Custom(format!(
+                        "Download of {} from the storage location {} captured at assignment failed: {}",
+                        source.storage_identifier, source.storage_location, e
+                    )));
+                } else {
+                    let delay = retry_delay(attempt);
+                    if let Some(deadline) = TaskDeadline::Setup.current() {
+                        if Instant::now() + delay >= deadline {
+                            fs::remove_file(file_path).ok();
+                            return Err(Error::DeadlineMissed(format!(
+                                "The download can't be retried before the setup deadline: {}",
+                                e
+                            )));
+                        }
+                    }
+                    println!(
+                        "Download attempt {} failed, resuming in {} ms: {}",
+                        attempt,
+                        delay.as_millis(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                attempt += 1;
+            }
+        }
+    }
+
+    tracing::info!("✅ Model successfully retrieved!");
+
+    if let Err(e) = verify_signature(chain, &client, task_id, &source.archive_url(), file_path).await {
+        // Remove the archive so that an unverified model can never be set up
+        fs::remove_file(file_path)?;
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY * 2u32.pow(attempt.saturating_sub(1).min(10))
+}
+
+/// Probes the size of the archive, so that a download that ends early is resumed instead of being set up truncated.
+/// `HEAD` is tried first and retried with backoff if the store fails transiently. Stores that block `HEAD` or omit
+/// `Content-Length` are asked for the first byte with a ranged `GET`, the total size is in its `Content-Range`.
+///
+/// # Returns
+/// The size in bytes, or `None` if the store doesn't tell it. The archive is then streamed until the store ends it.
+async fn probe_size(client: &Client, blob_url: &str) -> Option<u64> {
+    let mut attempt = 1;
+    loop {
+        let transient = match client.head(blob_url).send().await {
+            Ok(response) if response.status().is_success() => {
+                match content_length(response.headers()) {
+                    Some(size) => return Some(size),
+                    None => break,
+                }
+            }
+            Ok(response) => is_transient_status(response.status()),
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+        if !transient || attempt >= MAX_PROBE_ATTEMPTS {
+            break;
+        }
+        tokio::time::sleep(retry_delay(attempt)).await;
+        attempt += 1;
+    }
+
+    // The body is never read, dropping the response closes the connection should the store ignore the range
     let response = client
         .get(blob_url)
+        .header(header::RANGE, "bytes=0-0")
         .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(Error::Custom(format!("Failed to download blob: {}", response.status())));
+        .await
+        .ok()?;
+    let size = match response.status() {
+        StatusCode::PARTIAL_CONTENT => response
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(content_range_total),
+        status if status.is_success() => content_length(response.headers()),
+        _ => None,
+    };
+    if size.is_none() {
+        println!(
+            "The storage doesn't tell the size of {}, downloading it without",
+            blob_url
+        );
     }
+    size
+}
 
-    if !fs::metadata(&task_dir_path).is_ok() {
-        return Err(Error::Custom(format!("Directory does not exist: {}", task_dir_path)));
+fn content_length(headers: &header::HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// The total size of a `Content-Range` like `bytes 0-0/1234`, `None` if it is unknown (`bytes 0-0/*`)
+fn content_range_total(content_range: &str) -> Option<u64> {
+    content_range.rsplit_once('/')?.1.trim().parse().ok()
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Downloads `blob_url` into `file_path`, resuming after the bytes a previous attempt already wrote if the storage
+/// supports range requests
+///
+/// # Arguments
+/// * `size` - The probed size of the archive, a download that ends before it fails so that it is resumed
+async fn download_to(
+    client: &Client,
+    blob_url: &str,
+    file_path: &Path,
+    size: Option<u64>,
+) -> Result<()> {
+    let written = fs::metadata(file_path).map(|metadata| metadata.len()).unwrap_or(0);
+    if written > 0 && size == Some(written) {
+        return Ok(());
+    }
+    let mut request = client.get(blob_url);
+    if written > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", written));
     }
+    let response = request.send().await?;
 
-    let mut stream = response.bytes_stream();
-    let file_path = Path::new(&output_path);
+    // Without a known size, the previous attempt may have ended exactly at the end of the archive
+    if written > 0 && size.is_none() && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(());
+    }
+    if !response.status().is_success() {
+        return Err(Error::Custom(format!("Failed to download blob: {}", response.status())));
+    }
 
-    println!("File path: {}", file_path.display());
-    let mut file = File::create(&file_path)
-        .await?;
+    // Storages without range support send the whole archive again
+    let mut file = if response.status() == StatusCode::PARTIAL_CONTENT {
+        OpenOptions::new().append(true).open(file_path).await?
+    } else {
+        File::create(file_path).await?
+    };
 
     tracing::info!("Starting model download...");
 
+    let mut stream = response.bytes_stream();
     while let Some(chunk_result) = stream.next().await {
         let chunk = chunk_result?;
         file.write_all(&chunk)
             .await?
     }
 
-    tracing::info!("✅ Model successfully retrieved!");
+    file.flush().await?;
+
+    if let Some(size) = size {
+        let downloaded = file.metadata().await?.len();
+        if downloaded != size {
+            return Err(Error::Custom(format!(
+                "Download ended after {} of {} bytes",
+                downloaded, size
+            )));
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Fetches the gatekeeper signature that is published next to the archive (`<archive>.sig`) and verifies it for the
+/// task. Whoever can swap the archive can also remove its signature, so an archive without a valid signature is
+/// rejected, unless the operator disabled the check with `REQUIRE_ARCHIVE_SIGNATURE=false`.
+async fn verify_signature(
+    chain: &dyn ChainApi,
+    client: &Client,
+    task_id: u64,
+    blob_url: &str,
+    archive_path: &Path,
+) -> Result<()> {
+    if !config::security_env("REQUIRE_ARCHIVE_SIGNATURE", true)? {
+        tracing::warn!("REQUIRE_ARCHIVE_SIGNATURE=false, serving the archive without verifying its signature");
+        return Ok(());
+    }
+
+    let response = client
+        .get(format!("{}.sig", blob_url))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(Error::Custom(format!(
+            "Archive signature not found at {}.sig: {}",
+            blob_url,
+            response.status()
+        )));
+    }
+
+    let signature_hex = response.text().await?;
+
+    integrity::verify_archive_signature(chain, task_id, archive_path, signature_hex.trim()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_relative_identifiers_move_with_the_storage_location() {
+        let source = TaskSource {
+            storage_location: "https://old.example.com/blobs".to_string(),
+            storage_identifier: "model-7.tar.gz".to_string(),
+        };
+
+        let migrated = migrate_task_source(&source, "https://new.example.com/blobs/").unwrap();
+        assert_eq!(
+            migrated.archive_url(),
+            "https://new.example.com/blobs/model-7.tar.gz"
+        );
+        assert_eq!(migrate_task_source(&source, "https://old.example.com/blobs/"), None);
+
+        let absolute = TaskSource {
+            storage_identifier: "https://mirror.example.com/model-7.tar.gz".to_string(),
+            ..source
+        };
+        assert_eq!(migrate_task_source(&absolute, "https://new.example.com/blobs"), None);
+        assert_eq!(
+            absolute.archive_url(),
+            "https://mirror.example.com/model-7.tar.gz"
+        );
+    }
+
+    #[test]
+    fn sizes_are_read_from_content_ranges() {
+        assert_eq!(content_range_total("bytes 0-0/7340032"), Some(7340032));
+        assert_eq!(content_range_total("bytes 0-0/*"), None);
+        assert_eq!(content_range_total("garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_download_resumes_at_the_migrated_location() {
+        const ARCHIVE: &[u8] = b"model archive, the first half came from the old storage location";
+        let app = axum::Router::new().route(
+            "/blobs/model-7.tar.gz",
+            axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                let start = headers
+                    .get(header::RANGE)
+                    .and_then(|range| range.to_str().ok())
+                    .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+                match start {
+                    Some(start) => (StatusCode::PARTIAL_CONTENT, ARCHIVE[start..].to_vec()),
+                    None => (StatusCode::OK, ARCHIVE.to_vec()),
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let dir = std::env::temp_dir().join("cyborg-resumed-download-test");
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("model.tar.gz");
+        fs::write(&file_path, &ARCHIVE[..ARCHIVE.len() / 2]).unwrap();
+
+        let source = TaskSource {
+            storage_location: "http://old.invalid/blobs".to_string(),
+            storage_identifier: "model-7.tar.gz".to_string(),
+        };
+        let migrated = migrate_task_source(&source, &format!("http://{}/blobs", address)).unwrap();
+        download_to(&Client::new(), &migrated.archive_url(), &file_path, Some(ARCHIVE.len() as u64))
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), ARCHIVE);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
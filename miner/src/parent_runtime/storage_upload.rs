@@ -0,0 +1,255 @@
+use crate::{
+    config,
+    error::{Error, Result},
+};
+use futures::future::BoxFuture;
+use reqwest::{header, multipart, Client, Response};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Identifiers longer than this don't fit into the remarks and extrinsics they are submitted with
+pub const MAX_IDENTIFIER_BYTES: usize = 256;
+
+/// Where an upload ended up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    /// What is submitted on chain, the CID of content addressed backends or else the URL
+    pub id: String,
+    /// Where the object can be fetched from, if the backend knows
+    pub url: Option<String>,
+}
+
+/// A storage the miner uploads results, audit digests and logs to
+pub trait StorageBackend: Send + Sync {
+    /// Uploads an object.
+    ///
+    /// # Arguments
+    /// * `name` - The path of the object, eg. `results/1-<hash>.jsonl`
+    /// * `content_type` - The content type of the object
+    /// * `bytes` - The content
+    ///
+    /// # Returns
+    /// Where the object was stored, or an `Error` if the upload failed
+    fn upload<'a>(
+        &'a self,
+        name: &'a str,
+        content_type: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<StoredObject>>;
+}
+
+/// The backend uploads go to as configured by `STORAGE_UPLOAD`:
+/// - `put` (default): A `PUT` to `STORAGE_UPLOAD_URL`, by default the storage location, gateways of content addressed
+///   storage answer with the `cid` of the upload
+/// - `presigned`: `STORAGE_PRESIGN_URL` is asked for a presigned URL per object, which is then uploaded to with a `PUT`
+/// - `ipfs`: Added and pinned through the API of an IPFS node at `IPFS_API_URL` (default `http://127.0.0.1:5001`),
+///   their URLs point to `IPFS_GATEWAY_URL` if it is set
+///
+/// # Returns
+/// The backend, or an `Error` if the upload setting is invalid
+pub fn backend_from_env() -> Result<Arc<dyn StorageBackend>> {
+    let client = config::http_client()?;
+    match config::optional_env("STORAGE_UPLOAD", "put".to_string()).as_str() {
+        "put" => {
+            let base_url = match std::env::var("STORAGE_UPLOAD_URL") {
+                Ok(url) => url,
+                Err(_) => config::get_storage_location()?.clone(),
+            };
+            Ok(Arc::new(PutUpload::new(client, &base_url)))
+        }
+        "presigned" => {
+            let presign_url = std::env::var("STORAGE_PRESIGN_URL").map_err(|_| {
+                Error::Custom("STORAGE_UPLOAD=presigned requires STORAGE_PRESIGN_URL".to_string())
+            })?;
+            Ok(Arc::new(PresignedUpload {
+                client,
+                presign_url,
+            }))
+        }
+        "ipfs" => Ok(Arc::new(IpfsUpload {
+            client,
+            api_url: config::optional_env("IPFS_API_URL", "http://127.0.0.1:5001".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            gateway_url: std::env::var("IPFS_GATEWAY_URL")
+                .ok()
+                .map(|url| url.trim_end_matches('/').to_string()),
+        })),
+        other => Err(Error::Custom(format!(
+            "Invalid STORAGE_UPLOAD '{}', expected put, presigned or ipfs",
+            other
+        ))),
+    }
+}
+
+/// Uploads with a plain `PUT` below a base URL
+pub struct PutUpload {
+    client: Client,
+    base_url: String,
+}
+
+impl PutUpload {
+    pub fn new(client: Client, base_url: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl StorageBackend for PutUpload {
+    fn upload<'a>(
+        &'a self,
+        name: &'a str,
+        content_type: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<StoredObject>> {
+        Box::pin(async move {
+            let url = format!("{}/{}", self.base_url, name);
+            let response = put(&self.client, &url, content_type, bytes).await?;
+
+            // Content addressed backends answer with the id of the upload
+            let cid = response
+                .json::<Value>()
+                .await
+                .ok()
+                .and_then(|body| body["cid"].as_str().map(str::to_string));
+            stored(cid.unwrap_or_else(|| url.clone()), Some(url))
+        })
+    }
+}
+
+/// Uploads to URLs presigned by a service of the storage operator, so the miner holds no storage credentials
+struct PresignedUpload {
+    client: Client,
+    presign_url: String,
+}
+
+impl StorageBackend for PresignedUpload {
+    fn upload<'a>(
+        &'a self,
+        name: &'a str,
+        content_type: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<StoredObject>> {
+        Box::pin(async move {
+            let request = self.client.post(&self.presign_url).json(&json!({
+                "name": name,
+                "content_type": content_type,
+                "size": bytes.len(),
+            }));
+            let presigned: Value = config::send_with_retries(request)
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let Some(upload_url) = presigned["url"].as_str() else {
+                return Err(Error::Custom(format!(
+                    "The presign service returned no url for {}",
+                    name
+                )));
+            };
+
+            put(&self.client, upload_url, content_type, bytes).await?;
+
+            // The presigned URL expires, the service names the object the way it is fetched
+            let id = presigned["id"].as_str().unwrap_or(name).to_string();
+            let url = presigned["download_url"].as_str().map(str::to_string);
+            stored(id, url)
+        })
+    }
+}
+
+/// Adds and pins uploads on an IPFS node
+struct IpfsUpload {
+    client: Client,
+    api_url: String,
+    gateway_url: Option<String>,
+}
+
+impl StorageBackend for IpfsUpload {
+    fn upload<'a>(
+        &'a self,
+        name: &'a str,
+        content_type: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<StoredObject>> {
+        Box::pin(async move {
+            let file_name = name.rsplit('/').next().unwrap_or(name).to_string();
+            let part = multipart::Part::bytes(bytes)
+                .file_name(file_name)
+                .mime_str(content_type)?;
+            let added: Value = self
+                .client
+                .post(format!(
+                    "{}/api/v0/add?pin=true&cid-version=1",
+                    self.api_url
+                ))
+                .multipart(multipart::Form::new().part("file", part))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let Some(cid) = added["Hash"].as_str() else {
+                return Err(Error::Custom(format!(
+                    "The IPFS node returned no CID for {}",
+                    name
+                )));
+            };
+
+            let url = match &self.gateway_url {
+                Some(gateway) => format!("{}/ipfs/{}", gateway, cid),
+                None => format!("ipfs://{}", cid),
+            };
+            stored(cid.to_string(), Some(url))
+        })
+    }
+}
+
+async fn put(client: &Client, url: &str, content_type: &str, bytes: Vec<u8>) -> Result<Response> {
+    let request = client
+        .put(url)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(bytes);
+    let response = config::send_with_retries(request).await?;
+    if !response.status().is_success() {
+        return Err(Error::Custom(format!(
+            "Upload to {} failed: {}",
+            url,
+            response.status()
+        )));
+    }
+    Ok(response)
+}
+
+fn stored(id: String, url: Option<String>) -> Result<StoredObject> {
+    if id.len() > MAX_IDENTIFIER_BYTES {
+        return Err(Error::Custom(format!(
+            "The storage identifier {} is longer than the {} bytes an on-chain submission holds",
+            id, MAX_IDENTIFIER_BYTES
+        )));
+    }
+    Ok(StoredObject { id, url })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_must_fit_into_a_submission() {
+        let url = format!(
+            "https://storage.example/{}",
+            "a".repeat(MAX_IDENTIFIER_BYTES)
+        );
+        assert!(stored(url, None).is_err());
+        assert_eq!(
+            stored("bafy".to_string(), None).unwrap(),
+            StoredObject {
+                id: "bafy".to_string(),
+                url: None
+            }
+        );
+    }
+}
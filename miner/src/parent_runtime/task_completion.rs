@@ -0,0 +1,231 @@
+use crate::{
+    config,
+    error::{Error, Result},
+    events::{self, MinerEvent},
+    parent_runtime::storage_upload,
+    utils::{blocking::run_blocking, tx_builder::submit_completed_task, tx_queue::TxOutput},
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use subxt::utils::AccountId32;
+use subxt_signer::sr25519::Keypair;
+
+/// The results of a finite task, one JSON line per response, written to the task directory until they are uploaded
+const RESULTS_PATH: &str = "results.jsonl";
+
+/// Set in the manifest of batch jobs over a dataset, which are completed once their input is processed instead of
+/// being served until they are stopped. The task completes once `expected_results` responses were served, or when
+/// a client sends `{"command":"finish"}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FiniteTask {
+    #[serde(default)]
+    pub expected_results: Option<u64>,
+}
+
+/// What the miner submitted for a completed task
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletedTask {
+    pub task_id: u64,
+    /// Hex encoded SHA-256 of the uploaded results
+    pub result_hash: String,
+    /// The storage identifier of the uploaded results, see `StoredObject`
+    pub result: String,
+    pub result_count: u64,
+}
+
+#[derive(Default)]
+struct TaskResults {
+    count: u64,
+    completed: Option<CompletedTask>,
+}
+
+/// Results recorded per serving miner and task, the results themselves are kept on disk
+static TASK_RESULTS: Lazy<Mutex<HashMap<(AccountId32, u64), TaskResults>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Completions are uploaded and submitted one at a time, so a finish racing the last expected result submits once
+static COMPLETING: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Appends a response to the results of a finite task
+///
+/// # Arguments
+/// * `miner` - The account of the miner serving the task
+/// * `task_id` - The task the response was served for
+/// * `task_dir` - The task directory the results are written to
+/// * `finite` - The completion settings of the task
+/// * `response` - The response as sent to the client
+///
+/// # Returns
+/// Whether the task received all of its expected results with this one, or an `Error` if it can't be written
+pub fn record_result(
+    miner: &AccountId32,
+    task_id: u64,
+    task_dir: &Path,
+    finite: &FiniteTask,
+    response: &str,
+) -> Result<bool> {
+    let mut task_results = TASK_RESULTS.lock().unwrap();
+    let results = task_results.entry((miner.clone(), task_id)).or_default();
+    if results.completed.is_some() {
+        return Ok(false);
+    }
+
+    // Written under the lock, so that lines of concurrent connections don't interleave
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(results_path(task_dir))?;
+    let line = serde_json::from_str::<Value>(response).unwrap_or_else(|_| json!(response));
+    writeln!(file, "{}", line)?;
+    results.count += 1;
+
+    Ok(finite.expected_results == Some(results.count))
+}
+
+/// Whether the task was completed, completed tasks don't accept requests anymore
+pub fn is_completed(miner: &AccountId32, task_id: u64) -> bool {
+    TASK_RESULTS
+        .lock()
+        .unwrap()
+        .get(&(miner.clone(), task_id))
+        .is_some_and(|results| results.completed.is_some())
+}
+
+/// Completes a finite task: uploads its results to the storage location and submits their hash on chain. Completing
+/// a task again returns what was submitted the first time.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner serving the task
+/// * `task_id` - The task to complete
+/// * `task_dir` - The task directory the results were written to
+///
+/// # Returns
+/// The submitted completion, or an `Error` if the results can't be uploaded or the submission fails
+pub async fn complete_task(
+    keypair: &Keypair,
+    task_id: u64,
+    task_dir: &Path,
+) -> Result<CompletedTask> {
+    let _completing = COMPLETING.lock().await;
+    let miner = keypair.public_key().to_account_id();
+    let result_count = {
+        let task_results = TASK_RESULTS.lock().unwrap();
+        let results = task_results.get(&(miner.clone(), task_id));
+        if let Some(completed) = results.and_then(|results| results.completed.clone()) {
+            return Ok(completed);
+        }
+        results.map(|results| results.count).unwrap_or(0)
+    };
+
+    let path = results_path(task_dir);
+    let results = run_blocking(move || match fs::read(&path) {
+        Ok(results) => Ok(results),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    })
+    .await?;
+    let result_hash = hex::encode(Sha256::digest(&results));
+    let result = upload_results(task_id, &result_hash, results).await?;
+    println!(
+        "Uploaded {} results of task {} to {}",
+        result_count, task_id, result
+    );
+
+    let completed = CompletedTask {
+        task_id,
+        result_hash,
+        result,
+        result_count,
+    };
+    let submission = completed.clone();
+    let keypair = keypair.clone();
+    let rx = config::get_tx_queue()?
+        .enqueue(move || {
+            let keypair = keypair.clone();
+            let submission = submission.clone();
+            async move {
+                submit_completed_task(
+                    keypair,
+                    submission.task_id,
+                    &submission.result_hash,
+                    &submission.result,
+                )
+                .await?;
+                Ok(TxOutput::Success)
+            }
+        })
+        .await?;
+    rx.await
+        .map_err(|_| Error::Custom("Response channel dropped.".to_string()))??;
+
+    TASK_RESULTS
+        .lock()
+        .unwrap()
+        .entry((miner.clone(), task_id))
+        .or_default()
+        .completed = Some(completed.clone());
+    events::emit(
+        &miner,
+        MinerEvent::TaskCompleted {
+            task_id,
+            result_hash: completed.result_hash.clone(),
+        },
+    );
+
+    Ok(completed)
+}
+
+/// Uploads the results to the `results` directory of the storage backend
+///
+/// # Returns
+/// The identifier of the upload that is submitted on chain
+async fn upload_results(task_id: u64, result_hash: &str, results: Vec<u8>) -> Result<String> {
+    let name = format!("results/{}-{}.jsonl", task_id, result_hash);
+    let stored = storage_upload::backend_from_env()?
+        .upload(&name, "application/jsonl", results)
+        .await
+        .map_err(|e| {
+            Error::Custom(format!(
+                "Failed to upload the results of task {}: {}",
+                task_id, e
+            ))
+        })?;
+    Ok(stored.id)
+}
+
+fn results_path(task_dir: &Path) -> PathBuf {
+    task_dir.join(RESULTS_PATH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_expected_result_completes_the_input() {
+        let dir = std::env::temp_dir().join(format!("task-results-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let miner = AccountId32([7u8; 32]);
+        let finite = FiniteTask {
+            expected_results: Some(2),
+        };
+
+        assert!(!record_result(&miner, 1, &dir, &finite, r#"{"output":[1]}"#).unwrap());
+        assert!(record_result(&miner, 1, &dir, &finite, "not json").unwrap());
+        assert_eq!(
+            fs::read_to_string(results_path(&dir)).unwrap(),
+            "{\"output\":[1]}\n\"not json\"\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+use crate::error::Result;
+use crate::parent_runtime::task_completion::FiniteTask;
+use crate::parent_runtime::usage_policy::UsageRestrictions;
+use open_inference_runtime::{PipelineStep, PluginConfig, PostProcessing, PreProcessing};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Optional manifest of the task, shipped in the model archive next to the model files
+const TASK_MANIFEST_PATH: &str = "manifest.json";
+
+/// Options the task owner can set for their task. Every field is optional, a task without a manifest runs with the defaults.
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskManifest {
+    #[serde(default)]
+    pub proof_input: ProofInput,
+    /// How proofs too large for an extrinsic reach the verifier of the task owner
+    #[serde(default)]
+    pub proof_submission: ProofSubmission,
+    /// Pre-processing of encoded image or audio inputs of OpenInference models, so clients don't have to build tensors
+    #[serde(default)]
+    pub preprocessing: Vec<PreProcessing>,
+    /// Post-processing of the raw outputs of OpenInference models, applied before responses are sent to clients
+    #[serde(default)]
+    pub postprocessing: Vec<PostProcessing>,
+    /// Models of an OpenInference archive that are chained per request, e.g. encoder → decoder. Empty serves the single
+    /// model of the task, which may also be a Triton ensemble.
+    #[serde(default)]
+    pub pipeline: Vec<PipelineStep>,
+    /// WebAssembly modules of the archive that transform the requests and responses of OpenInference models, run
+    /// sandboxed so the miner never executes native code of the task owner
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    /// Size the task owner expects the extracted archive to have, lowers the extraction quota of the miner
+    #[serde(default)]
+    pub max_extracted_bytes: Option<u64>,
+    /// Hex encoded SHA-256 of files of the archive, keyed by their path in it. Files are verified as they are
+    /// extracted, the task fails on a mismatch.
+    #[serde(default)]
+    pub file_hashes: HashMap<String, String>,
+    /// License and usage restrictions of the model, miners whose operator policy conflicts with them refuse the task
+    #[serde(default)]
+    pub usage_restrictions: UsageRestrictions,
+    /// Model the inference history of the task is kept for, eg. "llama-3-8b". Tasks serving the same model share their
+    /// statistics, tasks without a name get statistics of their own.
+    #[serde(default)]
+    pub model_name: Option<String>,
+    /// Output of an OpenInference model holding the generated token ids, its elements are counted as tokens
+    #[serde(default)]
+    pub token_output: Option<String>,
+    /// Makes the task a batch job that is completed and submitted once its input is processed
+    #[serde(default)]
+    pub finite: Option<FiniteTask>,
+}
+
+/// Which input a proof is generated for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofInput {
+    /// The `input.json` of the model archive, only proves that the model can be run
+    #[default]
+    Canned,
+    /// The last request served by this miner, proves actual serving
+    LastServed,
+    /// The input committed on chain when the task was created
+    Chain,
+}
+
+/// How a proof is submitted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofSubmission {
+    /// As the argument of `submit_proof`, verified by the chain but bounded by its `MaxProofLength`
+    #[default]
+    Inline,
+    /// Uploaded to the storage backend, only its hash and location are submitted with a tagged remark. The chain
+    /// doesn't verify such proofs, the task owner fetches and verifies them.
+    Storage,
+}
+
+/// Reads the manifest of the task in `task_dir`, the defaults if the task has none
+pub fn read_manifest(task_dir: &str) -> Result<TaskManifest> {
+    let manifest_path = Path::new(task_dir).join(TASK_MANIFEST_PATH);
+    if manifest_path.exists() {
+        Ok(serde_json::from_str(&fs::read_to_string(manifest_path)?)?)
+    } else {
+        Ok(TaskManifest::default())
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{config::get_paths, error::Result, parent_runtime::response_anchor};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use subxt::utils::AccountId32;
+
+/// File next to the identity file that the checkpoints of the transcripts are appended to, one JSON record per line
+const TRANSCRIPTS_FILE_NAME: &str = "proof-transcripts.jsonl";
+
+/// The transcripts of the NeuroZK tasks served, per serving miner and task
+static TRANSCRIPTS: Lazy<Mutex<HashMap<(AccountId32, u64), Transcript>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A rolling hash over every request/response pair a task served, `hash = sha256(hash || leaf)` with the leaves of
+/// `response_anchor`. It never restarts while the task is served, so every checkpoint commits to all traffic before it.
+#[derive(Default)]
+struct Transcript {
+    hash: [u8; 32],
+    /// The hash at the last checkpoint
+    checkpoint: [u8; 32],
+    /// The leaves recorded since the last checkpoint
+    leaves: Vec<[u8; 32]>,
+}
+
+/// The traffic a task served between two proof checkpoints
+pub struct TranscriptCheckpoint {
+    pub task_id: u64,
+    /// The transcript hash at the previous checkpoint, all zeros for the first one
+    pub previous: [u8; 32],
+    pub hash: [u8; 32],
+    pub leaves: Vec<[u8; 32]>,
+}
+
+/// Extends the transcript of a task with a served request/response pair
+pub fn record(miner: &AccountId32, task_id: u64, request: &str, response: &str) {
+    let leaf = response_anchor::leaf_hash(request, response);
+    let mut transcripts = TRANSCRIPTS.lock().unwrap();
+    let transcript = transcripts.entry((miner.clone(), task_id)).or_default();
+    transcript.hash = extend(&transcript.hash, &leaf);
+    transcript.leaves.push(leaf);
+}
+
+/// The current segment of the transcript of a task, to be submitted with a proof. The segment only ends once the
+/// proof was submitted, see `commit`, so a failed submission leaves its leaves to the next proof.
+pub fn checkpoint(miner: &AccountId32, task_id: u64) -> TranscriptCheckpoint {
+    let mut transcripts = TRANSCRIPTS.lock().unwrap();
+    let transcript = transcripts.entry((miner.clone(), task_id)).or_default();
+
+    TranscriptCheckpoint {
+        task_id,
+        previous: transcript.checkpoint,
+        hash: transcript.hash,
+        leaves: transcript.leaves.clone(),
+    }
+}
+
+/// Ends the segment of a checkpoint once its proof was submitted, the next checkpoint continues from its hash. Pairs
+/// served since the checkpoint was taken stay in the next segment.
+pub fn commit(miner: &AccountId32, checkpoint: &TranscriptCheckpoint) {
+    let mut transcripts = TRANSCRIPTS.lock().unwrap();
+    let Some(transcript) = transcripts.get_mut(&(miner.clone(), checkpoint.task_id)) else {
+        return;
+    };
+    if transcript.checkpoint != checkpoint.previous {
+        return;
+    }
+
+    let committed = checkpoint.leaves.len().min(transcript.leaves.len());
+    transcript.leaves.drain(..committed);
+    transcript.checkpoint = checkpoint.hash;
+}
+
+/// Forgets the transcripts of a task once it stopped
+pub fn clear(task_id: u64) {
+    TRANSCRIPTS
+        .lock()
+        .unwrap()
+        .retain(|(_, task), _| *task != task_id);
+}
+
+/// Appends a checkpoint to `proof-transcripts.jsonl` along with the hash of the proof it was submitted with, kept
+/// next to the identity since the task directory is removed when the task stops. The leaves are kept, so anyone
+/// holding their own request/response pairs can check that they are part of what the proof was submitted for.
+///
+/// # Arguments
+/// * `checkpoint` - The checkpoint taken for the proof
+/// * `proof_sha256` - The hex encoded SHA-256 of the proof as submitted
+pub fn persist(checkpoint: &TranscriptCheckpoint, proof_sha256: &str) -> Result<()> {
+    let identity_path = PathBuf::from(&get_paths()?.identity_path);
+    let transcripts_path = identity_path.with_file_name(TRANSCRIPTS_FILE_NAME);
+
+    let record = serde_json::json!({
+        "task_id": checkpoint.task_id,
+        "transcript_hash": hex::encode(checkpoint.hash),
+        "previous_transcript_hash": hex::encode(checkpoint.previous),
+        "leaves": checkpoint.leaves.iter().map(hex::encode).collect::<Vec<_>>(),
+        "proof_sha256": proof_sha256,
+        "recorded_at": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0),
+    });
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(transcripts_path)?;
+    writeln!(file, "{}", record)?;
+
+    Ok(())
+}
+
+fn extend(hash: &[u8; 32], leaf: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(hash);
+    hasher.update(leaf);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints_chain_onto_each_other() {
+        let miner = AccountId32([7u8; 32]);
+        record(&miner, 42, "first", "1");
+        record(&miner, 42, "second", "2");
+        // A proof that fails to submit leaves its segment to the next one
+        let failed = checkpoint(&miner, 42);
+        let first = checkpoint(&miner, 42);
+        assert_eq!(failed.hash, first.hash);
+        commit(&miner, &first);
+        record(&miner, 42, "third", "3");
+        let second = checkpoint(&miner, 42);
+
+        assert_eq!(first.previous, [0u8; 32]);
+        assert_eq!(first.leaves.len(), 2);
+        assert_eq!(second.previous, first.hash);
+        assert_eq!(second.leaves.len(), 1);
+        assert_eq!(
+            second.hash,
+            extend(&first.hash, &response_anchor::leaf_hash("third", "3"))
+        );
+
+        clear(42);
+        assert_eq!(checkpoint(&miner, 42).hash, [0u8; 32]);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{
+    config,
+    error::{Error, Result},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// File next to the identity file that acknowledged usage restrictions are appended to, one JSON record per line
+const ACKNOWLEDGEMENTS_FILE_NAME: &str = "usage_acknowledgements.jsonl";
+
+/// Restrictions the licensor places on the use of a model, declared by the task owner in the task manifest. Clients
+/// are shown them when they connect, the miner refuses to serve the model if they conflict with its operator policy.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct UsageRestrictions {
+    /// Name or SPDX identifier of the license, eg. "CC-BY-NC-4.0"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license_url: Option<String>,
+    /// The model may only be served by miners that don't serve commercially
+    #[serde(default)]
+    pub non_commercial: bool,
+    /// ISO 3166-1 alpha-2 codes of the regions the model may be served from, empty allows every region
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_regions: Vec<String>,
+    /// ISO 3166-1 alpha-2 codes of the regions the model must not be served from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_regions: Vec<String>,
+    /// Further terms of use, shown to clients as they are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terms: Option<String>,
+}
+
+impl UsageRestrictions {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The message sent to clients when they connect, eg. `{"event":"usage_restrictions","license":"CC-BY-NC-4.0",...}`
+    pub fn announcement(&self) -> String {
+        let mut announcement = serde_json::json!({ "event": "usage_restrictions" });
+        if let (Some(announcement), Ok(serde_json::Value::Object(restrictions))) =
+            (announcement.as_object_mut(), serde_json::to_value(self))
+        {
+            announcement.extend(restrictions);
+        }
+        announcement.to_string()
+    }
+}
+
+/// How the operator runs the miner, declared with `MINER_COMMERCIAL_USE` (miners are paid for serving, so they count
+/// as commercial unless declared otherwise) and `MINER_REGION` (ISO 3166-1 alpha-2 code of the host's region).
+/// Restricted models are only served once the operator accepts their restrictions with `ACCEPT_USAGE_RESTRICTIONS=true`.
+#[derive(Debug, Clone, Serialize)]
+pub struct OperatorPolicy {
+    pub commercial: bool,
+    pub region: Option<String>,
+    #[serde(skip)]
+    pub accepts_restrictions: bool,
+}
+
+impl OperatorPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            commercial: config::optional_env("MINER_COMMERCIAL_USE", true),
+            region: std::env::var("MINER_REGION")
+                .ok()
+                .map(|region| region.trim().to_uppercase())
+                .filter(|region| !region.is_empty()),
+            accepts_restrictions: config::optional_env("ACCEPT_USAGE_RESTRICTIONS", false),
+        }
+    }
+
+    /// Why the miner may not serve a model under `restrictions`
+    ///
+    /// # Returns
+    /// The conflict, or `None` if the policy complies with the restrictions
+    pub fn conflict(&self, restrictions: &UsageRestrictions) -> Option<String> {
+        if restrictions.non_commercial && self.commercial {
+            return Some("The model is licensed for non-commercial use only".to_string());
+        }
+
+        let listed = |regions: &[String], region: &str| {
+            regions
+                .iter()
+                .any(|listed| listed.trim().eq_ignore_ascii_case(region))
+        };
+        let restricted_by_region =
+            !restrictions.allowed_regions.is_empty() || !restrictions.denied_regions.is_empty();
+        match &self.region {
+            None if restricted_by_region => Some(
+                "The model is restricted to certain regions, but MINER_REGION is not declared"
+                    .to_string(),
+            ),
+            Some(region)
+                if !restrictions.allowed_regions.is_empty()
+                    && !listed(&restrictions.allowed_regions, region) =>
+            {
+                Some(format!("The model may not be served from {}", region))
+            }
+            Some(region) if listed(&restrictions.denied_regions, region) => {
+                Some(format!("The model may not be served from {}", region))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Acknowledgement<'a> {
+    task_id: u64,
+    restrictions: &'a UsageRestrictions,
+    policy: &'a OperatorPolicy,
+    /// Unix time in seconds
+    acknowledged_at: u64,
+}
+
+/// Checks whether a task may be served under its usage restrictions, and records the acknowledgement of the operator.
+/// Tasks without restrictions are always served.
+///
+/// # Arguments
+/// * `task_id` - The task to be served
+/// * `restrictions` - The usage restrictions of the task manifest
+/// * `policy` - The declared policy of the operator
+/// * `identity_path` - The identity file of the miner, acknowledgements are recorded next to it
+///
+/// # Returns
+/// `Ok(())` if the task may be served, or an `Error` describing why it must not be
+pub fn enforce(
+    task_id: u64,
+    restrictions: &UsageRestrictions,
+    policy: &OperatorPolicy,
+    identity_path: &str,
+) -> Result<()> {
+    if restrictions.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(conflict) = policy.conflict(restrictions) {
+        return Err(Error::Custom(format!(
+            "Refusing to serve task {}: {}",
+            task_id, conflict
+        )));
+    }
+    if !policy.accepts_restrictions {
+        return Err(Error::Custom(format!(
+            "Task {} is served under usage restrictions ({}), set ACCEPT_USAGE_RESTRICTIONS=true to accept them",
+            task_id,
+            restrictions.license.as_deref().unwrap_or("unnamed license")
+        )));
+    }
+
+    let acknowledgement = Acknowledgement {
+        task_id,
+        restrictions,
+        policy,
+        acknowledged_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    let path = Path::new(identity_path).with_file_name(ACKNOWLEDGEMENTS_FILE_NAME);
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&acknowledgement)?)?;
+
+    println!(
+        "Accepted the usage restrictions of task {}, recorded in {}",
+        task_id,
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(commercial: bool, region: Option<&str>) -> OperatorPolicy {
+        OperatorPolicy {
+            commercial,
+            region: region.map(str::to_string),
+            accepts_restrictions: true,
+        }
+    }
+
+    #[test]
+    fn conflicting_policies_are_detected() {
+        let non_commercial = UsageRestrictions {
+            non_commercial: true,
+            ..Default::default()
+        };
+        assert!(policy(true, None).conflict(&non_commercial).is_some());
+        assert!(policy(false, None).conflict(&non_commercial).is_none());
+
+        let eu_only = UsageRestrictions {
+            allowed_regions: vec!["de".to_string(), "FR".to_string()],
+            denied_regions: vec!["FR".to_string()],
+            ..Default::default()
+        };
+        assert!(policy(true, Some("DE")).conflict(&eu_only).is_none());
+        assert!(policy(true, Some("FR")).conflict(&eu_only).is_some());
+        assert!(policy(true, Some("US")).conflict(&eu_only).is_some());
+        assert!(policy(true, None).conflict(&eu_only).is_some());
+    }
+}
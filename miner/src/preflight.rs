@@ -0,0 +1,371 @@
+use crate::{
+    chain_spec, config,
+    error::{Error, Result},
+    specs::{self, EngineMode},
+    substrate_interface,
+    types::TaskType,
+    utils::blocking::run_blocking,
+};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subxt::{OnlineClient, PolkadotConfig};
+
+/// Settings the miner can't start without
+const REQUIRED_ENV: [&str; 6] = [
+    "LOG_FILE_PATH",
+    "TASK_FILE_NAME",
+    "TASK_DIR_PATH",
+    "IDENTITY_FILE_PATH",
+    "TASK_OWNER_FILE_PATH",
+    "STORAGE_LOCATION",
+];
+/// How long the parachain node may take to answer
+const CHAIN_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    /// The miner runs, but some tasks or features won't work
+    Warn,
+    /// The miner won't start or won't serve tasks
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    outcome: Outcome,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, outcome: Outcome, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Checks that the host can run the miner and prints a pass/fail report, so that operators diagnose broken
+/// installations before registering. Only reads, nothing is registered or changed.
+///
+/// # Arguments
+/// * `parachain_url` - The parachain node to check connectivity and clock skew against, `PARACHAIN_URL` if not given
+///
+/// # Returns
+/// `Ok(())` if no check failed, an `Error` counting the failed checks otherwise
+pub async fn run_preflight(parachain_url: Option<&str>) -> Result<()> {
+    config::load_dotenv();
+    let parachain_url = parachain_url
+        .map(str::to_string)
+        .or_else(|| std::env::var("PARACHAIN_URL").ok());
+
+    let mut checks = vec![check_env()];
+    checks.extend(
+        run_blocking(|| {
+            Ok(vec![
+                check_docker(),
+                check_nvidia_runtime(),
+                check_tailscale(),
+            ])
+        })
+        .await?,
+    );
+    checks.push(check_triton().await);
+    checks.push(check_disk().await);
+    checks.extend(check_chain(parachain_url.as_deref()).await);
+
+    for check in &checks {
+        let label = match check.outcome {
+            Outcome::Pass => "PASS",
+            Outcome::Warn => "WARN",
+            Outcome::Fail => "FAIL",
+        };
+        println!("[{}] {:<16} {}", label, check.name, check.detail);
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.outcome == Outcome::Fail)
+        .count();
+    if failed > 0 {
+        return Err(Error::Custom(format!("{} preflight checks failed", failed)));
+    }
+    println!("The host is ready to run the miner");
+    Ok(())
+}
+
+fn check_env() -> Check {
+    let missing: Vec<&str> = REQUIRED_ENV
+        .into_iter()
+        .filter(|key| std::env::var(key).map_or(true, |value| value.trim().is_empty()))
+        .collect();
+
+    if missing.is_empty() {
+        Check::new(
+            "Configuration",
+            Outcome::Pass,
+            "all required settings are set",
+        )
+    } else {
+        Check::new(
+            "Configuration",
+            Outcome::Fail,
+            format!("missing {}", missing.join(", ")),
+        )
+    }
+}
+
+fn check_docker() -> Check {
+    if specs::command_succeeds("docker", &["info"]) {
+        Check::new("Docker", Outcome::Pass, "the daemon is reachable")
+    } else {
+        Check::new(
+            "Docker",
+            Outcome::Warn,
+            "not installed or the daemon is not reachable by this user",
+        )
+    }
+}
+
+fn check_nvidia_runtime() -> Check {
+    let runtimes = Command::new("docker")
+        .args(["info", "--format", "{{json .Runtimes}}"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+        .unwrap_or_default();
+    let gpus = Command::new("nvidia-smi")
+        .args(["--query-gpu=name", "--format=csv,noheader"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).lines().count())
+        .unwrap_or(0);
+
+    match (gpus, runtimes.contains("nvidia")) {
+        (0, _) => Check::new(
+            "NVIDIA runtime",
+            Outcome::Warn,
+            "no NVIDIA GPU found, models run on the CPU",
+        ),
+        (gpus, true) => Check::new(
+            "NVIDIA runtime",
+            Outcome::Pass,
+            format!("{} GPUs, the nvidia container runtime is installed", gpus),
+        ),
+        (gpus, false) => Check::new(
+            "NVIDIA runtime",
+            Outcome::Fail,
+            format!(
+                "{} GPUs, but the nvidia container runtime is missing, containers can't use them",
+                gpus
+            ),
+        ),
+    }
+}
+
+fn check_tailscale() -> Check {
+    let endpoint = config::optional_env("MINER_ENDPOINT", String::new());
+    match specs::tailnet_name() {
+        Some(name) => Check::new("Tailscale", Outcome::Pass, format!("connected as {}", name)),
+        None if !endpoint.is_empty() => Check::new(
+            "Tailscale",
+            Outcome::Pass,
+            format!("not connected, clients reach MINER_ENDPOINT {}", endpoint),
+        ),
+        None => Check::new(
+            "Tailscale",
+            Outcome::Warn,
+            "not connected and no MINER_ENDPOINT set, clients may not reach the miner",
+        ),
+    }
+}
+
+async fn check_triton() -> Check {
+    if specs::engine_mode(specs::engine_name(&TaskType::OpenInference)) == EngineMode::Off {
+        Check::new("Triton", Outcome::Pass, "not needed, OpenInference is disabled")
+    } else if specs::triton_available().await {
+        Check::new(
+            "Triton",
+            Outcome::Pass,
+            format!("ready on {}", config::triton_url()),
+        )
+    } else {
+        Check::new(
+            "Triton",
+            Outcome::Warn,
+            format!(
+                "not ready on {}, OpenInference tasks are declined",
+                config::triton_url()
+            ),
+        )
+    }
+}
+
+async fn check_disk() -> Check {
+    let task_dir = config::optional_env("TASK_DIR_PATH", ".".to_string());
+    let required = config::optional_env("PREFLIGHT_MIN_FREE_BYTES", 20u64 << 30);
+    let dir = task_dir.clone();
+    let free = run_blocking(move || {
+        // The directory may not exist before the first task, its closest existing ancestor is on the same disk
+        let existing = std::path::Path::new(&dir)
+            .ancestors()
+            .find(|ancestor| ancestor.exists())
+            .map(|ancestor| ancestor.to_string_lossy().to_string());
+        Ok(existing.and_then(|path| specs::available_storage(&path)))
+    })
+    .await
+    .ok()
+    .flatten();
+
+    match free {
+        Some(free) if free >= required => Check::new(
+            "Disk space",
+            Outcome::Pass,
+            format!("{} GiB free for {}", free >> 30, task_dir),
+        ),
+        Some(free) => Check::new(
+            "Disk space",
+            Outcome::Fail,
+            format!(
+                "{} GiB free for {}, at least {} GiB are needed",
+                free >> 30,
+                task_dir,
+                required >> 30
+            ),
+        ),
+        None => Check::new(
+            "Disk space",
+            Outcome::Warn,
+            format!("free space of {} is unknown", task_dir),
+        ),
+    }
+}
+
+/// Connects to the parachain, checks that its runtime can be decoded and compares the clock of the host with the
+/// timestamp of the latest block
+async fn check_chain(parachain_url: Option<&str>) -> Vec<Check> {
+    let Some(parachain_url) = parachain_url else {
+        return vec![Check::new(
+            "Chain",
+            Outcome::Fail,
+            "no parachain URL, pass --parachain-url or set PARACHAIN_URL",
+        )];
+    };
+
+    let connection = tokio::time::timeout(
+        CHAIN_TIMEOUT,
+        OnlineClient::<PolkadotConfig>::from_url(parachain_url),
+    )
+    .await;
+    let client = match connection {
+        Ok(Ok(client)) => client,
+        Ok(Err(e)) => {
+            return vec![Check::new(
+                "Chain",
+                Outcome::Fail,
+                format!("can't connect to {}: {}", parachain_url, e),
+            )]
+        }
+        Err(_) => {
+            return vec![Check::new(
+                "Chain",
+                Outcome::Fail,
+                format!(
+                    "{} didn't answer within {} s",
+                    parachain_url,
+                    CHAIN_TIMEOUT.as_secs()
+                ),
+            )]
+        }
+    };
+
+    let chain = match chain_spec::detect(&client).await {
+        Ok(runtime) => Check::new(
+            "Chain",
+            Outcome::Pass,
+            format!(
+                "connected to {}, runtime spec version {}",
+                parachain_url, runtime.spec_version
+            ),
+        ),
+        Err(e) => Check::new("Chain", Outcome::Fail, e.to_string()),
+    };
+
+    let chain_time = async {
+        client
+            .storage()
+            .at_latest()
+            .await?
+            .fetch(&substrate_interface::api::storage().timestamp().now())
+            .await
+    };
+    let clock = match chain_time.await {
+        Ok(Some(chain_ms)) => {
+            let local_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or(0);
+            let max_skew = config::optional_env("PREFLIGHT_MAX_CLOCK_SKEW_SECS", 60u64);
+            clock_skew_check(local_ms, chain_ms, max_skew)
+        }
+        Ok(None) => Check::new("Clock", Outcome::Warn, "the chain reports no timestamp"),
+        Err(e) => Check::new(
+            "Clock",
+            Outcome::Warn,
+            format!("can't read the chain timestamp: {}", e),
+        ),
+    };
+
+    vec![chain, clock]
+}
+
+/// Compares the clock of the host with the timestamp of the latest block. Blocks are a few seconds old when they are
+/// read, so the tolerance covers the block time. Signed requests and proofs are rejected by skewed clocks.
+fn clock_skew_check(local_ms: u64, chain_ms: u64, max_skew_secs: u64) -> Check {
+    let skew_secs = local_ms.abs_diff(chain_ms) / 1000;
+    let ahead = if local_ms >= chain_ms {
+        "ahead of"
+    } else {
+        "behind"
+    };
+
+    if skew_secs <= max_skew_secs {
+        Check::new(
+            "Clock",
+            Outcome::Pass,
+            format!("{} s {} the latest block", skew_secs, ahead),
+        )
+    } else {
+        Check::new(
+            "Clock",
+            Outcome::Fail,
+            format!(
+                "{} s {} the latest block, synchronize the clock (eg. with NTP)",
+                skew_secs, ahead
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clocks_are_compared_within_the_tolerance() {
+        let chain_ms = 1_700_000_000_000;
+
+        assert_eq!(
+            clock_skew_check(chain_ms + 12_000, chain_ms, 60).outcome,
+            Outcome::Pass
+        );
+        assert_eq!(
+            clock_skew_check(chain_ms - 300_000, chain_ms, 60).outcome,
+            Outcome::Fail
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{config, error::Result, parent_runtime::model_retention, snapshot};
+use std::fs;
+use std::path::Path;
+
+/// Removes what a previous run of the miner left behind before it accepts new tasks. Model files left in the task
+/// directory after a crash may belong to a task that was reassigned meanwhile, and an extracted model would be served
+/// in place of the model of the next task, since extraction skips models that already exist. The task state that
+/// snapshots carry between hosts, the NeuroZK setup outputs and the model files of a snapshot restored with
+/// `--include-models` are kept. The lock file and the containers of the miner are reconciled by `instance_lock` and
+/// `container_monitor`.
+pub fn remove_orphaned_resources() -> Result<()> {
+    let paths = config::get_paths()?;
+    remove_orphans(
+        Path::new(&paths.task_dir_path),
+        Path::new(&paths.task_owner_path),
+    )?;
+
+    let expired = model_retention::prune(paths)?;
+    if expired > 0 {
+        println!(
+            "Removed {} retained models past their retention period",
+            expired
+        );
+    }
+
+    // Components only the removed models linked are orphaned as well
+    if let Some(component_cache) = config::component_cache()? {
+        let removed = component_cache.prune()?;
+        if removed > 0 {
+            println!("Removed {} unused cached model components", removed);
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_orphans(task_dir: &Path, task_owner_path: &Path) -> Result<()> {
+    if task_dir.is_dir() {
+        let restored_models = snapshot::take_restored_models(task_dir)?;
+
+        for entry in fs::read_dir(task_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+
+            if file_type.is_file()
+                && (snapshot::is_task_state(&path) || snapshot::is_setup_output(&path))
+            {
+                continue;
+            }
+            // Restored model directories are kept as a whole
+            if restored_models
+                .iter()
+                .any(|restored| restored.starts_with(entry.file_name()))
+            {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+            println!("Removed orphaned task resource {}", path.display());
+        }
+    }
+
+    // The agent would otherwise keep serving the owner of a task this miner no longer runs
+    if task_owner_path.exists() {
+        fs::remove_file(task_owner_path)?;
+        println!(
+            "Removed orphaned task owner file {}",
+            task_owner_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_are_removed_and_task_state_is_kept() {
+        let root = std::env::temp_dir().join(format!("miner-reconcile-{}", std::process::id()));
+        let task_dir = root.join("current_task");
+        let task_owner_path = root.join("task_owner.json");
+        fs::create_dir_all(task_dir.join("model/1")).unwrap();
+        fs::write(task_dir.join("model/1/model.onnx"), b"model").unwrap();
+        fs::write(task_dir.join("model.tar.gz"), b"partial").unwrap();
+        fs::write(task_dir.join("kzg.srs"), b"srs").unwrap();
+        fs::write(task_dir.join("setup-progress.json"), b"{}").unwrap();
+        fs::write(task_dir.join("pk.key"), b"pk").unwrap();
+        fs::write(&task_owner_path, b"{}").unwrap();
+
+        remove_orphans(&task_dir, &task_owner_path).unwrap();
+
+        assert!(!task_dir.join("model").exists());
+        assert!(!task_dir.join("model.tar.gz").exists());
+        assert!(task_dir.join("kzg.srs").exists());
+        assert!(task_dir.join("setup-progress.json").exists());
+        assert!(task_dir.join("pk.key").exists());
+        assert!(!task_owner_path.exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn models_restored_from_a_snapshot_are_kept_once() {
+        let root =
+            std::env::temp_dir().join(format!("miner-reconcile-restored-{}", std::process::id()));
+        let task_dir = root.join("current_task");
+        let task_owner_path = root.join("task_owner.json");
+        fs::create_dir_all(task_dir.join("model/1")).unwrap();
+        fs::write(task_dir.join("model/1/model.onnx"), b"model").unwrap();
+        fs::write(task_dir.join("stale.onnx"), b"stale").unwrap();
+        fs::write(
+            task_dir.join("restored-models.json"),
+            br#"["model/1/model.onnx"]"#,
+        )
+        .unwrap();
+
+        remove_orphans(&task_dir, &task_owner_path).unwrap();
+        assert!(task_dir.join("model/1/model.onnx").exists());
+        assert!(!task_dir.join("stale.onnx").exists());
+        assert!(!task_dir.join("restored-models.json").exists());
+
+        // Only the first start after the restore keeps them
+        remove_orphans(&task_dir, &task_owner_path).unwrap();
+        assert!(!task_dir.join("model").exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
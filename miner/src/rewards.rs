@@ -0,0 +1,116 @@
+use crate::{
+    chain_properties, config,
+    error::{Error, Result},
+    substrate_interface::{self, api::runtime_types::cyborg_primitives::payment::RewardRates},
+    utils::{
+        substrate_queries::{get_allocated_tasks, get_pending_rewards, get_reward_rates},
+        tx_builder::distribute_rewards,
+        tx_queue::TxOutput,
+    },
+};
+use std::str::FromStr;
+use subxt_signer::{sr25519::Keypair, SecretUri};
+
+/// Prints the pending rewards and reward rates of the miner account, and the proof state of the tasks currently allocated to it.
+/// Only needs a parachain connection, no miner configuration.
+///
+/// # Arguments
+/// * `parachain_url` - The URL of the parachain node to query
+/// * `account_seed` - The secret URI of the miner keypair
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the rewards were printed, or an `Error` if it fails.
+pub async fn print_rewards(parachain_url: &str, account_seed: &str) -> Result<()> {
+    let account = keypair_from_seed(account_seed)?.public_key().to_account_id();
+    let api = chain_properties::connect(parachain_url).await?;
+
+    let pending = get_pending_rewards(&api, &account).await?;
+    let (active_rates, idle_rates) = get_reward_rates(&api, &account).await?;
+
+    println!("Rewards of {}", chain_properties::format_account(&account));
+    println!("  Pending rewards:   {}", chain_properties::format_balance(pending));
+    println!("  Active rates:      {}", display_rates(&active_rates));
+    println!("  Idle rates:        {}", display_rates(&idle_rates));
+
+    let task_ids = get_allocated_tasks(&api, &account).await?;
+    if task_ids.is_empty() {
+        println!("  Allocated tasks:   none");
+        return Ok(());
+    }
+
+    println!("  Allocated tasks:");
+    let storage = api.storage().at_latest().await?;
+    for task_id in task_ids {
+        let task = storage
+            .fetch(&substrate_interface::api::storage().task_management().tasks(task_id))
+            .await?;
+
+        let proof_state = match task.and_then(|task| task.nzk_data) {
+            Some(nzk_data) => match nzk_data.last_proof_accepted {
+                Some((true, block)) => format!("last proof accepted at block {}", block),
+                Some((false, block)) => format!("last proof rejected at block {}", block),
+                None => "no proof verified yet".to_string(),
+            },
+            None => "no proofs required".to_string(),
+        };
+        println!("    Task {}: {}", task_id, proof_state);
+    }
+
+    Ok(())
+}
+
+/// Submits the distribution of pending rewards through the transaction queue, signed by the miner keypair.
+///
+/// # Arguments
+/// * `parachain_url` - The URL of the parachain node to submit to
+/// * `account_seed` - The secret URI of the miner keypair
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the transaction was finalized, or an `Error` if the chain rejected it.
+pub async fn claim_rewards(parachain_url: &str, account_seed: &str) -> Result<()> {
+    let keypair = keypair_from_seed(account_seed)?;
+    config::init_parachain_client(parachain_url).await;
+
+    let tx_queue = config::get_tx_queue()?;
+    let rx = tx_queue
+        .enqueue(move || {
+            let keypair = keypair.clone();
+            async move {
+                let distributed = distribute_rewards(keypair).await?;
+                Ok(TxOutput::RewardsDistributed(distributed))
+            }
+        })
+        .await?;
+
+    match rx.await {
+        Ok(Ok(TxOutput::RewardsDistributed(Some(amount)))) => {
+            println!(
+                "Rewards distributed, received {}",
+                chain_properties::format_balance(amount)
+            )
+        }
+        Ok(Ok(_)) => println!("Rewards distributed, nothing was pending for this miner"),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return Err(Error::Custom("Response channel dropped.".to_string())),
+    }
+
+    Ok(())
+}
+
+fn keypair_from_seed(account_seed: &str) -> Result<Keypair> {
+    let uri = SecretUri::from_str(account_seed)
+        .map_err(|e| Error::Custom(format!("Invalid account seed: {}", e)))?;
+    Keypair::from_uri(&uri).map_err(|e| Error::Custom(format!("Invalid keypair: {}", e)))
+}
+
+fn display_rates(rates: &Option<RewardRates<u128>>) -> String {
+    match rates {
+        Some(rates) => format!(
+            "cpu {}, ram {}, storage {}",
+            chain_properties::format_balance(rates.cpu),
+            chain_properties::format_balance(rates.ram),
+            chain_properties::format_balance(rates.storage)
+        ),
+        None => "default".to_string(),
+    }
+}
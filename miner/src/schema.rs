@@ -0,0 +1,244 @@
+use crate::{
+    config,
+    error::{Error, Result},
+    parachain_interactor::identity::{read_identity_file, update_identity_file},
+    worker_migration,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use subxt::utils::AccountId32;
+
+/// Version of the identity file format written by this miner
+pub const IDENTITY_VERSION: u32 = 1;
+/// Version of the task owner file format written by this miner
+pub const TASK_OWNER_VERSION: u32 = 1;
+
+/// The identity the parachain assigned to this miner when it registered
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MinerIdentity {
+    pub version: u32,
+    /// The account that registered the miner
+    pub owner: AccountId32,
+    pub miner_id: u64,
+}
+
+/// The owner of the task the miner currently serves
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TaskOwner {
+    pub version: u32,
+    /// Kept under this name for the agent, which reads the task owner file
+    pub address: AccountId32,
+}
+
+impl MinerIdentity {
+    pub fn new(owner: AccountId32, miner_id: u64) -> Self {
+        Self {
+            version: IDENTITY_VERSION,
+            owner,
+            miner_id,
+        }
+    }
+
+    pub fn as_tuple(&self) -> (AccountId32, u64) {
+        (self.owner.clone(), self.miner_id)
+    }
+}
+
+/// Every identity format ever written, newest first. Unversioned files predate the version field.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredIdentity {
+    Current(MinerIdentity),
+    /// Written by registration until the formats were unified
+    Unversioned {
+        miner_identity: (AccountId32, u64),
+    },
+    /// The shape the configuration once expected, with 32 bit ids
+    Legacy {
+        owner: AccountId32,
+        id: u32,
+    },
+    /// `WorkerData` of the worker node the miner replaced, see `worker_migration`
+    Worker {
+        worker_identity: (AccountId32, u64),
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTaskOwner {
+    Current(TaskOwner),
+    Unversioned { address: AccountId32 },
+}
+
+/// Parses an identity file of any known format.
+///
+/// # Returns
+/// The identity in the current format and whether it had to be migrated, or an `Error` if the format is unknown
+pub(crate) fn parse_identity(content: &str) -> Result<(MinerIdentity, bool)> {
+    match serde_json::from_str::<StoredIdentity>(content) {
+        Ok(StoredIdentity::Current(identity)) if identity.version > IDENTITY_VERSION => {
+            Err(Error::Custom(format!(
+                "Identity file has version {}, this miner only understands up to {}",
+                identity.version, IDENTITY_VERSION
+            )))
+        }
+        Ok(StoredIdentity::Current(identity)) => Ok((identity, false)),
+        Ok(StoredIdentity::Unversioned { miner_identity }) => {
+            Ok((MinerIdentity::new(miner_identity.0, miner_identity.1), true))
+        }
+        Ok(StoredIdentity::Legacy { owner, id }) => {
+            Ok((MinerIdentity::new(owner, id.into()), true))
+        }
+        Ok(StoredIdentity::Worker { worker_identity }) => Ok((
+            MinerIdentity::new(worker_identity.0, worker_identity.1),
+            true,
+        )),
+        Err(e) => Err(Error::Custom(format!(
+            "Identity file is in an unknown format: {}",
+            e
+        ))),
+    }
+}
+
+pub(crate) fn parse_task_owner(content: &str) -> Result<(TaskOwner, bool)> {
+    match serde_json::from_str::<StoredTaskOwner>(content) {
+        Ok(StoredTaskOwner::Current(task_owner)) => Ok((task_owner, false)),
+        Ok(StoredTaskOwner::Unversioned { address }) => Ok((
+            TaskOwner {
+                version: TASK_OWNER_VERSION,
+                address,
+            },
+            true,
+        )),
+        Err(e) => Err(Error::Custom(format!(
+            "Task owner file is in an unknown format: {}",
+            e
+        ))),
+    }
+}
+
+/// Reads the identity of the miner.
+///
+/// # Returns
+/// `None` if the miner has not registered yet, or an `Error` if the identity file exists but can't be read. A broken
+/// identity file must not look like a missing one, that would make the miner register again.
+pub fn read_identity(path: &str) -> Result<Option<MinerIdentity>> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let (identity, _) = parse_identity(&read_identity_file(path)?)?;
+    Ok(Some(identity))
+}
+
+/// Reads the owner of the task the miner currently serves.
+///
+/// # Returns
+/// `None` if no task was scheduled, or an `Error` if the task owner file exists but can't be read
+pub fn read_task_owner(path: &str) -> Result<Option<TaskOwner>> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let (task_owner, _) = parse_task_owner(&read_identity_file(path)?)?;
+    Ok(Some(task_owner))
+}
+
+/// Persists the identity assigned at registration
+pub fn write_identity(path: &str, owner: AccountId32, miner_id: u64) -> Result<()> {
+    let identity = MinerIdentity::new(owner, miner_id);
+    update_identity_file(path, &serde_json::to_string(&identity)?)
+}
+
+/// Persists the owner of a newly scheduled task
+pub fn write_task_owner(path: &str, address: AccountId32) -> Result<()> {
+    let task_owner = TaskOwner {
+        version: TASK_OWNER_VERSION,
+        address,
+    };
+    update_identity_file(path, &serde_json::to_string(&task_owner)?)
+}
+
+/// Rewrites identity and task owner files of older formats in the current one. Runs at startup, before anything reads
+/// them, and fails if a file can't be understood instead of letting the miner continue without its identity.
+pub fn migrate_config_files() -> Result<()> {
+    let paths = config::get_paths()?;
+
+    if !Path::new(&paths.identity_path).exists() {
+        worker_migration::hint_unmigrated_worker();
+    }
+
+    if Path::new(&paths.identity_path).exists() {
+        let (identity, migrated) = parse_identity(&read_identity_file(&paths.identity_path)?)?;
+        if migrated {
+            update_identity_file(&paths.identity_path, &serde_json::to_string(&identity)?)?;
+            println!(
+                "Migrated identity file to version {}: {:?}",
+                IDENTITY_VERSION, identity
+            );
+        }
+    }
+
+    if Path::new(&paths.task_owner_path).exists() {
+        let (task_owner, migrated) =
+            parse_task_owner(&read_identity_file(&paths.task_owner_path)?)?;
+        if migrated {
+            update_identity_file(&paths.task_owner_path, &serde_json::to_string(&task_owner)?)?;
+            println!("Migrated task owner file to version {}", TASK_OWNER_VERSION);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+
+    #[test]
+    fn unversioned_identity_is_migrated() {
+        let content = format!(
+            r#"{{"miner_owner":"{}","miner_identity":["{}",7]}}"#,
+            ACCOUNT, ACCOUNT
+        );
+
+        let (identity, migrated) = parse_identity(&content).unwrap();
+
+        assert!(migrated);
+        assert_eq!(identity.miner_id, 7);
+        assert_eq!(identity.version, IDENTITY_VERSION);
+    }
+
+    #[test]
+    fn worker_data_is_migrated() {
+        let content = format!(
+            r#"{{"worker_owner":"{}","worker_identity":["{}",4]}}"#,
+            ACCOUNT, ACCOUNT
+        );
+
+        let (identity, migrated) = parse_identity(&content).unwrap();
+
+        assert!(migrated);
+        assert_eq!(identity.miner_id, 4);
+        assert_eq!(identity.owner.to_string(), ACCOUNT);
+    }
+
+    #[test]
+    fn current_identity_roundtrips() {
+        let identity = MinerIdentity::new(AccountId32::from([1u8; 32]), 3);
+
+        let (parsed, migrated) =
+            parse_identity(&serde_json::to_string(&identity).unwrap()).unwrap();
+
+        assert!(!migrated);
+        assert_eq!(parsed, identity);
+    }
+
+    #[test]
+    fn unknown_identity_format_is_an_error() {
+        assert!(parse_identity(r#"{"worker":1}"#).is_err());
+    }
+}
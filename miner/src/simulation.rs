@@ -0,0 +1,489 @@
+use crate::{
+    builder::MinerBuilder,
+    config,
+    error::{Error, Result},
+    parachain_interactor::{
+        backup_events::{BackupWorkerAssigned, BackupWorkerPromoted},
+        event_processor::{handle_event, ChainEvent},
+    },
+    schema,
+    substrate_interface::api::{
+        neuro_zk::events::NzkProofRequested,
+        runtime_types::{
+            bounded_collections::bounded_vec::BoundedVec, cyborg_primitives::task::TaskKind,
+        },
+        task_management::events::{TaskScheduled, TaskStopRequested},
+    },
+    traits::ChainApi,
+    types::{Capabilities, HardwareSpec},
+    utils::substrate_queries::{CyborgTask, NzkCommitment},
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use subxt::utils::AccountId32;
+use subxt_signer::{sr25519::Keypair, SecretUri};
+
+/// A scripted sequence of chain events, eg.
+/// `{"events":[{"event":"TaskScheduled","task_id":1,"task_kind":"neuro_zk","task":"<storage id>"},
+/// {"delay_secs":60,"event":"NzkProofRequested","task_id":1},{"event":"TaskStopRequested","task_id":1}]}`
+#[derive(Deserialize, Debug)]
+pub struct Scenario {
+    pub events: Vec<ScenarioStep>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScenarioStep {
+    /// Seconds to wait before the event is delivered
+    #[serde(default)]
+    pub delay_secs: u64,
+    #[serde(flatten)]
+    pub event: ScenarioEvent,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "event")]
+pub enum ScenarioEvent {
+    /// Schedules a task, by default to the simulated miner
+    TaskScheduled {
+        task_id: u64,
+        task_kind: ScenarioTaskKind,
+        /// The storage id of the task archive
+        task: String,
+        /// Schedules the task to another miner, the simulated one only confirms reception
+        #[serde(default)]
+        other_miner: bool,
+        /// The artifacts the simulated task owner commits to, required for NeuroZK tasks
+        #[serde(default)]
+        commitment: Option<ScenarioCommitment>,
+    },
+    NzkProofRequested {
+        task_id: u64,
+    },
+    TaskStopRequested {
+        task_id: u64,
+    },
+    /// Designates the simulated miner as the backup miner of a task
+    BackupWorkerAssigned {
+        task_id: u64,
+        task_kind: ScenarioTaskKind,
+        /// The storage id of the task archive
+        task: String,
+        /// The artifacts the simulated task owner commits to, required for NeuroZK tasks
+        #[serde(default)]
+        commitment: Option<ScenarioCommitment>,
+    },
+    /// Promotes the simulated miner from standby to serve the task
+    BackupWorkerPromoted {
+        task_id: u64,
+    },
+}
+
+/// Files holding the artifacts of a NeuroZK task, relative to the scenario
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ScenarioCommitment {
+    pub settings: PathBuf,
+    #[serde(default)]
+    pub input: Option<PathBuf>,
+    #[serde(default)]
+    pub verifying_key: Option<PathBuf>,
+}
+
+impl ScenarioCommitment {
+    fn load(&self, scenario_dir: &Path) -> Result<NzkCommitment> {
+        let read = |path: &Path| {
+            let path = scenario_dir.join(path);
+            fs::read(&path).map_err(|e| {
+                Error::Custom(format!(
+                    "Failed to read commitment {}: {}",
+                    path.display(),
+                    e
+                ))
+            })
+        };
+        Ok(NzkCommitment {
+            zk_settings: read(self.settings.as_path())?,
+            zk_input: self
+                .input
+                .as_deref()
+                .map(read)
+                .transpose()?
+                .unwrap_or_default(),
+            zk_verifying_key: self
+                .verifying_key
+                .as_deref()
+                .map(read)
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ScenarioTaskKind {
+    NeuroZk,
+    OpenInference,
+}
+
+impl From<ScenarioTaskKind> for TaskKind {
+    fn from(kind: ScenarioTaskKind) -> Self {
+        match kind {
+            ScenarioTaskKind::NeuroZk => TaskKind::NeuroZK,
+            ScenarioTaskKind::OpenInference => TaskKind::OpenInference,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            Error::Custom(format!("Failed to read scenario {}: {}", path.display(), e))
+        })?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::Custom(format!("Invalid scenario {}: {}", path.display(), e)))
+    }
+}
+
+/// `ChainApi` of a simulated run, submissions are printed instead of sent. The simulated miner also plays the
+/// gatekeeper and the task owners, so archives are verified against signatures of its own key and NeuroZK tasks
+/// against the commitments of the scenario.
+pub struct SimulatedChain {
+    gatekeeper: AccountId32,
+    nzk_commitments: HashMap<u64, NzkCommitment>,
+}
+
+impl SimulatedChain {
+    /// # Arguments
+    /// * `keypair` - The keypair of the simulated miner
+    /// * `scenario` - The scenario to play
+    /// * `scenario_dir` - The directory the commitment files of the scenario are relative to
+    pub fn new(keypair: &Keypair, scenario: &Scenario, scenario_dir: &Path) -> Result<Self> {
+        let mut nzk_commitments = HashMap::new();
+        for step in &scenario.events {
+            match &step.event {
+                ScenarioEvent::TaskScheduled {
+                    task_id,
+                    commitment: Some(commitment),
+                    ..
+                }
+                | ScenarioEvent::BackupWorkerAssigned {
+                    task_id,
+                    commitment: Some(commitment),
+                    ..
+                } => {
+                    nzk_commitments.insert(*task_id, commitment.load(scenario_dir)?);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            gatekeeper: keypair.public_key().to_account_id(),
+            nzk_commitments,
+        })
+    }
+
+    fn submit(&self, call: String) -> Result<()> {
+        println!("🧪 [simulated chain] {}", call);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChainApi for SimulatedChain {
+    async fn get_task(&self, task_id: u64) -> Result<CyborgTask> {
+        Err(Error::Custom(format!(
+            "Task {} can't be queried in a simulation",
+            task_id
+        )))
+    }
+
+    async fn get_nzk_commitment(&self, task_id: u64) -> Result<Option<NzkCommitment>> {
+        Ok(self.nzk_commitments.get(&task_id).cloned())
+    }
+
+    async fn get_gatekeeper(&self) -> Result<AccountId32> {
+        Ok(self.gatekeeper.clone())
+    }
+
+    async fn iter_workers(&self) -> Result<Vec<(AccountId32, u64)>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_registered_spec(&self, _: &AccountId32, _: u64) -> Result<HardwareSpec> {
+        Err("Registered specs can't be queried in a simulation".into())
+    }
+
+    async fn get_registered_domain(&self, _: &AccountId32, _: u64) -> Result<String> {
+        Err("Registered domains can't be queried in a simulation".into())
+    }
+
+    async fn register(&self, keypair: Keypair) -> Result<(AccountId32, u64)> {
+        self.submit("register".to_string())?;
+        Ok((keypair.public_key().to_account_id(), 0))
+    }
+
+    async fn remove_worker(&self, _: Keypair, miner_id: u64) -> Result<()> {
+        self.submit(format!("remove worker {}", miner_id))
+    }
+
+    async fn confirm_reception(&self, _: Keypair, task_id: u64) -> Result<()> {
+        self.submit(format!("confirm reception of task {}", task_id))
+    }
+
+    async fn confirm_vacation(&self, _: Keypair, task_id: u64) -> Result<()> {
+        self.submit(format!("confirm vacation of task {}", task_id))
+    }
+
+    async fn submit_proof(&self, _: Keypair, task_id: u64, proof: Vec<u8>) -> Result<()> {
+        self.submit(format!(
+            "submit proof of task {} ({} bytes)",
+            task_id,
+            proof.len()
+        ))
+    }
+
+    async fn submit_proof_reference(
+        &self,
+        _: Keypair,
+        task_id: u64,
+        proof_hash: &str,
+        proof: &str,
+    ) -> Result<()> {
+        self.submit(format!(
+            "submit proof reference of task {}: {} at {}",
+            task_id, proof_hash, proof
+        ))
+    }
+
+    async fn decline_task(&self, _: Keypair, task_id: u64, reason: &str) -> Result<()> {
+        self.submit(format!("decline task {}: {}", task_id, reason))
+    }
+
+    async fn report_integrity_mismatch(
+        &self,
+        _: Keypair,
+        task_id: u64,
+        mismatch: &str,
+    ) -> Result<()> {
+        self.submit(format!(
+            "report integrity mismatch of task {}: {}",
+            task_id, mismatch
+        ))
+    }
+
+    async fn publish_capabilities(
+        &self,
+        _: Keypair,
+        _: (AccountId32, u64),
+        capabilities: &Capabilities,
+    ) -> Result<()> {
+        self.submit(format!("publish capabilities {:?}", capabilities))
+    }
+
+    async fn publish_endpoint(
+        &self,
+        _: Keypair,
+        _: (AccountId32, u64),
+        endpoint: &str,
+    ) -> Result<()> {
+        self.submit(format!("publish endpoint {}", endpoint))
+    }
+}
+
+/// Runs the miner against a scripted scenario instead of a parachain, so the full task lifecycle can be tested locally.
+/// Paths and the storage location are read from the environment like for a real run, the task archives of the
+/// scenario are downloaded from the storage location and must be signed with the account seed, which stands in for
+/// the gatekeeper. Proofs of the on-chain input can't be generated, everything else runs as it would on chain.
+///
+/// # Arguments
+/// * `account_seed` - The secret URI the keypair of the simulated miner is derived from
+/// * `scenario_path` - The JSON scenario to play
+///
+/// # Returns
+/// `Ok(())` once the scenario finished and no task is served anymore, or an `Error` if the scenario is invalid or an
+/// event couldn't be handled
+pub async fn run_simulation(account_seed: &str, scenario_path: &Path) -> Result<()> {
+    let scenario = Scenario::load(scenario_path)?;
+    let uri = SecretUri::from_str(account_seed)
+        .map_err(|e| Error::Custom(format!("Invalid account seed: {}", e)))?;
+    let keypair =
+        Keypair::from_uri(&uri).map_err(|e| Error::Custom(format!("Invalid keypair: {}", e)))?;
+
+    config::init_simulation_config();
+    config::init_config_encryption(account_seed)?;
+
+    let scenario_dir = scenario_path.parent().unwrap_or(Path::new("."));
+    let chain: Arc<dyn ChainApi> =
+        Arc::new(SimulatedChain::new(&keypair, &scenario, scenario_dir)?);
+    let identity = chain.register(keypair.clone()).await?;
+
+    // The simulated identity must never replace the identity of a registered miner
+    let identity_path = &config::get_paths()?.identity_path;
+    if let Some(existing) = schema::read_identity(identity_path)? {
+        if existing.as_tuple() != identity {
+            return Err(Error::Custom(format!(
+                "Another identity exists at {}, simulations need their own IDENTITY_FILE_PATH",
+                identity_path
+            )));
+        }
+    }
+    schema::write_identity(identity_path, identity.0.clone(), identity.1)?;
+
+    let mut miner = MinerBuilder::default()
+        .keypair(keypair)
+        .chain(chain)
+        .config()?
+        .build()
+        .await?;
+
+    println!(
+        "🧪 Simulating {} events from {}",
+        scenario.events.len(),
+        scenario_path.display()
+    );
+
+    for step in scenario.events {
+        if step.delay_secs > 0 {
+            tokio::time::sleep(Duration::from_secs(step.delay_secs)).await;
+        }
+
+        println!("🧪 Simulated event: {:?}", step.event);
+        handle_event(&mut miner, chain_event(step.event, &identity)).await?;
+    }
+
+    match &miner.current_task {
+        Some(current_task) => {
+            println!(
+                "🧪 Scenario finished, task {} is served until the miner is stopped",
+                current_task.id
+            );
+            std::future::pending::<()>().await;
+            Ok(())
+        }
+        None => {
+            println!("🧪 Scenario finished");
+            Ok(())
+        }
+    }
+}
+
+/// The chain event a scenario event stands for, as the simulated miner would receive it
+fn chain_event(event: ScenarioEvent, identity: &(AccountId32, u64)) -> ChainEvent {
+    match event {
+        ScenarioEvent::TaskScheduled {
+            task_id,
+            task_kind,
+            task,
+            other_miner,
+            ..
+        } => ChainEvent::TaskScheduled(TaskScheduled {
+            assigned_worker: if other_miner {
+                (identity.0.clone(), identity.1 + 1)
+            } else {
+                identity.clone()
+            },
+            task_kind: task_kind.into(),
+            task_owner: identity.0.clone(),
+            task_id,
+            task: BoundedVec(task.into_bytes()),
+        }),
+        ScenarioEvent::NzkProofRequested { task_id } => {
+            ChainEvent::NzkProofRequested(NzkProofRequested {
+                requesting_account: identity.0.clone(),
+                task_id,
+            })
+        }
+        ScenarioEvent::TaskStopRequested { task_id } => {
+            ChainEvent::TaskStopRequested(TaskStopRequested { task_id })
+        }
+        ScenarioEvent::BackupWorkerAssigned {
+            task_id,
+            task_kind,
+            task,
+            ..
+        } => ChainEvent::BackupWorkerAssigned(BackupWorkerAssigned {
+            backup_worker: identity.clone(),
+            task_kind: task_kind.into(),
+            task_owner: identity.0.clone(),
+            task_id,
+            task: BoundedVec(task.into_bytes()),
+        }),
+        ScenarioEvent::BackupWorkerPromoted { task_id } => {
+            ChainEvent::BackupWorkerPromoted(BackupWorkerPromoted {
+                task_id,
+                backup_worker: identity.clone(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenarios_parse_with_default_delays() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{"events":[
+                {"event":"TaskScheduled","task_id":1,"task_kind":"neuro_zk","task":"abc"},
+                {"delay_secs":5,"event":"NzkProofRequested","task_id":1},
+                {"event":"TaskStopRequested","task_id":1}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(scenario.events.len(), 3);
+        assert_eq!(scenario.events[0].delay_secs, 0);
+        assert_eq!(
+            scenario.events[0].event,
+            ScenarioEvent::TaskScheduled {
+                task_id: 1,
+                task_kind: ScenarioTaskKind::NeuroZk,
+                task: "abc".to_string(),
+                other_miner: false,
+                commitment: None,
+            }
+        );
+        assert_eq!(scenario.events[1].delay_secs, 5);
+        assert_eq!(
+            scenario.events[2].event,
+            ScenarioEvent::TaskStopRequested { task_id: 1 }
+        );
+    }
+
+    #[tokio::test]
+    async fn the_simulated_chain_publishes_the_scenario_commitments() {
+        let dir = std::env::temp_dir().join("cyborg-simulation-test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("settings.json"), r#"{"scale":7}"#).unwrap();
+        let scenario: Scenario = serde_json::from_str(
+            r#"{"events":[
+                {"event":"TaskScheduled","task_id":1,"task_kind":"neuro_zk","task":"abc","commitment":{"settings":"settings.json"}},
+                {"event":"TaskScheduled","task_id":2,"task_kind":"open_inference","task":"def"}
+            ]}"#,
+        )
+        .unwrap();
+        let keypair = Keypair::from_uri(&SecretUri::from_str("//Alice").unwrap()).unwrap();
+
+        let chain = SimulatedChain::new(&keypair, &scenario, &dir).unwrap();
+        let commitment = chain.get_nzk_commitment(1).await.unwrap().unwrap();
+        assert_eq!(commitment.zk_settings, br#"{"scale":7}"#);
+        assert!(commitment.zk_input.is_empty());
+        assert!(chain.get_nzk_commitment(2).await.unwrap().is_none());
+        assert_eq!(
+            chain.get_gatekeeper().await.unwrap(),
+            keypair.public_key().to_account_id()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
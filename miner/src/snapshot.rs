@@ -0,0 +1,556 @@
+use crate::{
+    config::{self, Paths},
+    error::{Error, Result},
+    parachain_interactor::fingerprint,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Component, Path, PathBuf},
+};
+
+const SNAPSHOT_VERSION: u32 = 1;
+/// First entry of every snapshot, describes the files that follow
+const METADATA_ENTRY: &str = "snapshot.json";
+
+/// Locations of the snapshot entries, the files are restored to wherever the environment of the new host puts them
+const IDENTITY_ENTRY: &str = "identity/identity.json";
+const TASK_OWNER_ENTRY: &str = "identity/task_owner.json";
+const RESPONSE_ANCHORS_PREFIX: &str = "identity/response-anchors";
+const TASK_PREFIX: &str = "task";
+
+/// Files of the task directory that are always bundled, model files only with `--include-models`. The NeuroZK setup
+/// progress and the small files of its steps are task state, so that a restored or restarted miner resumes its setup.
+/// The inference sessions are too, so that clients reconnecting after a restart learn that their context is lost, and
+/// the digest of the archive whose signature was verified, so that a restored OpenInference model can be verified.
+const TASK_STATE_FILES: [&str; 7] = [
+    "manifest.json",
+    "proof-input.json",
+    "setup-progress.json",
+    "settings.json",
+    "input.json",
+    "inference_sessions.json",
+    "signed-archive.sha256",
+];
+/// Outputs of the NeuroZK setup steps that are as large as the model, kept at startup but only bundled with
+/// `--include-models`
+const SETUP_OUTPUT_FILES: [&str; 2] = ["network.ezkl", "pk.key"];
+const SRS_EXTENSION: &str = "srs";
+/// Lists the model files a restore put in the task directory, so that the next start of the miner keeps them
+const RESTORED_MODELS_FILE: &str = "restored-models.json";
+/// Appended to the files of a snapshot being restored until the whole snapshot is verified
+const STAGED_SUFFIX: &str = ".restoring";
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotMetadata {
+    version: u32,
+    created_at: String,
+    miner_version: String,
+    /// Files contained in the snapshot
+    files: Vec<SnapshotFile>,
+    /// Model files that were left out, they are fetched again when the task is assigned on the new host
+    models: Vec<SnapshotFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Bundles the identity, the task owner, the response anchors and the state of the current task into a gzipped
+/// tarball, so the miner can move to new hardware without registering again. The account seed is not part of the
+/// snapshot, it has to be passed to the miner on the new host as before.
+///
+/// # Arguments
+/// * `output` - The path of the snapshot to write
+/// * `include_models` - Whether to bundle the model files of the current task, which can be large
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the snapshot was written, or an `Error` if a file could not be read.
+pub fn create_snapshot(output: &Path, include_models: bool) -> Result<()> {
+    config::load_dotenv();
+    let paths = config::paths_from_env();
+
+    let (entries, left_out) = collect_entries(&paths, include_models)?;
+    if entries.is_empty() {
+        return Err(Error::Custom(
+            "Nothing to snapshot, the miner has no identity or task state yet".to_string(),
+        ));
+    }
+
+    let metadata = SnapshotMetadata {
+        version: SNAPSHOT_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        miner_version: env!("CARGO_PKG_VERSION").to_string(),
+        files: entries
+            .iter()
+            .map(|(entry, source)| describe(entry, source))
+            .collect::<Result<_>>()?,
+        models: left_out
+            .iter()
+            .map(|(entry, source)| describe(entry, source))
+            .collect::<Result<_>>()?,
+    };
+
+    // The snapshot contains the identity, it is as sensitive as the identity file itself
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(output)?;
+    // The mode above only applies to newly created files
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let metadata_json = serde_json::to_vec_pretty(&metadata)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(metadata_json.len() as u64);
+    header.set_mode(0o600);
+    header.set_cksum();
+    archive.append_data(&mut header, METADATA_ENTRY, metadata_json.as_slice())?;
+
+    for (entry, source) in &entries {
+        archive.append_path_with_name(source, entry)?;
+    }
+    archive.into_inner()?.finish()?;
+
+    println!(
+        "Snapshot written to {}: {} files, {} model files {}",
+        output.display(),
+        metadata.files.len(),
+        metadata.models.len(),
+        if include_models {
+            "included"
+        } else {
+            "left out"
+        }
+    );
+
+    Ok(())
+}
+
+/// Restores a snapshot created by `create_snapshot` to the paths configured in the environment. Every file is written
+/// next to its target and checked against the hashes in the snapshot before any file is replaced, and an existing
+/// identity is only replaced with `force`.
+///
+/// # Arguments
+/// * `input` - The path of the snapshot
+/// * `force` - Whether to replace an existing identity
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the snapshot was restored, or an `Error` if it is invalid or would overwrite an identity.
+pub fn restore_snapshot(input: &Path, force: bool) -> Result<()> {
+    config::load_dotenv();
+    let paths = config::paths_from_env();
+
+    if Path::new(&paths.identity_path).exists() && !force {
+        return Err(Error::Custom(format!(
+            "{} already exists, pass --force to replace the identity of this miner",
+            paths.identity_path
+        )));
+    }
+
+    let (metadata, staged) = read_snapshot(input, &paths)?;
+
+    // Model files restored with the snapshot belong to the task of the restored identity
+    let restored_models: Vec<&str> = staged
+        .iter()
+        .filter_map(|file| file.entry.strip_prefix(&format!("{}/", TASK_PREFIX)))
+        .filter(|relative| !is_task_state(Path::new(relative)))
+        .collect();
+
+    for file in &staged {
+        fs::rename(&file.staged, &file.target)?;
+    }
+
+    if !restored_models.is_empty() {
+        fs::write(
+            Path::new(&paths.task_dir_path).join(RESTORED_MODELS_FILE),
+            serde_json::to_vec(&restored_models)?,
+        )?;
+    }
+
+    // The identity moved to this host
+    fingerprint::unbind_identity(&paths.identity_path)?;
+
+    println!(
+        "Restored {} files from the snapshot created at {} by miner {}",
+        staged.len(),
+        metadata.created_at,
+        metadata.miner_version
+    );
+    for model in &metadata.models {
+        println!(
+            "  Not included: {} ({} bytes), fetched again with the next task",
+            model.path, model.size
+        );
+    }
+
+    Ok(())
+}
+
+/// A file of a snapshot written next to its target, moved into place once the whole snapshot is verified
+struct StagedFile {
+    entry: String,
+    staged: PathBuf,
+    target: PathBuf,
+}
+
+/// Streams all entries of a snapshot next to their targets and verifies them, so that an invalid snapshot doesn't leave
+/// a half restored miner. The staged files are removed again if the snapshot is invalid.
+fn read_snapshot(input: &Path, paths: &Paths) -> Result<(SnapshotMetadata, Vec<StagedFile>)> {
+    let mut staged = Vec::new();
+    match stage_snapshot(input, paths, &mut staged) {
+        Ok(metadata) => Ok((metadata, staged)),
+        Err(e) => {
+            for file in &staged {
+                fs::remove_file(&file.staged).ok();
+            }
+            Err(e)
+        }
+    }
+}
+
+fn stage_snapshot(
+    input: &Path,
+    paths: &Paths,
+    staged: &mut Vec<StagedFile>,
+) -> Result<SnapshotMetadata> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(input)?));
+
+    let mut metadata: Option<SnapshotMetadata> = None;
+    let mut hashes = HashMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+
+        if name == METADATA_ENTRY {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            metadata = Some(serde_json::from_slice(&content)?);
+            continue;
+        }
+
+        let target = target_path(paths, &name)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut staged_name = target.file_name().unwrap_or_default().to_os_string();
+        staged_name.push(STAGED_SUFFIX);
+        let staged_path = target.with_file_name(staged_name);
+
+        // A file left by an interrupted restore would keep its mode
+        fs::remove_file(&staged_path).ok();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        if name.starts_with("identity/") {
+            options.mode(0o600);
+        }
+        let mut file = options.open(&staged_path)?;
+        staged.push(StagedFile {
+            entry: name.clone(),
+            staged: staged_path,
+            target,
+        });
+
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = entry.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            file.write_all(&buffer[..read])?;
+        }
+        hashes.insert(name, hex::encode(hasher.finalize()));
+    }
+
+    let metadata = metadata.ok_or(Error::Custom(format!(
+        "{} is not a miner snapshot",
+        input.display()
+    )))?;
+    if metadata.version > SNAPSHOT_VERSION {
+        return Err(Error::Custom(format!(
+            "Snapshot version {} is newer than this miner supports ({})",
+            metadata.version, SNAPSHOT_VERSION
+        )));
+    }
+
+    let expected: HashMap<&str, &SnapshotFile> = metadata
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect();
+    if expected.len() != hashes.len() {
+        return Err(Error::Custom(format!(
+            "Snapshot lists {} files but contains {}",
+            expected.len(),
+            hashes.len()
+        )));
+    }
+    for (entry, hash) in &hashes {
+        let file = expected.get(entry.as_str()).ok_or(Error::Custom(format!(
+            "Snapshot contains unlisted file {}",
+            entry
+        )))?;
+        if *hash != file.sha256 {
+            return Err(Error::Custom(format!(
+                "{} is corrupted in the snapshot",
+                entry
+            )));
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn collect_entries(
+    paths: &Paths,
+    include_models: bool,
+) -> Result<(Vec<(String, PathBuf)>, Vec<(String, PathBuf)>)> {
+    let mut entries = Vec::new();
+    let mut left_out = Vec::new();
+
+    for (entry, path) in [
+        (IDENTITY_ENTRY, &paths.identity_path),
+        (TASK_OWNER_ENTRY, &paths.task_owner_path),
+    ] {
+        if Path::new(path).is_file() {
+            entries.push((entry.to_string(), PathBuf::from(path)));
+        }
+    }
+
+    let anchors_dir = response_anchors_dir(paths);
+    for (relative, source) in files_below(&anchors_dir)? {
+        entries.push((format!("{}/{}", RESPONSE_ANCHORS_PREFIX, relative), source));
+    }
+
+    for (relative, source) in files_below(Path::new(&paths.task_dir_path))? {
+        let entry = format!("{}/{}", TASK_PREFIX, relative);
+        if include_models || is_task_state(&source) {
+            entries.push((entry, source));
+        } else {
+            left_out.push((entry, source));
+        }
+    }
+
+    Ok((entries, left_out))
+}
+
+/// Takes the list of model files the last restore put in the task directory. The list is removed, so the files are only
+/// kept on the first start after the restore.
+///
+/// # Arguments
+/// * `task_dir` - The task directory the snapshot was restored to
+///
+/// # Returns
+/// The restored model files relative to the task directory, empty if no snapshot with models was restored since
+pub fn take_restored_models(task_dir: &Path) -> Result<Vec<PathBuf>> {
+    let path = task_dir.join(RESTORED_MODELS_FILE);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let restored: Vec<PathBuf> = serde_json::from_slice(&fs::read(&path)?)?;
+    fs::remove_file(path)?;
+    Ok(restored)
+}
+
+/// Outputs of the NeuroZK setup that a restarted miner resumes from, the setup progress binds them to their archive
+pub fn is_setup_output(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| SETUP_OUTPUT_FILES.contains(&name))
+}
+
+/// Small files that are expensive to obtain again: the manifest, the last proof input, the setup progress and cached
+/// SRS
+pub fn is_task_state(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    TASK_STATE_FILES.contains(&name)
+        || path.extension().and_then(|extension| extension.to_str()) == Some(SRS_EXTENSION)
+}
+
+/// Maps a snapshot entry to its location on this host, entries that could escape their directory are rejected
+fn target_path(paths: &Paths, entry: &str) -> Result<PathBuf> {
+    let invalid = || Error::Custom(format!("Invalid snapshot entry {}", entry));
+
+    if Path::new(entry)
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return Err(invalid());
+    }
+
+    match entry {
+        IDENTITY_ENTRY => Ok(PathBuf::from(&paths.identity_path)),
+        TASK_OWNER_ENTRY => Ok(PathBuf::from(&paths.task_owner_path)),
+        _ => {
+            if let Some(relative) = entry.strip_prefix(&format!("{}/", RESPONSE_ANCHORS_PREFIX)) {
+                Ok(response_anchors_dir(paths).join(relative))
+            } else if let Some(relative) = entry.strip_prefix(&format!("{}/", TASK_PREFIX)) {
+                Ok(Path::new(&paths.task_dir_path).join(relative))
+            } else {
+                Err(invalid())
+            }
+        }
+    }
+}
+
+/// Same location `response_anchor` persists its batches to
+fn response_anchors_dir(paths: &Paths) -> PathBuf {
+    Path::new(&paths.identity_path)
+        .parent()
+        .map(|dir| dir.join("response-anchors"))
+        .unwrap_or_else(|| PathBuf::from("response-anchors"))
+}
+
+/// All files below `dir` with their path relative to it, nothing if `dir` doesn't exist
+fn files_below(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.is_file() {
+                let relative = path
+                    .strip_prefix(dir)
+                    .map_err(Error::custom)?
+                    .to_string_lossy()
+                    .to_string();
+                files.push((relative, path));
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn describe(entry: &str, source: &Path) -> Result<SnapshotFile> {
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut File::open(source)?, &mut hasher)?;
+
+    Ok(SnapshotFile {
+        path: entry.to_string(),
+        size,
+        sha256: hex::encode(hasher.finalize()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths() -> Paths {
+        Paths {
+            log_path: PathBuf::from("/miner/logs/worker_log.txt"),
+            task_file_name: "model.tar.gz".to_string(),
+            task_dir_path: "/miner/task/current_task".to_string(),
+            task_owner_path: "/miner/config/task_owner.json".to_string(),
+            identity_path: "/miner/config/identity.json".to_string(),
+        }
+    }
+
+    #[test]
+    fn entries_map_to_configured_paths() {
+        assert_eq!(
+            target_path(&paths(), "task/kzg.srs").unwrap(),
+            PathBuf::from("/miner/task/current_task/kzg.srs")
+        );
+        assert_eq!(
+            target_path(&paths(), "identity/response-anchors/ab.json").unwrap(),
+            PathBuf::from("/miner/config/response-anchors/ab.json")
+        );
+    }
+
+    #[test]
+    fn corrupted_snapshots_are_not_restored() {
+        let root = std::env::temp_dir().join(format!("miner-snapshot-{}", std::process::id()));
+        let paths = Paths {
+            log_path: root.join("worker_log.txt"),
+            task_file_name: "model.tar.gz".to_string(),
+            task_dir_path: root.join("task").to_string_lossy().to_string(),
+            task_owner_path: root
+                .join("config/task_owner.json")
+                .to_string_lossy()
+                .to_string(),
+            identity_path: root
+                .join("config/identity.json")
+                .to_string_lossy()
+                .to_string(),
+        };
+        fs::create_dir_all(&root).unwrap();
+
+        let metadata = SnapshotMetadata {
+            version: SNAPSHOT_VERSION,
+            created_at: String::new(),
+            miner_version: String::new(),
+            files: vec![SnapshotFile {
+                path: "task/manifest.json".to_string(),
+                size: 2,
+                sha256: hex::encode(Sha256::digest(b"{}")),
+            }],
+            models: Vec::new(),
+        };
+        let write_snapshot = |content: &[u8]| {
+            let snapshot = root.join("snapshot.tar.gz");
+            let mut archive = tar::Builder::new(GzEncoder::new(
+                File::create(&snapshot).unwrap(),
+                Compression::default(),
+            ));
+            for (name, data) in [
+                (METADATA_ENTRY, serde_json::to_vec(&metadata).unwrap()),
+                ("task/manifest.json", content.to_vec()),
+            ] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o600);
+                header.set_cksum();
+                archive
+                    .append_data(&mut header, name, data.as_slice())
+                    .unwrap();
+            }
+            archive.into_inner().unwrap().finish().unwrap();
+            snapshot
+        };
+
+        let corrupted = write_snapshot(b"[]");
+        assert!(read_snapshot(&corrupted, &paths).is_err());
+        assert_eq!(fs::read_dir(root.join("task")).unwrap().count(), 0);
+
+        let valid = write_snapshot(b"{}");
+        let (_, staged) = read_snapshot(&valid, &paths).unwrap();
+        assert_eq!(staged.len(), 1);
+        assert_eq!(fs::read(&staged[0].staged).unwrap(), b"{}");
+        assert_eq!(staged[0].target, root.join("task/manifest.json"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn entries_escaping_their_directory_are_rejected() {
+        assert!(target_path(&paths(), "task/../../etc/passwd").is_err());
+        assert!(target_path(&paths(), "/etc/passwd").is_err());
+        assert!(target_path(&paths(), "other/file").is_err());
+    }
+}
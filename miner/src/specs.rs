@@ -1,15 +1,24 @@
+use open_inference_runtime::ConfigGeneration;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::process::{Command, Stdio};
+use std::time::Duration;
 use std::{env, str};
 use sysinfo::{MemoryRefreshKind, RefreshKind, System};
 
 use crate::{
-    error::Result,
+    config,
+    error::{Error, Result},
+    utils::blocking::run_blocking,
     /*substrate_interface::api::runtime_types::bounded_collections::bounded_vec::BoundedVec,*/
-    types::{IpResponse, MinerConfig},
+    types::{Capabilities, HardwareSpec, IpResponse, MinerConfig, TaskType},
 };
 
+/// Relative deviation (in percent) of RAM or storage from the registered value above which the miner re-registers
+const SPEC_CHANGE_TOLERANCE_PERCENT: u64 = 10;
+/// RAM below which NeuroZK tasks are declined unless `NZK_MIN_PROVING_RAM_BYTES` is set, as proving would run out of memory
+const DEFAULT_MIN_PROVING_RAM_BYTES: u64 = 8 * 1024 * 1024 * 1024;
+
 #[derive(Deserialize, Debug)]
 struct IpLocation {
     loc: Option<String>,
@@ -23,21 +32,8 @@ pub struct Location {
 }
 
 pub async fn gather_worker_spec() -> Result<MinerConfig> {
-    println!(
-        "Using IP: {}",
-        env::var("CYBORG_WORKER_NODE_TEST_IP").unwrap_or("".to_string())
-    );
-
-    let response = match env::var("CYBORG_WORKER_NODE_TEST_IP") {
-        Ok(val) => val,
-        Err(_) => {
-            reqwest::get("https://api.ipify.org?format=json")
-                .await?
-                .json::<IpResponse>()
-                .await?
-                .ip
-        }
-    };
+    let endpoint = resolve_endpoint().await?;
+    println!("Using endpoint: {}", endpoint);
 
     //let response = worker::IpResponse { ip: String::from("127.0.0.1") };
 
@@ -50,7 +46,7 @@ pub async fn gather_worker_spec() -> Result<MinerConfig> {
     let storage = return_total_storage();
 
     Ok(MinerConfig {
-        domain: response, 
+        domain: endpoint,
         latitude: location.coordinates.0,
         longitude: location.coordinates.1,
         ram,
@@ -59,6 +55,302 @@ pub async fn gather_worker_spec() -> Result<MinerConfig> {
     })
 }
 
+/// Resolves the endpoint clients reach the miner at, in this order: `CYBORG_WORKER_NODE_TEST_IP`, `MINER_ENDPOINT`
+/// (a domain or tailnet name), the tailnet name of the host if Tailscale is running and, only if
+/// `ENDPOINT_IP_FALLBACK` is enabled, the public IP as reported by api.ipify.org.
+///
+/// # Returns
+/// The endpoint, or an `Error` if none of the sources is available
+pub async fn resolve_endpoint() -> Result<String> {
+    for key in ["CYBORG_WORKER_NODE_TEST_IP", "MINER_ENDPOINT"] {
+        let endpoint = config::optional_env(key, String::new());
+        if !endpoint.is_empty() {
+            return Ok(endpoint);
+        }
+    }
+
+    if let Some(name) = run_blocking(|| Ok(tailnet_name())).await? {
+        return Ok(name);
+    }
+
+    if !config::optional_env("ENDPOINT_IP_FALLBACK", false) {
+        return Err(Error::Custom(
+            "No endpoint configured: set MINER_ENDPOINT, join a tailnet or enable ENDPOINT_IP_FALLBACK".to_string(),
+        ));
+    }
+
+    let request = config::http_client()?.get("https://api.ipify.org?format=json");
+    Ok(config::send_with_retries(request)
+        .await?
+        .json::<IpResponse>()
+        .await?
+        .ip)
+}
+
+/// The MagicDNS name of the host, if it is part of a tailnet
+pub fn tailnet_name() -> Option<String> {
+    let output = Command::new("tailscale")
+        .args(["status", "--json"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    let status: Value = serde_json::from_slice(&output.stdout).ok()?;
+    status["Self"]["DNSName"]
+        .as_str()
+        .map(|name| name.trim_end_matches('.').to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Gathers only the hardware related part of the miner specs, without any network lookups, so that it can be called periodically
+pub fn gather_hardware_spec() -> HardwareSpec {
+    HardwareSpec {
+        ram: return_total_memory(),
+        storage: return_total_storage(),
+        cpu: get_cpu_cores(),
+    }
+}
+
+/// Checks whether the current hardware differs from the registered hardware enough to warrant a re-registration.
+/// A changed CPU count always counts as material, RAM and storage are compared with a tolerance to ignore
+/// small fluctuations (eg. mounted volumes or reserved memory).
+pub fn spec_changed_materially(registered: &HardwareSpec, current: &HardwareSpec) -> bool {
+    registered.cpu != current.cpu
+        || deviates(registered.ram, current.ram)
+        || deviates(registered.storage, current.storage)
+}
+
+/// Gathers the runtime capabilities of the miner. Every probe is best effort, a missing tool is reported as an absent capability.
+///
+/// # Arguments
+/// * `task_dir` - The directory tasks are extracted to, its free space is reported as disk quota unless `DISK_QUOTA_BYTES` is set
+///
+/// # Returns
+/// The `Capabilities` of this miner
+pub async fn gather_capabilities(task_dir: &str) -> Capabilities {
+    let task_dir = task_dir.to_string();
+    // The probes run external commands, which must not block the main loop
+    let (docker_available, gpus, free_storage) = run_blocking(move || {
+        Ok((
+            command_succeeds("docker", &["info"]),
+            list_gpus(),
+            available_storage(&task_dir),
+        ))
+    })
+    .await
+    .unwrap_or_default();
+
+    let disk_quota = match env::var("DISK_QUOTA_BYTES").ok().and_then(|quota| quota.parse().ok()) {
+        Some(quota) => quota,
+        None => free_storage.unwrap_or(0),
+    };
+
+    // An engine is reported if a task of it would be accepted when it is scheduled
+    let mut engines = Vec::new();
+    for task_type in [TaskType::OpenInference, TaskType::NeuroZk] {
+        if unsupported_task_reason(&task_type).await.is_none() {
+            engines.push(engine_name(&task_type).to_string());
+        }
+    }
+    // ezkl is linked into the miner, it proves whenever the NeuroZK engine can serve tasks
+    let ezkl = engines.iter().any(|engine| engine == engine_name(&TaskType::NeuroZk));
+
+    Capabilities {
+        engines,
+        triton_available: triton_available().await,
+        docker_available,
+        gpus,
+        ezkl,
+        disk_quota,
+        hardware_fingerprint: None,
+    }
+}
+
+/// Whether the miner serves tasks of an engine, set per engine by `ENGINES`, eg. `ENGINES=open-inference:off` on a
+/// host without Triton, or `neuro-zk:on` to skip the checks of the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineMode {
+    /// Serves the tasks of the engine if the host can (default)
+    Auto,
+    /// Serves the tasks of the engine without checking the host
+    On,
+    /// Declines every task of the engine
+    Off,
+}
+
+/// The name of the engine serving tasks of a type, as reported in the capabilities and set in `ENGINES`
+pub fn engine_name(task_type: &TaskType) -> &'static str {
+    match task_type {
+        TaskType::OpenInference => "open-inference",
+        TaskType::NeuroZk => "neuro-zk",
+    }
+}
+
+/// The mode of an engine as set by `ENGINES`, a comma separated list of `<engine>:<auto|on|off>`
+pub fn engine_mode(engine: &str) -> EngineMode {
+    parse_engine_mode(&config::optional_env("ENGINES", String::new()), engine)
+}
+
+fn parse_engine_mode(setting: &str, engine: &str) -> EngineMode {
+    setting
+        .split(',')
+        .filter_map(|entry| entry.trim().split_once(':'))
+        .filter(|(name, _)| name.trim() == engine)
+        .map(|(_, mode)| match mode.trim() {
+            "on" | "true" => EngineMode::On,
+            "off" | "false" => EngineMode::Off,
+            _ => EngineMode::Auto,
+        })
+        .last()
+        .unwrap_or(EngineMode::Auto)
+}
+
+/// Checks whether the miner can serve a task of the given type, so that it is declined when it is scheduled instead
+/// of failing once the task owner relies on it, eg. at the first proof request.
+///
+/// # Returns
+/// Why the task can't be served, `None` if it can
+pub async fn unsupported_task_reason(task_type: &TaskType) -> Option<String> {
+    match engine_mode(engine_name(task_type)) {
+        EngineMode::Off => {
+            return Some(format!(
+                "the {} engine is disabled on this miner",
+                engine_name(task_type)
+            ))
+        }
+        EngineMode::On => return None,
+        EngineMode::Auto => {}
+    }
+
+    match task_type {
+        TaskType::NeuroZk => {
+            let required =
+                config::optional_env("NZK_MIN_PROVING_RAM_BYTES", DEFAULT_MIN_PROVING_RAM_BYTES);
+            let available = return_total_memory();
+            (available < required).then(|| {
+                format!(
+                    "proving needs {} bytes of RAM, the miner has {}",
+                    required, available
+                )
+            })
+        }
+        TaskType::OpenInference => (!triton_available().await)
+            .then(|| "Triton Inference Server is not available".to_string()),
+    }
+}
+
+/// How the Triton configuration of OpenInference models shipped without one is generated, `None` with
+/// `GENERATE_TRITON_CONFIG=false`. Models are placed on the GPU if the miner has one, requests are batched up to
+/// `TRITON_MAX_BATCH_SIZE` (default 8).
+pub async fn triton_config_generation() -> Option<ConfigGeneration> {
+    if !config::optional_env("GENERATE_TRITON_CONFIG", true) {
+        return None;
+    }
+
+    let gpu = run_blocking(|| Ok(!list_gpus().is_empty()))
+        .await
+        .unwrap_or(false);
+
+    Some(ConfigGeneration {
+        gpu,
+        max_batch_size: config::optional_env(
+            "TRITON_MAX_BATCH_SIZE",
+            open_inference_runtime::model_config::DEFAULT_MAX_BATCH_SIZE,
+        ),
+    })
+}
+
+/// The quota of the files extracted from a task archive: `MAX_EXTRACTED_BYTES`, by default 90% of the free space of
+/// the task directory, so that a single archive can't fill the disk every task runs on. A smaller size declared by the
+/// task manifest takes precedence.
+///
+/// # Arguments
+/// * `task_dir` - The directory the archive is extracted to
+/// * `declared` - The size declared by the task manifest, if any
+pub async fn extraction_quota(task_dir: &str, declared: Option<u64>) -> Option<u64> {
+    let configured = match env::var("MAX_EXTRACTED_BYTES")
+        .ok()
+        .and_then(|quota| quota.parse().ok())
+    {
+        Some(quota) => Some(quota),
+        None => {
+            let task_dir = task_dir.to_string();
+            run_blocking(move || Ok(available_storage(&task_dir)))
+                .await
+                .ok()
+                .flatten()
+                .map(|free_storage| free_storage / 10 * 9)
+        }
+    };
+
+    match (configured, declared) {
+        (Some(configured), Some(declared)) => Some(configured.min(declared)),
+        (configured, declared) => configured.or(declared),
+    }
+}
+
+pub async fn triton_available() -> bool {
+    let client = match config::http_client_builder()
+        .and_then(|builder| Ok(builder.timeout(Duration::from_secs(2)).build()?))
+    {
+        Ok(client) => client,
+        Err(_) => return false,
+    };
+
+    client
+        .get(format!("{}/health/ready", config::triton_url()))
+        .send()
+        .await
+        .map(|response| response.status().is_success())
+        .unwrap_or(false)
+}
+
+pub fn command_succeeds(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+fn list_gpus() -> Vec<String> {
+    let output = match Command::new("nvidia-smi")
+        .arg("--query-gpu=name,memory.total")
+        .arg("--format=csv,noheader")
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+pub fn available_storage(path: &str) -> Option<u64> {
+    let output = Command::new("df")
+        .arg("-B1")
+        .arg("--output=avail")
+        .arg(path)
+        .output()
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .nth(1)
+        .and_then(|line| line.trim().parse().ok())
+}
+
+fn deviates(registered: u64, current: u64) -> bool {
+    let difference = registered.abs_diff(current) as u128;
+    difference * 100 > registered as u128 * SPEC_CHANGE_TOLERANCE_PERCENT as u128
+}
+
 fn get_cpu_cores() -> u16 {
     let mut sys = System::new_all();
     sys.refresh_all();
@@ -126,7 +418,7 @@ fn get_gps_location() -> Result<(f64, f64)> {
 
 async fn get_ip_location() -> Result<(f64, f64)> {
     let url = "https://ipinfo.io/json";
-    let response = reqwest::get(url).await?;
+    let response = config::send_with_retries(config::http_client()?.get(url)).await?;
 
     if response.status().is_success() {
         let ip_info: IpLocation = response.json().await?;
@@ -223,3 +515,37 @@ pub fn return_total_storage() -> u64 {
     println!("Total disk space from /dev/: {}", total_space);
     total_space
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(ram: u64, storage: u64, cpu: u16) -> HardwareSpec {
+        HardwareSpec { ram, storage, cpu }
+    }
+
+    #[test]
+    fn test_small_fluctuations_are_ignored() {
+        let registered = spec(16_000, 500_000, 8);
+
+        assert!(!spec_changed_materially(&registered, &spec(15_500, 510_000, 8)));
+    }
+
+    #[test]
+    fn test_material_changes_are_detected() {
+        let registered = spec(16_000, 500_000, 8);
+
+        assert!(spec_changed_materially(&registered, &spec(8_000, 500_000, 8)));
+        assert!(spec_changed_materially(&registered, &spec(16_000, 250_000, 8)));
+        assert!(spec_changed_materially(&registered, &spec(16_000, 500_000, 4)));
+    }
+
+    #[test]
+    fn test_engine_modes_are_parsed_per_engine() {
+        let setting = "open-inference:off, neuro-zk : on";
+
+        assert_eq!(parse_engine_mode(setting, "open-inference"), EngineMode::Off);
+        assert_eq!(parse_engine_mode(setting, "neuro-zk"), EngineMode::On);
+        assert_eq!(parse_engine_mode("", "neuro-zk"), EngineMode::Auto);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{chain_properties, error::Result, substrate_interface};
+
+/// Prints the on-chain state of a task: its definition, the miner it is assigned to, its status and,
+/// for NeuroZK tasks, the state of its proofs. Only needs a parachain connection, no miner configuration.
+///
+/// # Arguments
+/// * `parachain_url` - The URL of the parachain node to query
+/// * `task_id` - The id of the task
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the task was found and printed, or an `Error` if it fails.
+pub async fn print_task_info(parachain_url: &str, task_id: u64) -> Result<()> {
+    let api = chain_properties::connect(parachain_url).await?;
+    let storage = api.storage().at_latest().await?;
+    let task_management = substrate_interface::api::storage().task_management();
+
+    let task = storage
+        .fetch(&task_management.tasks(task_id))
+        .await?
+        .ok_or(format!("Task {} not found", task_id))?;
+    let allocation = storage
+        .fetch(&task_management.task_allocations(task_id))
+        .await?;
+
+    println!("Task {}", task_id);
+    println!(
+        "  Owner:           {}",
+        chain_properties::format_account(&task.task_owner)
+    );
+    println!("  Kind:            {:?}", task.task_kind);
+    println!("  Status:          {:?}", task.task_status);
+    println!("  Created at:      block {}", task.create_block);
+    println!(
+        "  Metadata:        {}",
+        String::from_utf8_lossy(&task.metadata.0)
+    );
+
+    match allocation {
+        Some((owner, miner_id)) => println!(
+            "  Assigned miner:  {} / {}",
+            chain_properties::format_account(&owner),
+            miner_id
+        ),
+        None => println!("  Assigned miner:  none"),
+    }
+
+    println!(
+        "  Compute hours:   deposited {}, consumed {}",
+        display_optional(task.compute_hours_deposit),
+        display_optional(task.consume_compute_hours)
+    );
+    println!("  Time elapsed:    {}", display_optional(task.time_elapsed));
+
+    if let Some(result) = &task.result {
+        println!("  Result:          {}", String::from_utf8_lossy(&result.0));
+    }
+
+    if let Some(nzk_data) = &task.nzk_data {
+        println!("  NeuroZK:");
+        println!(
+            "    Committed input:    {} bytes, settings: {} bytes, verifying key: {} bytes",
+            nzk_data.zk_input.0.len(),
+            nzk_data.zk_settings.0.len(),
+            nzk_data.zk_verifying_key.0.len()
+        );
+
+        match &nzk_data.zk_proof {
+            Some(proof) => println!("    Pending proof:      {} bytes", proof.0.len()),
+            None => println!("    Pending proof:      none"),
+        }
+
+        match nzk_data.last_proof_accepted {
+            Some((true, block)) => println!("    Last proof:         accepted at block {}", block),
+            Some((false, block)) => println!("    Last proof:         rejected at block {}", block),
+            None => println!("    Last proof:         none verified yet"),
+        }
+    }
+
+    Ok(())
+}
+
+fn display_optional(value: Option<u32>) -> String {
+    value
+        .map(|value| value.to_string())
+        .unwrap_or("-".to_string())
+}
@@ -0,0 +1,267 @@
+use crate::{
+    admin::{self, AdminStatus},
+    config,
+    error::Result,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the terminal shows, kept between refreshes so a failed poll doesn't blank the screen
+#[derive(Default)]
+struct View {
+    status: Option<AdminStatus>,
+    /// Requests per second of every task, derived from the last two statuses
+    request_rates: HashMap<u64, f64>,
+    error: Option<String>,
+}
+
+/// Renders the live status of the miner process behind `admin_url` until the operator quits with `q`
+///
+/// # Arguments
+/// * `admin_url` - The admin API of the miner, eg. `http://127.0.0.1:7300`
+pub async fn run_top(admin_url: &str) -> Result<()> {
+    let client = config::http_client_builder()?
+        .timeout(Duration::from_secs(2))
+        .build()?;
+
+    let mut terminal = ratatui::init();
+    let result = refresh_until_quit(&mut terminal, &client, admin_url).await;
+    ratatui::restore();
+    result
+}
+
+async fn refresh_until_quit(
+    terminal: &mut DefaultTerminal,
+    client: &reqwest::Client,
+    admin_url: &str,
+) -> Result<()> {
+    let mut view = View::default();
+    let mut previous: Option<(Instant, AdminStatus)> = None;
+
+    loop {
+        match admin::fetch_status(client, admin_url).await {
+            Ok(status) => {
+                let now = Instant::now();
+                if let Some((fetched_at, previous_status)) = &previous {
+                    view.request_rates =
+                        request_rates(previous_status, &status, now.duration_since(*fetched_at));
+                }
+                previous = Some((now, status.clone()));
+                view.status = Some(status);
+                view.error = None;
+            }
+            Err(e) => {
+                view.error = Some(format!("Admin API unreachable at {}: {}", admin_url, e));
+            }
+        }
+
+        terminal.draw(|frame| render(frame, admin_url, &view))?;
+
+        // Waiting for keys blocks, the runtime keeps its other workers for the requests
+        if tokio::task::block_in_place(|| quit_requested(REFRESH_INTERVAL))? {
+            return Ok(());
+        }
+    }
+}
+
+/// Waits up to `timeout` for the operator to quit with `q`, `Esc` or `Ctrl+C`
+fn quit_requested(timeout: Duration) -> std::io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || !event::poll(remaining)? {
+            return Ok(false);
+        }
+
+        if let Event::Key(key) = event::read()? {
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press
+                && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c)
+            {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+fn request_rates(
+    previous: &AdminStatus,
+    current: &AdminStatus,
+    elapsed: Duration,
+) -> HashMap<u64, f64> {
+    let seconds = elapsed.as_secs_f64();
+    if seconds <= 0.0 {
+        return HashMap::new();
+    }
+
+    current
+        .tasks
+        .iter()
+        .filter_map(|task| {
+            let before = previous
+                .tasks
+                .iter()
+                .find(|previous_task| previous_task.task_id == task.task_id)?;
+            // A restarted server counts from zero again
+            let received = task
+                .requests_received
+                .checked_sub(before.requests_received)?;
+            Some((task.task_id, received as f64 / seconds))
+        })
+        .collect()
+}
+
+fn render(frame: &mut Frame, admin_url: &str, view: &View) {
+    let status = view.status.clone().unwrap_or_default();
+    let [header_area, tasks_area, gpus_area, logs_area] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(status.tasks.len().max(1) as u16 + 3),
+        Constraint::Length(status.gpus.len().max(1) as u16 + 3),
+        Constraint::Min(5),
+    ])
+    .areas(frame.area());
+
+    let load = match &status.load_shedding {
+        Some(reason) => Line::styled(
+            format!("Shedding load: {}", reason),
+            Style::default().fg(Color::Yellow),
+        ),
+        None => Line::from("Accepting load"),
+    };
+    let mut header = vec![
+        Line::from(format!("Transactions pending: {}", status.tx_queue_pending)),
+        load,
+    ];
+    if let Some(error) = &view.error {
+        header.push(Line::styled(error.clone(), Style::default().fg(Color::Red)));
+    }
+    frame.render_widget(
+        Paragraph::new(header).block(
+            Block::bordered().title(format!(" cyborg-miner top · {} · q to quit ", admin_url)),
+        ),
+        header_area,
+    );
+
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let task_rows = status.tasks.iter().map(|task| {
+        let status_style = match task.engine_status.as_str() {
+            "ready" => Style::default().fg(Color::Green),
+            status if status.starts_with("failed") => Style::default().fg(Color::Red),
+            status if status.starts_with("degraded") => Style::default().fg(Color::Yellow),
+            _ => Style::default(),
+        };
+        let addresses: Vec<String> = task
+            .addresses
+            .iter()
+            .map(|address| address.to_string())
+            .collect();
+
+        Row::new(vec![
+            Line::from(task.task_id.to_string()),
+            Line::styled(task.engine_status.clone(), status_style),
+            Line::from(task.requests_received.to_string()),
+            Line::from(
+                view.request_rates
+                    .get(&task.task_id)
+                    .map(|rate| format!("{:.1}", rate))
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            Line::from(addresses.join(", ")),
+        ])
+    });
+    frame.render_widget(
+        Table::new(
+            task_rows,
+            [
+                Constraint::Length(8),
+                Constraint::Percentage(35),
+                Constraint::Length(10),
+                Constraint::Length(8),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(Row::new(["Task", "Engine", "Requests", "Req/s", "Listening on"]).style(bold))
+        .block(Block::bordered().title(" Tasks ")),
+        tasks_area,
+    );
+
+    let gpu_rows = status.gpus.iter().map(|gpu| {
+        Row::new(vec![
+            gpu.name.clone(),
+            format!("{:.0}%", gpu.utilization_percent),
+            format!("{} / {} MiB", gpu.memory_used_mib, gpu.memory_total_mib),
+        ])
+    });
+    frame.render_widget(
+        Table::new(
+            gpu_rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(12),
+                Constraint::Length(24),
+            ],
+        )
+        .header(Row::new(["GPU", "Utilization", "Memory"]).style(bold))
+        .block(Block::bordered().title(" GPUs ")),
+        gpus_area,
+    );
+
+    // Only the newest lines that fit are shown
+    let visible_lines = logs_area.height.saturating_sub(2) as usize;
+    let logs: Vec<Line> = status.recent_logs
+        [status.recent_logs.len().saturating_sub(visible_lines)..]
+        .iter()
+        .map(|line| Line::from(line.clone()))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(logs).block(Block::bordered().title(" Recent logs ")),
+        logs_area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::TaskStatus;
+
+    fn status(requests: &[(u64, u64)]) -> AdminStatus {
+        AdminStatus {
+            tasks: requests
+                .iter()
+                .map(|(task_id, requests_received)| TaskStatus {
+                    task_id: *task_id,
+                    engine_status: "ready".to_string(),
+                    requests_received: *requests_received,
+                    addresses: Vec::new(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn request_rates_skip_new_and_restarted_servers() {
+        let rates = request_rates(
+            &status(&[(1, 10), (2, 50)]),
+            &status(&[(1, 30), (2, 5), (3, 7)]),
+            Duration::from_secs(2),
+        );
+
+        assert_eq!(rates.get(&1), Some(&10.0));
+        assert_eq!(rates.get(&2), None);
+        assert_eq!(rates.get(&3), None);
+    }
+}
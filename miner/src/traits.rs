@@ -1,15 +1,21 @@
 // use std::path::PathBuf;
 
 use crate::{
+    config,
     error::Result,
     parachain_interactor::{
-        behavior_control, event_processor, identity, registration::{self, RegistrationStatus}
+        behavior_control, event_processor, registration::{self, RegistrationStatus}
     },
     parent_runtime::{storage_interactor, inference, proof},
-    types::{CurrentTask, Miner, ParentRuntime},
+    types::{Capabilities, CurrentTask, HardwareSpec, Miner, ParentRuntime},
+    utils::{
+        substrate_queries::{self, CyborgTask, NzkCommitment},
+        tx_builder,
+    },
 };
 use async_trait::async_trait;
 use subxt::events::EventDetails;
+use subxt::utils::AccountId32;
 use subxt::PolkadotConfig;
 use subxt_signer::sr25519::Keypair;
 use tokio::task::JoinHandle;
@@ -19,11 +25,12 @@ pub trait InferenceServer {
     /// Downloads a model archive (containing the model and potential additional data eg. proving key) from CESS
     ///
     /// # Arguments
+    /// * `task_id` - The id of the task the archive is downloaded for, its signature must be for this task
     /// * `fid` - A `&str` representing the CESS fid (fiile ID) of the model archive
     ///
     /// # Returns
     /// A `Result` containing `Ok(())` if the model archive is successfully downloaded, or an `Error` if it fails.
-    async fn download_model_archive(&self, fid: &str, cipher: &str) -> Result<()>;
+    async fn download_model_archive(&self, task_id: u64, fid: &str, cipher: &str) -> Result<()>;
 
     /// Starts performing inference, selecting the correct inference engine based on the task type
     ///
@@ -36,23 +43,39 @@ pub trait InferenceServer {
 
     /// Generates a zkml proof for the model currently in execution.
     ///
+    /// # Arguments
+    /// * `task_id` - The id of the task the proof is generated for, used to report progress
+    ///
     /// # Returns
     /// A `Result` containing a vector of bytes representing the proof.
-    async fn generate_proof(&self) -> Result<Vec<u8>>;
+    async fn generate_proof(&self, task_id: u64) -> Result<Vec<u8>>;
+
+    /// Verifies the last proof generated for a task against its on-chain verifying key.
+    ///
+    /// # Arguments
+    /// * `task_id` - The id of the task whose proof is verified
+    ///
+    /// # Returns
+    /// A `Result` containing whether the proof verifies locally.
+    async fn verify_submitted_proof(&self, task_id: u64) -> Result<bool>;
 }
 
 #[async_trait]
 impl InferenceServer for ParentRuntime {
-    async fn download_model_archive(&self, cess_fid: &str, cipher: &str) -> Result<()> {
-        storage_interactor::download_model_archive(cess_fid, cipher).await
+    async fn download_model_archive(&self, task_id: u64, cess_fid: &str, cipher: &str) -> Result<()> {
+        storage_interactor::download_model_archive(self.chain.as_ref(), task_id, cess_fid, cipher).await
     }
 
     async fn spawn_inference_server(&self, current_task: &CurrentTask, keypair: &Keypair) -> Result<JoinHandle<()>> {
-        inference::spawn_inference_server(current_task, self.port, keypair).await
+        inference::spawn_inference_server(self.chain.clone(), current_task, self.port, keypair).await
     }
 
-    async fn generate_proof(&self) -> Result<Vec<u8>> {
-        proof::generate_proof().await
+    async fn generate_proof(&self, task_id: u64) -> Result<Vec<u8>> {
+        proof::generate_proof(task_id).await
+    }
+
+    async fn verify_submitted_proof(&self, task_id: u64) -> Result<bool> {
+        proof::verify_submitted_proof(task_id).await
     }
 }
 
@@ -68,6 +91,12 @@ pub trait ParachainInteractor {
     /// A `Result` indicating `Ok(true)` if successful, or an `Error` if confirmation fails.
     async fn confirm_registration(&self) -> Result<RegistrationStatus>;
 
+    /// Compares the local hardware with the specs registered on-chain and re-registers the miner if they changed materially.
+    ///
+    /// # Returns
+    /// A `Result` indicating `Ok(())` if the registered specs are up to date or were refreshed, or an `Error` if it fails.
+    async fn refresh_registered_spec(&mut self) -> Result<()>;
+
     /// Starts a miner by subscribing to events and listening to finalized blocks.
     ///
     /// # Returns
@@ -83,13 +112,6 @@ pub trait ParachainInteractor {
     /// An `Option<String>` containing relevant information derived from the event, or `None` if no information is extracted.
     async fn process_event(&mut self, event: &EventDetails<PolkadotConfig>) -> Result<()>;
 
-    /// Attempts to update the miner identity file.
-    ///
-    /// # Arguments
-    /// * `file_path` - A `&str` representing the path to the config file.
-    /// * `content` - A `&str` representing the content to be written to the config file.
-    fn update_identity_file(&self, path: &str, content: &str) -> Result<()>;
-
     //TODO this might also notify the user that the miner has been corrupted and that the current task should be pulled
     /// Suspends the miner by sending a transaction to the parachain that deactivates the miner for further tasks..
     ///
@@ -106,6 +128,10 @@ impl ParachainInteractor for Miner {
         registration::confirm_registration(self).await
     }
 
+    async fn refresh_registered_spec(&mut self) -> Result<()> {
+        registration::refresh_registered_spec(self).await
+    }
+
     async fn start_miner(&mut self) -> Result<()> {
         registration::start_miner(self).await
     }
@@ -114,11 +140,178 @@ impl ParachainInteractor for Miner {
         event_processor::process_event(self, event).await
     }
 
-    fn update_identity_file(&self, path: &str, content: &str) -> Result<()> {
-        identity::update_identity_file(path, content)
-    }
-
     async fn suspend_miner(&self) -> Result<()> {
         behavior_control::suspend_miner(self).await
     }
 }
+
+/// The storage queries and extrinsics of the parachain that the miner depends on. Registration and event processing
+/// go through this trait instead of calling subxt directly, so that they can be tested against a mock without a node.
+#[async_trait]
+pub trait ChainApi: Send + Sync {
+    /// Fetches a task from the task management pallet.
+    ///
+    /// # Arguments
+    /// * `task_id` - The id of the task
+    ///
+    /// # Returns
+    /// A `Result` containing the `CyborgTask`, or an `Error` if it doesn't exist.
+    #[allow(dead_code)]
+    async fn get_task(&self, task_id: u64) -> Result<CyborgTask>;
+
+    /// Fetches the artifacts the owner of a NeuroZK task committed to when scheduling it.
+    ///
+    /// # Arguments
+    /// * `task_id` - The id of the task
+    ///
+    /// # Returns
+    /// A `Result` containing the commitment, `None` if the task has none, or an `Error` if the task doesn't exist.
+    async fn get_nzk_commitment(&self, task_id: u64) -> Result<Option<NzkCommitment>>;
+
+    /// Fetches the gatekeeper account that signs task archives.
+    async fn get_gatekeeper(&self) -> Result<AccountId32>;
+
+    /// Lists the identities (owner and id) of all registered executable workers.
+    async fn iter_workers(&self) -> Result<Vec<(AccountId32, u64)>>;
+
+    /// Fetches the hardware spec a worker was registered with.
+    async fn get_registered_spec(&self, owner: &AccountId32, miner_id: u64) -> Result<HardwareSpec>;
+
+    /// Fetches the domain a worker was registered with.
+    async fn get_registered_domain(&self, owner: &AccountId32, miner_id: u64) -> Result<String>;
+
+    /// Registers the miner as a worker.
+    ///
+    /// # Returns
+    /// A `Result` containing the identity (owner and id) the chain assigned, or an `Error` if registration fails.
+    async fn register(&self, keypair: Keypair) -> Result<(AccountId32, u64)>;
+
+    /// Removes a worker of the miner from the chain.
+    async fn remove_worker(&self, keypair: Keypair, miner_id: u64) -> Result<()>;
+
+    /// Confirms that the miner received a task it was assigned.
+    async fn confirm_reception(&self, keypair: Keypair, task_id: u64) -> Result<()>;
+
+    /// Confirms that the miner stopped serving a task.
+    async fn confirm_vacation(&self, keypair: Keypair, task_id: u64) -> Result<()>;
+
+    /// Submits the zkml proof of a task.
+    async fn submit_proof(&self, keypair: Keypair, task_id: u64, proof: Vec<u8>) -> Result<()>;
+
+    /// Submits the hash and location of a proof uploaded to storage.
+    async fn submit_proof_reference(
+        &self,
+        keypair: Keypair,
+        task_id: u64,
+        proof_hash: &str,
+        proof: &str,
+    ) -> Result<()>;
+
+    /// Flags a task the miner can't serve.
+    async fn decline_task(&self, keypair: Keypair, task_id: u64, reason: &str) -> Result<()>;
+
+    /// Reports task artifacts that differ from what was committed to.
+    async fn report_integrity_mismatch(&self, keypair: Keypair, task_id: u64, mismatch: &str) -> Result<()>;
+
+    /// Publishes the runtime capabilities of a worker.
+    async fn publish_capabilities(
+        &self,
+        keypair: Keypair,
+        miner_identity: (AccountId32, u64),
+        capabilities: &Capabilities,
+    ) -> Result<()>;
+
+    /// Publishes the endpoint clients reach a worker at.
+    async fn publish_endpoint(
+        &self,
+        keypair: Keypair,
+        miner_identity: (AccountId32, u64),
+        endpoint: &str,
+    ) -> Result<()>;
+}
+
+/// `ChainApi` of the parachain the miner is connected to, through the client set up in `config`
+pub struct SubxtChain;
+
+#[async_trait]
+impl ChainApi for SubxtChain {
+    async fn get_task(&self, task_id: u64) -> Result<CyborgTask> {
+        substrate_queries::get_task(config::get_parachain_client()?, task_id).await
+    }
+
+    async fn get_nzk_commitment(&self, task_id: u64) -> Result<Option<NzkCommitment>> {
+        substrate_queries::get_nzk_commitment(config::get_parachain_client()?, task_id).await
+    }
+
+    async fn get_gatekeeper(&self) -> Result<AccountId32> {
+        substrate_queries::get_gatekeeper(config::get_parachain_client()?).await
+    }
+
+    async fn iter_workers(&self) -> Result<Vec<(AccountId32, u64)>> {
+        substrate_queries::get_workers(config::get_parachain_client()?).await
+    }
+
+    async fn get_registered_spec(&self, owner: &AccountId32, miner_id: u64) -> Result<HardwareSpec> {
+        substrate_queries::get_registered_spec(config::get_parachain_client()?, owner, miner_id).await
+    }
+
+    async fn get_registered_domain(&self, owner: &AccountId32, miner_id: u64) -> Result<String> {
+        substrate_queries::get_registered_domain(config::get_parachain_client()?, owner, miner_id).await
+    }
+
+    async fn register(&self, keypair: Keypair) -> Result<(AccountId32, u64)> {
+        tx_builder::register(keypair).await
+    }
+
+    async fn remove_worker(&self, keypair: Keypair, miner_id: u64) -> Result<()> {
+        tx_builder::remove_worker(keypair, miner_id).await
+    }
+
+    async fn confirm_reception(&self, keypair: Keypair, task_id: u64) -> Result<()> {
+        tx_builder::confirm_task_reception(keypair, task_id).await
+    }
+
+    async fn confirm_vacation(&self, keypair: Keypair, task_id: u64) -> Result<()> {
+        tx_builder::confirm_miner_vacation(keypair, task_id).await
+    }
+
+    async fn submit_proof(&self, keypair: Keypair, task_id: u64, proof: Vec<u8>) -> Result<()> {
+        tx_builder::submit_proof(proof, keypair, task_id).await
+    }
+
+    async fn submit_proof_reference(
+        &self,
+        keypair: Keypair,
+        task_id: u64,
+        proof_hash: &str,
+        proof: &str,
+    ) -> Result<()> {
+        tx_builder::submit_proof_reference(keypair, task_id, proof_hash, proof).await
+    }
+
+    async fn decline_task(&self, keypair: Keypair, task_id: u64, reason: &str) -> Result<()> {
+        tx_builder::flag_declined_task(keypair, task_id, reason).await
+    }
+
+    async fn report_integrity_mismatch(&self, keypair: Keypair, task_id: u64, mismatch: &str) -> Result<()> {
+        tx_builder::report_integrity_mismatch(keypair, task_id, mismatch).await
+    }
+
+    async fn publish_capabilities(
+        &self,
+        keypair: Keypair,
+        miner_identity: (AccountId32, u64),
+        capabilities: &Capabilities,
+    ) -> Result<()> {
+        tx_builder::publish_capabilities(keypair, miner_identity, capabilities).await
+    }
+
+    async fn publish_endpoint(
+        &self,
+        keypair: Keypair,
+        miner_identity: (AccountId32, u64),
+        endpoint: &str,
+    ) -> Result<()> {
+        tx_builder::publish_endpoint(keypair, miner_identity, endpoint).await
+    }
+}
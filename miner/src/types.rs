@@ -1,17 +1,12 @@
 // use crate::substrate_interface::api::runtime_types::bounded_collections::bounded_vec::BoundedVec;
-use codec::{Decode, Encode};
+use crate::substrate_interface::api::runtime_types::cyborg_primitives::task::TaskKind;
+use crate::traits::ChainApi;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use subxt::utils::AccountId32;
 use subxt_signer::sr25519::Keypair;
 use tokio::sync::RwLock;
-
-// Datastructure for worker registration persistence
-#[derive(Debug, Clone, PartialEq, Eq, Decode, Encode, Serialize, Deserialize)]
-pub struct MinerData {
-    pub miner_owner: String,
-    pub miner_identity: (AccountId32, u64),
-}
+use tokio::task::JoinHandle;
 
 #[derive(Clone, Debug)]
 pub struct CurrentTask {
@@ -19,16 +14,52 @@ pub struct CurrentTask {
     pub task_type: TaskType,
 }
 
+/// A task the miner is the backup miner of: its model is downloaded ahead, it is served once the miner is promoted
+#[derive(Debug)]
+pub struct StandbyTask {
+    pub id: u64,
+    pub task_type: TaskType,
+    pub task_owner: AccountId32,
+    pub storage_identifier: String,
+    /// The download of the model, resolves to whether it succeeded
+    pub download: JoinHandle<bool>,
+}
+
 #[derive(Clone, Debug)]
-#[allow(dead_code)]
 pub enum TaskType {
     OpenInference,
     NeuroZk,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct TaskOwner {
-    pub task_owner: String,
+impl From<&TaskKind> for TaskType {
+    fn from(kind: &TaskKind) -> Self {
+        match kind {
+            TaskKind::OpenInference => TaskType::OpenInference,
+            TaskKind::NeuroZK => TaskType::NeuroZk,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HardwareSpec {
+    pub ram: u64,
+    pub storage: u64,
+    pub cpu: u16,
+}
+
+/// Runtime capabilities of the miner, published on chain so that the scheduler and gatekeepers can filter compatible miners
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Capabilities {
+    pub engines: Vec<String>,
+    pub triton_available: bool,
+    pub docker_available: bool,
+    pub gpus: Vec<String>,
+    pub ezkl: bool,
+    pub disk_quota: u64,
+    /// Commitment to the hardware the miner runs on, only published with `PUBLISH_HARDWARE_FINGERPRINT`, so that
+    /// one identity reported from several hosts can be spotted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hardware_fingerprint: Option<String>,
 }
 
 pub struct MinerConfig {
@@ -58,10 +89,18 @@ pub struct Miner {
     pub miner_identity: Option<(AccountId32, u64)>,
     pub creator: Option<AccountId32>,
     pub current_task: Option<CurrentTask>,
+    /// The task the miner stands by for while it serves none
+    pub standby_task: Option<StandbyTask>,
     pub log_failure_count: u8,
+    /// The endpoint last published for the miner, `None` until it was compared with the registered one
+    pub published_endpoint: Option<String>,
+    /// The parachain the miner queries and submits transactions to
+    pub chain: Arc<dyn ChainApi>,
 }
 
 pub struct ParentRuntime {
     //This is kept as an option, because it might be user dynamic in the future
     pub port: Option<u16>,
+    /// The chain the commitments and the gatekeeper of tasks are queried from, the same as the miner's
+    pub chain: Arc<dyn ChainApi>,
 }
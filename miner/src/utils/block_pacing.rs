@@ -0,0 +1,103 @@
+use crate::config;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// The latest best block of the parachain, `None` until the first one arrived
+static LATEST_BLOCK: Lazy<watch::Sender<Option<u64>>> = Lazy::new(|| watch::channel(None).0);
+/// The best block when the last transaction of the queue was included
+static LAST_INCLUSION: Mutex<Option<u64>> = Mutex::new(None);
+static BLOCK_WATCHER: Once = Once::new();
+
+/// Follows the best blocks of the parachain for the rest of the process, without a parachain client (eg. in a
+/// simulation) no blocks are seen and paced transactions are submitted right away
+fn watch_blocks() {
+    BLOCK_WATCHER.call_once(|| {
+        let Ok(client) = config::get_parachain_client() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            loop {
+                match client.blocks().subscribe_best().await {
+                    Ok(mut blocks) => {
+                        while let Some(Ok(block)) = blocks.next().await {
+                            LATEST_BLOCK.send_replace(Some(u64::from(block.number())));
+                        }
+                    }
+                    Err(e) => println!("Failed to follow the best blocks for pacing: {}", e),
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    });
+}
+
+/// Records that a transaction of the queue was just included, paced transactions keep their distance to it
+pub fn record_inclusion() {
+    if let Some(block) = *LATEST_BLOCK.borrow() {
+        *LAST_INCLUSION.lock().unwrap() = Some(block);
+    }
+}
+
+/// Whether a paced transaction may be submitted at `latest`, at least `gap` blocks after the last inclusion
+fn is_submission_slot(latest: Option<u64>, last_inclusion: Option<u64>, gap: u64) -> bool {
+    match (latest, last_inclusion) {
+        (Some(latest), Some(last_inclusion)) => latest >= last_inclusion.saturating_add(gap),
+        _ => true,
+    }
+}
+
+/// Waits for the moment a proof is best submitted: right after a new best block was imported, so the transaction has
+/// the whole block time to reach the next author, and `PROOF_SUBMISSION_BLOCK_GAP` (default 1) blocks after the last
+/// inclusion of a transaction of the queue, so the proof doesn't compete with heavy confirmations of the same account
+/// for a block and a nonce. Gives up after `PROOF_PACING_MAX_WAIT_SECS` (default 30, 0 disables pacing), a proof is
+/// never held back by a stalled chain.
+pub async fn wait_for_proof_slot() {
+    let max_wait = match config::optional_env("PROOF_PACING_MAX_WAIT_SECS", 30u64) {
+        0 => return,
+        secs => Duration::from_secs(secs),
+    };
+    if config::get_parachain_client().is_err() {
+        return;
+    }
+    let gap = config::optional_env("PROOF_SUBMISSION_BLOCK_GAP", 1u64);
+
+    watch_blocks();
+    let mut blocks = LATEST_BLOCK.subscribe();
+    let slot = tokio::time::timeout(max_wait, async {
+        loop {
+            if blocks.changed().await.is_err() {
+                return;
+            }
+            let latest = *blocks.borrow_and_update();
+            if is_submission_slot(latest, *LAST_INCLUSION.lock().unwrap(), gap) {
+                return;
+            }
+        }
+    })
+    .await;
+
+    if slot.is_err() {
+        println!(
+            "No submission slot within {} s, submitting the proof anyway",
+            max_wait.as_secs()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_keep_their_distance_to_the_last_inclusion() {
+        assert!(!is_submission_slot(Some(10), Some(10), 1));
+        assert!(is_submission_slot(Some(11), Some(10), 1));
+        assert!(!is_submission_slot(Some(11), Some(10), 2));
+        assert!(is_submission_slot(Some(10), None, 1));
+        assert!(is_submission_slot(None, Some(10), 1));
+    }
+}
@@ -0,0 +1,36 @@
+use crate::config;
+use crate::error::{Error, Result};
+use once_cell::sync::Lazy;
+use tokio::sync::Semaphore;
+
+/// Limits how much blocking work runs at once, so a burst of it can't occupy every core of the host.
+/// Configurable with `MAX_BLOCKING_TASKS`, defaults to the number of available cores.
+static BLOCKING_PERMITS: Lazy<Semaphore> = Lazy::new(|| {
+    let default = std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1);
+    Semaphore::new(config::optional_env("MAX_BLOCKING_TASKS", default).max(1))
+});
+
+/// Runs blocking work (file hashing, subprocesses, hardware probing) on the blocking thread pool, so that the
+/// reactor driving the websockets and the chain subscription never stalls.
+///
+/// # Arguments
+/// * `work` - The blocking closure to run
+///
+/// # Returns
+/// The `Result` of the closure, or an `Error` if the blocking thread panicked
+pub async fn run_blocking<T, F>(work: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let _permit = BLOCKING_PERMITS
+        .acquire()
+        .await
+        .map_err(|e| Error::Custom(format!("Blocking task limiter closed: {}", e)))?;
+
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| Error::Custom(format!("Blocking task failed: {}", e)))?
+}
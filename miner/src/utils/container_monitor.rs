@@ -0,0 +1,236 @@
+use crate::{
+    alerting::{self, Alert},
+    config,
+    error::{Error, Result},
+    utils::{
+        blocking::run_blocking,
+        fault_injection::{self, Fault},
+        idle_power,
+    },
+};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    process::{Command, Stdio},
+    sync::{Mutex, Once},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Restarts of a container within this window count towards `CONTAINER_MAX_RESTARTS`
+const RESTART_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Wait before subscribing again after the event stream ended, eg. because the Docker daemon restarted
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+/// How often a `die` event may be faked, see `Fault::ContainerDie`
+const FAULT_INJECTION_INTERVAL: Duration = Duration::from_secs(60);
+
+static CONTAINER_MONITOR: Once = Once::new();
+
+/// The last known state of every container the miner owns, by name
+static CONTAINER_STATUS: Lazy<Mutex<BTreeMap<String, String>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// The containers being restarted by the monitor, a restart kills them too
+static RESTARTING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// When the monitor restarted each container within the restart window
+static RESTARTS: Lazy<Mutex<HashMap<String, Vec<Instant>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Starts following the Docker events of the containers the miner owns once per process. The miner doesn't launch
+/// containers itself, it owns the backends the operator runs in Docker for it and lists in `OWNED_CONTAINERS`, eg. the
+/// Triton server at `TRITON_URL`. A container that dies, runs out of memory or turns unhealthy is restarted right away
+/// instead of being discovered through failing requests, at most `CONTAINER_MAX_RESTARTS` times (default 3) within 10
+/// minutes before an alert is raised. Containers the miner stopped itself while idle are left stopped.
+/// `CONTAINER_MONITOR=false` disables the monitor.
+pub fn start() {
+    CONTAINER_MONITOR.call_once(|| {
+        let containers = idle_power::container_list("OWNED_CONTAINERS");
+        if containers.is_empty() || !config::optional_env("CONTAINER_MONITOR", true) {
+            return;
+        }
+
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = follow_events(&containers).await {
+                    println!("Failed to follow the Docker events: {}", e);
+                }
+                tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+            }
+        });
+    });
+}
+
+/// The last known state of every container the miner owns, eg. `healthy` or `died (exit code 137)`
+pub fn statuses() -> BTreeMap<String, String> {
+    CONTAINER_STATUS.lock().unwrap().clone()
+}
+
+/// Follows `docker events` for the containers until the stream ends
+async fn follow_events(containers: &[String]) -> Result<()> {
+    let mut command = tokio::process::Command::new("docker");
+    command.args([
+        "events",
+        "--format",
+        "{{json .}}",
+        "--filter",
+        "type=container",
+    ]);
+    for event in ["start", "die", "oom", "health_status"] {
+        command.args(["--filter", &format!("event={}", event)]);
+    }
+    for container in containers {
+        command.args(["--filter", &format!("container={}", container)]);
+    }
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| Error::Custom("docker events has no output".to_string()))?;
+
+    let mut lines = BufReader::new(stdout).lines();
+    let mut fault_injections = tokio::time::interval_at(
+        tokio::time::Instant::now() + FAULT_INJECTION_INTERVAL,
+        FAULT_INJECTION_INTERVAL,
+    );
+    let mut injected = 0;
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+                if let Ok(event) = serde_json::from_str::<Value>(&line) {
+                    handle_event(&event);
+                }
+            }
+            _ = fault_injections.tick() => {
+                // Takes the restart path of a real die, the restart itself is not faked
+                if fault_injection::inject(Fault::ContainerDie).is_err() {
+                    let container = &containers[injected % containers.len()];
+                    injected += 1;
+                    handle_event(&die_event(container));
+                }
+            }
+        }
+    }
+
+    Err(Error::Custom(format!(
+        "docker events exited: {}",
+        child.wait().await?
+    )))
+}
+
+/// A `die` event of the container as `docker events` reports it
+fn die_event(container: &str) -> Value {
+    serde_json::json!({
+        "Action": "die",
+        "Actor": {
+            "Attributes": {
+                "name": container,
+                "exitCode": "137",
+            },
+        },
+    })
+}
+
+fn handle_event(event: &Value) {
+    let attributes = &event["Actor"]["Attributes"];
+    let Some(name) = attributes["name"].as_str() else {
+        return;
+    };
+    let action = event["Action"].as_str().unwrap_or_default();
+
+    let (status, failed) = match action {
+        "start" => ("running".to_string(), false),
+        "oom" => ("out of memory".to_string(), true),
+        "die" => (
+            format!(
+                "died (exit code {})",
+                attributes["exitCode"].as_str().unwrap_or("unknown")
+            ),
+            true,
+        ),
+        "health_status: healthy" => ("healthy".to_string(), false),
+        "health_status: unhealthy" => ("unhealthy".to_string(), true),
+        _ => return,
+    };
+    CONTAINER_STATUS
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), status.clone());
+
+    let restarting = {
+        let mut restarting = RESTARTING.lock().unwrap();
+        if action == "start" {
+            restarting.remove(name);
+        }
+        restarting.contains(name)
+    };
+    // The die following an out of memory kill restarts the container, unless it is stopped for idling
+    if !failed || action == "oom" || restarting || idle_power::stopped_for_idling(name) {
+        return;
+    }
+    println!("Container {} is {}, restarting it", name, status);
+    if !record_restart(name) {
+        alerting::raise(
+            Alert::EngineFailed,
+            format!(
+                "Container {} is {} and was restarted too often, giving up",
+                name, status
+            ),
+        );
+        return;
+    }
+
+    // Restarted next to the event stream, which has to see the events of the restart to ignore them
+    RESTARTING.lock().unwrap().insert(name.to_string());
+    let container = name.to_string();
+    tokio::spawn(async move {
+        let restarted_container = container.clone();
+        let restarted = run_blocking(move || {
+            let output = Command::new("docker")
+                .args(["restart", &restarted_container])
+                .output()?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(Error::Custom(
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                ))
+            }
+        })
+        .await;
+        if let Err(e) = restarted {
+            RESTARTING.lock().unwrap().remove(&container);
+            alerting::raise(
+                Alert::EngineFailed,
+                format!(
+                    "Container {} is {} and can't be restarted: {}",
+                    container, status, e
+                ),
+            );
+        }
+    });
+}
+
+/// Counts a restart of the container
+///
+/// # Returns
+/// Whether the container may be restarted, `false` once it used up its restarts within the window
+fn record_restart(container: &str) -> bool {
+    let max_restarts = config::optional_env("CONTAINER_MAX_RESTARTS", 3usize);
+    let mut restarts = RESTARTS.lock().unwrap();
+    let restarts = restarts.entry(container.to_string()).or_default();
+    restarts.retain(|restarted_at| restarted_at.elapsed() < RESTART_WINDOW);
+    if restarts.len() >= max_restarts {
+        return false;
+    }
+    restarts.push(Instant::now());
+    true
+}
@@ -0,0 +1,158 @@
+// Fault injection for exercising the retry and recovery paths of the miner (tx queue, engines, container monitor).
+// Only active when compiled with the `chaos` feature, otherwise every injection point is a no-op.
+//
+// Probabilities are read once from the environment, eg. `CHAOS_CHAIN_SUBMISSION=0.3`, and `CHAOS_SEED` makes the
+// sequence of injected faults reproducible between runs.
+
+use crate::error::Result;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The model archive download from the storage location fails
+    StorageDownload,
+    /// Triton answers a request of the OpenInference client with a server error
+    InferenceEngine,
+    /// A transaction submission to the parachain times out
+    ChainSubmission,
+    /// The inference engine crashes during setup
+    EngineCrash,
+    /// An owned container dies, checked once a minute by the container monitor
+    ContainerDie,
+}
+
+#[cfg(feature = "chaos")]
+impl Fault {
+    fn env_var(&self) -> &'static str {
+        match self {
+            Fault::StorageDownload => "CHAOS_STORAGE_DOWNLOAD",
+            Fault::InferenceEngine => "CHAOS_INFERENCE_ENGINE",
+            Fault::ChainSubmission => "CHAOS_CHAIN_SUBMISSION",
+            Fault::EngineCrash => "CHAOS_ENGINE_CRASH",
+            Fault::ContainerDie => "CHAOS_CONTAINER_DIE",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Fault::StorageDownload => "storage download failed",
+            Fault::InferenceEngine => "inference engine returned 503 Service Unavailable",
+            Fault::ChainSubmission => "chain submission timed out",
+            Fault::EngineCrash => "inference engine crashed",
+            Fault::ContainerDie => "container died",
+        }
+    }
+}
+
+#[cfg(feature = "chaos")]
+mod injector {
+    use super::Fault;
+    use once_cell::sync::Lazy;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::{env, sync::Mutex};
+
+    pub struct FaultInjector {
+        rng: StdRng,
+    }
+
+    pub static INJECTOR: Lazy<Mutex<FaultInjector>> = Lazy::new(|| {
+        let seed = env::var("CHAOS_SEED")
+            .ok()
+            .and_then(|seed| seed.parse::<u64>().ok());
+
+        Mutex::new(FaultInjector::new(seed))
+    });
+
+    impl FaultInjector {
+        pub fn new(seed: Option<u64>) -> Self {
+            let rng = match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+
+            Self { rng }
+        }
+
+        pub fn should_inject(&mut self, probability: f64) -> bool {
+            self.rng.gen_bool(probability.clamp(0.0, 1.0))
+        }
+    }
+
+    pub fn probability(fault: Fault) -> f64 {
+        env::var(fault.env_var())
+            .ok()
+            .and_then(|probability| probability.parse::<f64>().ok())
+            .unwrap_or(0.0)
+    }
+}
+
+/// Returns an error if the given fault should be injected at this point, according to its configured probability.
+#[cfg(feature = "chaos")]
+pub fn inject(fault: Fault) -> Result<()> {
+    let probability = injector::probability(fault);
+
+    if probability <= 0.0 {
+        return Ok(());
+    }
+
+    let triggered = injector::INJECTOR
+        .lock()
+        .map(|mut injector| injector.should_inject(probability))
+        .unwrap_or(false);
+
+    if triggered {
+        tracing::warn!("Injecting fault: {:?}", fault);
+        return Err(crate::error::Error::Custom(format!(
+            "Injected fault: {}",
+            fault.description()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns an error if the given fault should be injected at this point, according to its configured probability.
+#[cfg(not(feature = "chaos"))]
+#[inline(always)]
+pub fn inject(_fault: Fault) -> Result<()> {
+    Ok(())
+}
+
+/// Injection point of the given fault for components outside the miner, eg. the Triton client, called per request and
+/// `true` if the fault should be injected.
+#[cfg(feature = "chaos")]
+pub fn hook(fault: Fault) -> Option<Arc<dyn Fn() -> bool + Send + Sync>> {
+    Some(Arc::new(move || inject(fault).is_err()))
+}
+
+/// Injection point of the given fault for components outside the miner, eg. the Triton client, called per request and
+/// `true` if the fault should be injected.
+#[cfg(not(feature = "chaos"))]
+#[inline(always)]
+pub fn hook(_fault: Fault) -> Option<Arc<dyn Fn() -> bool + Send + Sync>> {
+    None
+}
+
+#[cfg(all(test, feature = "chaos"))]
+mod tests {
+    use super::injector::FaultInjector;
+
+    #[test]
+    fn test_seeded_injection_is_deterministic() {
+        let mut first = FaultInjector::new(Some(42));
+        let mut second = FaultInjector::new(Some(42));
+
+        let first_run: Vec<bool> = (0..32).map(|_| first.should_inject(0.5)).collect();
+        let second_run: Vec<bool> = (0..32).map(|_| second.should_inject(0.5)).collect();
+
+        assert_eq!(first_run, second_run);
+    }
+
+    #[test]
+    fn test_probability_bounds() {
+        let mut injector = FaultInjector::new(Some(7));
+
+        assert!((0..32).all(|_| injector.should_inject(1.0)));
+        assert!((0..32).all(|_| !injector.should_inject(0.0)));
+    }
+}
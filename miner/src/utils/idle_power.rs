@@ -0,0 +1,245 @@
+use crate::config;
+use crate::utils::blocking::run_blocking;
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+use std::sync::Mutex;
+
+/// The tasks the miners of this process are serving, the GPUs idle once none is left
+static ACTIVE_TASKS: Lazy<Mutex<HashSet<u64>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+/// The Triton models the tasks of this process loaded, only these are unloaded while idle as Triton may serve others
+static TASK_MODELS: Lazy<Mutex<HashMap<u64, Vec<String>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+/// Whether the GPUs were put into the idle state and need to be re-warmed for the next task
+static IDLE: Mutex<bool> = Mutex::new(false);
+
+/// Idle power settings, operators paying for electricity want the miner to idle cheaply between tasks:
+/// - `IDLE_POWER_SAVING`: Unloads the models of the tasks from Triton once no task is served (default true)
+/// - `IDLE_STOP_CONTAINERS`: Comma separated containers, eg. a Triton container only serving the tasks of this miner,
+///   stopped while idle and started again when a task is assigned
+/// - `IDLE_GPU_CLOCKS`: `min,max` GPU clocks in MHz locked while idle through `nvidia-smi`, needs root
+struct IdleSettings {
+    enabled: bool,
+    containers: Vec<String>,
+    gpu_clocks: Option<String>,
+}
+
+impl IdleSettings {
+    fn from_env() -> Self {
+        Self {
+            enabled: config::optional_env("IDLE_POWER_SAVING", true),
+            containers: container_list("IDLE_STOP_CONTAINERS"),
+            gpu_clocks: std::env::var("IDLE_GPU_CLOCKS")
+                .ok()
+                .map(|clocks| clocks.trim().to_string())
+                .filter(|clocks| !clocks.is_empty()),
+        }
+    }
+}
+
+/// The containers in a comma separated list of an environment variable
+pub fn container_list(key: &str) -> Vec<String> {
+    parse_containers(&std::env::var(key).unwrap_or_default())
+}
+
+fn parse_containers(containers: &str) -> Vec<String> {
+    containers
+        .split(',')
+        .map(|container| container.trim().to_string())
+        .filter(|container| !container.is_empty())
+        .collect()
+}
+
+/// Whether the container is stopped on purpose, because the GPUs are idle and the operator listed it in
+/// `IDLE_STOP_CONTAINERS`
+pub fn stopped_for_idling(container: &str) -> bool {
+    *IDLE.lock().unwrap()
+        && IdleSettings::from_env()
+            .containers
+            .iter()
+            .any(|stopped| stopped == container)
+}
+
+/// Records the Triton models a task loaded, so that idling unloads them
+///
+/// # Arguments
+/// * `task_id` - The task the models are served for
+/// * `models` - The models of the task, eg. every model of its pipeline
+pub fn models_loaded(task_id: u64, models: Vec<&str>) {
+    TASK_MODELS
+        .lock()
+        .unwrap()
+        .insert(task_id, models.into_iter().map(str::to_string).collect());
+}
+
+/// Re-warms the GPUs for a task that was assigned, if they were idle: restores the clocks and starts the stopped
+/// containers. The models are loaded by the task setup as usual.
+///
+/// # Arguments
+/// * `task_id` - The task that is set up
+pub async fn task_started(task_id: u64) {
+    ACTIVE_TASKS.lock().unwrap().insert(task_id);
+    let was_idle = std::mem::replace(&mut *IDLE.lock().unwrap(), false);
+    if !was_idle {
+        return;
+    }
+
+    let settings = IdleSettings::from_env();
+    println!("Re-warming the GPUs for task {}", task_id);
+    let result = run_blocking(move || {
+        if settings.gpu_clocks.is_some() {
+            run("nvidia-smi", &["--reset-gpu-clocks"]);
+        }
+        for container in &settings.containers {
+            run("docker", &["start", container]);
+        }
+        Ok(())
+    })
+    .await;
+    if let Err(e) = result {
+        println!("Failed to re-warm the GPUs: {}", e);
+    }
+}
+
+/// Releases the GPUs once the last task of the process ended: unloads the models from Triton, stops the configured
+/// containers and locks the idle clocks. Miners of a fleet share the GPUs, so they idle only once all of them are.
+///
+/// # Arguments
+/// * `task_id` - The task that ended
+pub async fn task_ended(task_id: u64) {
+    {
+        let mut active_tasks = ACTIVE_TASKS.lock().unwrap();
+        active_tasks.remove(&task_id);
+        if !active_tasks.is_empty() {
+            return;
+        }
+    }
+    let settings = IdleSettings::from_env();
+    if !settings.enabled {
+        return;
+    }
+    {
+        let mut idle = IDLE.lock().unwrap();
+        if *idle {
+            return;
+        }
+        *idle = true;
+    }
+
+    println!("No task left to serve, releasing the GPUs");
+    let models = released_models(
+        &ACTIVE_TASKS.lock().unwrap(),
+        &mut TASK_MODELS.lock().unwrap(),
+    );
+    if let Err(e) = unload_triton_models(models).await {
+        println!("Failed to unload the Triton models: {}", e);
+    }
+    let result = run_blocking(move || {
+        for container in &settings.containers {
+            run("docker", &["stop", container]);
+        }
+        if let Some(clocks) = &settings.gpu_clocks {
+            run("nvidia-smi", &[&format!("--lock-gpu-clocks={}", clocks)]);
+        }
+        Ok(())
+    })
+    .await;
+    if let Err(e) = result {
+        println!("Failed to idle the GPUs: {}", e);
+    }
+}
+
+/// Takes the models of the tasks that ended, except those a task still being served uses as well
+///
+/// # Arguments
+/// * `active_tasks` - The tasks still being served
+/// * `task_models` - The models every task loaded, the entries of ended tasks are removed
+///
+/// # Returns
+/// The models no task uses anymore
+fn released_models(
+    active_tasks: &HashSet<u64>,
+    task_models: &mut HashMap<u64, Vec<String>>,
+) -> Vec<String> {
+    let ended: Vec<u64> = task_models
+        .keys()
+        .filter(|task_id| !active_tasks.contains(task_id))
+        .copied()
+        .collect();
+    let removed: Vec<String> = ended
+        .iter()
+        .filter_map(|task_id| task_models.remove(task_id))
+        .flatten()
+        .collect();
+    let mut released: Vec<String> = removed
+        .into_iter()
+        .filter(|model| !task_models.values().flatten().any(|used| used == model))
+        .collect();
+    released.sort();
+    released.dedup();
+    released
+}
+
+/// Unloads the models the tasks of this process loaded, models loaded by others stay
+async fn unload_triton_models(models: Vec<String>) -> crate::error::Result<()> {
+    let client = config::http_client()?;
+    let triton_url = config::triton_url();
+
+    for model in models {
+        println!("Unloading model {}", model);
+        match client
+            .post(format!("{}/repository/models/{}/unload", triton_url, model))
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            // No Triton running, nothing is loaded
+            Err(e) if e.is_connect() => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a command, failures are only logged since idling is best effort
+fn run(program: &str, args: &[&str]) {
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => println!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => println!("Failed to run {}: {}", program, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_models_of_ended_tasks_are_released() {
+        let active_tasks = HashSet::from([2]);
+        let mut task_models = HashMap::from([
+            (1, vec!["encoder".to_string(), "shared".to_string()]),
+            (2, vec!["shared".to_string()]),
+            (3, vec!["decoder".to_string()]),
+        ]);
+
+        let released = released_models(&active_tasks, &mut task_models);
+        assert_eq!(released, vec!["decoder".to_string(), "encoder".to_string()]);
+        assert_eq!(task_models.keys().collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn container_lists_skip_empty_entries() {
+        assert_eq!(
+            parse_containers(" flashinfer-0, ,flashinfer-1,"),
+            vec!["flashinfer-0".to_string(), "flashinfer-1".to_string()]
+        );
+        assert!(parse_containers("").is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    admin::{self, DEFAULT_ADMIN_PORT},
+    config,
+    error::{Error, Result},
+};
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Created in the data directory of a miner, next to its identity file
+const LOCK_FILE: &str = "miner.lock";
+
+/// Held for as long as a miner runs on a data directory, the lock is released by the OS when the process exits,
+/// however it exits, so a stale lock file never blocks the next start
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Locks the data directory of the miner, two miners on the same directory corrupt its logs and submit every
+/// transaction twice.
+///
+/// # Arguments
+/// * `identity_path` - The identity file of the miner, its directory is locked
+///
+/// # Returns
+/// The lock, to be kept until the miner stops, or an `Error` describing the instance already running on the directory
+pub async fn acquire(identity_path: &str) -> Result<InstanceLock> {
+    let path = lock_path(identity_path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Not truncated before the lock is held, the pid of the running instance must survive
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+
+    if file.try_lock_exclusive().is_err() {
+        let mut pid = String::new();
+        file.read_to_string(&mut pid)?;
+        return Err(Error::Custom(already_running(&path, pid.trim()).await));
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", std::process::id())?;
+    file.sync_all()?;
+
+    Ok(InstanceLock { _file: file })
+}
+
+fn lock_path(identity_path: &str) -> PathBuf {
+    Path::new(identity_path)
+        .parent()
+        .map(|dir| dir.join(LOCK_FILE))
+        .unwrap_or_else(|| PathBuf::from(LOCK_FILE))
+}
+
+/// Describes the instance holding the lock, with what its admin API reports if it is reachable
+async fn already_running(path: &Path, pid: &str) -> String {
+    let pid = if pid.is_empty() { "unknown" } else { pid };
+    let mut message = format!(
+        "Another miner (pid {}) is already running on this data directory, {} is locked. Stop it first, or give \
+         this miner its own IDENTITY_FILE_PATH",
+        pid,
+        path.display()
+    );
+
+    let port = config::optional_env("ADMIN_PORT", DEFAULT_ADMIN_PORT);
+    let client = config::http_client_builder()
+        .and_then(|builder| Ok(builder.timeout(Duration::from_secs(2)).build()?));
+    let status = match client {
+        Ok(client) => admin::fetch_status(&client, &format!("http://127.0.0.1:{}", port))
+            .await
+            .ok(),
+        Err(_) => None,
+    };
+    if let Some(status) = status.filter(|status| status.pid.to_string() == pid) {
+        let tasks: Vec<String> = status
+            .tasks
+            .iter()
+            .map(|task| format!("{} ({})", task.task_id, task.engine_status))
+            .collect();
+        message.push_str(&format!(
+            ". Its admin API on port {} reports tasks [{}] and {} pending transactions",
+            port,
+            tasks.join(", "),
+            status.tx_queue_pending
+        ));
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn a_second_instance_is_refused() {
+        let dir = std::env::temp_dir().join(format!("instance-lock-test-{}", std::process::id()));
+        let identity_path = dir.join("identity.json").to_string_lossy().to_string();
+
+        let lock = acquire(&identity_path).await.unwrap();
+        let error = acquire(&identity_path).await.err().unwrap().to_string();
+        assert!(error.contains(&std::process::id().to_string()));
+
+        drop(lock);
+        assert!(acquire(&identity_path).await.is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
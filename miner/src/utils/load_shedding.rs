@@ -0,0 +1,138 @@
+use crate::config;
+use once_cell::sync::Lazy;
+use std::process::Command;
+use std::sync::Once;
+use std::time::Duration;
+use sysinfo::System;
+use tokio::sync::watch;
+
+/// Pressure is only considered relieved once usage fell this many points below the threshold, so the miner doesn't
+/// flap between shedding and accepting load around the threshold
+const RELIEF_MARGIN_PERCENT: f32 = 5.0;
+
+/// Why the host is under pressure, `None` while it is not
+static PRESSURE: Lazy<watch::Sender<Option<String>>> = Lazy::new(|| watch::channel(None).0);
+
+static MONITOR: Once = Once::new();
+
+/// Usage thresholds in percent, 0 disables a threshold. GPU utilization is disabled by default, a GPU serving
+/// inference is expected to be fully utilized.
+struct Thresholds {
+    cpu: f32,
+    memory: f32,
+    gpu: f32,
+}
+
+impl Thresholds {
+    fn from_env() -> Self {
+        Self {
+            cpu: config::optional_env("LOAD_SHED_CPU_PERCENT", 95.0),
+            memory: config::optional_env("LOAD_SHED_MEMORY_PERCENT", 92.0),
+            gpu: config::optional_env("LOAD_SHED_GPU_PERCENT", 0.0),
+        }
+    }
+}
+
+/// Starts monitoring the host resources once per process, every `LOAD_SHED_INTERVAL_SECS` (default 5, 0 disables
+/// load shedding). A fleet shares the host, so all of its members shed load together.
+pub fn start_monitor() {
+    MONITOR.call_once(|| {
+        let interval = config::optional_env("LOAD_SHED_INTERVAL_SECS", 5u64);
+        if interval == 0 {
+            return;
+        }
+
+        let thresholds = Thresholds::from_env();
+        std::thread::spawn(move || {
+            let mut system = System::new();
+            loop {
+                std::thread::sleep(Duration::from_secs(interval));
+                let under_pressure = PRESSURE.borrow().is_some();
+                let pressure = measure(&mut system, &thresholds, under_pressure);
+
+                match (&pressure, under_pressure) {
+                    (Some(reason), false) => println!("Shedding load: {}", reason),
+                    (None, true) => println!("Resource pressure subsided, accepting load again"),
+                    _ => {}
+                }
+                PRESSURE.send_replace(pressure);
+            }
+        });
+    });
+}
+
+/// Why new load is currently rejected, or `None` if the host has capacity
+pub fn pressure() -> Option<String> {
+    PRESSURE.borrow().clone()
+}
+
+/// Waits until the host is no longer under pressure before starting background work, eg. a proof or a download
+///
+/// # Arguments
+/// * `job` - The work that waits, for the log
+pub async fn wait_for_relief(job: &str) {
+    let mut pressure = PRESSURE.subscribe();
+    if let Some(reason) = pressure.borrow().clone() {
+        println!(
+            "Pausing {} until resource pressure subsides: {}",
+            job, reason
+        );
+    }
+    let _ = pressure.wait_for(|pressure| pressure.is_none()).await;
+}
+
+/// Measures the usage of every resource with an enabled threshold and returns the first one under pressure
+fn measure(system: &mut System, thresholds: &Thresholds, under_pressure: bool) -> Option<String> {
+    // Lowering the thresholds while under pressure keeps shedding until usage clearly dropped
+    let limit = |threshold: f32| {
+        if under_pressure {
+            threshold - RELIEF_MARGIN_PERCENT
+        } else {
+            threshold
+        }
+    };
+
+    if thresholds.cpu > 0.0 {
+        system.refresh_cpu_usage();
+        let usage = system.global_cpu_usage();
+        if usage >= limit(thresholds.cpu) {
+            return Some(format!("CPU usage at {:.0}%", usage));
+        }
+    }
+
+    if thresholds.memory > 0.0 {
+        system.refresh_memory();
+        let total = system.total_memory();
+        if total > 0 {
+            let usage = 100.0 - system.available_memory() as f32 * 100.0 / total as f32;
+            if usage >= limit(thresholds.memory) {
+                return Some(format!("memory usage at {:.0}%", usage));
+            }
+        }
+    }
+
+    if thresholds.gpu > 0.0 {
+        if let Some(usage) = gpu_utilization() {
+            if usage >= limit(thresholds.gpu) {
+                return Some(format!("GPU utilization at {:.0}%", usage));
+            }
+        }
+    }
+
+    None
+}
+
+/// The utilization of the busiest GPU, `None` without NVIDIA GPUs
+fn gpu_utilization() -> Option<f32> {
+    let output = Command::new("nvidia-smi")
+        .arg("--query-gpu=utilization.gpu")
+        .arg("--format=csv,noheader,nounits")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse::<f32>().ok())
+        .reduce(f32::max)
+}
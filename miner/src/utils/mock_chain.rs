@@ -0,0 +1,201 @@
+use crate::{
+    error::Result,
+    traits::ChainApi,
+    types::{Capabilities, HardwareSpec, Miner, ParentRuntime},
+    utils::substrate_queries::{CyborgTask, NzkCommitment},
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use subxt::utils::AccountId32;
+use subxt_signer::{sr25519::Keypair, SecretUri};
+use tokio::sync::RwLock;
+
+/// A submission the miner made to the mock chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainCall {
+    Register,
+    RemoveWorker(u64),
+    ConfirmReception(u64),
+    ConfirmVacation(u64),
+    SubmitProof(u64, Vec<u8>),
+    SubmitProofReference(u64, String),
+    DeclineTask(u64, String),
+    ReportIntegrityMismatch(u64, String),
+    PublishCapabilities((AccountId32, u64)),
+    PublishEndpoint(String),
+}
+
+/// `ChainApi` backed by in-memory state, recording every submission instead of sending it
+#[derive(Default)]
+pub struct MockChain {
+    pub tasks: Mutex<Vec<CyborgTask>>,
+    pub nzk_commitments: Mutex<HashMap<u64, NzkCommitment>>,
+    pub gatekeeper: Mutex<Option<AccountId32>>,
+    pub workers: Mutex<Vec<(AccountId32, u64)>>,
+    pub registered_spec: Mutex<Option<HardwareSpec>>,
+    pub registered_domain: Mutex<Option<String>>,
+    calls: Mutex<Vec<ChainCall>>,
+}
+
+impl MockChain {
+    pub fn calls(&self) -> Vec<ChainCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, call: ChainCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+#[async_trait]
+impl ChainApi for MockChain {
+    async fn get_task(&self, task_id: u64) -> Result<CyborgTask> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|task| task.id == task_id)
+            .map(|task| CyborgTask {
+                id: task.id,
+                owner: task.owner.clone(),
+                cid: task.cid.clone(),
+            })
+            .ok_or("Task not found".into())
+    }
+
+    async fn get_nzk_commitment(&self, task_id: u64) -> Result<Option<NzkCommitment>> {
+        Ok(self.nzk_commitments.lock().unwrap().get(&task_id).cloned())
+    }
+
+    async fn get_gatekeeper(&self) -> Result<AccountId32> {
+        self.gatekeeper
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or("No gatekeeper set on-chain".into())
+    }
+
+    async fn iter_workers(&self) -> Result<Vec<(AccountId32, u64)>> {
+        Ok(self.workers.lock().unwrap().clone())
+    }
+
+    async fn get_registered_spec(&self, _: &AccountId32, _: u64) -> Result<HardwareSpec> {
+        self.registered_spec
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or("Miner not found".into())
+    }
+
+    async fn get_registered_domain(&self, _: &AccountId32, _: u64) -> Result<String> {
+        self.registered_domain
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or("Miner not found".into())
+    }
+
+    async fn register(&self, keypair: Keypair) -> Result<(AccountId32, u64)> {
+        self.record(ChainCall::Register);
+        let mut workers = self.workers.lock().unwrap();
+        let identity = (keypair.public_key().to_account_id(), workers.len() as u64);
+        workers.push(identity.clone());
+        Ok(identity)
+    }
+
+    async fn remove_worker(&self, _: Keypair, miner_id: u64) -> Result<()> {
+        self.record(ChainCall::RemoveWorker(miner_id));
+        Ok(())
+    }
+
+    async fn confirm_reception(&self, _: Keypair, task_id: u64) -> Result<()> {
+        self.record(ChainCall::ConfirmReception(task_id));
+        Ok(())
+    }
+
+    async fn confirm_vacation(&self, _: Keypair, task_id: u64) -> Result<()> {
+        self.record(ChainCall::ConfirmVacation(task_id));
+        Ok(())
+    }
+
+    async fn submit_proof(&self, _: Keypair, task_id: u64, proof: Vec<u8>) -> Result<()> {
+        self.record(ChainCall::SubmitProof(task_id, proof));
+        Ok(())
+    }
+
+    async fn submit_proof_reference(
+        &self,
+        _: Keypair,
+        task_id: u64,
+        proof_hash: &str,
+        _: &str,
+    ) -> Result<()> {
+        self.record(ChainCall::SubmitProofReference(
+            task_id,
+            proof_hash.to_string(),
+        ));
+        Ok(())
+    }
+
+    async fn decline_task(&self, _: Keypair, task_id: u64, reason: &str) -> Result<()> {
+        self.record(ChainCall::DeclineTask(task_id, reason.to_string()));
+        Ok(())
+    }
+
+    async fn report_integrity_mismatch(
+        &self,
+        _: Keypair,
+        task_id: u64,
+        mismatch: &str,
+    ) -> Result<()> {
+        self.record(ChainCall::ReportIntegrityMismatch(
+            task_id,
+            mismatch.to_string(),
+        ));
+        Ok(())
+    }
+
+    async fn publish_capabilities(
+        &self,
+        _: Keypair,
+        miner_identity: (AccountId32, u64),
+        _: &Capabilities,
+    ) -> Result<()> {
+        self.record(ChainCall::PublishCapabilities(miner_identity));
+        Ok(())
+    }
+
+    async fn publish_endpoint(
+        &self,
+        _: Keypair,
+        _: (AccountId32, u64),
+        endpoint: &str,
+    ) -> Result<()> {
+        self.record(ChainCall::PublishEndpoint(endpoint.to_string()));
+        Ok(())
+    }
+}
+
+/// A registered miner of `//Alice` talking to the mock chain
+pub fn miner(chain: Arc<MockChain>) -> Miner {
+    let uri = SecretUri::from_str("//Alice").unwrap();
+    let keypair = Keypair::from_uri(&uri).unwrap();
+    let identity = (keypair.public_key().to_account_id(), 0);
+
+    Miner {
+        keypair,
+        parent_runtime: Arc::new(RwLock::new(ParentRuntime {
+            port: None,
+            chain: chain.clone(),
+        })),
+        miner_identity: Some(identity.clone()),
+        creator: Some(identity.0),
+        current_task: None,
+        standby_task: None,
+        log_failure_count: 0,
+        published_endpoint: None,
+        chain,
+    }
+}
@@ -1,3 +1,13 @@
+pub mod block_pacing;
+pub mod blocking;
+pub mod container_monitor;
+pub mod fault_injection;
+pub mod idle_power;
+pub mod instance_lock;
+pub mod load_shedding;
+#[cfg(test)]
+pub mod mock_chain;
+pub mod scheduler;
 pub mod substrate_queries;
 //pub mod substrate_transactions;
 pub mod tx_queue;
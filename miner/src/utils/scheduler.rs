@@ -0,0 +1,106 @@
+use crate::config;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+/// Periodic maintenance work of the miner. Jobs are only scheduled here, they are executed by the main loop,
+/// since most of them need exclusive access to the `Miner`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceJob {
+    /// Compare the local hardware with the registered specs
+    SpecCheck,
+    /// Re-publish the capability report, eg. after Triton or a GPU became available
+    CapabilityReport,
+    /// Log that the miner is alive and what it is running
+    Heartbeat,
+    /// Anchor the Merkle root of the responses served for tasks without zk proofs
+    ResponseAnchor,
+    /// Publish the endpoint of the miner again if it changed, eg. after the host got a new address
+    EndpointCheck,
+    /// Sign a digest of the requests sampled for auditing since the last digest
+    AuditDigest,
+}
+
+impl MaintenanceJob {
+    /// The environment variable configuring the interval of the job in seconds, 0 disables the job
+    fn interval_env(&self) -> &'static str {
+        match self {
+            MaintenanceJob::SpecCheck => "SPEC_CHECK_INTERVAL_SECS",
+            MaintenanceJob::CapabilityReport => "CAPABILITY_REPORT_INTERVAL_SECS",
+            MaintenanceJob::Heartbeat => "HEARTBEAT_INTERVAL_SECS",
+            MaintenanceJob::ResponseAnchor => "RESPONSE_ANCHOR_INTERVAL_SECS",
+            MaintenanceJob::EndpointCheck => "ENDPOINT_CHECK_INTERVAL_SECS",
+            MaintenanceJob::AuditDigest => "AUDIT_DIGEST_INTERVAL_SECS",
+        }
+    }
+
+    fn default_interval(&self) -> Duration {
+        match self {
+            MaintenanceJob::SpecCheck => Duration::from_secs(60 * 60),
+            MaintenanceJob::CapabilityReport => Duration::from_secs(24 * 60 * 60),
+            MaintenanceJob::Heartbeat => Duration::from_secs(60),
+            MaintenanceJob::ResponseAnchor => Duration::from_secs(10 * 60),
+            MaintenanceJob::EndpointCheck => Duration::from_secs(5 * 60),
+            MaintenanceJob::AuditDigest => Duration::from_secs(60 * 60),
+        }
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        match config::optional_env(self.interval_env(), self.default_interval().as_secs()) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// Emits every maintenance job once per configured interval. Dropping the scheduler stops all timers.
+pub struct Scheduler {
+    receiver: mpsc::Receiver<MaintenanceJob>,
+    timers: Vec<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    /// Starts a timer for every enabled job. The first run of each job is one interval after startup,
+    /// work that should also happen at startup is done by the caller before entering the main loop.
+    pub fn start(jobs: &[MaintenanceJob]) -> Self {
+        let (sender, receiver) = mpsc::channel(jobs.len().max(1));
+
+        let timers = jobs
+            .iter()
+            .filter_map(|job| job.interval().map(|interval| (*job, interval)))
+            .map(|(job, interval)| {
+                let sender = sender.clone();
+                println!("Scheduling {:?} every {} s", job, interval.as_secs());
+
+                tokio::spawn(async move {
+                    let mut timer = time::interval_at(Instant::now() + interval, interval);
+                    // A job that is still waiting to be executed is not queued a second time
+                    timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+                    loop {
+                        timer.tick().await;
+                        if sender.send(job).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self { receiver, timers }
+    }
+
+    /// Waits for the next job that is due
+    pub async fn next(&mut self) -> Option<MaintenanceJob> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        for timer in &self.timers {
+            timer.abort();
+        }
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{error::Result, substrate_interface};
+use crate::{
+    error::{Error, Result},
+    parachain_interactor::task_identifier::TaskIdentifier,
+    substrate_interface::{self, api::runtime_types::cyborg_primitives::payment::RewardRates},
+    types::HardwareSpec,
+};
 use subxt::utils::AccountId32;
 use subxt::{OnlineClient, PolkadotConfig};
 
@@ -24,7 +29,9 @@ pub async fn get_task(api: &OnlineClient<PolkadotConfig>, task_id: u64) -> Resul
         .await?;
 
     if let Some(task) = task_query {
-        let ipfs_cid_string = String::from_utf8(task.metadata.0)?;
+        let ipfs_cid_string = TaskIdentifier::decode(&task.metadata.0)
+            .map_err(|e| Error::Custom(format!("Task {}: {}", task_id, e)))?
+            .to_string();
 
         Ok(CyborgTask {
             id: task_id,
@@ -36,6 +43,167 @@ pub async fn get_task(api: &OnlineClient<PolkadotConfig>, task_id: u64) -> Resul
     }
 }
 
+// The artifacts of a NeuroZK task that the task owner committed to on-chain when scheduling the task
+#[derive(Clone)]
+pub struct NzkCommitment {
+    pub zk_input: Vec<u8>,
+    pub zk_settings: Vec<u8>,
+    pub zk_verifying_key: Vec<u8>,
+}
+
+pub async fn get_nzk_commitment(api: &OnlineClient<PolkadotConfig>, task_id: u64) -> Result<Option<NzkCommitment>> {
+    let task_address = substrate_interface::api::storage()
+        .task_management()
+        .tasks(task_id);
+
+    let task_query = api
+        .storage()
+        .at_latest()
+        .await?
+        .fetch(&task_address)
+        .await?;
+
+    if let Some(task) = task_query {
+        Ok(task.nzk_data.map(|nzk_data| NzkCommitment {
+            zk_input: nzk_data.zk_input.0,
+            zk_settings: nzk_data.zk_settings.0,
+            zk_verifying_key: nzk_data.zk_verifying_key.0,
+        }))
+    } else {
+        Err("Task not found".into())
+    }
+}
+
+/// Rewards of a miner account that were accrued, but not distributed yet
+pub async fn get_pending_rewards(api: &OnlineClient<PolkadotConfig>, account: &AccountId32) -> Result<u128> {
+    let rewards_address = substrate_interface::api::storage()
+        .payment()
+        .miner_pending_rewards(account);
+
+    Ok(api
+        .storage()
+        .at_latest()
+        .await?
+        .fetch(&rewards_address)
+        .await?
+        .unwrap_or(0))
+}
+
+/// What serving a task costs as configured on chain. Tasks are billed per compute hour, there is no on-chain price
+/// per request or token.
+pub struct TaskPricing {
+    pub subscription_fee_per_hour: u128,
+    pub compute_hours_deposit: Option<u32>,
+    pub consumed_compute_hours: Option<u32>,
+    /// Custom rates of the serving miner, `None` where the default rates apply
+    pub miner_reward_rates: Option<RewardRates<u128>>,
+}
+
+pub async fn get_task_pricing(
+    api: &OnlineClient<PolkadotConfig>,
+    task_id: u64,
+    miner: &AccountId32,
+) -> Result<TaskPricing> {
+    let storage = api.storage().at_latest().await?;
+
+    let task = storage
+        .fetch(&substrate_interface::api::storage().task_management().tasks(task_id))
+        .await?
+        .ok_or(Error::Custom("Task not found".to_string()))?;
+    let subscription_fee_per_hour = storage
+        .fetch(&substrate_interface::api::storage().payment().subscription_fee())
+        .await?
+        .unwrap_or(0);
+    let miner_reward_rates = storage
+        .fetch(&substrate_interface::api::storage().payment().active_reward_rates(miner))
+        .await?;
+
+    Ok(TaskPricing {
+        subscription_fee_per_hour,
+        compute_hours_deposit: task.compute_hours_deposit,
+        consumed_compute_hours: task.consume_compute_hours,
+        miner_reward_rates,
+    })
+}
+
+/// Custom (active, idle) reward rates of a miner account, `None` where the default rates apply
+pub async fn get_reward_rates(
+    api: &OnlineClient<PolkadotConfig>,
+    account: &AccountId32,
+) -> Result<(Option<RewardRates<u128>>, Option<RewardRates<u128>>)> {
+    let payment = substrate_interface::api::storage().payment();
+    let storage = api.storage().at_latest().await?;
+
+    let active = storage.fetch(&payment.active_reward_rates(account)).await?;
+    let idle = storage.fetch(&payment.idle_reward_rates(account)).await?;
+
+    Ok((active, idle))
+}
+
+/// Ids of the tasks currently allocated to the miner with the given account, the account its transactions are signed
+/// with. An allocation that can't be read fails the query rather than being left out.
+pub async fn get_allocated_tasks(api: &OnlineClient<PolkadotConfig>, miner_account: &AccountId32) -> Result<Vec<u64>> {
+    let allocations_address = substrate_interface::api::storage()
+        .task_management()
+        .task_allocations_iter();
+
+    let mut allocations = api
+        .storage()
+        .at_latest()
+        .await?
+        .iter(allocations_address)
+        .await?;
+
+    let mut task_ids = Vec::new();
+    while let Some(allocation) = allocations.next().await {
+        let allocation = allocation?;
+        if allocation.value.0 == *miner_account {
+            let task_id = allocation
+                .keys
+                .decoded()
+                .map_err(|e| Error::Custom(format!("Failed to decode task id: {}", e)))?;
+            task_ids.push(task_id);
+        }
+    }
+
+    Ok(task_ids)
+}
+
+pub async fn get_gatekeeper(api: &OnlineClient<PolkadotConfig>) -> Result<AccountId32> {
+    let gatekeeper_address = substrate_interface::api::storage()
+        .task_management()
+        .gatekeeper_account();
+
+    api.storage()
+        .at_latest()
+        .await?
+        .fetch(&gatekeeper_address)
+        .await?
+        .ok_or("No gatekeeper set on-chain".into())
+}
+
+/// The identities (owner and id) of all registered executable workers
+pub async fn get_workers(api: &OnlineClient<PolkadotConfig>) -> Result<Vec<(AccountId32, u64)>> {
+    // Since there seems to be a bug in subxt that should have been resolved (and we possibly won't have a separate storage map for querying workers by id)
+    let workers_address = substrate_interface::api::storage()
+        .edge_connect()
+        .executable_workers_iter();
+
+    let mut workers_query = api
+        .storage()
+        .at_latest()
+        .await?
+        .iter(workers_address)
+        .await?;
+
+    let mut workers = Vec::new();
+    while let Some(Ok(worker)) = workers_query.next().await {
+        workers.push((worker.value.owner, worker.value.id));
+    }
+
+    Ok(workers)
+}
+
 pub async fn get_miner_by_domain(api: &OnlineClient<PolkadotConfig>, local_domain: &String) -> Result<(AccountId32, u64)> {
     let miner_address = substrate_interface::api::storage()
         .edge_connect()
@@ -57,3 +225,46 @@ pub async fn get_miner_by_domain(api: &OnlineClient<PolkadotConfig>, local_domai
 
     Err("Miner not found".into())
 }
+
+pub async fn get_registered_spec(api: &OnlineClient<PolkadotConfig>, owner: &AccountId32, miner_id: u64) -> Result<HardwareSpec> {
+    let miner_address = substrate_interface::api::storage()
+        .edge_connect()
+        .executable_workers(owner, miner_id);
+
+    let miner_query = api
+        .storage()
+        .at_latest()
+        .await?
+        .fetch(&miner_address)
+        .await?;
+
+    if let Some(miner) = miner_query {
+        Ok(HardwareSpec {
+            ram: miner.specs.ram,
+            storage: miner.specs.storage,
+            cpu: miner.specs.cpu,
+        })
+    } else {
+        Err("Miner not found".into())
+    }
+}
+
+/// The domain the miner was registered with
+pub async fn get_registered_domain(api: &OnlineClient<PolkadotConfig>, owner: &AccountId32, miner_id: u64) -> Result<String> {
+    let miner_address = substrate_interface::api::storage()
+        .edge_connect()
+        .executable_workers(owner, miner_id);
+
+    let miner_query = api
+        .storage()
+        .at_latest()
+        .await?
+        .fetch(&miner_address)
+        .await?;
+
+    if let Some(miner) = miner_query {
+        Ok(String::from_utf8(miner.api.domain.0)?)
+    } else {
+        Err("Miner not found".into())
+    }
+}
@@ -1,6 +1,7 @@
 // Contains all the possible transactions to the parachain, kept out of the `Miner` struct for so that they can contain data that is not the current data (eg. a previous taskId)
 
 use std::fmt::Debug;
+use crate::chain_properties;
 use crate::config;
 use crate::error::Error;
 use crate::specs;
@@ -12,6 +13,20 @@ use substrate_interface::api::neuro_zk::{Error as NzkError};
 use substrate_interface::api::edge_connect::{Error as EdgeConnectError};
 use crate::error::Result;
 use crate::substrate_interface::{self, api::runtime_types::cyborg_primitives::worker::WorkerType};
+use crate::parent_runtime::setup_progress::SetupStage;
+use crate::types::Capabilities;
+
+/// Prefixes of the remarks carrying data the chain has no dedicated extrinsics for
+const CAPABILITIES_REMARK_PREFIX: &str = "cyborg:capabilities:";
+const RESPONSE_ROOT_REMARK_PREFIX: &str = "cyborg:response-root:";
+const ENDPOINT_REMARK_PREFIX: &str = "cyborg:endpoint:";
+const TASK_DECLINED_REMARK_PREFIX: &str = "cyborg:task-declined:";
+const INTEGRITY_MISMATCH_REMARK_PREFIX: &str = "cyborg:integrity-mismatch:";
+const TASK_SETUP_REMARK_PREFIX: &str = "cyborg:task-setup:";
+const TASK_COMPLETED_REMARK_PREFIX: &str = "cyborg:task-completed:";
+const CHALLENGE_REMARK_PREFIX: &str = "cyborg:challenge:";
+const PROOF_TRANSCRIPT_REMARK_PREFIX: &str = "cyborg:proof-transcript:";
+const PROOF_REFERENCE_REMARK_PREFIX: &str = "cyborg:proof:";
 
 /// Registers a worker node on the blockchain.
 ///
@@ -69,10 +84,10 @@ pub async fn register(keypair: Keypair) -> Result<(AccountId32, u64)> {
                return Err(Error::Custom(e.to_string())) 
             } else {
                 match get_miner_by_domain(client, &worker_specs.domain).await {
-                    Ok((miner_id, miner_owner)) => {
-                        println!("Registered miner found: {miner_id}, {miner_owner}"); 
+                    Ok((miner_owner, miner_id)) => {
+                        println!("Registered miner found: {} / {}", chain_properties::format_account(&miner_owner), miner_id);
 
-                        return Ok((miner_id, miner_owner))
+                        return Ok((miner_owner, miner_id))
                     },
                     Err(e) => {
                         return Err(Error::Custom(format!("UNRECOVERABLE ERROR: Cannot bootstrap miner: {e}")));
@@ -83,6 +98,334 @@ pub async fn register(keypair: Keypair) -> Result<(AccountId32, u64)> {
     }
 }
 
+/// Removes the worker node from the blockchain, used when the miner needs to re-register (eg. after a hardware change).
+///
+/// # Arguments
+/// * `miner_id` - The id of the worker that should be removed.
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the worker was removed, or an `Error` if it fails.
+pub async fn remove_worker(keypair: Keypair, miner_id: u64) -> Result<()> {
+    let client = config::get_parachain_client()?;
+
+    let tx = substrate_interface::api::tx()
+        .edge_connect()
+        .remove_worker(WorkerType::Executable, miner_id);
+
+    println!("Transaction Details:");
+    println!("Module: {:?}", tx.pallet_name());
+    println!("Call: {:?}", tx.call_name());
+    println!("Parameters: {:?}", tx.call_data());
+
+    let tx_submission = client
+        .tx()
+        .sign_and_submit_then_watch_default(&tx, &keypair)
+        .await
+        .map(|e| {
+            println!("Miner removal submitted, waiting for transaction to be finalized...");
+            e
+        })?
+        .wait_for_finalized_success()
+        .await;
+
+    match tx_submission {
+        Ok(e) => {
+            let tx_event = e
+                .find_first::<substrate_interface::api::edge_connect::events::WorkerRemoved>(
+            )?;
+
+            if let Some(event) = tx_event {
+                println!("Miner removed successfully: {event:?}");
+            } else {
+                println!("No miner removal event found!");
+            }
+        },
+        Err(e) => {
+           check_for_acceptable_error(EdgeConnectError::WorkerDoesNotExist, e)?; 
+        },
+    }
+
+    Ok(())
+}
+
+/// Publishes the capabilities of the miner as a tagged remark, as there is no dedicated extrinsic for them (yet).
+/// The remark carries the worker identity, so indexers can attribute it to the registered worker.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `miner_identity` - The identity of the registered worker
+/// * `capabilities` - The capabilities gathered at startup
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn publish_capabilities(
+    keypair: Keypair,
+    miner_identity: (AccountId32, u64),
+    capabilities: &Capabilities,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "owner": miner_identity.0.to_string(),
+        "id": miner_identity.1,
+        "capabilities": capabilities,
+    });
+
+    submit_remark(keypair, CAPABILITIES_REMARK_PREFIX, payload, "Capability report").await
+}
+
+/// Publishes the endpoint of a registered miner as a tagged remark, since the domain of a worker can't be updated
+/// without registering it again.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `miner_identity` - The owner and id of the registered miner
+/// * `endpoint` - The domain, tailnet name or IP clients reach the miner at
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn publish_endpoint(
+    keypair: Keypair,
+    miner_identity: (AccountId32, u64),
+    endpoint: &str,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "owner": miner_identity.0.to_string(),
+        "id": miner_identity.1,
+        "endpoint": endpoint,
+    });
+
+    submit_remark(keypair, ENDPOINT_REMARK_PREFIX, payload, "Endpoint").await
+}
+
+/// Flags a task the miner can't serve as a tagged remark, as the chain has no extrinsic to decline a task.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The declined task
+/// * `reason` - Why the miner can't serve the task
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn flag_declined_task(keypair: Keypair, task_id: u64, reason: &str) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "reason": reason,
+    });
+
+    submit_remark(keypair, TASK_DECLINED_REMARK_PREFIX, payload, "Declined task").await
+}
+
+/// Reports task artifacts that differ from their commitment as a tagged remark, as the chain has no extrinsic to
+/// report a mismatch.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The task whose artifacts differ
+/// * `mismatch` - Which artifacts differ and how
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn report_integrity_mismatch(keypair: Keypair, task_id: u64, mismatch: &str) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "mismatch": mismatch,
+    });
+
+    submit_remark(keypair, INTEGRITY_MISMATCH_REMARK_PREFIX, payload, "Integrity mismatch").await
+}
+
+/// Publishes the stage the setup of a task reached as a tagged remark, so that task owners can follow long setups.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The task being set up
+/// * `stage` - The stage the setup reached
+/// * `detail` - Context for the stage, eg. why the setup failed
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn publish_setup_stage(
+    keypair: Keypair,
+    task_id: u64,
+    stage: SetupStage,
+    detail: Option<&str>,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "stage": stage,
+        "detail": detail,
+    });
+
+    submit_remark(keypair, TASK_SETUP_REMARK_PREFIX, payload, "Task setup stage").await
+}
+
+/// Anchors the Merkle root of the request/response pairs served for a task without zk proofs as a tagged remark,
+/// as there is no dedicated extrinsic for it (yet).
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The task the responses were served for
+/// * `root` - The Merkle root of the served request/response hashes
+/// * `leaf_count` - The number of request/response pairs covered by the root
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn anchor_response_root(
+    keypair: Keypair,
+    task_id: u64,
+    root: [u8; 32],
+    leaf_count: usize,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "root": hex::encode(root),
+        "leaves": leaf_count,
+    });
+
+    submit_remark(keypair, RESPONSE_ROOT_REMARK_PREFIX, payload, "Response root").await
+}
+
+/// Publishes the transcript hash a proof was submitted with as a tagged remark, as `submit_proof` has no field for it.
+/// The hash commits to every request/response pair the task served up to the proof.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The task the proof was submitted for
+/// * `transcript_hash` - The hex encoded transcript hash at the proof
+/// * `previous_transcript_hash` - The hex encoded transcript hash at the previous proof
+/// * `leaf_count` - The request/response pairs served between both
+/// * `proof_sha256` - The hex encoded SHA-256 of the submitted proof
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn anchor_proof_transcript(
+    keypair: Keypair,
+    task_id: u64,
+    transcript_hash: &str,
+    previous_transcript_hash: &str,
+    leaf_count: usize,
+    proof_sha256: &str,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "transcript_hash": transcript_hash,
+        "previous_transcript_hash": previous_transcript_hash,
+        "leaves": leaf_count,
+        "proof_sha256": proof_sha256,
+    });
+
+    submit_remark(keypair, PROOF_TRANSCRIPT_REMARK_PREFIX, payload, "Proof transcript").await
+}
+
+/// Submits the hash and location of a proof uploaded to storage as a tagged remark, for tasks whose proofs don't fit
+/// in `submit_proof`.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The task the proof was generated for
+/// * `proof_hash` - The hex encoded SHA-256 of the uploaded proof
+/// * `proof` - Where the proof was uploaded to
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn submit_proof_reference(
+    keypair: Keypair,
+    task_id: u64,
+    proof_hash: &str,
+    proof: &str,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "proof_hash": proof_hash,
+        "proof": proof,
+    });
+
+    submit_remark(keypair, PROOF_REFERENCE_REMARK_PREFIX, payload, "Proof reference").await
+}
+
+/// Submits the results of a completed finite task as a tagged remark. The task management pallet of the current
+/// runtime dropped `submit_completed_task`, the remark carries the same result hash and location.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The completed task
+/// * `result_hash` - The hex encoded SHA-256 of the uploaded results
+/// * `result` - Where the results were uploaded to
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn submit_completed_task(
+    keypair: Keypair,
+    task_id: u64,
+    result_hash: &str,
+    result: &str,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "result_hash": result_hash,
+        "result": result,
+    });
+
+    submit_remark(keypair, TASK_COMPLETED_REMARK_PREFIX, payload, "Completed task").await
+}
+
+/// Anchors the commitment of a challenge answered for the task owner as a tagged remark, so that the owner can hold the
+/// miner to its answer later.
+///
+/// # Arguments
+/// * `keypair` - The keypair of the miner
+/// * `task_id` - The task the challenge was sent to
+/// * `nonce` - The nonce the task owner chose for the challenge
+/// * `commitment` - The hex encoded commitment to the input and output of the challenge
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the remark was included, or an `Error` if it fails.
+pub async fn anchor_challenge_commitment(
+    keypair: Keypair,
+    task_id: u64,
+    nonce: &str,
+    commitment: &str,
+) -> Result<()> {
+    let payload = serde_json::json!({
+        "task_id": task_id,
+        "nonce": nonce,
+        "commitment": commitment,
+    });
+
+    submit_remark(keypair, CHALLENGE_REMARK_PREFIX, payload, "Challenge commitment").await
+}
+
+async fn submit_remark(
+    keypair: Keypair,
+    prefix: &str,
+    payload: serde_json::Value,
+    description: &str,
+) -> Result<()> {
+    let client = config::get_parachain_client()?;
+
+    let remark = format!("{}{}", prefix, payload).into_bytes();
+    let tx = substrate_interface::api::tx().system().remark_with_event(remark);
+
+    println!("Transaction Details:");
+    println!("Module: {:?}", tx.pallet_name());
+    println!("Call: {:?}", tx.call_name());
+    println!("Parameters: {}", payload);
+
+    client
+        .tx()
+        .sign_and_submit_then_watch_default(&tx, &keypair)
+        .await
+        .map(|e| {
+            println!("{} submitted, waiting for transaction to be finalized...", description);
+            e
+        })?
+        .wait_for_finalized_success()
+        .await?;
+
+    println!("{} published", description);
+
+    Ok(())
+}
+
 /// Submits a zkml (Zero Knowledge Machine Learning) proof to the blockchain.
 ///
 /// # Arguments
@@ -91,10 +434,24 @@ pub async fn register(keypair: Keypair) -> Result<(AccountId32, u64)> {
 /// # Returns
 /// A `Result` indicating `Ok(())` if the result is successfully submitted, or an `Error` if it fails.
 pub async fn submit_proof(proof: Vec<u8>, keypair: Keypair, current_task: u64) -> Result<()> {
-    let proof: BoundedVec<u8> = BoundedVec::from(BoundedVec(proof));
-
     let client = config::get_parachain_client()?;
 
+    // The verifier has no chunked submission, so an oversized proof would only be rejected after paying the fees
+    let max_proof_length = client
+        .constants()
+        .at(&substrate_interface::api::constants().zk_verifier().max_proof_length())?;
+
+    if proof.len() > max_proof_length as usize {
+        return Err(Error::Custom(format!(
+            "Proof of {} bytes exceeds the maximum of {} bytes accepted by the chain, the task manifest can ask for \
+             \"proof_submission\": \"storage\"",
+            proof.len(),
+            max_proof_length
+        )));
+    }
+
+    let proof: BoundedVec<u8> = BoundedVec::from(BoundedVec(proof));
+
     let tx = substrate_interface::api::tx()
         .neuro_zk()
         .submit_proof(current_task, proof);
@@ -231,6 +588,42 @@ pub async fn confirm_miner_vacation(keypair: Keypair, task_id: u64) -> Result<()
 }
 
 
+/// Transfers the pending rewards from the service provider account to the miners. Depending on the runtime this
+/// is restricted to privileged accounts, in which case the submission fails with the dispatch error of the chain.
+///
+/// # Returns
+/// A `Result` containing the distributed amount of the signing account if a `MinerRewarded` event was emitted for it
+pub async fn distribute_rewards(keypair: Keypair) -> Result<Option<u128>> {
+    let client = config::get_parachain_client()?;
+    let account = keypair.public_key().to_account_id();
+
+    let tx = substrate_interface::api::tx().payment().distribute_rewards();
+
+    println!("Transaction Details:");
+    println!("Module: {:?}", tx.pallet_name());
+    println!("Call: {:?}", tx.call_name());
+
+    let events = client
+        .tx()
+        .sign_and_submit_then_watch_default(&tx, &keypair)
+        .await
+        .map(|e| {
+            println!("Reward distribution submitted, waiting for transaction to be finalized...");
+            e
+        })?
+        .wait_for_finalized_success()
+        .await?;
+
+    for event in events.find::<substrate_interface::api::payment::events::MinerRewarded>() {
+        let event = event?;
+        if event.0 == account {
+            return Ok(Some(event.1));
+        }
+    }
+
+    Ok(None)
+}
+
 // This takes in a generic that implements debug as the errors that will be put in here are different types of errors
 /// Lets acceptable errors pass through so that the transaction queue doesn't repeat them, because the transaction already succeeded. In some cases for example, the parachain
 /// will accept a transaction, but return an error anyway which will cause the transaction queue to re-queue the transaction. Upon trying again, the transaction will be rejected again, 
@@ -11,16 +11,22 @@ use once_cell::sync::OnceCell;
 use subxt::utils::AccountId32;
 use tokio::time::{sleep, Duration};
 use tokio::sync::{oneshot, Mutex};
+use crate::alerting::{self, Alert};
 use crate::error::Result;
+use crate::utils::block_pacing;
+use crate::utils::fault_injection::{self, Fault};
 
 const MAX_RETRIES: u32 = 500;
 
 /// The type of an async transaction executor closure: no args, returns a Future Result
 type TxExecutor = Box<dyn Fn() -> Pin<Box<dyn Future<Output = Result<TxOutput>> + Send>> + Send + Sync>;
+/// Waits until a transaction should be submitted, awaited before every attempt
+type PacingHook = Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
 
 #[derive(Debug)]
 pub enum TxOutput{
     RegistrationInfo((AccountId32, u64)),
+    RewardsDistributed(Option<u128>),
     Success
 }
 
@@ -28,6 +34,7 @@ pub struct Transaction {
     executor: TxExecutor,
     responder: Option<oneshot::Sender<Result<TxOutput>>>,
     retry_count: u32,
+    pacing: Option<PacingHook>,
 }
 
 #[allow(dead_code)]
@@ -37,10 +44,15 @@ impl Transaction {
             executor,
             retry_count: 0,
             responder,
+            pacing: None,
         }
     }
 
     async fn execute(&self) -> Result<TxOutput> {
+        if let Some(pacing) = &self.pacing {
+            pacing().await;
+        }
+        fault_injection::inject(Fault::ChainSubmission)?;
         (self.executor)().await
     }
 
@@ -73,12 +85,45 @@ impl TransactionQueue {
         F: Fn() -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<TxOutput>> + Send + 'static,
     {
+        self.push(Box::new(move || Box::pin(executor())), None).await
+    }
+
+    /// Enqueues a transaction that is only submitted once `pacing` completes, eg. at a chosen point of the block
+    /// production. The queue waits for it before every attempt, transactions behind it wait as well.
+    ///
+    /// # Arguments
+    /// * `pacing` - Waits until the transaction should be submitted
+    /// * `executor` - Submits the transaction
+    pub async fn enqueue_paced<P, PFut, F, Fut>(
+        &self,
+        pacing: P,
+        executor: F,
+    ) -> Result<oneshot::Receiver<Result<TxOutput>>>
+    where
+        P: Fn() -> PFut + Send + Sync + 'static,
+        PFut: Future<Output = ()> + Send + 'static,
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<TxOutput>> + Send + 'static,
+    {
+        self.push(
+            Box::new(move || Box::pin(executor())),
+            Some(Box::new(move || Box::pin(pacing()))),
+        )
+        .await
+    }
+
+    async fn push(
+        &self,
+        executor: TxExecutor,
+        pacing: Option<PacingHook>,
+    ) -> Result<oneshot::Receiver<Result<TxOutput>>> {
         let (tx, rx) = oneshot::channel();
 
         let tx = Transaction {
-            executor: Box::new(move || Box::pin(executor())),
+            executor,
             responder: Some(tx),
             retry_count: 0,
+            pacing,
         };
 
         self.inner.lock().await.push_back(tx);
@@ -87,6 +132,11 @@ impl TransactionQueue {
         Ok(rx)
     }
 
+    /// Number of transactions waiting to be submitted, the one being submitted is not counted
+    pub async fn pending(&self) -> usize {
+        self.inner.lock().await.len()
+    }
+
     pub fn start_processing(&self) {
         if self.processing.swap(true, Ordering::SeqCst) {
             // Already processing
@@ -109,6 +159,7 @@ impl TransactionQueue {
                         match tx.execute().await{
                             Ok(result) => {
                                 println!("Transaction succeeded: {result:?}");
+                                block_pacing::record_inclusion();
                                 if let Some(responder) = tx.responder.take() {
                                     let _ = responder.send(Ok(result));
                                 }
@@ -126,6 +177,10 @@ impl TransactionQueue {
                             }
                             Err(e) => {
                                 println!("Transaction failed: {}", e);
+                                alerting::raise(
+                                    Alert::TransactionDeadLettered,
+                                    format!("A transaction was dropped after {} retries: {}", MAX_RETRIES, e),
+                                );
                                 if let Some(responder) = tx.responder.take() {
                                     let _ = responder.send(Err(e));
                                 }
@@ -142,3 +197,33 @@ impl TransactionQueue {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::ChainApi;
+    use crate::utils::mock_chain::{self, ChainCall, MockChain};
+
+    #[tokio::test]
+    async fn submissions_are_answered_through_the_receiver() {
+        let queue = TransactionQueue::new();
+        let chain = Arc::new(MockChain::default());
+        let keypair = mock_chain::miner(Arc::clone(&chain)).keypair;
+
+        let submitting_chain = Arc::clone(&chain);
+        let rx = queue
+            .enqueue(move || {
+                let chain = Arc::clone(&submitting_chain);
+                let keypair = keypair.clone();
+                async move {
+                    chain.confirm_reception(keypair, 3).await?;
+                    Ok(TxOutput::Success)
+                }
+            })
+            .await
+            .unwrap();
+
+        assert!(matches!(rx.await.unwrap(), Ok(TxOutput::Success)));
+        assert_eq!(chain.calls(), vec![ChainCall::ConfirmReception(3)]);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    chain_properties, config,
+    error::{Error, Result},
+    parachain_interactor::{fingerprint, identity},
+    schema::{self, MinerIdentity},
+    utils::substrate_queries::get_registered_domain,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Where the worker node kept its files before it was replaced by the miner
+pub const WORKER_DIR: &str = "/var/lib/cyborg/worker-node";
+
+/// Where the worker node kept its `WorkerData`, relative to its directory. Older releases kept it with the rest of
+/// the configuration.
+const WORKER_IDENTITY_FILES: [&str; 2] = ["identity.json", "config/worker_config.json"];
+const WORKER_TASK_OWNER_FILE: &str = "task/task_owner.json";
+const WORKER_TASK_DIR: &str = "task/current_task";
+const WORKER_LOG_DIR: &str = "logs";
+
+/// Moves the files of a worker node installation to the paths of the miner configured in the environment, so that
+/// operators upgrading to the miner keep their registration and logs. The `WorkerData` is converted to the identity
+/// of the miner and checked against the registration on the parachain before anything is moved. The identity is
+/// moved last, a migration that failed halfway can be repeated.
+///
+/// # Arguments
+/// * `worker_dir` - The directory of the worker node, `/var/lib/cyborg/worker-node` by default
+/// * `parachain_url` - The URL of the parachain node the registration is checked with
+/// * `force` - Whether to replace an existing identity of the miner
+///
+/// # Returns
+/// A `Result` indicating `Ok(())` if the worker node was migrated, or an `Error` if it isn't registered or a file can't
+/// be moved.
+pub async fn migrate_from_worker(
+    worker_dir: &Path,
+    parachain_url: &str,
+    force: bool,
+) -> Result<()> {
+    config::load_dotenv();
+    let paths = config::paths_from_env();
+
+    let (identity_file, worker_identity) = find_worker_identity(worker_dir)?.ok_or_else(|| {
+        Error::Custom(format!(
+            "No identity of a worker node found in {}",
+            worker_dir.display()
+        ))
+    })?;
+    if Path::new(&paths.identity_path).exists() && !force {
+        return Err(Error::Custom(format!(
+            "{} already exists, pass --force to replace the identity of this miner",
+            paths.identity_path
+        )));
+    }
+
+    let api = chain_properties::connect(parachain_url).await?;
+    let owner = chain_properties::format_account(&worker_identity.owner);
+    let domain = get_registered_domain(&api, &worker_identity.owner, worker_identity.miner_id)
+        .await
+        .map_err(|e| {
+            Error::Custom(format!(
+                "Worker {} / {} is not registered on the parachain ({}), nothing was migrated",
+                owner, worker_identity.miner_id, e
+            ))
+        })?;
+    println!(
+        "Worker {} / {} is registered with domain {}",
+        owner, worker_identity.miner_id, domain
+    );
+
+    let task_owner_file = worker_dir.join(WORKER_TASK_OWNER_FILE);
+    if task_owner_file.is_file() {
+        let (task_owner, _) = schema::parse_task_owner(&fs::read_to_string(&task_owner_file)?)?;
+        write_file(
+            Path::new(&paths.task_owner_path),
+            &serde_json::to_string(&task_owner)?,
+        )?;
+        fs::remove_file(&task_owner_file)?;
+        println!("Moved the task owner to {}", paths.task_owner_path);
+    }
+
+    let moved_task_files = move_dir_contents(
+        &worker_dir.join(WORKER_TASK_DIR),
+        Path::new(&paths.task_dir_path),
+    )?;
+    if moved_task_files > 0 {
+        println!(
+            "Moved {} files of the current task to {}",
+            moved_task_files, paths.task_dir_path
+        );
+    }
+
+    let log_dir = paths
+        .log_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let moved_logs = move_dir_contents(&worker_dir.join(WORKER_LOG_DIR), &log_dir)?;
+    if moved_logs > 0 {
+        println!("Moved {} log files to {}", moved_logs, log_dir.display());
+    }
+
+    write_file(
+        Path::new(&paths.identity_path),
+        &serde_json::to_string(&worker_identity)?,
+    )?;
+    // Bound to this host the next time the miner starts
+    fingerprint::unbind_identity(&paths.identity_path)?;
+
+    // Read back the way the miner reads it at startup
+    if schema::read_identity(&paths.identity_path)?.as_ref() != Some(&worker_identity) {
+        return Err(Error::Custom(format!(
+            "The migrated identity in {} doesn't match the worker node, {} was left in place",
+            paths.identity_path,
+            identity_file.display()
+        )));
+    }
+    fs::remove_file(&identity_file)?;
+
+    println!(
+        "Migrated worker {} / {} to {}, start the miner with the account seed of the worker",
+        owner, worker_identity.miner_id, paths.identity_path
+    );
+    Ok(())
+}
+
+/// Points operators who upgraded from the worker node to the migration, instead of letting the miner register again
+pub fn hint_unmigrated_worker() {
+    if let Ok(Some((identity_file, _))) = find_worker_identity(Path::new(WORKER_DIR)) {
+        println!(
+            "Found the identity of a worker node in {}, run `cyborg-miner migrate-from-worker` to keep its registration",
+            identity_file.display()
+        );
+    }
+}
+
+/// The `WorkerData` of the worker node in `worker_dir`, converted to the identity of the miner
+fn find_worker_identity(worker_dir: &Path) -> Result<Option<(PathBuf, MinerIdentity)>> {
+    for file in WORKER_IDENTITY_FILES {
+        let path = worker_dir.join(file);
+        if path.is_file() {
+            let (identity, _) = schema::parse_identity(&fs::read_to_string(&path)?)?;
+            return Ok(Some((path, identity)));
+        }
+    }
+    Ok(None)
+}
+
+/// Writes a configuration file only readable by the miner, encrypted by the miner at its next start if enabled
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    identity::write_private(path, content.as_bytes())
+}
+
+/// Moves the files and directories in `from` into `to`, files are copied if they are on another file system
+///
+/// # Returns
+/// The number of entries moved
+fn move_dir_contents(from: &Path, to: &Path) -> Result<usize> {
+    if !from.is_dir() {
+        return Ok(0);
+    }
+    fs::create_dir_all(to)?;
+
+    let mut moved = 0;
+    for entry in fs::read_dir(from)? {
+        let source = entry?.path();
+        let target = to.join(source.file_name().unwrap_or_default());
+        if target.exists() {
+            println!(
+                "Leaving {}, {} already exists",
+                source.display(),
+                target.display()
+            );
+            continue;
+        }
+
+        match fs::rename(&source, &target) {
+            Ok(()) => {}
+            Err(_) if source.is_file() => {
+                fs::copy(&source, &target)?;
+                fs::remove_file(&source)?;
+            }
+            Err(e) => {
+                return Err(Error::Custom(format!(
+                    "Failed to move {} to {}: {}",
+                    source.display(),
+                    target.display(),
+                    e
+                )))
+            }
+        }
+        moved += 1;
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_data_is_found_with_the_configuration_of_older_releases() {
+        let worker_dir = std::env::temp_dir().join(format!("worker-node-{}", std::process::id()));
+        fs::create_dir_all(worker_dir.join("config")).unwrap();
+        assert!(find_worker_identity(&worker_dir).unwrap().is_none());
+
+        let account = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+        fs::write(
+            worker_dir.join("config/worker_config.json"),
+            format!(
+                r#"{{"worker_owner":"{}","worker_identity":["{}",2]}}"#,
+                account, account
+            ),
+        )
+        .unwrap();
+
+        let (path, identity) = find_worker_identity(&worker_dir).unwrap().unwrap();
+        assert_eq!(path, worker_dir.join("config/worker_config.json"));
+        assert_eq!(identity.miner_id, 2);
+
+        fs::remove_dir_all(worker_dir).unwrap();
+    }
+}
@@ -0,0 +1,493 @@
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use zstd::stream::read::Decoder;
+
+/// File in the task directory recording how the model archive was last extracted
+const EXTRACTION_REPORT_PATH: &str = "extraction-report.json";
+/// File the write throughput of the task directory is measured with, removed right after
+const BENCHMARK_FILE_PATH: &str = "extraction-benchmark.tmp";
+/// Bytes read, decompressed and written to measure the throughputs, small enough to take well under a second on a
+/// server and a few seconds on an SD card
+const BENCHMARK_BYTES: u64 = 8 * 1024 * 1024;
+/// Below this throughput in MB/s a disk is treated as slow, eg. an SD card or a spinning disk of an edge device, and
+/// gets larger buffers so it sees fewer and longer requests
+const SLOW_DISK_MBPS: f64 = 100.0;
+/// Chunks a pipelined extraction decompresses ahead of the writer
+const PIPELINE_DEPTH: usize = 4;
+
+/// The throughputs of the host measured on a sample of the archive before it is extracted, in MB/s
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtractionBenchmark {
+    /// Reading the compressed archive from the disk
+    pub read_mbps: f64,
+    /// Decompressing the archive, counted in decompressed bytes
+    pub decode_mbps: f64,
+    /// Writing to the task directory, synced to the disk
+    pub write_mbps: f64,
+    /// How long the measurement took
+    pub elapsed: Duration,
+}
+
+impl ExtractionBenchmark {
+    /// Measures the throughputs on the first `BENCHMARK_BYTES` of the archive, the extracted files are written to
+    /// `prefix`. A freshly downloaded archive is usually still in the page cache, so reading it is measured as the
+    /// extraction will see it.
+    ///
+    /// # Arguments
+    /// * `archive_path` - The zstd compressed archive
+    /// * `prefix` - The directory the files will be extracted to
+    ///
+    /// # Returns
+    /// The `ExtractionBenchmark`, or an error if the archive can't be read or the directory not written
+    pub fn measure(archive_path: &Path, prefix: &Path) -> io::Result<Self> {
+        let started = Instant::now();
+
+        let read_started = Instant::now();
+        let read = io::copy(
+            &mut File::open(archive_path)?.take(BENCHMARK_BYTES),
+            &mut io::sink(),
+        )?;
+        let read_mbps = mbps(read, read_started.elapsed());
+
+        let decode_started = Instant::now();
+        let decoded = io::copy(
+            &mut Decoder::new(File::open(archive_path)?)?.take(BENCHMARK_BYTES),
+            &mut io::sink(),
+        )?;
+        let decode_mbps = mbps(decoded, decode_started.elapsed());
+
+        let benchmark_path = prefix.join(BENCHMARK_FILE_PATH);
+        let write_started = Instant::now();
+        let written = (|| {
+            let mut file = File::create(&benchmark_path)?;
+            let chunk = vec![0u8; 1024 * 1024];
+            for _ in 0..BENCHMARK_BYTES / chunk.len() as u64 {
+                file.write_all(&chunk)?;
+            }
+            file.sync_all()
+        })();
+        let write_mbps = mbps(BENCHMARK_BYTES, write_started.elapsed());
+        let _ = fs::remove_file(&benchmark_path);
+        written?;
+
+        Ok(Self {
+            read_mbps,
+            decode_mbps,
+            write_mbps,
+            elapsed: started.elapsed(),
+        })
+    }
+}
+
+fn mbps(bytes: u64, elapsed: Duration) -> f64 {
+    bytes as f64 / 1_000_000.0 / elapsed.as_secs_f64().max(1e-6)
+}
+
+/// How the model archive is extracted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractionTuning {
+    /// Bytes read from the archive at once
+    pub read_buffer: usize,
+    /// Bytes decompressed before they are handed to the writer
+    pub write_buffer: usize,
+    /// Whether the extracted files are written on a thread of their own while the next bytes are decompressed
+    pub pipelined: bool,
+}
+
+impl Default for ExtractionTuning {
+    /// Small buffers on a single thread, as archives were extracted before tuning
+    fn default() -> Self {
+        Self {
+            read_buffer: 8 * 1024,
+            write_buffer: 8 * 1024,
+            pipelined: false,
+        }
+    }
+}
+
+impl ExtractionTuning {
+    /// Tunes the extraction to the measured host. Slow disks get large buffers. Decompressing and writing are only
+    /// overlapped if a second core is free for it and neither takes less than a quarter of the time of the other, the
+    /// slower one bounds the extraction anyway.
+    ///
+    /// # Arguments
+    /// * `benchmark` - The throughputs of the host
+    /// * `cores` - The cores available to the extraction
+    ///
+    /// # Returns
+    /// The `ExtractionTuning` for the host
+    pub fn choose(benchmark: &ExtractionBenchmark, cores: usize) -> Self {
+        let buffer = |mbps: f64| {
+            if mbps < SLOW_DISK_MBPS {
+                4 * 1024 * 1024
+            } else {
+                256 * 1024
+            }
+        };
+        let slower = benchmark.decode_mbps.min(benchmark.write_mbps);
+        let faster = benchmark.decode_mbps.max(benchmark.write_mbps);
+
+        Self {
+            read_buffer: buffer(benchmark.read_mbps),
+            write_buffer: buffer(benchmark.write_mbps),
+            pipelined: cores > 1 && slower * 4.0 >= faster,
+        }
+    }
+}
+
+/// Where the time of the last extraction of the model archive went, kept in the task directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractionReport {
+    /// The measured throughputs, `None` if the extraction wasn't tuned to the host
+    pub benchmark: Option<ExtractionBenchmark>,
+    pub tuning: ExtractionTuning,
+    pub archive_bytes: u64,
+    pub extracted_bytes: u64,
+    /// Time spent reading and decompressing the archive
+    pub decode: Duration,
+    /// Time spent writing the extracted files, overlapping `decode` if the extraction was pipelined
+    pub write: Duration,
+    /// Duration of the whole extraction, without the benchmark
+    pub elapsed: Duration,
+}
+
+impl ExtractionReport {
+    /// Loads the report of the last extraction in the task directory
+    ///
+    /// # Returns
+    /// The `ExtractionReport`, `None` if the archive wasn't extracted yet or the report is unreadable
+    pub fn load(task_dir: &Path) -> Option<Self> {
+        let report: Value =
+            serde_json::from_str(&fs::read_to_string(task_dir.join(EXTRACTION_REPORT_PATH)).ok()?)
+                .ok()?;
+        let millis = |value: &Value| value.as_u64().map(Duration::from_millis);
+        let tuning = &report["tuning"];
+
+        Some(Self {
+            benchmark: report["benchmark"].as_object().and_then(|benchmark| {
+                Some(ExtractionBenchmark {
+                    read_mbps: benchmark.get("read_mbps")?.as_f64()?,
+                    decode_mbps: benchmark.get("decode_mbps")?.as_f64()?,
+                    write_mbps: benchmark.get("write_mbps")?.as_f64()?,
+                    elapsed: millis(benchmark.get("elapsed_ms")?)?,
+                })
+            }),
+            tuning: ExtractionTuning {
+                read_buffer: tuning["read_buffer"].as_u64()? as usize,
+                write_buffer: tuning["write_buffer"].as_u64()? as usize,
+                pipelined: tuning["pipelined"].as_bool()?,
+            },
+            archive_bytes: report["archive_bytes"].as_u64()?,
+            extracted_bytes: report["extracted_bytes"].as_u64()?,
+            decode: millis(&report["decode_ms"])?,
+            write: millis(&report["write_ms"])?,
+            elapsed: millis(&report["elapsed_ms"])?,
+        })
+    }
+
+    /// Records the report in the task directory, written to a temporary file first so a crash never leaves a corrupt
+    /// record
+    pub(crate) fn save(&self, task_dir: &Path) -> io::Result<()> {
+        let report = json!({
+            "benchmark": self.benchmark.map(|benchmark| json!({
+                "read_mbps": benchmark.read_mbps,
+                "decode_mbps": benchmark.decode_mbps,
+                "write_mbps": benchmark.write_mbps,
+                "elapsed_ms": benchmark.elapsed.as_millis() as u64,
+            })),
+            "tuning": {
+                "read_buffer": self.tuning.read_buffer,
+                "write_buffer": self.tuning.write_buffer,
+                "pipelined": self.tuning.pipelined,
+            },
+            "archive_bytes": self.archive_bytes,
+            "extracted_bytes": self.extracted_bytes,
+            "decode_ms": self.decode.as_millis() as u64,
+            "write_ms": self.write.as_millis() as u64,
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+        });
+
+        let path = task_dir.join(EXTRACTION_REPORT_PATH);
+        let temporary_path = path.with_extension("json.tmp");
+        fs::write(&temporary_path, report.to_string())?;
+        fs::rename(temporary_path, path)
+    }
+}
+
+/// Writes the files of an extraction in place, timing the writes
+pub(crate) struct InlineWriter {
+    buffer_size: usize,
+    file: Option<BufWriter<File>>,
+    elapsed: Duration,
+}
+
+impl InlineWriter {
+    fn new(buffer_size: usize) -> Self {
+        Self {
+            buffer_size,
+            file: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn timed<T>(&mut self, write: impl FnOnce(&mut Self) -> io::Result<T>) -> io::Result<T> {
+        let started = Instant::now();
+        let result = write(self);
+        self.elapsed += started.elapsed();
+        result
+    }
+
+    fn create(&mut self, partial_path: &Path) -> io::Result<()> {
+        self.timed(|writer| {
+            writer.file = Some(BufWriter::with_capacity(
+                writer.buffer_size,
+                File::create(partial_path)?,
+            ));
+            Ok(())
+        })
+    }
+
+    fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.timed(|writer| match &mut writer.file {
+            Some(file) => file.write_all(chunk),
+            None => Err(io::Error::other("No extracted file is open")),
+        })
+    }
+
+    /// Syncs the file and moves it to its final path, so an interrupted extraction never leaves a truncated file
+    fn finish(&mut self, partial_path: &Path, output_path: &Path) -> io::Result<()> {
+        self.timed(|writer| {
+            let file = writer
+                .file
+                .take()
+                .ok_or_else(|| io::Error::other("No extracted file is open"))?;
+            file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            fs::rename(partial_path, output_path)
+        })
+    }
+}
+
+pub(crate) enum WriteRequest {
+    Create(PathBuf),
+    Chunk(Vec<u8>),
+    Finish {
+        partial_path: PathBuf,
+        output_path: PathBuf,
+        done: mpsc::Sender<io::Result<()>>,
+    },
+}
+
+/// Writes the files of an extraction, on a thread of its own if the extraction is pipelined. A file only counts as
+/// extracted once `finish` returned, a pipelined writer waits for the writes of the file then.
+pub(crate) enum FileWriter {
+    Inline(InlineWriter),
+    Pipelined {
+        requests: mpsc::SyncSender<WriteRequest>,
+        thread: JoinHandle<Duration>,
+    },
+}
+
+impl FileWriter {
+    pub(crate) fn new(tuning: &ExtractionTuning) -> io::Result<Self> {
+        let mut writer = InlineWriter::new(tuning.write_buffer);
+        if !tuning.pipelined {
+            return Ok(FileWriter::Inline(writer));
+        }
+
+        let (requests, received) = mpsc::sync_channel::<WriteRequest>(PIPELINE_DEPTH);
+        let thread = std::thread::Builder::new()
+            .name("nzk-extraction-writer".to_string())
+            .spawn(move || {
+                // A failed write is reported when the file is finished, the rest of its chunks are dropped
+                let mut failure = None;
+                for request in received {
+                    match request {
+                        WriteRequest::Create(partial_path) => {
+                            failure = writer.create(&partial_path).err();
+                        }
+                        WriteRequest::Chunk(chunk) => {
+                            if failure.is_none() {
+                                failure = writer.write(&chunk).err();
+                            }
+                        }
+                        WriteRequest::Finish {
+                            partial_path,
+                            output_path,
+                            done,
+                        } => {
+                            let result = match failure.take() {
+                                Some(e) => Err(e),
+                                None => writer.finish(&partial_path, &output_path),
+                            };
+                            let _ = done.send(result);
+                        }
+                    }
+                }
+                writer.elapsed
+            })?;
+
+        Ok(FileWriter::Pipelined { requests, thread })
+    }
+
+    fn send(requests: &mpsc::SyncSender<WriteRequest>, request: WriteRequest) -> io::Result<()> {
+        requests
+            .send(request)
+            .map_err(|_| io::Error::other("The writer of the extraction stopped"))
+    }
+
+    pub(crate) fn create(&mut self, partial_path: &Path) -> io::Result<()> {
+        match self {
+            FileWriter::Inline(writer) => writer.create(partial_path),
+            FileWriter::Pipelined { requests, .. } => {
+                Self::send(requests, WriteRequest::Create(partial_path.to_path_buf()))
+            }
+        }
+    }
+
+    pub(crate) fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        match self {
+            FileWriter::Inline(writer) => writer.write(chunk),
+            FileWriter::Pipelined { requests, .. } => {
+                Self::send(requests, WriteRequest::Chunk(chunk.to_vec()))
+            }
+        }
+    }
+
+    pub(crate) fn finish(&mut self, partial_path: &Path, output_path: &Path) -> io::Result<()> {
+        match self {
+            FileWriter::Inline(writer) => writer.finish(partial_path, output_path),
+            FileWriter::Pipelined { requests, .. } => {
+                let (done, finished) = mpsc::channel();
+                Self::send(
+                    requests,
+                    WriteRequest::Finish {
+                        partial_path: partial_path.to_path_buf(),
+                        output_path: output_path.to_path_buf(),
+                        done,
+                    },
+                )?;
+                finished
+                    .recv()
+                    .map_err(|_| io::Error::other("The writer of the extraction stopped"))?
+            }
+        }
+    }
+
+    /// Stops the writer, a file that wasn't finished stays partial
+    ///
+    /// # Returns
+    /// The time spent writing
+    pub(crate) fn close(self) -> Duration {
+        match self {
+            FileWriter::Inline(writer) => writer.elapsed,
+            FileWriter::Pipelined { requests, thread } => {
+                drop(requests);
+                thread.join().unwrap_or_default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn benchmark(read_mbps: f64, decode_mbps: f64, write_mbps: f64) -> ExtractionBenchmark {
+        ExtractionBenchmark {
+            read_mbps,
+            decode_mbps,
+            write_mbps,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn slow_disks_get_large_buffers() {
+        let tuning = ExtractionTuning::choose(&benchmark(40.0, 500.0, 30.0), 4);
+
+        assert_eq!(tuning.read_buffer, 4 * 1024 * 1024);
+        assert_eq!(tuning.write_buffer, 4 * 1024 * 1024);
+
+        let tuning = ExtractionTuning::choose(&benchmark(2000.0, 500.0, 1500.0), 4);
+
+        assert_eq!(tuning.read_buffer, 256 * 1024);
+        assert_eq!(tuning.write_buffer, 256 * 1024);
+    }
+
+    #[test]
+    fn only_balanced_extractions_on_several_cores_are_pipelined() {
+        assert!(ExtractionTuning::choose(&benchmark(500.0, 400.0, 200.0), 2).pipelined);
+        assert!(!ExtractionTuning::choose(&benchmark(500.0, 400.0, 200.0), 1).pipelined);
+        assert!(!ExtractionTuning::choose(&benchmark(500.0, 1000.0, 50.0), 8).pipelined);
+    }
+
+    fn writer_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nzk-extraction-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn files_are_only_moved_in_place_once_finished() {
+        for pipelined in [false, true] {
+            let dir = writer_dir(&format!("finished-{}", pipelined));
+            let tuning = ExtractionTuning {
+                pipelined,
+                ..ExtractionTuning::default()
+            };
+            let mut writer = FileWriter::new(&tuning).unwrap();
+
+            let (partial, output) = (dir.join("model.partial"), dir.join("model"));
+            writer.create(&partial).unwrap();
+            writer.write(b"first ").unwrap();
+            writer.write(b"second").unwrap();
+            assert!(!output.exists());
+            writer.finish(&partial, &output).unwrap();
+
+            // A file the extraction stopped in stays partial
+            let (partial, unfinished) = (dir.join("settings.partial"), dir.join("settings"));
+            writer.create(&partial).unwrap();
+            writer.write(b"truncated").unwrap();
+            writer.close();
+
+            assert_eq!(fs::read(&output).unwrap(), b"first second");
+            assert!(!dir.join("model.partial").exists());
+            assert!(partial.exists());
+            assert!(!unfinished.exists());
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn failed_writes_are_reported_when_the_file_is_finished() {
+        let dir = writer_dir("failed");
+        let tuning = ExtractionTuning {
+            pipelined: true,
+            ..ExtractionTuning::default()
+        };
+        let mut writer = FileWriter::new(&tuning).unwrap();
+
+        let partial = dir.join("missing").join("model.partial");
+        writer.create(&partial).unwrap();
+        writer.write(b"dropped").unwrap();
+
+        assert!(writer.finish(&partial, &dir.join("model")).is_err());
+        assert!(!dir.join("model").exists());
+
+        // The writer keeps extracting the next files
+        let (partial, output) = (dir.join("settings.partial"), dir.join("settings"));
+        writer.create(&partial).unwrap();
+        writer.write(b"{}").unwrap();
+        writer.finish(&partial, &output).unwrap();
+        writer.close();
+
+        assert_eq!(fs::read(&output).unwrap(), b"{}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use serde_json::Value;
+use std::{fs, path::Path};
+
+/// Default upper bound for the size of a single inference request
+pub const DEFAULT_MAX_REQUEST_BYTES: usize = 1024 * 1024;
+
+/// Validates inference requests against the shape of the circuit before they are handed to EZKL, since
+/// malformed or oversized inputs make witness generation allocate without bounds.
+#[derive(Debug, Clone)]
+pub struct InputGuard {
+    /// Number of elements of every input tensor, in order
+    tensor_lengths: Vec<usize>,
+    /// Fixed point scale of every input tensor, values are multiplied by 2^scale during quantization
+    input_scales: Vec<i32>,
+}
+
+impl InputGuard {
+    /// Derives the expected inputs of the circuit. The number of inputs and their scales come from the circuit settings,
+    /// the tensor lengths from the reference input shipped with the task, which is covered by the on-chain commitment.
+    ///
+    /// # Arguments
+    /// * `settings_path` - The path to the circuit settings
+    /// * `reference_input_path` - The path to the reference input of the task
+    ///
+    /// # Returns
+    /// The `InputGuard` for the circuit, or an Error if the shapes cannot be derived
+    pub fn load(
+        settings_path: &Path,
+        reference_input_path: &Path,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let settings: Value = serde_json::from_str(&fs::read_to_string(settings_path)?)?;
+        let input_scales: Vec<i32> = settings["model_input_scales"]
+            .as_array()
+            .ok_or("Circuit settings contain no input scales")?
+            .iter()
+            .map(|scale| scale.as_i64().map(|scale| scale as i32))
+            .collect::<Option<_>>()
+            .ok_or("Circuit settings contain an invalid input scale")?;
+
+        let reference: Value = serde_json::from_str(&fs::read_to_string(reference_input_path)?)?;
+        let tensor_lengths: Vec<usize> = reference["input_data"]
+            .as_array()
+            .ok_or("Reference input contains no input data")?
+            .iter()
+            .map(|tensor| tensor.as_array().map(|values| values.len()))
+            .collect::<Option<_>>()
+            .ok_or("Reference input contains an invalid tensor")?;
+
+        if tensor_lengths.len() != input_scales.len() {
+            return Err(format!(
+                "Reference input has {} tensors, but the circuit expects {}",
+                tensor_lengths.len(),
+                input_scales.len()
+            )
+            .into());
+        }
+
+        Ok(Self {
+            tensor_lengths,
+            input_scales,
+        })
+    }
+
+    /// Checks that a request has exactly the expected tensors, and that every value is finite and representable
+    /// as a fixed point number at the scale of its tensor.
+    ///
+    /// # Arguments
+    /// * `request` - The inference request, `{"input_data": [[...], ...]}`
+    ///
+    /// # Returns
+    /// `Ok(())` if the request can be handed to EZKL, otherwise a description of the problem
+    pub fn validate(&self, request: &str) -> Result<(), String> {
+        let request: Value = serde_json::from_str(request)
+            .map_err(|e| format!("Request is not valid JSON: {}", e))?;
+
+        let tensors = request["input_data"]
+            .as_array()
+            .ok_or("Request contains no input_data array")?;
+
+        if tensors.len() != self.tensor_lengths.len() {
+            return Err(format!(
+                "Expected {} input tensors, got {}",
+                self.tensor_lengths.len(),
+                tensors.len()
+            ));
+        }
+
+        for (index, tensor) in tensors.iter().enumerate() {
+            let values = tensor
+                .as_array()
+                .ok_or(format!("Input tensor {} is not an array", index))?;
+
+            if values.len() != self.tensor_lengths[index] {
+                return Err(format!(
+                    "Input tensor {} must have {} values, got {}",
+                    index,
+                    self.tensor_lengths[index],
+                    values.len()
+                ));
+            }
+
+            // Quantized values are stored in an i64, anything at or above 2^63 after scaling overflows
+            let max_magnitude = 2f64.powi(63 - self.input_scales[index].min(62));
+
+            for value in values {
+                let value = value.as_f64().ok_or(format!(
+                    "Input tensor {} contains a non-numeric value",
+                    index
+                ))?;
+
+                if !value.is_finite() || value.abs() >= max_magnitude {
+                    return Err(format!(
+                        "Input tensor {} contains the out of range value {}",
+                        index, value
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes the circuit settings and the reference input of a task to a directory of the test
+    fn task_files(name: &str, settings: &str, reference_input: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("nzk-input-guard-{}", name));
+        fs::create_dir_all(&dir).unwrap();
+        let (settings_path, reference_input_path) =
+            (dir.join("settings.json"), dir.join("input.json"));
+        fs::write(&settings_path, settings).unwrap();
+        fs::write(&reference_input_path, reference_input).unwrap();
+        (settings_path, reference_input_path)
+    }
+
+    fn guard() -> InputGuard {
+        let (settings, reference_input) = task_files(
+            "guard",
+            r#"{"model_input_scales": [7, 60]}"#,
+            r#"{"input_data": [[0.1, 0.2, 0.3], [1.0]]}"#,
+        );
+        InputGuard::load(&settings, &reference_input).unwrap()
+    }
+
+    #[test]
+    fn requests_must_match_the_reference_input() {
+        let guard = guard();
+
+        assert!(guard
+            .validate(r#"{"input_data": [[1, -2, 3.5], [4]]}"#)
+            .is_ok());
+        assert!(guard.validate(r#"{"input_data": [[1, 2, 3]]}"#).is_err());
+        assert!(guard.validate(r#"{"input_data": [[1, 2], [4]]}"#).is_err());
+        assert!(guard
+            .validate(r#"{"input_data": [[1, 2, "3"], [4]]}"#)
+            .is_err());
+        assert!(guard.validate(r#"{"input_data": [[1, 2, 3], 4]}"#).is_err());
+        assert!(guard.validate(r#"{"input": [[1, 2, 3], [4]]}"#).is_err());
+        assert!(guard.validate("input_data").is_err());
+    }
+
+    #[test]
+    fn values_must_fit_the_scale_of_their_tensor() {
+        let guard = guard();
+
+        // At scale 60 only magnitudes below 2^3 fit in an i64
+        assert!(guard
+            .validate(r#"{"input_data": [[1, 2, 3], [-7.9]]}"#)
+            .is_ok());
+        assert!(guard
+            .validate(r#"{"input_data": [[1, 2, 3], [8]]}"#)
+            .is_err());
+        assert!(guard
+            .validate(r#"{"input_data": [[1e300, 2, 3], [4]]}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn the_reference_input_must_match_the_circuit() {
+        let (settings, reference_input) = task_files(
+            "mismatch",
+            r#"{"model_input_scales": [7, 7]}"#,
+            r#"{"input_data": [[0.1, 0.2, 0.3]]}"#,
+        );
+
+        assert!(InputGuard::load(&settings, &reference_input).is_err());
+    }
+}
@@ -1,21 +1,69 @@
 use ezkl::{
-    commands::Commands::{GenWitness, GetSrs, Prove},
+    commands::Commands::{GenWitness, GetSrs, Prove, Verify},
     execute::run,
     Commitments,
 };
 use zstd::stream::read::Decoder;
 use futures::{stream::StreamExt, Future, Stream};
-use std::io::{copy, BufReader};
+use inference_protocol::split_request_options;
+use std::io::{BufReader, Read};
 use std::{
     fs::{self, File},
     path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 use tar::Archive;
+use tokio::sync::Semaphore;
+
+mod extraction;
+mod input_guard;
+pub mod prover;
+mod setup_progress;
+
+pub use inference_protocol::{error_response, ErrorCode};
+pub use extraction::{ExtractionBenchmark, ExtractionReport, ExtractionTuning};
+use extraction::FileWriter;
+pub use input_guard::{InputGuard, DEFAULT_MAX_REQUEST_BYTES};
+pub use prover::{last_job_log, ProverProcess};
+pub use setup_progress::SetupStep;
+use setup_progress::SetupProgress;
 
 #[derive(Debug)]
 pub struct NeuroZKEngine {
     model_archive_path: PathBuf,
     task_dir_string: String,
+    request_timeout: Option<Duration>,
+    max_request_bytes: usize,
+    max_extracted_bytes: Option<u64>,
+    extraction_tuning: Option<ExtractionTuning>,
+    blocking_permits: Arc<Semaphore>,
+    prover: Option<ProverProcess>,
+}
+
+/// The stages a proof passes through, reported to the progress callback of `prove_inference_with_progress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofStage {
+    Witness,
+    Prove,
+    Done,
+}
+
+impl ProofStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProofStage::Witness => "witness",
+            ProofStage::Prove => "prove",
+            ProofStage::Done => "done",
+        }
+    }
+}
+
+/// A stage transition of a running proof, `elapsed` is measured from the start of the proof
+#[derive(Debug, Clone, Copy)]
+pub struct ProofProgress {
+    pub stage: ProofStage,
+    pub elapsed: Duration,
 }
 
 const MODEL_PATH: &str = "network.ezkl";
@@ -41,12 +89,99 @@ impl NeuroZKEngine {
             Ok(Self {
                 model_archive_path,
                 task_dir_string: task_dir_string.to_string(),
+                request_timeout: None,
+                max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+                max_extracted_bytes: None,
+                extraction_tuning: None,
+                blocking_permits: Arc::new(Semaphore::new(default_blocking_tasks())),
+                prover: None,
             })
         } else {
             return Err("Invalid model archive path".into());
         }
     }
 
+    /// Sets the default deadline for a single inference request. Requests can override it with a `timeout_ms` field.
+    ///
+    /// # Arguments
+    /// * `timeout` - The deadline, `None` lets requests run until they complete
+    ///
+    /// # Returns
+    /// The `NeuroZKEngine` with the timeout applied
+    pub fn with_request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum size of a single inference request, larger requests are rejected before they are parsed.
+    ///
+    /// # Arguments
+    /// * `max_request_bytes` - The maximum request size in bytes
+    ///
+    /// # Returns
+    /// The `NeuroZKEngine` with the limit applied
+    pub fn with_max_request_bytes(mut self, max_request_bytes: usize) -> Self {
+        self.max_request_bytes = max_request_bytes;
+        self
+    }
+
+    /// Sets the maximum size of the files extracted from the model archive, the extraction is aborted once they
+    /// exceed it, so a zip bomb can't fill the disk the other tasks of the miner run on.
+    ///
+    /// # Arguments
+    /// * `max_extracted_bytes` - The maximum extracted size in bytes, `None` extracts archives of any size
+    ///
+    /// # Returns
+    /// The `NeuroZKEngine` with the quota applied
+    pub fn with_max_extracted_bytes(mut self, max_extracted_bytes: Option<u64>) -> Self {
+        self.max_extracted_bytes = max_extracted_bytes;
+        self
+    }
+
+    /// Sets how the model archive is extracted. Multi-GB archives take minutes to extract on edge devices, so by
+    /// default the disk and the CPU are benchmarked on a sample of the archive first and the buffer sizes, and whether
+    /// writing the files overlaps decompressing the archive, are chosen for them. Where the time went is recorded in
+    /// the task directory, see `ExtractionReport::load`.
+    ///
+    /// # Arguments
+    /// * `tuning` - The tuning to extract with, `None` tunes the extraction to the host
+    ///
+    /// # Returns
+    /// The `NeuroZKEngine` with the tuning applied
+    pub fn with_extraction_tuning(mut self, tuning: Option<ExtractionTuning>) -> Self {
+        self.extraction_tuning = tuning;
+        self
+    }
+
+    /// Sets how many CPU heavy EZKL operations (eg. witness generation) may run at once. They run on the blocking
+    /// thread pool, so the async runtime driving the request stream never stalls, this bounds how many cores they take.
+    ///
+    /// # Arguments
+    /// * `max_blocking_tasks` - The maximum number of concurrent operations, defaults to the number of available cores
+    ///
+    /// # Returns
+    /// The `NeuroZKEngine` with the limit applied
+    pub fn with_max_blocking_tasks(mut self, max_blocking_tasks: usize) -> Self {
+        self.blocking_permits = Arc::new(Semaphore::new(max_blocking_tasks.max(1)));
+        self
+    }
+
+    /// Runs EZKL in child processes instead of in this process: the witnesses of requests, proofs and verifications.
+    /// A crashing EZKL only fails its job, a job can be killed by dropping it and can be given a memory budget, large
+    /// circuits take tens of GB for a witness.
+    ///
+    /// # Arguments
+    /// * `prover` - The child processes to use, `None` runs EZKL in this process
+    ///
+    /// # Returns
+    /// The `NeuroZKEngine` with the prover process applied
+    pub fn with_prover_process(mut self, prover: Option<ProverProcess>) -> Self {
+        self.prover = prover;
+        self
+    }
+
+    /// Sets the circuit up for proving. Every step is recorded in the task directory once it completed, a miner that
+    /// restarts during the setup resumes after the last completed step instead of starting over.
     pub async fn setup(&self) -> Result<(), Box<dyn std::error::Error>> {
         self.extract_model(
             &self.model_archive_path,
@@ -64,6 +199,92 @@ impl NeuroZKEngine {
         Ok(())
     }
 
+    /// Describes the model for clients building requests: the shape of every input, taken from the canned input of the
+    /// archive, and the scales and visibilities of the circuit settings. Only available after `setup`.
+    ///
+    /// # Returns
+    /// The description as JSON, or an error if the settings or the canned input can't be read
+    pub fn describe_model(&self) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let prefix = Path::new(&self.task_dir_string);
+        let settings: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(prefix.join(SETTINGS_PATH))?)?;
+        let canned_input: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(prefix.join(PROOF_INPUT_PATH))?)?;
+
+        let inputs: Vec<serde_json::Value> = canned_input["input_data"]
+            .as_array()
+            .map(|inputs| {
+                inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(index, input)| {
+                        serde_json::json!({
+                            "index": index,
+                            "length": input.as_array().map(|values| values.len()),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(serde_json::json!({
+            "request_format": { "input_data": "one flattened array per input" },
+            "inputs": inputs,
+            "input_scales": settings["model_input_scales"],
+            "output_scales": settings["model_output_scales"],
+            "input_visibility": settings["run_args"]["input_visibility"],
+            "output_visibility": settings["run_args"]["output_visibility"],
+        }))
+    }
+
+    /// Checks the set up circuit before it serves requests, so a broken archive fails once with a diagnostic instead
+    /// of failing every request. The settings, the SRS and the proving key are checked against each other while a
+    /// witness is generated for the canned input of the archive, which loads the compiled circuit. Only available
+    /// after `setup`.
+    ///
+    /// # Returns
+    /// `Ok(())` if the circuit can serve requests, or an error listing every failed check
+    pub async fn verify_setup(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let prefix = self.task_dir_string.clone();
+        let canned_input = fs::read_to_string(Path::new(&prefix).join(PROOF_INPUT_PATH));
+
+        let (file_checks, dry_run) = futures::join!(
+            async {
+                self.run_blocking(move || Ok(check_setup_files(Path::new(&prefix))))
+                    .await
+                    .map_err(|e| e.to_string())
+            },
+            async {
+                match canned_input {
+                    Ok(input) => self
+                        .generate_inference_result(
+                            &self.task_dir_string,
+                            MODEL_PATH,
+                            SRS_PATH,
+                            WITNESS_PATH,
+                            input,
+                        )
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(format!("{} can't be read: {}", PROOF_INPUT_PATH, e)),
+                }
+            }
+        );
+
+        let mut failures = file_checks?;
+        if let Err(e) = dry_run {
+            failures.push(format!(
+                "{} doesn't produce a witness for the canned input: {}",
+                MODEL_PATH, e
+            ));
+        }
+        if !failures.is_empty() {
+            return Err(format!("The model archive is broken: {}", failures.join("; ")).into());
+        }
+
+        Ok(())
+    }
+
     /// Takes a stream of inference data and starts performing inference, proving inference on request by submitting a ZK SNARK to the blockchain.
     ///
     /// # Arguments
@@ -83,29 +304,95 @@ impl NeuroZKEngine {
         C: FnMut(String) -> CFut + Send + 'static,
         CFut: Future<Output = ()> + Send + 'static,
     {
+        // Without the shapes, requests are still size limited, but malformed inputs reach EZKL
+        let input_guard = match InputGuard::load(
+            &PathBuf::from(format!("{}/{}", self.task_dir_string, SETTINGS_PATH)),
+            &PathBuf::from(format!("{}/{}", self.task_dir_string, PROOF_INPUT_PATH)),
+        ) {
+            Ok(input_guard) => Some(input_guard),
+            Err(e) => {
+                println!(
+                    "Failed to derive the circuit input shapes, only limiting request size: {}",
+                    e
+                );
+                None
+            }
+        };
+
         while let Some(request) = request_stream.next().await {
+            if request.len() > self.max_request_bytes {
+                println!("Rejected request of {} bytes", request.len());
+                response_closure(error_response(
+                    ErrorCode::RequestTooLarge,
+                    format!(
+                        "Request of {} bytes exceeds the limit of {} bytes",
+                        request.len(),
+                        self.max_request_bytes
+                    ),
+                ))
+                .await;
+                continue;
+            }
+
             println!("Processing inference for request: {}", request);
 
+            let (request, options) = split_request_options(request);
+            let timeout = options.timeout.or(self.request_timeout);
+
+            if let Some(Err(e)) = input_guard.as_ref().map(|guard| guard.validate(&request)) {
+                println!("Rejected invalid request: {}", e);
+                response_closure(error_response(ErrorCode::BadInput, e)).await;
+                continue;
+            }
+
             let response: String;
 
-            match self
-                .generate_inference_result(
-                    &self.task_dir_string,
-                    MODEL_PATH,
-                    SRS_PATH,
-                    WITNESS_PATH,
-                    request.clone(),
-                )
-                .await
-            {
+            let inference = self.generate_inference_result(
+                &self.task_dir_string,
+                MODEL_PATH,
+                SRS_PATH,
+                WITNESS_PATH,
+                request.clone(),
+            );
+
+            // A timed out inference is dropped, so its late result never reaches the client
+            let result = match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, inference).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        println!("Inference timed out after {} ms", timeout.as_millis());
+                        response_closure(error_response(
+                            ErrorCode::Timeout,
+                            format!(
+                                "Inference did not complete within {} ms",
+                                timeout.as_millis()
+                            ),
+                        ))
+                        .await;
+                        continue;
+                    }
+                },
+                None => inference.await,
+            };
+
+            match result {
                 Ok(result) => {
                     response = result;
                 }
                 Err(e) => {
                     println!("Failed to generate inference result, likely EZKL version mismatch OR incorrect request format! Error: {}", e);
-                    response =
-                        "Failed to generate inference result, likely incorrect request format!"
-                            .to_string();
+                    // Only blame the request if it isn't even valid JSON, otherwise the failure happened inside of EZKL
+                    response = if serde_json::from_str::<serde_json::Value>(&request).is_err() {
+                        error_response(
+                            ErrorCode::BadInput,
+                            "Invalid request format, expected JSON input data",
+                        )
+                    } else {
+                        error_response(
+                            ErrorCode::InferenceFailed,
+                            format!("Failed to generate inference result: {}", e),
+                        )
+                    };
                 }
             }
 
@@ -138,55 +425,108 @@ impl NeuroZKEngine {
         proving_key_file_name: &str,
         settings_file_name: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if self.check_files_exists(
-            prefix,
-            [
-                proof_input_file_name,
-                model_file_name,
-                proving_key_file_name,
-                settings_file_name,
-            ],
-        ) {
+        let mut progress = SetupProgress::load(Path::new(prefix));
+        if model_archive_location.exists() {
+            let archive = model_archive_location.clone();
+            let digest = self
+                .run_blocking(move || {
+                    setup_progress::archive_digest(&archive).map_err(|e| e.to_string())
+                })
+                .await?;
+            progress.bind_archive(&digest)?;
+        }
+        // Files of a completed step are only extracted again if they went missing since
+        let targets: Vec<(String, SetupStep)> = [
+            (proof_input_file_name, SetupStep::Settings),
+            (settings_file_name, SetupStep::Settings),
+            (model_file_name, SetupStep::Compiled),
+            (proving_key_file_name, SetupStep::ProvingKey),
+        ]
+        .into_iter()
+        .filter(|(file_name, step)| {
+            !progress.is_completed(*step) || !Path::new(prefix).join(file_name).exists()
+        })
+        .map(|(file_name, step)| (file_name.to_string(), step))
+        .collect();
+        if targets.is_empty() {
             return Ok(());
-        };
+        }
 
         println!("Opening archive at: {:?}", model_archive_location);
         if !model_archive_location.exists() {
             return Err("Model archive path does not exist".into());
         }
-        let archive_file = File::open(model_archive_location)?;
-        let decoder = Decoder::new(BufReader::new(archive_file))?;
-        let mut archive = Archive::new(decoder);
-
-        let targets = [
-            proof_input_file_name,
-            model_file_name,
-            proving_key_file_name,
-            settings_file_name,
-        ];
-
-        for entry_result in archive.entries()? {
-            println!("Extracting entry...");
-            let mut entry = entry_result?;
-            println!("Entry name...");
-            let path = entry.path()?;
-            println!("Entry path: {:?}...", path);
-            if let Some(file_name) = path.file_name().and_then(|f| f.to_str()) {
-                println!("File name: {:?}...", file_name);
-                if targets.contains(&file_name) {
-                    println!("Found target file: {:?}...", file_name);
-                    let output_path = Path::new(prefix).join(file_name);
-                    println!("Extracting to: {:?}", output_path);
-                    let mut out_file = File::create(output_path)?;
-                    copy(&mut entry, &mut out_file)?;
+        let model_archive_location = model_archive_location.clone();
+        let prefix = prefix.to_string();
+        let max_extracted_bytes = self.max_extracted_bytes;
+        let extraction_tuning = self.extraction_tuning;
+
+        // Decompressing the archive takes a while for large models
+        self.run_blocking(move || {
+            let (tuning, benchmark) = match extraction_tuning {
+                Some(tuning) => (tuning, None),
+                None => {
+                    let benchmark =
+                        ExtractionBenchmark::measure(&model_archive_location, Path::new(&prefix))
+                            .map_err(|e| format!("Failed to benchmark the extraction: {}", e))?;
+                    (
+                        ExtractionTuning::choose(&benchmark, default_blocking_tasks()),
+                        Some(benchmark),
+                    )
                 }
-            }
-        }
+            };
+            println!("Extracting with {:?}, tuned for {:?}", tuning, benchmark);
+
+            let report = extract_targets(
+                &model_archive_location,
+                &prefix,
+                &targets,
+                max_extracted_bytes,
+                progress,
+                tuning,
+                benchmark,
+            )
+            .map_err(|e| e.to_string())?;
+            println!(
+                "Extracted {} bytes in {} ms: {} ms decompressing, {} ms writing",
+                report.extracted_bytes,
+                report.elapsed.as_millis(),
+                report.decode.as_millis(),
+                report.write.as_millis()
+            );
+            report.save(Path::new(&prefix)).map_err(|e| e.to_string())
+        })
+        .await
+    }
 
-        Ok(())
+    /// Runs CPU heavy or blocking work on the blocking thread pool, bounded by the blocking permits of the engine.
+    /// The permit is held until the work completes, even if the caller stopped waiting for it.
+    ///
+    /// # Arguments
+    /// * `&self`
+    /// * `work` - The blocking closure to run
+    ///
+    /// # Returns
+    /// The result of the closure, or an error if the blocking thread panicked
+    async fn run_blocking<T, F>(&self, work: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, String> + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = Arc::clone(&self.blocking_permits).acquire_owned().await?;
+
+        let result = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            work()
+        })
+        .await?;
+
+        Ok(result?)
     }
 
-    /// Downloads the SRS and saves it to the fs
+    /// Downloads the SRS and saves it to the fs. An SRS that is already there, eg. kept from an earlier task or
+    /// restored from a snapshot, is reused if it supports the rows of the circuit. It is fetched to a partial file
+    /// that is only moved into place once complete, so an SRS at `srs_path` is never truncated.
     ///
     /// # Arguments
     /// * `&self`
@@ -204,41 +544,30 @@ impl NeuroZKEngine {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let srs_path = PathBuf::from(format!("{}/{}", prefix, srs_path));
         let settings_path = PathBuf::from(format!("{}/{}", prefix, settings_path));
+        let mut progress = SetupProgress::load(Path::new(prefix));
 
-        if !std::fs::metadata(&srs_path).is_ok() {
-            run(GetSrs {
-                settings_path: Some(settings_path),
-                srs_path: Some(srs_path),
-                commitment: Some(Commitments::KZG),
-                logrows: None,
-            })
-            .await?;
-
-            Ok(())
-        } else {
-            Ok(())
+        if progress.is_completed(SetupStep::Srs) && srs_path.exists() {
+            return Ok(());
         }
-    }
-
-    /// Checks if all of the necessary files exist in the given directory.
-    ///
-    /// # Arguments
-    /// * `&self`
-    /// * `prefix` - The directory for operations on NZK related files
-    /// * `nzk_files` - An array of file names
-    ///
-    /// # Returns
-    /// A `bool` indicating wether all of the files exist
-    fn check_files_exists(&self, prefix: &str, nzk_files: [&str; 4]) -> bool {
-        let res = true;
-
-        for file_path in nzk_files {
-            if !std::fs::metadata(format!("{}/{}", prefix, file_path)).is_ok() {
-                return false;
-            }
+        if srs_supports_circuit(&srs_path, &settings_path) {
+            println!("Reusing the SRS at {:?}", srs_path);
+            progress.complete(SetupStep::Srs)?;
+            return Ok(());
         }
 
-        res
+        let partial_path = srs_path.with_extension("srs.partial");
+        let _ = fs::remove_file(&partial_path);
+        run(GetSrs {
+            settings_path: Some(settings_path),
+            srs_path: Some(partial_path.clone()),
+            commitment: Some(Commitments::KZG),
+            logrows: None,
+        })
+        .await?;
+        fs::rename(&partial_path, &srs_path)?;
+        progress.complete(SetupStep::Srs)?;
+
+        Ok(())
     }
 
     /// Takes input and proves inference on the model currently loaded into the miner. Fails if `init_model` has not been called. Should be called intermittently to request a proof of correct model execution.
@@ -262,6 +591,46 @@ impl NeuroZKEngine {
         proof_witness_path: &str,
         proof_input_path: &str,
     ) -> Result<String, Box<dyn std::error::Error>> {
+        self.prove_inference_with_progress(
+            prefix,
+            model_path,
+            proving_key_path,
+            srs_path,
+            proof_witness_path,
+            proof_input_path,
+            |_| {},
+        )
+        .await
+    }
+
+    /// Same as `prove_inference`, but reports every stage transition to `on_progress`, since proving can take minutes.
+    ///
+    /// # Arguments
+    /// * `on_progress` - A closure that is called with the current `ProofProgress` whenever a new stage starts
+    ///
+    /// # Returns
+    /// `Result<String, Box<dyn std::error::Error>>`
+    pub async fn prove_inference_with_progress<P>(
+        &self,
+        prefix: &str,
+        model_path: &str,
+        proving_key_path: &str,
+        srs_path: &str,
+        proof_witness_path: &str,
+        proof_input_path: &str,
+        on_progress: P,
+    ) -> Result<String, Box<dyn std::error::Error>>
+    where
+        P: Fn(ProofProgress),
+    {
+        let started = Instant::now();
+        let report = |stage: ProofStage| {
+            on_progress(ProofProgress {
+                stage,
+                elapsed: started.elapsed(),
+            })
+        };
+
         let model_path = PathBuf::from(format!("{}/{}", prefix, model_path));
         let proving_key_path = PathBuf::from(format!("{}/{}", prefix, proving_key_path));
         let srs_path = PathBuf::from(format!("{}/{}", prefix, srs_path));
@@ -270,6 +639,34 @@ impl NeuroZKEngine {
 
         let input_string = fs::read_to_string(proof_input_path)?;
 
+        if let Some(prover) = &self.prover {
+            report(ProofStage::Witness);
+            prover
+                .run_job(serde_json::json!({
+                    "kind": "gen_witness",
+                    "data": input_string,
+                    "compiled_circuit": model_path,
+                    "output": proof_witness_path,
+                    "srs_path": srs_path,
+                }))
+                .await?;
+
+            report(ProofStage::Prove);
+            let proof = prover
+                .run_job(serde_json::json!({
+                    "kind": "prove",
+                    "witness": proof_witness_path,
+                    "compiled_circuit": model_path,
+                    "pk_path": proving_key_path,
+                    "srs_path": srs_path,
+                }))
+                .await?;
+
+            report(ProofStage::Done);
+            return Ok(proof);
+        }
+
+        report(ProofStage::Witness);
         let _ = run(GenWitness {
             data: Some(ezkl::commands::DataField(input_string)),
             compiled_circuit: Some(model_path.clone()),
@@ -279,6 +676,7 @@ impl NeuroZKEngine {
         })
         .await?;
 
+        report(ProofStage::Prove);
         let proof = run(Prove {
             witness: Some(proof_witness_path),
             compiled_circuit: Some(model_path),
@@ -290,9 +688,56 @@ impl NeuroZKEngine {
         })
         .await?;
 
+        report(ProofStage::Done);
+
         Ok(proof)
     }
 
+    /// Verifies a proof against a verifying key, eg. to find out why the chain rejected a submitted proof.
+    ///
+    /// # Arguments
+    /// * `&self`
+    /// * `prefix` - The directory for operations on NZK related files
+    /// * `proof_path` - The path to the proof as produced by `prove_inference`
+    /// * `settings_path` - The path to the circuit settings the proof is checked with
+    /// * `vk_path` - The path to the verifying key
+    /// * `srs_path` - The path to the SRS
+    ///
+    /// # Returns
+    /// `Result<bool, Box<dyn std::error::Error>>`, whether the proof is valid
+    pub async fn verify_proof(
+        &self,
+        prefix: &str,
+        proof_path: &str,
+        settings_path: &str,
+        vk_path: &str,
+        srs_path: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if let Some(prover) = &self.prover {
+            let verified = prover
+                .run_job(serde_json::json!({
+                    "kind": "verify",
+                    "settings_path": format!("{}/{}", prefix, settings_path),
+                    "proof_path": format!("{}/{}", prefix, proof_path),
+                    "vk_path": format!("{}/{}", prefix, vk_path),
+                    "srs_path": format!("{}/{}", prefix, srs_path),
+                }))
+                .await?;
+            return Ok(verified.trim() == "true");
+        }
+
+        let verified = run(Verify {
+            settings_path: Some(PathBuf::from(format!("{}/{}", prefix, settings_path))),
+            proof_path: Some(PathBuf::from(format!("{}/{}", prefix, proof_path))),
+            vk_path: Some(PathBuf::from(format!("{}/{}", prefix, vk_path))),
+            srs_path: Some(PathBuf::from(format!("{}/{}", prefix, srs_path))),
+            reduced_srs: None,
+        })
+        .await?;
+
+        Ok(verified.trim() == "true")
+    }
+
     /// Takes input and performs inference on the model currently loaded into the miner. Fails if `init_model` has not been called. Should be called for the vast majority of inference requests.
     ///
     /// # Arguments
@@ -319,15 +764,233 @@ impl NeuroZKEngine {
 
         println!("Generating inference result for: {}", input_data);
 
-        let witness = run(GenWitness {
-            data: Some(ezkl::commands::DataField(input_data)),
-            compiled_circuit: Some(model_path),
-            output: None,
-            vk_path: None,
-            srs_path: None,
+        if let Some(prover) = &self.prover {
+            // Still bounded like the blocking thread pool, every child takes a core
+            let _permit = Arc::clone(&self.blocking_permits).acquire_owned().await?;
+            return Ok(prover
+                .run_job(serde_json::json!({
+                    "kind": "gen_witness",
+                    "data": input_data,
+                    "compiled_circuit": model_path,
+                }))
+                .await?);
+        }
+
+        // Witness generation is CPU bound, it runs on its own thread instead of stalling the request stream
+        let runtime = tokio::runtime::Handle::current();
+        self.run_blocking(move || {
+            runtime
+                .block_on(run(GenWitness {
+                    data: Some(ezkl::commands::DataField(input_data)),
+                    compiled_circuit: Some(model_path),
+                    output: None,
+                    vk_path: None,
+                    srs_path: None,
+                }))
+                .map_err(|e| e.to_string())
         })
-        .await?;
+        .await
+    }
+}
 
-        Ok(witness)
+/// Extracts the target files of a zstd compressed tar archive into `prefix`
+///
+/// # Arguments
+/// * `model_archive_location` - The path to the model archive
+/// * `prefix` - The directory to extract to
+/// * `targets` - The names of the files to extract
+/// * `max_extracted_bytes` - The quota of the extracted files, counted as decompressed rather than as declared
+/// * `progress` - The setup progress, a step is recorded once all of its files are extracted
+/// * `tuning` - The buffer sizes and whether the files are written while the archive is decompressed further
+/// * `benchmark` - The throughputs `tuning` was chosen for, recorded in the report
+///
+/// # Returns
+/// The `ExtractionReport` of where the time went, or an error if the extracted files exceed the quota, they are
+/// removed then
+fn extract_targets(
+    model_archive_location: &Path,
+    prefix: &str,
+    targets: &[(String, SetupStep)],
+    max_extracted_bytes: Option<u64>,
+    mut progress: SetupProgress,
+    tuning: ExtractionTuning,
+    benchmark: Option<ExtractionBenchmark>,
+) -> Result<ExtractionReport, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    let archive_file = File::open(model_archive_location)?;
+    let archive_bytes = archive_file.metadata()?.len();
+    let decoder = Decoder::new(BufReader::with_capacity(tuning.read_buffer, archive_file))?;
+    let mut archive = Archive::new(decoder);
+    let mut extracted: u64 = 0;
+    let mut written = Vec::new();
+    let mut remaining: Vec<&(String, SetupStep)> = targets.iter().collect();
+    let mut writer = FileWriter::new(&tuning)?;
+    let mut chunk = vec![0u8; tuning.write_buffer];
+    // Skipping the entries that aren't extracted decompresses them as well
+    let mut decode = Duration::ZERO;
+
+    let mut entries = archive.entries()?;
+    loop {
+        let decode_started = Instant::now();
+        let Some(entry_result) = entries.next() else {
+            break;
+        };
+        decode += decode_started.elapsed();
+        println!("Extracting entry...");
+        let mut entry = entry_result?;
+        println!("Entry name...");
+        let path = entry.path()?;
+        println!("Entry path: {:?}...", path);
+        if let Some(file_name) = path.file_name().and_then(|f| f.to_str()) {
+            println!("File name: {:?}...", file_name);
+            if let Some(position) = remaining.iter().position(|(target, _)| target == file_name) {
+                println!("Found target file: {:?}...", file_name);
+                let output_path = Path::new(prefix).join(file_name);
+                // Written aside until complete, so an interrupted extraction never leaves a truncated file behind
+                let partial_path = Path::new(prefix).join(format!("{}.partial", file_name));
+                println!("Extracting to: {:?}", output_path);
+                writer.create(&partial_path)?;
+                written.push(partial_path.clone());
+                written.push(output_path.clone());
+                // One byte more than the remaining quota is enough to tell that the file doesn't fit
+                let limit = max_extracted_bytes
+                    .map_or(u64::MAX, |quota| quota.saturating_sub(extracted) + 1);
+                let mut entry = (&mut entry).take(limit);
+                loop {
+                    let decode_started = Instant::now();
+                    let read = entry.read(&mut chunk)?;
+                    decode += decode_started.elapsed();
+                    if read == 0 {
+                        break;
+                    }
+                    extracted += read as u64;
+                    writer.write(&chunk[..read])?;
+                }
+
+                if let Some(quota) = max_extracted_bytes.filter(|quota| extracted > *quota) {
+                    writer.close();
+                    for path in &written {
+                        let _ = std::fs::remove_file(path);
+                    }
+                    return Err(
+                        format!("Archive exceeds the extraction quota of {} bytes", quota).into(),
+                    );
+                }
+
+                writer.finish(&partial_path, &output_path)?;
+                let (_, step) = remaining.remove(position);
+                if !remaining.iter().any(|(_, other)| other == step) {
+                    progress.complete(*step)?;
+                }
+            }
+        }
+    }
+
+    Ok(ExtractionReport {
+        benchmark,
+        tuning,
+        archive_bytes,
+        extracted_bytes: extracted,
+        decode,
+        write: writer.close(),
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Checks the files of a set up circuit against each other: the settings parse and name the rows of the circuit, the
+/// SRS supports at least as many rows and the proving key was generated for a circuit of that size
+///
+/// # Arguments
+/// * `prefix` - The directory the files were set up in
+///
+/// # Returns
+/// A description of every failed check, empty if the files fit together
+fn check_setup_files(prefix: &Path) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    let settings = fs::read_to_string(prefix.join(SETTINGS_PATH))
+        .map_err(|e| e.to_string())
+        .and_then(|settings| {
+            serde_json::from_str::<serde_json::Value>(&settings).map_err(|e| e.to_string())
+        });
+    let logrows = match settings {
+        Ok(settings) => {
+            let logrows = settings["run_args"]["logrows"].as_u64().map(|logrows| logrows as u32);
+            if logrows.is_none() {
+                failures.push(format!("{} has no run_args.logrows", SETTINGS_PATH));
+            }
+            logrows
+        }
+        Err(e) => {
+            failures.push(format!("{} doesn't parse: {}", SETTINGS_PATH, e));
+            None
+        }
+    };
+
+    // The SRS starts with the log2 of the rows it supports, as a little endian u32
+    match read_header::<4>(&prefix.join(SRS_PATH)) {
+        Ok(header) => {
+            let srs_logrows = u32::from_le_bytes(header);
+            if let Some(logrows) = logrows.filter(|logrows| srs_logrows < *logrows) {
+                failures.push(format!(
+                    "{} supports 2^{} rows, the circuit needs 2^{}",
+                    SRS_PATH, srs_logrows, logrows
+                ));
+            }
+        }
+        Err(e) => failures.push(format!("{} can't be read: {}", SRS_PATH, e)),
     }
+
+    // The proving key starts with its verifying key, which carries the log2 of the rows of its circuit: after a
+    // version byte in current halo2 releases, as a big endian u32 in older ones
+    match read_header::<4>(&prefix.join(PROVING_KEY_PATH)) {
+        Ok(header) => {
+            let pk_logrows = match header {
+                [0, ..] => Some(u32::from_be_bytes(header)),
+                [0x02 | 0x03, k, ..] => Some(k as u32),
+                _ => None,
+            };
+            match (pk_logrows, logrows) {
+                (Some(pk_logrows), Some(logrows)) if pk_logrows != logrows => {
+                    failures.push(format!(
+                        "{} was generated for a circuit of 2^{} rows, the circuit has 2^{}",
+                        PROVING_KEY_PATH, pk_logrows, logrows
+                    ))
+                }
+                (None, _) => println!(
+                    "Unknown format of {}, its size is left to the first proof",
+                    PROVING_KEY_PATH
+                ),
+                _ => {}
+            }
+        }
+        Err(e) => failures.push(format!("{} can't be read: {}", PROVING_KEY_PATH, e)),
+    }
+
+    failures
+}
+
+/// Reads the first `N` bytes of a file
+/// Whether the SRS at `srs_path` supports at least the rows the settings name, `false` if either can't be read
+fn srs_supports_circuit(srs_path: &Path, settings_path: &Path) -> bool {
+    let logrows = fs::read_to_string(settings_path)
+        .ok()
+        .and_then(|settings| serde_json::from_str::<serde_json::Value>(&settings).ok())
+        .and_then(|settings| settings["run_args"]["logrows"].as_u64());
+    // The SRS starts with the log2 of the rows it supports, as a little endian u32
+    let srs_logrows = read_header::<4>(srs_path).map(u32::from_le_bytes);
+
+    matches!((logrows, srs_logrows), (Some(logrows), Ok(srs_logrows)) if u64::from(srs_logrows) >= logrows)
+}
+
+fn read_header<const N: usize>(path: &Path) -> std::io::Result<[u8; N]> {
+    let mut header = [0u8; N];
+    File::open(path)?.read_exact(&mut header)?;
+    Ok(header)
+}
+
+fn default_blocking_tasks() -> usize {
+    std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1)
 }
@@ -0,0 +1,410 @@
+use ezkl::{
+    commands::Commands::{GenWitness, Prove, Verify},
+    execute::run,
+};
+use serde_json::{json, Value};
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+/// Prefixes the line a job process reports its result on, EZKL may log to stdout as well
+const RESULT_MARKER: &str = "PROVER_RESULT ";
+
+/// Numbers the cgroups and logs of the jobs of this process
+static JOB_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Logs of every kind of job kept in the log directory, the oldest ones are removed
+const MAX_JOB_LOGS: usize = 20;
+
+/// Runs EZKL operations in a child process per job, so that a panic or memory corruption inside of EZKL only fails the
+/// job, a job can be killed at any point by dropping it, and a request that needs more memory than its budget fails
+/// on its own instead of getting the whole miner OOM-killed. The child is the binary embedding the engine, started
+/// with `args`, which must call `serve_job` for it.
+#[derive(Debug, Clone)]
+pub struct ProverProcess {
+    program: PathBuf,
+    args: Vec<String>,
+    memory_limit: Option<u64>,
+    cgroup_parent: Option<PathBuf>,
+    log_dir: Option<PathBuf>,
+}
+
+impl ProverProcess {
+    /// Creates a new `ProverProcess`.
+    ///
+    /// # Arguments
+    /// * `program` - The binary serving the jobs, usually the current executable
+    /// * `args` - The arguments that make `program` call `serve_job`
+    ///
+    /// # Returns
+    /// A new `ProverProcess` without a memory budget
+    pub fn new(program: PathBuf, args: Vec<String>) -> Self {
+        Self {
+            program,
+            args,
+            memory_limit: None,
+            cgroup_parent: None,
+            log_dir: None,
+        }
+    }
+
+    /// Sets the memory budget of a single job. It is enforced with `memory.max` of a cgroup created per job if a
+    /// cgroup parent is set, and by limiting the address space of the job otherwise.
+    ///
+    /// # Arguments
+    /// * `memory_limit` - The budget in bytes, `None` for no budget
+    ///
+    /// # Returns
+    /// The `ProverProcess` with the budget applied
+    pub fn with_memory_limit(mut self, memory_limit: Option<u64>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// Sets the cgroup v2 directory the cgroups of the jobs are created in. It must be delegated to the user running
+    /// the miner and have the memory controller enabled for its children.
+    ///
+    /// # Arguments
+    /// * `cgroup_parent` - The directory, eg. `/sys/fs/cgroup/cyborg-miner.slice/provers`
+    ///
+    /// # Returns
+    /// The `ProverProcess` with the cgroup parent applied
+    pub fn with_cgroup_parent(mut self, cgroup_parent: Option<PathBuf>) -> Self {
+        self.cgroup_parent = cgroup_parent;
+        self
+    }
+
+    /// Sets the directory the output of EZKL is written to, one log file per job, instead of being interleaved with
+    /// the output of the miner. Errors of a job name its log file, the last `MAX_JOB_LOGS` logs of every kind of job
+    /// are kept.
+    ///
+    /// # Arguments
+    /// * `log_dir` - The directory, `None` passes the output through
+    ///
+    /// # Returns
+    /// The `ProverProcess` with the log directory applied
+    pub fn with_log_dir(mut self, log_dir: Option<PathBuf>) -> Self {
+        self.log_dir = log_dir;
+        self
+    }
+
+    /// Runs a job in a new child process, which is killed if the returned future is dropped, eg. on a timeout.
+    ///
+    /// # Arguments
+    /// * `job` - The job, as read by `serve_job`
+    ///
+    /// # Returns
+    /// The output of the job, or why it failed
+    pub(crate) async fn run_job(&self, job: Value) -> Result<String, String> {
+        let cgroup = match (&self.cgroup_parent, self.memory_limit) {
+            (Some(parent), Some(limit)) => Some(JobCgroup::create(parent, limit)?),
+            _ => None,
+        };
+
+        let mut log = match &self.log_dir {
+            Some(log_dir) => Some(JobLog::create(
+                log_dir,
+                job["kind"].as_str().unwrap_or("job"),
+            )?),
+            None => None,
+        };
+        let stderr = match &log {
+            Some(log) => Stdio::from(
+                log.file
+                    .try_clone()
+                    .map_err(|e| format!("Failed to open the log of the job: {}", e))?,
+            ),
+            None => Stdio::inherit(),
+        };
+
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .kill_on_drop(true);
+        let cgroup_procs = match &cgroup {
+            Some(cgroup) => {
+                let procs = cgroup.path.join("cgroup.procs");
+                Some(CString::new(procs.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?)
+            }
+            None => None,
+        };
+        let address_space_limit = self.memory_limit.filter(|_| cgroup.is_none());
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(procs) = &cgroup_procs {
+                    // Writing 0 moves the writing process, so the job never allocates outside of its cgroup
+                    let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+                    if fd < 0 || libc::write(fd, b"0".as_ptr().cast(), 1) != 1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    libc::close(fd);
+                }
+                if let Some(limit) = address_space_limit {
+                    let limit = libc::rlimit {
+                        rlim_cur: limit as libc::rlim_t,
+                        rlim_max: limit as libc::rlim_t,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to start the prover process: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(job.to_string().as_bytes())
+                .await
+                .map_err(|e| format!("Failed to send the job to the prover process: {}", e))?;
+        }
+
+        let mut result = None;
+        if let Some(stdout) = child.stdout.take() {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                match (line.strip_prefix(RESULT_MARKER), log.as_mut()) {
+                    (Some(reported), _) => result = serde_json::from_str::<Value>(reported).ok(),
+                    (None, Some(log)) => {
+                        let _ = writeln!(log.file, "{}", line);
+                    }
+                    (None, None) => println!("{}", line),
+                }
+            }
+        }
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| format!("Failed to wait for the prover process: {}", e))?;
+        let out_of_memory = cgroup.as_ref().is_some_and(JobCgroup::was_oom_killed);
+
+        let result = match result {
+            Some(result) => match (result["ok"].as_str(), result["error"].as_str()) {
+                (Some(output), _) => Ok(output.to_string()),
+                (None, Some(error)) => Err(error.to_string()),
+                _ => Err("The prover process reported an invalid result".to_string()),
+            },
+            None if out_of_memory || self.killed_for_memory(status) => Err(format!(
+                "The request exceeded the memory limit of {} bytes",
+                self.memory_limit.unwrap_or_default()
+            )),
+            None => Err(format!("The prover process failed: {}", status)),
+        };
+        match (result, log) {
+            (Err(error), Some(log)) => Err(format!("{} (log: {})", error, log.path.display())),
+            (result, _) => result,
+        }
+    }
+
+    /// Whether the job died the way jobs over their address space limit do, Rust aborts on failed allocations
+    fn killed_for_memory(&self, status: ExitStatus) -> bool {
+        self.memory_limit.is_some()
+            && matches!(status.signal(), Some(libc::SIGKILL) | Some(libc::SIGABRT))
+    }
+}
+
+/// The log file of a single job, named after the kind of the job and when it started
+struct JobLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl JobLog {
+    fn create(log_dir: &Path, kind: &str) -> Result<Self, String> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        let path = log_dir.join(format!(
+            "{}-{}-{}.log",
+            kind,
+            started_at,
+            JOB_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = fs::create_dir_all(log_dir)
+            .and_then(|_| File::create(&path))
+            .map_err(|e| format!("Failed to create the log {}: {}", path.display(), e))?;
+
+        // Per kind, the many witnesses of requests would otherwise crowd out the log of the last proof
+        let mut logs = job_logs(log_dir, Some(kind));
+        if logs.len() > MAX_JOB_LOGS {
+            for (old, _) in logs.drain(..logs.len() - MAX_JOB_LOGS) {
+                let _ = fs::remove_file(old);
+            }
+        }
+
+        Ok(Self { path, file })
+    }
+}
+
+/// The logs of the jobs in a log directory, oldest first
+///
+/// # Arguments
+/// * `log_dir` - The log directory of the jobs
+/// * `kind` - Only logs of this kind of job, eg. `prove`
+fn job_logs(log_dir: &Path, kind: Option<&str>) -> Vec<(PathBuf, SystemTime)> {
+    let mut logs: Vec<(PathBuf, SystemTime)> = fs::read_dir(log_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "log"))
+                .filter(|path| {
+                    kind.is_none_or(|kind| {
+                        path.file_name()
+                            .and_then(|name| name.to_str())
+                            .is_some_and(|name| name.starts_with(&format!("{}-", kind)))
+                    })
+                })
+                .filter_map(|path| {
+                    let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+                    Some((path, modified))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    logs.sort_by_key(|(_, modified)| *modified);
+    logs
+}
+
+/// The log of the last job of a kind in a log directory, eg. of the last proof
+///
+/// # Arguments
+/// * `log_dir` - The log directory of the jobs
+/// * `kind` - The kind of job, eg. `prove`, or `None` for the last job of any kind
+///
+/// # Returns
+/// The path of the log, `None` if no job of the kind was logged
+pub fn last_job_log(log_dir: &Path, kind: Option<&str>) -> Option<PathBuf> {
+    job_logs(log_dir, kind).pop().map(|(path, _)| path)
+}
+
+/// The cgroup of a single job, removed once it is dropped
+struct JobCgroup {
+    path: PathBuf,
+}
+
+impl JobCgroup {
+    fn create(parent: &std::path::Path, memory_limit: u64) -> Result<Self, String> {
+        let path = parent.join(format!(
+            "prover-{}-{}",
+            std::process::id(),
+            JOB_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let setup = || -> std::io::Result<()> {
+            fs::create_dir(&path)?;
+            fs::write(path.join("memory.max"), memory_limit.to_string())?;
+            // Swapping would only postpone the limit, slowly
+            let _ = fs::write(path.join("memory.swap.max"), "0");
+            Ok(())
+        };
+        if let Err(e) = setup() {
+            let _ = fs::remove_dir(&path);
+            return Err(format!(
+                "Failed to create the cgroup {}: {}",
+                path.display(),
+                e
+            ));
+        }
+
+        Ok(Self { path })
+    }
+
+    fn was_oom_killed(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| {
+                events
+                    .lines()
+                    .find_map(|line| line.strip_prefix("oom_kill "))
+                    .and_then(|count| count.trim().parse::<u64>().ok())
+            })
+            .is_some_and(|count| count > 0)
+    }
+}
+
+impl Drop for JobCgroup {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir(&self.path);
+    }
+}
+
+/// Serves a job in the child process started by `ProverProcess`: reads it from stdin, runs it and reports the result
+/// on stdout. The embedding binary calls it when started with the arguments of its `ProverProcess`.
+///
+/// # Returns
+/// The exit code of the process
+pub async fn serve_job() -> i32 {
+    let mut job = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut job) {
+        return report(Err(format!("Failed to read the job: {}", e)));
+    }
+    let job: Value = match serde_json::from_str(&job) {
+        Ok(job) => job,
+        Err(e) => return report(Err(format!("Invalid job: {}", e))),
+    };
+
+    report(run_job(job).await)
+}
+
+async fn run_job(job: Value) -> Result<String, String> {
+    let path = |field: &str| job[field].as_str().map(PathBuf::from);
+
+    match job["kind"].as_str() {
+        Some("gen_witness") => run(GenWitness {
+            data: Some(ezkl::commands::DataField(
+                job["data"].as_str().unwrap_or_default().to_string(),
+            )),
+            compiled_circuit: path("compiled_circuit"),
+            output: path("output"),
+            vk_path: None,
+            srs_path: path("srs_path"),
+        })
+        .await
+        .map_err(|e| e.to_string()),
+        Some("prove") => run(Prove {
+            witness: path("witness"),
+            compiled_circuit: path("compiled_circuit"),
+            pk_path: path("pk_path"),
+            proof_path: None,
+            srs_path: path("srs_path"),
+            proof_type: ezkl::pfsys::ProofType::Single,
+            check_mode: None,
+        })
+        .await
+        .map_err(|e| e.to_string()),
+        Some("verify") => run(Verify {
+            settings_path: path("settings_path"),
+            proof_path: path("proof_path"),
+            vk_path: path("vk_path"),
+            srs_path: path("srs_path"),
+            reduced_srs: None,
+        })
+        .await
+        .map_err(|e| e.to_string()),
+        other => Err(format!("Unknown job {:?}", other)),
+    }
+}
+
+fn report(result: Result<String, String>) -> i32 {
+    let (report, code) = match result {
+        Ok(output) => (json!({ "ok": output }), 0),
+        Err(error) => (json!({ "error": error }), 1),
+    };
+    println!("{}{}", RESULT_MARKER, report);
+    code
+}
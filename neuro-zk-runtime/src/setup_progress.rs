@@ -0,0 +1,123 @@
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File in the task directory recording the completed steps of the setup
+const SETUP_PROGRESS_PATH: &str = "setup-progress.json";
+
+/// The steps of setting up a circuit. A step only counts as completed once it is recorded, the files of an
+/// interrupted step may exist but be truncated, so they are produced again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupStep {
+    /// The circuit settings and the canned input are extracted
+    Settings,
+    /// The compiled circuit is extracted
+    Compiled,
+    /// The SRS is fetched
+    Srs,
+    /// The proving key is extracted
+    ProvingKey,
+}
+
+impl SetupStep {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SetupStep::Settings => "settings",
+            SetupStep::Compiled => "compiled",
+            SetupStep::Srs => "srs",
+            SetupStep::ProvingKey => "pk",
+        }
+    }
+}
+
+/// The completed setup steps of a task, persisted so that a restarted miner resumes after the last completed one. The
+/// steps are bound to the archive they were set up from, the files of another model are never taken as set up.
+pub struct SetupProgress {
+    path: PathBuf,
+    /// The hex encoded SHA-256 of the archive the steps were completed for
+    archive: Option<String>,
+    completed: Vec<String>,
+}
+
+impl SetupProgress {
+    /// Loads the progress recorded in the task directory, a missing or unreadable record starts over
+    pub fn load(task_dir: &Path) -> Self {
+        let path = task_dir.join(SETUP_PROGRESS_PATH);
+        let progress = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok());
+        let archive = progress
+            .as_ref()
+            .and_then(|progress| progress["archive"].as_str().map(str::to_string));
+        let completed = progress
+            .as_ref()
+            .and_then(|progress| {
+                progress["completed"].as_array().map(|steps| {
+                    steps
+                        .iter()
+                        .filter_map(|step| step.as_str().map(str::to_string))
+                        .collect()
+                })
+            })
+            .unwrap_or_default();
+
+        Self {
+            path,
+            archive,
+            completed,
+        }
+    }
+
+    /// Binds the progress to the archive being set up, the steps recorded for a different archive start over
+    ///
+    /// # Arguments
+    /// * `digest` - The hex encoded SHA-256 of the archive, see `archive_digest`
+    pub fn bind_archive(&mut self, digest: &str) -> io::Result<()> {
+        if self.archive.as_deref() == Some(digest) {
+            return Ok(());
+        }
+        if !self.completed.is_empty() {
+            println!("The setup steps were completed for another archive, setting up again");
+        }
+        self.archive = Some(digest.to_string());
+        self.completed.clear();
+        self.save()
+    }
+
+    pub fn is_completed(&self, step: SetupStep) -> bool {
+        self.completed
+            .iter()
+            .any(|completed| completed == step.as_str())
+    }
+
+    /// Records a completed step
+    pub fn complete(&mut self, step: SetupStep) -> io::Result<()> {
+        if self.is_completed(step) {
+            return Ok(());
+        }
+        self.completed.push(step.as_str().to_string());
+        self.save()
+    }
+
+    /// Written to a temporary file first so a crash never leaves a corrupt record
+    fn save(&self) -> io::Result<()> {
+        let temporary_path = self.path.with_extension("json.tmp");
+        fs::write(
+            &temporary_path,
+            serde_json::json!({ "archive": self.archive, "completed": self.completed }).to_string(),
+        )?;
+        fs::rename(temporary_path, &self.path)
+    }
+}
+
+/// The hex encoded SHA-256 of a model archive
+pub fn archive_digest(archive_path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(archive_path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
@@ -0,0 +1,55 @@
+use base64::{engine::general_purpose, Engine as _};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// A binary output of an inference, eg. a generated image or audio clip, encoded by a post-processing stage
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// Name of the output the artifact was encoded from
+    pub output: String,
+    /// Position of the artifact in the batch of the output
+    pub index: usize,
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+impl Artifact {
+    /// Hex encoded SHA-256 of the content, clients verify what they fetch against it
+    pub fn sha256(&self) -> String {
+        hex::encode(Sha256::digest(&self.bytes))
+    }
+
+    /// A file name derived from the content, identical artifacts share it
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.sha256(), self.extension)
+    }
+
+    /// The reference to a stored artifact, `location` tells clients where to fetch it, eg. `{"url": ...}`
+    pub fn reference(&self, location: Value) -> Value {
+        let mut reference = json!({
+            "content_type": self.content_type,
+            "sha256": self.sha256(),
+            "size": self.bytes.len(),
+        });
+        if let (Some(reference), Value::Object(location)) = (reference.as_object_mut(), location) {
+            reference.extend(location);
+        }
+        reference
+    }
+
+    /// The artifact embedded into the response, for clients of models without an artifact store
+    pub fn inline(&self) -> Value {
+        self.reference(json!({ "base64": general_purpose::STANDARD.encode(&self.bytes) }))
+    }
+}
+
+/// Keeps the artifacts of inferences outside of the responses, so that responses only carry references to them
+pub trait ArtifactStore: Send + Sync {
+    /// Stores an artifact
+    ///
+    /// # Returns
+    /// The reference sent to the client in place of the artifact, or a description of why it couldn't be stored
+    fn store<'a>(&'a self, artifact: &'a Artifact) -> BoxFuture<'a, Result<Value, String>>;
+}
@@ -0,0 +1,155 @@
+use crate::client::TensorData;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Latency and throughput of a benchmark, for renters that verify advertised performance before sending real traffic
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchReport {
+    pub requests: usize,
+    pub failed: usize,
+    pub mean_latency_ms: f64,
+    pub p50_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub p99_latency_ms: f64,
+    /// Inferences completed per second of wall time, with the concurrency the miner serves connections with
+    pub throughput_per_sec: f64,
+    pub elapsed_ms: f64,
+}
+
+/// The `n` of a benchmark request, eg. `{"command":"bench","n":100}`, `None` for any other request
+pub fn bench_request(request: &str) -> Option<usize> {
+    let request: Value = serde_json::from_str(request).ok()?;
+    if request.get("command")?.as_str()? != "bench" {
+        return None;
+    }
+    Some(request.get("n").and_then(Value::as_u64).unwrap_or(10) as usize)
+}
+
+/// Generates synthetic inputs for every input of a model from its Triton metadata. Dynamic dimensions (`-1`) are
+/// sent with a size of 1, so the benchmark measures the smallest request the model accepts.
+///
+/// # Arguments
+/// * `metadata` - The model metadata reported by Triton
+///
+/// # Returns
+/// The inputs with the shape they are sent with, or a description of the input that can't be generated
+pub fn generate_inputs(
+    metadata: &Value,
+) -> Result<HashMap<String, (TensorData, Vec<usize>)>, String> {
+    let model_inputs = metadata["inputs"]
+        .as_array()
+        .ok_or("Invalid model metadata format: 'inputs' not found")?;
+
+    let mut inputs = HashMap::new();
+    for input in model_inputs {
+        let name = input["name"]
+            .as_str()
+            .ok_or("Model metadata is missing 'name'")?;
+        let shape: Vec<usize> = input["shape"]
+            .as_array()
+            .ok_or_else(|| format!("Model metadata is missing the shape of '{}'", name))?
+            .iter()
+            .map(|dim| dim.as_i64().filter(|dim| *dim > 0).unwrap_or(1) as usize)
+            .collect();
+        let len = shape.iter().product::<usize>();
+
+        let data = match input["datatype"].as_str().unwrap_or_default() {
+            "FP32" => TensorData::F32((0..len).map(|i| (i % 7) as f32 / 7.0).collect()),
+            "INT32" => TensorData::I32((0..len).map(|i| (i % 7) as i32).collect()),
+            "INT64" => TensorData::I64((0..len).map(|i| (i % 7) as i64).collect()),
+            "UINT8" => TensorData::U8((0..len).map(|i| (i % 7) as u8).collect()),
+            "BOOL" => TensorData::Bool((0..len).map(|i| i % 2 == 0).collect()),
+            "BYTES" => TensorData::Str(vec!["benchmark".to_string(); len]),
+            datatype => {
+                return Err(format!(
+                    "Can't generate inputs of type {} for '{}'",
+                    datatype, name
+                ))
+            }
+        };
+        inputs.insert(name.to_string(), (data, shape));
+    }
+
+    Ok(inputs)
+}
+
+/// Summarizes the latencies of the successful inferences of a benchmark
+///
+/// # Arguments
+/// * `latencies` - Of the inferences that succeeded
+/// * `failed` - Inferences that failed
+/// * `elapsed` - Wall time of the whole benchmark
+pub fn summarize(mut latencies: Vec<Duration>, failed: usize, elapsed: Duration) -> BenchReport {
+    latencies.sort_unstable();
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    // Nearest rank
+    let percentile = |p: f64| {
+        if latencies.is_empty() {
+            return 0.0;
+        }
+        ms(latencies[((p * latencies.len() as f64).ceil() as usize).max(1) - 1])
+    };
+    let mean = if latencies.is_empty() {
+        0.0
+    } else {
+        latencies.iter().copied().map(ms).sum::<f64>() / latencies.len() as f64
+    };
+
+    BenchReport {
+        requests: latencies.len() + failed,
+        failed,
+        mean_latency_ms: mean,
+        p50_latency_ms: percentile(0.5),
+        p95_latency_ms: percentile(0.95),
+        p99_latency_ms: percentile(0.99),
+        throughput_per_sec: if elapsed.is_zero() {
+            0.0
+        } else {
+            latencies.len() as f64 / elapsed.as_secs_f64()
+        },
+        elapsed_ms: ms(elapsed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn inputs_are_generated_from_the_metadata() {
+        let metadata = json!({
+            "inputs": [
+                {"name": "pixels", "datatype": "FP32", "shape": [-1, 3, 2, 2]},
+                {"name": "prompt", "datatype": "BYTES", "shape": [1]},
+            ]
+        });
+
+        let inputs = generate_inputs(&metadata).unwrap();
+
+        let (pixels, shape) = &inputs["pixels"];
+        assert_eq!(shape, &vec![1, 3, 2, 2]);
+        assert!(matches!(pixels, TensorData::F32(data) if data.len() == 12));
+        assert!(matches!(&inputs["prompt"].0, TensorData::Str(data) if data.len() == 1));
+
+        let unsupported = json!({"inputs": [{"name": "x", "datatype": "FP16", "shape": [1]}]});
+        assert!(generate_inputs(&unsupported).is_err());
+    }
+
+    #[test]
+    fn reports_summarize_the_latencies() {
+        let latencies = (1..=10).map(|i| Duration::from_millis(i * 10)).collect();
+
+        let report = summarize(latencies, 2, Duration::from_secs(2));
+
+        assert_eq!(report.requests, 12);
+        assert_eq!(report.p50_latency_ms, 50.0);
+        assert_eq!(report.p99_latency_ms, 100.0);
+        assert_eq!(report.mean_latency_ms, 55.0);
+        assert_eq!(report.throughput_per_sec, 5.0);
+        assert_eq!(bench_request(r#"{"command":"bench","n":100}"#), Some(100));
+        assert_eq!(bench_request(r#"{"command":"pricing"}"#), None);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::client::TensorData;
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Length of the JSON header of a request or response using Triton's binary tensor data extension, the tensors
+/// follow it as raw bytes
+pub const HEADER_LENGTH: &str = "Inference-Header-Content-Length";
+
+/// Encodes an inference request with the binary tensor data extension. Strings are sent as raw length-prefixed
+/// bytes, so text in any language and script reaches the model unchanged, and every output is requested in binary
+/// too, Triton can't represent non-UTF8 `BYTES` outputs in JSON.
+///
+/// # Arguments
+/// * `inputs` - The inputs with the shape they are sent with
+///
+/// # Returns
+/// The body of the request and the length of its JSON header, sent as `Inference-Header-Content-Length`
+pub fn encode_request(inputs: &HashMap<&str, (TensorData, Vec<usize>)>) -> (Vec<u8>, usize) {
+    let mut binary = Vec::new();
+    let header_inputs: Vec<Value> = inputs
+        .iter()
+        .map(|(name, (data, shape))| {
+            let bytes = tensor_bytes(data);
+            let input = json!({
+                "name": name,
+                "shape": shape,
+                "datatype": datatype(data),
+                "parameters": { "binary_data_size": bytes.len() },
+            });
+            binary.extend_from_slice(&bytes);
+            input
+        })
+        .collect();
+
+    let mut body = json!({
+        "inputs": header_inputs,
+        "parameters": { "binary_data_output": true },
+    })
+    .to_string()
+    .into_bytes();
+    let header_length = body.len();
+    body.extend_from_slice(&binary);
+
+    (body, header_length)
+}
+
+/// The Triton datatype of a tensor
+pub fn datatype(data: &TensorData) -> &'static str {
+    match data {
+        TensorData::F32(_) => "FP32",
+        TensorData::I32(_) => "INT32",
+        TensorData::I64(_) => "INT64",
+        TensorData::U8(_) => "UINT8",
+        TensorData::Bool(_) => "BOOL",
+        TensorData::Str(_) => "BYTES",
+    }
+}
+
+/// The raw bytes of a tensor, little endian, with every string prefixed by its length in 4 bytes
+fn tensor_bytes(data: &TensorData) -> Vec<u8> {
+    match data {
+        TensorData::F32(data) => data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        TensorData::I32(data) => data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        TensorData::I64(data) => data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        TensorData::U8(data) => data.clone(),
+        TensorData::Bool(data) => data.iter().map(|v| *v as u8).collect(),
+        TensorData::Str(data) => data
+            .iter()
+            .flat_map(|v| {
+                let mut element = (v.len() as u32).to_le_bytes().to_vec();
+                element.extend_from_slice(v.as_bytes());
+                element
+            })
+            .collect(),
+    }
+}
+
+/// Decodes an inference response into the JSON form, so that post-processing doesn't depend on how the outputs were
+/// sent. `BYTES` elements that are not valid UTF-8 are returned as `{"base64": ...}`.
+///
+/// # Arguments
+/// * `body` - The body of the response
+/// * `header_length` - The `Inference-Header-Content-Length` of the response, `None` for plain JSON responses
+///
+/// # Returns
+/// The response with the binary outputs decoded into their `data`, or a description of the malformed part
+pub fn decode_response(body: &[u8], header_length: Option<usize>) -> Result<Value, String> {
+    let header_length = header_length.unwrap_or(body.len());
+    if header_length > body.len() {
+        return Err(format!(
+            "Inference response header of {} bytes is longer than the response",
+            header_length
+        ));
+    }
+    let (header, mut binary) = body.split_at(header_length);
+    let mut response: Value = serde_json::from_slice(header)
+        .map_err(|e| format!("Inference response is not valid JSON: {}", e))?;
+
+    let Some(outputs) = response["outputs"].as_array_mut() else {
+        return Ok(response);
+    };
+    // The binary outputs follow the header in the order of the outputs
+    for output in outputs {
+        let Some(size) = output["parameters"]["binary_data_size"].as_u64() else {
+            continue;
+        };
+        let name = output["name"].as_str().unwrap_or_default().to_string();
+        if size as usize > binary.len() {
+            return Err(format!("Binary data of output '{}' is truncated", name));
+        }
+        let (bytes, rest) = binary.split_at(size as usize);
+        binary = rest;
+
+        let datatype = output["datatype"].as_str().unwrap_or_default();
+        let data = decode_tensor(datatype, bytes)
+            .map_err(|e| format!("Output '{}' has invalid binary data: {}", name, e))?;
+        if let Some(output) = output.as_object_mut() {
+            output.insert("data".to_string(), data);
+            strip_binary_size(output);
+        }
+    }
+
+    Ok(response)
+}
+
+fn strip_binary_size(output: &mut Map<String, Value>) {
+    if let Some(parameters) = output.get_mut("parameters").and_then(Value::as_object_mut) {
+        parameters.remove("binary_data_size");
+        if parameters.is_empty() {
+            output.remove("parameters");
+        }
+    }
+}
+
+/// Decodes the raw little endian elements of a tensor into a flat JSON array
+fn decode_tensor(datatype: &str, bytes: &[u8]) -> Result<Value, String> {
+    macro_rules! elements {
+        ($ty:ty, $convert:expr) => {{
+            const SIZE: usize = std::mem::size_of::<$ty>();
+            if bytes.len() % SIZE != 0 {
+                return Err(format!(
+                    "{} bytes are not a whole number of {} elements",
+                    bytes.len(),
+                    datatype
+                ));
+            }
+            bytes
+                .chunks_exact(SIZE)
+                .map(|chunk| <$ty>::from_le_bytes(chunk.try_into().unwrap()))
+                .map($convert)
+                .collect::<Vec<Value>>()
+        }};
+    }
+
+    let data = match datatype {
+        "BOOL" => bytes.iter().map(|v| json!(*v != 0)).collect(),
+        "UINT8" => bytes.iter().map(|v| json!(v)).collect(),
+        "INT8" => bytes.iter().map(|v| json!(*v as i8)).collect(),
+        "UINT16" => elements!(u16, |v| json!(v)),
+        "INT16" => elements!(i16, |v| json!(v)),
+        "UINT32" => elements!(u32, |v| json!(v)),
+        "INT32" => elements!(i32, |v| json!(v)),
+        "UINT64" => elements!(u64, |v| json!(v)),
+        "INT64" => elements!(i64, |v| json!(v)),
+        "FP16" => elements!(u16, |v| json!(f16_to_f32(v))),
+        "BF16" => elements!(u16, |v| json!(f32::from_bits((v as u32) << 16))),
+        "FP32" => elements!(f32, |v| json!(v)),
+        "FP64" => elements!(f64, |v| json!(v)),
+        "BYTES" => decode_strings(bytes)?,
+        other => return Err(format!("unsupported datatype '{}'", other)),
+    };
+
+    Ok(Value::Array(data))
+}
+
+/// Splits length-prefixed `BYTES` elements, text stays text whatever its script
+fn decode_strings(mut bytes: &[u8]) -> Result<Vec<Value>, String> {
+    let mut elements = Vec::new();
+    while !bytes.is_empty() {
+        if bytes.len() < 4 {
+            return Err("truncated length of a BYTES element".to_string());
+        }
+        let (length, rest) = bytes.split_at(4);
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        if length > rest.len() {
+            return Err("truncated BYTES element".to_string());
+        }
+        let (element, rest) = rest.split_at(length);
+        bytes = rest;
+
+        elements.push(match std::str::from_utf8(element) {
+            Ok(text) => json!(text),
+            Err(_) => json!({ "base64": general_purpose::STANDARD.encode(element) }),
+        });
+    }
+    Ok(elements)
+}
+
+/// Converts an IEEE 754 half precision float
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half >> 15) as u32) << 31;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // Subnormal, normalized for f32
+        (0, mantissa) => {
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | ((mantissa << shift) & 0x3ff) << 13
+        }
+        (0x1f, mantissa) => sign | 0x7f80_0000 | (mantissa << 13),
+        (exponent, mantissa) => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_roundtrip_through_the_binary_extension() {
+        let inputs = HashMap::from([(
+            "prompt",
+            (
+                TensorData::Str(vec!["こんにちは".to_string(), "Grüße".to_string()]),
+                vec![2],
+            ),
+        )]);
+
+        let (body, header_length) = encode_request(&inputs);
+        let header: Value = serde_json::from_slice(&body[..header_length]).unwrap();
+        let size = header["inputs"][0]["parameters"]["binary_data_size"]
+            .as_u64()
+            .unwrap() as usize;
+        assert_eq!(header_length + size, body.len());
+
+        // Triton answers in the same layout, followed by an element that is not UTF-8
+        let mut response = json!({
+            "outputs": [{
+                "name": "text",
+                "datatype": "BYTES",
+                "shape": [3],
+                "parameters": { "binary_data_size": size + 6 },
+            }]
+        })
+        .to_string()
+        .into_bytes();
+        let response_header_length = response.len();
+        response.extend_from_slice(&body[header_length..]);
+        response.extend_from_slice(&[2, 0, 0, 0, 0xff, 0xfe]);
+
+        let decoded = decode_response(&response, Some(response_header_length)).unwrap();
+        assert_eq!(
+            decoded["outputs"][0]["data"],
+            json!(["こんにちは", "Grüße", { "base64": "//4=" }])
+        );
+        assert!(decoded["outputs"][0].get("parameters").is_none());
+    }
+
+    #[test]
+    fn numeric_outputs_are_decoded() {
+        assert_eq!(
+            decode_tensor("FP16", &[0x00, 0x3c, 0x00, 0xc0]).unwrap(),
+            json!([1.0, -2.0])
+        );
+        assert_eq!(
+            decode_tensor("INT64", &(-3i64).to_le_bytes()).unwrap(),
+            json!([-3])
+        );
+        assert!(decode_tensor("FP32", &[0, 0, 0]).is_err());
+        // Plain JSON responses pass through
+        let plain = br#"{"outputs":[{"name":"y","datatype":"FP32","shape":[1],"data":[0.5]}]}"#;
+        assert_eq!(
+            decode_response(plain, None).unwrap()["outputs"][0]["data"],
+            json!([0.5])
+        );
+    }
+}
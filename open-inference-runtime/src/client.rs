@@ -1,6 +1,20 @@
-use crate::models::ModelExtractor;
+use crate::artifacts::ArtifactStore;
+use crate::bench::{self, BenchReport};
+use crate::binary_data;
+use crate::component_cache::ComponentCache;
+#[cfg(feature = "ort")]
+use crate::fallback::OnnxFallback;
+use crate::models::{ExtractionOptions, ModelExtractor};
+use crate::pipeline::{self, PipelineStep, Source};
+#[cfg(feature = "wasm")]
+use crate::plugins::WasmPlugin;
+use crate::plugins::{Phase, PluginConfig, PluginLimits};
+use crate::postprocess::{self, PostProcessing, PostProcessor};
+use crate::preprocess::{self, PreProcessing};
+use crate::repository_index::{RepositoryIndex, RepositoryModel, DEFAULT_INDEX_TTL};
 use futures::{stream::StreamExt, Future, Stream};
-use reqwest::Client;
+use inference_protocol::{error_response, split_request_options, EngineError, ErrorCode};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::Value;
@@ -9,12 +23,66 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, Read};
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "ort")]
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Number of retries for requests to Triton that fail with a transient error
+const MAX_TRANSIENT_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY_MS: u64 = 200;
+/// Items a single batch request may carry, see `handle_batch`
+const MAX_BATCH_ITEMS: usize = 256;
+
+/// Settings of the connections to Triton, requests themselves are bounded by the request timeout
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    pub connect_timeout: Duration,
+    /// Idle connections kept open, reconnecting per request churns through sockets under load
+    pub pool_max_idle_per_host: usize,
+    pub user_agent: String,
+}
+
+/// Model inputs with the shape they are sent to Triton with
+type ShapedInputs = HashMap<String, (TensorData, Vec<usize>)>;
+
+/// Decides per request whether Triton's answer is replaced by a simulated server error, see
+/// `TritonClient::with_fault_injection`
+pub type FaultInjection = Arc<dyn Fn() -> bool + Send + Sync>;
 
 pub struct TritonClient {
     client: Client,
     url: String,
     model_name: String,
     model_path: PathBuf,
+    request_timeout: Option<Duration>,
+    max_in_flight: usize,
+    post_processors: Vec<PostProcessor>,
+    // Shared with the blocking pool the encoded inputs are decoded on
+    pre_processors: Arc<[PreProcessing]>,
+    pipeline: Vec<PipelineStep>,
+    /// Keeps encoded images and audio out of the responses, they are embedded as base64 without it
+    artifact_store: Option<Arc<dyn ArtifactStore>>,
+    /// Set while requests are served by the CPU fallback because Triton is unreachable
+    degraded: Arc<AtomicBool>,
+    repository_index: Arc<RepositoryIndex>,
+    fault_injection: Option<FaultInjection>,
+    #[cfg(feature = "ort")]
+    fallback: Option<Arc<OnnxFallback>>,
+    #[cfg(feature = "wasm")]
+    plugins: Vec<Arc<WasmPlugin>>,
+}
+
+/// Order in which the responses of a connection are delivered when several requests are in flight
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Delivery {
+    /// Responses arrive in the order of the requests
+    #[default]
+    Ordered,
+    /// Responses arrive as soon as they are ready, clients match them by `request_id`
+    Unordered,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -45,29 +113,86 @@ impl TritonClient {
         triton_url: &str,
         model_name: &str,
         model_path: PathBuf,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::new_with_component_cache(triton_url, model_name, model_path, None).await
+    }
+
+    /// Creates the client, extracting large model files through a cache shared with other tasks
+    pub async fn new_with_component_cache(
+        triton_url: &str,
+        model_name: &str,
+        model_path: PathBuf,
+        component_cache: Option<ComponentCache>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let options = ExtractionOptions {
+            component_cache,
+            ..Default::default()
+        };
+        Self::new_with_extraction(triton_url, model_name, model_path, options).await
+    }
+
+    /// Creates the client, extracting the model archive as configured by `options`. Fails if the archive can't be
+    /// extracted, eg. because it exceeds the extraction quota.
+    pub async fn new_with_extraction(
+        triton_url: &str,
+        model_name: &str,
+        model_path: PathBuf,
+        options: ExtractionOptions,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         // Initialize the client
+        let http_client = Client::new();
         let client = TritonClient {
-            client: Client::new(),
+            repository_index: Arc::new(RepositoryIndex::new(
+                http_client.clone(),
+                triton_url,
+                DEFAULT_INDEX_TTL,
+            )),
+            client: http_client,
             url: triton_url.to_string(),
             model_name: model_name.to_string(),
             model_path: model_path.clone(),
+            request_timeout: None,
+            max_in_flight: 1,
+            post_processors: Vec::new(),
+            pre_processors: Arc::from(Vec::new()),
+            pipeline: Vec::new(),
+            artifact_store: None,
+            degraded: Arc::new(AtomicBool::new(false)),
+            fault_injection: None,
+            #[cfg(feature = "ort")]
+            fallback: None,
+            #[cfg(feature = "wasm")]
+            plugins: Vec::new(),
         };
 
         match ModelExtractor::new(&client.model_name, model_path.clone()) {
             Ok(extractor) => {
-                if let Err(e) = extractor.extract_model() {
+                if let Err(e) = extractor
+                    .with_component_cache(options.component_cache)
+                    .with_config_generation(options.config_generation)
+                    .with_max_extracted_bytes(options.max_extracted_bytes)
+                    .with_expected_hashes(options.expected_hashes)
+                    .with_workers(options.workers)
+                    .with_progress(options.progress)
+                    .extract_model()
+                {
                     println!("❌ Extraction failed: {:?}", e);
-                } else {
+                    return Err(format!("Extraction of the model archive failed: {}", e).into());
                 }
             }
             Err(e) => {
                 println!("❌ Initialization of ModelExtractor failed: {:?}", e);
             }
         }
-        // Check if the server is live
+        // Check if the server is live, an unreachable server is tolerated so that the fallback can take over
         let mut url = format!("{}/health/ready", &client.url);
-        let mut response = client.client.get(&url).send().await?;
+        let mut response = match client.client.get(&url).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                println!("❌ Server is not reachable: {}", e);
+                return Ok(client);
+            }
+        };
         if !response.status().is_success() {
             println!("✅ Server is not live: {}", response.status());
         }
@@ -81,20 +206,271 @@ impl TritonClient {
         Ok(client)
     }
 
+    /// Sets the default deadline for a single inference request. Requests can override it with a `timeout_ms` field,
+    /// `None` lets requests run until they complete.
+    pub fn with_request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Applies the connection settings of the embedding application to the requests to Triton.
+    ///
+    /// # Returns
+    /// The client, or an `Error` if the HTTP client can't be built with the settings
+    pub fn with_http_options(mut self, options: HttpOptions) -> Result<Self, reqwest::Error> {
+        self.client = Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .pool_max_idle_per_host(options.pool_max_idle_per_host)
+            .user_agent(options.user_agent)
+            .build()?;
+        self.repository_index = Arc::new(RepositoryIndex::new(
+            self.client.clone(),
+            &self.url,
+            self.repository_index.ttl(),
+        ));
+        Ok(self)
+    }
+
+    /// Sets how long the repository index is cached, see `list_models`
+    pub fn with_repository_index_ttl(mut self, ttl: Duration) -> Self {
+        self.repository_index = Arc::new(RepositoryIndex::new(self.client.clone(), &self.url, ttl));
+        self
+    }
+
+    /// Sets how many requests of a connection may run against Triton at the same time. `1` runs them strictly one
+    /// after another.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Lets the embedding application fail requests to Triton with a simulated 503, to exercise the retries and error
+    /// handling of the request path without a misbehaving server
+    pub fn with_fault_injection(mut self, fault_injection: Option<FaultInjection>) -> Self {
+        self.fault_injection = fault_injection;
+        self
+    }
+
+    /// Serves requests with ONNX Runtime on the CPU while Triton is unreachable, for plain ONNX models of a single step.
+    /// Only available with the `ort` feature, the client serves through Triton alone otherwise.
+    #[cfg_attr(not(feature = "ort"), allow(unused_mut))]
+    pub fn with_onnx_fallback(mut self, enabled: bool) -> Self {
+        if !enabled {
+            return self;
+        }
+
+        #[cfg(feature = "ort")]
+        {
+            let model_file = self
+                .model_path
+                .join(&self.model_name)
+                .join("1")
+                .join("model.onnx");
+            if !self.pipeline.is_empty() || !model_file.exists() {
+                println!(
+                    "❌ CPU fallback needs a single plain ONNX model, serving through Triton only"
+                );
+                return self;
+            }
+            match OnnxFallback::load(&model_file) {
+                Ok(fallback) => self.fallback = Some(Arc::new(fallback)),
+                Err(e) => println!("❌ Failed to load the model for the CPU fallback: {}", e),
+            }
+        }
+
+        #[cfg(not(feature = "ort"))]
+        println!("❌ CPU fallback requested, but the runtime was built without the `ort` feature");
+
+        self
+    }
+
+    /// Whether requests are currently served by the CPU fallback, shared so that it can be read without the client
+    pub fn degraded_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.degraded)
+    }
+
+    /// Whether the CPU fallback can take over when Triton is unreachable
+    pub fn has_fallback(&self) -> bool {
+        #[cfg(feature = "ort")]
+        return self.fallback.is_some();
+        #[cfg(not(feature = "ort"))]
+        return false;
+    }
+
+    /// Whether Triton answers its liveness probe
+    pub async fn is_reachable(&self) -> bool {
+        let url = format!("{}/health/live", self.url);
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .map(|response| response.status().is_success())
+            .unwrap_or(false)
+    }
+
+    /// Sets the pre-processing stages of the model inputs, so that clients can send encoded images or audio
+    /// instead of tensors
+    pub fn with_preprocessing(mut self, stages: Vec<PreProcessing>) -> Self {
+        self.pre_processors = stages.into();
+        self
+    }
+
+    /// Sets the post-processing stages applied to the outputs of every inference, so that clients receive labels,
+    /// detections or text instead of raw tensors. Label and vocabulary files are resolved against the model path.
+    pub fn with_postprocessing(mut self, stages: Vec<PostProcessing>) -> io::Result<Self> {
+        self.post_processors = stages
+            .into_iter()
+            .map(|stage| PostProcessor::load(stage, &self.model_path))
+            .collect::<io::Result<_>>()?;
+        Ok(self)
+    }
+
+    /// Stores the images and audio encoded by post-processing, responses then carry references to them instead of
+    /// their base64 encoded content
+    pub fn with_artifact_store(mut self, store: Option<Arc<dyn ArtifactStore>>) -> Self {
+        self.artifact_store = store;
+        self
+    }
+
+    /// Runs the WebAssembly plugins of the task around every inference, in the order they are configured. Modules are
+    /// resolved against the model path and their resources clamped to `limits`. Only available with the `wasm`
+    /// feature, a task with plugins can't be served correctly without them, so they are refused otherwise.
+    #[cfg_attr(not(feature = "wasm"), allow(unused_variables))]
+    pub fn with_plugins(
+        self,
+        configs: Vec<PluginConfig>,
+        limits: PluginLimits,
+    ) -> Result<Self, String> {
+        if configs.is_empty() {
+            return Ok(self);
+        }
+
+        #[cfg(feature = "wasm")]
+        {
+            let mut client = self;
+            client.plugins = configs
+                .into_iter()
+                .map(|config| WasmPlugin::load(config, &client.model_path, limits).map(Arc::new))
+                .collect::<Result<_, _>>()?;
+            Ok(client)
+        }
+
+        #[cfg(not(feature = "wasm"))]
+        Err(format!(
+            "The task declares {} plugins, but the runtime was built without the `wasm` feature",
+            configs.len()
+        ))
+    }
+
+    /// Serves a pipeline of models instead of the single model of the task. Every request runs through all steps, the
+    /// response is the one of the last step.
+    pub fn with_pipeline(mut self, steps: Vec<PipelineStep>) -> Result<Self, String> {
+        pipeline::validate(&steps)?;
+        self.pipeline = steps;
+        Ok(self)
+    }
+
+    /// The models that are loaded for serving, the model of the task or every model of the pipeline
+    pub fn served_models(&self) -> Vec<&str> {
+        if self.pipeline.is_empty() {
+            return vec![self.model_name.as_str()];
+        }
+
+        let mut models: Vec<&str> = Vec::new();
+        for step in &self.pipeline {
+            if !models.contains(&step.model.as_str()) {
+                models.push(&step.model);
+            }
+        }
+        models
+    }
+
+    /// Sends a request to Triton, retrying with jittered exponential backoff if it fails transiently (connection refused,
+    /// timeout, 429/502/503/504). Other failures, like 400 for malformed input, are returned immediately.
+    async fn send_with_retry<F>(&self, build_request: F) -> Result<Response, reqwest::Error>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let result = match self.injected_fault() {
+                Some(response) => Ok(response),
+                None => build_request().send().await,
+            };
+
+            let transient = match &result {
+                Ok(response) => is_transient_status(response.status()),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+
+            if !transient || attempt >= MAX_TRANSIENT_RETRIES {
+                return result;
+            }
+
+            attempt += 1;
+            let delay = backoff_delay(attempt);
+            println!(
+                "⏳ Transient Triton error, retrying in {} ms (attempt {}/{})",
+                delay.as_millis(),
+                attempt,
+                MAX_TRANSIENT_RETRIES
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// The simulated answer of Triton if a fault is injected into this request
+    fn injected_fault(&self) -> Option<Response> {
+        let inject = self.fault_injection.as_ref()?;
+        if !inject() {
+            return None;
+        }
+
+        println!("⚠️ Injecting a simulated Triton server error");
+        http::Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body("Injected fault: inference engine returned 503 Service Unavailable")
+            .ok()
+            .map(Response::from)
+    }
+
+    /// Lists the models in the repository of Triton. The index is cached for its TTL and invalidated whenever this
+    /// client loads or unloads a model.
+    pub async fn list_models(
+        &self,
+    ) -> Result<Vec<RepositoryModel>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.repository_index.models().await?)
+    }
+
+    /// The cached repository index, shared so that it can be polled and subscribed to without the client
+    pub fn repository_index(&self) -> Arc<RepositoryIndex> {
+        Arc::clone(&self.repository_index)
+    }
+
+    /// Loads the model, or every model of the pipeline
     pub async fn load_model(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!("{}/repository/models/{}/load", self.url, self.model_name);
+        for model in self.served_models() {
+            self.load_named_model(model).await?;
+        }
+        Ok(())
+    }
+
+    async fn load_named_model(
+        &self,
+        model: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/repository/models/{}/load", self.url, model);
         let response = self
-            .client
-            .post(&url)
-            .json(&serde_json::json!({}))
-            .send()
+            .send_with_retry(|| self.client.post(&url).json(&serde_json::json!({})))
             .await?;
+        self.repository_index.invalidate();
         if response.status().is_success() {
             Ok(())
         } else {
             Err(format!(
                 "Failed to load model '{}'. HTTP Status: {:?}",
-                self.model_name,
+                model,
                 response.status()
             )
             .into())
@@ -126,22 +502,30 @@ impl TritonClient {
         }
     }
 
-    // Unload a model from Triton
+    // Unload the model, or every model of the pipeline, from Triton
     pub async fn unload_model(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!("{}/repository/models/{}/unload", self.url, self.model_name);
+        for model in self.served_models() {
+            self.unload_named_model(model).await?;
+        }
+        Ok(())
+    }
+
+    async fn unload_named_model(
+        &self,
+        model: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/repository/models/{}/unload", self.url, model);
         let response = self
-            .client
-            .post(&url)
-            .json(&serde_json::json!({}))
-            .send()
+            .send_with_retry(|| self.client.post(&url).json(&serde_json::json!({})))
             .await?;
+        self.repository_index.invalidate();
 
         if response.status().is_success() {
             Ok(())
         } else {
             Err(format!(
                 "Failed to unload model '{}'. HTTP Status: {:?}",
-                self.model_name,
+                model,
                 response.status()
             )
             .into())
@@ -152,9 +536,16 @@ impl TritonClient {
     pub async fn get_model_metadata(
         &self,
     ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        let url = format!("{}/models/{}", self.url, self.model_name);
+        self.get_named_model_metadata(&self.model_name).await
+    }
+
+    async fn get_named_model_metadata(
+        &self,
+        model: &str,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/models/{}", self.url, model);
 
-        let response = self.client.get(&url).send().await?;
+        let response = self.send_with_retry(|| self.client.get(&url)).await?;
 
         if response.status().is_success() {
             let metadata: Value = response.json().await?;
@@ -166,12 +557,76 @@ impl TritonClient {
             );
             Err(format!(
                 "❌ Failed to fetch metadata for model '{}'. HTTP Status: {:?}",
-                self.model_name,
+                model,
                 response.status()
             )
             .into())
         }
     }
+    /// Describes the model for clients building requests: the inputs and outputs reported by Triton, the inputs that
+    /// accept encoded payloads and the post-processing applied to the outputs. Loads the model only for the duration of the call.
+    pub async fn describe_model(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        self.load_model().await?;
+        let metadata = self.get_served_metadata().await;
+        self.unload_model().await?;
+        let served_metadata = metadata?;
+        // The response of a pipeline is the one of its last step
+        let metadata = served_metadata.last().cloned().unwrap_or_default();
+
+        let request_inputs = if self.pipeline.is_empty() {
+            metadata["inputs"].as_array().cloned().unwrap_or_default()
+        } else {
+            pipeline::request_inputs(&self.pipeline, &served_metadata)
+        };
+
+        let inputs: Vec<Value> = request_inputs
+            .into_iter()
+            .map(|mut input| {
+                let stage = self
+                    .pre_processors
+                    .iter()
+                    .find(|stage| input["name"].as_str() == Some(stage.input.as_str()));
+                if let Some(stage) = stage {
+                    input["preprocessing"] = json!(stage.stage);
+                }
+                input
+            })
+            .collect();
+
+        let mut description = json!({
+            "name": self.model_name,
+            "platform": metadata["platform"],
+            "versions": metadata["versions"],
+            "inputs": inputs,
+            "outputs": metadata["outputs"],
+            "postprocessing": self
+                .post_processors
+                .iter()
+                .map(PostProcessor::describe)
+                .collect::<Vec<_>>(),
+        });
+        if !self.pipeline.is_empty() {
+            description["pipeline"] = json!(self.pipeline);
+        }
+
+        Ok(description)
+    }
+
+    /// Fetches the metadata of the model, or of every step of the pipeline in order
+    async fn get_served_metadata(
+        &self,
+    ) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
+        if self.pipeline.is_empty() {
+            return Ok(vec![self.get_model_metadata().await?]);
+        }
+
+        let mut metadata = Vec::new();
+        for step in &self.pipeline {
+            metadata.push(self.get_named_model_metadata(&step.model).await?);
+        }
+        Ok(metadata)
+    }
+
     pub async fn align_inputs(
         &self,
         inputs: HashMap<String, TensorData>,
@@ -179,7 +634,9 @@ impl TritonClient {
     {
         // Fetch model metadata
         let metadata_url = format!("{}/models/{}", self.url, self.model_name);
-        let metadata_response = self.client.get(&metadata_url).send().await?;
+        let metadata_response = self
+            .send_with_retry(|| self.client.get(&metadata_url))
+            .await?;
 
         if !metadata_response.status().is_success() {
             let error_message = metadata_response.text().await.unwrap_or_default();
@@ -189,162 +646,709 @@ impl TritonClient {
         }
 
         let metadata: serde_json::Value = metadata_response.json().await?;
-        let model_inputs = metadata["inputs"]
-            .as_array()
-            .ok_or("❌ Invalid model metadata format: 'inputs' not found")?;
-
-        let mut aligned_inputs = HashMap::new();
-
-        for input in model_inputs {
-            let name = input["name"]
-                .as_str()
-                .ok_or("❌ Model metadata is missing 'name'")?;
-            let expected_shape = input["shape"]
-                .as_array()
-                .ok_or("❌ Model metadata is missing 'shape'")?
-                .iter()
-                .map(|v| v.as_u64().unwrap() as usize)
-                .collect::<Vec<usize>>();
-
-            let expected_len = expected_shape.iter().product::<usize>();
-
-            let tensor_data = inputs
-                .get(name)
-                .ok_or_else(|| format!("❌ Missing input data for '{}'", name))?;
-
-            let data_len = match tensor_data {
-                TensorData::F32(data) => data.len(),
-                TensorData::I32(data) => data.len(),
-                TensorData::I64(data) => data.len(),
-                TensorData::U8(data) => data.len(),
-                TensorData::Bool(data) => data.len(),
-                TensorData::Str(data) => data.len(),
-            };
-
-            if data_len != expected_len {
-                return Err(format!(
-                    "❌ Shape mismatch for '{}'. Expected {:?}, got {}",
-                    name, expected_shape, data_len
-                )
-                .into());
-            }
-
-            aligned_inputs.insert(name.to_string(), (tensor_data.clone(), expected_shape));
-        }
-
-        Ok(aligned_inputs)
+        align_to_metadata(&metadata, &inputs, HashMap::new())
     }
 
     pub async fn infer(
         &self,
         input_data: HashMap<&str, (TensorData, Vec<usize>)>,
     ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
-        let model_inputs: Vec<_> = input_data
-            .iter()
-            .map(|(name, (tensor_data, shape))| {
-                let datatype = match tensor_data {
-                    TensorData::F32(_) => "FP32",
-                    TensorData::I32(_) => "INT32",
-                    TensorData::I64(_) => "INT64",
-                    TensorData::U8(_) => "UINT8",
-                    TensorData::Bool(_) => "BOOL",
-                    TensorData::Str(_) => "BYTES",
-                };
-                serde_json::json!({
-                    "name": name,
-                    "shape": shape,
-                    "datatype": datatype,
-                    "data": tensor_data.to_serializable()
-                })
-            })
-            .collect();
+        self.infer_model(&self.model_name, input_data).await
+    }
 
-        let request_body = serde_json::json!({ "inputs": model_inputs });
+    async fn infer_model(
+        &self,
+        model: &str,
+        input_data: HashMap<&str, (TensorData, Vec<usize>)>,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let (body, header_length) = binary_data::encode_request(&input_data);
 
-        let url = format!("{}/models/{}/infer", self.url, self.model_name);
-        let response = self.client.post(&url).json(&request_body).send().await?;
+        let url = format!("{}/models/{}/infer", self.url, model);
+        let response = self
+            .send_with_retry(|| {
+                self.client
+                    .post(&url)
+                    .header(binary_data::HEADER_LENGTH, header_length)
+                    .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                    .body(body.clone())
+            })
+            .await?;
 
-        if response.status().is_success() {
-            let result = response.json::<serde_json::Value>().await?;
-            Ok(result)
+        let status = response.status();
+        if status.is_success() {
+            let header_length = response
+                .headers()
+                .get(binary_data::HEADER_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .and_then(|length| length.parse().ok());
+            let body = response.bytes().await?;
+            binary_data::decode_response(&body, header_length).map_err(|detail| {
+                EngineError {
+                    code: ErrorCode::InferenceFailed,
+                    detail,
+                }
+                .into()
+            })
         } else {
             let error_message = response
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            Err(format!("❌ Inference failed: HTTP - {}", error_message).into())
+
+            if status.is_client_error() {
+                Err(EngineError {
+                    code: ErrorCode::BadInput,
+                    detail: format!(
+                        "❌ Invalid inference request: HTTP {} - {}",
+                        status, error_message
+                    ),
+                }
+                .into())
+            } else {
+                Err(EngineError {
+                    code: ErrorCode::InferenceFailed,
+                    detail: format!(
+                        "❌ Inference server error: HTTP {} - {}",
+                        status, error_message
+                    ),
+                }
+                .into())
+            }
         }
     }
 
     pub async fn run<S, C, CFut>(
         &self,
-        mut request_stream: S,
+        request_stream: S,
+        response_closure: C,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        S: Stream<Item = String> + Unpin + Send + 'static,
+        C: FnMut(String) -> CFut + Send + 'static,
+        CFut: Future<Output = ()> + Send + 'static,
+    {
+        self.run_with_delivery(request_stream, response_closure, Delivery::Ordered)
+            .await
+    }
+
+    /// Serves the requests of a connection, with up to `max_in_flight` of them running concurrently. With more than
+    /// one request in flight the model stays loaded until the stream ends, instead of being loaded per request.
+    ///
+    /// # Arguments
+    /// * `request_stream` - The requests of the connection
+    /// * `response_closure` - Called with the response to every request
+    /// * `delivery` - Whether responses are delivered in request order or as soon as they are ready
+    pub async fn run_with_delivery<S, C, CFut>(
+        &self,
+        request_stream: S,
         mut response_closure: C,
+        delivery: Delivery,
     ) -> Result<(), Box<dyn std::error::Error>>
     where
         S: Stream<Item = String> + Unpin + Send + 'static,
         C: FnMut(String) -> CFut + Send + 'static,
         CFut: Future<Output = ()> + Send + 'static,
     {
-        while let Some(request) = request_stream.next().await {
-            let parsed_inputs: Result<HashMap<String, TensorData>, _> =
-                serde_json::from_str(&request);
-
-            let result: Result<Value, Box<dyn std::error::Error + Send + Sync>> =
-                match parsed_inputs {
-                    Ok(inputs) => self.run_inference(inputs).await,
-                    Err(e) => {
-                        println!("❌ Failed to parse inputs: {}", e);
-                        Err(format!("Invalid input format: {}", e).into())
+        let concurrency = self.max_in_flight.max(1);
+        let mut keep_loaded = concurrency > 1;
+
+        if keep_loaded {
+            println!("⏳ Loading model: {}", self.model_name);
+            if let Err(e) = self.load_model().await {
+                // The CPU fallback serves the connection, requests load the model themselves once Triton is back
+                if !(self.has_fallback() && is_unreachable(e.as_ref())) {
+                    return Err(e.to_string().into());
+                }
+                keep_loaded = false;
+            }
+        }
+
+        let responses = request_stream.map(|request| self.handle_request(request, keep_loaded));
+
+        match delivery {
+            Delivery::Ordered => {
+                let mut responses = responses.buffered(concurrency);
+                while let Some(response) = responses.next().await {
+                    response_closure(response).await;
+                }
+            }
+            Delivery::Unordered => {
+                let mut responses = responses.buffer_unordered(concurrency);
+                while let Some(response) = responses.next().await {
+                    response_closure(response).await;
+                }
+            }
+        }
+
+        if keep_loaded {
+            self.unload_model().await.map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `n` inferences with synthetic inputs generated from the model metadata, `max_in_flight` of them
+    /// concurrently like the requests of a connection. Pre-processing and plugins are skipped, the benchmark measures
+    /// the engine.
+    ///
+    /// # Arguments
+    /// * `n` - The number of inferences
+    /// * `model_loaded` - Whether the model is already loaded, it is loaded for the benchmark otherwise
+    ///
+    /// # Returns
+    /// The `BenchReport`, or an error if the model can't be benchmarked
+    pub async fn bench(
+        &self,
+        n: usize,
+        model_loaded: bool,
+    ) -> Result<BenchReport, Box<dyn std::error::Error + Send + Sync>> {
+        if !self.pipeline.is_empty() {
+            return Err("Pipelines can't be benchmarked with synthetic inputs".into());
+        }
+        if !model_loaded {
+            self.load_model().await?;
+        }
+
+        let result = async {
+            let metadata = self.get_model_metadata().await?;
+            let inputs = bench::generate_inputs(&metadata)?;
+            let started = Instant::now();
+            let results: Vec<_> = futures::stream::iter(0..n)
+                .map(|_| {
+                    let inputs: HashMap<&str, (TensorData, Vec<usize>)> = inputs
+                        .iter()
+                        .map(|(name, input)| (name.as_str(), input.clone()))
+                        .collect();
+                    async move {
+                        let inference = Instant::now();
+                        self.infer(inputs).await.map(|_| inference.elapsed())
                     }
-                };
+                })
+                .buffer_unordered(self.max_in_flight.max(1))
+                .collect()
+                .await;
+            let elapsed = started.elapsed();
 
-            let response = match result {
-                Ok(json) => json.to_string(),
-                Err(e) => format!("❌ Inference error: {}", e),
+            let failed = results.iter().filter(|result| result.is_err()).count();
+            let latencies = results.into_iter().filter_map(Result::ok).collect();
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(bench::summarize(
+                latencies, failed, elapsed,
+            ))
+        }
+        .await;
+
+        if !model_loaded {
+            self.unload_model().await?;
+        }
+        result
+    }
+
+    /// Runs a single request and builds its response, echoing the `request_id` of the request if it has one
+    async fn handle_request(&self, request: String, model_loaded: bool) -> String {
+        let (request, options) = split_request_options(request);
+        let timeout = options.timeout.or(self.request_timeout);
+
+        if let Some(n) = bench::bench_request(&request) {
+            let response = match self.bench(n, model_loaded).await {
+                Ok(report) => json!({ "command": "bench", "report": report }).to_string(),
+                Err(e) => error_response(ErrorCode::InferenceFailed, format!("Benchmark failed: {}", e)),
             };
+            return with_request_id(response, options.request_id);
+        }
+
+        let response = match batch_items(&request) {
+            Some(Ok(items)) => self.handle_batch(items, timeout, model_loaded).await.to_string(),
+            Some(Err(e)) => error_response(ErrorCode::BadInput, e),
+            None => match self.serve(request, timeout, model_loaded).await {
+                Ok(json) => json.to_string(),
+                Err(e) => error_response(e.code, e.detail),
+            },
+        };
+
+        with_request_id(response, options.request_id)
+    }
+
+    /// Runs the items of a batch independently, up to `max_in_flight` of them concurrently, so that an invalid item
+    /// only fails itself. Items can carry their own `request_id` and `timeout_ms`, the deadline of the batch applies
+    /// to items without one.
+    ///
+    /// # Returns
+    /// The batch envelope, `{"batch":[{"status":"ok","response":{..}},{"status":"error","error":{..}}],
+    /// "succeeded":1,"failed":1}` with the results in the order of the items
+    async fn handle_batch(
+        &self,
+        items: Vec<Value>,
+        timeout: Option<Duration>,
+        model_loaded: bool,
+    ) -> Value {
+        let results: Vec<Value> = futures::stream::iter(items)
+            .map(|item| async move {
+                let (item, options) = split_request_options(item.to_string());
+                let result = if bench::bench_request(&item).is_some() {
+                    Err(EngineError {
+                        code: ErrorCode::BadInput,
+                        detail: "Commands can't be batched".to_string(),
+                    })
+                } else {
+                    self.serve(item, options.timeout.or(timeout), model_loaded)
+                        .await
+                };
+
+                let mut result = match result {
+                    Ok(response) => json!({ "status": "ok", "response": response }),
+                    Err(e) => json!({
+                        "status": "error",
+                        "error": { "code": e.code.as_str(), "detail": e.detail },
+                    }),
+                };
+                if let Some(request_id) = options.request_id {
+                    result["request_id"] = request_id;
+                }
+                result
+            })
+            .buffered(self.max_in_flight.max(1))
+            .collect()
+            .await;
+
+        let failed = results
+            .iter()
+            .filter(|result| result["status"] == "error")
+            .count();
+        json!({
+            "succeeded": results.len() - failed,
+            "failed": failed,
+            "batch": results,
+        })
+    }
+
+    /// Runs a request through the plugins, pre-processing, the inference and post-processing
+    ///
+    /// # Returns
+    /// The response, or the `EngineError` the request failed with
+    async fn serve(
+        &self,
+        request: String,
+        timeout: Option<Duration>,
+        model_loaded: bool,
+    ) -> Result<Value, EngineError> {
+        let request = self.run_plugins(Phase::Pre, request).await.map_err(|e| {
+            println!("❌ Pre-processing plugin failed: {}", e);
+            EngineError {
+                code: ErrorCode::BadInput,
+                detail: e,
+            }
+        })?;
+        let inputs = if self.pre_processors.is_empty() {
+            preprocess::parse_inputs(&self.pre_processors, &request)
+        } else {
+            // Decoding images and audio is CPU bound, it must not hold up the other connections on the runtime
+            let stages = Arc::clone(&self.pre_processors);
+            tokio::task::spawn_blocking(move || preprocess::parse_inputs(&stages, &request))
+                .await
+                .unwrap_or_else(|e| Err(format!("Pre-processing thread failed: {}", e)))
+        };
+        let inputs = inputs.map_err(|e| {
+            println!("❌ Failed to parse inputs: {}", e);
+            EngineError {
+                code: ErrorCode::BadInput,
+                detail: format!("Invalid input format: {}", e),
+            }
+        })?;
+
+        // A timed out inference is dropped, so its late result never reaches the client
+        let inference = self.run_inference_with(inputs, model_loaded);
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, inference).await.map_err(|_| {
+                println!("❌ Inference timed out after {} ms", timeout.as_millis());
+                EngineError {
+                    code: ErrorCode::Timeout,
+                    detail: format!(
+                        "Inference did not complete within {} ms",
+                        timeout.as_millis()
+                    ),
+                }
+            })?,
+            None => inference.await,
+        };
+
+        self.build_response(result).await
+    }
+
+    async fn build_response(
+        &self,
+        result: Result<Value, Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Result<Value, EngineError> {
+        let result = match result {
+            Ok(json) => self.post_process(json).await,
+            Err(e) => Err(e),
+        };
+
+        result.map_err(|e| EngineError {
+            code: e
+                .downcast_ref::<EngineError>()
+                .map(|e| e.code)
+                .unwrap_or(ErrorCode::InferenceFailed),
+            detail: e.to_string(),
+        })
+    }
 
-            response_closure(response).await;
+    /// Applies the post-processors to an inference response and stores the artifacts they encoded
+    async fn post_process(
+        &self,
+        json: Value,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let failed = |detail: String| EngineError {
+            code: ErrorCode::InferenceFailed,
+            detail,
+        };
+
+        let json = match &self.artifact_store {
+            None => postprocess::apply(&self.post_processors, json)
+                .map_err(|detail| failed(format!("Post-processing failed: {}", detail)))?,
+            Some(store) => {
+                let (mut json, artifacts) =
+                    postprocess::apply_with_artifacts(&self.post_processors, json)
+                        .map_err(|detail| failed(format!("Post-processing failed: {}", detail)))?;
+                for artifact in &artifacts {
+                    let reference = store.store(artifact).await.map_err(|detail| {
+                        failed(format!(
+                            "Failed to store output '{}': {}",
+                            artifact.output, detail
+                        ))
+                    })?;
+                    postprocess::insert_reference(&mut json, artifact, reference);
+                }
+                json
+            }
+        };
+
+        let transformed = self
+            .run_plugins(Phase::Post, json.to_string())
+            .await
+            .map_err(failed)?;
+        match serde_json::from_str(&transformed) {
+            Ok(json) => Ok(json),
+            Err(e) => Err(failed(format!("Post-processing plugin returned invalid JSON: {}", e)).into()),
         }
+    }
 
-        Ok(())
+    /// Passes a request or response through the plugins of a phase, on the blocking pool as they are CPU bound
+    #[cfg_attr(not(feature = "wasm"), allow(unused_variables))]
+    async fn run_plugins(&self, phase: Phase, payload: String) -> Result<String, String> {
+        #[cfg(feature = "wasm")]
+        {
+            let mut payload = payload;
+            for plugin in self.plugins.iter().filter(|plugin| plugin.phase() == phase) {
+                let plugin = Arc::clone(plugin);
+                payload = tokio::task::spawn_blocking(move || plugin.transform(&payload))
+                    .await
+                    .map_err(|e| format!("Plugin thread failed: {}", e))??;
+            }
+            Ok(payload)
+        }
+
+        #[cfg(not(feature = "wasm"))]
+        Ok(payload)
     }
 
     pub async fn run_inference(
         &self,
         inputs: HashMap<String, TensorData>,
     ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        self.run_inference_with(inputs, false).await
+    }
+
+    /// Runs an inference on Triton, or on the CPU fallback while Triton is unreachable
+    async fn run_inference_with(
+        &self,
+        inputs: HashMap<String, TensorData>,
+        model_loaded: bool,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        #[cfg(feature = "ort")]
+        if let Some(fallback) = &self.fallback {
+            // Probing first avoids waiting for the retries of every request while Triton is down
+            if self.degraded.load(Ordering::Relaxed) && !self.is_reachable().await {
+                return run_on_fallback(fallback, inputs).await;
+            }
+
+            let result = self.run_on_triton(inputs.clone(), model_loaded).await;
+            let unreachable = matches!(&result, Err(e) if is_unreachable(e.as_ref()));
+            self.set_degraded(unreachable);
+            if unreachable {
+                return run_on_fallback(fallback, inputs).await;
+            }
+            return result;
+        }
+
+        self.run_on_triton(inputs, model_loaded).await
+    }
+
+    #[cfg(feature = "ort")]
+    fn set_degraded(&self, degraded: bool) {
+        if self.degraded.swap(degraded, Ordering::Relaxed) != degraded {
+            if degraded {
+                println!("❌ Triton is unreachable, serving on the CPU fallback");
+            } else {
+                println!("✅ Triton is reachable again, leaving the CPU fallback");
+            }
+        }
+    }
+
+    /// Runs an inference, loading and unloading the model around it unless it is already loaded for the connection
+    async fn run_on_triton(
+        &self,
+        inputs: HashMap<String, TensorData>,
+        model_loaded: bool,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        if model_loaded {
+            return self.infer_loaded(inputs).await;
+        }
+
         //  Load the Model
         println!("⏳ Loading model: {}", self.model_name);
-        self.load_model().await.unwrap();
-        match self.get_model_metadata().await {
-            Ok(_) => println!(),
-            Err(e) => {
-                return Err(e);
-            }
+        self.load_model().await?;
+        let result = self.infer_loaded(inputs).await;
+        self.unload_model().await?;
+        result
+    }
+
+    async fn infer_loaded(
+        &self,
+        inputs: HashMap<String, TensorData>,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        if !self.pipeline.is_empty() {
+            return self.run_pipeline(inputs).await;
         }
 
+        self.get_model_metadata().await?;
+        println!();
+
         // Run Inference
-        let aligned_inputs_result = self.align_inputs(inputs).await;
-        match aligned_inputs_result {
-            Ok(aligned_inputs) => {
-                let aligned_refs: HashMap<&str, (TensorData, Vec<usize>)> = aligned_inputs
-                    .iter()
-                    .map(|(k, v)| (k.as_str(), v.clone()))
-                    .collect();
+        let aligned_inputs = self.align_inputs(inputs).await?;
+        let aligned_refs: HashMap<&str, (TensorData, Vec<usize>)> = aligned_inputs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone()))
+            .collect();
+
+        self.infer(aligned_refs).await
+    }
 
-                match self.infer(aligned_refs).await {
-                    Ok(result) => {
-                        self.unload_model().await?;
-                        Ok(result)
+    /// Runs the steps of the pipeline in order, passing outputs on as the inputs of later steps
+    async fn run_pipeline(
+        &self,
+        inputs: HashMap<String, TensorData>,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let mut step_outputs: HashMap<&str, HashMap<String, (TensorData, Vec<usize>)>> =
+            HashMap::new();
+        let mut response = Value::Null;
+
+        for step in &self.pipeline {
+            let metadata = self.get_named_model_metadata(&step.model).await?;
+
+            let mut request_inputs = HashMap::new();
+            let mut shaped = HashMap::new();
+            for input in metadata["inputs"].as_array().into_iter().flatten() {
+                let Some(name) = input["name"].as_str() else {
+                    continue;
+                };
+                match step.source(name) {
+                    Source::Request(field) => {
+                        if let Some(tensor) = inputs.get(field) {
+                            request_inputs.insert(name.to_string(), tensor.clone());
+                        }
                     }
-                    Err(e) => {
-                        self.unload_model().await?;
-                        Err(format!("❌ Inference failed: {:?}", e).into())
+                    Source::Output { model, output } => {
+                        let tensor = step_outputs
+                            .get(model)
+                            .and_then(|outputs| outputs.get(output))
+                            .ok_or_else(|| EngineError {
+                                code: ErrorCode::InferenceFailed,
+                                detail: format!(
+                                    "Pipeline step '{}' produced no output '{}' for step '{}'",
+                                    model, output, step.model
+                                ),
+                            })?;
+                        shaped.insert(name.to_string(), tensor.clone());
                     }
                 }
             }
-            Err(e) => Err(format!("❌ Inference failed: {:?}", e).into()),
+
+            let aligned_inputs = align_to_metadata(&metadata, &request_inputs, shaped)?;
+            let aligned_refs: HashMap<&str, (TensorData, Vec<usize>)> = aligned_inputs
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.clone()))
+                .collect();
+
+            response = self.infer_model(&step.model, aligned_refs).await?;
+            let outputs = pipeline::output_tensors(&response).map_err(|detail| EngineError {
+                code: ErrorCode::InferenceFailed,
+                detail: format!("Pipeline step '{}' failed: {}", step.model, detail),
+            })?;
+            step_outputs.insert(&step.model, outputs);
         }
+
+        Ok(response)
+    }
+}
+
+/// Checks the inputs against the metadata of a model and attaches the shapes the model expects. Inputs in `shaped`
+/// already have their shape, e.g. outputs of an earlier pipeline step, and are passed through as they are.
+fn align_to_metadata(
+    metadata: &Value,
+    inputs: &HashMap<String, TensorData>,
+    mut shaped: ShapedInputs,
+) -> Result<ShapedInputs, Box<dyn std::error::Error + Send + Sync>> {
+    let model_inputs = metadata["inputs"]
+        .as_array()
+        .ok_or("❌ Invalid model metadata format: 'inputs' not found")?;
+
+    let mut aligned_inputs = HashMap::new();
+
+    for input in model_inputs {
+        let name = input["name"]
+            .as_str()
+            .ok_or("❌ Model metadata is missing 'name'")?;
+        if let Some(tensor) = shaped.remove(name) {
+            aligned_inputs.insert(name.to_string(), tensor);
+            continue;
+        }
+        let expected_shape = input["shape"]
+            .as_array()
+            .ok_or("❌ Model metadata is missing 'shape'")?
+            .iter()
+            .map(|v| v.as_u64().unwrap() as usize)
+            .collect::<Vec<usize>>();
+
+        let expected_len = expected_shape.iter().product::<usize>();
+
+        let tensor_data = inputs.get(name).ok_or_else(|| EngineError {
+            code: ErrorCode::BadInput,
+            detail: format!("❌ Missing input data for '{}'", name),
+        })?;
+
+        let data_len = match tensor_data {
+            TensorData::F32(data) => data.len(),
+            TensorData::I32(data) => data.len(),
+            TensorData::I64(data) => data.len(),
+            TensorData::U8(data) => data.len(),
+            TensorData::Bool(data) => data.len(),
+            TensorData::Str(data) => data.len(),
+        };
+
+        if data_len != expected_len {
+            return Err(EngineError {
+                code: ErrorCode::BadInput,
+                detail: format!(
+                    "❌ Shape mismatch for '{}'. Expected {:?}, got {}",
+                    name, expected_shape, data_len
+                ),
+            }
+            .into());
+        }
+
+        aligned_inputs.insert(name.to_string(), (tensor_data.clone(), expected_shape));
+    }
+
+    Ok(aligned_inputs)
+}
+
+/// Whether a request failed because Triton could not be reached at all, as opposed to rejecting the request
+fn is_unreachable(error: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+/// Runs the CPU bound inference of the fallback off the async workers
+#[cfg(feature = "ort")]
+async fn run_on_fallback(
+    fallback: &Arc<OnnxFallback>,
+    inputs: HashMap<String, TensorData>,
+) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+    let fallback = Arc::clone(fallback);
+    tokio::task::spawn_blocking(move || fallback.infer(inputs))
+        .await
+        .map_err(|e| format!("CPU fallback panicked: {}", e))?
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// The items of a batch request, `{"batch":[<request>, ...]}`, `None` for requests that aren't batches
+fn batch_items(request: &str) -> Option<Result<Vec<Value>, String>> {
+    let Ok(Value::Object(mut fields)) = serde_json::from_str(request) else {
+        return None;
+    };
+    let batch = fields.remove("batch")?;
+
+    Some(match batch {
+        _ if !fields.is_empty() => Err("A batch request carries nothing but its items".to_string()),
+        Value::Array(items) if items.is_empty() => Err("The batch has no items".to_string()),
+        Value::Array(items) if items.len() > MAX_BATCH_ITEMS => Err(format!(
+            "The batch has {} items, at most {} are allowed",
+            items.len(),
+            MAX_BATCH_ITEMS
+        )),
+        Value::Array(items) => Ok(items),
+        _ => Err("The batch must be an array of requests".to_string()),
+    })
+}
+
+/// Adds the `request_id` of a request to its response
+fn with_request_id(response: String, request_id: Option<Value>) -> String {
+    let Some(request_id) = request_id else {
+        return response;
+    };
+
+    match serde_json::from_str(&response) {
+        Ok(Value::Object(mut fields)) => {
+            fields.insert("request_id".to_string(), request_id);
+            Value::Object(fields).to_string()
+        }
+        _ => response,
+    }
+}
+
+/// Exponential backoff with up to 50% jitter, so that retries of concurrent requests don't hit Triton in lockstep
+fn backoff_delay(attempt: u32) -> Duration {
+    let base_ms = RETRY_BASE_DELAY_MS * 2u64.pow(attempt.saturating_sub(1));
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos() as u64)
+        .unwrap_or(0);
+    let jitter_ms = nanos % (base_ms / 2 + 1);
+
+    Duration::from_millis(base_ms + jitter_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_id_is_echoed_in_errors() {
+        let response = with_request_id(error_response(ErrorCode::Timeout, "late"), Some(json!(7)));
+
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["request_id"], 7);
+        assert_eq!(response["error"]["code"], "TIMEOUT");
+    }
+
+    #[test]
+    fn batches_are_told_apart_from_requests() {
+        assert!(batch_items(r#"{"x":{"F32":[1.0]}}"#).is_none());
+        assert_eq!(
+            batch_items(r#"{"batch":[{"x":{"F32":[1.0]}},{"x":"invalid"}]}"#),
+            Some(Ok(vec![json!({"x":{"F32":[1.0]}}), json!({"x":"invalid"})]))
+        );
+        assert!(matches!(batch_items(r#"{"batch":[]}"#), Some(Err(_))));
+        assert!(matches!(batch_items(r#"{"batch":{}}"#), Some(Err(_))));
     }
 }
@@ -0,0 +1,163 @@
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Distinguishes the files of concurrent extractions while they are hashed
+static INCOMING: AtomicU64 = AtomicU64::new(0);
+
+/// Files smaller than this are written directly, linking them saves less than the cache entry costs
+pub const DEFAULT_MIN_COMPONENT_BYTES: u64 = 64 * 1024;
+
+/// Content-addressed store of model components (tokenizers, embeddings, shared weights) that many tasks ship
+/// unchanged. Extracted files are stored once under their SHA-256 and hard linked into the task directories, so a
+/// miner serving many similar models keeps a single copy on disk. The cache has to be on the same filesystem as the
+/// task directories, files are copied instead where linking fails.
+#[derive(Debug, Clone)]
+pub struct ComponentCache {
+    dir: PathBuf,
+    min_size: u64,
+}
+
+impl ComponentCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            min_size: DEFAULT_MIN_COMPONENT_BYTES,
+        }
+    }
+
+    /// Sets the size below which files bypass the cache
+    pub fn with_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Writes a file of an archive to `destination`, through the cache if it is large enough
+    ///
+    /// # Arguments
+    /// * `reader` - The content of the file
+    /// * `size` - The size of the file as declared by the archive
+    /// * `destination` - Where the file is extracted to
+    pub fn write(&self, reader: &mut impl Read, size: u64, destination: &Path) -> io::Result<()> {
+        if size < self.min_size {
+            let mut file = File::create(destination)?;
+            io::copy(reader, &mut file)?;
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let temp_path = self.dir.join(format!(
+            ".incoming-{}-{}",
+            std::process::id(),
+            INCOMING.fetch_add(1, Ordering::Relaxed)
+        ));
+        let hash = {
+            let mut temp_file = File::create(&temp_path)?;
+            let mut hasher = Sha256::new();
+            let mut buffer = [0u8; 64 * 1024];
+            loop {
+                let read = reader.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                temp_file.write_all(&buffer[..read])?;
+            }
+            temp_file.sync_all()?;
+            hex::encode(hasher.finalize())
+        };
+
+        let cached_path = self.dir.join(&hash);
+        if cached_path.exists() {
+            println!(
+                "♻️ Reusing cached component {} for {}",
+                hash,
+                destination.display()
+            );
+            fs::remove_file(&temp_path)?;
+        } else {
+            fs::rename(&temp_path, &cached_path)?;
+            // Shared by every task linking it, so it must never be modified in place
+            let mut permissions = fs::metadata(&cached_path)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(&cached_path, permissions)?;
+        }
+
+        if destination.exists() {
+            fs::remove_file(destination)?;
+        }
+        if fs::hard_link(&cached_path, destination).is_err() {
+            fs::copy(&cached_path, destination)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the components no task links anymore
+    ///
+    /// # Returns
+    /// The number of removed components
+    #[cfg(unix)]
+    pub fn prune(&self) -> io::Result<usize> {
+        use std::os::unix::fs::MetadataExt;
+
+        if !self.dir.is_dir() {
+            return Ok(0);
+        }
+
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let incoming = entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".incoming-");
+            if metadata.is_file() && (metadata.nlink() == 1 || incoming) {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Link counts are only available on unix, elsewhere components are kept
+    #[cfg(not(unix))]
+    pub fn prune(&self) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_components_are_stored_once() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = ComponentCache::new(root.path().join("cache")).with_min_size(4);
+        let content = b"{\"vocab\": {}}";
+
+        for task in ["task-a", "task-b"] {
+            fs::create_dir_all(root.path().join(task)).unwrap();
+            let destination = root.path().join(task).join("tokenizer.json");
+            cache
+                .write(&mut &content[..], content.len() as u64, &destination)
+                .unwrap();
+            assert_eq!(fs::read(&destination).unwrap(), content);
+        }
+
+        assert_eq!(fs::read_dir(root.path().join("cache")).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            fs::remove_dir_all(root.path().join("task-a")).unwrap();
+            assert_eq!(cache.prune().unwrap(), 0);
+            fs::remove_dir_all(root.path().join("task-b")).unwrap();
+            assert_eq!(cache.prune().unwrap(), 1);
+        }
+    }
+}
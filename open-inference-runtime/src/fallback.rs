@@ -0,0 +1,112 @@
+use crate::client::TensorData;
+use ort::session::Session;
+use ort::value::{DynValue, Tensor};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Runs plain ONNX models on the CPU with ONNX Runtime, so that a task keeps serving at reduced speed while Triton is
+/// unreachable. Responses have the shape of Triton responses, so post-processing applies unchanged.
+pub struct OnnxFallback {
+    session: Session,
+}
+
+impl OnnxFallback {
+    /// Loads the model, fails if it can't be run by ONNX Runtime
+    pub fn load(model_file: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let session = Session::builder()?.commit_from_file(model_file)?;
+        Ok(Self { session })
+    }
+
+    /// Runs an inference. Flat input tensors are shaped by the dimensions the model declares, a single dynamic
+    /// dimension is derived from the length of the tensor.
+    pub fn infer(
+        &self,
+        inputs: HashMap<String, TensorData>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let mut values: Vec<(String, DynValue)> = Vec::new();
+
+        for input in &self.session.inputs {
+            let tensor = inputs
+                .get(&input.name)
+                .ok_or_else(|| format!("❌ Missing input data for '{}'", input.name))?;
+            let dimensions = input
+                .input_type
+                .tensor_dimensions()
+                .ok_or_else(|| format!("Input '{}' is not a tensor", input.name))?;
+            let shape = resolve_shape(dimensions, tensor_len(tensor))
+                .map_err(|e| format!("Input '{}': {}", input.name, e))?;
+
+            let value = match tensor.clone() {
+                TensorData::F32(data) => Tensor::from_array((shape, data))?.into_dyn(),
+                TensorData::I32(data) => Tensor::from_array((shape, data))?.into_dyn(),
+                TensorData::I64(data) => Tensor::from_array((shape, data))?.into_dyn(),
+                TensorData::U8(data) => Tensor::from_array((shape, data))?.into_dyn(),
+                TensorData::Bool(data) => Tensor::from_array((shape, data))?.into_dyn(),
+                TensorData::Str(_) => {
+                    return Err(format!(
+                        "Input '{}' is a string tensor, which the CPU fallback does not support",
+                        input.name
+                    )
+                    .into())
+                }
+            };
+            values.push((input.name.clone(), value));
+        }
+
+        let outputs = self.session.run(values)?;
+
+        let mut results = Vec::new();
+        for (name, value) in outputs.iter() {
+            let output = if let Ok((shape, data)) = value.try_extract_raw_tensor::<f32>() {
+                json!({ "name": name, "datatype": "FP32", "shape": shape, "data": data })
+            } else if let Ok((shape, data)) = value.try_extract_raw_tensor::<i64>() {
+                json!({ "name": name, "datatype": "INT64", "shape": shape, "data": data })
+            } else if let Ok((shape, data)) = value.try_extract_raw_tensor::<i32>() {
+                json!({ "name": name, "datatype": "INT32", "shape": shape, "data": data })
+            } else if let Ok((shape, data)) = value.try_extract_raw_tensor::<u8>() {
+                json!({ "name": name, "datatype": "UINT8", "shape": shape, "data": data })
+            } else if let Ok((shape, data)) = value.try_extract_raw_tensor::<bool>() {
+                json!({ "name": name, "datatype": "BOOL", "shape": shape, "data": data })
+            } else {
+                return Err(format!("Output '{}' has an unsupported type", name).into());
+            };
+            results.push(output);
+        }
+
+        Ok(json!({ "outputs": results }))
+    }
+}
+
+fn tensor_len(tensor: &TensorData) -> usize {
+    match tensor {
+        TensorData::F32(data) => data.len(),
+        TensorData::I32(data) => data.len(),
+        TensorData::I64(data) => data.len(),
+        TensorData::U8(data) => data.len(),
+        TensorData::Bool(data) => data.len(),
+        TensorData::Str(data) => data.len(),
+    }
+}
+
+/// Replaces the dynamic dimension (-1) of a declared shape, so that the shape holds `len` elements
+fn resolve_shape(dimensions: &[i64], len: usize) -> Result<Vec<i64>, String> {
+    let fixed: i64 = dimensions.iter().filter(|dim| **dim > 0).product();
+    let dynamic = dimensions.iter().filter(|dim| **dim <= 0).count();
+
+    match dynamic {
+        0 if fixed as usize == len => Ok(dimensions.to_vec()),
+        1 if fixed > 0 && len as i64 % fixed == 0 => Ok(dimensions
+            .iter()
+            .map(|dim| if *dim <= 0 { len as i64 / fixed } else { *dim })
+            .collect()),
+        0 | 1 => Err(format!(
+            "Shape mismatch, expected {:?}, got {} values",
+            dimensions, len
+        )),
+        _ => Err(format!(
+            "Shape {:?} has more than one dynamic dimension",
+            dimensions
+        )),
+    }
+}
@@ -1,8 +1,30 @@
+pub mod artifacts;
+pub mod bench;
+pub mod binary_data;
 pub mod client;
+pub mod component_cache;
+#[cfg(feature = "ort")]
+pub mod fallback;
+pub mod model_config;
 pub mod models;
+pub mod pipeline;
+pub mod plugins;
+pub mod postprocess;
+pub mod preprocess;
+pub mod repository_index;
 
-pub use client::{TensorData, TritonClient};
-pub use models::ModelExtractor;
+pub use artifacts::{Artifact, ArtifactStore};
+pub use bench::BenchReport;
+pub use client::{Delivery, FaultInjection, HttpOptions, TensorData, TritonClient};
+pub use component_cache::ComponentCache;
+pub use inference_protocol::{error_response, EngineError, ErrorCode};
+pub use model_config::ConfigGeneration;
+pub use models::{ExtractionOptions, ExtractionProgress, ModelExtractor};
+pub use pipeline::PipelineStep;
+pub use plugins::{PluginConfig, PluginLimits};
+pub use postprocess::PostProcessing;
+pub use preprocess::{binary_request, PreProcessing};
+pub use repository_index::{RepositoryChange, RepositoryIndex, RepositoryModel};
 
 // #[cfg(test)]
 // mod tests;
@@ -0,0 +1,386 @@
+use prost::Message;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Batch size Triton may combine requests up to, for models whose first dimension is dynamic
+pub const DEFAULT_MAX_BATCH_SIZE: u32 = 8;
+
+/// How `config.pbtxt` is generated for archives that only ship the raw ONNX model
+#[derive(Debug, Clone)]
+pub struct ConfigGeneration {
+    /// Places the model instance on the GPU instead of the CPU
+    pub gpu: bool,
+    /// Largest batch Triton may form, batching is disabled with 0 or for models without a dynamic batch dimension
+    pub max_batch_size: u32,
+}
+
+impl Default for ConfigGeneration {
+    fn default() -> Self {
+        Self {
+            gpu: false,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+        }
+    }
+}
+
+/// An input or output of an ONNX graph, `None` dims are dynamic
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorSpec {
+    pub name: String,
+    pub data_type: &'static str,
+    pub dims: Vec<Option<i64>>,
+}
+
+// The subset of onnx.proto needed to read the signature of a model, everything else is skipped while decoding
+
+#[derive(Clone, PartialEq, Message)]
+struct ModelProto {
+    #[prost(message, optional, tag = "7")]
+    graph: Option<GraphProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct GraphProto {
+    #[prost(message, repeated, tag = "5")]
+    initializer: Vec<TensorProto>,
+    #[prost(message, repeated, tag = "11")]
+    input: Vec<ValueInfoProto>,
+    #[prost(message, repeated, tag = "12")]
+    output: Vec<ValueInfoProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorProto {
+    #[prost(string, tag = "8")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ValueInfoProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, optional, tag = "2")]
+    r#type: Option<TypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TypeProto {
+    #[prost(message, optional, tag = "1")]
+    tensor_type: Option<TensorTypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorTypeProto {
+    #[prost(int32, tag = "1")]
+    elem_type: i32,
+    #[prost(message, optional, tag = "2")]
+    shape: Option<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorShapeProto {
+    #[prost(message, repeated, tag = "1")]
+    dim: Vec<Dimension>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Dimension {
+    #[prost(int64, optional, tag = "1")]
+    dim_value: Option<i64>,
+    #[prost(string, optional, tag = "2")]
+    dim_param: Option<String>,
+}
+
+/// The Triton data type of an ONNX tensor element type
+fn triton_data_type(elem_type: i32) -> Option<&'static str> {
+    Some(match elem_type {
+        1 => "TYPE_FP32",
+        2 => "TYPE_UINT8",
+        3 => "TYPE_INT8",
+        4 => "TYPE_UINT16",
+        5 => "TYPE_INT16",
+        6 => "TYPE_INT32",
+        7 => "TYPE_INT64",
+        8 => "TYPE_STRING",
+        9 => "TYPE_BOOL",
+        10 => "TYPE_FP16",
+        11 => "TYPE_FP64",
+        12 => "TYPE_UINT32",
+        13 => "TYPE_UINT64",
+        16 => "TYPE_BF16",
+        _ => return None,
+    })
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn tensor_spec(value_info: &ValueInfoProto) -> io::Result<TensorSpec> {
+    let tensor_type = value_info
+        .r#type
+        .as_ref()
+        .and_then(|type_proto| type_proto.tensor_type.as_ref())
+        .ok_or_else(|| invalid_data(format!("'{}' is not a tensor", value_info.name)))?;
+    let data_type = triton_data_type(tensor_type.elem_type).ok_or_else(|| {
+        invalid_data(format!(
+            "'{}' has the unsupported element type {}",
+            value_info.name, tensor_type.elem_type
+        ))
+    })?;
+    let dims = tensor_type
+        .shape
+        .as_ref()
+        .map(|shape| {
+            shape
+                .dim
+                .iter()
+                // Symbolic and unset dimensions are both dynamic for Triton
+                .map(|dim| dim.dim_value.filter(|value| *value > 0))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(TensorSpec {
+        name: value_info.name.clone(),
+        data_type,
+        dims,
+    })
+}
+
+/// Reads the inputs and outputs of an ONNX model
+///
+/// # Arguments
+/// * `model_file` - The `.onnx` file
+///
+/// # Returns
+/// The inputs and the outputs of the graph, or an error if the file is not an ONNX model Triton can describe
+pub fn read_onnx_signature(model_file: &Path) -> io::Result<(Vec<TensorSpec>, Vec<TensorSpec>)> {
+    let model = ModelProto::decode(fs::read(model_file)?.as_slice())
+        .map_err(|e| invalid_data(format!("Not an ONNX model: {}", e)))?;
+    let graph = model
+        .graph
+        .ok_or_else(|| invalid_data("The ONNX model has no graph".to_string()))?;
+
+    // Older exporters list the weights as graph inputs too, they are not fed by requests
+    let inputs = graph
+        .input
+        .iter()
+        .filter(|input| {
+            !graph
+                .initializer
+                .iter()
+                .any(|initializer| initializer.name == input.name)
+        })
+        .map(tensor_spec)
+        .collect::<io::Result<Vec<_>>>()?;
+    let outputs = graph
+        .output
+        .iter()
+        .map(tensor_spec)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok((inputs, outputs))
+}
+
+fn render_tensors(kind: &str, tensors: &[TensorSpec], batched: bool) -> String {
+    let entries: Vec<String> = tensors
+        .iter()
+        .map(|tensor| {
+            let dims: Vec<String> = tensor
+                .dims
+                .iter()
+                .skip(usize::from(batched))
+                .map(|dim| dim.unwrap_or(-1).to_string())
+                .collect();
+            // Triton needs at least one dimension, scalars are reshaped from a single element
+            let shape = if dims.is_empty() {
+                "    dims: [ 1 ]\n    reshape: { shape: [ ] }".to_string()
+            } else {
+                format!("    dims: [ {} ]", dims.join(", "))
+            };
+            format!(
+                "  {{\n    name: \"{}\"\n    data_type: {}\n{}\n  }}",
+                tensor.name, tensor.data_type, shape
+            )
+        })
+        .collect();
+
+    format!("{} [\n{}\n]\n", kind, entries.join(",\n"))
+}
+
+/// Renders the Triton configuration of an ONNX model. Requests are batched only if the first dimension of every input
+/// and output is dynamic, Triton can't split the results of a batch otherwise.
+pub fn render_config(
+    model_name: &str,
+    inputs: &[TensorSpec],
+    outputs: &[TensorSpec],
+    generation: &ConfigGeneration,
+) -> String {
+    let batched = generation.max_batch_size > 0
+        && inputs
+            .iter()
+            .chain(outputs)
+            .all(|tensor| tensor.dims.first() == Some(&None));
+
+    let max_batch_size = if batched {
+        generation.max_batch_size
+    } else {
+        0
+    };
+    let instance_kind = if generation.gpu {
+        "KIND_GPU"
+    } else {
+        "KIND_CPU"
+    };
+
+    let mut config = format!(
+        "name: \"{}\"\nplatform: \"onnxruntime_onnx\"\nmax_batch_size: {}\n",
+        model_name, max_batch_size
+    );
+    config.push_str(&render_tensors("input", inputs, batched));
+    config.push_str(&render_tensors("output", outputs, batched));
+    config.push_str(&format!(
+        "instance_group [\n  {{\n    count: 1\n    kind: {}\n  }}\n]\n",
+        instance_kind
+    ));
+    if batched {
+        config.push_str("dynamic_batching { }\n");
+    }
+
+    config
+}
+
+/// Writes `config.pbtxt` for a model directory that only contains the raw ONNX model (`1/model.onnx`)
+///
+/// # Arguments
+/// * `model_dir` - The directory of the model in the Triton model repository, its name is the model name
+/// * `generation` - How the configuration is generated
+///
+/// # Returns
+/// Whether a configuration was generated, directories with a configuration or without an ONNX model are left alone
+pub fn generate_config(model_dir: &Path, generation: &ConfigGeneration) -> io::Result<bool> {
+    let config_path = model_dir.join("config.pbtxt");
+    let model_file = model_dir.join("1").join("model.onnx");
+    if config_path.exists() || !model_file.exists() {
+        return Ok(false);
+    }
+
+    let model_name = model_dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| invalid_data(format!("Invalid model directory {}", model_dir.display())))?;
+    let (inputs, outputs) = read_onnx_signature(&model_file)?;
+    fs::write(
+        &config_path,
+        render_config(model_name, &inputs, &outputs, generation),
+    )?;
+
+    println!(
+        "🛠️ Generated config.pbtxt for {} ({} inputs, {} outputs)",
+        model_name,
+        inputs.len(),
+        outputs.len()
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value_info(name: &str, elem_type: i32, dims: &[Dimension]) -> ValueInfoProto {
+        ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(TypeProto {
+                tensor_type: Some(TensorTypeProto {
+                    elem_type,
+                    shape: Some(TensorShapeProto { dim: dims.to_vec() }),
+                }),
+            }),
+        }
+    }
+
+    fn fixed(value: i64) -> Dimension {
+        Dimension {
+            dim_value: Some(value),
+            dim_param: None,
+        }
+    }
+
+    fn symbolic(name: &str) -> Dimension {
+        Dimension {
+            dim_value: None,
+            dim_param: Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn configs_are_generated_from_the_graph_signature() {
+        let model = ModelProto {
+            graph: Some(GraphProto {
+                initializer: vec![TensorProto {
+                    name: "weights".to_string(),
+                }],
+                input: vec![
+                    value_info(
+                        "pixels",
+                        1,
+                        &[symbolic("batch"), fixed(3), fixed(224), fixed(224)],
+                    ),
+                    value_info("weights", 1, &[fixed(10)]),
+                ],
+                output: vec![value_info("logits", 1, &[symbolic("batch"), fixed(1000)])],
+            }),
+        };
+        let root = tempfile::tempdir().unwrap();
+        let model_dir = root.path().join("resnet");
+        fs::create_dir_all(model_dir.join("1")).unwrap();
+        fs::write(
+            model_dir.join("1").join("model.onnx"),
+            model.encode_to_vec(),
+        )
+        .unwrap();
+
+        assert!(generate_config(&model_dir, &ConfigGeneration::default()).unwrap());
+        let config = fs::read_to_string(model_dir.join("config.pbtxt")).unwrap();
+
+        assert!(config.contains("name: \"resnet\""));
+        assert!(config.contains("max_batch_size: 8"));
+        assert!(config.contains("dims: [ 3, 224, 224 ]"));
+        assert!(config.contains("dims: [ 1000 ]"));
+        assert!(!config.contains("weights"));
+        assert!(config.contains("kind: KIND_CPU"));
+        assert!(config.contains("dynamic_batching"));
+
+        // An existing configuration is never replaced
+        assert!(!generate_config(&model_dir, &ConfigGeneration::default()).unwrap());
+    }
+
+    #[test]
+    fn fixed_batch_dimensions_disable_batching() {
+        let inputs = vec![TensorSpec {
+            name: "input".to_string(),
+            data_type: "TYPE_INT64",
+            dims: vec![Some(1), None],
+        }];
+        let outputs = vec![TensorSpec {
+            name: "output".to_string(),
+            data_type: "TYPE_FP32",
+            dims: vec![],
+        }];
+        let generation = ConfigGeneration {
+            gpu: true,
+            max_batch_size: 8,
+        };
+
+        let config = render_config("model", &inputs, &outputs, &generation);
+
+        assert!(config.contains("max_batch_size: 0"));
+        assert!(config.contains("dims: [ 1, -1 ]"));
+        assert!(config.contains("reshape: { shape: [ ] }"));
+        assert!(config.contains("kind: KIND_GPU"));
+        assert!(!config.contains("dynamic_batching"));
+    }
+}
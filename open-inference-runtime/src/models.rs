@@ -1,9 +1,14 @@
+use crate::component_cache::ComponentCache;
+use crate::model_config::{self, ConfigGeneration};
 use base64::{engine::general_purpose, Engine as _};
 use flate2::read::GzDecoder;
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs::{remove_file, File};
 use std::io::{self, copy, BufReader, Read, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use tar::Archive;
 use zip::ZipArchive;
 
@@ -11,6 +16,114 @@ use zip::ZipArchive;
 pub struct ModelExtractor {
     archive_path: PathBuf,
     output_folder: PathBuf,
+    component_cache: Option<ComponentCache>,
+    config_generation: Option<ConfigGeneration>,
+    max_extracted_bytes: Option<u64>,
+    expected_hashes: HashMap<String, String>,
+    workers: usize,
+    progress: Arc<ExtractionProgress>,
+}
+
+/// How a model archive is extracted, see the `with_*` methods of `ModelExtractor`
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionOptions {
+    pub component_cache: Option<ComponentCache>,
+    pub config_generation: Option<ConfigGeneration>,
+    pub max_extracted_bytes: Option<u64>,
+    pub expected_hashes: HashMap<String, String>,
+    /// Entries of zip archives extracted at the same time, 0 for one per core
+    pub workers: usize,
+    pub progress: Arc<ExtractionProgress>,
+}
+
+/// How far an extraction got, shared with whoever reports it while the extraction blocks
+#[derive(Debug, Default)]
+pub struct ExtractionProgress {
+    /// The size of the archive, decompressed sizes are only known entry by entry
+    archive_bytes: AtomicU64,
+    /// Bytes of the archive consumed so far
+    read_bytes: AtomicU64,
+    files: AtomicUsize,
+}
+
+impl ExtractionProgress {
+    /// The share of the archive extracted, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        match self.archive_bytes.load(Ordering::Relaxed) {
+            0 => 0.0,
+            total => (self.read_bytes.load(Ordering::Relaxed) as f64 / total as f64).min(1.0),
+        }
+    }
+
+    /// Files extracted so far
+    pub fn files(&self) -> usize {
+        self.files.load(Ordering::Relaxed)
+    }
+}
+
+/// The bytes an extraction wrote so far, checked against the quota of the task. Shared by the workers of a
+/// parallel extraction.
+struct ExtractionBudget {
+    quota: Option<u64>,
+    extracted: AtomicU64,
+    written: Mutex<Vec<PathBuf>>,
+}
+
+impl ExtractionBudget {
+    /// Reserves the declared size of an entry before it is written, so that workers extracting in parallel can't
+    /// together exceed the quota
+    fn reserve(&self, size: u64) -> io::Result<()> {
+        self.extracted
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |extracted| {
+                let reserved = extracted.checked_add(size)?;
+                match self.quota {
+                    Some(quota) if reserved > quota => None,
+                    _ => Some(reserved),
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| self.exceeded_error())
+    }
+
+    /// Gives back the part of a reservation an entry didn't use
+    fn release(&self, unused: u64) {
+        self.extracted.fetch_sub(unused, Ordering::Relaxed);
+    }
+
+    fn exceeded_error(&self) -> io::Error {
+        io::Error::other(format!(
+            "Archive exceeds the extraction quota of {} bytes",
+            self.quota.unwrap_or(0)
+        ))
+    }
+}
+
+/// Counts the compressed bytes a tar.gz extraction consumed
+struct CountingReader<'a, R> {
+    inner: R,
+    read_bytes: &'a AtomicU64,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read_bytes.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Hashes what is read through it, so files are verified while they are written instead of being read again
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
 }
 
 impl ModelExtractor {
@@ -41,9 +154,116 @@ impl ModelExtractor {
         Ok(Self {
             archive_path,
             output_folder: PathBuf::from(base_path),
+            component_cache: None,
+            config_generation: None,
+            max_extracted_bytes: None,
+            expected_hashes: HashMap::new(),
+            workers: 0,
+            progress: Arc::default(),
         })
     }
 
+    /// Extracts large files through a cache shared with other tasks instead of writing duplicates
+    pub fn with_component_cache(mut self, component_cache: Option<ComponentCache>) -> Self {
+        self.component_cache = component_cache;
+        self
+    }
+
+    /// Generates `config.pbtxt` from the ONNX graph for models the archive ships without one
+    pub fn with_config_generation(mut self, config_generation: Option<ConfigGeneration>) -> Self {
+        self.config_generation = config_generation;
+        self
+    }
+
+    /// Aborts the extraction once the extracted files exceed `max_extracted_bytes`, so a zip bomb can't fill the disk
+    /// the other tasks of the miner run on
+    pub fn with_max_extracted_bytes(mut self, max_extracted_bytes: Option<u64>) -> Self {
+        self.max_extracted_bytes = max_extracted_bytes;
+        self
+    }
+
+    /// Verifies the files of the archive against their hex encoded SHA-256, keyed by their path in the archive. The
+    /// extraction fails on the first mismatch, or if a listed file is missing from the archive.
+    pub fn with_expected_hashes(mut self, expected_hashes: HashMap<String, String>) -> Self {
+        self.expected_hashes = expected_hashes
+            .into_iter()
+            .map(|(path, hash)| (normalized_entry_name(&path), hash.to_lowercase()))
+            .collect();
+        self
+    }
+
+    /// Sets how many entries of a zip archive are extracted at the same time, 0 for one per core. The entries of a
+    /// tar.gz archive share a single compressed stream, they are always extracted one after another.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Reports the progress of the extraction to `progress`
+    pub fn with_progress(mut self, progress: Arc<ExtractionProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Writes a file of the archive. The size the archive declares is reserved from the budget up front, and the entry
+    /// may decompress to no more than that.
+    fn write_file(
+        &self,
+        reader: &mut impl Read,
+        size: u64,
+        entry_name: &str,
+        output_path: &Path,
+        budget: &ExtractionBudget,
+    ) -> io::Result<()> {
+        budget.reserve(size)?;
+
+        // One byte more than the reservation is enough to tell that the entry is larger than it declares
+        let mut limited = HashingReader {
+            inner: reader.take(size.saturating_add(1)),
+            hasher: Sha256::new(),
+        };
+        budget
+            .written
+            .lock()
+            .unwrap()
+            .push(output_path.to_path_buf());
+        match &self.component_cache {
+            Some(component_cache) => component_cache.write(&mut limited, size, output_path)?,
+            None => {
+                let mut out_file = File::create(output_path)?;
+                copy(&mut limited, &mut out_file)?;
+            }
+        }
+        let hash = hex::encode(limited.hasher.finalize());
+
+        let written = std::fs::metadata(output_path)?.len();
+        if written > size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} decompresses to more than the {} bytes the archive declares",
+                    entry_name, size
+                ),
+            ));
+        }
+        budget.release(size - written);
+
+        let name = normalized_entry_name(entry_name);
+        if let Some(expected) = self.expected_hashes.get(&name) {
+            if *expected != hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Checksum mismatch for {}: expected {}, extracted {}",
+                        name, expected, hash
+                    ),
+                ));
+            }
+        }
+        self.progress.files.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
     pub fn extract_model(&self) -> io::Result<()> {
         let extension = self
             .archive_path
@@ -51,14 +271,31 @@ impl ModelExtractor {
             .and_then(|ext| ext.to_str())
             .unwrap_or("");
 
-        match extension {
-            "gz" => self.extract_tar_gz(),
-            "zip" => self.extract_zip(),
+        let budget = ExtractionBudget {
+            quota: self.max_extracted_bytes,
+            extracted: AtomicU64::new(0),
+            written: Mutex::new(Vec::new()),
+        };
+        self.progress.archive_bytes.store(
+            std::fs::metadata(&self.archive_path)?.len(),
+            Ordering::Relaxed,
+        );
+        let extracted = match extension {
+            "gz" => self.extract_tar_gz(&budget),
+            "zip" => self.extract_zip(&budget),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "Unsupported archive format",
             )),
-        }?;
+        }
+        .and_then(|files| self.check_listed_files(&files));
+        if let Err(e) = extracted {
+            // Frees the disk right away instead of leaving a partial model until the task is removed
+            for path in budget.written.lock().unwrap().iter() {
+                let _ = remove_file(path);
+            }
+            return Err(e);
+        }
 
         // Delete archive after extraction
         remove_file(&self.archive_path)?;
@@ -88,14 +325,62 @@ impl ModelExtractor {
             }
         }
 
+        if let Some(config_generation) = &self.config_generation {
+            self.generate_missing_configs(config_generation)?;
+        }
+
+        Ok(())
+    }
+
+    /// Generates the configurations of every extracted model lacking one, pipelines ship several models
+    fn generate_missing_configs(&self, config_generation: &ConfigGeneration) -> io::Result<()> {
+        for entry in std::fs::read_dir(&self.output_folder)? {
+            let model_dir = entry?.path();
+            if !model_dir.is_dir() {
+                continue;
+            }
+            // Triton reports the missing configuration when the model is loaded
+            if let Err(e) = model_config::generate_config(&model_dir, config_generation) {
+                eprintln!(
+                    "❌ Failed to generate config.pbtxt for {}: {}",
+                    model_dir.display(),
+                    e
+                );
+            }
+        }
         Ok(())
     }
 
+    /// Fails if a file with an expected hash is not among the extracted files
+    fn check_listed_files(&self, extracted: &[String]) -> io::Result<()> {
+        match self
+            .expected_hashes
+            .keys()
+            .find(|listed| !extracted.contains(listed))
+        {
+            Some(missing) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "File {} with an expected checksum is missing from the archive",
+                    missing
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
     /// Extracts all files from the tar.gz archive to the specified output folder
-    fn extract_tar_gz(&self) -> io::Result<()> {
-        let archive_file = File::open(&self.archive_path)?;
+    ///
+    /// # Returns
+    /// The names of the extracted files, or an `io::Error` if an entry can't be extracted
+    fn extract_tar_gz(&self, budget: &ExtractionBudget) -> io::Result<Vec<String>> {
+        let archive_file = CountingReader {
+            inner: File::open(&self.archive_path)?,
+            read_bytes: &self.progress.read_bytes,
+        };
         let decoder = GzDecoder::new(BufReader::new(archive_file));
         let mut archive = Archive::new(decoder);
+        let mut extracted = Vec::new();
 
         for entry_result in archive.entries()? {
             let mut entry = entry_result?;
@@ -111,10 +396,12 @@ impl ModelExtractor {
                 std::fs::create_dir_all(parent)?;
             }
 
-            let mut out_file = File::create(&output_path)?;
-            copy(&mut entry, &mut out_file)?;
+            let size = entry.header().size()?;
+            let name = path.to_string_lossy().to_string();
+            self.write_file(&mut entry, size, &name, &output_path, budget)?;
+            extracted.push(normalized_entry_name(&name));
         }
-        Ok(())
+        Ok(extracted)
     }
     pub fn hash_model_file(model_path: &Path, output_blob_path: &Path) -> io::Result<()> {
         // Read model bytes
@@ -140,13 +427,69 @@ impl ModelExtractor {
         Ok(())
     }
 
-    /// Extracts all files from the .zip archive to the specified output folder
+    /// Extracts all files from the .zip archive to the specified output folder. Entries are compressed independently,
+    /// so they are extracted by several workers, each reading the archive through its own handle.
+    ///
+    /// # Returns
+    /// The names of the extracted files, or an `io::Error` if an entry can't be extracted
+    fn extract_zip(&self, budget: &ExtractionBudget) -> io::Result<Vec<String>> {
+        let entries = ZipArchive::new(File::open(&self.archive_path)?)?.len();
+        let workers = match self.workers {
+            0 => std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+            workers => workers,
+        }
+        .clamp(1, entries.max(1));
+
+        let next_entry = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results: Vec<io::Result<Vec<String>>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let result =
+                            self.extract_zip_entries(&next_entry, entries, &failed, budget);
+                        if result.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        result
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(io::Error::other("Extraction worker panicked")))
+                })
+                .collect()
+        });
+
+        let mut extracted = Vec::new();
+        for result in results {
+            extracted.extend(result?);
+        }
+        Ok(extracted)
+    }
+
+    /// Extracts zip entries until none are left or another worker failed
     #[allow(deprecated)]
-    fn extract_zip(&self) -> io::Result<()> {
-        let archive_file = File::open(&self.archive_path)?;
-        let mut archive = ZipArchive::new(archive_file)?;
+    fn extract_zip_entries(
+        &self,
+        next_entry: &AtomicUsize,
+        entries: usize,
+        failed: &AtomicBool,
+        budget: &ExtractionBudget,
+    ) -> io::Result<Vec<String>> {
+        let mut archive = ZipArchive::new(File::open(&self.archive_path)?)?;
+        let mut extracted = Vec::new();
+
+        loop {
+            let i = next_entry.fetch_add(1, Ordering::Relaxed);
+            if i >= entries || failed.load(Ordering::Relaxed) {
+                return Ok(extracted);
+            }
 
-        for i in 0..archive.len() {
             let mut file = archive.by_index(i)?;
             let out_path = self.output_folder.join(file.sanitized_name());
 
@@ -156,10 +499,150 @@ impl ModelExtractor {
                 if let Some(parent) = out_path.parent() {
                     std::fs::create_dir_all(parent)?;
                 }
-                let mut out_file = File::create(&out_path)?;
-                copy(&mut file, &mut out_file)?;
+                let size = file.size();
+                let name = file.name().to_string();
+                self.write_file(&mut file, size, &name, &out_path, budget)?;
+                extracted.push(normalized_entry_name(&name));
             }
+            self.progress
+                .read_bytes
+                .fetch_add(file.compressed_size(), Ordering::Relaxed);
         }
-        Ok(())
+    }
+}
+
+/// Resolves a file the manifest of the task refers to. The manifest is written by the task owner, so the path has to
+/// stay within the model directory, otherwise the owner could have any file the miner can read served back.
+///
+/// # Arguments
+/// * `model_dir` - The directory the model was extracted to
+/// * `relative_path` - The path from the manifest
+///
+/// # Returns
+/// The path within `model_dir`, or an `io::Error` if the path is absolute or leaves the directory
+pub(crate) fn model_file(model_dir: &Path, relative_path: &str) -> io::Result<PathBuf> {
+    let path = Path::new(relative_path);
+    let confined = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if relative_path.is_empty() || !confined {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a path within the model directory", relative_path),
+        ));
+    }
+    Ok(model_dir.join(path))
+}
+
+/// The name of an archive entry as listed in the expected hashes, without a leading `./`
+fn normalized_entry_name(name: &str) -> String {
+    name.trim_start_matches("./").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    #[test]
+    fn model_files_stay_within_the_model_directory() {
+        let model_dir = Path::new("/models/task");
+
+        assert_eq!(
+            model_file(model_dir, "./labels/classes.txt").unwrap(),
+            model_dir.join("./labels/classes.txt")
+        );
+        for path in [
+            "/root/.ssh/id_rsa",
+            "../identity.json",
+            "labels/../../key",
+            "",
+        ] {
+            assert!(model_file(model_dir, path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn entries_reserve_their_declared_size() {
+        let budget = ExtractionBudget {
+            quota: Some(100),
+            extracted: AtomicU64::new(0),
+            written: Mutex::new(Vec::new()),
+        };
+
+        budget.reserve(60).unwrap();
+        assert!(budget.reserve(60).is_err());
+        budget.release(30);
+        budget.reserve(60).unwrap();
+        assert_eq!(budget.extracted.load(Ordering::Relaxed), 90);
+        assert!(budget.reserve(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn extraction_aborts_once_the_quota_is_exceeded() {
+        let root = tempfile::tempdir().unwrap();
+        let archive = File::create(root.path().join("model.tar.gz")).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::default()));
+        for name in ["model/1/model.onnx", "model/1/weights.bin"] {
+            let content = vec![0u8; 4096];
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_slice())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let result = ModelExtractor::new("model", root.path().to_path_buf())
+            .unwrap()
+            .with_max_extracted_bytes(Some(6000))
+            .extract_model();
+
+        assert!(result.is_err());
+        assert!(!root.path().join("model/1/model.onnx").exists());
+        assert!(!root.path().join("model/1/weights.bin").exists());
+    }
+
+    #[test]
+    fn zip_entries_are_extracted_in_parallel_and_verified() {
+        let root = tempfile::tempdir().unwrap();
+        let write_archive = |name: &str| {
+            let archive = File::create(root.path().join(format!("{}.zip", name))).unwrap();
+            let mut writer = zip::ZipWriter::new(archive);
+            for i in 0..8 {
+                writer
+                    .start_file(format!("{}/1/part-{}.bin", name, i), Default::default())
+                    .unwrap();
+                writer.write_all(&[i as u8; 1024]).unwrap();
+            }
+            writer.finish().unwrap();
+        };
+        let part_hash = hex::encode(Sha256::digest([3u8; 1024]));
+
+        write_archive("good");
+        let progress = Arc::new(ExtractionProgress::default());
+        ModelExtractor::new("good", root.path().to_path_buf())
+            .unwrap()
+            .with_workers(4)
+            .with_expected_hashes(HashMap::from([(
+                "./good/1/part-3.bin".to_string(),
+                part_hash.to_uppercase(),
+            )]))
+            .with_progress(Arc::clone(&progress))
+            .extract_model()
+            .unwrap();
+        assert!(root.path().join("good/1/part-7.bin").exists());
+        assert_eq!(progress.files(), 8);
+
+        write_archive("bad");
+        let result = ModelExtractor::new("bad", root.path().to_path_buf())
+            .unwrap()
+            .with_workers(4)
+            .with_expected_hashes(HashMap::from([("bad/1/part-4.bin".to_string(), part_hash)]))
+            .extract_model();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(!root.path().join("bad/1/part-4.bin").exists());
     }
 }
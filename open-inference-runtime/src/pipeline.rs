@@ -0,0 +1,198 @@
+use crate::client::TensorData;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Prefix of the sources that refer to an input of the request instead of an output of an earlier step
+const REQUEST_SOURCE: &str = "request";
+
+/// A step of a multi-model pipeline as configured in the task manifest, e.g. tokenizer → encoder → decoder. The
+/// models of all steps are shipped in the model archive and served from the same model repository.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PipelineStep {
+    /// Name of the model in the model repository
+    pub model: String,
+    /// Source of every input of the model, `request.<input>` for an input of the request or `<model>.<output>` for an
+    /// output of an earlier step. Inputs without a source are taken from the request input of the same name.
+    #[serde(default)]
+    pub inputs: HashMap<String, String>,
+}
+
+/// Where the tensor of a model input comes from
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Source<'a> {
+    Request(&'a str),
+    Output { model: &'a str, output: &'a str },
+}
+
+impl PipelineStep {
+    pub(crate) fn source<'a>(&'a self, input: &'a str) -> Source<'a> {
+        match self
+            .inputs
+            .get(input)
+            .and_then(|source| source.split_once('.'))
+        {
+            Some((REQUEST_SOURCE, field)) => Source::Request(field),
+            Some((model, output)) => Source::Output { model, output },
+            None => Source::Request(input),
+        }
+    }
+}
+
+/// Checks that every step only consumes outputs of steps that run before it
+pub fn validate(steps: &[PipelineStep]) -> Result<(), String> {
+    for (index, step) in steps.iter().enumerate() {
+        for (input, source) in &step.inputs {
+            if source.split_once('.').is_none() {
+                return Err(format!(
+                    "Input '{}' of step '{}' has source '{}', expected '<model>.<output>' or 'request.<input>'",
+                    input, step.model, source
+                ));
+            }
+            if let Source::Output { model, .. } = step.source(input) {
+                if !steps[..index].iter().any(|earlier| earlier.model == model) {
+                    return Err(format!(
+                        "Input '{}' of step '{}' consumes model '{}', which does not run before it",
+                        input, step.model, model
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The inputs a request to the pipeline has to provide, as described by the metadata of the steps, renamed to the
+/// request inputs they are taken from
+///
+/// # Arguments
+/// * `steps` - The steps of the pipeline
+/// * `metadata` - The Triton metadata of the model of every step, in the same order
+pub(crate) fn request_inputs(steps: &[PipelineStep], metadata: &[Value]) -> Vec<Value> {
+    let mut inputs: Vec<Value> = Vec::new();
+
+    for (step, metadata) in steps.iter().zip(metadata) {
+        for input in metadata["inputs"].as_array().into_iter().flatten() {
+            let Some(name) = input["name"].as_str() else {
+                continue;
+            };
+            let Source::Request(field) = step.source(name) else {
+                continue;
+            };
+            if inputs.iter().any(|input| input["name"] == field) {
+                continue;
+            }
+
+            let mut input = input.clone();
+            input["name"] = Value::from(field);
+            inputs.push(input);
+        }
+    }
+
+    inputs
+}
+
+/// Reads the outputs of a Triton inference response as tensors with their shapes, to feed them to the next step.
+/// Outputs are passed on in their own datatype, datatypes without a matching `TensorData`, e.g. `FP16` or `INT8`,
+/// are rejected instead of being widened to a type the next model doesn't accept.
+pub(crate) fn output_tensors(
+    response: &Value,
+) -> Result<HashMap<String, (TensorData, Vec<usize>)>, String> {
+    let outputs = response["outputs"]
+        .as_array()
+        .ok_or("Inference response has no 'outputs'")?;
+
+    outputs
+        .iter()
+        .map(|output| {
+            let name = output["name"].as_str().ok_or("Output is missing 'name'")?;
+            let shape = serde_json::from_value::<Vec<usize>>(output["shape"].clone())
+                .map_err(|e| format!("Output '{}' has an invalid shape: {}", name, e))?;
+            let data = output["data"].clone();
+
+            let tensor = match output["datatype"].as_str().unwrap_or_default() {
+                "FP32" => serde_json::from_value(data).map(TensorData::F32),
+                "INT32" => serde_json::from_value(data).map(TensorData::I32),
+                "INT64" => serde_json::from_value(data).map(TensorData::I64),
+                "UINT8" => serde_json::from_value(data).map(TensorData::U8),
+                "BOOL" => serde_json::from_value(data).map(TensorData::Bool),
+                "BYTES" => serde_json::from_value(data).map(TensorData::Str),
+                other => {
+                    return Err(format!(
+                        "Output '{}' has datatype '{}', which can't be passed to another model",
+                        name, other
+                    ))
+                }
+            }
+            .map_err(|e| format!("Output '{}' has invalid data: {}", name, e))?;
+
+            Ok((name.to_string(), (tensor, shape)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn steps(value: Value) -> Vec<PipelineStep> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn sources_default_to_the_request() {
+        let steps = steps(json!([
+            { "model": "encoder" },
+            { "model": "decoder", "inputs": { "hidden": "encoder.last_hidden_state", "mask": "request.attention_mask" } },
+        ]));
+
+        assert_eq!(steps[0].source("input_ids"), Source::Request("input_ids"));
+        assert_eq!(steps[1].source("mask"), Source::Request("attention_mask"));
+        assert_eq!(
+            steps[1].source("hidden"),
+            Source::Output {
+                model: "encoder",
+                output: "last_hidden_state"
+            }
+        );
+        assert!(validate(&steps).is_ok());
+    }
+
+    #[test]
+    fn steps_cannot_consume_later_steps() {
+        let steps = steps(json!([
+            { "model": "decoder", "inputs": { "hidden": "encoder.last_hidden_state" } },
+            { "model": "encoder" },
+        ]));
+
+        assert!(validate(&steps).is_err());
+    }
+
+    #[test]
+    fn outputs_keep_their_shape() {
+        let response = json!({
+            "outputs": [{ "name": "logits", "datatype": "FP32", "shape": [1, 2], "data": [0.5, 1.5] }]
+        });
+
+        let outputs = output_tensors(&response).unwrap();
+
+        let (TensorData::F32(data), shape) = &outputs["logits"] else {
+            panic!("expected a float tensor");
+        };
+        assert_eq!(data, &[0.5, 1.5]);
+        assert_eq!(shape, &[1, 2]);
+    }
+
+    #[test]
+    fn outputs_are_not_widened() {
+        let response = json!({
+            "outputs": [{ "name": "logits", "datatype": "FP16", "shape": [1], "data": [0.5] }]
+        });
+
+        let error = output_tensors(&response).unwrap_err();
+
+        assert!(error.contains("FP16"), "{}", error);
+    }
+}
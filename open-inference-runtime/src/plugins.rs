@@ -0,0 +1,210 @@
+#[cfg(feature = "wasm")]
+use crate::models;
+use serde::Deserialize;
+#[cfg(feature = "wasm")]
+use std::path::Path;
+#[cfg(feature = "wasm")]
+use wasmtime::{Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// A WebAssembly module of the task that transforms requests or responses, as configured in the task manifest.
+///
+/// Modules export their `memory`, `alloc(len: u32) -> u32` returning the address of `len` writable bytes, and
+/// `transform(ptr: u32, len: u32) -> u64` taking the UTF-8 JSON of the request or response and returning the address
+/// of the transformed JSON in the upper and its length in the lower 32 bits. They get no imports, so all they can do
+/// is compute on their own memory.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginConfig {
+    /// Path of the `.wasm` file, relative to the model directory
+    pub module: String,
+    pub phase: Phase,
+    /// Instructions a single transformation may execute before it is aborted
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    /// Linear memory the module may grow to
+    #[serde(default = "default_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Transforms the request before its inputs are parsed and pre-processed
+    Pre,
+    /// Transforms the response after the post-processing stages
+    Post,
+}
+
+/// The most a plugin may use, set by the operator of the miner. A manifest asking for more is clamped to them, as the
+/// task owner writes the manifest.
+#[derive(Debug, Clone, Copy)]
+pub struct PluginLimits {
+    pub max_fuel: u64,
+    pub max_memory_bytes: usize,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            max_fuel: default_fuel(),
+            max_memory_bytes: default_max_memory_bytes(),
+        }
+    }
+}
+
+impl PluginConfig {
+    /// The configuration with its resources clamped to the limits of the operator
+    pub fn clamped(mut self, limits: PluginLimits) -> Self {
+        self.fuel = self.fuel.min(limits.max_fuel);
+        self.max_memory_bytes = self.max_memory_bytes.min(limits.max_memory_bytes);
+        self
+    }
+}
+
+fn default_fuel() -> u64 {
+    1_000_000_000
+}
+
+fn default_max_memory_bytes() -> usize {
+    64 << 20
+}
+
+/// A compiled plugin. Every transformation runs in a fresh instance, so nothing carries over between requests.
+#[cfg(feature = "wasm")]
+pub struct WasmPlugin {
+    module_name: String,
+    phase: Phase,
+    fuel: u64,
+    max_memory_bytes: usize,
+    engine: Engine,
+    module: Module,
+}
+
+#[cfg(feature = "wasm")]
+impl WasmPlugin {
+    /// Compiles the module of a plugin
+    ///
+    /// # Arguments
+    /// * `config` - The plugin as configured in the manifest
+    /// * `model_dir` - The directory the module path is relative to, the module has to be within it
+    /// * `limits` - The limits of the operator the resources of the plugin are clamped to
+    ///
+    /// # Returns
+    /// The `WasmPlugin`, or a description of why the module can't be used
+    pub fn load(
+        config: PluginConfig,
+        model_dir: &Path,
+        limits: PluginLimits,
+    ) -> Result<Self, String> {
+        let config = config.clamped(limits);
+        let module_path = models::model_file(model_dir, &config.module)
+            .map_err(|e| format!("Invalid plugin module: {}", e))?;
+
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config).map_err(|e| e.to_string())?;
+        let module = Module::from_file(&engine, module_path)
+            .map_err(|e| format!("Failed to compile plugin '{}': {}", config.module, e))?;
+
+        if let Some(import) = module.imports().next() {
+            return Err(format!(
+                "Plugin '{}' imports '{}::{}', plugins get no host functions",
+                config.module,
+                import.module(),
+                import.name()
+            ));
+        }
+
+        Ok(Self {
+            module_name: config.module,
+            phase: config.phase,
+            fuel: config.fuel,
+            max_memory_bytes: config.max_memory_bytes,
+            engine,
+            module,
+        })
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Runs the transformation of the plugin, CPU bound until it completes or runs out of fuel
+    ///
+    /// # Returns
+    /// The transformed JSON, or a description of why the plugin failed
+    pub fn transform(&self, input: &str) -> Result<String, String> {
+        let failed = |e: wasmtime::Error| format!("Plugin '{}' failed: {}", self.module_name, e);
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory_bytes)
+            .instances(1)
+            .build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel).map_err(failed)?;
+
+        let instance = Instance::new(&mut store, &self.module, &[]).map_err(failed)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or(format!("Plugin '{}' exports no memory", self.module_name))?;
+        let alloc = instance
+            .get_typed_func::<u32, u32>(&mut store, "alloc")
+            .map_err(failed)?;
+        let transform = instance
+            .get_typed_func::<(u32, u32), u64>(&mut store, "transform")
+            .map_err(failed)?;
+
+        let input_len = u32::try_from(input.len())
+            .map_err(|_| format!("Input of plugin '{}' is too large", self.module_name))?;
+        let input_ptr = alloc.call(&mut store, input_len).map_err(failed)?;
+        memory
+            .write(&mut store, input_ptr as usize, input.as_bytes())
+            .map_err(|e| format!("Plugin '{}' failed: {}", self.module_name, e))?;
+
+        let packed = transform
+            .call(&mut store, (input_ptr, input_len))
+            .map_err(failed)?;
+        let (output_ptr, output_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        let output = memory
+            .data(&store)
+            .get(output_ptr..output_ptr + output_len)
+            .ok_or(format!(
+                "Plugin '{}' returned output outside of its memory",
+                self.module_name
+            ))?;
+
+        String::from_utf8(output.to_vec())
+            .map_err(|_| format!("Plugin '{}' returned invalid UTF-8", self.module_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugins_default_to_bounded_resources() {
+        let config: PluginConfig =
+            serde_json::from_str(r#"{"module": "plugins/tokenize.wasm", "phase": "pre"}"#).unwrap();
+
+        assert_eq!(config.phase, Phase::Pre);
+        assert_eq!(config.fuel, default_fuel());
+        assert_eq!(config.max_memory_bytes, 64 << 20);
+    }
+
+    #[test]
+    fn plugins_are_clamped_to_the_operator_limits() {
+        let config: PluginConfig = serde_json::from_str(
+            r#"{"module": "plugins/tokenize.wasm", "phase": "post", "fuel": 1000000000000, "max_memory_bytes": 8}"#,
+        )
+        .unwrap();
+        let limits = PluginLimits {
+            max_fuel: 1_000,
+            max_memory_bytes: 1 << 20,
+        };
+
+        let clamped = config.clamped(limits);
+        assert_eq!(clamped.fuel, 1_000);
+        assert_eq!(clamped.max_memory_bytes, 8);
+    }
+}
@@ -0,0 +1,738 @@
+use crate::artifacts::Artifact;
+use crate::models::model_file;
+use crate::preprocess::Layout;
+use image::{DynamicImage, GrayImage, ImageOutputFormat, RgbImage, RgbaImage};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+/// A post-processing stage as configured in the task manifest, turning a raw output tensor into a usable result
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostProcessing {
+    /// Name of the output tensor the stage applies to, the first output if not set
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(flatten)]
+    pub stage: Stage,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Stage {
+    /// Scores of shape [..., classes], reports the `top_k` classes with their labels
+    Classification {
+        #[serde(default)]
+        labels: Option<Vec<String>>,
+        /// File with one label per line, relative to the model directory
+        #[serde(default)]
+        labels_file: Option<String>,
+        #[serde(default = "default_top_k")]
+        top_k: usize,
+        /// Classes scoring below the threshold are omitted
+        #[serde(default)]
+        threshold: Option<f64>,
+        /// Applies softmax first, for models that output logits
+        #[serde(default)]
+        softmax: bool,
+    },
+    /// Boxes of shape [..., boxes, 6] with rows `[x1, y1, x2, y2, score, class]`, filtered and reduced with per class non-maximum suppression
+    Detection {
+        #[serde(default)]
+        labels: Option<Vec<String>>,
+        #[serde(default)]
+        labels_file: Option<String>,
+        #[serde(default = "default_score_threshold")]
+        score_threshold: f64,
+        #[serde(default = "default_iou_threshold")]
+        iou_threshold: f64,
+    },
+    /// Token ids of shape [..., tokens], decoded with a vocabulary of one token per line
+    Detokenize {
+        vocabulary_file: String,
+        #[serde(default)]
+        skip_token_ids: Vec<i64>,
+    },
+    /// Pixels of shape [..., channels, height, width] (or channels last), with 1, 3 or 4 channels, encoded as PNG
+    Image {
+        #[serde(default)]
+        layout: Layout,
+        /// Factor from the output values to pixel values in [0, 255], for models that output [0, 1]
+        #[serde(default = "default_pixel_scale")]
+        scale: f64,
+    },
+    /// Mono samples in [-1, 1] of shape [..., samples], encoded as 16 bit WAV
+    Audio { sample_rate: u32 },
+}
+
+fn default_top_k() -> usize {
+    1
+}
+
+fn default_score_threshold() -> f64 {
+    0.25
+}
+
+fn default_iou_threshold() -> f64 {
+    0.45
+}
+
+fn default_pixel_scale() -> f64 {
+    255.0
+}
+
+/// A post-processing stage with its label or vocabulary files loaded
+#[derive(Debug, Clone)]
+pub struct PostProcessor {
+    output: Option<String>,
+    stage: LoadedStage,
+}
+
+#[derive(Debug, Clone)]
+enum LoadedStage {
+    Classification {
+        labels: Vec<String>,
+        top_k: usize,
+        threshold: Option<f64>,
+        softmax: bool,
+    },
+    Detection {
+        labels: Vec<String>,
+        score_threshold: f64,
+        iou_threshold: f64,
+    },
+    Detokenize {
+        vocabulary: Vec<String>,
+        skip_token_ids: Vec<i64>,
+        /// Whether the vocabulary continues words with "##", so tokens without it start a new word
+        wordpiece: bool,
+    },
+    Image {
+        layout: Layout,
+        scale: f64,
+    },
+    Audio {
+        sample_rate: u32,
+    },
+}
+
+impl PostProcessor {
+    /// Loads the files a stage refers to.
+    ///
+    /// # Arguments
+    /// * `config` - The stage as configured in the manifest
+    /// * `model_dir` - The directory label and vocabulary files are relative to
+    ///
+    /// # Returns
+    /// The `PostProcessor`, or an `io::Error` if a referenced file can't be read
+    pub fn load(config: PostProcessing, model_dir: &Path) -> io::Result<Self> {
+        let stage = match config.stage {
+            Stage::Classification {
+                labels,
+                labels_file,
+                top_k,
+                threshold,
+                softmax,
+            } => LoadedStage::Classification {
+                labels: load_labels(labels, labels_file, model_dir)?,
+                top_k: top_k.max(1),
+                threshold,
+                softmax,
+            },
+            Stage::Detection {
+                labels,
+                labels_file,
+                score_threshold,
+                iou_threshold,
+            } => LoadedStage::Detection {
+                labels: load_labels(labels, labels_file, model_dir)?,
+                score_threshold,
+                iou_threshold,
+            },
+            Stage::Detokenize {
+                vocabulary_file,
+                skip_token_ids,
+            } => {
+                let vocabulary = read_lines(&model_file(model_dir, &vocabulary_file)?)?;
+                LoadedStage::Detokenize {
+                    wordpiece: vocabulary.iter().any(|token| token.starts_with("##")),
+                    vocabulary,
+                    skip_token_ids,
+                }
+            }
+            Stage::Image { layout, scale } => LoadedStage::Image { layout, scale },
+            Stage::Audio { sample_rate } => LoadedStage::Audio { sample_rate },
+        };
+
+        Ok(Self {
+            output: config.output,
+            stage,
+        })
+    }
+
+    /// Describes the stage for clients, without the label and vocabulary contents
+    pub fn describe(&self) -> Value {
+        let (kind, details) = match &self.stage {
+            LoadedStage::Classification { labels, top_k, .. } => (
+                "classification",
+                json!({ "labels": labels.len(), "top_k": top_k }),
+            ),
+            LoadedStage::Detection { labels, .. } => {
+                ("detection", json!({ "labels": labels.len() }))
+            }
+            LoadedStage::Detokenize { vocabulary, .. } => {
+                ("detokenize", json!({ "vocabulary_size": vocabulary.len() }))
+            }
+            LoadedStage::Image { layout, .. } => ("image", json!({ "layout": layout })),
+            LoadedStage::Audio { sample_rate } => ("audio", json!({ "sample_rate": sample_rate })),
+        };
+
+        json!({ "output": self.output, "kind": kind, "details": details })
+    }
+}
+
+/// Applies the post-processors to a Triton inference response, with encoded images and audio embedded as base64.
+/// Processed outputs are moved from `outputs` to `results`, keyed by output name, outputs without a post-processor
+/// stay as they are.
+///
+/// # Arguments
+/// * `processors` - The loaded post-processors of the model
+/// * `response` - The response of Triton, as returned by `infer`
+///
+/// # Returns
+/// The processed response, or a description of why an output doesn't fit its post-processor
+pub fn apply(processors: &[PostProcessor], response: Value) -> Result<Value, String> {
+    let (mut response, artifacts) = apply_with_artifacts(processors, response)?;
+    for artifact in &artifacts {
+        insert_reference(&mut response, artifact, artifact.inline());
+    }
+    Ok(response)
+}
+
+/// Places the reference to a stored artifact into the results of the response it was taken from
+pub fn insert_reference(response: &mut Value, artifact: &Artifact, reference: Value) {
+    response["results"][&artifact.output][artifact.index] = reference;
+}
+
+/// Applies the post-processors like `apply`, but leaves encoded images and audio out of the response. Their results
+/// hold a `null` per artifact until the reference to the stored artifact is inserted with `insert_reference`.
+///
+/// # Returns
+/// The processed response and the artifacts taken from it, or a description of why an output doesn't fit its
+/// post-processor
+pub fn apply_with_artifacts(
+    processors: &[PostProcessor],
+    mut response: Value,
+) -> Result<(Value, Vec<Artifact>), String> {
+    if processors.is_empty() {
+        return Ok((response, Vec::new()));
+    }
+
+    let outputs = response
+        .get_mut("outputs")
+        .and_then(Value::as_array_mut)
+        .ok_or("Inference response has no outputs")?;
+
+    let mut results = Map::new();
+    let mut artifacts = Vec::new();
+    for processor in processors {
+        let index = match &processor.output {
+            Some(name) => outputs
+                .iter()
+                .position(|output| output["name"].as_str() == Some(name.as_str()))
+                .ok_or(format!("Output '{}' not found", name))?,
+            None if !outputs.is_empty() => 0,
+            None => return Err("Inference response has no outputs".to_string()),
+        };
+
+        let output = outputs.remove(index);
+        let name = output["name"].as_str().unwrap_or("output").to_string();
+        let shape: Vec<usize> = output["shape"]
+            .as_array()
+            .map(|shape| {
+                shape
+                    .iter()
+                    .filter_map(Value::as_u64)
+                    .map(|dim| dim as usize)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let data = output["data"]
+            .as_array()
+            .ok_or(format!("Output '{}' has no data", name))?;
+
+        let result = match &processor.stage {
+            LoadedStage::Classification {
+                labels,
+                top_k,
+                threshold,
+                softmax,
+            } => classify(
+                &numbers(data)?,
+                &shape,
+                labels,
+                *top_k,
+                *threshold,
+                *softmax,
+            )?,
+            LoadedStage::Detection {
+                labels,
+                score_threshold,
+                iou_threshold,
+            } => detect(
+                &numbers(data)?,
+                &shape,
+                labels,
+                *score_threshold,
+                *iou_threshold,
+            )?,
+            LoadedStage::Detokenize {
+                vocabulary,
+                skip_token_ids,
+                wordpiece,
+            } => detokenize(
+                &numbers(data)?,
+                &shape,
+                vocabulary,
+                skip_token_ids,
+                *wordpiece,
+            )?,
+            LoadedStage::Image { layout, scale } => {
+                let images = encode_images(&numbers(data)?, &shape, *layout, *scale)?;
+                take_artifacts(&name, images, "image/png", "png", &mut artifacts)
+            }
+            LoadedStage::Audio { sample_rate } => {
+                let clips = encode_audio(&numbers(data)?, &shape, *sample_rate)?;
+                take_artifacts(&name, clips, "audio/wav", "wav", &mut artifacts)
+            }
+        };
+
+        results.insert(name, result);
+    }
+
+    response["results"] = Value::Object(results);
+    Ok((response, artifacts))
+}
+
+/// Moves encoded outputs into artifacts, the result keeps a placeholder for each of them
+fn take_artifacts(
+    output: &str,
+    encoded: Vec<Vec<u8>>,
+    content_type: &'static str,
+    extension: &'static str,
+    artifacts: &mut Vec<Artifact>,
+) -> Value {
+    let placeholders = vec![Value::Null; encoded.len()];
+    artifacts.extend(
+        encoded
+            .into_iter()
+            .enumerate()
+            .map(|(index, bytes)| Artifact {
+                output: output.to_string(),
+                index,
+                content_type,
+                extension,
+                bytes,
+            }),
+    );
+    Value::Array(placeholders)
+}
+
+fn classify(
+    scores: &[f64],
+    shape: &[usize],
+    labels: &[String],
+    top_k: usize,
+    threshold: Option<f64>,
+    softmax: bool,
+) -> Result<Value, String> {
+    let classes = last_dim(scores, shape)?;
+
+    let rows = scores
+        .chunks(classes)
+        .map(|row| {
+            let row = if softmax {
+                softmax_of(row)
+            } else {
+                row.to_vec()
+            };
+            let mut ranked: Vec<(usize, f64)> = row.into_iter().enumerate().collect();
+            ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+            let classes: Vec<Value> = ranked
+                .into_iter()
+                .filter(|(_, score)| threshold.is_none_or(|threshold| *score >= threshold))
+                .take(top_k)
+                .map(|(index, score)| {
+                    json!({ "index": index, "label": label(labels, index), "score": score })
+                })
+                .collect();
+            Value::Array(classes)
+        })
+        .collect();
+
+    Ok(Value::Array(rows))
+}
+
+fn detect(
+    data: &[f64],
+    shape: &[usize],
+    labels: &[String],
+    score_threshold: f64,
+    iou_threshold: f64,
+) -> Result<Value, String> {
+    if last_dim(data, shape)? != 6 {
+        return Err(format!(
+            "Detection output must have rows of [x1, y1, x2, y2, score, class], got shape {:?}",
+            shape
+        ));
+    }
+    let boxes_per_image = shape
+        .len()
+        .checked_sub(2)
+        .map(|dim| shape[dim])
+        .unwrap_or(data.len() / 6)
+        .max(1);
+
+    let images = data
+        .chunks(boxes_per_image * 6)
+        .map(|image| {
+            let mut candidates: Vec<&[f64]> = image
+                .chunks(6)
+                .filter(|row| row[4] >= score_threshold)
+                .collect();
+            candidates.sort_by(|a, b| b[4].total_cmp(&a[4]));
+
+            let mut kept: Vec<&[f64]> = Vec::new();
+            for candidate in candidates {
+                let suppressed = kept
+                    .iter()
+                    .any(|kept| kept[5] == candidate[5] && iou(kept, candidate) > iou_threshold);
+                if !suppressed {
+                    kept.push(candidate);
+                }
+            }
+
+            let detections: Vec<Value> = kept
+                .into_iter()
+                .map(|row| {
+                    json!({
+                        "box": [row[0], row[1], row[2], row[3]],
+                        "score": row[4],
+                        "index": row[5] as usize,
+                        "label": label(labels, row[5] as usize),
+                    })
+                })
+                .collect();
+            Value::Array(detections)
+        })
+        .collect();
+
+    Ok(Value::Array(images))
+}
+
+fn detokenize(
+    ids: &[f64],
+    shape: &[usize],
+    vocabulary: &[String],
+    skip_token_ids: &[i64],
+    wordpiece: bool,
+) -> Result<Value, String> {
+    let tokens = last_dim(ids, shape)?;
+
+    let texts = ids
+        .chunks(tokens)
+        .map(|sequence| {
+            let mut text = String::new();
+            for id in sequence.iter().map(|id| *id as i64) {
+                if skip_token_ids.contains(&id) {
+                    continue;
+                }
+                let token = usize::try_from(id)
+                    .ok()
+                    .and_then(|id| vocabulary.get(id))
+                    .map(String::as_str)
+                    .unwrap_or("");
+
+                // WordPiece continues words with "##", SentencePiece and byte level BPE mark word starts with "▁" and "Ġ"
+                match token.strip_prefix("##") {
+                    Some(continuation) => text.push_str(continuation),
+                    None if text.is_empty() => text.push_str(token),
+                    None if token.starts_with('▁') || token.starts_with('Ġ') => {
+                        text.push_str(token)
+                    }
+                    None if wordpiece => {
+                        text.push(' ');
+                        text.push_str(token);
+                    }
+                    None => text.push_str(token),
+                }
+            }
+
+            Value::String(text.replace(['▁', 'Ġ'], " ").trim().to_string())
+        })
+        .collect();
+
+    Ok(Value::Array(texts))
+}
+
+fn encode_images(
+    pixels: &[f64],
+    shape: &[usize],
+    layout: Layout,
+    scale: f64,
+) -> Result<Vec<Vec<u8>>, String> {
+    let [a, b, c] = match shape.len().checked_sub(3) {
+        Some(start) => [shape[start], shape[start + 1], shape[start + 2]],
+        None => {
+            return Err(format!(
+                "Image output must have at least 3 dimensions, got shape {:?}",
+                shape
+            ))
+        }
+    };
+    let (channels, height, width) = match layout {
+        Layout::Nchw => (a, b, c),
+        Layout::Nhwc => (c, a, b),
+    };
+    let image_len = channels * height * width;
+    if !matches!(channels, 1 | 3 | 4) || image_len == 0 || !pixels.len().is_multiple_of(image_len) {
+        return Err(format!(
+            "Output of {} values is not a batch of images with 1, 3 or 4 channels, shape {:?}",
+            pixels.len(),
+            shape
+        ));
+    }
+
+    pixels
+        .chunks(image_len)
+        .map(|image| {
+            // Interleaved as the image crate expects it
+            let mut buffer = Vec::with_capacity(image_len);
+            for pixel in 0..height * width {
+                for channel in 0..channels {
+                    let value = match layout {
+                        Layout::Nchw => image[channel * height * width + pixel],
+                        Layout::Nhwc => image[pixel * channels + channel],
+                    };
+                    buffer.push((value * scale).round().clamp(0.0, 255.0) as u8);
+                }
+            }
+
+            let (width, height) = (width as u32, height as u32);
+            let image = match channels {
+                1 => GrayImage::from_raw(width, height, buffer).map(DynamicImage::ImageLuma8),
+                3 => RgbImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgb8),
+                _ => RgbaImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgba8),
+            }
+            .ok_or("Image output does not match its dimensions")?;
+
+            let mut png = Cursor::new(Vec::new());
+            image
+                .write_to(&mut png, ImageOutputFormat::Png)
+                .map_err(|e| format!("Failed to encode image: {}", e))?;
+            Ok(png.into_inner())
+        })
+        .collect()
+}
+
+fn encode_audio(
+    samples: &[f64],
+    shape: &[usize],
+    sample_rate: u32,
+) -> Result<Vec<Vec<u8>>, String> {
+    let clip_len = last_dim(samples, shape)?;
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    samples
+        .chunks(clip_len)
+        .map(|clip| {
+            let mut wav = Cursor::new(Vec::new());
+            let mut writer = hound::WavWriter::new(&mut wav, spec)
+                .map_err(|e| format!("Failed to encode audio: {}", e))?;
+            for sample in clip {
+                writer
+                    .write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f64) as i16)
+                    .map_err(|e| format!("Failed to encode audio: {}", e))?;
+            }
+            writer
+                .finalize()
+                .map_err(|e| format!("Failed to encode audio: {}", e))?;
+            Ok(wav.into_inner())
+        })
+        .collect()
+}
+
+/// Length of the innermost dimension, all other dimensions are treated as batch dimensions
+fn last_dim(data: &[f64], shape: &[usize]) -> Result<usize, String> {
+    let last = shape.last().copied().unwrap_or(data.len());
+    if last == 0 || !data.len().is_multiple_of(last) {
+        return Err(format!(
+            "Output of {} values does not match shape {:?}",
+            data.len(),
+            shape
+        ));
+    }
+    Ok(last)
+}
+
+fn numbers(data: &[Value]) -> Result<Vec<f64>, String> {
+    data.iter()
+        .map(|value| {
+            value
+                .as_f64()
+                .ok_or(format!("Expected a numeric output, got {}", value))
+        })
+        .collect()
+}
+
+fn softmax_of(row: &[f64]) -> Vec<f64> {
+    let max = row.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<f64> = row.iter().map(|value| (value - max).exp()).collect();
+    let sum: f64 = exps.iter().sum();
+    exps.into_iter().map(|value| value / sum).collect()
+}
+
+fn iou(a: &[f64], b: &[f64]) -> f64 {
+    let width = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let height = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let intersection = width * height;
+    let union = (a[2] - a[0]) * (a[3] - a[1]) + (b[2] - b[0]) * (b[3] - b[1]) - intersection;
+
+    if union <= 0.0 {
+        0.0
+    } else {
+        intersection / union
+    }
+}
+
+fn label(labels: &[String], index: usize) -> Value {
+    labels
+        .get(index)
+        .map(|label| Value::String(label.clone()))
+        .unwrap_or(Value::Null)
+}
+
+fn load_labels(
+    labels: Option<Vec<String>>,
+    labels_file: Option<String>,
+    model_dir: &Path,
+) -> io::Result<Vec<String>> {
+    match (labels, labels_file) {
+        (Some(labels), _) => Ok(labels),
+        (None, Some(labels_file)) => read_lines(&model_file(model_dir, &labels_file)?),
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim_end().to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor(stage: LoadedStage) -> PostProcessor {
+        PostProcessor {
+            output: None,
+            stage,
+        }
+    }
+
+    fn response(shape: &[usize], data: Value) -> Value {
+        json!({ "outputs": [{ "name": "output", "datatype": "FP32", "shape": shape, "data": data }] })
+    }
+
+    #[test]
+    fn classification_reports_labelled_top_class() {
+        let classifier = processor(LoadedStage::Classification {
+            labels: vec!["cat".to_string(), "dog".to_string(), "bird".to_string()],
+            top_k: 1,
+            threshold: None,
+            softmax: false,
+        });
+
+        let processed = apply(&[classifier], response(&[1, 3], json!([0.1, 0.7, 0.2]))).unwrap();
+
+        assert_eq!(processed["results"]["output"][0][0]["label"], "dog");
+        assert_eq!(processed["outputs"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn detection_suppresses_overlapping_boxes_of_the_same_class() {
+        let detector = processor(LoadedStage::Detection {
+            labels: Vec::new(),
+            score_threshold: 0.3,
+            iou_threshold: 0.5,
+        });
+        #[rustfmt::skip]
+        let boxes = json!([
+            0.0, 0.0, 10.0, 10.0, 0.9, 0.0,
+            1.0, 1.0, 10.0, 10.0, 0.8, 0.0,
+            1.0, 1.0, 10.0, 10.0, 0.8, 1.0,
+            20.0, 20.0, 30.0, 30.0, 0.1, 0.0
+        ]);
+
+        let processed = apply(&[detector], response(&[1, 4, 6], boxes)).unwrap();
+
+        assert_eq!(
+            processed["results"]["output"][0].as_array().unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn detokenize_joins_wordpiece_continuations() {
+        let detokenizer = processor(LoadedStage::Detokenize {
+            vocabulary: ["[CLS]", "hello", "wor", "##ld"]
+                .iter()
+                .map(|token| token.to_string())
+                .collect(),
+            skip_token_ids: vec![0],
+            wordpiece: true,
+        });
+
+        let processed = apply(&[detokenizer], response(&[1, 4], json!([0, 1, 2, 3]))).unwrap();
+
+        assert_eq!(processed["results"]["output"][0], "hello world");
+    }
+
+    #[test]
+    fn images_are_taken_out_of_the_response_as_artifacts() {
+        let encoder = processor(LoadedStage::Image {
+            layout: Layout::Nchw,
+            scale: 255.0,
+        });
+        // Two 1x2 RGB images
+        let pixels = json!([1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0]);
+
+        let (mut processed, artifacts) =
+            apply_with_artifacts(&[encoder], response(&[2, 3, 1, 2], pixels)).unwrap();
+
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(processed["results"]["output"], json!([null, null]));
+        let decoded = image::load_from_memory(&artifacts[0].bytes)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(decoded.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(decoded.get_pixel(1, 0).0, [0, 255, 0]);
+
+        let reference = artifacts[1].reference(json!({ "url": "stored" }));
+        insert_reference(&mut processed, &artifacts[1], reference);
+        assert_eq!(processed["results"]["output"][1]["url"], "stored");
+        assert_eq!(
+            processed["results"]["output"][1]["sha256"],
+            artifacts[1].sha256()
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use crate::client::TensorData;
+use base64::{engine::general_purpose, Engine as _};
+use image::{imageops::FilterType, DynamicImage};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// Field of a request that carries the payload of a binary websocket message, see `binary_request`
+const BINARY_FIELD: &str = "binary";
+
+/// A pre-processing stage as configured in the task manifest, turning an encoded image or audio payload into the
+/// tensor of a model input
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PreProcessing {
+    /// Name of the model input the payload is converted for
+    pub input: String,
+    #[serde(flatten)]
+    pub stage: InputStage,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InputStage {
+    /// Any format the `image` crate decodes, resized to `width` x `height` and normalized per channel as
+    /// `(pixel * scale - mean) / std`
+    Image {
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        grayscale: bool,
+        #[serde(default)]
+        layout: Layout,
+        #[serde(default = "default_scale")]
+        scale: f32,
+        #[serde(default)]
+        mean: Option<Vec<f32>>,
+        #[serde(default)]
+        std: Option<Vec<f32>>,
+    },
+    /// WAV audio, mixed down to mono with samples in [-1, 1]
+    Audio {
+        /// Resamples to this rate if set
+        #[serde(default)]
+        sample_rate: Option<u32>,
+        /// Pads with silence or truncates to this number of samples if set
+        #[serde(default)]
+        samples: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Channels first, what most vision models exported from PyTorch expect
+    #[default]
+    Nchw,
+    Nhwc,
+}
+
+fn default_scale() -> f32 {
+    1.0 / 255.0
+}
+
+/// Wraps the payload of a binary websocket message into a request, for models with exactly one pre-processed input
+pub fn binary_request(payload: &[u8]) -> String {
+    serde_json::json!({ BINARY_FIELD: general_purpose::STANDARD.encode(payload) }).to_string()
+}
+
+/// Parses the inputs of a request. Inputs with a pre-processing stage may be sent as a base64 string (or as
+/// `{"base64": ...}`) instead of a tensor, they are decoded and converted here. All other inputs are tensors.
+///
+/// # Arguments
+/// * `stages` - The pre-processing stages of the model
+/// * `request` - The request as received from the client
+///
+/// # Returns
+/// The tensors of the request, or a description of why the request can't be converted
+pub fn parse_inputs(
+    stages: &[PreProcessing],
+    request: &str,
+) -> Result<HashMap<String, TensorData>, String> {
+    if stages.is_empty() {
+        return serde_json::from_str(request).map_err(|e| {
+            let binary = serde_json::from_str::<Map<String, Value>>(request)
+                .is_ok_and(|fields| fields.contains_key(BINARY_FIELD));
+            if binary {
+                "Binary requests need a pre-processed input, the model has none".to_string()
+            } else {
+                e.to_string()
+            }
+        });
+    }
+
+    let mut fields: Map<String, Value> =
+        serde_json::from_str(request).map_err(|e| e.to_string())?;
+
+    if let Some(payload) = fields.remove(BINARY_FIELD) {
+        let [stage] = stages else {
+            return Err(format!(
+                "Binary requests need exactly one pre-processed input, the model has {}",
+                stages.len()
+            ));
+        };
+        fields.insert(stage.input.clone(), payload);
+    }
+
+    let mut inputs = HashMap::new();
+    for (name, value) in fields {
+        let stage = stages.iter().find(|stage| stage.input == name);
+        let tensor = match (stage, encoded_payload(&value)) {
+            (Some(stage), Some(payload)) => {
+                let bytes = general_purpose::STANDARD
+                    .decode(payload)
+                    .map_err(|e| format!("Input '{}' is not valid base64: {}", name, e))?;
+                TensorData::F32(
+                    convert(&stage.stage, &bytes)
+                        .map_err(|e| format!("Failed to pre-process input '{}': {}", name, e))?,
+                )
+            }
+            _ => serde_json::from_value(value).map_err(|e| e.to_string())?,
+        };
+        inputs.insert(name, tensor);
+    }
+
+    Ok(inputs)
+}
+
+fn encoded_payload(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(payload) => Some(payload),
+        Value::Object(fields) => fields.get("base64").and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+fn convert(stage: &InputStage, bytes: &[u8]) -> Result<Vec<f32>, String> {
+    match stage {
+        InputStage::Image {
+            width,
+            height,
+            grayscale,
+            layout,
+            scale,
+            mean,
+            std,
+        } => {
+            let image = image::load_from_memory(bytes)
+                .map_err(|e| e.to_string())?
+                .resize_exact(*width, *height, FilterType::Triangle);
+            Ok(image_tensor(
+                image,
+                *grayscale,
+                *layout,
+                *scale,
+                mean.as_deref(),
+                std.as_deref(),
+            ))
+        }
+        InputStage::Audio {
+            sample_rate,
+            samples,
+        } => audio_tensor(bytes, *sample_rate, *samples),
+    }
+}
+
+fn image_tensor(
+    image: DynamicImage,
+    grayscale: bool,
+    layout: Layout,
+    scale: f32,
+    mean: Option<&[f32]>,
+    std: Option<&[f32]>,
+) -> Vec<f32> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let (channels, pixels) = if grayscale {
+        (1, image.to_luma8().into_raw())
+    } else {
+        (3, image.to_rgb8().into_raw())
+    };
+
+    // A single mean or std applies to every channel
+    let channel_value = |values: Option<&[f32]>, channel: usize, default: f32| {
+        values
+            .and_then(|values| values.get(channel).or(values.first()).copied())
+            .unwrap_or(default)
+    };
+
+    let mut tensor = vec![0.0; pixels.len()];
+    for (index, pixel) in pixels.iter().enumerate() {
+        let channel = index % channels;
+        let position = index / channels;
+        let value = (*pixel as f32 * scale - channel_value(mean, channel, 0.0))
+            / channel_value(std, channel, 1.0);
+
+        let target = match layout {
+            Layout::Nhwc => index,
+            Layout::Nchw => channel * width * height + position,
+        };
+        tensor[target] = value;
+    }
+
+    tensor
+}
+
+fn audio_tensor(
+    bytes: &[u8],
+    sample_rate: Option<u32>,
+    samples: Option<usize>,
+) -> Result<Vec<f32>, String> {
+    let mut reader = hound::WavReader::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?,
+        hound::SampleFormat::Int => {
+            let max = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / max))
+                .collect::<Result<_, _>>()
+                .map_err(|e| e.to_string())?
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+
+    let mut audio = match sample_rate {
+        Some(rate) if rate != spec.sample_rate => resample(&mono, spec.sample_rate, rate),
+        _ => mono,
+    };
+
+    if let Some(samples) = samples {
+        audio.resize(samples, 0.0);
+    }
+
+    Ok(audio)
+}
+
+/// Linear interpolation, good enough for the speech models this is meant for
+fn resample(audio: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if audio.is_empty() || from == 0 {
+        return Vec::new();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let length = (audio.len() as f64 / ratio).round() as usize;
+
+    (0..length)
+        .map(|index| {
+            let position = index as f64 * ratio;
+            let left = position.floor() as usize;
+            let right = (left + 1).min(audio.len() - 1);
+            let fraction = (position - left as f64) as f32;
+            audio[left.min(audio.len() - 1)] * (1.0 - fraction) + audio[right] * fraction
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+
+    fn png(width: u32, height: u32, color: [u8; 3]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb(color)))
+            .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn image_stage(layout: Layout) -> PreProcessing {
+        PreProcessing {
+            input: "image".to_string(),
+            stage: InputStage::Image {
+                width: 2,
+                height: 2,
+                grayscale: false,
+                layout,
+                scale: 1.0,
+                mean: None,
+                std: None,
+            },
+        }
+    }
+
+    #[test]
+    fn image_is_resized_into_channels_first_tensor() {
+        let request = binary_request(&png(4, 4, [10, 20, 30]));
+
+        let inputs = parse_inputs(&[image_stage(Layout::Nchw)], &request).unwrap();
+
+        let TensorData::F32(tensor) = &inputs["image"] else {
+            panic!("expected a float tensor");
+        };
+        assert_eq!(
+            tensor,
+            &[10.0, 10.0, 10.0, 10.0, 20.0, 20.0, 20.0, 20.0, 30.0, 30.0, 30.0, 30.0]
+        );
+    }
+
+    #[test]
+    fn tensors_pass_through_next_to_encoded_inputs() {
+        let request = serde_json::json!({
+            "image": { "base64": general_purpose::STANDARD.encode(png(2, 2, [1, 2, 3])) },
+            "mask": { "I64": [1, 0] },
+        })
+        .to_string();
+
+        let inputs = parse_inputs(&[image_stage(Layout::Nhwc)], &request).unwrap();
+
+        assert!(matches!(&inputs["mask"], TensorData::I64(mask) if mask == &[1, 0]));
+        assert!(
+            matches!(&inputs["image"], TensorData::F32(image) if image[..3] == [1.0, 2.0, 3.0])
+        );
+    }
+
+    #[test]
+    fn binary_requests_need_a_pre_processed_input() {
+        let error = parse_inputs(&[], &binary_request(&png(2, 2, [1, 2, 3]))).unwrap_err();
+
+        assert!(error.contains("pre-processed input"), "{}", error);
+    }
+
+    #[test]
+    fn resampling_halves_the_number_of_samples() {
+        assert_eq!(resample(&[0.0, 1.0, 0.0, 1.0], 16000, 8000), vec![0.0, 0.0]);
+    }
+}
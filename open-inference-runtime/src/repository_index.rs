@@ -0,0 +1,218 @@
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// How long a fetched index is served before Triton is asked again
+pub const DEFAULT_INDEX_TTL: Duration = Duration::from_secs(5);
+
+/// Changes a slow subscriber may fall behind by before it misses some
+const CHANGE_CAPACITY: usize = 64;
+
+/// A model version in the repository of Triton, as listed by `/repository/index`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RepositoryModel {
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// `READY`, `UNAVAILABLE`, `LOADING` or `UNLOADING`, missing for models that were never loaded
+    #[serde(default)]
+    pub state: Option<String>,
+    /// Why the model is in its state, eg. why loading it failed
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl RepositoryModel {
+    /// Whether the model version is loaded and serves requests
+    pub fn is_ready(&self) -> bool {
+        self.state.as_deref() == Some("READY")
+    }
+}
+
+/// How the repository changed between two fetches of its index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepositoryChange {
+    Added(RepositoryModel),
+    Removed(RepositoryModel),
+    /// The model version was loaded, unloaded or failed, with its new state
+    StateChanged(RepositoryModel),
+}
+
+#[derive(Default)]
+struct CachedIndex {
+    fetched_at: Option<Instant>,
+    /// Kept when the index is invalidated, so that the next fetch reports what changed since
+    models: Option<Vec<RepositoryModel>>,
+}
+
+/// Caches the repository index of Triton, so that listing the models doesn't cost a request to Triton every time.
+/// Every fetch is compared with the previous one, subscribers learn about models that were added, removed, loaded or
+/// unloaded, also by others than this client. The first fetch only establishes what is known.
+pub struct RepositoryIndex {
+    client: Client,
+    url: String,
+    ttl: Duration,
+    cached: Mutex<CachedIndex>,
+    /// Concurrent callers of a stale index wait for a single fetch
+    refreshing: tokio::sync::Mutex<()>,
+    changes: broadcast::Sender<RepositoryChange>,
+}
+
+impl RepositoryIndex {
+    /// Creates a new `RepositoryIndex`.
+    ///
+    /// # Arguments
+    /// * `client` - The HTTP client the index is fetched with
+    /// * `url` - The base URL of Triton, eg. `http://localhost:8000/v2`
+    /// * `ttl` - How long a fetched index is served
+    ///
+    /// # Returns
+    /// A new `RepositoryIndex` that fetches on first use
+    pub fn new(client: Client, url: &str, ttl: Duration) -> Self {
+        Self {
+            client,
+            url: url.to_string(),
+            ttl,
+            cached: Mutex::new(CachedIndex::default()),
+            refreshing: tokio::sync::Mutex::new(()),
+            changes: broadcast::channel(CHANGE_CAPACITY).0,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Lists the models of the repository, from the cache while it is fresh
+    ///
+    /// # Returns
+    /// The model versions of the repository, or an `Error` if the index can't be fetched
+    pub async fn models(&self) -> Result<Vec<RepositoryModel>, reqwest::Error> {
+        if let Some(models) = self.fresh() {
+            return Ok(models);
+        }
+
+        let _refreshing = self.refreshing.lock().await;
+        if let Some(models) = self.fresh() {
+            return Ok(models);
+        }
+        self.fetch().await
+    }
+
+    /// Fetches the index regardless of the cache, eg. to poll for changes made by others
+    ///
+    /// # Returns
+    /// The model versions of the repository, or an `Error` if the index can't be fetched
+    pub async fn refresh(&self) -> Result<Vec<RepositoryModel>, reqwest::Error> {
+        let _refreshing = self.refreshing.lock().await;
+        self.fetch().await
+    }
+
+    /// Marks the cached index as stale, the next listing fetches it again. Called after models were loaded or unloaded.
+    pub fn invalidate(&self) {
+        self.cached.lock().unwrap().fetched_at = None;
+    }
+
+    /// Subscribes to the changes found by the following fetches
+    pub fn subscribe(&self) -> broadcast::Receiver<RepositoryChange> {
+        self.changes.subscribe()
+    }
+
+    fn fresh(&self) -> Option<Vec<RepositoryModel>> {
+        let cached = self.cached.lock().unwrap();
+        match (cached.fetched_at, &cached.models) {
+            (Some(fetched_at), Some(models)) if fetched_at.elapsed() < self.ttl => {
+                Some(models.clone())
+            }
+            _ => None,
+        }
+    }
+
+    async fn fetch(&self) -> Result<Vec<RepositoryModel>, reqwest::Error> {
+        let models: Vec<RepositoryModel> = self
+            .client
+            .post(format!("{}/repository/index", self.url))
+            .json(&json!({}))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let changes = {
+            let mut cached = self.cached.lock().unwrap();
+            let changes = cached
+                .models
+                .as_ref()
+                .map(|previous| diff(previous, &models))
+                .unwrap_or_default();
+            cached.models = Some(models.clone());
+            cached.fetched_at = Some(Instant::now());
+            changes
+        };
+        for change in changes {
+            // Nobody may be subscribed
+            let _ = self.changes.send(change);
+        }
+
+        Ok(models)
+    }
+}
+
+/// The changes between two listings of the repository, model versions are matched by name and version
+fn diff(previous: &[RepositoryModel], current: &[RepositoryModel]) -> Vec<RepositoryChange> {
+    let key = |model: &RepositoryModel| (model.name.clone(), model.version.clone());
+    let previous_by_key: HashMap<_, _> = previous.iter().map(|model| (key(model), model)).collect();
+    let current_by_key: HashMap<_, _> = current.iter().map(|model| (key(model), model)).collect();
+
+    let mut changes = Vec::new();
+    for model in current {
+        match previous_by_key.get(&key(model)) {
+            None => changes.push(RepositoryChange::Added(model.clone())),
+            Some(known) if known.state != model.state => {
+                changes.push(RepositoryChange::StateChanged(model.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for model in previous {
+        if !current_by_key.contains_key(&key(model)) {
+            changes.push(RepositoryChange::Removed(model.clone()));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(name: &str, state: Option<&str>) -> RepositoryModel {
+        RepositoryModel {
+            name: name.to_string(),
+            version: Some("1".to_string()),
+            state: state.map(str::to_string),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn diff_reports_added_removed_and_changed_models() {
+        let previous = vec![model("kept", Some("READY")), model("gone", Some("READY"))];
+        let current = vec![model("kept", Some("UNAVAILABLE")), model("new", None)];
+
+        assert_eq!(
+            diff(&previous, &current),
+            vec![
+                RepositoryChange::StateChanged(model("kept", Some("UNAVAILABLE"))),
+                RepositoryChange::Added(model("new", None)),
+                RepositoryChange::Removed(model("gone", Some("READY"))),
+            ]
+        );
+        assert!(diff(&current, &current).is_empty());
+    }
+}